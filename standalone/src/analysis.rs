@@ -0,0 +1,905 @@
+use crate::callgraph::resolve_calls;
+use crate::clone::RangeChanges;
+use crate::imports::{resolve_imports, Target};
+use crate::storage::{EdgeRecord, NodeRecord};
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// How one language marks functions that are used from outside the repo's own
+/// call graph: entry points, public API and tests.
+#[derive(Debug, Clone)]
+pub struct LanguageRule {
+    pub language: &'static str,
+    pub extensions: &'static [&'static str],
+    /// Names that are always entry points, e.g. `main`.
+    pub names: &'static [&'static str],
+    /// Regexes over the function name, e.g. Go's `^Test`.
+    pub name_patterns: &'static [&'static str],
+    /// Regexes over the function source, e.g. a leading `pub` or `#[test]`.
+    pub body_patterns: &'static [&'static str],
+    /// Go exports every capitalized name.
+    pub exported_if_capitalized: bool,
+}
+
+pub const DEFAULT_RULES: &[LanguageRule] = &[
+    LanguageRule {
+        language: "rust",
+        extensions: &["rs"],
+        names: &["main"],
+        name_patterns: &[],
+        body_patterns: &[
+            r"^\s*pub\b",
+            r"#\[(test|bench|no_mangle)\]",
+            r"#\[[\w:]*::(test|main)\b",
+        ],
+        exported_if_capitalized: false,
+    },
+    LanguageRule {
+        language: "python",
+        extensions: &["py"],
+        names: &["main"],
+        name_patterns: &[r"^__\w+__$", r"^test"],
+        // decorators usually register the function with a framework
+        body_patterns: &[r"^\s*@"],
+        exported_if_capitalized: false,
+    },
+    LanguageRule {
+        language: "go",
+        extensions: &["go"],
+        names: &["main", "init"],
+        name_patterns: &[r"^(Test|Benchmark|Example|Fuzz)"],
+        body_patterns: &[],
+        exported_if_capitalized: true,
+    },
+    LanguageRule {
+        language: "javascript",
+        extensions: &["js", "jsx", "mjs", "cjs", "ts", "tsx"],
+        names: &[],
+        name_patterns: &[],
+        body_patterns: &[r"^\s*export\b"],
+        exported_if_capitalized: false,
+    },
+    LanguageRule {
+        language: "java",
+        extensions: &["java", "kt", "kts"],
+        names: &["main"],
+        name_patterns: &[],
+        body_patterns: &[r"^\s*(@\w+\s*)*(public|protected)\b", r"@Test\b"],
+        exported_if_capitalized: false,
+    },
+    LanguageRule {
+        language: "ruby",
+        extensions: &["rb"],
+        names: &["initialize", "method_missing", "respond_to_missing?"],
+        name_patterns: &[r"^test_"],
+        body_patterns: &[],
+        exported_if_capitalized: false,
+    },
+    LanguageRule {
+        language: "swift",
+        extensions: &["swift"],
+        names: &["main"],
+        name_patterns: &[r"^test"],
+        body_patterns: &[r"^\s*(public|open)\b"],
+        exported_if_capitalized: false,
+    },
+    LanguageRule {
+        language: "c",
+        extensions: &["c", "h", "cpp", "cc", "cxx", "hpp", "hh"],
+        names: &["main"],
+        name_patterns: &[],
+        body_patterns: &[],
+        exported_if_capitalized: false,
+    },
+];
+
+/// Files whose functions are run by a test harness rather than called.
+pub(crate) const TEST_FILES: &str =
+    r"(^|/)(tests?|__tests__|spec)/|_test\.(go|py)$|(^|/)test_[^/]*\.py$|\.(test|spec)\.[jt]sx?$";
+
+struct CompiledRule {
+    rule: &'static LanguageRule,
+    name_patterns: Vec<Regex>,
+    body_patterns: Vec<Regex>,
+}
+
+/// The entry point heuristic: the per-language rules plus names the caller
+/// wants kept whatever the language.
+pub struct EntryPoints {
+    rules: Vec<CompiledRule>,
+    extra: Vec<Regex>,
+    test_files: Regex,
+}
+
+impl EntryPoints {
+    /// `extra` are regexes over function names, e.g. `^handle_` for handlers
+    /// registered by name. Fails on one that doesn't compile.
+    pub fn new(rules: &'static [LanguageRule], extra: &[String]) -> Result<Self> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern)
+                .with_context(|| format!("invalid entry point pattern '{}'", pattern))
+        };
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                Ok(CompiledRule {
+                    rule,
+                    name_patterns: rule
+                        .name_patterns
+                        .iter()
+                        .map(|p| compile(p))
+                        .collect::<Result<_>>()?,
+                    body_patterns: rule
+                        .body_patterns
+                        .iter()
+                        .map(|p| compile(p))
+                        .collect::<Result<_>>()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(EntryPoints {
+            rules,
+            extra: extra.iter().map(|p| compile(p)).collect::<Result<_>>()?,
+            test_files: compile(TEST_FILES)?,
+        })
+    }
+
+    pub fn is_entry_point(&self, function: &NodeRecord) -> bool {
+        if self.test_files.is_match(&function.file) {
+            return true;
+        }
+        if self.extra.iter().any(|re| re.is_match(&function.name)) {
+            return true;
+        }
+        let ext = Path::new(&function.file)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        let Some(compiled) = self.rules.iter().find(|r| r.rule.extensions.contains(&ext)) else {
+            return false;
+        };
+        let rule = compiled.rule;
+        rule.names.contains(&function.name.as_str())
+            || (rule.exported_if_capitalized
+                && function.name.starts_with(|c: char| c.is_uppercase()))
+            || compiled
+                .name_patterns
+                .iter()
+                .any(|re| re.is_match(&function.name))
+            || compiled
+                .body_patterns
+                .iter()
+                .any(|re| re.is_match(&function.body))
+    }
+}
+
+impl Default for EntryPoints {
+    fn default() -> Self {
+        EntryPoints::new(DEFAULT_RULES, &[]).expect("default entry point rules compile")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Unreferenced {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    pub start: usize,
+    pub end: usize,
+    /// Set when something suggests the function is used in a way the call
+    /// graph can't see, with `reason` saying what.
+    pub uncertain: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Functions of a repo's graph that nothing calls, other than themselves, and
+/// that aren't entry points. Calls come from the resolved call graph, so a call
+/// to a name counts for every definition it might resolve to.
+pub fn find_unreferenced(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    entry_points: &EntryPoints,
+) -> Vec<Unreferenced> {
+    let graph = resolve_calls(nodes, edges);
+    let mut called: HashSet<&str> = HashSet::new();
+    for function in &graph.functions {
+        for call in &function.calls {
+            for candidate in &call.candidates {
+                if candidate.id != function.id {
+                    called.insert(candidate.id.as_str());
+                }
+            }
+        }
+    }
+
+    let functions: Vec<&NodeRecord> = nodes.iter().filter(|n| n.kind == "Function").collect();
+    let mentions = Mentions::collect(&functions);
+    let mut dead: Vec<Unreferenced> = functions
+        .iter()
+        .filter(|f| !called.contains(f.id.as_str()) && !entry_points.is_entry_point(f))
+        .map(|f| {
+            let reason = mentions.reason(f);
+            Unreferenced {
+                id: f.id.clone(),
+                name: f.name.clone(),
+                file: f.file.clone(),
+                start: f.start,
+                end: f.end,
+                uncertain: reason.is_some(),
+                reason,
+            }
+        })
+        .collect();
+    dead.sort_by(|a, b| (&a.file, a.start).cmp(&(&b.file, b.start)));
+    dead
+}
+
+/// Where function names turn up in other functions' source without a call
+/// the graph resolved.
+struct Mentions<'a> {
+    /// Identifier -> ids of the functions whose body mentions it.
+    words: HashMap<&'a str, HashSet<&'a str>>,
+    /// Names used after `.` or `->`, i.e. as methods on some receiver.
+    methods: HashSet<&'a str>,
+}
+
+impl<'a> Mentions<'a> {
+    fn collect(functions: &[&'a NodeRecord]) -> Self {
+        static WORD: OnceLock<Regex> = OnceLock::new();
+        static METHOD: OnceLock<Regex> = OnceLock::new();
+        let word = WORD.get_or_init(|| Regex::new(r"[A-Za-z_][A-Za-z0-9_]*").unwrap());
+        let method =
+            METHOD.get_or_init(|| Regex::new(r"(?:\.|->)\s*([A-Za-z_][A-Za-z0-9_]*)").unwrap());
+        let mut words: HashMap<&str, HashSet<&str>> = HashMap::new();
+        let mut methods = HashSet::new();
+        for function in functions {
+            for m in word.find_iter(&function.body) {
+                words.entry(m.as_str()).or_default().insert(&function.id);
+            }
+            for c in method.captures_iter(&function.body) {
+                if let Some(name) = c.get(1) {
+                    methods.insert(name.as_str());
+                }
+            }
+        }
+        Mentions { words, methods }
+    }
+
+    fn reason(&self, function: &NodeRecord) -> Option<String> {
+        let name = function.name.as_str();
+        if self.methods.contains(name) {
+            return Some(format!(
+                "`{}` is called as a method somewhere; the receiver's type isn't known, so this may be dynamically dispatched",
+                name
+            ));
+        }
+        let elsewhere = self
+            .words
+            .get(name)
+            .is_some_and(|ids| ids.iter().any(|id| *id != function.id));
+        if elsewhere {
+            return Some(format!(
+                "`{}` is mentioned by other functions without being called, e.g. passed as a callback",
+                name
+            ));
+        }
+        if function.meta.contains_key("operand") {
+            return Some(
+                "a method, which may implement a trait or interface called elsewhere".to_string(),
+            );
+        }
+        None
+    }
+}
+
+/// Node kinds that only organise the graph; a diff is over what they contain.
+const CONTAINER_KINDS: &[&str] = &["Repository", "Directory", "File", "Language"];
+
+/// How a symbol present on both sides of a diff changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Signature,
+    Body,
+    /// Same source at different lines.
+    Moved,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub id: String,
+    pub kind: String,
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChangedSymbol {
+    pub base: Symbol,
+    pub head: Symbol,
+    pub changes: Vec<Change>,
+    /// Both signatures, when they differ.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<(String, String)>,
+}
+
+/// An edge between two symbols, listed under the file of its source.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DiffEdge {
+    pub kind: String,
+    pub source: String,
+    pub target: String,
+    pub target_file: String,
+}
+
+/// The structural change to one file between two graphs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FileDiff {
+    pub file: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<Symbol>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<Symbol>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<ChangedSymbol>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_edges: Vec<DiffEdge>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_edges: Vec<DiffEdge>,
+}
+
+/// What identifies a symbol across refs. Node ids carry the start line, so a
+/// function that only moved would look removed and re-added by id; this key
+/// leaves position out. Same-named symbols in one file, e.g. overloads, are
+/// told apart by their order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct SymbolKey {
+    file: String,
+    kind: String,
+    operand: String,
+    name: String,
+    verb: String,
+    nth: usize,
+}
+
+struct Side<'a> {
+    symbols: BTreeMap<SymbolKey, &'a NodeRecord>,
+    edges: BTreeSet<(String, SymbolKey, SymbolKey)>,
+}
+
+impl<'a> Side<'a> {
+    fn new(nodes: &'a [NodeRecord], edges: &[EdgeRecord]) -> Self {
+        let mut sorted: Vec<&NodeRecord> = nodes
+            .iter()
+            .filter(|n| !CONTAINER_KINDS.contains(&n.kind.as_str()))
+            .collect();
+        sorted.sort_by(|a, b| (&a.file, a.start, &a.id).cmp(&(&b.file, b.start, &b.id)));
+        let mut symbols = BTreeMap::new();
+        let mut by_id = HashMap::new();
+        let mut seen: HashMap<SymbolKey, usize> = HashMap::new();
+        for node in sorted {
+            let meta = |key: &str| node.meta.get(key).cloned().unwrap_or_default();
+            let mut key = SymbolKey {
+                file: node.file.clone(),
+                kind: node.kind.clone(),
+                operand: meta("operand"),
+                name: node.name.clone(),
+                verb: meta("verb"),
+                nth: 0,
+            };
+            let nth = seen.entry(key.clone()).or_default();
+            key.nth = *nth;
+            *nth += 1;
+            by_id.insert(node.id.as_str(), key.clone());
+            symbols.insert(key, node);
+        }
+        let edges = edges
+            .iter()
+            .filter_map(|e| {
+                let source = by_id.get(e.source.as_str())?;
+                let target = by_id.get(e.target.as_str())?;
+                Some((e.kind.clone(), source.clone(), target.clone()))
+            })
+            .collect();
+        Side { symbols, edges }
+    }
+}
+
+/// The structural delta from `base` to `head`, one entry per file that has
+/// any. Symbols are matched by kind, file and name rather than by id, so an
+/// edited or moved function shows up as changed instead of as a removal and
+/// an addition.
+pub fn diff(
+    base: (&[NodeRecord], &[EdgeRecord]),
+    head: (&[NodeRecord], &[EdgeRecord]),
+) -> Vec<FileDiff> {
+    let base = Side::new(base.0, base.1);
+    let head = Side::new(head.0, head.1);
+    let mut files: BTreeMap<String, FileDiff> = BTreeMap::new();
+
+    for (key, node) in &base.symbols {
+        match head.symbols.get(key) {
+            None => file_diff(&mut files, &key.file).removed.push(symbol(node)),
+            Some(after) => {
+                if let Some(changed) = compare(node, after) {
+                    file_diff(&mut files, &key.file).changed.push(changed);
+                }
+            }
+        }
+    }
+    for (key, node) in &head.symbols {
+        if !base.symbols.contains_key(key) {
+            file_diff(&mut files, &key.file).added.push(symbol(node));
+        }
+    }
+    let edge = |(kind, source, target): &(String, SymbolKey, SymbolKey)| DiffEdge {
+        kind: kind.clone(),
+        source: source.name.clone(),
+        target: target.name.clone(),
+        target_file: target.file.clone(),
+    };
+    for e in base.edges.difference(&head.edges) {
+        file_diff(&mut files, &e.1.file).removed_edges.push(edge(e));
+    }
+    for e in head.edges.difference(&base.edges) {
+        file_diff(&mut files, &e.1.file).added_edges.push(edge(e));
+    }
+    files.into_values().collect()
+}
+
+fn file_diff<'a>(files: &'a mut BTreeMap<String, FileDiff>, file: &str) -> &'a mut FileDiff {
+    files.entry(file.to_string()).or_insert_with(|| FileDiff {
+        file: file.to_string(),
+        ..Default::default()
+    })
+}
+
+fn symbol(node: &NodeRecord) -> Symbol {
+    Symbol {
+        id: node.id.clone(),
+        kind: node.kind.clone(),
+        name: node.name.clone(),
+        start: node.start,
+        end: node.end,
+    }
+}
+
+fn compare(base: &NodeRecord, head: &NodeRecord) -> Option<ChangedSymbol> {
+    let (before, after) = (signature(&base.body), signature(&head.body));
+    let mut changes = Vec::new();
+    if before != after {
+        changes.push(Change::Signature);
+    } else if base.body != head.body {
+        changes.push(Change::Body);
+    }
+    if base.start != head.start && base.body == head.body {
+        changes.push(Change::Moved);
+    }
+    if changes.is_empty() {
+        return None;
+    }
+    Some(ChangedSymbol {
+        base: symbol(base),
+        head: symbol(head),
+        signature: (before != after).then_some((before, after)),
+        changes,
+    })
+}
+
+/// The declaration part of a symbol's source, whitespace collapsed: up to the
+/// opening `{`, or the first line for languages that end it with `:` or don't
+/// use braces.
+fn signature(body: &str) -> String {
+    let first_line = body.lines().next().unwrap_or_default();
+    let declaration = match body.find('{') {
+        Some(brace) if !first_line.trim_end().ends_with(':') => &body[..brace],
+        _ => first_line,
+    };
+    declaration.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// What a commit range did to a symbol.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum RangeChange {
+    Added,
+    Modified,
+    /// Same source, somewhere else.
+    Moved,
+    Deleted,
+}
+
+/// A symbol a commit range changed, where it ended up; a deleted one where
+/// it last was.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SymbolChange {
+    pub change: RangeChange,
+    pub file: String,
+    #[serde(flatten)]
+    pub symbol: Symbol,
+    /// The file a moved symbol's source was in before.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moved_from: Option<String>,
+    /// The commits in the range that wrote its lines, by id.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub commits: Vec<String>,
+}
+
+/// The symbols `changes` added, modified, moved or deleted, sorted by file
+/// and line. `head` is the graph as of the range's last commit, with paths
+/// relative to the checkout. A symbol is modified when blame puts some of its
+/// lines down to the range and added when it puts all of them, unless the
+/// same source was in a file the range touched, which makes it a move.
+///
+/// Deletions can only be named from `base`, a graph as of the range's first
+/// commit: symbols whose every line was removed, with nothing of the same
+/// kind and name left in their file and no move of their source.
+pub fn range_diff(
+    changes: &RangeChanges,
+    head: &[NodeRecord],
+    base: &[NodeRecord],
+) -> Vec<SymbolChange> {
+    let same = |a: &NodeRecord, b: &NodeRecord| a.kind == b.kind && a.name == b.name;
+    let mut found = Vec::new();
+    let mut moved = HashSet::new();
+    for file in &changes.files {
+        let Some(path) = &file.new_path else {
+            continue;
+        };
+        for node in symbols_in(head, path) {
+            let lines = node.start..=node.end;
+            let commits: BTreeSet<&String> =
+                lines.clone().filter_map(|l| file.written.get(&l)).collect();
+            if commits.is_empty() {
+                continue;
+            }
+            let mut moved_from = None;
+            let change = if lines.clone().any(|l| !file.written.contains_key(&l)) {
+                RangeChange::Modified
+            } else if let Some(from) = previous_file(changes, &node.body) {
+                moved.insert(node.body.as_str());
+                moved_from = Some(from.to_string());
+                RangeChange::Moved
+            } else if file
+                .old_path
+                .as_deref()
+                .is_some_and(|old| symbols_in(base, old).any(|b| same(b, node)))
+            {
+                RangeChange::Modified
+            } else {
+                RangeChange::Added
+            };
+            found.push(SymbolChange {
+                change,
+                file: path.clone(),
+                symbol: symbol(node),
+                moved_from,
+                commits: commits.into_iter().cloned().collect(),
+            });
+        }
+    }
+    for file in &changes.files {
+        let Some(path) = &file.old_path else {
+            continue;
+        };
+        for node in symbols_in(base, path) {
+            let removed = (node.start..=node.end).all(|l| file.removed.contains(&l));
+            let kept = file
+                .new_path
+                .as_deref()
+                .is_some_and(|new| symbols_in(head, new).any(|h| same(h, node)));
+            if removed && !kept && !moved.contains(node.body.as_str()) {
+                found.push(SymbolChange {
+                    change: RangeChange::Deleted,
+                    file: path.clone(),
+                    symbol: symbol(node),
+                    moved_from: None,
+                    commits: Vec::new(),
+                });
+            }
+        }
+    }
+    found.sort_by(|a, b| {
+        (&a.file, a.symbol.start, a.change).cmp(&(&b.file, b.symbol.start, b.change))
+    });
+    found
+}
+
+fn symbols_in<'a>(nodes: &'a [NodeRecord], file: &'a str) -> impl Iterator<Item = &'a NodeRecord> {
+    nodes
+        .iter()
+        .filter(move |n| n.file == file && !CONTAINER_KINDS.contains(&n.kind.as_str()))
+}
+
+/// The first file the range touched whose old text holds `body`.
+fn previous_file<'a>(changes: &'a RangeChanges, body: &str) -> Option<&'a str> {
+    if body.trim().is_empty() {
+        return None;
+    }
+    changes
+        .files
+        .iter()
+        .find(|f| f.old_source.contains(body))
+        .and_then(|f| f.old_path.as_deref())
+}
+
+/// How many related files `/related` returns unless asked for more.
+pub const DEFAULT_RELATED_LIMIT: usize = 20;
+
+/// What each kind of link between two files adds to their relatedness,
+/// per link.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct RelatednessWeights {
+    /// One file imports the other.
+    pub imports: f64,
+    /// A module both files import.
+    pub shared_imports: f64,
+    /// A call from a function in one file to a function in the other.
+    pub calls: f64,
+    /// A commit that changed both files.
+    pub co_changes: f64,
+}
+
+impl Default for RelatednessWeights {
+    fn default() -> Self {
+        RelatednessWeights {
+            imports: 4.0,
+            shared_imports: 1.0,
+            calls: 2.0,
+            co_changes: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelatedFile {
+    pub file: String,
+    pub score: f64,
+    /// Imports between the two files, either way.
+    pub imports: usize,
+    pub shared_imports: usize,
+    /// Calls between the two files, either way.
+    pub calls: usize,
+    pub co_changes: usize,
+}
+
+/// The files of the graph linked to `file`, highest score first: by imports
+/// between them, modules they both import, calls crossing between them and,
+/// from `co_changes`, commits that changed both. Files with no link at all
+/// are left out.
+pub fn relatedness(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    file: &str,
+    co_changes: &HashMap<String, usize>,
+    weights: &RelatednessWeights,
+) -> Vec<RelatedFile> {
+    let mut related: BTreeMap<String, RelatedFile> = BTreeMap::new();
+    let mut entry = |other: &str| {
+        related
+            .entry(other.to_string())
+            .or_insert_with(|| RelatedFile {
+                file: other.to_string(),
+                score: 0.0,
+                imports: 0,
+                shared_imports: 0,
+                calls: 0,
+                co_changes: 0,
+            })
+    };
+
+    let mut imported_by: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for import in resolve_imports(nodes) {
+        let module = match import.target {
+            Target::File(target) => {
+                if import.file == file && target != file {
+                    entry(&target).imports += 1;
+                } else if target == file && import.file != file {
+                    entry(&import.file).imports += 1;
+                }
+                target
+            }
+            Target::External(module) => module,
+            Target::Repo { file: target, .. } => target,
+        };
+        imported_by.entry(module).or_default().insert(import.file);
+    }
+    for importers in imported_by.values() {
+        if importers.contains(file) {
+            for other in importers.iter().filter(|f| *f != file) {
+                entry(other).shared_imports += 1;
+            }
+        }
+    }
+
+    for function in resolve_calls(nodes, edges).functions {
+        for candidate in function.calls.iter().flat_map(|c| &c.candidates) {
+            if function.file == file && candidate.file != file {
+                entry(&candidate.file).calls += 1;
+            } else if candidate.file == file && function.file != file {
+                entry(&function.file).calls += 1;
+            }
+        }
+    }
+    for (other, count) in co_changes.iter().filter(|(f, _)| *f != file) {
+        entry(other).co_changes += count;
+    }
+
+    let mut related: Vec<RelatedFile> = related
+        .into_values()
+        .map(|mut r| {
+            r.score = weights.imports * r.imports as f64
+                + weights.shared_imports * r.shared_imports as f64
+                + weights.calls * r.calls as f64
+                + weights.co_changes * r.co_changes as f64;
+            r
+        })
+        .filter(|r| r.score > 0.0)
+        .collect();
+    // ties keep the path order of the map
+    related.sort_by(|a, b| b.score.total_cmp(&a.score));
+    related
+}
+
+/// How many cycles `/cycles` returns unless asked for more, and at most.
+pub const DEFAULT_CYCLE_LIMIT: usize = 100;
+pub const MAX_CYCLE_LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cycle {
+    /// Every file of the strongly connected component, by path: each one
+    /// imports the others, through one another.
+    pub files: Vec<String>,
+    /// One way round the loop, from the first of `files` back to it, so
+    /// there's an import to start breaking it at.
+    pub path: Vec<String>,
+}
+
+/// The import cycles among the files of the graph: the strongly connected
+/// components of the file-to-file `IMPORTS` edges with more than one file,
+/// or with a file importing itself, largest first and then by path. Tarjan's
+/// algorithm is run without recursion, so a deep import chain can't
+/// overflow the stack, and it and the paths are linear in the edges. Also
+/// returns whether there were more than `limit`.
+pub fn cycles(nodes: &[NodeRecord], edges: &[EdgeRecord], limit: usize) -> (Vec<Cycle>, bool) {
+    let files: HashMap<&str, &str> = nodes
+        .iter()
+        .filter(|n| n.kind == "File")
+        .map(|n| (n.id.as_str(), n.file.as_str()))
+        .collect();
+    let mut imports: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for edge in edges.iter().filter(|e| e.kind == "IMPORTS") {
+        if let (Some(from), Some(to)) = (
+            files.get(edge.source.as_str()),
+            files.get(edge.target.as_str()),
+        ) {
+            imports.entry(from).or_default().insert(to);
+            imports.entry(to).or_default();
+        }
+    }
+    let files: Vec<&str> = imports.keys().copied().collect();
+    let index: HashMap<&str, usize> = files.iter().enumerate().map(|(i, f)| (*f, i)).collect();
+    let out: Vec<Vec<usize>> = files
+        .iter()
+        .map(|f| imports[f].iter().map(|t| index[t]).collect())
+        .collect();
+
+    let mut cycles: Vec<Cycle> = strongly_connected(&out)
+        .into_iter()
+        .filter(|c| c.len() > 1 || out[c[0]].contains(&c[0]))
+        .map(|component| {
+            let mut members: Vec<usize> = component;
+            members.sort_by_key(|&i| files[i]);
+            let path = loop_through(&out, &members)
+                .into_iter()
+                .map(|i| files[i].to_string())
+                .collect();
+            Cycle {
+                files: members.iter().map(|&i| files[i].to_string()).collect(),
+                path,
+            }
+        })
+        .collect();
+    cycles.sort_by(|a, b| {
+        b.files
+            .len()
+            .cmp(&a.files.len())
+            .then(a.files.cmp(&b.files))
+    });
+    let truncated = cycles.len() > limit;
+    cycles.truncate(limit);
+    (cycles, truncated)
+}
+
+/// The strongly connected components of the graph whose edges out of node
+/// `i` are `out[i]`, by Tarjan's algorithm with an explicit stack.
+fn strongly_connected(out: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNSEEN: usize = usize::MAX;
+    let mut index = vec![UNSEEN; out.len()];
+    let mut low = vec![0; out.len()];
+    let mut on_stack = vec![false; out.len()];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+    let mut next = 0;
+    for root in 0..out.len() {
+        if index[root] != UNSEEN {
+            continue;
+        }
+        // (node, how many of its edges have been followed)
+        let mut frames = vec![(root, 0)];
+        index[root] = next;
+        low[root] = next;
+        next += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        while let Some(frame) = frames.last_mut() {
+            let node = frame.0;
+            if let Some(&target) = out[node].get(frame.1) {
+                frame.1 += 1;
+                if index[target] == UNSEEN {
+                    index[target] = next;
+                    low[target] = next;
+                    next += 1;
+                    stack.push(target);
+                    on_stack[target] = true;
+                    frames.push((target, 0));
+                } else if on_stack[target] {
+                    low[node] = low[node].min(index[target]);
+                }
+                continue;
+            }
+            frames.pop();
+            if let Some(&(parent, _)) = frames.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+            if low[node] == index[node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
+/// The shortest way from the first of `members` back to itself along `out`,
+/// staying within `members`, first and last included.
+fn loop_through(out: &[Vec<usize>], members: &[usize]) -> Vec<usize> {
+    let start = members[0];
+    let within: HashSet<usize> = members.iter().copied().collect();
+    let mut came_from: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        for &target in out[node].iter().filter(|t| within.contains(t)) {
+            if target == start {
+                let mut path = vec![start];
+                let mut at = node;
+                while at != start {
+                    path.push(at);
+                    at = came_from[&at];
+                }
+                path.push(start);
+                path.reverse();
+                return path;
+            }
+            if let std::collections::hash_map::Entry::Vacant(entry) = came_from.entry(target) {
+                entry.insert(node);
+                queue.push_back(target);
+            }
+        }
+    }
+    vec![start]
+}
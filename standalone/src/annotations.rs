@@ -0,0 +1,249 @@
+use crate::captures::GRAMMARS;
+use crate::storage::{kind_key, EdgeRecord, NodeRecord, Span};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Node, Query, QueryCursor};
+
+pub const ANNOTATION_KIND: &str = "Annotation";
+pub const ANNOTATED_BY: &str = "ANNOTATED_BY";
+/// The meta key holding what an annotation was given, e.g. `("/users")`.
+pub const ARGUMENTS: &str = "arguments";
+
+/// Node kinds an annotation can be attached to.
+const SYMBOL_KINDS: &[&str] = &[
+    "Function",
+    "Class",
+    "Trait",
+    "Interface",
+    "DataModel",
+    "Var",
+    "Endpoint",
+    "UnitTest",
+    "IntegrationTest",
+    "E2eTest",
+];
+
+/// How one grammar of [`GRAMMARS`] writes decorators, annotations or
+/// attributes.
+#[derive(Debug, Clone)]
+pub struct AnnotationRule {
+    pub grammar: &'static str,
+    pub kinds: &'static [&'static str],
+    /// Whether an annotation precedes what it annotates, as Rust's
+    /// attributes do, rather than being part of it.
+    pub precedes: bool,
+}
+
+pub const ANNOTATION_RULES: &[AnnotationRule] = &[
+    AnnotationRule {
+        grammar: "rust",
+        kinds: &["attribute_item"],
+        precedes: true,
+    },
+    AnnotationRule {
+        grammar: "python",
+        kinds: &["decorator"],
+        precedes: false,
+    },
+    AnnotationRule {
+        grammar: "java",
+        kinds: &["marker_annotation", "annotation"],
+        precedes: false,
+    },
+    AnnotationRule {
+        grammar: "typescript",
+        kinds: &["decorator"],
+        precedes: false,
+    },
+    AnnotationRule {
+        grammar: "tsx",
+        kinds: &["decorator"],
+        precedes: false,
+    },
+];
+
+impl AnnotationRule {
+    pub fn for_path(path: &Path) -> Option<&'static AnnotationRule> {
+        let ext = path.extension()?.to_str()?;
+        let grammar = GRAMMARS.iter().find(|g| g.extensions.contains(&ext))?;
+        ANNOTATION_RULES.iter().find(|r| r.grammar == grammar.name)
+    }
+
+    /// The definition `annotation` is attached to, if it is attached to one
+    /// rather than to a parameter or a type.
+    fn definition<'t>(&self, annotation: Node<'t>) -> Option<Node<'t>> {
+        let definition = if self.precedes {
+            let mut next = annotation.next_named_sibling();
+            while next
+                .is_some_and(|n| self.kinds.contains(&n.kind()) || n.kind().contains("comment"))
+            {
+                next = next.and_then(|n| n.next_named_sibling());
+            }
+            next?
+        } else {
+            let parent = annotation.parent()?;
+            match parent.kind() {
+                "decorated_definition" => parent.child_by_field_name("definition")?,
+                "export_statement" => parent.child_by_field_name("declaration")?,
+                "modifiers" => parent.parent()?,
+                _ => parent,
+            }
+        };
+        let kind = definition.kind();
+        (!kind.ends_with("parameter") && kind != "annotated_type").then_some(definition)
+    }
+}
+
+/// An `Annotation` node for every decorator, annotation or attribute on a
+/// symbol in `nodes`, with an `ANNOTATED_BY` edge to it from the symbol, so
+/// "every function annotated with `route`" is a query over the graph. The
+/// node is named as the annotation is written, `app.route` for
+/// `@app.route("/")`, and keeps what it was given in its
+/// [`ARGUMENTS`] meta. Files are read from the checkout at `root`; one that
+/// can't be read any more, or doesn't parse within `timeout`, gets no
+/// annotations.
+pub fn detect(
+    root: &Path,
+    repo_id: &str,
+    nodes: &[NodeRecord],
+    timeout: Option<Duration>,
+) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let mut by_file: BTreeMap<&str, Vec<&NodeRecord>> = BTreeMap::new();
+    for node in nodes
+        .iter()
+        .filter(|n| SYMBOL_KINDS.contains(&n.kind.as_str()))
+    {
+        by_file.entry(node.file.as_str()).or_default().push(node);
+    }
+    let root_prefix = format!("{}/", root.display());
+    let mut found = (Vec::new(), Vec::new());
+    for (file, symbols) in by_file {
+        if AnnotationRule::for_path(Path::new(file)).is_none() {
+            continue;
+        }
+        let rel = file
+            .find(&root_prefix)
+            .map_or(file, |at| &file[at + root_prefix.len()..]);
+        let Ok(decoded) = crate::encoding::read(&root.join(rel)) else {
+            continue;
+        };
+        match annotate(file, &decoded.text, repo_id, &symbols, timeout) {
+            Ok((nodes, edges)) => {
+                found.0.extend(nodes);
+                found.1.extend(edges);
+            }
+            Err(e) => tracing::warn!("Failed to read the annotations of {}: {:#}", rel, e),
+        }
+    }
+    found
+}
+
+/// [`detect`] for the one `file`, whose text is `source` and whose symbols
+/// are `symbols`. An annotation belongs to the symbol starting between its
+/// line and the line naming the definition it's attached to.
+pub fn annotate(
+    file: &str,
+    source: &str,
+    repo_id: &str,
+    symbols: &[&NodeRecord],
+    timeout: Option<Duration>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let Some(rule) = AnnotationRule::for_path(Path::new(file)) else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let grammar = GRAMMARS.iter().find(|g| g.name == rule.grammar).unwrap();
+    let language = grammar.language();
+    let Some(tree) = crate::lang::parse(&language, source, timeout)? else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let alternatives: Vec<String> = rule.kinds.iter().map(|k| format!("({})", k)).collect();
+    let query = Query::new(
+        &language,
+        &format!("[{}] @annotation", alternatives.join(" ")),
+    )
+    .with_context(|| format!("invalid annotation query for {}", rule.grammar))?;
+
+    let bytes = source.as_bytes();
+    let mut nodes: BTreeMap<String, NodeRecord> = BTreeMap::new();
+    let mut edges = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&query, tree.root_node(), bytes);
+    while let Some(m) = matches.next() {
+        let Some(annotation) = m.captures.first().map(|c| c.node) else {
+            continue;
+        };
+        let Some(definition) = rule.definition(annotation) else {
+            continue;
+        };
+        let named_at = definition
+            .child_by_field_name("name")
+            .unwrap_or(definition)
+            .start_position()
+            .row;
+        let first = annotation.start_position().row;
+        let annotated: Vec<&&NodeRecord> = symbols
+            .iter()
+            .filter(|s| (first..=named_at).contains(&s.start))
+            .collect();
+        if annotated.is_empty() {
+            continue;
+        }
+        let body = annotation.utf8_text(bytes)?;
+        let (name, arguments) = parse(body);
+        let id = kind_key(ANNOTATION_KIND, name, file, first, None);
+        if nodes.contains_key(&id) {
+            continue;
+        }
+        nodes.insert(
+            id.clone(),
+            NodeRecord {
+                repo_id: repo_id.to_string(),
+                id: id.clone(),
+                kind: ANNOTATION_KIND.to_string(),
+                name: name.to_string(),
+                file: file.to_string(),
+                start: first,
+                end: annotation.end_position().row,
+                body: body.to_string(),
+                meta: arguments
+                    .map(|a| BTreeMap::from([(ARGUMENTS.to_string(), a.to_string())]))
+                    .unwrap_or_default(),
+                span: Some(Span {
+                    start_column: annotation.start_position().column,
+                    end_column: annotation.end_position().column,
+                    start_byte: annotation.start_byte(),
+                    end_byte: annotation.end_byte(),
+                }),
+            },
+        );
+        for symbol in annotated {
+            edges.push(EdgeRecord {
+                repo_id: repo_id.to_string(),
+                kind: ANNOTATED_BY.to_string(),
+                source: symbol.id.clone(),
+                target: id.clone(),
+            });
+        }
+    }
+    let mut nodes: Vec<NodeRecord> = nodes.into_values().collect();
+    nodes.sort_by_key(|n| n.start);
+    Ok((nodes, edges))
+}
+
+/// The name and arguments of an annotation as written: `derive` and
+/// `(Debug)` for `#[derive(Debug)]`, `Override` and nothing for `@Override`.
+pub fn parse(text: &str) -> (&str, Option<&str>) {
+    let inner = match text.strip_prefix("#[") {
+        Some(rest) => rest.strip_suffix(']').unwrap_or(rest),
+        None => text.trim_start_matches('@'),
+    };
+    let inner = inner.trim();
+    let end = inner
+        .find(|c: char| c == '(' || c == '=' || c.is_whitespace())
+        .unwrap_or(inner.len());
+    let arguments = inner[end..].trim();
+    (&inner[..end], (!arguments.is_empty()).then_some(arguments))
+}
@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::path::{Component, Path, PathBuf};
+use tar::EntryType;
+
+/// Most bytes an uploaded archive may unpack to, so a small gzip bomb can't
+/// fill the disk.
+pub const MAX_UNPACKED_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Unpacks a `.tar.gz` into `dest` and returns the directory to ingest:
+/// `dest`, or the single top-level directory `git archive --prefix` and
+/// forge tarballs wrap everything in. Entries with a `..` component or an
+/// absolute path fail the whole upload rather than being skipped, as does
+/// running past [`MAX_UNPACKED_BYTES`]. Links, devices and the pax headers
+/// `git archive` adds are left out; only files and directories are written.
+pub fn unpack(archive: &[u8], dest: &Path) -> Result<PathBuf> {
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    let mut unpacked = 0u64;
+    for entry in archive.entries().context("not a gzipped tar archive")? {
+        let mut entry = entry.context("corrupt archive entry")?;
+        let path = entry.path().context("unreadable entry path")?.into_owned();
+        let relative = checked_path(&path)?;
+        let target = dest.join(&relative);
+        match entry.header().entry_type() {
+            EntryType::Directory => std::fs::create_dir_all(&target)
+                .with_context(|| format!("cannot create {}", relative.display()))?,
+            EntryType::Regular | EntryType::Continuous => {
+                unpacked += entry.size();
+                if unpacked > MAX_UNPACKED_BYTES {
+                    anyhow::bail!("archive unpacks to more than {} bytes", MAX_UNPACKED_BYTES);
+                }
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)
+                        .with_context(|| format!("cannot create {}", parent.display()))?;
+                }
+                entry
+                    .unpack(&target)
+                    .with_context(|| format!("cannot unpack {}", relative.display()))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(single_dir(dest)?.unwrap_or_else(|| dest.to_path_buf()))
+}
+
+/// `path` without `.` components, refused if it could land outside the
+/// directory it's unpacked into.
+fn checked_path(path: &Path) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                anyhow::bail!("archive entry {} steps outside the archive", path.display())
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                anyhow::bail!("archive entry {} is an absolute path", path.display())
+            }
+        }
+    }
+    Ok(relative)
+}
+
+fn single_dir(dest: &Path) -> Result<Option<PathBuf>> {
+    let mut entries = std::fs::read_dir(dest)
+        .with_context(|| format!("cannot read {}", dest.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if entries.len() != 1 {
+        return Ok(None);
+    }
+    let entry = entries.remove(0);
+    Ok(entry.file_type()?.is_dir().then(|| entry.path()))
+}
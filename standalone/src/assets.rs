@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// The UI's files, served as-is from the static directory.
+pub const STATIC_FILES: &[&str] = &["index.html", "styles.css", "app.js", "utils.js"];
+
+/// Served at `/` when no static directory is found, so the server still
+/// answers with something that says what's wrong.
+pub const FALLBACK_INDEX: &str = r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8" />
+    <title>Stakgraph</title>
+  </head>
+  <body>
+    <h1>Stakgraph</h1>
+    <p>The server is running, but its static files were not found.
+    Set <code>MESH_STATIC_DIR</code> to the directory holding <code>index.html</code>.</p>
+  </body>
+</html>
+"#;
+
+// how far up from the binary to look, enough for target/<profile>/deps
+const SEARCH_DEPTH: usize = 4;
+
+/// Where the UI's files are: the `configured` directory, else the first
+/// `static` (or `standalone/static`) directory with an `index.html` next to
+/// the running binary or above it. `None`, with a warning, when there's none;
+/// the working directory never matters.
+pub fn static_dir(configured: Option<&Path>) -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok();
+    let found = find_static_dir(configured, exe.as_deref());
+    if found.is_none() {
+        match configured {
+            Some(dir) => warn!(
+                "static directory {} has no index.html; serving a placeholder page",
+                dir.display()
+            ),
+            None => warn!(
+                "no static directory found near {}; set MESH_STATIC_DIR, serving a placeholder page",
+                exe.as_deref().unwrap_or(Path::new("the binary")).display()
+            ),
+        }
+    }
+    found
+}
+
+/// [`static_dir`] with the binary's path given. A configured directory is used or
+/// rejected as it is, never searched past.
+pub fn find_static_dir(configured: Option<&Path>, exe: Option<&Path>) -> Option<PathBuf> {
+    let has_index = |dir: &Path| dir.join("index.html").is_file();
+    if let Some(dir) = configured {
+        return has_index(dir).then(|| dir.to_path_buf());
+    }
+    exe?.ancestors()
+        .skip(1)
+        .take(SEARCH_DEPTH)
+        .flat_map(|dir| [dir.join("static"), dir.join("standalone").join("static")])
+        .find(|dir| has_index(dir))
+}
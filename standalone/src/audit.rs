@@ -0,0 +1,198 @@
+//! Who changed what: an entry for every ingest, fetch and clear, naming the
+//! key it was made with and how it went, appended to a file or the backend
+//! and listed by `/audit`.
+
+use crate::auth::Auth;
+use crate::storage::Storage;
+use crate::types::ErrorKind;
+use crate::{logging, AppState};
+use anyhow::{Context, Result};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+/// The `MESH_AUDIT_LOG` that keeps the log in the storage backend rather
+/// than a file.
+pub const BACKEND: &str = "backend";
+/// How many entries `/audit` lists unless asked for another number.
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+/// The identity of a request made without a key.
+pub const ANONYMOUS: &str = "anonymous";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Unix seconds when the operation finished.
+    pub timestamp: u64,
+    /// The request's `X-Request-Id`.
+    pub request_id: Option<String>,
+    /// The [`key_id`](crate::auth::key_id) of the key the request was made
+    /// with, or [`ANONYMOUS`].
+    pub identity: String,
+    /// The route, e.g. `process` or `clear`.
+    pub operation: String,
+    /// The repo operated on; `None` for every repo, or for a request refused
+    /// before its repo was known.
+    pub repo: Option<String>,
+    /// `succeeded`, or the kind of error the request failed with.
+    pub outcome: String,
+}
+
+enum Sink {
+    /// One JSON entry per line, opened for appending only.
+    File {
+        path: PathBuf,
+        file: Mutex<File>,
+    },
+    Backend,
+}
+
+pub struct AuditLog {
+    sink: Sink,
+}
+
+impl AuditLog {
+    /// The log `target` names: [`BACKEND`], or a file, created if need be.
+    pub fn open(target: &str) -> Result<Self> {
+        if target == BACKEND {
+            return Ok(AuditLog {
+                sink: Sink::Backend,
+            });
+        }
+        let path = Path::new(target);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open the audit log {}", path.display()))?;
+        Ok(AuditLog {
+            sink: Sink::File {
+                path: path.to_path_buf(),
+                file: Mutex::new(file),
+            },
+        })
+    }
+
+    pub async fn append(&self, storage: &dyn Storage, entry: &AuditEntry) -> Result<()> {
+        match &self.sink {
+            Sink::File { path, file } => {
+                let mut line = serde_json::to_vec(entry)?;
+                line.push(b'\n');
+                // one write per entry, so concurrent ones don't interleave
+                let mut file = file.lock().unwrap();
+                file.write_all(&line)
+                    .and_then(|_| file.flush())
+                    .with_context(|| format!("failed to write the audit log {}", path.display()))
+            }
+            Sink::Backend => storage.append_audit(entry).await,
+        }
+    }
+
+    /// The last `limit` entries, newest first.
+    pub async fn recent(&self, storage: &dyn Storage, limit: usize) -> Result<Vec<AuditEntry>> {
+        match &self.sink {
+            Sink::File { path, .. } => {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || read_last(&path, limit)).await?
+            }
+            Sink::Backend => storage.audit_entries(limit).await,
+        }
+    }
+}
+
+fn read_last(path: &Path, limit: usize) -> Result<Vec<AuditEntry>> {
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+    let file = File::open(path)
+        .with_context(|| format!("failed to read the audit log {}", path.display()))?;
+    let mut last = VecDeque::with_capacity(limit.min(MAX_LIMIT));
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if last.len() == limit {
+            last.pop_front();
+        }
+        last.push_back(line);
+    }
+    last.iter()
+        .rev()
+        .map(|line| {
+            serde_json::from_str(line)
+                .with_context(|| format!("bad entry in the audit log {}", path.display()))
+        })
+        .collect()
+}
+
+tokio::task_local! {
+    static REPO: Arc<Mutex<Option<String>>>;
+}
+
+/// Names the repo of the request being audited, for a handler to call once
+/// it knows it; a no-op anywhere else, and for an empty id.
+pub fn note_repo(repo: &str) {
+    if repo.is_empty() {
+        return;
+    }
+    let _ = REPO.try_with(|noted| *noted.lock().unwrap() = Some(repo.to_string()));
+}
+
+fn identity(auth: Option<&Auth>, headers: &HeaderMap) -> String {
+    auth.and_then(|auth| auth.identity(headers))
+        .unwrap_or_else(|| ANONYMOUS.to_string())
+}
+
+/// Middleware appending an entry for every request to `operation` to the
+/// audit log, when there is one, once it has been handled. An entry that
+/// can't be written is logged rather than failing the request, which has
+/// already had its effect. A `GET` of the route, like asking `/clear` for a
+/// token, changes nothing and isn't recorded.
+pub async fn record(
+    State((state, operation)): State<(Arc<AppState>, &'static str)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(log) = state.audit.clone() else {
+        return next.run(request).await;
+    };
+    if request.method() == Method::GET {
+        return next.run(request).await;
+    }
+    let identity = identity(state.auth.as_deref(), request.headers());
+    let repo = Arc::new(Mutex::new(None));
+    let response = REPO.scope(repo.clone(), next.run(request)).await;
+    let outcome = match response.extensions().get::<ErrorKind>() {
+        Some(ErrorKind(kind)) => kind.to_string(),
+        None if response.status().is_success() => "succeeded".to_string(),
+        None => response.status().as_u16().to_string(),
+    };
+    let entry = AuditEntry {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        request_id: logging::current(),
+        identity,
+        operation: operation.to_string(),
+        repo: repo.lock().unwrap().take(),
+        outcome,
+    };
+    if let Err(e) = log.append(state.storage.as_ref(), &entry).await {
+        error!(
+            "failed to audit {} by {}: {:#}",
+            operation, entry.identity, e
+        );
+    }
+    response
+}
@@ -0,0 +1,159 @@
+use crate::types::MeshError;
+use crate::AppState;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The groups of routes a key can be required for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Every route that writes to the graph or starts or stops work.
+    Mutating,
+    /// `/events`, `/events/stats` and `/ws`.
+    Events,
+    /// The UI's static files.
+    Static,
+    /// `/audit`, and raw statements sent to `/graph/query`, which only the
+    /// admin keys open, whatever else is protected.
+    Admin,
+}
+
+/// The keys clients present as `Authorization: Bearer <key>`. Only their
+/// digests are kept, so comparing them takes the same time whatever the key.
+pub struct ApiKeys {
+    digests: Vec<[u8; 32]>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: &[&str]) -> Self {
+        ApiKeys {
+            digests: keys
+                .iter()
+                .map(|k| k.trim())
+                .filter(|k| !k.is_empty())
+                .map(digest)
+                .collect(),
+        }
+    }
+
+    /// Whether no key was given, which leaves every route open.
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+
+    /// Whether `key` is one of the keys. Every key is compared, in constant
+    /// time, so neither the match nor its position leaks through timing.
+    pub fn verify(&self, key: &str) -> bool {
+        let presented = digest(key);
+        self.digests
+            .iter()
+            .fold(false, |found, d| constant_time_eq(d, &presented) | found)
+    }
+}
+
+fn digest(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+/// How the audit log names `key`: the first 12 hex digits of its SHA-256,
+/// enough to tell keys apart without giving any of them away.
+pub fn key_id(key: &str) -> String {
+    hex::encode(&digest(key)[..6])
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The keys and which routes need one.
+pub struct Auth {
+    keys: ApiKeys,
+    admin: ApiKeys,
+    protected: HashSet<Scope>,
+}
+
+impl Auth {
+    pub fn new(keys: ApiKeys, protected: &[Scope]) -> Self {
+        Auth {
+            keys,
+            admin: ApiKeys::new(&[]),
+            protected: protected.iter().copied().collect(),
+        }
+    }
+
+    /// Keys that open [`Scope::Admin`] as well as everything the other keys
+    /// do.
+    pub fn with_admin_keys(mut self, admin: ApiKeys) -> Self {
+        self.admin = admin;
+        self
+    }
+
+    pub fn protects(&self, scope: Scope) -> bool {
+        scope == Scope::Admin || self.protected.contains(&scope)
+    }
+
+    /// Whether the request carries one of the keys, or an admin key, as a
+    /// bearer token.
+    pub fn authorize(&self, headers: &HeaderMap) -> bool {
+        bearer(headers).is_some_and(|key| self.keys.verify(key) | self.admin.verify(key))
+    }
+
+    /// Whether the request carries one of the admin keys.
+    pub fn authorize_admin(&self, headers: &HeaderMap) -> bool {
+        bearer(headers).is_some_and(|key| self.admin.verify(key))
+    }
+
+    /// The [`key_id`] of the key the request carries, when it is one of
+    /// them.
+    pub fn identity(&self, headers: &HeaderMap) -> Option<String> {
+        bearer(headers)
+            .filter(|key| self.keys.verify(key) | self.admin.verify(key))
+            .map(key_id)
+    }
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, key)| key.trim())
+}
+
+/// Whether a request with `headers` is turned away from `scope`: when the
+/// server has keys, protects that scope and none of them was presented.
+/// [`Scope::Admin`] always needs an admin key, so without any it is shut.
+pub fn refuses(state: &AppState, scope: Scope, headers: &HeaderMap) -> bool {
+    match (&state.auth, scope) {
+        (Some(auth), Scope::Admin) => !auth.authorize_admin(headers),
+        (None, Scope::Admin) => true,
+        (Some(auth), scope) => auth.protects(scope) && !auth.authorize(headers),
+        (None, _) => false,
+    }
+}
+
+/// Middleware for the routes in `scope`: 401 when [`refuses`] says so, a
+/// pass-through otherwise.
+pub async fn require_key(
+    State((state, scope)): State<(Arc<AppState>, Scope)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if refuses(&state, scope, request.headers()) {
+        let message = match scope {
+            Scope::Admin => "Missing or invalid admin key",
+            _ => "Missing or invalid API key",
+        };
+        let mut response = MeshError::Unauthorized(message.to_string()).into_response();
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        return response;
+    }
+    next.run(request).await
+}
@@ -0,0 +1,88 @@
+//! Ingesting an editor's unsaved buffer of a file in place of the file on
+//! disk. The parsers only read checkouts, so the buffer is written to a
+//! scratch checkout of its own and parsed there, and what's parsed is then
+//! moved to the repo's paths. The buffer stands for the file until the next
+//! ingest from disk, which the hashes stored for it make sure parses the
+//! file again.
+
+use crate::projects::MANIFESTS;
+use crate::storage::{EdgeRecord, NodeRecord};
+use anyhow::{Context, Result};
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Stored as the hash of a file, and of its repo's commit, once a buffer
+/// has been ingested for it, so neither matches what's on disk.
+pub const BUFFER_HASH: &str = "buffer";
+
+/// A scratch checkout holding `content` as `file`, and copies of the
+/// manifests of the directories above it in `repo_path`, so the file's
+/// language and project are told as they would be in the repo.
+pub fn checkout(repo_path: &Path, file: &str, content: &str) -> Result<TempDir> {
+    let scratch = tempfile::Builder::new()
+        .prefix("mesh-buffer")
+        .tempdir()
+        .context("failed to create a checkout for the buffer")?;
+    for dir in Path::new(file).ancestors().skip(1) {
+        for manifest in MANIFESTS {
+            let from = repo_path.join(dir).join(manifest);
+            if from.is_file() {
+                std::fs::create_dir_all(scratch.path().join(dir))?;
+                std::fs::copy(&from, scratch.path().join(dir).join(manifest))
+                    .with_context(|| format!("failed to copy {}", from.display()))?;
+            }
+        }
+    }
+    let path = scratch.path().join(file);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, content).with_context(|| format!("failed to write {}", file))?;
+    Ok(scratch)
+}
+
+/// Moves `nodes` and `edges` parsed in the checkout at `scratch` to
+/// `repo_path`: files stored under `scratch` are stored under `repo_path`
+/// instead, and the ids made from them, and the edges between them, follow.
+/// Nothing changes for files stored relative to their checkout.
+pub fn rebase(nodes: &mut [NodeRecord], edges: &mut [EdgeRecord], scratch: &str, repo_path: &str) {
+    let scratch = format!("{}/", scratch.trim_end_matches('/'));
+    let repo_path = format!("{}/", repo_path.trim_end_matches('/'));
+    let mut moved = Vec::new();
+    for node in nodes.iter_mut() {
+        let Some(at) = node.file.find(&scratch) else {
+            continue;
+        };
+        let file = format!(
+            "{}{}{}",
+            &node.file[..at],
+            repo_path,
+            &node.file[at + scratch.len()..]
+        );
+        // ids hold their file as `kind_key` writes it, between dashes
+        let (from, to) = (key_part(&node.file), key_part(&file));
+        let id = node.id.replacen(&from, &to, 1);
+        if id != node.id {
+            moved.push((node.id.clone(), id.clone()));
+            node.id = id;
+        }
+        node.file = file;
+    }
+    let moved: std::collections::HashMap<String, String> = moved.into_iter().collect();
+    for edge in edges.iter_mut() {
+        for end in [&mut edge.source, &mut edge.target] {
+            if let Some(id) = moved.get(end.as_str()) {
+                *end = id.clone();
+            }
+        }
+    }
+}
+
+fn key_part(file: &str) -> String {
+    let part: String = file
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    format!("-{}-", part)
+}
@@ -0,0 +1,554 @@
+use crate::fanout;
+use crate::imports::{self, Target};
+use crate::storage::{EdgeRecord, NodeRecord};
+use crate::symbols::SymbolIndex;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Names that look like calls in most languages but never are.
+const NOT_CALLS: &[&str] = &[
+    "if", "for", "while", "match", "switch", "return", "catch", "fn", "function", "def", "func",
+    "elif", "and", "or", "not", "sizeof", "typeof", "new", "await", "async", "super", "self",
+];
+
+/// Levels `/callers` and `/callees` go out by default, and at most.
+pub const DEFAULT_CHAIN_DEPTH: usize = 1;
+pub const MAX_CHAIN_DEPTH: usize = 10;
+
+const SAME_FILE: f32 = 1.0;
+const IMPORTED: f32 = 0.8;
+const ELSEWHERE: f32 = 0.5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    /// 1.0 when the callee was resolved by `ast` or defined in the caller's file;
+    /// split between candidates when a name has several equally likely definitions.
+    pub confidence: f32,
+    /// The repo defining the callee, when [`link_across_repos`] found it in
+    /// another repo than the caller's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Call {
+    pub name: String,
+    /// Most likely first.
+    pub candidates: Vec<Candidate>,
+    /// Candidates the edge cap left out, see [`CallGraph::cap_fan_out`].
+    #[serde(default, skip_serializing_if = "fanout::is_zero")]
+    pub omitted: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FunctionCalls {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    pub calls: Vec<Call>,
+    /// Called names with no definition in the graph, i.e. library or external symbols.
+    pub unresolved: Vec<String>,
+    /// Calls the edge cap left out.
+    #[serde(default, skip_serializing_if = "fanout::is_zero")]
+    pub omitted: usize,
+}
+
+/// Caller -> callee adjacency for every function in the graph.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CallGraph {
+    pub functions: Vec<FunctionCalls>,
+}
+
+/// A function calling a definition.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    /// 1-based lines of the calls in `file`; the caller's first line when
+    /// `ast` resolved the call but the body doesn't spell it out.
+    pub lines: Vec<usize>,
+    /// The call's confidence for this definition, as in [`Candidate`].
+    pub confidence: f32,
+}
+
+/// A definition and the functions calling it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Usages {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    /// 1-based line of the definition.
+    pub line: usize,
+    /// Most confident first.
+    pub references: Vec<Reference>,
+    /// References the edge cap left out, see [`cap_references`].
+    #[serde(default, skip_serializing_if = "fanout::is_zero")]
+    pub omitted: usize,
+}
+
+/// Which way [`call_chains`] follows calls from its root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// To the functions calling the root.
+    Callers,
+    /// To the functions the root calls.
+    Callees,
+}
+
+/// A function [`call_chains`] reached from its root.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reached {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    /// Calls between it and the root; `1` for a direct caller or callee.
+    pub distance: usize,
+    /// The function one call closer to the root it was reached through.
+    pub via: String,
+}
+
+/// The functions within some number of calls of one definition.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CallChain {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    /// Nearest first, each function once, at its shortest distance.
+    pub functions: Vec<Reached>,
+    /// Whether a chain of calls within the depth leads back to the root, as
+    /// recursion does.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recursive: bool,
+}
+
+/// Resolves calls across files. Edges `ast` already resolved are taken as-is;
+/// other call sites are found by name in function bodies and matched against
+/// every definition of that name, preferring the caller's own file, then files
+/// it imports.
+pub fn resolve_calls(nodes: &[NodeRecord], edges: &[EdgeRecord]) -> CallGraph {
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut definitions: HashMap<&str, Vec<&NodeRecord>> = HashMap::new();
+    for node in nodes.iter().filter(|n| n.kind == "Function") {
+        definitions
+            .entry(node.name.as_str())
+            .or_default()
+            .push(node);
+    }
+    let mut imports: HashMap<&str, Vec<&str>> = HashMap::new();
+    for node in nodes.iter().filter(|n| n.kind == "Import") {
+        imports
+            .entry(node.file.as_str())
+            .or_default()
+            .push(&node.body);
+    }
+    let mut known: HashMap<&str, Vec<&NodeRecord>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.kind == "CALLS") {
+        if let Some(target) = by_id.get(edge.target.as_str()) {
+            known.entry(edge.source.as_str()).or_default().push(target);
+        }
+    }
+
+    let mut functions = Vec::new();
+    for caller in nodes.iter().filter(|n| n.kind == "Function") {
+        let mut calls: BTreeMap<String, Vec<Candidate>> = BTreeMap::new();
+        for target in known.get(caller.id.as_str()).into_iter().flatten() {
+            calls
+                .entry(target.name.clone())
+                .or_default()
+                .push(candidate(target, SAME_FILE));
+        }
+
+        let mut unresolved = BTreeSet::new();
+        for name in call_sites(caller) {
+            if calls.contains_key(&name) {
+                continue;
+            }
+            let Some(defs) = definitions.get(name.as_str()) else {
+                unresolved.insert(name);
+                continue;
+            };
+            let caller_imports = imports.get(caller.file.as_str());
+            let scored: Vec<(f32, &NodeRecord)> = defs
+                .iter()
+                .map(|def| (score(caller, def, caller_imports), *def))
+                .collect();
+            let mut candidates: Vec<Candidate> = scored
+                .iter()
+                .map(|(base, def)| {
+                    let ties = scored.iter().filter(|(s, _)| s == base).count() as f32;
+                    candidate(def, base / ties)
+                })
+                .collect();
+            candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+            calls.insert(name, candidates);
+        }
+
+        functions.push(FunctionCalls {
+            id: caller.id.clone(),
+            name: caller.name.clone(),
+            file: caller.file.clone(),
+            calls: calls
+                .into_iter()
+                .map(|(name, candidates)| Call {
+                    name,
+                    candidates,
+                    omitted: 0,
+                })
+                .collect(),
+            unresolved: unresolved.into_iter().collect(),
+            omitted: 0,
+        });
+    }
+    CallGraph { functions }
+}
+
+/// Links the calls `graph` left unresolved to definitions in other repos of
+/// the mesh: a name the caller's file imports from a module outside its repo
+/// is looked up in `index` under that module. Names the index can't place,
+/// as when the repo defining them isn't ingested, stay unresolved.
+pub fn link_across_repos(graph: &mut CallGraph, nodes: &[NodeRecord], index: &SymbolIndex) {
+    if index.is_empty() {
+        return;
+    }
+    let mut external: HashMap<String, Vec<String>> = HashMap::new();
+    for import in imports::resolve_imports(nodes) {
+        if let Target::External(module) = import.target {
+            external.entry(import.file).or_default().push(module);
+        }
+    }
+    for function in &mut graph.functions {
+        let Some(modules) = external.get(&function.file) else {
+            continue;
+        };
+        let mut linked = Vec::new();
+        function.unresolved.retain(|name| {
+            let mut found: Vec<_> = modules
+                .iter()
+                .filter_map(|module| index.symbol(module, name))
+                .collect();
+            found.sort_by(|a, b| (&a.repo_id, &a.id).cmp(&(&b.repo_id, &b.id)));
+            found.dedup_by(|a, b| a.id == b.id && a.repo_id == b.repo_id);
+            let [symbol] = found.as_slice() else {
+                return true;
+            };
+            linked.push(Call {
+                name: name.clone(),
+                candidates: vec![Candidate {
+                    id: symbol.id.clone(),
+                    name: symbol.name.clone(),
+                    file: symbol.file.clone(),
+                    confidence: IMPORTED,
+                    repo_id: Some(symbol.repo_id.clone()),
+                }],
+                omitted: 0,
+            });
+            false
+        });
+        if !linked.is_empty() {
+            function.calls.extend(linked);
+            function.calls.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+    }
+}
+
+impl CallGraph {
+    /// Only the functions reachable from any function named `root`, through
+    /// any candidate of any call.
+    pub fn reachable_from(self, root: &str) -> Self {
+        let index: HashMap<&str, &FunctionCalls> =
+            self.functions.iter().map(|f| (f.id.as_str(), f)).collect();
+        let mut seen = BTreeSet::new();
+        let mut queue: VecDeque<&str> = self
+            .functions
+            .iter()
+            .filter(|f| f.name == root)
+            .map(|f| f.id.as_str())
+            .collect();
+        while let Some(id) = queue.pop_front() {
+            if !seen.insert(id.to_string()) {
+                continue;
+            }
+            if let Some(function) = index.get(id) {
+                for call in &function.calls {
+                    queue.extend(call.candidates.iter().map(|c| c.id.as_str()));
+                }
+            }
+        }
+        let functions = self
+            .functions
+            .iter()
+            .filter(|f| seen.contains(&f.id))
+            .cloned()
+            .collect();
+        CallGraph { functions }
+    }
+
+    /// Cuts every function's calls, and every call's candidates, down to
+    /// `max`, see [`crate::fanout`]. Candidates are ranked by confidence and
+    /// calls by their likeliest candidate's, ties going to the callee more
+    /// functions call, as the more central one.
+    pub fn cap_fan_out(&mut self, max: Option<usize>) {
+        if max.is_none() {
+            return;
+        }
+        let callers = self.callers();
+        let callers_of = |id: &str| callers.get(id).copied().unwrap_or_default();
+        let best = |call: &Call| {
+            call.candidates
+                .first()
+                .map(|c| (c.confidence, callers_of(&c.id)))
+                .unwrap_or_default()
+        };
+        for function in &mut self.functions {
+            for call in &mut function.calls {
+                call.omitted += fanout::cap(&mut call.candidates, max, |a, b| {
+                    b.confidence
+                        .total_cmp(&a.confidence)
+                        .then_with(|| callers_of(&b.id).cmp(&callers_of(&a.id)))
+                        .then_with(|| a.id.cmp(&b.id))
+                });
+            }
+            function.omitted += fanout::cap(&mut function.calls, max, |a, b| {
+                let ((a_confidence, a_callers), (b_confidence, b_callers)) = (best(a), best(b));
+                b_confidence
+                    .total_cmp(&a_confidence)
+                    .then_with(|| b_callers.cmp(&a_callers))
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+        }
+    }
+
+    /// How many functions call each one, through any candidate.
+    fn callers(&self) -> HashMap<String, usize> {
+        let mut callers: HashMap<String, usize> = HashMap::new();
+        for function in &self.functions {
+            let callees: BTreeSet<&str> = function
+                .calls
+                .iter()
+                .flat_map(|c| &c.candidates)
+                .map(|c| c.id.as_str())
+                .collect();
+            for callee in callees {
+                *callers.entry(callee.to_string()).or_default() += 1;
+            }
+        }
+        callers
+    }
+}
+
+/// Cuts the references of each of `usages` down to `max`, see
+/// [`crate::fanout`]: the most confident first, ties going to the calling
+/// function that is itself called the most, as the more central one.
+pub fn cap_references(usages: &mut [Usages], graph: &CallGraph, max: Option<usize>) {
+    if max.is_none() {
+        return;
+    }
+    let callers = graph.callers();
+    let callers_of = |id: &str| callers.get(id).copied().unwrap_or_default();
+    for usage in usages {
+        usage.omitted += fanout::cap(&mut usage.references, max, |a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| callers_of(&b.id).cmp(&callers_of(&a.id)))
+                .then_with(|| (&a.file, &a.lines).cmp(&(&b.file, &b.lines)))
+        });
+    }
+}
+
+/// The usages of every function named `name`, from the calls in `graph`. A
+/// call that could reach several definitions of the name is listed under each
+/// of them, with that candidate's confidence.
+pub fn references(graph: &CallGraph, nodes: &[NodeRecord], name: &str) -> Vec<Usages> {
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut usages: Vec<Usages> = nodes
+        .iter()
+        .filter(|n| n.kind == "Function" && n.name == name)
+        .map(|def| Usages {
+            id: def.id.clone(),
+            name: def.name.clone(),
+            file: def.file.clone(),
+            line: def.start + 1,
+            references: Vec::new(),
+            omitted: 0,
+        })
+        .collect();
+    for function in &graph.functions {
+        for call in function.calls.iter().filter(|c| c.name == name) {
+            for candidate in call.candidates.iter().filter(|c| c.repo_id.is_none()) {
+                let Some(usage) = usages.iter_mut().find(|u| u.id == candidate.id) else {
+                    continue;
+                };
+                let lines = by_id
+                    .get(function.id.as_str())
+                    .map(|caller| call_lines(caller, name))
+                    .unwrap_or_default();
+                usage.references.push(Reference {
+                    id: function.id.clone(),
+                    name: function.name.clone(),
+                    file: function.file.clone(),
+                    lines,
+                    confidence: candidate.confidence,
+                });
+            }
+        }
+    }
+    for usage in &mut usages {
+        usage.references.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| (&a.file, &a.lines).cmp(&(&b.file, &b.lines)))
+        });
+    }
+    usages.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    usages
+}
+
+/// The callers or callees of every function named `name`, out to `depth`
+/// calls, through any candidate of any call. Each function is listed once
+/// at the distance it's first reached at, so a cycle ends the chain instead
+/// of going round it.
+pub fn call_chains(
+    graph: &CallGraph,
+    name: &str,
+    direction: Direction,
+    depth: usize,
+) -> Vec<CallChain> {
+    let functions: HashMap<&str, &FunctionCalls> =
+        graph.functions.iter().map(|f| (f.id.as_str(), f)).collect();
+    let mut next: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for function in &graph.functions {
+        for candidate in function.calls.iter().flat_map(|c| &c.candidates) {
+            if candidate.repo_id.is_some() {
+                continue;
+            }
+            let (from, to) = match direction {
+                Direction::Callees => (function.id.as_str(), candidate.id.as_str()),
+                Direction::Callers => (candidate.id.as_str(), function.id.as_str()),
+            };
+            next.entry(from).or_default().insert(to);
+        }
+    }
+    let mut chains: Vec<CallChain> = graph
+        .functions
+        .iter()
+        .filter(|f| f.name == name)
+        .map(|root| {
+            let mut seen = BTreeSet::from([root.id.as_str()]);
+            let mut layer = vec![root.id.as_str()];
+            let mut reached = Vec::new();
+            let mut recursive = false;
+            for distance in 1..=depth {
+                let mut found = Vec::new();
+                for &from in &layer {
+                    for &to in next.get(from).into_iter().flatten() {
+                        recursive |= to == root.id;
+                        if !seen.insert(to) {
+                            continue;
+                        }
+                        let Some(function) = functions.get(to) else {
+                            continue;
+                        };
+                        found.push(to);
+                        reached.push(Reached {
+                            id: function.id.clone(),
+                            name: function.name.clone(),
+                            file: function.file.clone(),
+                            distance,
+                            via: from.to_string(),
+                        });
+                    }
+                }
+                if found.is_empty() {
+                    break;
+                }
+                layer = found;
+            }
+            reached.sort_by(|a, b| {
+                (a.distance, &a.file, &a.name).cmp(&(b.distance, &b.file, &b.name))
+            });
+            CallChain {
+                id: root.id.clone(),
+                name: root.name.clone(),
+                file: root.file.clone(),
+                functions: reached,
+                recursive,
+            }
+        })
+        .collect();
+    chains.sort_by(|a, b| (&a.file, &a.id).cmp(&(&b.file, &b.id)));
+    chains
+}
+
+/// The lines of `caller` calling `name`, skipping its own signature.
+fn call_lines(caller: &NodeRecord, name: &str) -> Vec<usize> {
+    let re = Regex::new(&format!(r"\b{}\s*\(", regex::escape(name))).unwrap();
+    let mut lines = Vec::new();
+    let mut skipped_signature = caller.name != name;
+    for (offset, line) in caller.body.lines().enumerate() {
+        for _ in re.find_iter(line) {
+            if !skipped_signature {
+                skipped_signature = true;
+                continue;
+            }
+            lines.push(caller.start + offset + 1);
+        }
+    }
+    lines.dedup();
+    if lines.is_empty() {
+        lines.push(caller.start + 1);
+    }
+    lines
+}
+
+fn candidate(node: &NodeRecord, confidence: f32) -> Candidate {
+    Candidate {
+        id: node.id.clone(),
+        name: node.name.clone(),
+        file: node.file.clone(),
+        confidence,
+        repo_id: None,
+    }
+}
+
+fn score(caller: &NodeRecord, def: &NodeRecord, caller_imports: Option<&Vec<&str>>) -> f32 {
+    if def.file == caller.file {
+        return SAME_FILE;
+    }
+    let stem = Path::new(&def.file)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let imported = !stem.is_empty()
+        && caller_imports.is_some_and(|bodies| bodies.iter().any(|b| b.contains(stem)));
+    if imported {
+        IMPORTED
+    } else {
+        ELSEWHERE
+    }
+}
+
+/// Names called in the function body, skipping its own name in the signature.
+fn call_sites(function: &NodeRecord) -> BTreeSet<String> {
+    static CALL: OnceLock<Regex> = OnceLock::new();
+    let re = CALL.get_or_init(|| Regex::new(r"([A-Za-z_][A-Za-z0-9_]*)\s*\(").unwrap());
+    let mut names = BTreeSet::new();
+    let mut skipped_signature = false;
+    for capture in re.captures_iter(&function.body) {
+        let name = &capture[1];
+        if !skipped_signature && name == function.name {
+            skipped_signature = true;
+            continue;
+        }
+        if !NOT_CALLS.contains(&name) {
+            names.insert(name.to_string());
+        }
+    }
+    names
+}
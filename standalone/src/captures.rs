@@ -0,0 +1,457 @@
+use crate::grep::{TextRule, COMMENT_KIND, STRING_KIND, TEXT_RULES};
+use crate::lang::{self, Diagnostic, Severity};
+use crate::source::Source;
+use crate::storage::Span;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Language, Query, QueryCursor, Tree};
+
+/// A grammar custom queries can be written against, named as the directory
+/// holding its queries.
+pub struct Grammar {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    language: fn() -> Language,
+}
+
+impl Grammar {
+    pub fn language(&self) -> Language {
+        (self.language)()
+    }
+
+    /// The grammar of the language `path` is written in, if there is one.
+    pub fn for_path(path: &Path) -> Option<&'static Grammar> {
+        let ext = path.extension()?.to_str()?;
+        GRAMMARS.iter().find(|g| g.extensions.contains(&ext))
+    }
+
+    /// Parses `source`, or returns `None` when that runs past `timeout`.
+    pub fn parse(&self, source: &str, timeout: Option<Duration>) -> Result<Option<Tree>> {
+        lang::parse(&self.language(), source, timeout)
+    }
+}
+
+/// The languages `ast` parses that custom queries can extend.
+pub const GRAMMARS: &[Grammar] = &[
+    Grammar {
+        name: "rust",
+        extensions: &["rs"],
+        language: || tree_sitter_rust::LANGUAGE.into(),
+    },
+    Grammar {
+        name: "python",
+        extensions: &["py"],
+        language: || tree_sitter_python::LANGUAGE.into(),
+    },
+    Grammar {
+        name: "go",
+        extensions: &["go"],
+        language: || tree_sitter_go::LANGUAGE.into(),
+    },
+    Grammar {
+        name: "typescript",
+        extensions: &["ts"],
+        language: || tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+    },
+    // the tsx grammar parses plain javascript too
+    Grammar {
+        name: "tsx",
+        extensions: &["tsx", "js", "jsx"],
+        language: || tree_sitter_typescript::LANGUAGE_TSX.into(),
+    },
+    Grammar {
+        name: "java",
+        extensions: &["java"],
+        language: || tree_sitter_java::LANGUAGE.into(),
+    },
+    Grammar {
+        name: "ruby",
+        extensions: &["rb"],
+        language: || tree_sitter_ruby::LANGUAGE.into(),
+    },
+];
+
+/// A node of a user-named kind, matched by a custom query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomNode {
+    pub kind: String,
+    pub name: String,
+    pub file: String,
+    pub body: String,
+    pub start: usize,
+    pub end: usize,
+    pub span: Span,
+}
+
+/// An edge kind defined by a tree-sitter query, one `[[custom_edges]]`
+/// table of `mesh.toml`. Each match links the node enclosing its `source`
+/// capture to the node enclosing its `target` capture.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EdgeDefinition {
+    /// The kind of the edges, e.g. `HANDLES_ROUTE`.
+    pub edge: String,
+    /// A name of [`GRAMMARS`].
+    pub language: String,
+    pub query: String,
+    /// The capture, without its `@`, the edge starts from.
+    pub source: String,
+    /// The capture the edge points to.
+    pub target: String,
+}
+
+/// Where one end of a [`CustomEdge`] was captured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub span: Span,
+}
+
+/// A match of an [`EdgeDefinition`]'s query, before its ends are resolved
+/// to nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomEdge {
+    pub kind: String,
+    pub file: String,
+    pub source: Endpoint,
+    pub target: Endpoint,
+}
+
+struct EdgeQuery {
+    kind: String,
+    query: Query,
+    source: u32,
+    target: u32,
+}
+
+struct KindQuery {
+    kind: String,
+    query: Query,
+    /// Indexes comments or strings, whose nodes are named by their first line.
+    text: bool,
+}
+
+// how much of a comment or string's first line names its node
+const TEXT_NAME_LEN: usize = 80;
+
+struct LanguageQueries {
+    language: Language,
+    queries: Vec<KindQuery>,
+    edges: Vec<EdgeQuery>,
+}
+
+/// Tree-sitter queries loaded from `.scm` files, run over every file of
+/// their language after the built-in extraction. The directory holds one
+/// subdirectory per language of [`GRAMMARS`], e.g. `rust/Route.scm`; each
+/// file's name is the kind of the nodes its query matches. A match's `@name`
+/// capture names the node, and its `@definition` capture, or the name when
+/// there's none, gives the node's body and span. [`Self::index_text`] adds
+/// built-in queries for comments and strings, and [`Self::define_edges`]
+/// queries for edges.
+#[derive(Default)]
+pub struct CustomQueries {
+    languages: Vec<LanguageQueries>,
+    by_extension: HashMap<&'static str, usize>,
+}
+
+impl CustomQueries {
+    /// Compiles every query under `dir`, failing on the first that is
+    /// invalid, names no `@name`, or sits in a directory that isn't a
+    /// language.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut custom = CustomQueries::default();
+        let mut entries: Vec<_> = std::fs::read_dir(dir)
+            .with_context(|| format!("failed to read query directory {}", dir.display()))?
+            .collect::<std::io::Result<_>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries.iter().filter(|e| e.path().is_dir()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(grammar) = GRAMMARS.iter().find(|g| g.name == name) else {
+                let known: Vec<&str> = GRAMMARS.iter().map(|g| g.name).collect();
+                bail!(
+                    "unknown language '{}' in {}, expected one of {}",
+                    name,
+                    dir.display(),
+                    known.join(", ")
+                );
+            };
+            let language = grammar.language();
+            let queries = load_language(&entry.path(), &language)?;
+            if queries.is_empty() {
+                continue;
+            }
+            let idx = custom.languages.len();
+            for ext in grammar.extensions {
+                custom.by_extension.insert(ext, idx);
+            }
+            custom.languages.push(LanguageQueries {
+                language,
+                queries,
+                edges: Vec::new(),
+            });
+        }
+        Ok(custom)
+    }
+
+    /// Adds queries indexing the comments and string literals of every
+    /// language with a [`TextRule`], as [`COMMENT_KIND`] and [`STRING_KIND`]
+    /// nodes, for `/grep`.
+    pub fn index_text(&mut self) -> Result<()> {
+        for rule in TEXT_RULES {
+            let Some(grammar) = GRAMMARS.iter().find(|g| g.name == rule.grammar) else {
+                continue;
+            };
+            let language = grammar.language();
+            let mut queries = Vec::new();
+            for (kind, kinds) in [(COMMENT_KIND, rule.comments), (STRING_KIND, rule.strings)] {
+                let query = Query::new(&language, &TextRule::query(kinds))
+                    .with_context(|| format!("invalid {} query for {}", kind, rule.grammar))?;
+                queries.push(KindQuery {
+                    kind: kind.to_string(),
+                    query,
+                    text: true,
+                });
+            }
+            self.language_mut(grammar).queries.extend(queries);
+        }
+        Ok(())
+    }
+
+    /// Compiles the queries of `edges`, failing on the first that is
+    /// invalid, is for a language that isn't one of [`GRAMMARS`], or lacks
+    /// its `source` or `target` capture.
+    pub fn define_edges(&mut self, edges: &[EdgeDefinition]) -> Result<()> {
+        for definition in edges {
+            let edge = &definition.edge;
+            // kinds end up as relationship types, so they're kept to identifiers
+            let valid = edge.chars().next().is_some_and(|c| c.is_ascii_uppercase())
+                && edge
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+            if !valid {
+                bail!(
+                    "invalid edge kind '{}': it must be capital letters, digits and underscores",
+                    edge
+                );
+            }
+            let Some(grammar) = GRAMMARS.iter().find(|g| g.name == definition.language) else {
+                let known: Vec<&str> = GRAMMARS.iter().map(|g| g.name).collect();
+                bail!(
+                    "unknown language '{}' for edge {}, expected one of {}",
+                    definition.language,
+                    edge,
+                    known.join(", ")
+                );
+            };
+            let query = Query::new(&grammar.language(), &definition.query)
+                .with_context(|| format!("invalid query for edge {}", edge))?;
+            let capture = |name: &str| {
+                let name = name.trim_start_matches('@');
+                query
+                    .capture_index_for_name(name)
+                    .with_context(|| format!("the query for edge {} captures no @{}", edge, name))
+            };
+            let (source, target) = (capture(&definition.source)?, capture(&definition.target)?);
+            self.language_mut(grammar).edges.push(EdgeQuery {
+                kind: edge.clone(),
+                query,
+                source,
+                target,
+            });
+        }
+        Ok(())
+    }
+
+    /// The queries of `grammar`, added with none if there aren't any yet.
+    fn language_mut(&mut self, grammar: &Grammar) -> &mut LanguageQueries {
+        let known = grammar
+            .extensions
+            .iter()
+            .find_map(|ext| self.by_extension.get(ext));
+        let idx = match known {
+            Some(idx) => *idx,
+            None => {
+                let idx = self.languages.len();
+                for ext in grammar.extensions {
+                    self.by_extension.insert(ext, idx);
+                }
+                self.languages.push(LanguageQueries {
+                    language: grammar.language(),
+                    queries: Vec::new(),
+                    edges: Vec::new(),
+                });
+                idx
+            }
+        };
+        &mut self.languages[idx]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_empty()
+    }
+
+    /// Whether there are queries for `path`'s language.
+    pub fn claims(&self, path: &Path) -> bool {
+        self.queries_for(path).is_some()
+    }
+
+    fn queries_for(&self, path: &Path) -> Option<&LanguageQueries> {
+        let ext = path.extension()?.to_str()?;
+        self.by_extension.get(ext).map(|idx| &self.languages[*idx])
+    }
+
+    /// The nodes and edges the queries for `file`'s language match in
+    /// `source`. A parse past `timeout` yields none and a warning instead.
+    pub fn extract<S: Source + ?Sized>(
+        &self,
+        file: &str,
+        source: &S,
+        timeout: Option<Duration>,
+    ) -> Result<(Vec<CustomNode>, Vec<CustomEdge>, Option<Diagnostic>)> {
+        let Some(language) = self.queries_for(Path::new(file)) else {
+            return Ok((Vec::new(), Vec::new(), None));
+        };
+        let Some(tree) = lang::parse_source(&language.language, source, timeout)
+            .with_context(|| format!("custom queries failed to parse {}", file))?
+        else {
+            let diagnostic = Diagnostic {
+                file: file.to_string(),
+                start: 0,
+                end: source.len(),
+                message: format!(
+                    "parsing took longer than {} ms, custom queries skipped",
+                    timeout.unwrap_or_default().as_millis()
+                ),
+                severity: Severity::Warning,
+            };
+            return Ok((Vec::new(), Vec::new(), Some(diagnostic)));
+        };
+        let mut nodes = Vec::new();
+        for KindQuery { kind, query, text } in &language.queries {
+            let names = query.capture_names();
+            let mut cursor = QueryCursor::new();
+            let mut matches = cursor.matches(query, tree.root_node(), lang::text_provider(source));
+            while let Some(m) = matches.next() {
+                let capture = |wanted: &str| {
+                    m.captures
+                        .iter()
+                        .find(|c| names[c.index as usize] == wanted)
+                        .map(|c| c.node)
+                };
+                let Some(name) = capture("name") else {
+                    continue;
+                };
+                let span = capture("definition").unwrap_or(name);
+                let mut name = source.text(name.byte_range())?.into_owned();
+                if *text {
+                    name = first_line(&name);
+                }
+                nodes.push(CustomNode {
+                    kind: kind.clone(),
+                    name,
+                    file: file.to_string(),
+                    body: source.text(span.byte_range())?.into_owned(),
+                    start: span.start_position().row,
+                    end: span.end_position().row,
+                    span: Span {
+                        start_column: span.start_position().column,
+                        end_column: span.end_position().column,
+                        start_byte: span.start_byte(),
+                        end_byte: span.end_byte(),
+                    },
+                });
+            }
+        }
+        let mut edges = Vec::new();
+        for edge in &language.edges {
+            let mut cursor = QueryCursor::new();
+            let mut matches =
+                cursor.matches(&edge.query, tree.root_node(), lang::text_provider(source));
+            while let Some(m) = matches.next() {
+                let capture = |index: u32| m.captures.iter().find(|c| c.index == index);
+                let (Some(from), Some(to)) = (capture(edge.source), capture(edge.target)) else {
+                    continue;
+                };
+                edges.push(CustomEdge {
+                    kind: edge.kind.clone(),
+                    file: file.to_string(),
+                    source: endpoint(from.node, source)?,
+                    target: endpoint(to.node, source)?,
+                });
+            }
+        }
+        Ok((nodes, edges, None))
+    }
+}
+
+fn endpoint<S: Source + ?Sized>(node: tree_sitter::Node, source: &S) -> Result<Endpoint> {
+    Ok(Endpoint {
+        text: source.text(node.byte_range())?.into_owned(),
+        start: node.start_position().row,
+        end: node.end_position().row,
+        span: Span {
+            start_column: node.start_position().column,
+            end_column: node.end_position().column,
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+        },
+    })
+}
+
+/// The first line of `text` with anything in it, cut to [`TEXT_NAME_LEN`]
+/// characters.
+fn first_line(text: &str) -> String {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .unwrap_or_default();
+    line.chars().take(TEXT_NAME_LEN).collect()
+}
+
+fn load_language(dir: &Path, language: &Language) -> Result<Vec<KindQuery>> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read query directory {}", dir.display()))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    paths.sort();
+    let mut queries = Vec::new();
+    for path in paths
+        .iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "scm"))
+    {
+        let kind = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        // kinds end up as graph labels, so they're kept to identifiers
+        let valid = kind.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            bail!(
+                "invalid node kind '{}' for {}: the file name must be letters, digits and underscores",
+                kind,
+                path.display()
+            );
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read query {}", path.display()))?;
+        let query = Query::new(language, &text)
+            .with_context(|| format!("invalid query {}", path.display()))?;
+        if !query.capture_names().contains(&"name") {
+            bail!("query {} captures no @name", path.display());
+        }
+        queries.push(KindQuery {
+            kind: kind.to_string(),
+            query,
+            text: false,
+        });
+    }
+    Ok(queries)
+}
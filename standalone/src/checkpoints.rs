@@ -0,0 +1,63 @@
+//! Checkpoints of the ingests in progress, kept in the backend so an ingest
+//! cut short by a crash can be picked up where it stopped, or undone. Each
+//! file is checkpointed as its transaction commits, with the hash of the
+//! content it was parsed from, under an id of its own next to the repo's; a
+//! finished ingest drops its checkpoint.
+
+use crate::storage::Storage;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// What an ingest that never finished had stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// The commit it was ingesting.
+    pub commit: String,
+    /// The repo-relative files it stored, with the hashes of their content.
+    pub files: HashMap<String, String>,
+}
+
+/// The id the checkpoint of `repo_id` is stored under. `~` can't be part of
+/// a snapshot's name, so it can't be taken for one.
+pub fn id(repo_id: &str) -> String {
+    format!("{}#~checkpoint", repo_id)
+}
+
+/// Records that an ingest of `repo_id` at `commit` is under way, in place of
+/// any checkpoint left before.
+pub async fn start(storage: &dyn Storage, repo_id: &str, commit: &str) -> Result<()> {
+    let id = id(repo_id);
+    storage.clear(Some(&id)).await?;
+    storage.set_repo_hash(&id, commit).await
+}
+
+/// Checkpoints `files`, by repo-relative path and content hash, as stored.
+pub async fn stored(
+    storage: &dyn Storage,
+    repo_id: &str,
+    files: &[(String, String)],
+) -> Result<()> {
+    storage.set_file_hashes(&id(repo_id), files).await
+}
+
+/// Drops the checkpoint of `repo_id`, once its ingest is done with.
+pub async fn finish(storage: &dyn Storage, repo_id: &str) -> Result<()> {
+    let id = id(repo_id);
+    if storage.repo_hash(&id).await?.is_none_or(|c| c.is_empty()) {
+        return Ok(());
+    }
+    storage.clear(Some(&id)).await?;
+    storage.set_repo_hash(&id, "").await
+}
+
+/// The checkpoint an ingest of `repo_id` left without finishing, if any.
+pub async fn interrupted(storage: &dyn Storage, repo_id: &str) -> Result<Option<Checkpoint>> {
+    let id = id(repo_id);
+    let Some(commit) = storage.repo_hash(&id).await?.filter(|c| !c.is_empty()) else {
+        return Ok(None);
+    };
+    Ok(Some(Checkpoint {
+        commit,
+        files: storage.file_hashes(&id).await?,
+    }))
+}
@@ -0,0 +1,294 @@
+//! A typed client for the server's HTTP API, for Rust services built on
+//! mesh. Requests and responses are the server's own types from
+//! [`crate::types`], re-exported here, so the two can't drift apart.
+
+use crate::clone::RetryPolicy;
+use anyhow::{Context, Result};
+use axum::body::Bytes;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+pub use crate::events::StatusEvent;
+pub use crate::types::{
+    ProcessBody, ProcessResponse, QueryBody, QueryResponse, SearchBody, SearchResponse,
+};
+
+/// How many times in a row [`MeshClient::subscribe_events`] tries to
+/// reconnect before it gives up, and how long it waits in between.
+pub const DEFAULT_RECONNECT: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    base_delay: Duration::from_millis(500),
+};
+
+/// An error response from the server: its status and the `error` and `kind`
+/// of its body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    pub status: u16,
+    pub kind: String,
+    pub message: String,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.status, self.kind, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+#[derive(Clone)]
+pub struct MeshClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    reconnect: RetryPolicy,
+}
+
+impl MeshClient {
+    /// A client of the server at `base_url`, e.g. `http://localhost:7799`.
+    pub fn new(base_url: &str) -> Self {
+        MeshClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: None,
+            reconnect: DEFAULT_RECONNECT,
+        }
+    }
+
+    /// Sends `key` as a bearer token, for servers with `MESH_API_KEYS` set.
+    pub fn with_api_key(mut self, key: &str) -> Self {
+        self.api_key = Some(key.to_string());
+        self
+    }
+
+    pub fn with_reconnect(mut self, reconnect: RetryPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// `POST /process`, which returns once the ingest is done. Follow it
+    /// with [`MeshClient::subscribe_events`].
+    pub async fn process(&self, body: &ProcessBody) -> Result<ProcessResponse> {
+        self.post("/process", body).await
+    }
+
+    /// `POST /graph/query`, one page of rows at a time.
+    pub async fn query(&self, body: &QueryBody) -> Result<QueryResponse> {
+        self.post("/graph/query", body).await
+    }
+
+    pub async fn search(&self, body: &SearchBody) -> Result<SearchResponse> {
+        self.post("/search", body).await
+    }
+
+    /// The events of `/events`, parsed. When the connection drops it
+    /// reconnects with the `Last-Event-ID` of the last event it got, so the
+    /// server replays what was missed in between. The stream ends with an
+    /// error once [`MeshClient::with_reconnect`]'s attempts to reconnect in
+    /// a row have all failed.
+    pub fn subscribe_events(&self) -> impl Stream<Item = Result<StatusEvent>> + Send + 'static {
+        self.subscribe(None)
+    }
+
+    /// [`MeshClient::subscribe_events`] starting with the events after
+    /// `id`, as far as the server still buffers them; `0` for all of those.
+    pub fn subscribe_events_after(
+        &self,
+        id: u64,
+    ) -> impl Stream<Item = Result<StatusEvent>> + Send + 'static {
+        self.subscribe(Some(id))
+    }
+
+    fn subscribe(
+        &self,
+        last_id: Option<u64>,
+    ) -> impl Stream<Item = Result<StatusEvent>> + Send + 'static {
+        let subscription = Subscription {
+            client: self.clone(),
+            body: None,
+            parser: SseParser::default(),
+            pending: VecDeque::new(),
+            last_id,
+            failures: 0,
+            done: false,
+        };
+        stream::unfold(subscription, |mut sub| async move {
+            let item = sub.next().await?;
+            Some((item, sub))
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    async fn post<B: Serialize, R: DeserializeOwned>(&self, path: &str, body: &B) -> Result<R> {
+        let response = self
+            .request(reqwest::Method::POST, path)
+            .json(body)
+            .send()
+            .await
+            .with_context(|| format!("failed to reach {}{}", self.base_url, path))?;
+        let response = check(response).await?;
+        response
+            .json()
+            .await
+            .with_context(|| format!("unexpected response from {}", path))
+    }
+
+    async fn connect_events(
+        &self,
+        last_id: Option<u64>,
+    ) -> Result<BoxStream<'static, reqwest::Result<Bytes>>> {
+        let mut request = self
+            .request(reqwest::Method::GET, "/events")
+            .header(reqwest::header::ACCEPT, "text/event-stream");
+        if let Some(id) = last_id {
+            request = request.header("Last-Event-ID", id.to_string());
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("failed to reach {}/events", self.base_url))?;
+        Ok(check(response).await?.bytes_stream().boxed())
+    }
+}
+
+/// `response` when it succeeded, its [`ApiError`] when not.
+async fn check(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    let text = |key: &str| body[key].as_str().unwrap_or_default().to_string();
+    Err(ApiError {
+        status: status.as_u16(),
+        kind: text("kind"),
+        message: text("error"),
+    }
+    .into())
+}
+
+struct Subscription {
+    client: MeshClient,
+    body: Option<BoxStream<'static, reqwest::Result<Bytes>>>,
+    parser: SseParser,
+    pending: VecDeque<Result<StatusEvent>>,
+    last_id: Option<u64>,
+    /// Connections in a row that failed or dropped before an event came.
+    failures: u32,
+    done: bool,
+}
+
+impl Subscription {
+    async fn next(&mut self) -> Option<Result<StatusEvent>> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            if self.done {
+                return None;
+            }
+            let Some(body) = &mut self.body else {
+                tokio::time::sleep(self.client.reconnect.delay(self.failures + 1)).await;
+                match self.client.connect_events(self.last_id).await {
+                    Ok(body) => {
+                        self.body = Some(body);
+                        self.parser = SseParser::default();
+                    }
+                    Err(e) => {
+                        self.failures += 1;
+                        if self.failures >= self.client.reconnect.max_attempts.max(1) {
+                            self.done = true;
+                            return Some(Err(e));
+                        }
+                    }
+                }
+                continue;
+            };
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    for message in self.parser.push(&chunk) {
+                        self.failures = 0;
+                        if let Some(id) = message.id.as_deref().and_then(|id| id.parse().ok()) {
+                            self.last_id = Some(id);
+                        }
+                        self.pending.push_back(message.event());
+                    }
+                }
+                // dropped; reconnected on the next turn
+                Some(Err(_)) | None => {
+                    self.body = None;
+                    self.failures += 1;
+                }
+            }
+        }
+    }
+}
+
+/// One event of an SSE stream.
+struct Message {
+    id: Option<String>,
+    data: String,
+}
+
+impl Message {
+    fn event(self) -> Result<StatusEvent> {
+        let mut event: StatusEvent =
+            serde_json::from_str(&self.data).context("unexpected event from /events")?;
+        event.id = self.id.and_then(|id| id.parse().ok()).unwrap_or_default();
+        Ok(event)
+    }
+}
+
+/// Splits the bytes of an SSE stream into its events, keeping what is left
+/// of a line until the rest of it arrives. Comments, like the keep-alives,
+/// and events without data are dropped.
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    id: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<Message> {
+        self.buffer.extend_from_slice(chunk);
+        let mut messages = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                let data = std::mem::take(&mut self.data);
+                if !data.is_empty() {
+                    messages.push(Message {
+                        id: self.id.take(),
+                        data: data.join("\n"),
+                    });
+                }
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "id" => self.id = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        messages
+    }
+}
@@ -0,0 +1,769 @@
+use crate::events::{EventSender, StatusEvent};
+use crate::storage::repo_id;
+use git2::{
+    build::CheckoutBuilder, BlameOptions, Cred, CredentialType, Delta, ErrorClass, ErrorCode,
+    FetchOptions, Oid, Patch, RemoteCallbacks, Repository, SubmoduleUpdateOptions,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::warn;
+
+pub const DEFAULT_ATTEMPTS: u32 = 5;
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+pub const DEFAULT_DEPTH: u32 = 1;
+/// Branches tried, in order, when the remote doesn't say which is its HEAD.
+pub const DEFAULT_BRANCHES: [&str; 2] = ["main", "master"];
+/// libgit2's depth for fetching whatever history a shallow clone is missing.
+const UNSHALLOW: i32 = i32::MAX;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    /// Delay before the second attempt; doubled for each one after that.
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_ATTEMPTS,
+            base_delay: DEFAULT_BASE_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before `attempt` (1-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        if attempt <= 1 {
+            return Duration::ZERO;
+        }
+        self.base_delay * 2u32.saturating_pow(attempt - 2)
+    }
+}
+
+/// How much of the remote a clone brings down. The default is the tip of each
+/// branch and tag with the whole tree, which is all a one-off ingest needs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloneScope {
+    /// Commits fetched back from each tip; `None` fetches the full history,
+    /// which diffing against an earlier ingest needs.
+    pub depth: Option<u32>,
+    /// Directories or files to check out, relative to the repo root; empty
+    /// checks out everything.
+    pub sparse_paths: Vec<String>,
+    /// Also checks out the submodules the tree pins, and theirs, each with
+    /// its whole history, as libgit2 can't fetch them shallow. Their code is
+    /// then part of the repo's graph, so calls into it resolve as any other
+    /// file's do.
+    pub submodules: bool,
+    /// Branches to check out, the first the remote has, when no ref is asked
+    /// for and the remote doesn't say which branch its HEAD is; its first
+    /// branch by name when it has none of them.
+    pub default_branches: Vec<String>,
+}
+
+impl Default for CloneScope {
+    fn default() -> Self {
+        CloneScope {
+            depth: Some(DEFAULT_DEPTH),
+            sparse_paths: Vec::new(),
+            submodules: false,
+            default_branches: DEFAULT_BRANCHES.map(String::from).to_vec(),
+        }
+    }
+}
+
+impl CloneScope {
+    /// Full history and tree, turning an earlier shallow clone into a full one.
+    pub fn full() -> Self {
+        CloneScope {
+            depth: None,
+            ..CloneScope::default()
+        }
+    }
+}
+
+/// Refuses sparse paths that are empty, absolute or step out of the repo
+/// with `..`.
+pub fn check_sparse_paths(paths: &[String]) -> Result<(), String> {
+    for path in paths {
+        let mut normal = false;
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(_) => normal = true,
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    return Err(format!("sparse path {} steps outside the repo", path))
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(format!("sparse path {} is an absolute path", path))
+                }
+            }
+        }
+        if !normal {
+            return Err(format!("sparse path '{}' names no directory", path));
+        }
+    }
+    Ok(())
+}
+
+/// Sent with a token when no username is given; GitHub and GitLab both take it.
+pub const TOKEN_USERNAME: &str = "x-access-token";
+/// Stands in for a secret in anything logged or returned.
+pub const REDACTED: &str = "***";
+
+/// What a clone authenticates with: a token over HTTPS, a private key over
+/// SSH, or nothing for a public repo. Debug output never shows the secrets.
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Credentials {
+    /// Sent with the token, [`TOKEN_USERNAME`] when unset. Over SSH the
+    /// URL's user wins, then this, then `git`.
+    pub username: Option<String>,
+    pub token: Option<String>,
+    /// A private key file, for `ssh://` and `git@host:` remotes.
+    pub ssh_key: Option<PathBuf>,
+    pub passphrase: Option<String>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secret = |s: &Option<String>| s.as_ref().map(|_| REDACTED);
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("token", &secret(&self.token))
+            .field("ssh_key", &self.ssh_key)
+            .field("passphrase", &secret(&self.passphrase))
+            .finish()
+    }
+}
+
+impl Credentials {
+    /// HTTPS token auth; no auth at all without a token.
+    pub fn token(username: Option<String>, token: Option<String>) -> Self {
+        Credentials {
+            username,
+            token,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.token.is_none() && self.ssh_key.is_none()
+    }
+
+    /// `text` with the secrets replaced and any credentials in its URLs
+    /// dropped, fit to log or return.
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = redact_urls(text);
+        for secret in [&self.token, &self.passphrase].into_iter().flatten() {
+            if !secret.is_empty() {
+                text = text.replace(secret.as_str(), REDACTED);
+            }
+        }
+        text
+    }
+
+    /// Answers the remote's credential requests. libgit2 asks again for as
+    /// long as the remote refuses what it was sent, so a second request is
+    /// refused instead, as an auth error.
+    fn callbacks(&self) -> RemoteCallbacks<'_> {
+        let mut callbacks = RemoteCallbacks::new();
+        if self.is_empty() {
+            return callbacks;
+        }
+        let mut sent = false;
+        callbacks.credentials(move |_, url_user, allowed| {
+            let user = url_user.or(self.username.as_deref());
+            // SSH first asks for the user alone when the URL has none
+            if allowed.contains(CredentialType::USERNAME) {
+                return Cred::username(user.unwrap_or("git"));
+            }
+            if std::mem::replace(&mut sent, true) {
+                return Err(auth_error("the remote rejected the credentials"));
+            }
+            match (&self.ssh_key, &self.token) {
+                (Some(key), _) if allowed.contains(CredentialType::SSH_KEY) => {
+                    Cred::ssh_key(user.unwrap_or("git"), None, key, self.passphrase.as_deref())
+                }
+                (_, Some(token)) if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) => {
+                    Cred::userpass_plaintext(
+                        self.username.as_deref().unwrap_or(TOKEN_USERNAME),
+                        token,
+                    )
+                }
+                _ => Err(auth_error(
+                    "the remote asks for a kind of credentials that wasn't given",
+                )),
+            }
+        });
+        callbacks
+    }
+}
+
+fn auth_error(message: &str) -> git2::Error {
+    git2::Error::new(ErrorCode::Auth, ErrorClass::Callback, message)
+}
+
+/// Whether `error` means the remote refused to let us in, which retrying
+/// with the same credentials won't change.
+pub fn is_auth_error(error: &git2::Error) -> bool {
+    let message = error.message().to_ascii_lowercase();
+    error.code() == ErrorCode::Auth
+        || (error.class() == ErrorClass::Http
+            && ["401", "403", "authentication"]
+                .iter()
+                .any(|s| message.contains(s)))
+        || (error.class() == ErrorClass::Ssh && message.contains("authentication"))
+}
+
+/// `text` with the `user:password@` of every URL in it dropped.
+pub fn redact_urls(text: &str) -> String {
+    static USERINFO: OnceLock<Regex> = OnceLock::new();
+    let userinfo = USERINFO.get_or_init(|| Regex::new(r"://[^/@\s]+@").unwrap());
+    userinfo.replace_all(text, "://").into_owned()
+}
+
+/// What a fetch checked out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fetched {
+    /// Full SHA of the commit HEAD resolved to.
+    pub commit: String,
+    /// The branch checked out, or the tag or commit asked for when HEAD is
+    /// detached.
+    pub git_ref: String,
+    /// The remote's default branch, when no ref was asked for and it was
+    /// checked out in its place.
+    pub default_branch: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloneError {
+    pub url: String,
+    pub attempts: u32,
+    /// The git error from the last attempt.
+    pub message: String,
+    /// The remote refused the credentials, or asked for some and got none.
+    pub auth: bool,
+}
+
+/// Why one attempt failed. Plain messages are retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttemptError {
+    pub message: String,
+    /// An auth failure, which retrying won't fix, so it ends the retries.
+    pub auth: bool,
+}
+
+impl From<String> for AttemptError {
+    fn from(message: String) -> Self {
+        AttemptError {
+            message,
+            auth: false,
+        }
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails on auth, or the policy runs out,
+/// calling `on_retry` with the attempt number and the previous error before
+/// each retry.
+pub async fn with_retry<T, E, F, Fut>(
+    url: &str,
+    policy: RetryPolicy,
+    mut on_retry: impl FnMut(u32, &str),
+    mut attempt: F,
+) -> Result<T, CloneError>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<AttemptError>,
+{
+    let mut last_error = String::new();
+    for n in 1..=policy.max_attempts {
+        if n > 1 {
+            on_retry(n, &last_error);
+            tokio::time::sleep(policy.delay(n)).await;
+        }
+        match attempt(n).await.map_err(Into::into) {
+            Ok(value) => return Ok(value),
+            Err(e) if e.auth => {
+                warn!("clone of {} was refused: {}", url, e.message);
+                return Err(CloneError {
+                    url: url.to_string(),
+                    attempts: n,
+                    message: e.message,
+                    auth: true,
+                });
+            }
+            Err(e) => {
+                warn!("clone of {} failed (attempt {}): {}", url, n, e.message);
+                last_error = e.message;
+            }
+        }
+    }
+    Err(CloneError {
+        url: url.to_string(),
+        attempts: policy.max_attempts,
+        message: last_error,
+        auth: false,
+    })
+}
+
+/// Clones `url` into `dest`, retrying with backoff and reporting each retry
+/// on `tx` as "retrying (n/max)". `git_ref` is checked out instead of the
+/// default branch when given. Returns what was checked out. An auth failure
+/// isn't retried. The URL and messages in the error and the retry updates
+/// have the credentials redacted.
+pub async fn clone_repo(
+    url: &str,
+    dest: &Path,
+    credentials: &Credentials,
+    git_ref: Option<&str>,
+    scope: &CloneScope,
+    policy: RetryPolicy,
+    tx: &EventSender,
+) -> Result<Fetched, CloneError> {
+    let on_retry = |n: u32, error: &str| {
+        let message = format!("retrying ({}/{}): {}", n, policy.max_attempts, error);
+        // no subscribers is not an error
+        tx.send(StatusEvent::new("retrying", message).for_repo(&repo_id(url, "")));
+    };
+    with_retry(&without_credentials(url), policy, on_retry, |_| {
+        let (url, dest) = (url.to_string(), dest.to_path_buf());
+        let credentials = credentials.clone();
+        let git_ref = git_ref.map(str::to_string);
+        let scope = scope.clone();
+        async move {
+            tokio::task::spawn_blocking(move || {
+                fetch_into(&url, &dest, &credentials, git_ref.as_deref(), &scope).map_err(|e| {
+                    AttemptError {
+                        message: credentials.redact(e.message()),
+                        auth: is_auth_error(&e),
+                    }
+                })
+            })
+            .await
+            .map_err(|e| AttemptError::from(e.to_string()))?
+        }
+    })
+    .await
+}
+
+/// One clone attempt. Instead of `git clone`, which throws everything away on
+/// failure, this initialises `dest` once and fetches into it, so a retry keeps
+/// the repository and whatever refs were already fetched.
+/// `git_ref` may name a branch, a tag or a commit; a tag or commit is checked
+/// out detached. A commit older than a shallow `scope` reaches is fetched by
+/// deepening the clone. Local remotes are always fetched in full, as libgit2
+/// can't fetch them shallow, and copying their objects is cheap anyway.
+pub fn fetch_into(
+    url: &str,
+    dest: &Path,
+    credentials: &Credentials,
+    git_ref: Option<&str>,
+    scope: &CloneScope,
+) -> Result<Fetched, git2::Error> {
+    let repo = match Repository::open(dest) {
+        Ok(repo) => repo,
+        Err(_) => Repository::init(dest)?,
+    };
+    let mut remote = match repo.find_remote("origin") {
+        Ok(remote) if remote.url() == Some(url) => remote,
+        Ok(_) => {
+            repo.remote_set_url("origin", url)?;
+            repo.find_remote("origin")?
+        }
+        Err(_) => repo.remote("origin", url)?,
+    };
+
+    let depth = match scope.depth {
+        _ if is_local(url) => None,
+        Some(depth) => Some(depth.clamp(1, UNSHALLOW as u32) as i32),
+        None if repo.is_shallow() => Some(UNSHALLOW),
+        None => None,
+    };
+    let mut fetch = |depth: Option<i32>| {
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(credentials.callbacks());
+        if let Some(depth) = depth {
+            options.depth(depth);
+        }
+        remote.fetch(
+            &[
+                "+refs/heads/*:refs/remotes/origin/*",
+                "+refs/tags/*:refs/tags/*",
+            ],
+            Some(&mut options),
+            None,
+        )
+    };
+    fetch(depth)?;
+
+    let branch = match git_ref {
+        Some(git_ref) if !is_branch(&repo, git_ref) => {
+            let object = match repo.revparse_single(git_ref) {
+                Err(_) if depth.is_some_and(|d| d != UNSHALLOW) => {
+                    fetch(Some(UNSHALLOW))?;
+                    repo.revparse_single(git_ref)?
+                }
+                found => found?,
+            };
+            let commit = object.peel_to_commit()?;
+            repo.set_head_detached(commit.id())?;
+            repo.checkout_head(Some(&mut checkout(scope)))?;
+            if scope.submodules {
+                update_submodules(&repo, credentials)?;
+            }
+            return Ok(Fetched {
+                commit: commit.id().to_string(),
+                git_ref: git_ref.to_string(),
+                default_branch: None,
+            });
+        }
+        Some(git_ref) => git_ref.to_string(),
+        None => match remote_head(&remote).filter(|b| is_branch(&repo, b)) {
+            Some(branch) => {
+                repo.reference_symbolic(
+                    "refs/remotes/origin/HEAD",
+                    &format!("refs/remotes/origin/{}", branch),
+                    true,
+                    "mesh: remote HEAD",
+                )?;
+                branch
+            }
+            None => default_branch(&repo, &scope.default_branches)?,
+        },
+    };
+    let target = repo
+        .find_reference(&format!("refs/remotes/origin/{}", branch))?
+        .peel_to_commit()?;
+    let local = format!("refs/heads/{}", branch);
+    repo.reference(&local, target.id(), true, "mesh: fetch")?;
+    repo.set_head(&local)?;
+    repo.checkout_head(Some(&mut checkout(scope)))?;
+    if scope.submodules {
+        update_submodules(&repo, credentials)?;
+    }
+    Ok(Fetched {
+        commit: target.id().to_string(),
+        default_branch: git_ref.is_none().then(|| branch.clone()),
+        git_ref: branch,
+    })
+}
+
+/// Clones or updates every submodule of `repo` to the commit its tree pins,
+/// then theirs. A relative URL is taken against `repo`'s own remote.
+fn update_submodules(repo: &Repository, credentials: &Credentials) -> Result<(), git2::Error> {
+    for mut submodule in repo.submodules()? {
+        let mut fetch = FetchOptions::new();
+        fetch.remote_callbacks(credentials.callbacks());
+        let mut options = SubmoduleUpdateOptions::new();
+        options.fetch(fetch);
+        submodule.update(true, Some(&mut options))?;
+        update_submodules(&submodule.open()?, credentials)?;
+    }
+    Ok(())
+}
+
+/// A submodule checked out under a repo, as [`submodules`] finds it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Submodule {
+    /// Its directory, relative to the checkout of the outermost repo.
+    pub path: String,
+    /// Where it's cloned from, without credentials.
+    pub remote: String,
+    /// Full SHA of the commit it has out.
+    pub commit: String,
+}
+
+/// The submodules checked out under the checkout at `root`, nested ones
+/// too, outermost first. Those not cloned yet are left out.
+pub fn submodules(root: &Path) -> Vec<Submodule> {
+    let mut found = Vec::new();
+    if let Ok(repo) = Repository::open(root) {
+        collect_submodules(&repo, "", &mut found);
+    }
+    found
+}
+
+fn collect_submodules(repo: &Repository, prefix: &str, found: &mut Vec<Submodule>) {
+    let Ok(submodules) = repo.submodules() else {
+        return;
+    };
+    for submodule in submodules {
+        let Ok(inner) = submodule.open() else {
+            continue;
+        };
+        let Some(commit) = inner.head().ok().and_then(|h| h.target()) else {
+            continue;
+        };
+        let path = format!("{}{}", prefix, submodule.path().to_string_lossy());
+        found.push(Submodule {
+            path: path.clone(),
+            remote: without_credentials(submodule.url().unwrap_or_default()),
+            commit: commit.to_string(),
+        });
+        collect_submodules(&inner, &format!("{}/", path), found);
+    }
+}
+
+/// `url` without the `user:password@` it may carry, fit to log or return.
+pub fn without_credentials(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let host_end = rest.find('/').unwrap_or(rest.len());
+    match rest[..host_end].rfind('@') {
+        Some(at) => format!("{}://{}", scheme, &rest[at + 1..]),
+        None => url.to_string(),
+    }
+}
+
+/// What the checkout at `path` has out now, with the commit as its ref when
+/// HEAD is detached; `None` when it isn't a git checkout or has no commits.
+pub fn checked_out(path: &Path) -> Option<Fetched> {
+    let repo = Repository::open(path).ok()?;
+    let head = repo.head().ok()?;
+    let commit = head.peel_to_commit().ok()?.id().to_string();
+    let git_ref = match head.shorthand() {
+        Some(branch) if head.is_branch() => branch.to_string(),
+        _ => commit.clone(),
+    };
+    Some(Fetched {
+        commit,
+        git_ref,
+        default_branch: None,
+    })
+}
+
+/// A forced checkout, limited to the sparse paths when there are any. Files
+/// outside them are neither written nor removed.
+fn checkout(scope: &CloneScope) -> CheckoutBuilder<'static> {
+    let mut builder = CheckoutBuilder::new();
+    builder.force();
+    for path in &scope.sparse_paths {
+        let path = path.trim_start_matches("./").trim_end_matches('/');
+        builder.path(path);
+    }
+    builder
+}
+
+/// Whether `url` is a path on this machine rather than a network remote.
+fn is_local(url: &str) -> bool {
+    url.starts_with("file://") || Path::new(url).exists()
+}
+
+fn is_branch(repo: &Repository, name: &str) -> bool {
+    repo.find_reference(&format!("refs/remotes/origin/{}", name))
+        .is_ok()
+}
+
+/// Where a ref of the repo cloned at `path` gets its own checkout, so several
+/// refs can be on disk, and ingested, at once.
+pub fn ref_path(path: &str, git_ref: &str) -> String {
+    let git_ref: String = git_ref
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}@{}", path.trim_end_matches('/'), git_ref)
+}
+
+/// The branch the remote's HEAD points at, as it told the last fetch.
+fn remote_head(remote: &git2::Remote) -> Option<String> {
+    let head = remote.default_branch().ok()?;
+    let head = head.as_str()?;
+    head.strip_prefix("refs/heads/").map(str::to_string)
+}
+
+/// The first of `preferred` the remote has, otherwise its first branch.
+fn default_branch(repo: &Repository, preferred: &[String]) -> Result<String, git2::Error> {
+    let prefix = "refs/remotes/origin/";
+    let mut names: Vec<String> = repo
+        .references_glob(&format!("{}*", prefix))?
+        .filter_map(|r| r.ok())
+        .filter_map(|r| r.name().map(|n| n.trim_start_matches(prefix).to_string()))
+        // the remote's HEAD, once recorded, isn't a branch of its own
+        .filter(|n| n != "HEAD")
+        .collect();
+    names.sort();
+    if let Some(branch) = preferred.iter().find(|b| names.contains(b)) {
+        return Ok(branch.clone());
+    }
+    names
+        .into_iter()
+        .next()
+        .ok_or_else(|| git2::Error::from_str("remote has no branches"))
+}
+
+/// Commits further back than this don't count towards co-change.
+pub const CO_CHANGE_COMMITS: usize = 1000;
+/// Commits touching more files than this, e.g. reformats, relate nothing.
+const CO_CHANGE_MAX_FILES: usize = 50;
+
+/// How many of the last [`CO_CHANGE_COMMITS`] commits of the checkout holding
+/// `file` changed each of `others` along with it, keyed like `others`. Empty
+/// when `file` isn't in a git checkout or the history can't be read.
+pub fn co_changes(file: &str, others: &[String]) -> HashMap<String, usize> {
+    co_change_counts(file, others).unwrap_or_default()
+}
+
+fn co_change_counts(file: &str, others: &[String]) -> Option<HashMap<String, usize>> {
+    let path = Path::new(file).canonicalize().ok()?;
+    let repo = Repository::discover(path.parent()?).ok()?;
+    let workdir = repo.workdir()?.canonicalize().ok()?;
+    let relative = |file: &str| -> Option<PathBuf> {
+        let path = Path::new(file).canonicalize().ok()?;
+        Some(path.strip_prefix(&workdir).ok()?.to_path_buf())
+    };
+    let target = relative(file)?;
+
+    let mut changed_with: HashMap<PathBuf, usize> = HashMap::new();
+    let mut walk = repo.revwalk().ok()?;
+    walk.push_head().ok()?;
+    for id in walk.take(CO_CHANGE_COMMITS) {
+        let commit = repo.find_commit(id.ok()?).ok()?;
+        let tree = commit.tree().ok()?;
+        let parent = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree().ok()?),
+            Err(_) => None,
+        };
+        let diff = repo
+            .diff_tree_to_tree(parent.as_ref(), Some(&tree), None)
+            .ok()?;
+        if diff.deltas().len() > CO_CHANGE_MAX_FILES {
+            continue;
+        }
+        let paths: Vec<&Path> = diff.deltas().filter_map(|d| d.new_file().path()).collect();
+        if !paths.contains(&target.as_path()) {
+            continue;
+        }
+        for path in paths.into_iter().filter(|p| *p != target) {
+            *changed_with.entry(path.to_path_buf()).or_default() += 1;
+        }
+    }
+    Some(
+        others
+            .iter()
+            .filter_map(|other| {
+                let count = *changed_with.get(&relative(other)?)?;
+                Some((other.clone(), count))
+            })
+            .collect(),
+    )
+}
+
+/// What a commit range did to one file. Lines are 0-based, like stored nodes'.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileChange {
+    /// Relative to the checkout root; `None` for a file the range added.
+    pub old_path: Option<String>,
+    /// `None` for a file the range deleted.
+    pub new_path: Option<String>,
+    /// Lines of the old file that the range removed or rewrote.
+    pub removed: BTreeSet<usize>,
+    /// Lines of the new file written within the range, each with the commit
+    /// that last touched it, as blamed.
+    pub written: BTreeMap<usize, String>,
+    /// The old file's text, for telling a moved symbol from a new one.
+    pub old_source: String,
+}
+
+/// The files changed between two commits of a checkout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeChanges {
+    /// The checkout's working directory, which the paths are relative to.
+    pub root: PathBuf,
+    pub files: Vec<FileChange>,
+}
+
+/// The full id of the commit `rev` names in the checkout holding `path`, or
+/// `None` when there's no such commit, e.g. one a shallow clone left out.
+pub fn resolve_commit(path: &Path, rev: &str) -> Option<String> {
+    let repo = Repository::discover(path).ok()?;
+    let commit = repo.revparse_single(rev).ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+/// Diffs commit `base` against `head`, following renames, and blames each
+/// file `head` has with history cut off at `base`, so every written line is
+/// put down to a commit in the range.
+pub fn range_changes(path: &Path, base: &str, head: &str) -> Result<RangeChanges, String> {
+    let git = |e: git2::Error| e.message().to_string();
+    let repo = Repository::discover(path).map_err(git)?;
+    let root = repo
+        .workdir()
+        .ok_or_else(|| format!("{} has no working directory", path.display()))?
+        .to_path_buf();
+    let base = Oid::from_str(base).map_err(git)?;
+    let head = Oid::from_str(head).map_err(git)?;
+    let tree = |id: Oid| repo.find_commit(id).and_then(|c| c.tree());
+    let mut diff = repo
+        .diff_tree_to_tree(
+            Some(&tree(base).map_err(git)?),
+            Some(&tree(head).map_err(git)?),
+            None,
+        )
+        .map_err(git)?;
+    diff.find_similar(None).map_err(git)?;
+
+    let mut files = Vec::new();
+    for (i, delta) in diff.deltas().enumerate() {
+        let path = |file: git2::DiffFile| file.path().map(|p| p.to_string_lossy().into_owned());
+        let mut change = FileChange {
+            old_path: (delta.status() != Delta::Added)
+                .then(|| path(delta.old_file()))
+                .flatten(),
+            new_path: (delta.status() != Delta::Deleted)
+                .then(|| path(delta.new_file()))
+                .flatten(),
+            ..Default::default()
+        };
+        if change.old_path.is_some() {
+            let blob = repo.find_blob(delta.old_file().id()).map_err(git)?;
+            change.old_source = String::from_utf8_lossy(blob.content()).into_owned();
+        }
+        if let Some(patch) = Patch::from_diff(&diff, i).map_err(git)? {
+            for hunk in 0..patch.num_hunks() {
+                for n in 0..patch.num_lines_in_hunk(hunk).map_err(git)? {
+                    let line = patch.line_in_hunk(hunk, n).map_err(git)?;
+                    if let ('-', Some(old)) = (line.origin(), line.old_lineno()) {
+                        change.removed.insert(old as usize - 1);
+                    }
+                }
+            }
+        }
+        if let Some(new_path) = &change.new_path {
+            let mut options = BlameOptions::new();
+            options.newest_commit(head).oldest_commit(base);
+            let blame = repo
+                .blame_file(Path::new(new_path), Some(&mut options))
+                .map_err(git)?;
+            for hunk in blame.iter() {
+                // lines older than the range are put down to `base` itself
+                if hunk.is_boundary() || hunk.final_commit_id() == base {
+                    continue;
+                }
+                let start = hunk.final_start_line().saturating_sub(1);
+                let commit = hunk.final_commit_id().to_string();
+                for line in start..start + hunk.lines_in_hunk() {
+                    change.written.insert(line, commit.clone());
+                }
+            }
+        }
+        files.push(change);
+    }
+    Ok(RangeChanges { root, files })
+}
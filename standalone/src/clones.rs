@@ -0,0 +1,209 @@
+use crate::storage::repo_id;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// Where clones go when `MESH_CLONE_DIR` isn't set, under the system temp dir.
+pub const DEFAULT_DIR: &str = "mesh-clones";
+/// Free space a clone needs to start, in MB.
+pub const DEFAULT_MIN_FREE_MB: u64 = 1024;
+
+/// The directory remote repos are cloned into, one checkout per repo and ref
+/// at `<root>/<owner>/<name>`. A checkout is removed once the last ingest
+/// using it ends, unless clones are kept.
+pub struct CloneDir {
+    root: PathBuf,
+    /// Clones are refused below this much free space on the root's disk.
+    min_free_bytes: u64,
+    keep: bool,
+    /// Ingests using each checkout.
+    leases: Mutex<HashMap<PathBuf, usize>>,
+}
+
+impl Default for CloneDir {
+    fn default() -> Self {
+        CloneDir::new(
+            std::env::temp_dir().join(DEFAULT_DIR),
+            DEFAULT_MIN_FREE_MB * 1024 * 1024,
+            false,
+        )
+    }
+}
+
+impl CloneDir {
+    pub fn new(root: PathBuf, min_free_bytes: u64, keep: bool) -> Self {
+        CloneDir {
+            root,
+            min_free_bytes,
+            keep,
+            leases: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where `url` is checked out; fails for a URL naming no repo.
+    pub fn path_for(&self, url: &str) -> Result<String, String> {
+        let id = repo_id(url, "");
+        if id.is_empty() {
+            return Err(format!("{} names no repository", url));
+        }
+        let mut path = self.root.clone();
+        for part in id.split('/') {
+            path.push(safe(part));
+        }
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    /// Refuses a clone when the root's disk has less free space than the
+    /// configured minimum, naming both.
+    pub fn check_space(&self) -> Result<(), String> {
+        if self.min_free_bytes == 0 {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.root)
+            .map_err(|e| format!("failed to create {}: {}", self.root.display(), e))?;
+        let free = fs4::available_space(&self.root).map_err(|e| {
+            format!(
+                "failed to read free space of {}: {}",
+                self.root.display(),
+                e
+            )
+        })?;
+        if free < self.min_free_bytes {
+            return Err(format!(
+                "only {} MB free in {}, a clone needs at least {} MB",
+                free / (1024 * 1024),
+                self.root.display(),
+                self.min_free_bytes / (1024 * 1024)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Marks the checkout at `path` in use until the lease is dropped. Paths
+    /// outside the root, such as local repos, are never removed.
+    pub fn lease(self: &Arc<Self>, path: &str) -> CloneLease {
+        let path = PathBuf::from(path);
+        let path = path.starts_with(&self.root).then(|| {
+            *self.leases.lock().unwrap().entry(path.clone()).or_default() += 1;
+            path
+        });
+        CloneLease {
+            clones: self.clone(),
+            path,
+        }
+    }
+
+    /// Removes the checkouts left under the root by an earlier run, which
+    /// would otherwise never be cleaned up. Only git checkouts at
+    /// `<owner>/<name>` are touched, whatever else the root holds. Returns
+    /// how many were removed.
+    pub fn sweep(&self) -> usize {
+        if self.keep {
+            return 0;
+        }
+        let Ok(owners) = fs::read_dir(&self.root) else {
+            return 0;
+        };
+        let mut removed = 0;
+        for owner in owners.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if !owner.is_dir() {
+                continue;
+            }
+            let Ok(repos) = fs::read_dir(&owner) else {
+                continue;
+            };
+            for repo in repos.filter_map(|e| e.ok()).map(|e| e.path()) {
+                if !repo.join(".git").exists() {
+                    continue;
+                }
+                match fs::remove_dir_all(&repo) {
+                    Ok(()) => removed += 1,
+                    Err(e) => warn!("failed to remove orphaned clone {}: {}", repo.display(), e),
+                }
+            }
+            // only goes when nothing else is left in it
+            let _ = fs::remove_dir(&owner);
+        }
+        removed
+    }
+
+    fn release(&self, path: &Path) {
+        let mut leases = self.leases.lock().unwrap();
+        let Some(count) = leases.get_mut(path) else {
+            return;
+        };
+        *count -= 1;
+        if *count > 0 {
+            return;
+        }
+        leases.remove(path);
+        if self.keep || !path.exists() {
+            return;
+        }
+        // moved aside under the lock, so a new lease can't start on a
+        // half-removed checkout; the slow part runs off the async workers
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let doomed = path.with_file_name(format!(".{}.removing", name));
+        if let Err(e) = fs::rename(path, &doomed) {
+            warn!("failed to remove clone {}: {}", path.display(), e);
+            return;
+        }
+        let owner = path
+            .parent()
+            .filter(|p| *p != self.root)
+            .map(Path::to_path_buf);
+        let path = path.to_path_buf();
+        let remove = move || {
+            match fs::remove_dir_all(&doomed) {
+                Ok(()) => info!("removed clone {}", path.display()),
+                Err(e) => warn!("failed to remove clone {}: {}", path.display(), e),
+            }
+            if let Some(owner) = owner {
+                let _ = fs::remove_dir(owner);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(remove)),
+            Err(_) => remove(),
+        }
+    }
+}
+
+/// A checkout in use by an ingest; see [`CloneDir::lease`].
+pub struct CloneLease {
+    clones: Arc<CloneDir>,
+    path: Option<PathBuf>,
+}
+
+impl Drop for CloneLease {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            self.clones.release(path);
+        }
+    }
+}
+
+/// `part` with anything that could step out of its directory replaced.
+fn safe(part: &str) -> String {
+    let part: String = part
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if part.chars().all(|c| c == '.') {
+        part.replace('.', "-")
+    } else {
+        part
+    }
+}
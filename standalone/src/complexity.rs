@@ -0,0 +1,287 @@
+use crate::captures::GRAMMARS;
+use crate::storage::NodeRecord;
+use anyhow::Result;
+use std::path::Path;
+use tree_sitter::{Node, Tree};
+
+/// The meta keys a node's measures are stored under.
+pub const COMPLEXITY: &str = "complexity";
+pub const LINES: &str = "lines";
+pub const NESTING: &str = "nesting";
+
+/// What makes code of one language branch, in the node kinds of its grammar.
+#[derive(Debug, Clone)]
+pub struct BranchRule {
+    /// A name of [`GRAMMARS`].
+    pub grammar: &'static str,
+    /// Each adds a path through the code: conditionals, loops, cases, handlers.
+    pub branches: &'static [&'static str],
+    /// Blocks that nest the code inside them one level deeper.
+    pub nesting: &'static [&'static str],
+    /// Binary expressions whose `operator` is one of [`Self::logical`]
+    /// short-circuit, which is a branch too.
+    pub binary: &'static [&'static str],
+    pub logical: &'static [&'static str],
+}
+
+pub const DEFAULT_RULES: &[BranchRule] = &[
+    BranchRule {
+        grammar: "rust",
+        branches: &[
+            "if_expression",
+            "match_arm",
+            "while_expression",
+            "for_expression",
+            "loop_expression",
+        ],
+        nesting: &[
+            "if_expression",
+            "match_expression",
+            "while_expression",
+            "for_expression",
+            "loop_expression",
+            "closure_expression",
+        ],
+        binary: &["binary_expression"],
+        logical: &["&&", "||"],
+    },
+    BranchRule {
+        grammar: "python",
+        branches: &[
+            "if_statement",
+            "elif_clause",
+            "for_statement",
+            "while_statement",
+            "except_clause",
+            "case_clause",
+            "conditional_expression",
+            "boolean_operator",
+            "for_in_clause",
+            "if_clause",
+        ],
+        nesting: &[
+            "if_statement",
+            "for_statement",
+            "while_statement",
+            "try_statement",
+            "with_statement",
+            "match_statement",
+            "lambda",
+        ],
+        binary: &[],
+        logical: &[],
+    },
+    BranchRule {
+        grammar: "go",
+        branches: &[
+            "if_statement",
+            "for_statement",
+            "expression_case",
+            "type_case",
+            "communication_case",
+        ],
+        nesting: &[
+            "if_statement",
+            "for_statement",
+            "expression_switch_statement",
+            "type_switch_statement",
+            "select_statement",
+            "func_literal",
+        ],
+        binary: &["binary_expression"],
+        logical: &["&&", "||"],
+    },
+    BranchRule {
+        grammar: "typescript",
+        branches: JS_BRANCHES,
+        nesting: JS_NESTING,
+        binary: &["binary_expression"],
+        logical: &["&&", "||", "??"],
+    },
+    BranchRule {
+        grammar: "tsx",
+        branches: JS_BRANCHES,
+        nesting: JS_NESTING,
+        binary: &["binary_expression"],
+        logical: &["&&", "||", "??"],
+    },
+    BranchRule {
+        grammar: "java",
+        branches: &[
+            "if_statement",
+            "for_statement",
+            "enhanced_for_statement",
+            "while_statement",
+            "do_statement",
+            "switch_label",
+            "switch_rule",
+            "catch_clause",
+            "ternary_expression",
+        ],
+        nesting: &[
+            "if_statement",
+            "for_statement",
+            "enhanced_for_statement",
+            "while_statement",
+            "do_statement",
+            "switch_expression",
+            "try_statement",
+            "lambda_expression",
+        ],
+        binary: &["binary_expression"],
+        logical: &["&&", "||"],
+    },
+    BranchRule {
+        grammar: "ruby",
+        branches: &[
+            "if",
+            "elsif",
+            "unless",
+            "while",
+            "until",
+            "for",
+            "when",
+            "rescue",
+            "conditional",
+            "if_modifier",
+            "unless_modifier",
+            "while_modifier",
+            "until_modifier",
+        ],
+        nesting: &[
+            "if", "unless", "while", "until", "for", "case", "begin", "block", "do_block",
+        ],
+        binary: &["binary"],
+        logical: &["&&", "||", "and", "or"],
+    },
+];
+
+const JS_BRANCHES: &[&str] = &[
+    "if_statement",
+    "for_statement",
+    "for_in_statement",
+    "while_statement",
+    "do_statement",
+    "switch_case",
+    "catch_clause",
+    "ternary_expression",
+];
+
+const JS_NESTING: &[&str] = &[
+    "if_statement",
+    "for_statement",
+    "for_in_statement",
+    "while_statement",
+    "do_statement",
+    "switch_statement",
+    "try_statement",
+    "arrow_function",
+];
+
+/// How hard a function or file is to follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Complexity {
+    /// McCabe's: one plus the branches.
+    pub cyclomatic: usize,
+    pub lines: usize,
+    /// How many blocks deep the deepest code is; 0 for straight-line code.
+    pub nesting: usize,
+}
+
+impl BranchRule {
+    pub fn for_path(path: &Path) -> Option<&'static BranchRule> {
+        let ext = path.extension()?.to_str()?;
+        let grammar = GRAMMARS.iter().find(|g| g.extensions.contains(&ext))?;
+        DEFAULT_RULES.iter().find(|r| r.grammar == grammar.name)
+    }
+
+    /// The measures of the code under `root`.
+    pub fn measure(&self, root: Node) -> Complexity {
+        let mut complexity = Complexity {
+            cyclomatic: 1,
+            lines: root.end_position().row - root.start_position().row + 1,
+            nesting: 0,
+        };
+        let mut pending = vec![(root, 0)];
+        while let Some((node, depth)) = pending.pop() {
+            if self.branches.contains(&node.kind()) || self.short_circuits(node) {
+                complexity.cyclomatic += 1;
+            }
+            let depth = if node != root && self.nests(node) {
+                depth + 1
+            } else {
+                depth
+            };
+            complexity.nesting = complexity.nesting.max(depth);
+            let mut cursor = node.walk();
+            pending.extend(node.named_children(&mut cursor).map(|child| (child, depth)));
+        }
+        complexity
+    }
+
+    fn short_circuits(&self, node: Node) -> bool {
+        self.binary.contains(&node.kind())
+            && node
+                .child_by_field_name("operator")
+                .is_some_and(|op| self.logical.contains(&op.kind()))
+    }
+
+    /// Whether `node` is a block one level deeper than its parent's. An
+    /// `else if` continues the chain it's in rather than opening a level.
+    fn nests(&self, node: Node) -> bool {
+        if !self.nesting.contains(&node.kind()) {
+            return false;
+        }
+        let Some(parent) = node.parent() else {
+            return true;
+        };
+        let chained = parent.kind() == "else_clause"
+            || (parent.kind() == node.kind()
+                && parent.child_by_field_name("alternative") == Some(node));
+        !chained
+    }
+}
+
+/// Stores the measures of the `Function` and `File` nodes of `file` in their
+/// meta, given `tree`, the syntax tree of `source` if it parsed in time. A
+/// function is measured through its span, so one without a span only gets
+/// its line count; so does every node of a language without a
+/// [`BranchRule`], and of a file without a tree.
+pub fn annotate<'a>(
+    file: &str,
+    source: &str,
+    tree: Option<&Tree>,
+    nodes: impl IntoIterator<Item = &'a mut NodeRecord>,
+) -> Result<()> {
+    let rule = BranchRule::for_path(Path::new(file));
+    for node in nodes {
+        let measured = match (node.kind.as_str(), rule, tree) {
+            ("File", Some(rule), Some(tree)) => Some(rule.measure(tree.root_node())),
+            ("Function", Some(rule), Some(tree)) => node.span.and_then(|span| {
+                let found = tree
+                    .root_node()
+                    .descendant_for_byte_range(span.start_byte, span.end_byte)?;
+                Some(rule.measure(found))
+            }),
+            _ => None,
+        };
+        let lines = match node.kind.as_str() {
+            "File" => source.lines().count(),
+            "Function" => node.end.saturating_sub(node.start) + 1,
+            _ => continue,
+        };
+        let complexity = Complexity {
+            lines,
+            ..measured.unwrap_or_default()
+        };
+        if complexity.cyclomatic > 0 {
+            node.meta
+                .insert(COMPLEXITY.to_string(), complexity.cyclomatic.to_string());
+            node.meta
+                .insert(NESTING.to_string(), complexity.nesting.to_string());
+        }
+        node.meta
+            .insert(LINES.to_string(), complexity.lines.to_string());
+    }
+    Ok(())
+}
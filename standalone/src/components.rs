@@ -0,0 +1,246 @@
+//! The component tree of a frontend written with a JSX framework. When a
+//! `package.json` of the repo depends on one of [`FRAMEWORKS`], every
+//! capitalized function, class or variable of a `.jsx`, `.tsx` or `.js` file
+//! that returns JSX becomes a `Component` node, with a `RENDERS` edge to each
+//! component it writes as an element and a `USES_COMPONENT` edge to each one
+//! it only hands on, as in `<Route component={Settings} />`.
+
+use crate::captures::GRAMMARS;
+use crate::projects::PROJECT_KIND;
+use crate::storage::{kind_key, EdgeRecord, NodeRecord};
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::time::Duration;
+use tree_sitter::Node;
+
+pub const COMPONENT_KIND: &str = "Component";
+pub const RENDERS: &str = "RENDERS";
+pub const USES_COMPONENT: &str = "USES_COMPONENT";
+/// The meta key of the framework a component was written for, e.g. `react`.
+pub const FRAMEWORK: &str = "framework";
+/// The meta key of the id of the symbol defining a component.
+pub const DEFINED_BY: &str = "defined_by";
+
+/// The packages that make a repo a JSX framework's, and the framework each
+/// is named as.
+pub const FRAMEWORKS: &[(&str, &str)] = &[
+    ("react", "react"),
+    ("preact", "preact"),
+    ("solid-js", "solid"),
+];
+
+/// The grammar components are read with; `.js` files are parsed with it too.
+const GRAMMAR: &str = "tsx";
+
+/// Node kinds a component can be defined as.
+const SYMBOL_KINDS: &[&str] = &["Function", "Class", "Var"];
+
+/// The framework the `package.json` in `manifest` depends on, if any.
+pub fn framework(manifest: &str) -> Option<&'static str> {
+    let manifest: serde_json::Value = serde_json::from_str(manifest).ok()?;
+    ["dependencies", "devDependencies", "peerDependencies"]
+        .iter()
+        .filter_map(|section| manifest.get(section)?.as_object())
+        .find_map(|deps| {
+            FRAMEWORKS
+                .iter()
+                .find(|(package, _)| deps.contains_key(*package))
+                .map(|(_, name)| *name)
+        })
+}
+
+/// [`link`] over the files of `nodes`, read from the checkout at `root`,
+/// when the repo's top-level `package.json` or that of one of its projects
+/// names a framework; nothing otherwise. A file that can't be read any more
+/// is left out.
+pub fn detect(
+    root: &Path,
+    repo_id: &str,
+    nodes: &[NodeRecord],
+    timeout: Option<Duration>,
+) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let mut dirs = BTreeSet::from([".".to_string()]);
+    dirs.extend(
+        nodes
+            .iter()
+            .filter(|n| n.kind == PROJECT_KIND)
+            .filter(|n| n.meta.get("manifest").is_some_and(|m| m == "package.json"))
+            .filter_map(|n| n.meta.get("root").cloned()),
+    );
+    let Some(framework) = dirs.iter().find_map(|dir| {
+        let manifest = std::fs::read_to_string(root.join(dir).join("package.json")).ok()?;
+        framework(&manifest)
+    }) else {
+        return (Vec::new(), Vec::new());
+    };
+
+    let root_prefix = format!("{}/", root.display());
+    let mut sources = Vec::new();
+    for node in nodes
+        .iter()
+        .filter(|n| n.kind == "File" && has_jsx(&n.file))
+    {
+        let rel = node
+            .file
+            .find(&root_prefix)
+            .map_or(node.file.as_str(), |at| {
+                &node.file[at + root_prefix.len()..]
+            });
+        if let Ok(decoded) = crate::encoding::read(&root.join(rel)) {
+            sources.push((node.file.as_str(), decoded.text));
+        }
+    }
+    let sources: Vec<(&str, &str)> = sources.iter().map(|(f, s)| (*f, s.as_str())).collect();
+    let symbols: Vec<&NodeRecord> = nodes.iter().collect();
+    match link(framework, repo_id, &sources, &symbols, timeout) {
+        Ok(found) => found,
+        Err(e) => {
+            tracing::warn!("Failed to read the components of {}: {:#}", repo_id, e);
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+/// The components of `framework` defined by `symbols` in the `(file,
+/// source)` pairs of `sources`, and the edges between them. A component
+/// written in another file is found by name when only one has it; one
+/// defined outside `sources`, as a library's are, gets no edge, and a file
+/// that doesn't parse within `timeout` is left out.
+pub fn link(
+    framework: &str,
+    repo_id: &str,
+    sources: &[(&str, &str)],
+    symbols: &[&NodeRecord],
+    timeout: Option<Duration>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let grammar = GRAMMARS.iter().find(|g| g.name == GRAMMAR).unwrap();
+    let language = grammar.language();
+    let mut components: Vec<NodeRecord> = Vec::new();
+    // (component id, name used, edge kind)
+    let mut used: Vec<(String, String, &'static str)> = Vec::new();
+    for (file, source) in sources {
+        let Some(tree) = crate::lang::parse(&language, source, timeout)? else {
+            continue;
+        };
+        let candidates: Vec<&NodeRecord> = symbols
+            .iter()
+            .copied()
+            .filter(|s| s.file == *file && SYMBOL_KINDS.contains(&s.kind.as_str()))
+            .filter(|s| s.name.starts_with(|c: char| c.is_ascii_uppercase()))
+            .collect();
+        let mut found: BTreeMap<String, NodeRecord> = BTreeMap::new();
+        for (line, name, kind) in references(tree.root_node(), source.as_bytes()) {
+            let Some(symbol) = candidates
+                .iter()
+                .filter(|s| s.start <= line && line <= s.end)
+                .min_by_key(|s| s.end - s.start)
+            else {
+                continue;
+            };
+            let component = found
+                .entry(symbol.id.clone())
+                .or_insert_with(|| NodeRecord {
+                    repo_id: repo_id.to_string(),
+                    id: kind_key(COMPONENT_KIND, &symbol.name, file, symbol.start, None),
+                    kind: COMPONENT_KIND.to_string(),
+                    name: symbol.name.clone(),
+                    file: file.to_string(),
+                    start: symbol.start,
+                    end: symbol.end,
+                    body: String::new(),
+                    meta: BTreeMap::from([
+                        (FRAMEWORK.to_string(), framework.to_string()),
+                        (DEFINED_BY.to_string(), symbol.id.clone()),
+                    ]),
+                    span: symbol.span.clone(),
+                });
+            if let Some(name) = name {
+                used.push((component.id.clone(), name, kind));
+            }
+        }
+        components.extend(found.into_values());
+    }
+
+    let mut by_name: BTreeMap<&str, Vec<&NodeRecord>> = BTreeMap::new();
+    for component in &components {
+        by_name
+            .entry(component.name.as_str())
+            .or_default()
+            .push(component);
+    }
+    let file_of: BTreeMap<&str, &str> = components
+        .iter()
+        .map(|c| (c.id.as_str(), c.file.as_str()))
+        .collect();
+    let mut edges = BTreeSet::new();
+    for (source, name, kind) in &used {
+        let Some(named) = by_name.get(name.as_str()) else {
+            continue;
+        };
+        let file = file_of[source.as_str()];
+        let target = match named.iter().copied().find(|c| c.file == file) {
+            Some(target) => target,
+            None if named.len() == 1 => named[0],
+            None => continue,
+        };
+        if target.id != *source {
+            edges.insert((source.clone(), target.id.clone(), *kind));
+        }
+    }
+    let edges = edges
+        .into_iter()
+        .map(|(source, target, kind)| EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind: kind.to_string(),
+            source,
+            target,
+        })
+        .collect();
+    components.sort_by(|a, b| (&a.file, a.start).cmp(&(&b.file, b.start)));
+    Ok((components, edges))
+}
+
+/// Whether `file` can hold JSX.
+fn has_jsx(file: &str) -> bool {
+    let ext = Path::new(file).extension().and_then(|e| e.to_str());
+    GRAMMARS
+        .iter()
+        .find(|g| g.name == GRAMMAR)
+        .is_some_and(|g| ext.is_some_and(|ext| g.extensions.contains(&ext)))
+}
+
+/// The line of every JSX element under `root`, with the component it
+/// renders or hands on when that's capitalized, as components are and
+/// elements such as `div` aren't. A member such as `Menu.Item` is taken
+/// by its last name.
+fn references(root: Node, source: &[u8]) -> Vec<(usize, Option<String>, &'static str)> {
+    let name_of = |node: Node| {
+        let text = node.utf8_text(source).ok()?;
+        let name = text.rsplit('.').next()?;
+        name.starts_with(|c: char| c.is_ascii_uppercase())
+            .then(|| name.to_string())
+    };
+    let mut found = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        let line = node.start_position().row;
+        match node.kind() {
+            "jsx_opening_element" | "jsx_self_closing_element" => {
+                let name = node.child_by_field_name("name").and_then(name_of);
+                found.push((line, name, RENDERS));
+            }
+            "jsx_expression" if node.parent().is_some_and(|p| p.kind() == "jsx_attribute") => {
+                if let Some(value) = node.named_child(0).filter(|v| v.kind() == "identifier") {
+                    if let Some(name) = name_of(value) {
+                        found.push((line, Some(name), USES_COMPONENT));
+                    }
+                }
+            }
+            _ => {}
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+    found
+}
@@ -0,0 +1,966 @@
+use crate::audit::AuditLog;
+use crate::auth::{ApiKeys, Auth, Scope};
+use crate::captures::EdgeDefinition;
+use crate::clone::{self, Credentials, RetryPolicy};
+use crate::clones::{self, CloneDir};
+use crate::embeddings::{self, Embedder};
+use crate::events::KeepAliveConfig;
+use crate::ids::IdScheme;
+use crate::limits::{self, GraphLimit, RateLimiter};
+use crate::outbound::{self, Outbound, OutboundPolicy};
+use crate::paths::PathMap;
+use crate::redact::Redaction;
+use crate::server::{self, HttpConfig};
+use crate::storage::cache;
+use crate::storage::reconnect::{self, PoolConfig};
+use crate::tasks::{self, TaskRules};
+use crate::webhook::WebhookConfig;
+use crate::{
+    cors, events, fanout, health, idempotency, local, pipeline, query, retention, shutdown, source,
+    workspaces,
+};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Read from the working directory when `MESH_CONFIG` doesn't name a file.
+pub const DEFAULT_CONFIG_FILE: &str = "mesh.toml";
+
+/// Every setting the server reads at startup, each from a key of `mesh.toml`
+/// or the environment variable noted on it, which wins over the file.
+/// Lists are arrays in the file and comma-separated in the environment,
+/// except `allowed_roots`, which is a path list there (`:`-separated on unix).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `PORT`
+    pub port: u16,
+    /// `MESH_BACKEND`, `neo4j` or `sqlite`; whichever was compiled in when
+    /// unset.
+    pub backend: Option<String>,
+    /// `MESH_SQLITE_PATH`
+    pub sqlite_path: PathBuf,
+    /// `MESH_WORKSPACES`, names of workspaces served alongside the default
+    /// graph, each with its own repos; see [`crate::workspaces`].
+    pub workspaces: Vec<String>,
+    /// `MESH_WRITE_BATCH`, node and edge writes buffered before a flush;
+    /// `0` or `1` writes each one straight away.
+    pub write_batch: usize,
+    /// `MESH_CORS_ORIGINS`; any origin may call the API when unset.
+    pub cors_origins: Option<Vec<String>>,
+    /// `MESH_API_KEYS`; every route is open when there are none.
+    pub api_keys: Vec<String>,
+    /// `MESH_ADMIN_KEYS`, the only keys `/audit` opens to; they open every
+    /// other route too.
+    pub admin_keys: Vec<String>,
+    /// `MESH_AUTH_EVENTS`, whether the event streams need a key too.
+    pub auth_events: bool,
+    /// `MESH_AUTH_STATIC`, whether the UI needs a key too.
+    pub auth_static: bool,
+    /// `MESH_RATE_LIMIT_PER_MIN`, mutating requests per client; `0` turns
+    /// the limit off.
+    pub rate_limit_per_min: f64,
+    /// `MESH_RATE_LIMIT_BURST`
+    pub rate_limit_burst: f64,
+    /// `MESH_MAX_CONCURRENT_INGESTS`
+    pub max_concurrent_ingests: usize,
+    /// `MESH_ALLOWED_ROOTS`, the directories `/ingest-path`, and a request's
+    /// `repo_path`, may read from.
+    pub allowed_roots: Vec<PathBuf>,
+    /// `MESH_MAX_FILE_BYTES`; `0` parses files of any size.
+    pub max_file_bytes: u64,
+    /// `MESH_STREAM_FILE_BYTES`, the size past which a file is parsed a
+    /// chunk at a time rather than read into memory whole; `0` never does.
+    pub stream_file_bytes: u64,
+    /// `MESH_MAX_NODES_PER_REPO`; `0` lets a repo's graph grow as large as it gets.
+    pub max_nodes_per_repo: usize,
+    /// `MESH_MAX_EDGES_PER_REPO`; `0` for no limit.
+    pub max_edges_per_repo: usize,
+    /// `MESH_MAX_EDGES_PER_NODE`, edges of one node a traversal returns at
+    /// most; see [`crate::fanout`]. `0` returns them all.
+    pub max_edges_per_node: usize,
+    /// `MESH_SLOW_QUERY_MS`, how long a `/query` may wait on the backend
+    /// before it's logged, with its template and parameters, and counted in
+    /// `mesh_slow_queries_total`; `0` logs none.
+    pub slow_query_ms: u64,
+    /// `MESH_QUERY_TIMEOUT_MS`, how long a `/query` or traversal may run
+    /// before it's stopped, in the backend too, and answered with a 504; `0`
+    /// lets them run as long as they take.
+    pub query_timeout_ms: u64,
+    /// `MESH_PARSE_TIMEOUT_MS`; `0` lets parses run as long as they take.
+    pub parse_timeout_ms: u64,
+    /// `MESH_PARSE_WORKERS`; `0` uses one per CPU.
+    pub parse_workers: usize,
+    /// `MESH_WRITE_QUEUE`, how many parsed files may wait on the storage
+    /// writer before parsing waits for it.
+    pub write_queue: usize,
+    /// `MESH_GRAPH_CACHE_BYTES`, how much memory the graphs of repos warmed
+    /// through `/warm` may take up; `0` turns warming off.
+    pub graph_cache_bytes: usize,
+    /// `MESH_NODE_IDS`, `position` or `stable`; see
+    /// [`IdScheme`](crate::ids::IdScheme).
+    pub node_ids: IdScheme,
+    /// `MESH_PATH_PREFIX_STRIP`, a leading directory taken off the paths
+    /// nodes are stored under, e.g. the clone directory; see
+    /// [`PathMap`](crate::paths::PathMap).
+    pub path_prefix_strip: Option<String>,
+    /// `MESH_PATH_PREFIX_ADD`, put in front of the stored paths once
+    /// `path_prefix_strip` is off them.
+    pub path_prefix_add: Option<String>,
+    /// `MESH_EMBEDDING_URL`, where the source of functions and files is
+    /// posted to be embedded during ingest; nothing is embedded when unset.
+    /// Needs the `embeddings` feature.
+    pub embedding_url: Option<String>,
+    /// `MESH_EMBEDDING_MODEL`, sent along with each request when set.
+    pub embedding_model: Option<String>,
+    /// `MESH_EMBEDDING_TOKEN`, sent as a bearer token when set.
+    pub embedding_token: Option<String>,
+    /// `MESH_EMBEDDING_TIMEOUT_MS`, how long one symbol's embedding may take
+    /// before it is skipped.
+    pub embedding_timeout_ms: u64,
+    /// `MESH_OUTBOUND_ATTEMPTS`, tries of each call to a service outside the
+    /// server, such as the embedding provider.
+    pub outbound_attempts: u32,
+    /// `MESH_OUTBOUND_BACKOFF_MS`
+    pub outbound_backoff_ms: u64,
+    /// `MESH_BREAKER_FAILURES`, failed calls to a service in a row after
+    /// which it isn't called until `breaker_cooldown_secs` have passed.
+    pub breaker_failures: u32,
+    /// `MESH_BREAKER_COOLDOWN_SECS`
+    pub breaker_cooldown_secs: u64,
+    /// `MESH_RETENTION_TTL_SECS`, how long a repo may go without being
+    /// ingested or read before its graph is cleared; `0` keeps every repo.
+    pub retention_ttl_secs: u64,
+    /// `MESH_RETENTION_SWEEP_SECS`, how often repos past the TTL are looked for.
+    pub retention_sweep_secs: u64,
+    /// `MESH_PINNED_REPOS`, repo ids kept however long they go unused.
+    pub pinned_repos: Vec<String>,
+    /// `MESH_AUDIT_LOG`, a file ingests, fetches and clears are appended to,
+    /// or `backend` to keep them in the storage backend; none are recorded
+    /// when unset.
+    pub audit_log: Option<String>,
+    /// `MESH_QUERY_DIR`, where custom tree-sitter queries are loaded from;
+    /// see [`CustomQueries`](crate::captures::CustomQueries).
+    pub query_dir: Option<PathBuf>,
+    /// `MESH_INDEX_TEXT`, whether comments and string literals are stored
+    /// for `/grep`. Off by default, since it grows the graph.
+    pub index_text: bool,
+    /// `MESH_EMBEDDED_SQL`, whether string literals holding SQL are parsed
+    /// and linked to the tables they query; see [`crate::sql`]. Off by
+    /// default, since it reads every file a second time.
+    pub embedded_sql: bool,
+    /// `MESH_INTERFACE_REPOS`, repo ids whose OpenAPI documents and
+    /// protobuf files are ingested and linked to their handlers; see
+    /// [`crate::interfaces`].
+    pub interface_repos: Vec<String>,
+    /// `MESH_TASK_MARKERS`, the markers, e.g. `TODO`, whose comments are
+    /// stored as `Task` nodes; see [`crate::tasks`]. Empty to store none.
+    pub task_markers: Vec<String>,
+    /// `MESH_TASK_ASSIGNEE_PATTERN`, the regex finding who a task is for in
+    /// what follows its marker; its first group is the assignee.
+    pub task_assignee_pattern: String,
+    /// `MESH_TASK_ISSUE_PATTERN`, the regex finding the issue a task is
+    /// tracked by, likewise.
+    pub task_issue_pattern: String,
+    /// `MESH_REDACT_PATTERNS`, regexes over names, bodies and meta whose
+    /// matches are redacted before anything is stored; see
+    /// [`crate::redact`]. One per line in the environment, since a regex may
+    /// hold a comma.
+    pub redact_patterns: Vec<String>,
+    /// `MESH_REDACT_TEXT_REPOS`, repo ids whose comments and string literals
+    /// are stored without their text.
+    pub redact_text_repos: Vec<String>,
+    /// Edge kinds matched by tree-sitter queries, as `[[custom_edges]]`
+    /// tables; see [`EdgeDefinition`]. File only, like `git_credentials`.
+    pub custom_edges: Vec<EdgeDefinition>,
+    /// `MESH_EVENT_BUFFER`
+    pub event_buffer: usize,
+    /// `MESH_EVENT_REPLAY`; `0` turns replay off.
+    pub event_replay: usize,
+    /// `MESH_EVENT_ID_FILE`; empty keeps event ids in memory.
+    pub event_id_file: Option<PathBuf>,
+    /// `MESH_SSE_KEEPALIVE_MS`, how long an `/events` stream may be idle
+    /// before it sends a comment.
+    pub sse_keepalive_ms: u64,
+    /// `MESH_SSE_KEEPALIVE_TEXT`, the comment sent.
+    pub sse_keepalive_text: String,
+    /// `MESH_MAX_SSE_CLIENTS`, the `/events` streams that may be open at
+    /// once; `0` lets any number connect.
+    pub max_sse_clients: usize,
+    /// `MESH_SSE_COALESCE_MS`, how often each repo's progress may go out on
+    /// an `/events` stream, the latest winning; `0` sends every update.
+    pub sse_coalesce_ms: u64,
+    /// `MESH_HTTP2`, whether HTTP/2 is served alongside HTTP/1.1.
+    pub http2: bool,
+    /// `MESH_HTTP2_MAX_STREAMS`, the requests, `/events` streams among
+    /// them, one HTTP/2 connection may have open at once.
+    pub http2_max_streams: u32,
+    /// `MESH_HTTP2_KEEPALIVE_SECS`, how often an HTTP/2 connection is
+    /// pinged; `0` never pings.
+    pub http2_keepalive_secs: u64,
+    /// `MESH_HTTP2_KEEPALIVE_TIMEOUT_SECS`, how long a ping may go
+    /// unanswered before the connection is closed.
+    pub http2_keepalive_timeout_secs: u64,
+    /// `MESH_HTTP1_KEEPALIVE`, whether HTTP/1.1 connections are kept open
+    /// between requests.
+    pub http1_keepalive: bool,
+    /// `MESH_STATIC_DIR`; searched for next to the binary when unset.
+    pub static_dir: Option<PathBuf>,
+    /// `MESH_TLS_CERT`, a PEM certificate chain; with `tls_key` the server
+    /// speaks HTTPS, in builds with the `tls` feature.
+    pub tls_cert: Option<PathBuf>,
+    /// `MESH_TLS_KEY`, the certificate's PEM private key.
+    pub tls_key: Option<PathBuf>,
+    /// `MESH_WEBHOOK_SECRET`; `/webhook` refuses deliveries when unset.
+    pub webhook_secret: Option<String>,
+    /// `MESH_WEBHOOK_REPOS`
+    pub webhook_repos: Vec<String>,
+    /// `MESH_SHUTDOWN_GRACE_SECS`
+    pub shutdown_grace_secs: u64,
+    /// `MESH_READY_TIMEOUT_MS`
+    pub ready_timeout_ms: u64,
+    /// `MESH_IDEMPOTENCY_TTL_SECS`, how long an `Idempotency-Key`'s result
+    /// is replayed.
+    pub idempotency_ttl_secs: u64,
+    /// `MESH_ALLOW_RAW_CYPHER`; the statements still need an admin key.
+    pub allow_raw_cypher: bool,
+    /// `MESH_READONLY`, for a query server in front of a read replica: the
+    /// routes that write answer 405 and only reads are served.
+    pub readonly: bool,
+    /// `MESH_CLONE_ATTEMPTS`
+    pub clone_attempts: u32,
+    /// `MESH_CLONE_BACKOFF_MS`
+    pub clone_backoff_ms: u64,
+    /// `MESH_DEFAULT_BRANCHES`, comma-separated, tried in order when a fetch
+    /// asks for no ref and the remote doesn't say which branch its HEAD is.
+    pub default_branches: Vec<String>,
+    /// Named credentials a request can clone with by passing `credential`
+    /// instead of a token, as `[git_credentials.<name>]` tables of a token
+    /// or an `ssh_key` file. File only, as the environment has no way to
+    /// spell a table.
+    pub git_credentials: BTreeMap<String, Credentials>,
+    /// `MESH_CLONE_DIR`, where remote repos are cloned; a directory under the
+    /// system temp dir when unset.
+    pub clone_dir: Option<PathBuf>,
+    /// `MESH_CLONE_MIN_FREE_MB`, the free space a clone needs to start; 0
+    /// turns the check off.
+    pub clone_min_free_mb: u64,
+    /// `MESH_KEEP_CLONES`, keeps checkouts after their ingest and across
+    /// restarts instead of removing them.
+    pub keep_clones: bool,
+    /// `MESH_STORAGE_POOL_SIZE`, the most connections neo4j keeps open.
+    pub storage_pool_size: usize,
+    /// `MESH_STORAGE_CONNECT_TIMEOUT_MS`
+    pub storage_connect_timeout_ms: u64,
+    /// `MESH_STORAGE_ATTEMPTS`, for each operation whose connection dropped.
+    pub storage_attempts: u32,
+    /// `MESH_STORAGE_BACKOFF_MS`
+    pub storage_backoff_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 7777,
+            backend: None,
+            sqlite_path: PathBuf::from("mesh.db"),
+            workspaces: Vec::new(),
+            write_batch: crate::storage::batch::DEFAULT_BATCH_SIZE,
+            cors_origins: None,
+            api_keys: Vec::new(),
+            admin_keys: Vec::new(),
+            auth_events: false,
+            auth_static: false,
+            rate_limit_per_min: limits::DEFAULT_RATE_LIMIT_PER_MIN,
+            rate_limit_burst: limits::DEFAULT_RATE_LIMIT_BURST,
+            max_concurrent_ingests: limits::DEFAULT_MAX_CONCURRENT_INGESTS,
+            allowed_roots: Vec::new(),
+            max_file_bytes: local::DEFAULT_MAX_FILE_BYTES,
+            stream_file_bytes: source::DEFAULT_STREAM_BYTES,
+            max_nodes_per_repo: 0,
+            max_edges_per_repo: 0,
+            max_edges_per_node: fanout::DEFAULT_MAX_EDGES_PER_NODE,
+            slow_query_ms: query::DEFAULT_SLOW_QUERY_MS,
+            query_timeout_ms: query::DEFAULT_QUERY_TIMEOUT_MS,
+            parse_timeout_ms: crate::lang::DEFAULT_PARSE_TIMEOUT_MS,
+            parse_workers: 0,
+            write_queue: pipeline::DEFAULT_WRITE_QUEUE,
+            graph_cache_bytes: cache::DEFAULT_GRAPH_CACHE_BYTES,
+            node_ids: IdScheme::default(),
+            path_prefix_strip: None,
+            path_prefix_add: None,
+            embedding_url: None,
+            embedding_model: None,
+            embedding_token: None,
+            embedding_timeout_ms: embeddings::DEFAULT_TIMEOUT_MS,
+            outbound_attempts: outbound::DEFAULT_ATTEMPTS,
+            outbound_backoff_ms: outbound::DEFAULT_BASE_DELAY.as_millis() as u64,
+            breaker_failures: outbound::DEFAULT_BREAKER_FAILURES,
+            breaker_cooldown_secs: outbound::DEFAULT_BREAKER_COOLDOWN.as_secs(),
+            retention_ttl_secs: 0,
+            retention_sweep_secs: retention::DEFAULT_SWEEP_INTERVAL.as_secs(),
+            pinned_repos: Vec::new(),
+            audit_log: None,
+            query_dir: None,
+            index_text: false,
+            embedded_sql: false,
+            interface_repos: Vec::new(),
+            task_markers: tasks::DEFAULT_MARKERS
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+            task_assignee_pattern: tasks::DEFAULT_ASSIGNEE_PATTERN.to_string(),
+            task_issue_pattern: tasks::DEFAULT_ISSUE_PATTERN.to_string(),
+            redact_patterns: Vec::new(),
+            redact_text_repos: Vec::new(),
+            custom_edges: Vec::new(),
+            event_buffer: events::DEFAULT_EVENT_BUFFER,
+            event_replay: events::DEFAULT_REPLAY_BUFFER,
+            event_id_file: Some(PathBuf::from(events::DEFAULT_ID_FILE)),
+            sse_keepalive_ms: events::DEFAULT_KEEPALIVE.as_millis() as u64,
+            sse_keepalive_text: events::DEFAULT_KEEPALIVE_TEXT.to_string(),
+            max_sse_clients: events::DEFAULT_MAX_SSE_CLIENTS,
+            sse_coalesce_ms: 0,
+            http2: true,
+            http2_max_streams: server::DEFAULT_MAX_STREAMS,
+            http2_keepalive_secs: server::DEFAULT_KEEPALIVE.as_secs(),
+            http2_keepalive_timeout_secs: server::DEFAULT_KEEPALIVE_TIMEOUT.as_secs(),
+            http1_keepalive: true,
+            static_dir: None,
+            tls_cert: None,
+            tls_key: None,
+            webhook_secret: None,
+            webhook_repos: Vec::new(),
+            shutdown_grace_secs: shutdown::DEFAULT_GRACE_PERIOD.as_secs(),
+            ready_timeout_ms: health::DEFAULT_READY_TIMEOUT.as_millis() as u64,
+            idempotency_ttl_secs: idempotency::DEFAULT_TTL.as_secs(),
+            allow_raw_cypher: false,
+            readonly: false,
+            clone_attempts: clone::DEFAULT_ATTEMPTS,
+            clone_backoff_ms: clone::DEFAULT_BASE_DELAY.as_millis() as u64,
+            default_branches: clone::DEFAULT_BRANCHES.map(String::from).to_vec(),
+            git_credentials: BTreeMap::new(),
+            clone_dir: None,
+            clone_min_free_mb: clones::DEFAULT_MIN_FREE_MB,
+            keep_clones: false,
+            storage_pool_size: reconnect::DEFAULT_POOL_SIZE,
+            storage_connect_timeout_ms: reconnect::DEFAULT_CONNECT_TIMEOUT.as_millis() as u64,
+            storage_attempts: reconnect::DEFAULT_ATTEMPTS,
+            storage_backoff_ms: reconnect::DEFAULT_BASE_DELAY.as_millis() as u64,
+        }
+    }
+}
+
+impl Config {
+    /// The file `MESH_CONFIG` names, or `mesh.toml` if there is one, with the
+    /// environment on top.
+    pub fn load() -> Result<Self> {
+        let path = match std::env::var_os("MESH_CONFIG") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.is_file()),
+        };
+        Config::load_from(path.as_deref(), |key| std::env::var(key).ok())
+    }
+
+    /// [`load`](Config::load) with the file and the environment given: `env`
+    /// returns the value of a variable, if it is set.
+    pub fn load_from(path: Option<&Path>, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read config file {}", path.display()))?;
+                toml::from_str(&text)
+                    .with_context(|| format!("invalid config file {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env(&env)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, env: &dyn Fn(&str) -> Option<String>) -> Result<()> {
+        set(env, "PORT", &mut self.port)?;
+        set_optional(env, "MESH_BACKEND", &mut self.backend)?;
+        set(env, "MESH_SQLITE_PATH", &mut self.sqlite_path)?;
+        if let Some(names) = env("MESH_WORKSPACES") {
+            self.workspaces = list(&names);
+        }
+        set(env, "MESH_WRITE_BATCH", &mut self.write_batch)?;
+        if let Some(origins) = env("MESH_CORS_ORIGINS") {
+            self.cors_origins = (!origins.trim().is_empty()).then(|| list(&origins));
+        }
+        if let Some(keys) = env("MESH_API_KEYS") {
+            self.api_keys = list(&keys);
+        }
+        if let Some(keys) = env("MESH_ADMIN_KEYS") {
+            self.admin_keys = list(&keys);
+        }
+        set_flag(env, "MESH_AUTH_EVENTS", &mut self.auth_events)?;
+        set_flag(env, "MESH_AUTH_STATIC", &mut self.auth_static)?;
+        set(env, "MESH_RATE_LIMIT_PER_MIN", &mut self.rate_limit_per_min)?;
+        set(env, "MESH_RATE_LIMIT_BURST", &mut self.rate_limit_burst)?;
+        set(
+            env,
+            "MESH_MAX_CONCURRENT_INGESTS",
+            &mut self.max_concurrent_ingests,
+        )?;
+        if let Some(roots) = env("MESH_ALLOWED_ROOTS") {
+            self.allowed_roots = std::env::split_paths(&roots)
+                .filter(|p| !p.as_os_str().is_empty())
+                .collect();
+        }
+        set(env, "MESH_MAX_FILE_BYTES", &mut self.max_file_bytes)?;
+        set(env, "MESH_STREAM_FILE_BYTES", &mut self.stream_file_bytes)?;
+        set(env, "MESH_MAX_NODES_PER_REPO", &mut self.max_nodes_per_repo)?;
+        set(env, "MESH_MAX_EDGES_PER_REPO", &mut self.max_edges_per_repo)?;
+        set(env, "MESH_MAX_EDGES_PER_NODE", &mut self.max_edges_per_node)?;
+        set(env, "MESH_SLOW_QUERY_MS", &mut self.slow_query_ms)?;
+        set(env, "MESH_QUERY_TIMEOUT_MS", &mut self.query_timeout_ms)?;
+        set(env, "MESH_PARSE_TIMEOUT_MS", &mut self.parse_timeout_ms)?;
+        set(env, "MESH_PARSE_WORKERS", &mut self.parse_workers)?;
+        set(env, "MESH_WRITE_QUEUE", &mut self.write_queue)?;
+        set(env, "MESH_GRAPH_CACHE_BYTES", &mut self.graph_cache_bytes)?;
+        set(env, "MESH_NODE_IDS", &mut self.node_ids)?;
+        set_optional(env, "MESH_PATH_PREFIX_STRIP", &mut self.path_prefix_strip)?;
+        set_optional(env, "MESH_PATH_PREFIX_ADD", &mut self.path_prefix_add)?;
+        set_optional(env, "MESH_EMBEDDING_URL", &mut self.embedding_url)?;
+        set_optional(env, "MESH_EMBEDDING_MODEL", &mut self.embedding_model)?;
+        set_optional(env, "MESH_EMBEDDING_TOKEN", &mut self.embedding_token)?;
+        set(
+            env,
+            "MESH_EMBEDDING_TIMEOUT_MS",
+            &mut self.embedding_timeout_ms,
+        )?;
+        set(env, "MESH_OUTBOUND_ATTEMPTS", &mut self.outbound_attempts)?;
+        set(
+            env,
+            "MESH_OUTBOUND_BACKOFF_MS",
+            &mut self.outbound_backoff_ms,
+        )?;
+        set(env, "MESH_BREAKER_FAILURES", &mut self.breaker_failures)?;
+        set(
+            env,
+            "MESH_BREAKER_COOLDOWN_SECS",
+            &mut self.breaker_cooldown_secs,
+        )?;
+        set(env, "MESH_RETENTION_TTL_SECS", &mut self.retention_ttl_secs)?;
+        set(
+            env,
+            "MESH_RETENTION_SWEEP_SECS",
+            &mut self.retention_sweep_secs,
+        )?;
+        if let Some(repos) = env("MESH_PINNED_REPOS") {
+            self.pinned_repos = list(&repos);
+        }
+        set_optional(env, "MESH_AUDIT_LOG", &mut self.audit_log)?;
+        set_optional(env, "MESH_QUERY_DIR", &mut self.query_dir)?;
+        set_flag(env, "MESH_INDEX_TEXT", &mut self.index_text)?;
+        set_flag(env, "MESH_EMBEDDED_SQL", &mut self.embedded_sql)?;
+        if let Some(repos) = env("MESH_INTERFACE_REPOS") {
+            self.interface_repos = list(&repos);
+        }
+        if let Some(markers) = env("MESH_TASK_MARKERS") {
+            self.task_markers = list(&markers);
+        }
+        set(
+            env,
+            "MESH_TASK_ASSIGNEE_PATTERN",
+            &mut self.task_assignee_pattern,
+        )?;
+        set(env, "MESH_TASK_ISSUE_PATTERN", &mut self.task_issue_pattern)?;
+        if let Some(patterns) = env("MESH_REDACT_PATTERNS") {
+            self.redact_patterns = patterns
+                .lines()
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(repos) = env("MESH_REDACT_TEXT_REPOS") {
+            self.redact_text_repos = list(&repos);
+        }
+        set(env, "MESH_EVENT_BUFFER", &mut self.event_buffer)?;
+        set(env, "MESH_EVENT_REPLAY", &mut self.event_replay)?;
+        set_optional(env, "MESH_EVENT_ID_FILE", &mut self.event_id_file)?;
+        set(env, "MESH_SSE_KEEPALIVE_MS", &mut self.sse_keepalive_ms)?;
+        if let Some(text) = env("MESH_SSE_KEEPALIVE_TEXT") {
+            self.sse_keepalive_text = text;
+        }
+        set(env, "MESH_MAX_SSE_CLIENTS", &mut self.max_sse_clients)?;
+        set(env, "MESH_SSE_COALESCE_MS", &mut self.sse_coalesce_ms)?;
+        set_flag(env, "MESH_HTTP2", &mut self.http2)?;
+        set(env, "MESH_HTTP2_MAX_STREAMS", &mut self.http2_max_streams)?;
+        set(
+            env,
+            "MESH_HTTP2_KEEPALIVE_SECS",
+            &mut self.http2_keepalive_secs,
+        )?;
+        set(
+            env,
+            "MESH_HTTP2_KEEPALIVE_TIMEOUT_SECS",
+            &mut self.http2_keepalive_timeout_secs,
+        )?;
+        set_flag(env, "MESH_HTTP1_KEEPALIVE", &mut self.http1_keepalive)?;
+        set_optional(env, "MESH_STATIC_DIR", &mut self.static_dir)?;
+        set_optional(env, "MESH_TLS_CERT", &mut self.tls_cert)?;
+        set_optional(env, "MESH_TLS_KEY", &mut self.tls_key)?;
+        set_optional(env, "MESH_WEBHOOK_SECRET", &mut self.webhook_secret)?;
+        if let Some(repos) = env("MESH_WEBHOOK_REPOS") {
+            self.webhook_repos = list(&repos);
+        }
+        set(
+            env,
+            "MESH_SHUTDOWN_GRACE_SECS",
+            &mut self.shutdown_grace_secs,
+        )?;
+        set(env, "MESH_READY_TIMEOUT_MS", &mut self.ready_timeout_ms)?;
+        set(
+            env,
+            "MESH_IDEMPOTENCY_TTL_SECS",
+            &mut self.idempotency_ttl_secs,
+        )?;
+        set_flag(env, "MESH_ALLOW_RAW_CYPHER", &mut self.allow_raw_cypher)?;
+        set_flag(env, "MESH_READONLY", &mut self.readonly)?;
+        set(env, "MESH_CLONE_ATTEMPTS", &mut self.clone_attempts)?;
+        set(env, "MESH_CLONE_BACKOFF_MS", &mut self.clone_backoff_ms)?;
+        if let Some(branches) = env("MESH_DEFAULT_BRANCHES") {
+            self.default_branches = list(&branches);
+        }
+        set_optional(env, "MESH_CLONE_DIR", &mut self.clone_dir)?;
+        set(env, "MESH_CLONE_MIN_FREE_MB", &mut self.clone_min_free_mb)?;
+        set_flag(env, "MESH_KEEP_CLONES", &mut self.keep_clones)?;
+        set(env, "MESH_STORAGE_POOL_SIZE", &mut self.storage_pool_size)?;
+        set(
+            env,
+            "MESH_STORAGE_CONNECT_TIMEOUT_MS",
+            &mut self.storage_connect_timeout_ms,
+        )?;
+        set(env, "MESH_STORAGE_ATTEMPTS", &mut self.storage_attempts)?;
+        set(env, "MESH_STORAGE_BACKOFF_MS", &mut self.storage_backoff_ms)?;
+        Ok(())
+    }
+
+    /// What the types alone don't rule out. In the file an empty path or
+    /// secret means unset, as it does in the environment.
+    fn validate(&mut self) -> Result<()> {
+        for path in [
+            &mut self.event_id_file,
+            &mut self.static_dir,
+            &mut self.tls_cert,
+            &mut self.tls_key,
+            &mut self.query_dir,
+            &mut self.clone_dir,
+        ] {
+            if path.as_ref().is_some_and(|p| p.as_os_str().is_empty()) {
+                *path = None;
+            }
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(_), None) => bail!("tls_cert is set without tls_key"),
+            (None, Some(_)) => bail!("tls_key is set without tls_cert"),
+            (Some(_), Some(_)) if !cfg!(feature = "tls") => {
+                bail!("TLS is configured but this build has no tls feature")
+            }
+            _ => {}
+        }
+        for (name, credentials) in &self.git_credentials {
+            match (&credentials.token, &credentials.ssh_key) {
+                (None, None) => bail!("git credential {} has neither a token nor an ssh_key", name),
+                (Some(_), Some(_)) => {
+                    bail!("git credential {} has both a token and an ssh_key", name)
+                }
+                (None, Some(key)) if !key.is_file() => bail!(
+                    "ssh_key {} of git credential {} is not a file",
+                    key.display(),
+                    name
+                ),
+                _ => {}
+            }
+        }
+        for value in [
+            &mut self.webhook_secret,
+            &mut self.embedding_url,
+            &mut self.path_prefix_strip,
+            &mut self.path_prefix_add,
+            &mut self.embedding_model,
+            &mut self.embedding_token,
+            &mut self.audit_log,
+        ] {
+            if value.as_ref().is_some_and(|s| s.is_empty()) {
+                *value = None;
+            }
+        }
+        if self.embedding_url.is_some() && !cfg!(feature = "embeddings") {
+            bail!("embedding_url is set but this build has no embeddings feature")
+        }
+        if self.backend.as_ref().is_some_and(|b| b.is_empty()) {
+            self.backend = None;
+        }
+        if self.cors_origins.as_ref().is_some_and(|o| o.is_empty()) {
+            self.cors_origins = None;
+        }
+        for (key, value) in [
+            ("max_concurrent_ingests", self.max_concurrent_ingests),
+            ("event_buffer", self.event_buffer),
+            ("write_queue", self.write_queue),
+            ("sse_keepalive_ms", self.sse_keepalive_ms as usize),
+            ("http2_max_streams", self.http2_max_streams as usize),
+            (
+                "http2_keepalive_timeout_secs",
+                self.http2_keepalive_timeout_secs as usize,
+            ),
+            ("clone_attempts", self.clone_attempts as usize),
+            ("outbound_attempts", self.outbound_attempts as usize),
+            ("breaker_failures", self.breaker_failures as usize),
+            ("storage_pool_size", self.storage_pool_size),
+            (
+                "storage_connect_timeout_ms",
+                self.storage_connect_timeout_ms as usize,
+            ),
+            ("storage_attempts", self.storage_attempts as usize),
+            ("idempotency_ttl_secs", self.idempotency_ttl_secs as usize),
+            ("retention_sweep_secs", self.retention_sweep_secs as usize),
+        ] {
+            if value == 0 {
+                bail!("{} must be at least 1", key);
+            }
+        }
+        for (key, value) in [
+            ("rate_limit_per_min", self.rate_limit_per_min),
+            ("rate_limit_burst", self.rate_limit_burst),
+        ] {
+            if !(value >= 0.0 && value.is_finite()) {
+                bail!("{} must be a number of requests, got {}", key, value);
+            }
+        }
+        // sent as an SSE comment, which ends at the first line break
+        if self.sse_keepalive_text.contains(['\r', '\n']) {
+            bail!("sse_keepalive_text must be a single line");
+        }
+        for (i, name) in self.workspaces.iter().enumerate() {
+            workspaces::validate_name(name)?;
+            if self.workspaces[..i].contains(name) {
+                bail!("workspace {} is listed twice", name);
+            }
+        }
+        self.cors_origins()?;
+        Ok(())
+    }
+
+    /// The origins as CORS header values; fails on any that isn't an origin.
+    pub fn cors_origins(&self) -> Result<Option<Vec<axum::http::HeaderValue>>> {
+        match &self.cors_origins {
+            Some(origins) => cors::parse_origins(&origins.join(",")).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The API keys and the routes they guard: every route that writes or
+    /// starts work, plus the event streams and UI when asked. Admin keys on
+    /// their own guard only `/audit`. `None` when there are no keys.
+    pub fn auth(&self) -> Option<Auth> {
+        let keys: Vec<&str> = self.api_keys.iter().map(String::as_str).collect();
+        let keys = ApiKeys::new(&keys);
+        let admin: Vec<&str> = self.admin_keys.iter().map(String::as_str).collect();
+        let admin = ApiKeys::new(&admin);
+        if keys.is_empty() && admin.is_empty() {
+            return None;
+        }
+        let mut protected = Vec::new();
+        if !keys.is_empty() {
+            protected.push(Scope::Mutating);
+            if self.auth_events {
+                protected.push(Scope::Events);
+            }
+            if self.auth_static {
+                protected.push(Scope::Static);
+            }
+        }
+        Some(Auth::new(keys, &protected).with_admin_keys(admin))
+    }
+
+    /// The audit log `MESH_AUDIT_LOG` names; fails when its file can't be
+    /// opened.
+    pub fn audit_log(&self) -> Result<Option<AuditLog>> {
+        self.audit_log.as_deref().map(AuditLog::open).transpose()
+    }
+
+    /// How task markers are read, unless there are none to look for; fails
+    /// when a pattern doesn't compile.
+    pub fn task_rules(&self) -> Result<Option<TaskRules>> {
+        if self.task_markers.is_empty() {
+            return Ok(None);
+        }
+        TaskRules::new(
+            &self.task_markers,
+            &self.task_assignee_pattern,
+            &self.task_issue_pattern,
+        )
+        .map(Some)
+    }
+
+    /// What's redacted before storing, unless there's nothing to; fails when
+    /// a pattern doesn't compile.
+    pub fn redaction(&self) -> Result<Option<Redaction>> {
+        if self.redact_patterns.is_empty() && self.redact_text_repos.is_empty() {
+            return Ok(None);
+        }
+        Redaction::new(&self.redact_patterns, &self.redact_text_repos).map(Some)
+    }
+
+    pub fn rate_limit(&self) -> Option<RateLimiter> {
+        (self.rate_limit_per_min > 0.0)
+            .then(|| RateLimiter::new(self.rate_limit_per_min / 60.0, self.rate_limit_burst))
+    }
+
+    pub fn webhook(&self) -> Option<WebhookConfig> {
+        let secret = self.webhook_secret.as_deref()?;
+        let repos: Vec<&str> = self.webhook_repos.iter().map(String::as_str).collect();
+        Some(WebhookConfig::new(secret, &repos))
+    }
+
+    /// What ingested symbols are embedded with, when `embedding_url` is set,
+    /// calling the provider through `outbound`.
+    pub fn embedder(&self, outbound: Arc<Outbound>) -> Result<Option<Arc<dyn Embedder>>> {
+        let Some(url) = self.embedding_url.as_deref() else {
+            return Ok(None);
+        };
+        #[cfg(feature = "embeddings")]
+        {
+            let embedder = embeddings::HttpEmbedder::new(
+                url,
+                self.embedding_model.as_deref(),
+                self.embedding_token.as_deref(),
+                outbound,
+            )
+            .context("Failed to build the embedding client")?;
+            Ok(Some(Arc::new(embedder)))
+        }
+        #[cfg(not(feature = "embeddings"))]
+        {
+            let _ = outbound;
+            bail!(
+                "cannot embed with {}: this build has no embeddings feature",
+                url
+            )
+        }
+    }
+
+    /// How the other services the server calls are retried and their
+    /// breakers tripped.
+    pub fn outbound_policy(&self) -> OutboundPolicy {
+        OutboundPolicy {
+            timeout: outbound::DEFAULT_TIMEOUT,
+            retry: RetryPolicy {
+                max_attempts: self.outbound_attempts,
+                base_delay: Duration::from_millis(self.outbound_backoff_ms),
+            },
+            breaker_failures: self.breaker_failures,
+            breaker_cooldown: Duration::from_secs(self.breaker_cooldown_secs),
+        }
+    }
+
+    /// The calls to the embedding provider, each allowed
+    /// `embedding_timeout_ms`.
+    pub fn embedding_calls(&self) -> Outbound {
+        let policy = OutboundPolicy {
+            timeout: Duration::from_millis(self.embedding_timeout_ms),
+            ..self.outbound_policy()
+        };
+        Outbound::new("embeddings", policy)
+    }
+
+    pub fn retention_ttl(&self) -> Option<Duration> {
+        (self.retention_ttl_secs > 0).then(|| Duration::from_secs(self.retention_ttl_secs))
+    }
+
+    pub fn retention_sweep(&self) -> Duration {
+        Duration::from_secs(self.retention_sweep_secs)
+    }
+
+    pub fn max_file_bytes(&self) -> Option<u64> {
+        (self.max_file_bytes > 0).then_some(self.max_file_bytes)
+    }
+
+    pub fn stream_file_bytes(&self) -> Option<u64> {
+        (self.stream_file_bytes > 0).then_some(self.stream_file_bytes)
+    }
+
+    pub fn graph_limit(&self) -> GraphLimit {
+        GraphLimit {
+            max_nodes: (self.max_nodes_per_repo > 0).then_some(self.max_nodes_per_repo),
+            max_edges: (self.max_edges_per_repo > 0).then_some(self.max_edges_per_repo),
+        }
+    }
+
+    pub fn edge_cap(&self) -> Option<usize> {
+        (self.max_edges_per_node > 0).then_some(self.max_edges_per_node)
+    }
+
+    pub fn slow_query(&self) -> Option<Duration> {
+        (self.slow_query_ms > 0).then(|| Duration::from_millis(self.slow_query_ms))
+    }
+
+    pub fn query_timeout(&self) -> Option<Duration> {
+        (self.query_timeout_ms > 0).then(|| Duration::from_millis(self.query_timeout_ms))
+    }
+
+    pub fn parse_timeout(&self) -> Option<Duration> {
+        (self.parse_timeout_ms > 0).then(|| Duration::from_millis(self.parse_timeout_ms))
+    }
+
+    pub fn parse_workers(&self) -> usize {
+        match self.parse_workers {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    pub fn clone_dir(&self) -> CloneDir {
+        let root = match &self.clone_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::temp_dir().join(clones::DEFAULT_DIR),
+        };
+        CloneDir::new(
+            root,
+            self.clone_min_free_mb.saturating_mul(1024 * 1024),
+            self.keep_clones,
+        )
+    }
+
+    pub fn clone_retry(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.clone_attempts,
+            base_delay: Duration::from_millis(self.clone_backoff_ms),
+        }
+    }
+
+    pub fn storage_pool(&self) -> PoolConfig {
+        PoolConfig {
+            max_connections: self.storage_pool_size,
+            connect_timeout: Duration::from_millis(self.storage_connect_timeout_ms),
+            retry: RetryPolicy {
+                max_attempts: self.storage_attempts,
+                base_delay: Duration::from_millis(self.storage_backoff_ms),
+            },
+        }
+    }
+
+    /// The certificate and key to serve HTTPS with; plain HTTP when `None`.
+    pub fn tls(&self) -> Option<(&Path, &Path)> {
+        Some((self.tls_cert.as_deref()?, self.tls_key.as_deref()?))
+    }
+
+    pub fn ready_timeout(&self) -> Duration {
+        Duration::from_millis(self.ready_timeout_ms)
+    }
+
+    pub fn keep_alive(&self) -> KeepAliveConfig {
+        KeepAliveConfig {
+            interval: Duration::from_millis(self.sse_keepalive_ms),
+            text: self.sse_keepalive_text.clone(),
+        }
+    }
+
+    pub fn max_sse_clients(&self) -> Option<usize> {
+        (self.max_sse_clients > 0).then_some(self.max_sse_clients)
+    }
+
+    pub fn sse_coalesce(&self) -> Option<Duration> {
+        (self.sse_coalesce_ms > 0).then(|| Duration::from_millis(self.sse_coalesce_ms))
+    }
+
+    pub fn path_map(&self) -> PathMap {
+        PathMap::new(
+            self.path_prefix_strip.as_deref(),
+            self.path_prefix_add.as_deref(),
+        )
+    }
+
+    pub fn http(&self) -> HttpConfig {
+        HttpConfig {
+            http2: self.http2,
+            max_concurrent_streams: self.http2_max_streams,
+            keep_alive: (self.http2_keepalive_secs > 0)
+                .then(|| Duration::from_secs(self.http2_keepalive_secs)),
+            keep_alive_timeout: Duration::from_secs(self.http2_keepalive_timeout_secs),
+            http1_keep_alive: self.http1_keepalive,
+        }
+    }
+
+    pub fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    pub fn idempotency_ttl(&self) -> Duration {
+        Duration::from_secs(self.idempotency_ttl_secs)
+    }
+}
+
+fn set<T>(env: &dyn Fn(&str) -> Option<String>, key: &str, field: &mut T) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(value) = env(key) {
+        *field = parse(key, &value)?;
+    }
+    Ok(())
+}
+
+/// An empty variable unsets the setting.
+fn set_optional<T>(
+    env: &dyn Fn(&str) -> Option<String>,
+    key: &str,
+    field: &mut Option<T>,
+) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(value) = env(key) {
+        *field = if value.is_empty() {
+            None
+        } else {
+            Some(parse(key, &value)?)
+        };
+    }
+    Ok(())
+}
+
+fn set_flag(env: &dyn Fn(&str) -> Option<String>, key: &str, field: &mut bool) -> Result<()> {
+    if let Some(value) = env(key) {
+        *field = match value.trim() {
+            "true" => true,
+            "false" | "" => false,
+            other => bail!("{} must be true or false, got '{}'", key, other),
+        };
+    }
+    Ok(())
+}
+
+fn parse<T>(key: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| anyhow::anyhow!("invalid {} '{}': {}", key, value, e))
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect()
+}
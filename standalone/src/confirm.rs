@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What `confirm` is set to for a clear of every repo.
+pub const ALL: &str = "ALL";
+/// How long a token from `GET /clear` can be used for.
+pub const TOKEN_TTL: Duration = Duration::from_secs(60);
+
+struct Issued {
+    /// The ref-scoped repo id the token clears, `None` for everything.
+    scope: Option<String>,
+    expires: Instant,
+}
+
+/// Single-use tokens confirming a clear of one scope, handed out by
+/// `GET /clear` so a client can show what is about to go first.
+#[derive(Default)]
+pub struct ConfirmTokens {
+    issued: Mutex<HashMap<String, Issued>>,
+}
+
+impl ConfirmTokens {
+    /// A new token for clearing `scope`, valid for [`TOKEN_TTL`].
+    pub fn issue(&self, scope: Option<&str>) -> String {
+        // every `RandomState` is keyed from the OS's randomness, so the
+        // hashes can't be guessed from the tokens handed out before
+        let token = format!(
+            "{:016x}{:016x}",
+            RandomState::new().hash_one(scope),
+            RandomState::new().hash_one(Instant::now())
+        );
+        let now = Instant::now();
+        let mut issued = self.issued.lock().unwrap();
+        issued.retain(|_, t| t.expires > now);
+        issued.insert(
+            token.clone(),
+            Issued {
+                scope: scope.map(str::to_string),
+                expires: now + TOKEN_TTL,
+            },
+        );
+        token
+    }
+
+    /// Whether `token` was issued for `scope` and hasn't expired. A token
+    /// is used up by the first attempt, whatever its outcome.
+    pub fn redeem(&self, token: &str, scope: Option<&str>) -> bool {
+        match self.issued.lock().unwrap().remove(token) {
+            Some(issued) => issued.expires > Instant::now() && issued.scope.as_deref() == scope,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::storage::{EdgeRecord, Storage};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Records read from storage per query while checking, so a check never holds
+/// more than this many of any kind in memory.
+pub const DEFAULT_CHUNK: usize = 1000;
+/// How many of each problem a report lists; the counts are always complete.
+pub const MAX_SAMPLES: usize = 50;
+
+/// What a consistency check found in one repo's graph.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ConsistencyReport {
+    pub repo_id: String,
+    /// Edges with an endpoint that no longer exists.
+    pub dangling_edges: usize,
+    /// Nodes without a single edge in or out. They are reported, not repaired:
+    /// a lone node can be legitimate, e.g. an empty file.
+    pub orphan_nodes: usize,
+    /// Node ids stored more than once.
+    pub duplicate_nodes: usize,
+    pub dangling_sample: Vec<EdgeRecord>,
+    pub orphan_sample: Vec<String>,
+    pub duplicate_sample: Vec<String>,
+    pub repaired: Option<Repaired>,
+}
+
+/// What a repair removed.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Repaired {
+    pub edges_removed: usize,
+    /// Extra copies of duplicated nodes; one of each is kept.
+    pub nodes_removed: usize,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.dangling_edges == 0 && self.duplicate_nodes == 0
+    }
+}
+
+/// Scans `repo_id`'s graph `chunk` records at a time for dangling edges,
+/// orphan nodes and duplicated node ids. With `repair`, each chunk of
+/// dangling edges is deleted and each duplicated id cut down to one node as
+/// they are found, so the counts are of what was there before the repair.
+pub async fn check(
+    storage: &dyn Storage,
+    repo_id: &str,
+    repair: bool,
+    chunk: usize,
+) -> Result<ConsistencyReport> {
+    let chunk = chunk.max(1);
+    let mut report = ConsistencyReport {
+        repo_id: repo_id.to_string(),
+        repaired: repair.then(Repaired::default),
+        ..Default::default()
+    };
+
+    let mut after: Option<EdgeRecord> = None;
+    loop {
+        let edges = storage
+            .dangling_edges(repo_id, after.as_ref(), chunk)
+            .await?;
+        report.dangling_edges += edges.len();
+        sample(&mut report.dangling_sample, edges.iter().cloned());
+        if let Some(repaired) = &mut report.repaired {
+            repaired.edges_removed += storage.delete_edges(&edges).await?;
+        }
+        if edges.len() < chunk {
+            break;
+        }
+        after = edges.last().cloned();
+    }
+
+    let mut after: Option<String> = None;
+    loop {
+        let ids = storage
+            .duplicate_nodes(repo_id, after.as_deref(), chunk)
+            .await?;
+        report.duplicate_nodes += ids.len();
+        sample(&mut report.duplicate_sample, ids.iter().cloned());
+        if let Some(repaired) = &mut report.repaired {
+            repaired.nodes_removed += storage.dedupe_nodes(repo_id, &ids).await?;
+        }
+        if ids.len() < chunk {
+            break;
+        }
+        after = ids.last().cloned();
+    }
+
+    // after the repair, so a node whose only edges dangled counts as orphaned
+    let mut after: Option<String> = None;
+    loop {
+        let ids = storage
+            .orphan_nodes(repo_id, after.as_deref(), chunk)
+            .await?;
+        report.orphan_nodes += ids.len();
+        sample(&mut report.orphan_sample, ids.iter().cloned());
+        if ids.len() < chunk {
+            break;
+        }
+        after = ids.last().cloned();
+    }
+    Ok(report)
+}
+
+fn sample<T>(samples: &mut Vec<T>, found: impl Iterator<Item = T>) {
+    let room = MAX_SAMPLES.saturating_sub(samples.len());
+    samples.extend(found.take(room));
+}
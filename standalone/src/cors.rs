@@ -0,0 +1,56 @@
+use anyhow::{bail, Context, Result};
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Parses `scheme://host[:port]` origins. Anything else is an error rather
+/// than skipped, so a typo can't quietly lock a frontend out.
+pub fn parse_origins(raw: &str) -> Result<Vec<HeaderValue>> {
+    let mut origins = Vec::new();
+    for origin in raw.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        let Some((scheme, authority)) = origin.split_once("://") else {
+            bail!("invalid CORS origin '{}': expected scheme://host", origin);
+        };
+        if scheme != "http" && scheme != "https" {
+            bail!(
+                "invalid CORS origin '{}': scheme must be http or https",
+                origin
+            );
+        }
+        let valid_authority = !authority.is_empty()
+            && authority
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'));
+        if !valid_authority {
+            bail!(
+                "invalid CORS origin '{}': only a host and optional port may follow the scheme",
+                origin
+            );
+        }
+        let value = HeaderValue::from_str(origin)
+            .with_context(|| format!("invalid CORS origin '{}'", origin))?;
+        origins.push(value);
+    }
+    if origins.is_empty() {
+        bail!("MESH_CORS_ORIGINS is set but lists no origins");
+    }
+    Ok(origins)
+}
+
+/// Exactly the listed origins, the methods the API uses and the headers
+/// clients send, the API key included, with the request id readable in
+/// responses; permissive when `origins` is `None`.
+pub fn layer(origins: Option<&[HeaderValue]>) -> CorsLayer {
+    let Some(origins) = origins else {
+        return CorsLayer::permissive();
+    };
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins.iter().cloned()))
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::CACHE_CONTROL,
+            header::AUTHORIZATION,
+            HeaderName::from_static("last-event-id"),
+        ])
+        .expose_headers([HeaderName::from_static(crate::logging::REQUEST_ID)])
+}
@@ -0,0 +1,269 @@
+use crate::analysis::TEST_FILES;
+use crate::callgraph::resolve_calls;
+use crate::storage::{EdgeRecord, NodeRecord};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// From a test to a function it calls, directly or through other calls.
+pub const TESTS: &str = "TESTS";
+
+/// How one language marks test functions.
+#[derive(Debug, Clone)]
+pub struct TestRule {
+    pub extensions: &'static [&'static str],
+    /// Regexes over the function name, e.g. Go's `^Test`.
+    pub name_patterns: &'static [&'static str],
+    /// Regexes over the function source, e.g. `#[test]`.
+    pub body_patterns: &'static [&'static str],
+    /// Only functions in test files count, for names too common to go by
+    /// alone.
+    pub test_files_only: bool,
+}
+
+pub const TEST_RULES: &[TestRule] = &[
+    TestRule {
+        extensions: &["rs"],
+        name_patterns: &[],
+        body_patterns: &[r"#\[(test|bench)\]", r"#\[[\w:]*::test\b"],
+        test_files_only: false,
+    },
+    TestRule {
+        extensions: &["py"],
+        name_patterns: &[r"^test"],
+        body_patterns: &[],
+        test_files_only: true,
+    },
+    TestRule {
+        extensions: &["go"],
+        name_patterns: &[r"^(Test|Benchmark|Fuzz)([A-Z_]|$)"],
+        body_patterns: &[],
+        test_files_only: true,
+    },
+    TestRule {
+        // `describe`, `it` and `test` blocks, and the functions around them
+        extensions: &["js", "jsx", "mjs", "cjs", "ts", "tsx"],
+        name_patterns: &[r"^test"],
+        body_patterns: &[r"\b(describe|it|test)(\.\w+)?\s*\("],
+        test_files_only: true,
+    },
+    TestRule {
+        extensions: &["java", "kt", "kts"],
+        name_patterns: &[],
+        body_patterns: &[r"@(Test|ParameterizedTest)\b"],
+        test_files_only: false,
+    },
+    TestRule {
+        extensions: &["rb"],
+        name_patterns: &[r"^test_"],
+        body_patterns: &[],
+        test_files_only: false,
+    },
+    TestRule {
+        extensions: &["swift"],
+        name_patterns: &[r"^test[A-Z_]"],
+        body_patterns: &[],
+        test_files_only: false,
+    },
+];
+
+struct CompiledRule {
+    rule: &'static TestRule,
+    name_patterns: Vec<Regex>,
+    body_patterns: Vec<Regex>,
+}
+
+fn rules() -> &'static (Vec<CompiledRule>, Regex) {
+    static RULES: OnceLock<(Vec<CompiledRule>, Regex)> = OnceLock::new();
+    RULES.get_or_init(|| {
+        let compile = |patterns: &[&str]| patterns.iter().map(|p| Regex::new(p).unwrap()).collect();
+        let rules = TEST_RULES
+            .iter()
+            .map(|rule| CompiledRule {
+                rule,
+                name_patterns: compile(rule.name_patterns),
+                body_patterns: compile(rule.body_patterns),
+            })
+            .collect();
+        (rules, Regex::new(TEST_FILES).unwrap())
+    })
+}
+
+/// Whether `function` is a test, by its language's [`TestRule`].
+pub fn is_test(function: &NodeRecord) -> bool {
+    if function.kind != "Function" {
+        return false;
+    }
+    let (rules, test_files) = rules();
+    let ext = Path::new(&function.file)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let Some(compiled) = rules.iter().find(|r| r.rule.extensions.contains(&ext)) else {
+        return false;
+    };
+    if compiled.rule.test_files_only && !test_files.is_match(&function.file) {
+        return false;
+    }
+    compiled
+        .name_patterns
+        .iter()
+        .any(|re| re.is_match(&function.name))
+        || compiled
+            .body_patterns
+            .iter()
+            .any(|re| re.is_match(&function.body))
+}
+
+/// `TESTS` edges from every test to the functions it reaches through the
+/// call graph. Only a call's most likely definitions are followed, so a
+/// common name doesn't tie a test to every function called that; other
+/// tests reached along the way aren't linked.
+pub fn test_edges(nodes: &[NodeRecord], edges: &[EdgeRecord], repo_id: &str) -> Vec<EdgeRecord> {
+    let tests: HashSet<&str> = nodes
+        .iter()
+        .filter(|n| is_test(n))
+        .map(|n| n.id.as_str())
+        .collect();
+    if tests.is_empty() {
+        return Vec::new();
+    }
+    let graph = resolve_calls(nodes, edges);
+    let mut callees: HashMap<&str, Vec<&str>> = HashMap::new();
+    for function in &graph.functions {
+        let targets = callees.entry(function.id.as_str()).or_default();
+        for call in &function.calls {
+            let local = call.candidates.iter().filter(|c| c.repo_id.is_none());
+            let best = local.clone().map(|c| c.confidence).fold(0.0, f32::max);
+            targets.extend(
+                local
+                    .filter(|c| c.confidence == best)
+                    .map(|c| c.id.as_str()),
+            );
+        }
+    }
+
+    let mut linked = BTreeSet::new();
+    for test in &tests {
+        let mut seen = HashSet::from([*test]);
+        let mut queue: VecDeque<&str> = VecDeque::from([*test]);
+        while let Some(id) = queue.pop_front() {
+            for &callee in callees.get(id).into_iter().flatten() {
+                if !seen.insert(callee) {
+                    continue;
+                }
+                if !tests.contains(callee) {
+                    linked.insert((test.to_string(), callee.to_string()));
+                }
+                queue.push_back(callee);
+            }
+        }
+    }
+    linked
+        .into_iter()
+        .map(|(source, target)| EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind: TESTS.to_string(),
+            source,
+            target,
+        })
+        .collect()
+}
+
+/// A function or test, as listed under another.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    /// 1-based line of the definition.
+    pub line: usize,
+}
+
+impl From<&NodeRecord> for Symbol {
+    fn from(node: &NodeRecord) -> Self {
+        Symbol {
+            id: node.id.clone(),
+            name: node.name.clone(),
+            file: node.file.clone(),
+            line: node.start + 1,
+        }
+    }
+}
+
+/// A definition and the tests exercising it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tested {
+    #[serde(flatten)]
+    pub symbol: Symbol,
+    /// By file and line.
+    pub tests: Vec<Symbol>,
+}
+
+/// A test and the functions it exercises.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TestCoverage {
+    #[serde(flatten)]
+    pub symbol: Symbol,
+    /// By file and line.
+    pub covers: Vec<Symbol>,
+}
+
+/// The tests reaching every function named `name`, from the stored `TESTS`
+/// edges.
+pub fn tests_for(nodes: &[NodeRecord], edges: &[EdgeRecord], name: &str) -> Vec<Tested> {
+    linked(nodes, edges, name, false)
+        .into_iter()
+        .map(|(symbol, tests)| Tested { symbol, tests })
+        .collect()
+}
+
+/// What every test named `name` exercises, from the stored `TESTS` edges.
+pub fn covered_by(nodes: &[NodeRecord], edges: &[EdgeRecord], name: &str) -> Vec<TestCoverage> {
+    linked(nodes, edges, name, true)
+        .into_iter()
+        .map(|(symbol, covers)| TestCoverage { symbol, covers })
+        .collect()
+}
+
+/// The functions named `name`, each with what `TESTS` edges link it to:
+/// from it when `outgoing`, into it otherwise.
+fn linked(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    name: &str,
+    outgoing: bool,
+) -> Vec<(Symbol, Vec<Symbol>)> {
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut links: HashMap<&str, Vec<&NodeRecord>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.kind == TESTS) {
+        let (from, to) = if outgoing {
+            (&edge.source, &edge.target)
+        } else {
+            (&edge.target, &edge.source)
+        };
+        if let Some(node) = by_id.get(to.as_str()) {
+            links.entry(from.as_str()).or_default().push(node);
+        }
+    }
+    let by_place = |a: &Symbol, b: &Symbol| (&a.file, a.line).cmp(&(&b.file, b.line));
+    let mut found: Vec<(Symbol, Vec<Symbol>)> = nodes
+        .iter()
+        .filter(|n| n.kind == "Function" && n.name == name)
+        .filter(|n| !outgoing || is_test(n))
+        .map(|n| {
+            let mut linked: Vec<Symbol> = links
+                .get(n.id.as_str())
+                .into_iter()
+                .flatten()
+                .map(|linked| Symbol::from(*linked))
+                .collect();
+            linked.sort_by(by_place);
+            (Symbol::from(n), linked)
+        })
+        .collect();
+    found.sort_by(|a, b| by_place(&a.0, &b.0));
+    found
+}
@@ -0,0 +1,275 @@
+//! The documentation of each symbol, as its language writes it: the `///`
+//! comments above a Rust item, the `/** */` block above a Java, JavaScript
+//! or TypeScript definition, the `//` and `#` lines right above a Go or
+//! Ruby one, and the docstring that opens a Python function or class. The
+//! text is stored without its comment markers, so it reads as written.
+
+use crate::captures::GRAMMARS;
+use crate::storage::NodeRecord;
+use crate::typing::definition;
+use crate::visibility::VisibilityRule;
+use anyhow::Result;
+use std::path::Path;
+use tree_sitter::{Node, Tree};
+
+/// The meta key of a symbol's documentation.
+pub const DOC: &str = "doc";
+
+/// How one language documents a definition.
+#[derive(Debug, Clone)]
+pub struct DocRule {
+    /// A name of [`GRAMMARS`].
+    pub grammar: &'static str,
+    /// Reads the documentation of a definition, given it and the source of
+    /// its file.
+    pub read: fn(Node, &str) -> Option<String>,
+}
+
+pub const DEFAULT_RULES: &[DocRule] = &[
+    DocRule {
+        grammar: "rust",
+        read: rust,
+    },
+    DocRule {
+        grammar: "python",
+        read: python,
+    },
+    DocRule {
+        grammar: "go",
+        read: go,
+    },
+    DocRule {
+        grammar: "typescript",
+        read: javadoc,
+    },
+    DocRule {
+        grammar: "tsx",
+        read: javadoc,
+    },
+    DocRule {
+        grammar: "java",
+        read: javadoc,
+    },
+    DocRule {
+        grammar: "ruby",
+        read: ruby,
+    },
+];
+
+impl DocRule {
+    /// The rule for the language `path` is written in, if it has one.
+    pub fn for_path(path: &Path) -> Option<&'static DocRule> {
+        let ext = path.extension()?.to_str()?;
+        let grammar = GRAMMARS.iter().find(|g| g.extensions.contains(&ext))?;
+        DEFAULT_RULES.iter().find(|r| r.grammar == grammar.name)
+    }
+}
+
+fn rust(node: Node, source: &str) -> Option<String> {
+    preceding(node, source, &["attribute_item"], |text| {
+        (text.starts_with("///") && !text.starts_with("////"))
+            || (text.starts_with("/**") && !text.starts_with("/***") && text != "/**/")
+    })
+}
+
+fn python(node: Node, source: &str) -> Option<String> {
+    let node = match node.kind() {
+        "decorated_definition" => node.child_by_field_name("definition")?,
+        _ => node,
+    };
+    let body = node.child_by_field_name("body")?;
+    let mut cursor = body.walk();
+    let first = body
+        .named_children(&mut cursor)
+        .find(|c| c.kind() != "comment")?;
+    let string = Some(first)
+        .filter(|f| f.kind() == "expression_statement")?
+        .named_child(0)
+        .filter(|s| s.kind() == "string")?;
+    let text = string.utf8_text(source.as_bytes()).ok()?;
+    let text = text.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    let inner = ["\"\"\"", "'''", "\"", "'"]
+        .iter()
+        .find_map(|quote| text.strip_prefix(quote)?.strip_suffix(quote))?;
+    let mut lines = inner.lines();
+    let summary = lines.next().unwrap_or_default().trim().to_string();
+    let rest: Vec<&str> = lines.collect();
+    // the lines after the first are dedented by what they share
+    let indent = rest
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut cleaned = vec![summary];
+    cleaned.extend(
+        rest.iter()
+            .map(|l| l.get(indent..).unwrap_or_default().trim_end().to_string()),
+    );
+    joined(cleaned)
+}
+
+fn go(node: Node, source: &str) -> Option<String> {
+    let node = outermost(
+        node,
+        &["type_declaration", "var_declaration", "const_declaration"],
+    );
+    preceding(node, source, &[], |text| text.starts_with("//"))
+}
+
+fn javadoc(node: Node, source: &str) -> Option<String> {
+    let node = outermost(
+        node,
+        &[
+            "variable_declarator",
+            "lexical_declaration",
+            "variable_declaration",
+            "export_statement",
+        ],
+    );
+    preceding(node, source, &["decorator"], |text| {
+        text.starts_with("/**") && text != "/**/"
+    })
+}
+
+fn ruby(node: Node, source: &str) -> Option<String> {
+    preceding(node, source, &[], |text| {
+        text.starts_with('#') && !text.starts_with("#!")
+    })
+}
+
+/// `node`, or the outermost of the `wrappers` around it, which is where the
+/// comments documenting it are written: above the `export` of a function,
+/// or the `type` block of a Go type.
+fn outermost<'t>(mut node: Node<'t>, wrappers: &[&str]) -> Node<'t> {
+    while let Some(parent) = node.parent().filter(|p| wrappers.contains(&p.kind())) {
+        node = parent;
+    }
+    node
+}
+
+/// The text of the comments `is_doc` accepts right above `node`, with
+/// nothing but the siblings `skip` names, such as attributes, in between:
+/// each on a line of its own, with no blank line before the next. A block
+/// comment is taken on its own.
+fn preceding(
+    mut node: Node,
+    source: &str,
+    skip: &[&str],
+    is_doc: impl Fn(&str) -> bool,
+) -> Option<String> {
+    // a comment ahead of the first statement of a body, as in a Ruby class,
+    // can be left outside the body
+    while let Some(parent) = node
+        .parent()
+        .filter(|p| p.start_byte() == node.start_byte() && node.prev_sibling().is_none())
+    {
+        node = parent;
+    }
+    let mut next_row = node.start_position().row;
+    let mut comments = Vec::new();
+    let mut sibling = node.prev_sibling();
+    while let Some(prev) = sibling {
+        // a line comment can take the newline after it along
+        let text = prev.utf8_text(source.as_bytes()).ok()?.trim_end();
+        let start_row = prev.start_position().row;
+        if start_row + text.matches('\n').count() + 1 < next_row {
+            break;
+        }
+        next_row = start_row;
+        sibling = prev.prev_sibling();
+        if skip.contains(&prev.kind()) {
+            continue;
+        }
+        let line_start = source[..prev.start_byte()].rfind('\n').map_or(0, |i| i + 1);
+        let own_line = source[line_start..prev.start_byte()].trim().is_empty();
+        if !prev.kind().ends_with("comment") || !own_line || !is_doc(text) {
+            break;
+        }
+        comments.push(text);
+        if text.starts_with("/*") {
+            break;
+        }
+    }
+    let lines = comments.into_iter().rev().flat_map(uncomment).collect();
+    joined(lines)
+}
+
+/// The lines of `comment` without its markers: the `/**`, `*/` and leading
+/// `*` of a block, the `///`, `//` or `#` of a line, and the space after
+/// them.
+fn uncomment(comment: &str) -> Vec<String> {
+    let unspaced = |line: &str| {
+        line.strip_prefix(' ')
+            .unwrap_or(line)
+            .trim_end()
+            .to_string()
+    };
+    if let Some(block) = comment.strip_prefix("/**") {
+        let block = block.strip_suffix("*/").unwrap_or(block);
+        return block
+            .lines()
+            .map(|line| {
+                let line = line.trim_start();
+                unspaced(line.strip_prefix('*').unwrap_or(line))
+            })
+            .collect();
+    }
+    let line = ["///", "//", "#"]
+        .iter()
+        .find_map(|marker| comment.strip_prefix(marker))
+        .unwrap_or(comment);
+    vec![unspaced(line)]
+}
+
+/// `lines` as one text, without the blank lines at either end; `None` when
+/// nothing's left.
+fn joined(lines: Vec<String>) -> Option<String> {
+    let text = lines.join("\n");
+    let text = text.trim_matches('\n').trim_end();
+    (!text.trim().is_empty()).then(|| text.to_string())
+}
+
+/// Stores the documentation of the symbols of `file` under [`DOC`] in their
+/// meta, read from `tree`, the syntax tree of `source`. Definitions are
+/// found as [`crate::visibility::annotate`] finds them, through the nodes'
+/// spans; symbols without a span or without documentation, and every node
+/// of a language without a [`DocRule`] or of a file without a tree, are left
+/// as they are.
+pub fn annotate<'a>(
+    file: &str,
+    source: &str,
+    tree: Option<&Tree>,
+    nodes: impl IntoIterator<Item = &'a mut NodeRecord>,
+) -> Result<()> {
+    let path = Path::new(file);
+    let (Some(rule), Some(tree)) = (DocRule::for_path(path), tree) else {
+        return Ok(());
+    };
+    let Some(visibility) = VisibilityRule::for_path(path) else {
+        return Ok(());
+    };
+    for node in nodes {
+        let Some(span) = node.span else {
+            continue;
+        };
+        if node.kind == "File" {
+            continue;
+        }
+        let Some(found) = definition(tree, &span, |k| visibility.definitions.contains(&k)) else {
+            continue;
+        };
+        // a symbol inside a definition, e.g. a function's local, isn't
+        // documented by it
+        let Some(before) = source.get(..span.start_byte) else {
+            continue;
+        };
+        if found.start_position().row != before.matches('\n').count() {
+            continue;
+        }
+        if let Some(doc) = (rule.read)(found, source) {
+            node.meta.insert(DOC.to_string(), doc);
+        }
+    }
+    Ok(())
+}
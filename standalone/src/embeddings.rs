@@ -0,0 +1,129 @@
+use crate::storage::NodeRecord;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use tracing::warn;
+
+/// The meta key an embedding is stored under, as a JSON array of numbers.
+pub const EMBEDDING: &str = "embedding";
+/// How many symbols are sent to the embedder at once.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+pub const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+
+/// Turns the source of a symbol into a vector for semantic search.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed(&self, node: &NodeRecord) -> Result<Vec<f32>>;
+}
+
+/// Whether `node` is embedded: functions and files with a body.
+pub fn embeddable(node: &NodeRecord) -> bool {
+    (node.kind == "Function" || node.kind == "File") && !node.body.trim().is_empty()
+}
+
+/// What [`embed_nodes`] did.
+#[derive(Debug, Default)]
+pub struct Embedded {
+    pub embedded: usize,
+    /// The ids of the nodes that were kept without an embedding.
+    pub failed: Vec<String>,
+    /// Why the first of them failed.
+    pub first_error: Option<String>,
+}
+
+/// Asks `embedder` for the embedding of every [`embeddable`] node, up to
+/// `concurrency` at once, and stores each under [`EMBEDDING`]. A symbol it
+/// fails on is left as it is, so an embedder that is down only costs the
+/// search, not the graph.
+pub async fn embed_nodes(
+    embedder: &dyn Embedder,
+    nodes: &mut [NodeRecord],
+    concurrency: usize,
+) -> Embedded {
+    let results: Vec<(usize, Result<Vec<f32>>)> = stream::iter(
+        nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| embeddable(node)),
+    )
+    .map(|(i, node)| async move { (i, embedder.embed(node).await) })
+    .buffer_unordered(concurrency.max(1))
+    .collect()
+    .await;
+    let mut report = Embedded::default();
+    for (i, result) in results {
+        let node = &mut nodes[i];
+        match result.and_then(|vector| Ok(serde_json::to_string(&vector)?)) {
+            Ok(vector) => {
+                node.meta.insert(EMBEDDING.to_string(), vector);
+                report.embedded += 1;
+            }
+            Err(e) => {
+                warn!("Failed to embed {}: {:#}", node.id, e);
+                report.first_error.get_or_insert_with(|| format!("{:#}", e));
+                report.failed.push(node.id.clone());
+            }
+        }
+    }
+    report
+}
+
+/// An [`Embedder`] posting each symbol's source to an HTTP endpoint, as
+/// `{"input": ..., "model": ...}`. The vector is read from `embedding` or,
+/// as OpenAI-style APIs answer, `data[0].embedding`.
+#[cfg(feature = "embeddings")]
+pub struct HttpEmbedder {
+    client: reqwest::Client,
+    outbound: std::sync::Arc<crate::outbound::Outbound>,
+    url: String,
+    model: Option<String>,
+    token: Option<String>,
+}
+
+#[cfg(feature = "embeddings")]
+impl HttpEmbedder {
+    /// Each request is made through `outbound`, which times it out and
+    /// retries it.
+    pub fn new(
+        url: &str,
+        model: Option<&str>,
+        token: Option<&str>,
+        outbound: std::sync::Arc<crate::outbound::Outbound>,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder().build()?;
+        Ok(HttpEmbedder {
+            client,
+            outbound,
+            url: url.to_string(),
+            model: model.map(str::to_string),
+            token: token.map(str::to_string),
+        })
+    }
+}
+
+#[cfg(feature = "embeddings")]
+#[async_trait]
+impl Embedder for HttpEmbedder {
+    async fn embed(&self, node: &NodeRecord) -> Result<Vec<f32>> {
+        let mut body = serde_json::json!({ "input": node.body });
+        if let Some(model) = &self.model {
+            body["model"] = model.clone().into();
+        }
+        let body = &body;
+        let response: serde_json::Value = self
+            .outbound
+            .call(|| async move {
+                let mut request = self.client.post(&self.url).json(body);
+                if let Some(token) = &self.token {
+                    request = request.bearer_auth(token);
+                }
+                Ok(request.send().await?.error_for_status()?.json().await?)
+            })
+            .await?;
+        let vector = response
+            .get("embedding")
+            .or_else(|| response.pointer("/data/0/embedding"))
+            .ok_or_else(|| anyhow::anyhow!("the response has no embedding"))?;
+        Ok(serde_json::from_value(vector.clone())?)
+    }
+}
@@ -0,0 +1,138 @@
+use encoding_rs::{UTF_16BE, UTF_16LE, WINDOWS_1252};
+use std::fmt;
+use std::path::Path;
+
+pub(crate) const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+/// Bytes Windows-1252 leaves unassigned; text holding any isn't in it.
+const UNASSIGNED_1252: &[u8] = &[0x81, 0x8D, 0x8F, 0x90, 0x9D];
+
+/// What a source file was written in before it was read as UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// UTF-8 led by a byte order mark, which is dropped.
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// Latin-1 and Windows' superset of it.
+    Windows1252,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 with a BOM",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Windows1252 => "Windows-1252",
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Source text as UTF-8, without a byte order mark. Every byte offset the
+/// graph stores is into this text, never the bytes on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    pub text: String,
+    pub encoding: Encoding,
+}
+
+/// `bytes` as UTF-8 text. A BOM decides the encoding when there is one;
+/// otherwise the bytes are taken as UTF-8 if they are valid, as UTF-16 if
+/// every other byte is NUL, as mostly-ASCII text would be, or as
+/// Windows-1252 if they hold no control characters and nothing it leaves
+/// unassigned. Anything else fails, naming why.
+pub fn decode(bytes: &[u8]) -> Result<Decoded, String> {
+    if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+        let text = std::str::from_utf8(rest)
+            .map_err(|_| "it starts with a UTF-8 BOM but isn't valid UTF-8".to_string())?;
+        return Ok(Decoded {
+            text: text.to_string(),
+            encoding: Encoding::Utf8Bom,
+        });
+    }
+    if let Some((encoding, rest)) = utf16(bytes) {
+        let codec = match encoding {
+            Encoding::Utf16Be => UTF_16BE,
+            _ => UTF_16LE,
+        };
+        let text = codec
+            .decode_without_bom_handling_and_without_replacement(rest)
+            .ok_or_else(|| format!("it isn't valid {}", encoding))?;
+        return Ok(Decoded {
+            text: text.into_owned(),
+            encoding,
+        });
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(Decoded {
+            text: text.to_string(),
+            encoding: Encoding::Utf8,
+        });
+    }
+    let control = |b: &u8| (*b < 0x20 && !b"\t\n\r\x0c".contains(b)) || *b == 0x7F;
+    if !bytes
+        .iter()
+        .any(|b| control(b) || UNASSIGNED_1252.contains(b))
+    {
+        let (text, _) = WINDOWS_1252.decode_without_bom_handling(bytes);
+        return Ok(Decoded {
+            text: text.into_owned(),
+            encoding: Encoding::Windows1252,
+        });
+    }
+    Err("its encoding could not be determined".to_string())
+}
+
+/// Whether `head`, the start of a file, is UTF-16 by [`decode`]'s rules,
+/// which tells it from a binary file despite its NUL bytes.
+pub fn is_utf16(head: &[u8]) -> bool {
+    utf16(head).is_some()
+}
+
+/// The UTF-16 byte order of `bytes` and the text after any BOM.
+fn utf16(bytes: &[u8]) -> Option<(Encoding, &[u8])> {
+    if let Some(rest) = bytes.strip_prefix(UTF16LE_BOM) {
+        return Some((Encoding::Utf16Le, rest));
+    }
+    if let Some(rest) = bytes.strip_prefix(UTF16BE_BOM) {
+        return Some((Encoding::Utf16Be, rest));
+    }
+    if bytes.len() < 2 || bytes.len() % 2 != 0 {
+        return None;
+    }
+    let nuls = |offset: usize| {
+        bytes
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    let (even, odd) = (nuls(0), nuls(1));
+    let units = bytes.len() / 2;
+    // nine in ten of the high bytes NUL, none of the low ones
+    if even == 0 && odd * 10 >= units * 9 {
+        Some((Encoding::Utf16Le, bytes))
+    } else if odd == 0 && even * 10 >= units * 9 {
+        Some((Encoding::Utf16Be, bytes))
+    } else {
+        None
+    }
+}
+
+/// Reads and [`decode`]s the file at `path`; text that can't be decoded is
+/// an [`InvalidData`](std::io::ErrorKind::InvalidData) error.
+pub fn read(path: &Path) -> std::io::Result<Decoded> {
+    let bytes = std::fs::read(path)?;
+    decode(&bytes).map_err(|reason| std::io::Error::new(std::io::ErrorKind::InvalidData, reason))
+}
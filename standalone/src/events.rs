@@ -0,0 +1,861 @@
+use crate::clone::Credentials;
+use crate::types::MeshError;
+use crate::AppState;
+use ast::repo::StatusUpdate;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use axum::Json;
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+/// Capacity of the status broadcast channel.
+pub const DEFAULT_EVENT_BUFFER: usize = 10000;
+/// How many recent events are kept for reconnecting clients.
+pub const DEFAULT_REPLAY_BUFFER: usize = 256;
+/// How many events of the last ingest of each repo are kept for
+/// `/repos/log`; past that the oldest are dropped.
+pub const INGEST_LOG_EVENTS: usize = 1000;
+
+/// How often an idle `/events` stream sends a comment, so proxies don't
+/// close it.
+pub const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(15);
+pub const DEFAULT_KEEPALIVE_TEXT: &str = "ping";
+
+/// How many `/events` streams may be open at once.
+pub const DEFAULT_MAX_SSE_CLIENTS: usize = 1024;
+/// What a client turned away from a full `/events` is told to wait.
+pub const SSE_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// The comment an idle `/events` stream sends, and how often.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeepAliveConfig {
+    pub interval: Duration,
+    pub text: String,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        KeepAliveConfig {
+            interval: DEFAULT_KEEPALIVE,
+            text: DEFAULT_KEEPALIVE_TEXT.to_string(),
+        }
+    }
+}
+
+impl KeepAliveConfig {
+    pub fn keep_alive(&self) -> KeepAlive {
+        KeepAlive::new()
+            .interval(self.interval)
+            .text(self.text.as_str())
+    }
+}
+
+/// Holds back the progress updates of an `/events` stream so each repo's
+/// go out at most once per interval, the latest of them winning; progress
+/// carries the running count, so the ones dropped say nothing the one sent
+/// doesn't. Anything more severe goes out at once and supersedes any
+/// progress of its repo still held back.
+#[derive(Debug)]
+pub struct Coalescer {
+    interval: Duration,
+    pending: BTreeMap<Option<String>, StatusEvent>,
+    last_sent: HashMap<Option<String>, Instant>,
+}
+
+impl Coalescer {
+    pub fn new(interval: Duration) -> Self {
+        Coalescer {
+            interval,
+            pending: BTreeMap::new(),
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// What to send of `event` now: itself, or nothing when it's progress
+    /// that has to wait for [`Coalescer::due`].
+    pub fn push(&mut self, event: StatusEvent, now: Instant) -> Option<StatusEvent> {
+        let repo = event.repo_id.clone();
+        if event.severity() > EventSeverity::Progress {
+            self.pending.remove(&repo);
+            return Some(event);
+        }
+        match self.last_sent.get(&repo) {
+            Some(sent) if now.duration_since(*sent) < self.interval => {
+                self.pending.insert(repo, event);
+                None
+            }
+            _ => {
+                self.last_sent.insert(repo, now);
+                Some(event)
+            }
+        }
+    }
+
+    /// When the first update held back may go out.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending
+            .keys()
+            .filter_map(|repo| self.last_sent.get(repo))
+            .map(|sent| *sent + self.interval)
+            .min()
+    }
+
+    /// The updates held back whose interval is up by `now`, oldest first.
+    pub fn due(&mut self, now: Instant) -> Vec<StatusEvent> {
+        let ready: Vec<Option<String>> = self
+            .pending
+            .keys()
+            .filter(|repo| {
+                self.last_sent
+                    .get(*repo)
+                    .is_none_or(|sent| now.duration_since(*sent) >= self.interval)
+            })
+            .cloned()
+            .collect();
+        let mut due: Vec<StatusEvent> = ready
+            .into_iter()
+            .filter_map(|repo| {
+                self.last_sent.insert(repo.clone(), now);
+                self.pending.remove(&repo)
+            })
+            .collect();
+        due.sort_by_key(|e| e.id);
+        due
+    }
+}
+
+/// How many event ids are claimed in the id file at a time, so it's written
+/// once every that many events rather than on each.
+pub const ID_BLOCK: u64 = 1000;
+
+/// Where event ids are persisted across restarts unless configured
+/// otherwise. Without an id file they start over at 1 with every restart.
+pub const DEFAULT_ID_FILE: &str = "mesh-events.id";
+
+/// What `/events` streams: an update from `ast` or the handlers, plus how far
+/// the current ingest has got. `total` is `None` when the amount of work isn't known.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StatusEvent {
+    /// Sequence number assigned by `EventSender::send`, sent as the SSE event id.
+    #[serde(skip)]
+    pub id: u64,
+    #[serde(flatten)]
+    pub update: StatusUpdate,
+    /// The repo the work is for, so clients can follow one ingest among several.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<usize>,
+    /// What the write this event reports on added, written out by `as_json_str`.
+    #[serde(skip)]
+    pub added: Option<Added>,
+    /// Only on the `complete` event that ends an ingest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<IngestSummary>,
+}
+
+/// How much an update matters, lowest first, for `/events?min_severity=`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSeverity {
+    /// Per-file updates as an ingest goes: `stored`, `cached` and whatever
+    /// `ast` reports, along with any status not listed below.
+    #[default]
+    Progress,
+    /// Milestones, such as `cloned` and the `complete` that ends an ingest.
+    Info,
+    /// Something was skipped or retried, but the work goes on.
+    Warning,
+    /// The work, or some of it, failed.
+    Error,
+}
+
+/// What a write handed to storage: per file for `stored` events, per
+/// repo for the `complete` event that ends an ingest.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Added {
+    pub nodes: usize,
+    pub edges: usize,
+    /// Nodes per kind; only filled in for the repo-level summary.
+    pub by_kind: BTreeMap<String, usize>,
+}
+
+/// How an ingest went, sent once at its end so automation can wait for it
+/// rather than for the events to go quiet.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct IngestSummary {
+    /// Files a parser produced nodes for.
+    pub files_parsed: usize,
+    /// Files counted up front that nothing was produced for.
+    pub files_skipped: usize,
+    /// Files whose writes failed and were rolled back.
+    pub files_failed: usize,
+    /// The size of the repo's graph once the ingest is done.
+    pub nodes: usize,
+    pub edges: usize,
+    pub duration_ms: u64,
+    /// The commit the checkout was at; `None` outside git.
+    pub commit: Option<String>,
+}
+
+impl Added {
+    /// Adds `other` in, kinds included.
+    pub fn extend(&mut self, other: &Added) {
+        self.nodes += other.nodes;
+        self.edges += other.edges;
+        for (kind, count) in &other.by_kind {
+            *self.by_kind.entry(kind.clone()).or_insert(0) += count;
+        }
+    }
+}
+
+impl StatusEvent {
+    pub fn new(status: &str, message: String) -> Self {
+        StatusUpdate {
+            status: status.to_string(),
+            message,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    pub fn for_repo(mut self, repo_id: &str) -> Self {
+        self.repo_id = Some(repo_id.to_string());
+        self
+    }
+
+    pub fn severity(&self) -> EventSeverity {
+        match self.update.status.as_str() {
+            "error" | "aborted" | "graph_too_large" => EventSeverity::Error,
+            status if status.ends_with("_failed") => EventSeverity::Error,
+            "warning" | "diagnostic" | "retrying" | "cancelled" | "grammar_changed" => {
+                EventSeverity::Warning
+            }
+            "complete" | "cloned" | "repaired" | "swept" | "file_deleted" | "transcoded"
+            | "dry_run" | "bulk_ingested" => EventSeverity::Info,
+            _ => EventSeverity::Progress,
+        }
+    }
+
+    pub fn percent(&self) -> Option<usize> {
+        let total = self.total.filter(|t| *t > 0)?;
+        Some(self.completed.unwrap_or(0).min(total) * 100 / total)
+    }
+
+    pub fn with_added(mut self, added: Added) -> Self {
+        self.added = Some(added);
+        self
+    }
+
+    pub fn with_summary(mut self, summary: IngestSummary) -> Self {
+        self.summary = Some(summary);
+        self
+    }
+
+    pub fn as_json_str(&self) -> String {
+        self.to_json().to_string()
+    }
+
+    /// The event as `/repos/log` lists it: what `/events` sends, with the id
+    /// it was sent under.
+    pub fn to_log_json(&self) -> serde_json::Value {
+        let mut value = self.to_json();
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert("id".to_string(), self.id.into());
+        }
+        value
+    }
+
+    /// What `/events` sends as the event's data.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(obj) = value.as_object_mut() {
+            if let Some(percent) = self.percent() {
+                obj.insert("percent".to_string(), percent.into());
+            }
+            if let Some(added) = &self.added {
+                obj.insert("nodes_added".to_string(), added.nodes.into());
+                obj.insert("edges_added".to_string(), added.edges.into());
+                if !added.by_kind.is_empty() {
+                    let by_kind = serde_json::to_value(&added.by_kind).unwrap_or_default();
+                    obj.insert("nodes_by_kind".to_string(), by_kind);
+                }
+            }
+        }
+        value
+    }
+}
+
+impl From<StatusUpdate> for StatusEvent {
+    fn from(update: StatusUpdate) -> Self {
+        StatusEvent {
+            id: 0,
+            update,
+            repo_id: None,
+            total: None,
+            completed: None,
+            added: None,
+            summary: None,
+        }
+    }
+}
+
+/// The status broadcast channel plus a bounded log of the most recent events,
+/// so a client reconnecting with `Last-Event-ID` can catch up on what it missed.
+#[derive(Clone)]
+pub struct EventSender {
+    inner: Arc<EventLog>,
+}
+
+struct EventLog {
+    tx: broadcast::Sender<StatusEvent>,
+    next_id: AtomicU64,
+    recent: Mutex<VecDeque<StatusEvent>>,
+    replay: usize,
+    ids: Option<IdFile>,
+    /// The events of the last ingest of each repo, from when its
+    /// [`Progress`] was made.
+    ingests: Mutex<HashMap<String, VecDeque<StatusEvent>>>,
+}
+
+/// The id file holds the first id no run has handed out yet. Ids up to it
+/// are claimed a block at a time, so however a run ends the next one starts
+/// past every id it may have sent.
+struct IdFile {
+    path: PathBuf,
+    claimed: AtomicU64,
+}
+
+impl IdFile {
+    fn claim(&self, up_to: u64) -> std::io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, up_to.to_string())?;
+        std::fs::rename(&tmp, &self.path)?;
+        self.claimed.store(up_to, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Where a client reconnecting with `Last-Event-ID` picks up.
+#[derive(Debug)]
+pub enum Resume {
+    /// The buffered events it missed, oldest first; none when it missed nothing.
+    Replay(Vec<StatusEvent>),
+    /// Some of what it missed is gone, aged out of the buffer or sent before a
+    /// restart, so it has to reload whatever it built from the events and
+    /// carry on from `latest`, the last id sent.
+    Reset { latest: u64 },
+}
+
+impl EventSender {
+    pub fn new(capacity: usize, replay: usize) -> Self {
+        Self::with_ids(capacity, replay, 1, None)
+    }
+
+    /// Like `new`, but ids continue from where the last run with the same
+    /// `path` left off. Fails if the file can't be read back or written.
+    pub fn persisted(capacity: usize, replay: usize, path: &Path) -> anyhow::Result<Self> {
+        let first = match std::fs::read_to_string(path) {
+            Ok(text) => text
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| anyhow::anyhow!("invalid event id file {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 1,
+            Err(e) => anyhow::bail!("failed to read event id file {}: {}", path.display(), e),
+        };
+        let ids = IdFile {
+            path: path.to_path_buf(),
+            claimed: AtomicU64::new(first),
+        };
+        ids.claim(first + ID_BLOCK).map_err(|e| {
+            anyhow::anyhow!("failed to write event id file {}: {}", path.display(), e)
+        })?;
+        Ok(Self::with_ids(capacity, replay, first, Some(ids)))
+    }
+
+    fn with_ids(capacity: usize, replay: usize, first: u64, ids: Option<IdFile>) -> Self {
+        let (tx, _rx) = broadcast::channel(capacity);
+        EventSender {
+            inner: Arc::new(EventLog {
+                tx,
+                next_id: AtomicU64::new(first),
+                recent: Mutex::new(VecDeque::with_capacity(replay)),
+                replay,
+                ids,
+                ingests: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Stamps the next id on `event`, records it for replay and broadcasts it.
+    /// Ids start at 1, or where the id file says, and only ever increase.
+    /// Having no subscribers is not an error: the event is only recorded.
+    pub fn send(&self, mut event: StatusEvent) -> u64 {
+        // held across the broadcast so receivers see ids in order
+        let mut recent = self.inner.recent.lock().unwrap();
+        event.id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let id = event.id;
+        if let Some(ids) = &self.inner.ids {
+            if id >= ids.claimed.load(Ordering::Relaxed) {
+                // the id is still used: an unwritable file only risks reused
+                // ids after the next restart
+                if let Err(e) = ids.claim(id + ID_BLOCK) {
+                    warn!(
+                        "failed to write event id file {}: {}",
+                        ids.path.display(),
+                        e
+                    );
+                }
+            }
+        }
+        if self.inner.replay > 0 {
+            if recent.len() == self.inner.replay {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+        if let Some(repo_id) = &event.repo_id {
+            let mut ingests = self.inner.ingests.lock().unwrap();
+            if let Some(log) = ingests.get_mut(repo_id) {
+                if log.len() == INGEST_LOG_EVENTS {
+                    log.pop_front();
+                }
+                log.push_back(event.clone());
+            }
+        }
+        // a broadcast with no receivers fails, and there's no one to tell
+        if self.inner.tx.receiver_count() > 0 {
+            let _ = self.inner.tx.send(event);
+        }
+        id
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StatusEvent> {
+        self.inner.tx.subscribe()
+    }
+
+    /// Buffered events sent after `last_id`, oldest first. Events that have
+    /// already aged out of the buffer are gone.
+    pub fn since(&self, last_id: u64) -> Vec<StatusEvent> {
+        let recent = self.inner.recent.lock().unwrap();
+        recent.iter().filter(|e| e.id > last_id).cloned().collect()
+    }
+
+    /// Starts the log of a new ingest of `repo_id`, in place of the last one.
+    pub fn start_log(&self, repo_id: &str) {
+        let mut ingests = self.inner.ingests.lock().unwrap();
+        ingests.insert(repo_id.to_string(), VecDeque::new());
+    }
+
+    /// The events of the last ingest of `repo_id` this run, oldest first, or
+    /// `None` when it ran none.
+    pub fn ingest_log(&self, repo_id: &str) -> Option<Vec<StatusEvent>> {
+        let ingests = self.inner.ingests.lock().unwrap();
+        ingests
+            .get(repo_id)
+            .map(|log| log.iter().cloned().collect())
+    }
+
+    /// What a client that last saw `last_id` gets: a replay when the buffer
+    /// still holds everything sent since, a reset otherwise. An id this run
+    /// hasn't reached yet, as when ids weren't persisted across a restart,
+    /// is a reset too.
+    pub fn resume(&self, last_id: u64) -> Resume {
+        let recent = self.inner.recent.lock().unwrap();
+        let next = self.inner.next_id.load(Ordering::Relaxed);
+        let oldest = recent.front().map_or(next, |e| e.id);
+        if last_id >= next || last_id + 1 < oldest {
+            return Resume::Reset { latest: next - 1 };
+        }
+        Resume::Replay(recent.iter().filter(|e| e.id > last_id).cloned().collect())
+    }
+}
+
+/// Tracks files completed during one ingest and stamps the count on every
+/// update it sends, so `completed` never goes backwards on the wire.
+pub struct Progress {
+    tx: EventSender,
+    repo_id: Option<String>,
+    total: Option<usize>,
+    completed: Mutex<usize>,
+    /// Set once the work is finished, aborted or cancelled; late updates from
+    /// `ast` are dropped.
+    stopped: AtomicBool,
+}
+
+impl Progress {
+    /// Work for a repo starts a new [`EventSender::ingest_log`] for it.
+    pub fn new(tx: EventSender, repo_id: Option<&str>, total: Option<usize>) -> Arc<Self> {
+        if let Some(repo_id) = repo_id {
+            tx.start_log(repo_id);
+        }
+        Arc::new(Progress {
+            tx,
+            repo_id: repo_id.map(str::to_string),
+            total,
+            completed: Mutex::new(0),
+            stopped: AtomicBool::new(false),
+        })
+    }
+
+    pub fn total(&self) -> Option<usize> {
+        self.total
+    }
+
+    /// Marks `files` more files as done, capped at `total`. With a known total
+    /// an update is only sent when the percentage moves, to spare the channel.
+    pub fn advance(&self, files: usize, status: &str, message: String) {
+        let mut completed = self.completed.lock().unwrap();
+        let before = *completed;
+        *completed += files;
+        if let Some(total) = self.total.filter(|t| *t > 0) {
+            *completed = (*completed).min(total);
+            if before * 100 / total == *completed * 100 / total {
+                return;
+            }
+        }
+        self.send_locked(*completed, StatusEvent::new(status, message));
+    }
+
+    /// Sent instead of `finish` when shutdown interrupts the work.
+    pub fn abort(&self, message: String) {
+        let completed = self.completed.lock().unwrap();
+        self.stopped.store(true, Ordering::Relaxed);
+        self.send_locked(*completed, StatusEvent::new("aborted", message));
+    }
+
+    /// Sent instead of `finish` when the ingest is cancelled through `/cancel`.
+    pub fn cancel(&self, message: String) {
+        let completed = self.completed.lock().unwrap();
+        self.stopped.store(true, Ordering::Relaxed);
+        self.send_locked(*completed, StatusEvent::new("cancelled", message));
+    }
+
+    /// Sent instead of `finish` when the repo's graph outgrows its limit.
+    pub fn too_large(&self, message: String) {
+        let completed = self.completed.lock().unwrap();
+        self.stopped.store(true, Ordering::Relaxed);
+        self.send_locked(*completed, StatusEvent::new("graph_too_large", message));
+    }
+
+    pub fn finish(&self, message: String) {
+        self.finish_event(StatusEvent::new("complete", message));
+    }
+
+    /// `finish`, with the totals of what the ingest wrote and how it went.
+    pub fn finish_ingest(&self, message: String, added: Added, summary: IngestSummary) {
+        let event = StatusEvent::new("complete", message)
+            .with_added(added)
+            .with_summary(summary);
+        self.finish_event(event);
+    }
+
+    /// Only the first end of the work is sent, and nothing from `ast` after it.
+    fn finish_event(&self, event: StatusEvent) {
+        let mut completed = self.completed.lock().unwrap();
+        if self.stopped.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(total) = self.total {
+            *completed = (*completed).max(total);
+        }
+        self.send_locked(*completed, event);
+    }
+
+    /// Reports what was written for `file`. Unlike `advance` it is sent for
+    /// every file, so the counts of all of them add up to the repo's.
+    pub fn stored(&self, file: &str, added: Added) {
+        let completed = self.completed.lock().unwrap();
+        let event = StatusEvent::new("stored", format!("Stored {}", file)).with_added(added);
+        self.send_locked(*completed, event);
+    }
+
+    pub fn send(&self, update: StatusUpdate) {
+        let completed = self.completed.lock().unwrap();
+        if self.stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        self.send_locked(*completed, update.into());
+    }
+
+    fn send_locked(&self, completed: usize, mut event: StatusEvent) {
+        event.repo_id = self.repo_id.clone();
+        event.total = self.total;
+        event.completed = Some(completed);
+        self.tx.send(event);
+    }
+
+    /// A sender to hand to `ast`; whatever it reports is re-sent with progress attached,
+    /// and with `credentials` and those in URLs redacted, as `ast` may quote its remote.
+    pub fn forwarder(
+        self: &Arc<Self>,
+        capacity: usize,
+        credentials: &Credentials,
+    ) -> broadcast::Sender<StatusUpdate> {
+        let (tx, mut rx) = broadcast::channel::<StatusUpdate>(capacity);
+        let progress = self.clone();
+        let credentials = credentials.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(mut update) => {
+                        update.message = credentials.redact(&update.message);
+                        progress.send(update)
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("status forwarder lagged, skipped {} messages", skipped)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        tx
+    }
+}
+
+/// Counters shared by every `/events` connection.
+#[derive(Default)]
+pub struct EventStats {
+    subscribers: AtomicUsize,
+    lagged: AtomicU64,
+    high_water: AtomicUsize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EventStatsResponse {
+    pub capacity: usize,
+    pub subscribers: usize,
+    /// Messages dropped across all connections because a receiver fell behind.
+    pub lagged: u64,
+    /// Deepest backlog any single connection has had waiting.
+    pub high_water: usize,
+}
+
+impl EventStats {
+    pub fn record_lag(&self, skipped: u64) {
+        self.lagged.fetch_add(skipped, Ordering::Relaxed);
+    }
+    pub fn record_backlog(&self, pending: usize) {
+        self.high_water.fetch_max(pending, Ordering::Relaxed);
+    }
+    pub fn snapshot(&self, capacity: usize) -> EventStatsResponse {
+        EventStatsResponse {
+            capacity,
+            subscribers: self.subscribers.load(Ordering::Relaxed),
+            lagged: self.lagged.load(Ordering::Relaxed),
+            high_water: self.high_water.load(Ordering::Relaxed),
+        }
+    }
+    pub(crate) fn subscribe(self: &Arc<Self>) -> Subscription {
+        self.subscribers.fetch_add(1, Ordering::Relaxed);
+        Subscription(self.clone())
+    }
+}
+
+/// Counts a live SSE or WebSocket connection until it is dropped.
+pub(crate) struct Subscription(Arc<EventStats>);
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.0.subscribers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub async fn stats(State(app_state): State<Arc<AppState>>) -> Json<EventStatsResponse> {
+    Json(app_state.event_stats.snapshot(app_state.event_capacity))
+}
+
+/// The query of `/events`.
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsParams {
+    /// Names each event after its update's `status`, e.g. `progress` or
+    /// `complete`, so an `EventSource` can listen for the kinds it wants.
+    /// Off by default, as `onmessage` only sees unnamed events.
+    #[serde(default)]
+    pub named: bool,
+    /// Leaves out updates less severe than this, replayed ones included;
+    /// `progress`, the default, sends them all.
+    #[serde(default)]
+    pub min_severity: EventSeverity,
+}
+
+fn sse_event(msg: &StatusEvent, named: bool) -> Event {
+    let event = Event::default()
+        .data(msg.as_json_str())
+        .id(msg.id.to_string());
+    if named {
+        event.event(&msg.update.status)
+    } else {
+        event
+    }
+}
+
+/// Tells a reconnecting client the events it missed can't be replayed. Its
+/// id is the last one sent, so reconnecting again doesn't repeat it.
+fn reset_event(last_id: u64, latest: u64) -> Event {
+    let data = serde_json::json!({
+        "status": "reset",
+        "message": format!(
+            "Events after {} are no longer available; reload and resubscribe",
+            last_id
+        ),
+    });
+    Event::default()
+        .event("reset")
+        .data(data.to_string())
+        .id(latest.to_string())
+}
+
+/// Streams status events. A client reconnecting with `Last-Event-ID` first
+/// gets the buffered events it missed, then the live stream. When some of
+/// them are gone, e.g. after a restart, it gets a `reset` event instead.
+/// See [`EventsParams`] for `?named=true` and `?min_severity=`. With
+/// [`AppState::sse_coalesce`] set, live progress goes through a
+/// [`Coalescer`]; replayed events are sent as they were. Past
+/// [`AppState::sse_slots`] streams open, it's refused with 503.
+pub async fn sse_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<EventsParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, MeshError> {
+    // held by the stream, so it's given back however the client goes
+    let slot = app_state.sse_slots.as_ref().map(sse_slot).transpose()?;
+    // subscribe before reading the log so nothing sent in between is lost;
+    // the live stream then skips whatever the replay already covered
+    let rx = app_state.tx.subscribe();
+    let last_id = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let (reset, replay) = match last_id.map(|id| app_state.tx.resume(id)) {
+        Some(Resume::Reset { latest }) => (Some(latest), Vec::new()),
+        Some(Resume::Replay(events)) => (None, events),
+        None => (None, Vec::new()),
+    };
+    // past everything replayed, whether or not the filter sends it
+    let seen = replay
+        .last()
+        .map(|e| e.id)
+        .or(reset)
+        .or(last_id)
+        .unwrap_or(0);
+    let reset = stream::iter(
+        reset.map(|latest| Ok::<Event, Infallible>(reset_event(last_id.unwrap_or(0), latest))),
+    );
+    let min_severity = params.min_severity;
+    let replay: VecDeque<StatusEvent> = replay
+        .into_iter()
+        .filter(|msg| msg.severity() >= min_severity)
+        .collect();
+    let subscription = app_state.event_stats.subscribe();
+    let shutdown = app_state.shutdown.clone();
+    let named = params.named;
+    let coalescer = app_state.sse_coalesce.map(Coalescer::new);
+
+    // ending the stream on shutdown lets graceful shutdown complete instead of
+    // waiting on connections that never close
+    let initial = (rx, (subscription, slot), replay, (seen, coalescer));
+    let stream = stream::unfold(
+        initial,
+        move |(mut rx, (sub, slot), mut replay, (seen, mut coalescer))| {
+            let shutdown = shutdown.clone();
+            async move {
+                // held-back progress that fell due is queued here too
+                if let Some(msg) = replay.pop_front() {
+                    let event = sse_event(&msg, named);
+                    return Some((
+                        Ok::<Event, Infallible>(event),
+                        (rx, (sub, slot), replay, (seen, coalescer)),
+                    ));
+                }
+                loop {
+                    let deadline = coalescer.as_ref().and_then(Coalescer::deadline);
+                    let held_back = async {
+                        match deadline {
+                            Some(at) => tokio::time::sleep_until(at.into()).await,
+                            None => std::future::pending().await,
+                        }
+                    };
+                    let received = tokio::select! {
+                        received = rx.recv() => received,
+                        _ = held_back => {
+                            if let Some(c) = coalescer.as_mut() {
+                                replay.extend(c.due(Instant::now()));
+                            }
+                            let Some(msg) = replay.pop_front() else {
+                                continue;
+                            };
+                            let event = sse_event(&msg, named);
+                            return Some((
+                                Ok::<Event, Infallible>(event),
+                                (rx, (sub, slot), replay, (seen, coalescer)),
+                            ));
+                        }
+                        _ = shutdown.cancelled() => return None,
+                    };
+                    match received {
+                        Ok(msg) if msg.id <= seen || msg.severity() < min_severity => continue,
+                        Ok(msg) => {
+                            let msg = match coalescer.as_mut() {
+                                Some(c) => match c.push(msg, Instant::now()) {
+                                    Some(msg) => msg,
+                                    None => continue,
+                                },
+                                None => msg,
+                            };
+                            sub.0.record_backlog(rx.len());
+                            let event = sse_event(&msg, named);
+                            return Some((
+                                Ok::<Event, Infallible>(event),
+                                (rx, (sub, slot), replay, (seen, coalescer)),
+                            ));
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            sub.0.record_lag(skipped);
+                            warn!("SSE receiver lagged, skipped {} messages", skipped);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => {
+                            return None;
+                        }
+                    }
+                }
+            }
+        },
+    );
+
+    let headers = [
+        ("Cache-Control", "no-cache, no-store, must-revalidate"),
+        ("Connection", "keep-alive"),
+        ("Content-Type", "text/event-stream"),
+        ("X-Accel-Buffering", "no"), // nginx
+        ("X-Proxy-Buffering", "no"), // other proxies
+    ];
+    Ok((
+        headers,
+        Sse::new(reset.chain(stream)).keep_alive(app_state.keep_alive.keep_alive()),
+    ))
+}
+
+fn sse_slot(slots: &Arc<Semaphore>) -> Result<OwnedSemaphorePermit, MeshError> {
+    slots
+        .clone()
+        .try_acquire_owned()
+        .map_err(|_| MeshError::Unavailable {
+            message: "Too many event streams open, try again later".to_string(),
+            retry_after: SSE_RETRY_AFTER,
+        })
+}
@@ -0,0 +1,610 @@
+use crate::callgraph;
+use crate::hierarchy;
+use crate::storage::{EdgeRecord, NodeRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+
+/// Used when the caller doesn't set `max_nodes`; past this GraphViz layouts get unreadable.
+pub const DEFAULT_MAX_NODES: usize = 500;
+
+/// Id of the node standing in for everything dropped by `max_nodes`.
+pub const TRUNCATED_ID: &str = "__truncated__";
+
+#[derive(Debug, Clone)]
+pub struct DotOptions {
+    /// Node kinds to keep, e.g. `Function`; every kind when empty.
+    pub kinds: Vec<String>,
+    pub max_nodes: usize,
+}
+
+impl Default for DotOptions {
+    fn default() -> Self {
+        DotOptions {
+            kinds: Vec::new(),
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+}
+
+/// Renders the nodes and the edges between them as a GraphViz digraph.
+/// Nodes are kept in the order given; once `max_nodes` is reached the rest are
+/// replaced by a single truncation marker and their edges are dropped.
+pub fn to_dot(nodes: &[NodeRecord], edges: &[EdgeRecord], options: &DotOptions) -> String {
+    let matching: Vec<&NodeRecord> = nodes
+        .iter()
+        .filter(|n| options.kinds.is_empty() || options.kinds.iter().any(|k| k == &n.kind))
+        .collect();
+    let kept = &matching[..matching.len().min(options.max_nodes)];
+    let ids: HashSet<&str> = kept.iter().map(|n| n.id.as_str()).collect();
+
+    let mut out = String::from("digraph mesh {\n");
+    out.push_str("  rankdir=LR;\n");
+    out.push_str("  node [shape=box, fontname=\"Helvetica\"];\n");
+    for node in kept {
+        let _ = writeln!(
+            out,
+            "  {} [label={}, tooltip={}];",
+            quote(&node.id),
+            quote(&node.name),
+            quote(&format!("{} in {}", node.kind, node.file))
+        );
+    }
+    let dropped = matching.len() - kept.len();
+    if dropped > 0 {
+        let _ = writeln!(
+            out,
+            "  {} [label={}, shape=note, style=dashed];",
+            quote(TRUNCATED_ID),
+            quote(&format!("{} more nodes not shown", dropped))
+        );
+    }
+    for edge in edges {
+        if !ids.contains(edge.source.as_str()) || !ids.contains(edge.target.as_str()) {
+            continue;
+        }
+        let _ = writeln!(
+            out,
+            "  {} -> {} [{}];",
+            quote(&edge.source),
+            quote(&edge.target),
+            edge_style(&edge.kind)
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// How far `/export/cytoscape` walks from the `around` symbol by default.
+pub const DEFAULT_RADIUS: usize = 2;
+
+#[derive(Debug, Clone)]
+pub struct CytoscapeOptions {
+    /// Node kinds to keep, e.g. `Function`; every kind when empty.
+    pub kinds: Vec<String>,
+    pub max_nodes: usize,
+    /// Only the neighborhood of the nodes with this name or id.
+    pub around: Option<String>,
+    /// Edges to follow, either way, from `around`.
+    pub radius: usize,
+}
+
+impl Default for CytoscapeOptions {
+    fn default() -> Self {
+        CytoscapeOptions {
+            kinds: Vec::new(),
+            max_nodes: DEFAULT_MAX_NODES,
+            around: None,
+            radius: DEFAULT_RADIUS,
+        }
+    }
+}
+
+/// A graph in the Cytoscape.js JSON format, which `cy.json()` and
+/// `cytoscape({ elements })` read directly.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CytoscapeGraph {
+    pub elements: Vec<Element>,
+    /// Nodes left out by `max_nodes`.
+    pub truncated: usize,
+}
+
+/// A node or an edge, told apart by `group`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Element {
+    /// `nodes` or `edges`.
+    pub group: String,
+    pub data: ElementData,
+    /// Space-separated, for selectors like `node.function`; see [`node_class`].
+    pub classes: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ElementData {
+    pub id: String,
+    /// The node or edge kind, e.g. `Function` or `CALLS`.
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// The style classes of a node kind: the broad group it belongs to, so one
+/// rule styles every kind of type, followed by the kind itself.
+pub fn node_class(kind: &str) -> String {
+    let group = match kind {
+        "Function" => "function",
+        "Class" | "Trait" | "Interface" | "DataModel" => "type",
+        "File" | "Directory" | "Repository" => "file",
+        "Import" => "import",
+        "Var" => "variable",
+        _ => "other",
+    };
+    format!("{} {}", group, kind.to_lowercase())
+}
+
+/// Renders the nodes and the edges between them as Cytoscape.js elements,
+/// nodes first. With `around` set only nodes within `radius` edges of it are
+/// kept, nearest first, so `max_nodes` cuts the far edge of the neighborhood;
+/// otherwise nodes are kept in the order given. `None` when `around` names no
+/// node.
+pub fn to_cytoscape(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    options: &CytoscapeOptions,
+) -> Option<CytoscapeGraph> {
+    let matching: Vec<&NodeRecord> = nodes
+        .iter()
+        .filter(|n| options.kinds.is_empty() || options.kinds.iter().any(|k| k == &n.kind))
+        .collect();
+    let matching = match &options.around {
+        Some(around) => neighborhood(&matching, edges, around, options.radius)?,
+        None => matching,
+    };
+    let kept = &matching[..matching.len().min(options.max_nodes)];
+    let ids: HashSet<&str> = kept.iter().map(|n| n.id.as_str()).collect();
+
+    let mut elements: Vec<Element> = kept
+        .iter()
+        .map(|node| Element {
+            group: "nodes".to_string(),
+            data: ElementData {
+                id: node.id.clone(),
+                kind: node.kind.clone(),
+                label: Some(node.name.clone()),
+                file: Some(node.file.clone()),
+                source: None,
+                target: None,
+            },
+            classes: node_class(&node.kind),
+        })
+        .collect();
+    let mut edge_ids = HashSet::new();
+    for edge in edges {
+        if !ids.contains(edge.source.as_str()) || !ids.contains(edge.target.as_str()) {
+            continue;
+        }
+        let id = format!("{}-{}-{}", edge.kind, edge.source, edge.target);
+        if !edge_ids.insert(id.clone()) {
+            continue;
+        }
+        elements.push(Element {
+            group: "edges".to_string(),
+            data: ElementData {
+                id,
+                kind: edge.kind.clone(),
+                label: None,
+                file: None,
+                source: Some(edge.source.clone()),
+                target: Some(edge.target.clone()),
+            },
+            classes: edge.kind.to_lowercase(),
+        });
+    }
+    Some(CytoscapeGraph {
+        elements,
+        truncated: matching.len() - kept.len(),
+    })
+}
+
+/// The nodes within `radius` edges of those named or identified by `around`,
+/// by distance and then in the order given.
+fn neighborhood<'a>(
+    nodes: &[&'a NodeRecord],
+    edges: &[EdgeRecord],
+    around: &str,
+    radius: usize,
+) -> Option<Vec<&'a NodeRecord>> {
+    let by_id: HashMap<&str, (usize, &NodeRecord)> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_str(), (i, *n)))
+        .collect();
+    let mut adjacent: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in edges {
+        if by_id.contains_key(edge.source.as_str()) && by_id.contains_key(edge.target.as_str()) {
+            adjacent.entry(&edge.source).or_default().push(&edge.target);
+            adjacent.entry(&edge.target).or_default().push(&edge.source);
+        }
+    }
+    let mut distance: HashMap<&str, usize> = nodes
+        .iter()
+        .filter(|n| n.name == around || n.id == around)
+        .map(|n| (n.id.as_str(), 0))
+        .collect();
+    if distance.is_empty() {
+        return None;
+    }
+    let mut queue: VecDeque<&str> = distance.keys().copied().collect();
+    while let Some(id) = queue.pop_front() {
+        let next = distance[id] + 1;
+        if next > radius {
+            continue;
+        }
+        for &other in adjacent.get(id).into_iter().flatten() {
+            if !distance.contains_key(other) {
+                distance.insert(other, next);
+                queue.push_back(other);
+            }
+        }
+    }
+    let mut found: Vec<(usize, usize, &NodeRecord)> = distance
+        .into_iter()
+        .map(|(id, d)| {
+            let (i, node) = by_id[id];
+            (d, i, node)
+        })
+        .collect();
+    found.sort_by_key(|(d, i, _)| (*d, *i));
+    Some(found.into_iter().map(|(_, _, node)| node).collect())
+}
+
+fn edge_style(kind: &str) -> String {
+    let style = match kind {
+        "CALLS" => "style=solid, color=\"#1f77b4\"",
+        "IMPORTS" => "style=dashed, color=\"#2ca02c\"",
+        "CONTAINS" => "style=dotted, color=\"#7f7f7f\", arrowhead=none",
+        _ => "style=solid",
+    };
+    format!("label={}, {}", quote(kind), style)
+}
+
+/// A DOT double-quoted string; quotes and backslashes are escaped and newlines
+/// become `\n` so a statement never spans lines.
+fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Used when the caller doesn't set `max_nodes` on a Mermaid export; Mermaid
+/// diagrams are read inline in docs, where a few dozen nodes is already a lot.
+pub const DEFAULT_MERMAID_NODES: usize = 50;
+
+/// What a Mermaid export draws around its symbol.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MermaidGraph {
+    /// Callers and callees, as `/call-graph` resolves them.
+    #[default]
+    Calls,
+    /// Base types and subtypes, as `/hierarchy` finds them.
+    Hierarchy,
+}
+
+/// Which way a Mermaid flowchart is laid out.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Direction {
+    #[serde(rename = "TB", alias = "TD", alias = "tb", alias = "td")]
+    TopDown,
+    #[serde(rename = "BT", alias = "bt")]
+    BottomUp,
+    #[default]
+    #[serde(rename = "LR", alias = "lr")]
+    LeftRight,
+    #[serde(rename = "RL", alias = "rl")]
+    RightLeft,
+}
+
+impl Direction {
+    fn keyword(self) -> &'static str {
+        match self {
+            Direction::TopDown => "TB",
+            Direction::BottomUp => "BT",
+            Direction::LeftRight => "LR",
+            Direction::RightLeft => "RL",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MermaidOptions {
+    pub graph: MermaidGraph,
+    /// Name or id of the symbol to draw around.
+    pub around: String,
+    pub direction: Direction,
+    pub max_nodes: usize,
+    /// Calls to follow, either way, from `around`; a hierarchy is drawn
+    /// whole.
+    pub radius: usize,
+}
+
+impl Default for MermaidOptions {
+    fn default() -> Self {
+        MermaidOptions {
+            graph: MermaidGraph::default(),
+            around: String::new(),
+            direction: Direction::default(),
+            max_nodes: DEFAULT_MERMAID_NODES,
+            radius: DEFAULT_RADIUS,
+        }
+    }
+}
+
+/// Renders the call graph or type hierarchy around `options.around` as a
+/// Mermaid flowchart. Nodes are kept nearest first, so `max_nodes` cuts the
+/// far edge of the diagram, and what it cuts is counted on a single
+/// truncation marker as in [`to_dot`]. Node ids are numbered, as Mermaid
+/// reads some words as keywords, and labels are escaped with Mermaid's
+/// entity codes. `None` when `around` names no function or type.
+pub fn to_mermaid(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    options: &MermaidOptions,
+) -> Option<String> {
+    let drawn = match options.graph {
+        MermaidGraph::Calls => call_edges(nodes, edges),
+        MermaidGraph::Hierarchy => hierarchy_edges(nodes, edges, &options.around)?,
+    };
+    let kinds: &[&str] = match options.graph {
+        MermaidGraph::Calls => &["Function"],
+        MermaidGraph::Hierarchy => &["Class", "Trait", "Interface", "DataModel"],
+    };
+    let candidates: Vec<&NodeRecord> = nodes
+        .iter()
+        .filter(|n| kinds.contains(&n.kind.as_str()))
+        .collect();
+    let radius = match options.graph {
+        MermaidGraph::Calls => options.radius,
+        MermaidGraph::Hierarchy => usize::MAX,
+    };
+    let found = neighborhood(&candidates, &drawn, &options.around, radius)?;
+    let kept = &found[..found.len().min(options.max_nodes)];
+    let ids: HashMap<&str, String> = kept
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_str(), format!("n{}", i)))
+        .collect();
+
+    let mut out = format!("flowchart {}\n", options.direction.keyword());
+    for node in kept {
+        let _ = writeln!(
+            out,
+            "  {}[\"{}\"]",
+            ids[node.id.as_str()],
+            mermaid_label(&node.name)
+        );
+    }
+    let dropped = found.len() - kept.len();
+    if dropped > 0 {
+        let _ = writeln!(
+            out,
+            "  {}[\"{} more nodes not shown\"]",
+            TRUNCATED_ID, dropped
+        );
+    }
+    let mut seen = HashSet::new();
+    for edge in &drawn {
+        let (Some(source), Some(target)) =
+            (ids.get(edge.source.as_str()), ids.get(edge.target.as_str()))
+        else {
+            continue;
+        };
+        if !seen.insert((source, target, &edge.kind)) {
+            continue;
+        }
+        let arrow = if edge.kind == hierarchy::IMPLEMENTS {
+            "-.->"
+        } else {
+            "-->"
+        };
+        let _ = writeln!(out, "  {} {}|{}| {}", source, arrow, edge.kind, target);
+    }
+    // the symbol asked about stands out
+    let focus: Vec<&str> = kept
+        .iter()
+        .filter(|n| n.name == options.around || n.id == options.around)
+        .map(|n| ids[n.id.as_str()].as_str())
+        .collect();
+    if !focus.is_empty() {
+        out.push_str("  classDef focus stroke-width:3px\n");
+        let _ = writeln!(out, "  class {} focus", focus.join(","));
+    }
+    Some(out)
+}
+
+/// Caller to callee `CALLS` edges for every call resolved, to each of its
+/// candidates.
+fn call_edges(nodes: &[NodeRecord], edges: &[EdgeRecord]) -> Vec<EdgeRecord> {
+    let graph = callgraph::resolve_calls(nodes, edges);
+    let mut drawn = Vec::new();
+    for function in &graph.functions {
+        for call in &function.calls {
+            for candidate in &call.candidates {
+                drawn.push(EdgeRecord {
+                    repo_id: String::new(),
+                    kind: "CALLS".to_string(),
+                    source: function.id.clone(),
+                    target: candidate.id.clone(),
+                });
+            }
+        }
+    }
+    drawn
+}
+
+/// Derived to base `EXTENDS` and `IMPLEMENTS` edges within the hierarchies of
+/// the types named or identified by `around`; `None` when it names no type.
+fn hierarchy_edges(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    around: &str,
+) -> Option<Vec<EdgeRecord>> {
+    let by_id = nodes.iter().find(|n| n.id == around);
+    let name = by_id.map_or(around, |n| n.name.as_str());
+    let mut types = hierarchy::hierarchy(nodes, edges, name);
+    if let Some(node) = by_id {
+        types.retain(|t| t.id == node.id);
+    }
+    if types.is_empty() {
+        return None;
+    }
+    let edge = |kind: &str, source: &str, target: &str| EdgeRecord {
+        repo_id: String::new(),
+        kind: kind.to_string(),
+        source: source.to_string(),
+        target: target.to_string(),
+    };
+    let mut drawn = Vec::new();
+    for found in &types {
+        for base in &found.ancestors {
+            drawn.push(edge(&base.relation, &base.via, &base.id));
+        }
+        for sub in &found.descendants {
+            drawn.push(edge(&sub.relation, &sub.id, &sub.via));
+        }
+    }
+    Some(drawn)
+}
+
+/// A label fit to sit between the quotes of a Mermaid node. Quotes, and the
+/// characters Mermaid would read as markup or entities, become its `#name;`
+/// entity codes, and the label is kept to one line.
+fn mermaid_label(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("#quot;"),
+            '#' => out.push_str("#35;"),
+            '<' => out.push_str("#lt;"),
+            '>' => out.push_str("#gt;"),
+            '`' => out.push_str("#96;"),
+            '\n' | '\r' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// One line of a newline-delimited JSON graph dump, tagged `"type": "node"`
+/// or `"type": "edge"`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum GraphLine {
+    Node(NodeRecord),
+    Edge(EdgeRecord),
+}
+
+impl GraphLine {
+    /// The line, including its trailing newline.
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).unwrap_or_default();
+        line.push('\n');
+        line
+    }
+}
+
+/// The dump lines for a graph, nodes first so a reader meets every node before
+/// the edges that use it.
+pub fn ndjson_lines(
+    nodes: Vec<NodeRecord>,
+    edges: Vec<EdgeRecord>,
+) -> impl Iterator<Item = String> {
+    nodes
+        .into_iter()
+        .map(GraphLine::Node)
+        .chain(edges.into_iter().map(GraphLine::Edge))
+        .map(|line| line.to_line())
+}
+
+/// The first line of a dump that couldn't be read, numbered from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for LineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Reads a dump written by `ndjson_lines`, moving every record into `repo_id`
+/// when given. Blank lines are skipped. Every edge must connect nodes of its
+/// own repo that appear somewhere in the dump; a malformed line is reported
+/// before any dangling edge.
+pub fn parse_ndjson(
+    text: &str,
+    repo_id: Option<&str>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>), LineError> {
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    for (i, raw) in text.lines().enumerate() {
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let line = serde_json::from_str::<GraphLine>(raw).map_err(|e| LineError {
+            line: i + 1,
+            message: e.to_string(),
+        })?;
+        match line {
+            GraphLine::Node(mut node) => {
+                if let Some(repo_id) = repo_id {
+                    node.repo_id = repo_id.to_string();
+                }
+                nodes.push(node);
+            }
+            GraphLine::Edge(mut edge) => {
+                if let Some(repo_id) = repo_id {
+                    edge.repo_id = repo_id.to_string();
+                }
+                edges.push((i + 1, edge));
+            }
+        }
+    }
+
+    let known: HashSet<(&str, &str)> = nodes
+        .iter()
+        .map(|n| (n.repo_id.as_str(), n.id.as_str()))
+        .collect();
+    for (line, edge) in &edges {
+        for endpoint in [&edge.source, &edge.target] {
+            if !known.contains(&(edge.repo_id.as_str(), endpoint.as_str())) {
+                return Err(LineError {
+                    line: *line,
+                    message: format!(
+                        "{} edge references unknown node {} in {}",
+                        edge.kind, endpoint, edge.repo_id
+                    ),
+                });
+            }
+        }
+    }
+    Ok((nodes, edges.into_iter().map(|(_, edge)| edge).collect()))
+}
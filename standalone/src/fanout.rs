@@ -0,0 +1,34 @@
+//! How traversals cope with nodes of very high degree, such as a utility
+//! function with tens of thousands of callers: each node contributes at
+//! most a configured number of edges to a `/call-graph`, `/references` or
+//! `/neighborhood` response, the most relevant ones, and says how many it
+//! left out. Lists within the cap keep their usual order.
+
+use std::cmp::Ordering;
+
+/// Edges of one node a traversal returns at most, by default.
+pub const DEFAULT_MAX_EDGES_PER_NODE: usize = 1_000;
+
+/// Keeps the `max` first of `items` once sorted by `relevance`, most
+/// relevant first, and returns how many were left out. Nothing is reordered
+/// when no more than `max` are given, or when `max` is `None`.
+pub fn cap<T>(
+    items: &mut Vec<T>,
+    max: Option<usize>,
+    relevance: impl FnMut(&T, &T) -> Ordering,
+) -> usize {
+    let Some(max) = max else {
+        return 0;
+    };
+    if items.len() <= max {
+        return 0;
+    }
+    items.sort_by(relevance);
+    let omitted = items.len() - max;
+    items.truncate(max);
+    omitted
+}
+
+pub(crate) fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
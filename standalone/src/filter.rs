@@ -0,0 +1,360 @@
+use crate::lang::LanguageRegistry;
+use crate::storage::{EdgeRecord, NodeRecord};
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// File extensions of the languages `ast` parses, plus the build and shell
+/// scripts found next to them, keyed by the names accepted in `include_langs`.
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("rust", &["rs"]),
+    ("python", &["py"]),
+    ("go", &["go"]),
+    ("typescript", &["ts", "tsx"]),
+    ("javascript", &["js", "jsx", "mjs", "cjs"]),
+    ("react", &["jsx", "tsx"]),
+    ("ruby", &["rb"]),
+    ("java", &["java"]),
+    ("kotlin", &["kt", "kts"]),
+    ("swift", &["swift"]),
+    ("c", &["c", "h"]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh", "h"]),
+    ("shell", &["sh", "bash", "zsh"]),
+    ("make", &["mk"]),
+    ("dockerfile", &["dockerfile"]),
+];
+
+/// Files recognized by name alone, whatever their extension says.
+const FILE_NAMES: &[(&str, &str)] = &[
+    ("Makefile", "make"),
+    ("makefile", "make"),
+    ("GNUmakefile", "make"),
+    ("Dockerfile", "dockerfile"),
+    ("Containerfile", "dockerfile"),
+    ("Rakefile", "ruby"),
+    ("Gemfile", "ruby"),
+];
+
+/// Shebang interpreters, with any version suffix (`python3.12`) removed.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("python", "python"),
+    ("ruby", "ruby"),
+    ("node", "javascript"),
+    ("nodejs", "javascript"),
+    ("deno", "javascript"),
+    ("bun", "javascript"),
+    ("ts-node", "typescript"),
+    ("sh", "shell"),
+    ("bash", "shell"),
+    ("zsh", "shell"),
+    ("dash", "shell"),
+    ("make", "make"),
+];
+
+/// Seen in C++ headers but not in C ones.
+const CPP_MARKERS: &[&str] = &[
+    "class ",
+    "namespace ",
+    "template<",
+    "template <",
+    "std::",
+    "public:",
+    "private:",
+];
+
+/// An extension of the files of `lang`, an `include_langs` name, so a file
+/// named with it is parsed as that language.
+pub fn extension_for(lang: &str, languages: &LanguageRegistry) -> Option<String> {
+    let lang = lang.trim();
+    LANGUAGES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(lang))
+        .map(|(_, exts)| exts[0].to_string())
+        .or_else(|| languages.extensions_for(lang)?.into_iter().next())
+}
+
+/// The `include_langs` name of the language `ast` parses `path` as, if any.
+pub fn language_of(path: &str) -> Option<&'static str> {
+    let ext = Path::new(path).extension()?.to_str()?;
+    LANGUAGES
+        .iter()
+        .find(|(_, exts)| exts.contains(&ext))
+        .map(|(name, _)| *name)
+}
+
+/// The `include_langs` name of what `path` holds, going by its first
+/// bytes, `head`, where the path alone doesn't say. An extension with a single
+/// language wins; then come well-known file names like `Makefile` and
+/// `Dockerfile`, then the shebang line. A `.h` header is C++ when `head` uses
+/// C++ syntax and C otherwise.
+pub fn detect_language(path: &Path, head: &[u8]) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("h") => return Some(header_language(head)),
+        Some(_) => {
+            if let Some(lang) = language_of(name) {
+                return Some(lang);
+            }
+        }
+        None => {}
+    }
+    let known = FILE_NAMES
+        .iter()
+        .find(|(known, _)| name == *known || name.starts_with(&format!("{}.", known)));
+    if let Some((_, lang)) = known {
+        return Some(lang);
+    }
+    shebang_language(head)
+}
+
+/// `#!/usr/bin/python3`, `#!/usr/bin/env node` and `#!/usr/bin/env -S deno run`
+/// all name their interpreter; the rest of the line is its arguments.
+fn shebang_language(head: &[u8]) -> Option<&'static str> {
+    let line = head.strip_prefix(b"#!")?;
+    let line = line.split(|b| *b == b'\n').next()?;
+    let line = String::from_utf8_lossy(line);
+    let mut words = line.split_whitespace();
+    let mut program = interpreter(words.next()?);
+    if program == "env" {
+        // skip env's own flags and variable assignments
+        program = words
+            .find(|w| !w.starts_with('-') && !w.contains('='))
+            .map(interpreter)?;
+    }
+    INTERPRETERS
+        .iter()
+        .find(|(name, _)| *name == program)
+        .map(|(_, lang)| *lang)
+}
+
+fn interpreter(word: &str) -> &str {
+    let program = word.rsplit('/').next().unwrap_or(word);
+    program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')
+}
+
+fn header_language(head: &[u8]) -> &'static str {
+    let head = String::from_utf8_lossy(head);
+    if CPP_MARKERS.iter().any(|marker| head.contains(marker)) {
+        "cpp"
+    } else {
+        "c"
+    }
+}
+
+/// Which repo files an ingest visits. Decided from the path alone, before
+/// anything is parsed; only [`FileFilter::allows_in`] reads the start of a
+/// file, and only when its path doesn't say what language it is.
+#[derive(Default)]
+pub struct FileFilter {
+    /// Allowed extensions; `None` means every language.
+    extensions: Option<HashSet<String>>,
+    /// The built-in languages among the allowed ones, for files recognized by
+    /// [`detect_language`] rather than their extension.
+    languages: HashSet<&'static str>,
+    exclude: Option<GlobSet>,
+    /// The repo's `.meshignore`, once [`FileFilter::ignore_file`] found one.
+    ignore: Option<Gitignore>,
+    /// Files [`FileFilter::skip`] took out, e.g. for being too large to parse.
+    skipped: HashSet<String>,
+    /// The repo-relative directory of [`FileFilter::within`].
+    subdir: Option<String>,
+}
+
+impl FileFilter {
+    /// Fails on a language that neither `ast` nor a registered plugin knows,
+    /// or on a glob that doesn't compile.
+    pub fn new(
+        include_langs: &[String],
+        exclude_globs: &[String],
+        languages: &LanguageRegistry,
+    ) -> Result<Self> {
+        let mut extensions = None;
+        let mut builtins = HashSet::new();
+        if !include_langs.is_empty() {
+            let mut allowed = HashSet::new();
+            for lang in include_langs {
+                let builtin = LANGUAGES
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(lang.trim()));
+                if let Some((name, _)) = builtin {
+                    builtins.insert(*name);
+                }
+                let exts = builtin
+                    .map(|(_, exts)| exts.iter().map(|e| e.to_string()).collect())
+                    .or_else(|| languages.extensions_for(lang.trim()))
+                    .with_context(|| format!("unknown language '{}'", lang))?;
+                allowed.extend(exts);
+            }
+            extensions = Some(allowed);
+        }
+
+        let mut exclude = None;
+        if !exclude_globs.is_empty() {
+            let mut builder = GlobSetBuilder::new();
+            for glob in exclude_globs {
+                // `*` stays within one directory; `**` crosses them
+                let glob = GlobBuilder::new(glob.trim_start_matches("./"))
+                    .literal_separator(true)
+                    .build()
+                    .with_context(|| format!("invalid exclude glob '{}'", glob))?;
+                builder.add(glob);
+            }
+            exclude = Some(builder.build()?);
+        }
+        Ok(FileFilter {
+            extensions,
+            languages: builtins,
+            exclude,
+            ignore: None,
+            skipped: HashSet::new(),
+            subdir: None,
+        })
+    }
+
+    /// Also leaves out what the `.meshignore` at `root` ignores, if there is
+    /// one. Fails on a file that can't be read or parsed.
+    pub fn ignore_file(&mut self, root: &Path) -> Result<()> {
+        let path = root.join(crate::local::IGNORE_FILE);
+        if !path.is_file() {
+            return Ok(());
+        }
+        let mut builder = GitignoreBuilder::new(root);
+        if let Some(err) = builder.add(&path) {
+            return Err(err).with_context(|| format!("invalid {}", path.display()));
+        }
+        self.ignore = Some(builder.build()?);
+        Ok(())
+    }
+
+    /// Also leaves out every file outside the repo-relative directory
+    /// `subdir`. Paths are still matched from the repo root, so globs and the
+    /// `.meshignore` work as they would for the whole repo.
+    pub fn within(&mut self, subdir: &str) {
+        let subdir = subdir.trim_start_matches("./").trim_end_matches('/');
+        self.subdir = (!subdir.is_empty() && subdir != ".").then(|| subdir.to_string());
+    }
+
+    pub fn subdir(&self) -> Option<&str> {
+        self.subdir.as_deref()
+    }
+
+    /// Whether the repo-relative `path` is under [`FileFilter::within`]'s
+    /// directory, judged from the path alone; true when there is none.
+    pub fn in_subdir(&self, path: &str) -> bool {
+        let Some(subdir) = &self.subdir else {
+            return true;
+        };
+        path.trim_start_matches("./")
+            .strip_prefix(subdir.as_str())
+            .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    /// Also leaves out these repo-relative files.
+    pub fn skip(&mut self, files: impl IntoIterator<Item = String>) {
+        self.skipped.extend(files);
+    }
+
+    /// True when the filter lets everything through.
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_none()
+            && self.exclude.is_none()
+            && self.ignore.is_none()
+            && self.skipped.is_empty()
+            && self.subdir.is_none()
+    }
+
+    /// Whether the repo-relative `path` should be parsed.
+    pub fn allows(&self, path: &str) -> bool {
+        self.allows_with(path, |_| None)
+    }
+
+    /// Like [`FileFilter::allows`], but a file the language filter would
+    /// leave out for its extension is let in when [`detect_language`], given
+    /// the file's first bytes under `root`, finds an allowed language, e.g. an
+    /// extensionless `#!/usr/bin/env python3` script when `python` is.
+    pub fn allows_in(&self, root: &Path, path: &str) -> bool {
+        self.allows_with(path, |path| crate::local::detect_language(root, path))
+    }
+
+    fn allows_with(&self, path: &str, detect: impl Fn(&str) -> Option<&'static str>) -> bool {
+        let path = path.trim_start_matches("./");
+        if self.skipped.contains(path) || !self.in_subdir(path) {
+            return false;
+        }
+        if let Some(extensions) = &self.extensions {
+            let ext = Path::new(path).extension().and_then(|e| e.to_str());
+            if !ext.is_some_and(|e| extensions.contains(e))
+                && !detect(path).is_some_and(|lang| self.languages.contains(lang))
+            {
+                return false;
+            }
+        }
+        if let Some(ignore) = &self.ignore {
+            if ignore.matched_path_or_any_parents(path, false).is_ignore() {
+                return false;
+            }
+        }
+        !self.exclude.as_ref().is_some_and(|set| set.is_match(path))
+    }
+
+    pub fn apply(&self, files: Vec<String>) -> Vec<String> {
+        files.into_iter().filter(|f| self.allows(f)).collect()
+    }
+
+    /// [`FileFilter::apply`] with [`FileFilter::allows_in`], for files on
+    /// disk under `root`.
+    pub fn apply_in(&self, root: &Path, files: Vec<String>) -> Vec<String> {
+        files
+            .into_iter()
+            .filter(|f| self.allows_in(root, f))
+            .collect()
+    }
+}
+
+/// Which node kinds an ingest stores, from `node_kinds`, matched ignoring
+/// case. Unlike [`FileFilter`] it applies after parsing, to the records.
+#[derive(Default, Clone)]
+pub struct KindFilter {
+    /// Normalized the way node ids spell their kind; `None` keeps every kind.
+    kinds: Option<HashSet<String>>,
+}
+
+impl KindFilter {
+    pub fn new(node_kinds: &[String]) -> Self {
+        let kinds: HashSet<String> = node_kinds
+            .iter()
+            .map(|kind| id_kind(kind))
+            .filter(|kind| !kind.is_empty())
+            .collect();
+        KindFilter {
+            kinds: (!kinds.is_empty()).then_some(kinds),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_none()
+    }
+
+    /// Drops the nodes of other kinds, and every edge with an endpoint of
+    /// another kind. Endpoints are judged by the kind their id starts with,
+    /// so an edge into a node stored by an earlier ingest is dropped the same
+    /// way as one into a node of this ingest.
+    pub fn apply(&self, nodes: &mut Vec<NodeRecord>, edges: &mut Vec<EdgeRecord>) {
+        let Some(kinds) = &self.kinds else {
+            return;
+        };
+        nodes.retain(|node| kinds.contains(&id_kind(&node.kind)));
+        let kept = |id: &str| kinds.contains(id.split('-').next().unwrap_or_default());
+        edges.retain(|edge| kept(&edge.source) && kept(&edge.target));
+    }
+}
+
+/// `Function` -> `function`, the way [`crate::storage::node_key`] starts an id.
+fn id_kind(kind: &str) -> String {
+    kind.to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
@@ -0,0 +1,148 @@
+//! The versions of the grammars a repo was parsed with. Each node is tagged
+//! with the version of its file's grammar, and each ingest stores the
+//! versions it parsed with under an id of its own next to the repo's, so a
+//! graph built before a grammar upgrade isn't silently mixed with nodes
+//! parsed since: an ingest that finds the versions changed parses every
+//! file again, and startup warns about every repo it would.
+
+use crate::captures::{Grammar, GRAMMARS};
+use crate::storage::Storage;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The meta key of the version of the grammar a node was parsed with.
+pub const GRAMMAR_VERSION: &str = "grammar_version";
+
+/// A grammar whose version isn't the one a repo was parsed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// A name of [`GRAMMARS`].
+    pub grammar: String,
+    /// The version it was parsed with; `None` for a grammar added since.
+    pub stored: Option<String>,
+    /// The version it's parsed with now; `None` for one that's gone.
+    pub current: Option<String>,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_none = |v: &Option<String>| v.clone().unwrap_or_else(|| "none".to_string());
+        write!(
+            f,
+            "{} {} -> {}",
+            self.grammar,
+            or_none(&self.stored),
+            or_none(&self.current)
+        )
+    }
+}
+
+/// The version of `grammar`, from the semantic version its parser was
+/// generated with, or where it predates tree-sitter 0.25 and only carries
+/// its ABI, from that and the number of node kinds it has.
+pub fn version(grammar: &Grammar) -> String {
+    let language = grammar.language();
+    match language.metadata() {
+        Some(m) => format!(
+            "{}.{}.{} (abi {})",
+            m.major_version,
+            m.minor_version,
+            m.patch_version,
+            language.abi_version()
+        ),
+        None => format!(
+            "abi {}, {} node kinds",
+            language.abi_version(),
+            language.node_kind_count()
+        ),
+    }
+}
+
+/// The version of every grammar of [`GRAMMARS`], by name.
+pub fn current() -> &'static BTreeMap<String, String> {
+    static CURRENT: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+    CURRENT.get_or_init(|| {
+        GRAMMARS
+            .iter()
+            .map(|g| (g.name.to_string(), version(g)))
+            .collect()
+    })
+}
+
+/// The version of the grammar `path` is parsed with, if it's one of
+/// [`GRAMMARS`].
+pub fn version_for(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?;
+    let grammar = GRAMMARS.iter().find(|g| g.extensions.contains(&ext))?;
+    current().get(grammar.name).map(String::as_str)
+}
+
+/// The id the grammar versions of `repo_id` are stored under, which `~`
+/// keeps from being taken for a snapshot's.
+pub fn id(repo_id: &str) -> String {
+    format!("{}#~grammars", repo_id)
+}
+
+/// Records that `repo_id` was just parsed with the [`current`] grammars.
+pub async fn record(storage: &dyn Storage, repo_id: &str) -> Result<()> {
+    let versions = serde_json::to_string(current())?;
+    storage.set_repo_hash(&id(repo_id), &versions).await
+}
+
+/// The grammar versions `repo_id` was last parsed with, or `None` when none
+/// were recorded, as for a repo ingested before they were.
+pub async fn stored(
+    storage: &dyn Storage,
+    repo_id: &str,
+) -> Result<Option<BTreeMap<String, String>>> {
+    let stored = storage.repo_hash(&id(repo_id)).await?;
+    let Some(versions) = stored.filter(|v| !v.is_empty()) else {
+        return Ok(None);
+    };
+    let versions = serde_json::from_str(&versions)
+        .with_context(|| format!("invalid grammar versions stored for {}", repo_id))?;
+    Ok(Some(versions))
+}
+
+/// How `stored` differs from `current`, by grammar name.
+pub fn mismatches(
+    stored: &BTreeMap<String, String>,
+    current: &BTreeMap<String, String>,
+) -> Vec<Mismatch> {
+    let mut names: Vec<&String> = stored.keys().chain(current.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| stored.get(*name) != current.get(*name))
+        .map(|name| Mismatch {
+            grammar: name.clone(),
+            stored: stored.get(name).cloned(),
+            current: current.get(name).cloned(),
+        })
+        .collect()
+}
+
+/// The grammars whose version changed since `repo_id` was last parsed;
+/// none when no versions were recorded for it.
+pub async fn check(storage: &dyn Storage, repo_id: &str) -> Result<Vec<Mismatch>> {
+    Ok(match stored(storage, repo_id).await? {
+        Some(stored) => mismatches(&stored, current()),
+        None => Vec::new(),
+    })
+}
+
+/// Every stored repo whose grammars changed since it was last parsed, with
+/// how, for the warnings logged at startup.
+pub async fn drifted(storage: &dyn Storage) -> Result<Vec<(String, Vec<Mismatch>)>> {
+    let mut drifted = Vec::new();
+    for repo in storage.repos().await? {
+        let changed = check(storage, &repo.repo_id).await?;
+        if !changed.is_empty() {
+            drifted.push((repo.repo_id, changed));
+        }
+    }
+    Ok(drifted)
+}
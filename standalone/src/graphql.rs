@@ -0,0 +1,373 @@
+use crate::callgraph::{self, CallGraph};
+use crate::storage::{repo_relative, EdgeRecord, NodeRecord, RepoRecord, Storage};
+use crate::AppState;
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Result, Schema};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::extract::State;
+use axum::Extension;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How deeply selections may nest, e.g. symbol -> callers -> file -> symbols
+/// is four levels.
+pub const MAX_DEPTH: usize = 12;
+
+pub type MeshSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn schema() -> MeshSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// Runs a query against the stored graph. Each request gets its own
+/// [`GraphLoader`], so a repo is read at most once per query however many
+/// fields need it.
+pub async fn handler(
+    State(state): State<Arc<AppState>>,
+    Extension(schema): Extension<MeshSchema>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let loader = DataLoader::new(GraphLoader::new(state.storage.clone()), tokio::spawn);
+    let request = request
+        .into_inner()
+        .data(state.storage.clone())
+        .data(loader);
+    schema.execute(request).await.into()
+}
+
+/// One repo's graph with its calls resolved.
+pub struct RepoGraph {
+    nodes: Vec<NodeRecord>,
+    by_id: HashMap<String, usize>,
+    calls: CallGraph,
+    /// Callee id to the ids of the functions calling it.
+    callers: HashMap<String, Vec<String>>,
+}
+
+impl RepoGraph {
+    pub fn new(nodes: Vec<NodeRecord>, edges: &[EdgeRecord]) -> Self {
+        let calls = callgraph::resolve_calls(&nodes, edges);
+        let mut callers: HashMap<String, Vec<String>> = HashMap::new();
+        for function in &calls.functions {
+            for call in &function.calls {
+                for candidate in call.candidates.iter().filter(|c| c.repo_id.is_none()) {
+                    callers
+                        .entry(candidate.id.clone())
+                        .or_default()
+                        .push(function.id.clone());
+                }
+            }
+        }
+        for ids in callers.values_mut() {
+            ids.sort();
+            ids.dedup();
+        }
+        let by_id = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.id.clone(), i))
+            .collect();
+        RepoGraph {
+            nodes,
+            by_id,
+            calls,
+            callers,
+        }
+    }
+
+    fn node(&self, id: &str) -> Option<&NodeRecord> {
+        self.by_id.get(id).map(|i| &self.nodes[*i])
+    }
+
+    fn symbols(&self, ids: &[String]) -> Vec<Symbol> {
+        ids.iter()
+            .filter_map(|id| self.node(id))
+            .map(|n| Symbol(n.clone()))
+            .collect()
+    }
+}
+
+/// Loads the [`RepoGraph`] of each repo id asked for in the same query.
+pub struct GraphLoader {
+    storage: Arc<dyn Storage>,
+}
+
+impl GraphLoader {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        GraphLoader { storage }
+    }
+}
+
+impl Loader<String> for GraphLoader {
+    type Value = Arc<RepoGraph>;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(
+        &self,
+        repo_ids: &[String],
+    ) -> Result<HashMap<String, Arc<RepoGraph>>, Self::Error> {
+        let mut graphs = HashMap::new();
+        for repo_id in repo_ids {
+            let (nodes, edges) = self
+                .storage
+                .load_graph(Some(repo_id))
+                .await
+                .map_err(Arc::new)?;
+            graphs.insert(repo_id.clone(), Arc::new(RepoGraph::new(nodes, &edges)));
+        }
+        Ok(graphs)
+    }
+}
+
+async fn repo_graph(ctx: &Context<'_>, repo_id: &str) -> Result<Arc<RepoGraph>> {
+    let loader = ctx.data::<DataLoader<GraphLoader>>()?;
+    let graph = loader.load_one(repo_id.to_string()).await?;
+    Ok(graph.unwrap_or_else(|| Arc::new(RepoGraph::new(Vec::new(), &[]))))
+}
+
+/// The symbols of `repo_id`, only those of `name` and `kind` when given.
+async fn repo_symbols(
+    ctx: &Context<'_>,
+    repo_id: &str,
+    name: Option<String>,
+    kind: Option<String>,
+) -> Result<Vec<Symbol>> {
+    let graph = repo_graph(ctx, repo_id).await?;
+    Ok(graph
+        .nodes
+        .iter()
+        .filter(|n| name.as_ref().is_none_or(|name| &n.name == name))
+        .filter(|n| kind.as_ref().is_none_or(|kind| &n.kind == kind))
+        .map(|n| Symbol(n.clone()))
+        .collect())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Every ingested repo, ordered by id.
+    async fn repos(&self, ctx: &Context<'_>) -> Result<Vec<Repo>> {
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        Ok(storage.repos().await?.into_iter().map(Repo).collect())
+    }
+
+    async fn repo(&self, ctx: &Context<'_>, id: String) -> Result<Option<Repo>> {
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        let repos = storage.repos().await?;
+        Ok(repos.into_iter().find(|r| r.repo_id == id).map(Repo))
+    }
+
+    /// A file of `repo` by its repo-relative path.
+    async fn file(&self, repo: String, path: String) -> File {
+        File {
+            repo_id: repo,
+            path,
+        }
+    }
+
+    /// The symbols of `repo`, optionally only those of a name or kind.
+    async fn symbols(
+        &self,
+        ctx: &Context<'_>,
+        repo: String,
+        name: Option<String>,
+        kind: Option<String>,
+    ) -> Result<Vec<Symbol>> {
+        repo_symbols(ctx, &repo, name, kind).await
+    }
+
+    async fn symbol(&self, ctx: &Context<'_>, repo: String, id: String) -> Result<Option<Symbol>> {
+        let graph = repo_graph(ctx, &repo).await?;
+        Ok(graph.node(&id).map(|n| Symbol(n.clone())))
+    }
+}
+
+/// What the last ingest of a repo was made from.
+pub struct Repo(RepoRecord);
+
+#[Object]
+impl Repo {
+    async fn id(&self) -> &str {
+        &self.0.repo_id
+    }
+
+    /// Empty for a directory on disk.
+    async fn url(&self) -> &str {
+        &self.0.url
+    }
+
+    #[graphql(name = "ref")]
+    async fn git_ref(&self) -> Option<&str> {
+        self.0.git_ref.as_deref()
+    }
+
+    async fn commit(&self) -> Option<&str> {
+        self.0.commit.as_deref()
+    }
+
+    /// Unix seconds.
+    async fn ingested_at(&self) -> u64 {
+        self.0.ingested_at
+    }
+
+    async fn files(&self, ctx: &Context<'_>) -> Result<Vec<File>> {
+        let graph = repo_graph(ctx, &self.0.repo_id).await?;
+        Ok(graph
+            .nodes
+            .iter()
+            .filter(|n| n.kind == "File")
+            .map(|n| File {
+                repo_id: self.0.repo_id.clone(),
+                path: n.file.clone(),
+            })
+            .collect())
+    }
+
+    async fn symbols(
+        &self,
+        ctx: &Context<'_>,
+        name: Option<String>,
+        kind: Option<String>,
+    ) -> Result<Vec<Symbol>> {
+        repo_symbols(ctx, &self.0.repo_id, name, kind).await
+    }
+}
+
+pub struct File {
+    repo_id: String,
+    path: String,
+}
+
+#[Object]
+impl File {
+    /// As stored, which may start with the directory the repo was cloned into.
+    async fn path(&self) -> &str {
+        &self.path
+    }
+
+    async fn repo(&self) -> &str {
+        &self.repo_id
+    }
+
+    /// Everything defined in the file, in the order it was stored.
+    async fn symbols(&self, ctx: &Context<'_>) -> Result<Vec<Symbol>> {
+        let graph = repo_graph(ctx, &self.repo_id).await?;
+        Ok(graph
+            .nodes
+            .iter()
+            .filter(|n| {
+                n.kind != "File"
+                    && (n.file == self.path || repo_relative(&n.file, &self.repo_id) == self.path)
+            })
+            .map(|n| Symbol(n.clone()))
+            .collect())
+    }
+}
+
+/// A node of the graph: a function, class, import, ...
+pub struct Symbol(NodeRecord);
+
+#[Object]
+impl Symbol {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    async fn kind(&self) -> &str {
+        &self.0.kind
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn file(&self) -> File {
+        File {
+            repo_id: self.0.repo_id.clone(),
+            path: self.0.file.clone(),
+        }
+    }
+
+    /// 1-based, inclusive.
+    async fn start_line(&self) -> usize {
+        self.0.start + 1
+    }
+
+    async fn end_line(&self) -> usize {
+        self.0.end + 1
+    }
+
+    async fn body(&self) -> &str {
+        &self.0.body
+    }
+
+    /// The functions that call this one through any candidate of the call.
+    async fn callers(&self, ctx: &Context<'_>) -> Result<Vec<Symbol>> {
+        let graph = repo_graph(ctx, &self.0.repo_id).await?;
+        let ids = graph.callers.get(&self.0.id).cloned().unwrap_or_default();
+        Ok(graph.symbols(&ids))
+    }
+
+    /// The functions this one calls in its repo, most likely definitions first.
+    async fn callees(&self, ctx: &Context<'_>) -> Result<Vec<Symbol>> {
+        let graph = repo_graph(ctx, &self.0.repo_id).await?;
+        let Some(function) = graph.calls.functions.iter().find(|f| f.id == self.0.id) else {
+            return Ok(Vec::new());
+        };
+        let ids: Vec<String> = function
+            .calls
+            .iter()
+            .flat_map(|call| &call.candidates)
+            .filter(|c| c.repo_id.is_none())
+            .map(|c| c.id.clone())
+            .collect();
+        Ok(graph.symbols(&ids))
+    }
+
+    /// The call sites of this function, as `/references` lists them.
+    async fn references(&self, ctx: &Context<'_>) -> Result<Vec<Reference>> {
+        let graph = repo_graph(ctx, &self.0.repo_id).await?;
+        let usages = callgraph::references(&graph.calls, &graph.nodes, &self.0.name);
+        let Some(usage) = usages.into_iter().find(|u| u.id == self.0.id) else {
+            return Ok(Vec::new());
+        };
+        Ok(usage
+            .references
+            .into_iter()
+            .filter_map(|r| {
+                let caller = graph.node(&r.id)?.clone();
+                Some(Reference {
+                    caller: Symbol(caller),
+                    lines: r.lines,
+                    confidence: r.confidence,
+                })
+            })
+            .collect())
+    }
+}
+
+/// A function calling a definition.
+pub struct Reference {
+    caller: Symbol,
+    lines: Vec<usize>,
+    confidence: f32,
+}
+
+#[Object]
+impl Reference {
+    async fn caller(&self) -> &Symbol {
+        &self.caller
+    }
+
+    /// 1-based lines of the calls in the caller's file.
+    async fn lines(&self) -> &[usize] {
+        &self.lines
+    }
+
+    async fn confidence(&self) -> f32 {
+        self.confidence
+    }
+}
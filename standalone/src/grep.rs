@@ -0,0 +1,162 @@
+use crate::storage::NodeRecord;
+use anyhow::{bail, Context, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// The kinds of the nodes indexed for `/grep` when `MESH_INDEX_TEXT` is on.
+pub const COMMENT_KIND: &str = "Comment";
+pub const STRING_KIND: &str = "StringLiteral";
+
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+/// Longest pattern accepted, in bytes.
+pub const MAX_PATTERN_LEN: usize = 1024;
+/// What a compiled regex, and each of its lazy DFAs, may grow to.
+pub const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// How long one search may run before it returns what it has.
+pub const SEARCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The node kinds holding comments and string literals in one grammar of
+/// [`GRAMMARS`](crate::captures::GRAMMARS).
+#[derive(Debug, Clone)]
+pub struct TextRule {
+    pub grammar: &'static str,
+    pub comments: &'static [&'static str],
+    pub strings: &'static [&'static str],
+}
+
+pub const TEXT_RULES: &[TextRule] = &[
+    TextRule {
+        grammar: "rust",
+        comments: &["line_comment", "block_comment"],
+        strings: &["string_literal", "raw_string_literal"],
+    },
+    TextRule {
+        grammar: "python",
+        comments: &["comment"],
+        strings: &["string"],
+    },
+    TextRule {
+        grammar: "go",
+        comments: &["comment"],
+        strings: &["interpreted_string_literal", "raw_string_literal"],
+    },
+    TextRule {
+        grammar: "typescript",
+        comments: &["comment"],
+        strings: &["string", "template_string"],
+    },
+    TextRule {
+        grammar: "tsx",
+        comments: &["comment"],
+        strings: &["string", "template_string"],
+    },
+    TextRule {
+        grammar: "java",
+        comments: &["line_comment", "block_comment"],
+        strings: &["string_literal"],
+    },
+    TextRule {
+        grammar: "ruby",
+        comments: &["comment"],
+        strings: &["string"],
+    },
+];
+
+impl TextRule {
+    /// The tree-sitter query matching each of `kinds` as a whole `@name`.
+    pub fn query(kinds: &[&str]) -> String {
+        let alternatives: Vec<String> = kinds.iter().map(|k| format!("({})", k)).collect();
+        format!("[{}] @name", alternatives.join(" "))
+    }
+}
+
+/// What a line has to contain to be a hit.
+pub enum Matcher {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// A regex is compiled within [`REGEX_SIZE_LIMIT`]; matching one is
+    /// linear in the text, so a pattern can't blow up the search, only fail
+    /// to compile.
+    pub fn new(pattern: &str, regex: bool) -> Result<Self> {
+        if pattern.is_empty() {
+            bail!("pattern must not be empty");
+        }
+        if pattern.len() > MAX_PATTERN_LEN {
+            bail!("pattern is longer than {} bytes", MAX_PATTERN_LEN);
+        }
+        if !regex {
+            return Ok(Matcher::Substring(pattern.to_string()));
+        }
+        let regex = RegexBuilder::new(pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .dfa_size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .with_context(|| format!("invalid regex {}", pattern))?;
+        Ok(Matcher::Regex(regex))
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            Matcher::Substring(needle) => line.contains(needle.as_str()),
+            Matcher::Regex(regex) => regex.is_match(line),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GrepHit {
+    pub file: String,
+    /// 1-based.
+    pub line: usize,
+    /// [`COMMENT_KIND`] or [`STRING_KIND`].
+    pub kind: String,
+    /// The matching line, trimmed.
+    pub text: String,
+    /// The comment or string the line is in.
+    pub id: String,
+}
+
+/// Every line of the indexed comments and strings among `nodes` that
+/// `matcher` matches, by file and line, up to `limit` of them. Also returns
+/// whether there may be more: the limit was reached, or the search ran past
+/// `timeout` and stopped early.
+pub fn grep(
+    nodes: &[NodeRecord],
+    matcher: &Matcher,
+    limit: usize,
+    timeout: Duration,
+) -> (Vec<GrepHit>, bool) {
+    let deadline = Instant::now() + timeout;
+    let mut text: Vec<&NodeRecord> = nodes
+        .iter()
+        .filter(|n| n.kind == COMMENT_KIND || n.kind == STRING_KIND)
+        .collect();
+    text.sort_by(|a, b| a.file.cmp(&b.file).then(a.start.cmp(&b.start)));
+    let mut hits = Vec::new();
+    for node in text {
+        if Instant::now() > deadline {
+            return (hits, true);
+        }
+        for (i, line) in node.body.lines().enumerate() {
+            if !matcher.is_match(line) {
+                continue;
+            }
+            if hits.len() == limit {
+                return (hits, true);
+            }
+            hits.push(GrepHit {
+                file: node.file.clone(),
+                line: node.start + i + 1,
+                kind: node.kind.clone(),
+                text: line.trim().to_string(),
+                id: node.id.clone(),
+            });
+        }
+    }
+    (hits, false)
+}
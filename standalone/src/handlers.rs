@@ -1,202 +1,4831 @@
+use crate::analysis::{self, EntryPoints};
+use crate::annotations;
+use crate::archive;
+use crate::audit;
+use crate::auth::{self, Scope};
+use crate::buffers;
+use crate::callgraph;
+use crate::captures::{Grammar, GRAMMARS};
+use crate::checkpoints;
+use crate::clone::{self, Credentials};
+use crate::complexity;
+use crate::components;
+use crate::confirm;
+use crate::consistency::{self, ConsistencyReport};
+use crate::coverage;
+use crate::docs;
+use crate::embeddings;
+use crate::encoding;
+use crate::events::{Added, IngestSummary, Progress, StatusEvent};
+use crate::export::{self, CytoscapeOptions, DotOptions, MermaidOptions};
+use crate::filter::{self, FileFilter, KindFilter};
+use crate::grammars;
+use crate::grep;
+use crate::hierarchy;
+use crate::imports;
+use crate::ingests::{Cancel, IngestGuard};
+use crate::interfaces;
+use crate::lang::{Diagnostic, Extraction, Severity};
+use crate::limits;
+use crate::local;
+use crate::logging;
+use crate::msgpack::Format;
+use crate::neighborhood;
+use crate::pipeline::Pipeline;
+use crate::projects;
+use crate::query::{self, PageError};
+use crate::report::{self, ReportFormat};
+use crate::schedule;
+use crate::search;
+use crate::snapshots;
+use crate::snippet;
+use crate::sql;
+use crate::stats;
+use crate::storage::{
+    self, cache::CachedGraph, cache::Inserted, records_from_graph, records_from_plugins,
+    repo_relative, same_file, stored_paths, EdgeRecord, NodeRecord, RepoRecord, RowStream,
+    SnapshotRecord, Span, Transaction,
+};
+use crate::symbols::{self, SymbolIndex};
+use crate::tasks::{self, TaskRules};
 use crate::types::{
-    AppError, FetchRepoBody, FetchRepoResponse, ProcessBody, ProcessResponse, Result,
+    ArchiveParams, AuditParams, AuditResponse, BulkRegisterBody, BulkRegisterResponse,
+    CallChainBody, CallChainResponse, CallGraphBody, CancelBody, CancelResponse,
+    ChangedSymbolsBody, ChangedSymbolsResponse, ClearBody, ClearTokenQuery, ClearTokenResponse,
+    CompactResponse, CoverageBody, CoveredByResponse, CyclesBody, CyclesResponse, DeadCodeBody,
+    DeadCodeResponse, DependenciesBody, DependenciesResponse, DiagnosticsParams,
+    DiagnosticsResponse, DiffBody, DiffResponse, ExportCytoscapeParams, ExportDotParams,
+    ExportJsonParams, ExportMermaidParams, FetchRepoBody, FetchRepoResponse, GrepBody,
+    GrepResponse, HierarchyBody, HierarchyResponse, ImportJsonParams, IngestPathBody, MeshError,
+    NeighborhoodBody, NeighborhoodResponse, ParseStreamParams, ParseTreeBody, ParseTreeResponse,
+    PinBody, PinResponse, ProcessBody, ProcessFileBody, ProcessFileResponse, ProcessResponse,
+    Provenance, QueryBody, QueryResponse, ReanalyzeBody, ReanalyzeResponse, ReferencesBody,
+    ReferencesResponse, RegisteredRepo, RelatedBody, RelatedResponse, RepoLogParams,
+    RepoLogResponse, RepoSummary, ReposResponse, ResolveBody, ResolveResponse, Result,
+    ScheduleBody, ScheduleResponse, SearchBody, SearchResponse, SnapshotBody, SnapshotResponse,
+    SnapshotsQuery, SnapshotsResponse, SnippetBody, SnippetResponse, StatsBody, StatsResponse,
+    TestsForResponse, ValidateBody, WarmBody, WarmResponse, WebhookResponse,
 };
+use crate::typing;
+use crate::visibility;
+use crate::webhook::{self, Delivery, Push};
 use crate::AppState;
-use ast::lang::graphs::graph_ops::GraphOps;
-use ast::lang::Graph;
+use ast::lang::graphs::BTreeMapGraph;
+use ast::lang::NodeType;
 use ast::repo::Repo;
-use axum::{extract::State, Json};
-use lsp::git::get_commit_hash;
-use std::{sync::Arc, time::Instant};
-use tracing::info;
+use axum::body::{Body, Bytes};
+use axum::extract::{FromRequest, Multipart, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use futures::future::{join_all, ready};
+use futures::stream::{self, StreamExt};
+use lsp::git::{get_changed_files_between, get_commit_hash};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
 #[axum::debug_handler]
-pub async fn process(body: Json<ProcessBody>) -> Result<Json<ProcessResponse>> {
-    let (final_repo_path, final_repo_url, username, pat) = resolve_repo(&body)?;
+#[cfg_attr(
+    feature = "otel",
+    tracing::instrument(
+        skip_all,
+        fields(
+            repo_url = ?body.repo_url.as_deref().map(clone::without_credentials),
+            repo_path = ?body.repo_path
+        )
+    )
+)]
+pub async fn process(
+    State(state): State<Arc<AppState>>,
+    body: Json<ProcessBody>,
+) -> Result<Json<ProcessResponse>> {
+    let repo_id = ingest_id(&state, &body);
+    audit::note_repo(&repo_id);
+    let _permit = limits::ingest_permit(&state.ingest_slots)?;
+    let timer = state.metrics.ingest_timer();
+    let response = logging::ingest(
+        &repo_id,
+        body.git_ref.as_deref(),
+        process_repo(&state, &body),
+    )
+    .await?;
+    if body.dry_run {
+        timer.discard();
+    } else {
+        timer.succeeded();
+    }
+    Ok(Json(response))
+}
+
+/// The work behind `/process`, also run for webhook pushes.
+async fn process_repo(state: &Arc<AppState>, body: &ProcessBody) -> Result<ProcessResponse> {
+    let (final_repo_path, final_repo_url, credentials) = resolve_repo(state, body)?;
+    let mut filter = file_filter(state, body)?;
+    // a clone is removed once the last ingest using it is done
+    let _lease = state.clones.lease(&final_repo_path);
 
     let total_start = Instant::now();
 
     let repo_path = &final_repo_path;
     let repo_url = &final_repo_url;
+    let repo_id = scoped_repo_id(body, repo_url, repo_path);
+    let hash_key = subdir_hash_key(
+        &storage::with_ref(repo_url, body.git_ref.as_deref()),
+        filter.subdir(),
+    );
+    fetch_checkout(state, body, repo_url, repo_path, &credentials).await?;
 
     let current_hash = match get_commit_hash(&repo_path).await {
         Ok(hash) => hash,
-        Err(e) => {
-            return Err(AppError::Anyhow(anyhow::anyhow!(
-                "Could not get current hash: {}",
-                e
-            )))
-        }
+        Err(e) => return Err(MeshError::Git(format!("Could not get current hash: {}", e))),
     };
 
-    let mut graph_ops = GraphOps::new();
-    graph_ops.connect().await?;
-
-    let stored_hash = match graph_ops.graph.get_repository_hash(&repo_url).await {
-        Ok(hash) => Some(hash),
-        Err(_) => None,
-    };
+    // held from the first read of the stored graph, so a clear can't come
+    // between what's read here and the recovery and deletions based on it
+    let _lock = state.repo_locks.write(Some(&repo_id)).await;
+    let stored_hash = state
+        .storage
+        .repo_hash(&hash_key)
+        .await
+        .map_err(MeshError::Storage)?;
 
     info!(
         "Current hash: {} | Stored hash: {:?}",
         current_hash, stored_hash
     );
 
-    if let Some(hash) = &stored_hash {
+    let interrupted =
+        !body.dry_run && recover_interrupted(state, body, &repo_id, repo_path).await?;
+    // nodes parsed with another grammar aren't mixed with the ones stored
+    let drifted = grammars::check(state.storage.as_ref(), &repo_id)
+        .await
+        .map_err(MeshError::Storage)?;
+    if !drifted.is_empty() {
+        let changed: Vec<String> = drifted.iter().map(|m| m.to_string()).collect();
+        send_status(
+            state,
+            &repo_id,
+            "grammar_changed",
+            format!(
+                "Grammars changed since {} was parsed ({}), parsing every file again",
+                repo_id,
+                changed.join(", ")
+            ),
+        );
+    }
+    let force = body.force || !drifted.is_empty();
+    if let Some(hash) = stored_hash
+        .as_ref()
+        .filter(|_| !force && !body.dry_run && !interrupted)
+    {
         if hash == &current_hash {
-            let (nodes, edges) = graph_ops.graph.get_graph_size();
-            return Ok(Json(ProcessResponse {
+            let (nodes, edges) = state
+                .storage
+                .graph_size(Some(&repo_id))
+                .await
+                .map_err(MeshError::Storage)?;
+            return Ok(ProcessResponse {
                 status: "success".to_string(),
                 message: "Repository already processed".to_string(),
-                nodes: nodes as usize,
-                edges: edges as usize,
-            }));
+                nodes,
+                edges,
+            });
         }
     }
 
-    let (nodes, edges) = if let Some(hash) = stored_hash {
+    let changed = if let Some(hash) = &stored_hash {
         info!("Updating repository hash from {} to {}", hash, current_hash);
-        graph_ops
-            .update_incremental(
-                &repo_url,
-                username.clone(),
-                pat.clone(),
-                &current_hash,
-                &hash,
-                None,
-            )
-            .await?
+        get_changed_files_between(&repo_path, hash, &current_hash)
+            .await
+            .map_err(|e| {
+                MeshError::Git(format!("Could not diff {}..{}: {}", hash, current_hash, e))
+            })?
     } else {
         info!("Adding new repository hash: {}", current_hash);
-        graph_ops
-            .update_full(
-                &repo_url,
-                username.clone(),
-                pat.clone(),
-                &current_hash,
-                None,
-            )
-            .await?
+        Vec::new()
+    };
+    let candidates = if drifted.is_empty() {
+        changed.clone()
+    } else {
+        Vec::new()
+    };
+    let (files, skipped) = select_files(
+        state,
+        &mut filter,
+        &repo_id,
+        repo_url,
+        repo_path,
+        &credentials,
+        candidates,
+    )
+    .await?;
+    let pristine = is_clone(state, repo_url, repo_path);
+    let cached = skip_cached(state, &repo_id, repo_path, files, force, pristine).await?;
+    // what changed outside `subdir` is left for an ingest of it
+    let stale = changed
+        .iter()
+        .filter(|f| !cached.unchanged.contains(*f) && filter.in_subdir(f));
+    for file in stale.filter(|_| !body.dry_run) {
+        delete_stored_file(state, &repo_id, repo_path, file).await?;
+    }
+    if !cached.unchanged.is_empty() && cached.parse.is_empty() {
+        if !body.dry_run {
+            state
+                .storage
+                .set_repo_hash(&hash_key, &current_hash)
+                .await
+                .map_err(MeshError::Storage)?;
+        }
+        let (nodes, edges) = state
+            .storage
+            .graph_size(Some(&repo_id))
+            .await
+            .map_err(MeshError::Storage)?;
+        return Ok(ProcessResponse {
+            status: "success".to_string(),
+            message: format!(
+                "All {} files unchanged since they were last parsed",
+                cached.unchanged.len()
+            ),
+            nodes,
+            edges,
+        });
+    }
+    let files = cached.parse;
+
+    if files.is_empty() && !filter.is_empty() {
+        // an empty list would make `ast` parse everything
+        if !body.dry_run {
+            state
+                .storage
+                .set_repo_hash(&hash_key, &current_hash)
+                .await
+                .map_err(MeshError::Storage)?;
+        }
+        let (nodes, edges) = state
+            .storage
+            .graph_size(Some(&repo_id))
+            .await
+            .map_err(MeshError::Storage)?;
+        return Ok(ProcessResponse {
+            status: "success".to_string(),
+            message: "No changed files match the filters".to_string(),
+            nodes,
+            edges,
+        });
+    }
+
+    let total = if files.is_empty() {
+        count_files(repo_path).await?
+    } else {
+        Some(files.len())
     };
+    let progress = Progress::new(state.tx.clone(), Some(&repo_id), total);
+    let ingest = state.ingests.start(&repo_id, &state.shutdown);
+    if !body.dry_run {
+        checkpoints::start(state.storage.as_ref(), &repo_id, &current_hash)
+            .await
+            .map_err(MeshError::Storage)?;
+    }
+    let checkpoint: HashMap<String, String> = cached.hashes.iter().cloned().collect();
+
+    let graph = build_graph(
+        state,
+        &progress,
+        ingest.token(),
+        graph_source(state, body, repo_url, repo_path),
+        repo_path,
+        &credentials,
+        files.clone(),
+    )
+    .await?;
+    let written = write_graph(
+        state,
+        &progress,
+        ingest.token(),
+        &graph,
+        repo_path,
+        &repo_id,
+        &files,
+        &skipped,
+        &KindFilter::new(&body.node_kinds),
+        body.dry_run,
+        Some(&checkpoint),
+    )
+    .await?;
+    if body.dry_run {
+        ingest.succeeded();
+        return Ok(dry_run_response(written.nodes, written.edges));
+    }
+    // files that failed to store are parsed again next time
+    let hashes: Vec<(String, String)> = cached
+        .hashes
+        .into_iter()
+        .filter(|(key, _)| !written.failed.iter().any(|f| local::covers(key, f)))
+        .collect();
+    state
+        .storage
+        .set_file_hashes(&repo_id, &hashes)
+        .await
+        .map_err(MeshError::Storage)?;
+    checkpoints::finish(state.storage.as_ref(), &repo_id)
+        .await
+        .map_err(MeshError::Storage)?;
+    if written.failed.is_empty() {
+        state
+            .storage
+            .set_repo_hash(&hash_key, &current_hash)
+            .await
+            .map_err(MeshError::Storage)?;
+    }
+    finish_ingest(
+        state,
+        &progress,
+        ingest,
+        &repo_id,
+        repo_url,
+        repo_path,
+        &written,
+        total_start,
+    )
+    .await?;
+
     info!(
         "\n\n ==>> Total processing time: {:.2?} \n\n",
         total_start.elapsed()
     );
 
-    Ok(Json(ProcessResponse {
+    Ok(ProcessResponse {
         status: "success".to_string(),
-        message: "Repository processed successfully".to_string(),
-        nodes: nodes as usize,
-        edges: edges as usize,
-    }))
+        message: stored_message("Repository processed successfully", &written.failed),
+        nodes: written.nodes,
+        edges: written.edges,
+    })
 }
 
-pub async fn clear_graph() -> Result<Json<ProcessResponse>> {
-    let mut graph_ops = GraphOps::new();
-    graph_ops.connect().await?;
-    let (nodes, edges) = graph_ops.clear().await?;
-    Ok(Json(ProcessResponse {
-        status: "success".to_string(),
-        message: "Graph cleared".to_string(),
-        nodes: nodes as usize,
-        edges: edges as usize,
-    }))
+/// Picks up after an ingest of `repo_id` that was cut short, returning
+/// whether there was one. The files it checkpointed are taken as parsed, so
+/// only the rest are parsed again; with `discard_interrupted` they're
+/// removed instead, and parsed again whatever their hashes say. A `force`d
+/// ingest parses everything again anyway.
+async fn recover_interrupted(
+    state: &AppState,
+    body: &ProcessBody,
+    repo_id: &str,
+    repo_path: &str,
+) -> Result<bool> {
+    let Some(checkpoint) = checkpoints::interrupted(state.storage.as_ref(), repo_id)
+        .await
+        .map_err(MeshError::Storage)?
+    else {
+        return Ok(false);
+    };
+    let files: Vec<(String, String)> = checkpoint.files.into_iter().collect();
+    if body.discard_interrupted {
+        for (file, _) in &files {
+            delete_stored_file(state, repo_id, repo_path, file).await?;
+        }
+        let forgotten: Vec<(String, String)> = files
+            .iter()
+            .map(|(f, _)| (f.clone(), String::new()))
+            .collect();
+        state
+            .storage
+            .set_file_hashes(repo_id, &forgotten)
+            .await
+            .map_err(MeshError::Storage)?;
+        checkpoints::finish(state.storage.as_ref(), repo_id)
+            .await
+            .map_err(MeshError::Storage)?;
+        send_status(
+            state,
+            repo_id,
+            "rolled_back",
+            format!(
+                "Rolled back the interrupted ingest of {} at {}, removing the {} files it stored",
+                repo_id,
+                checkpoint.commit,
+                files.len()
+            ),
+        );
+    } else if !body.force {
+        state
+            .storage
+            .set_file_hashes(repo_id, &files)
+            .await
+            .map_err(MeshError::Storage)?;
+        send_status(
+            state,
+            repo_id,
+            "resumed",
+            format!(
+                "Resuming the interrupted ingest of {} at {}, {} files were already stored",
+                repo_id,
+                checkpoint.commit,
+                files.len()
+            ),
+        );
+    }
+    Ok(true)
 }
 
-pub async fn fetch_repo(body: Json<FetchRepoBody>) -> Result<Json<FetchRepoResponse>> {
-    let mut graph_ops = GraphOps::new();
-    graph_ops.connect().await?;
-    let repo_node = graph_ops.fetch_repo(&body.repo_name).await?;
-    Ok(Json(FetchRepoResponse {
-        status: "success".to_string(),
-        repo_name: repo_node.name,
-    }))
+/// `message`, or which files weren't stored when some failed.
+fn stored_message(message: &str, failed: &[String]) -> String {
+    match failed {
+        [] => message.to_string(),
+        [file] => format!("Stored all files except {}, which failed", file),
+        files => format!(
+            "Stored all files except {} that failed: {}",
+            files.len(),
+            files.join(", ")
+        ),
+    }
+}
+
+/// What a read-only server answers every route that writes with.
+pub async fn read_only() -> MeshError {
+    MeshError::ReadOnly(
+        "This server is read-only; send writes to the one serving the primary backend".to_string(),
+    )
+}
+
+/// Re-ingests a repo when GitHub or GitLab reports a push to its default
+/// branch, or to another branch registered as one of its refs. The signature
+/// is checked before the payload is read at all; the work itself runs in the
+/// background once the delivery is accepted.
+pub async fn webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response> {
+    let Some(config) = state.webhook.clone() else {
+        return Err(MeshError::NotFound(
+            "Webhooks are not configured".to_string(),
+        ));
+    };
+    if !config.verify(&headers, &body) {
+        return Err(MeshError::Unauthorized(
+            "Invalid webhook signature".to_string(),
+        ));
+    }
+    let ignored = |message: String, repo_id: Option<String>| {
+        let response = WebhookResponse {
+            status: "ignored".to_string(),
+            message,
+            repo_id,
+            files: None,
+        };
+        Ok((StatusCode::ACCEPTED, Json(response)).into_response())
+    };
+    let push = match webhook::parse(&headers, &body).map_err(MeshError::Validation)? {
+        Delivery::Push(push) => push,
+        Delivery::Ignored(reason) => return ignored(reason, None),
+    };
+    if !config.is_configured(&push.repo_id) {
+        return Err(MeshError::NotFound(format!(
+            "{} is not configured for webhooks",
+            push.repo_id
+        )));
+    }
+    // another branch, or any branch when the payload names no default, is
+    // ingested only when it was fetched as a ref of its own
+    let git_ref =
+        (push.default_branch.as_deref() != Some(push.branch.as_str())).then(|| push.branch.clone());
+    let repo_id = storage::with_ref(&push.repo_id, git_ref.as_deref());
+    if git_ref.is_some() {
+        let repos = state.storage.repos().await.map_err(MeshError::Storage)?;
+        if !repos.iter().any(|r| r.repo_id == repo_id) {
+            let message = format!("pushes to {} are not ingested", push.branch);
+            return ignored(message, Some(push.repo_id));
+        }
+    }
+
+    let permit = limits::ingest_permit(&state.ingest_slots)?;
+    let response = WebhookResponse {
+        status: "queued".to_string(),
+        message: format!("Re-ingesting {} at {}", push.repo_id, push.branch),
+        repo_id: Some(repo_id.clone()),
+        files: push
+            .incremental()
+            .then(|| push.changed.len() + push.removed.len()),
+    };
+    tokio::spawn(logging::propagate(async move {
+        let _permit = permit;
+        let timer = state.metrics.ingest_timer();
+        let work = apply_push(&state, &push, git_ref.as_deref());
+        match logging::ingest(&push.repo_id, git_ref.as_deref(), work).await {
+            Ok(()) => timer.succeeded(),
+            Err(e) => {
+                error!("webhook re-ingest of {} failed: {}", repo_id, e);
+                send_status(&state, &repo_id, "webhook_failed", e.to_string());
+            }
+        }
+    }));
+    Ok((StatusCode::ACCEPTED, Json(response)).into_response())
+}
+
+/// Brings the checkout of the default branch, or of `git_ref`, up to date,
+/// then re-processes just the pushed files when the payload lists them all
+/// and the repo was ingested before, or diffs the whole repo as `/process`
+/// does otherwise.
+async fn apply_push(state: &Arc<AppState>, push: &Push, git_ref: Option<&str>) -> Result<()> {
+    let mut path = state
+        .clones
+        .path_for(&push.url)
+        .map_err(MeshError::Validation)?;
+    if let Some(git_ref) = git_ref {
+        path = clone::ref_path(&path, git_ref);
+    }
+    let _lease = state.clones.lease(&path);
+    let repo = ProcessBody {
+        repo_url: Some(push.url.clone()),
+        repo_path: Some(path.clone()),
+        git_ref: git_ref.map(str::to_string),
+        ..Default::default()
+    };
+    // `/process` diffs against whatever is checked out, so fetch first
+    clone_into(
+        state,
+        &push.url,
+        Path::new(&path),
+        &git_credentials(state, None, None, None)?,
+        git_ref,
+        &clone::CloneScope::full(),
+    )
+    .await?;
+
+    let hash_key = storage::with_ref(&push.url, git_ref);
+    let stored_hash = state
+        .storage
+        .repo_hash(&hash_key)
+        .await
+        .map_err(MeshError::Storage)?;
+    if !push.incremental() || stored_hash.is_none() {
+        process_repo(state, &repo).await?;
+        return Ok(());
+    }
+
+    let files = push
+        .removed
+        .iter()
+        .map(|f| (f, true))
+        .chain(push.changed.iter().map(|f| (f, false)));
+    for (file, deleted) in files {
+        let body = ProcessFileBody {
+            repo: repo.clone(),
+            file: file.clone(),
+            deleted,
+            content: None,
+        };
+        reprocess_file(state, &body).await?;
+    }
+    let hash = get_commit_hash(&path)
+        .await
+        .map_err(|e| MeshError::Git(format!("Could not get current hash: {}", e)))?;
+    state
+        .storage
+        .set_repo_hash(&hash_key, &hash)
+        .await
+        .map_err(MeshError::Storage)?;
+    Ok(())
 }
 
+/// Re-parses a single file and reconciles its subgraph with what is stored:
+/// nodes that still exist keep their identity, vanished ones are detached along
+/// with their edges, and new symbols are announced on the event stream.
 #[axum::debug_handler]
-pub async fn ingest(
+#[cfg_attr(
+    feature = "otel",
+    tracing::instrument(skip_all, fields(file = %body.file, deleted = body.deleted))
+)]
+pub async fn process_file(
     State(state): State<Arc<AppState>>,
-    body: Json<ProcessBody>,
-) -> Result<Json<ProcessResponse>> {
-    let start_total = Instant::now();
-    let (_final_repo_path, final_repo_url, username, pat) = resolve_repo(&body)?;
+    body: Json<ProcessFileBody>,
+) -> Result<Json<ProcessFileResponse>> {
+    reprocess_file(&state, &body).await.map(Json)
+}
 
-    let repo_url = final_repo_url.clone();
+async fn reprocess_file(
+    state: &Arc<AppState>,
+    body: &ProcessFileBody,
+) -> Result<ProcessFileResponse> {
+    let (repo_path, repo_url, credentials) = resolve_repo(state, &body.repo)?;
+    let file = relative_file(&body.file)?;
+    let repo_id = scoped_repo_id(&body.repo, &repo_url, &repo_path);
+    let _lock = state.repo_locks.write(Some(&repo_id)).await;
+    let start = Instant::now();
 
-    let start_build = Instant::now();
+    if body.deleted && body.content.is_some() {
+        return Err(MeshError::validation(
+            "a deleted file has no content; leave out one of 'deleted' and 'content'",
+        ));
+    }
+    if body.deleted {
+        let removed = delete_stored_file(state, &repo_id, &repo_path, &file).await?;
+        send_status(
+            state,
+            &repo_id,
+            "file_deleted",
+            format!("Removed {} nodes for deleted file {}", removed, file),
+        );
+        return Ok(ProcessFileResponse {
+            status: "success".to_string(),
+            file,
+            added: 0,
+            removed,
+            unchanged: 0,
+        });
+    }
 
-    let mut repos = Repo::new_clone_multi_detect(
-        &repo_url,
-        username.clone(),
-        pat.clone(),
-        Vec::new(),
-        Vec::new(),
-        None,
+    // an unsaved buffer is parsed in a checkout of its own, then stored as
+    // the repo's file
+    let scratch = match &body.content {
+        Some(content) => Some(buffer_checkout(&repo_path, &file, content).await?),
+        None => None,
+    };
+    let root = match &scratch {
+        Some(scratch) => scratch.path().display().to_string(),
+        None => repo_path.clone(),
+    };
+    if let Some(reason) = local::unparseable(Path::new(&root), &file, state.max_file_bytes) {
+        send_status(
+            state,
+            &repo_id,
+            "warning",
+            format!("Skipped {}: {}", file, reason),
+        );
+        let skipped = reason.diagnostic(Path::new(&root), &file);
+        store_skipped(state, &repo_id, &[skipped]).await?;
+        return Ok(ProcessFileResponse {
+            status: "skipped".to_string(),
+            file,
+            added: 0,
+            removed: 0,
+            unchanged: 0,
+        });
+    }
+
+    let progress = Progress::new(state.tx.clone(), Some(&repo_id), Some(1));
+    let source = match &scratch {
+        Some(_) => "",
+        None => graph_source(state, &body.repo, &repo_url, &repo_path),
+    };
+    let file_graph = build_graph(
+        state,
+        &progress,
+        &state.shutdown,
+        source,
+        &root,
+        &credentials,
+        vec![file.clone()],
     )
-    .await
-    .map_err(|e| anyhow::anyhow!("Repo detection failed: {}", e))?;
+    .await?;
+
+    let (nodes, mut edges) = records_from_graph(&file_graph, &repo_id);
+    let mut nodes = enrich(state, &root, nodes).await?;
+    let extracted = extract_plugins(state, &root, &[file.clone()]).await?;
+    report_timeouts(state, &repo_id, &extracted.timed_out);
+    store_diagnostics(state, &repo_id, &[file.clone()], &extracted.diagnostics).await?;
+    let (plugin_nodes, plugin_edges) = records_from_plugins(&extracted, &repo_id, &nodes, &root);
+    nodes.extend(plugin_nodes);
+    edges.extend(plugin_edges);
+    let project_edges;
+    (nodes, project_edges) = detect_projects(&root, &repo_id, nodes).await?;
+    edges.extend(project_edges);
+    let annotation_edges;
+    (nodes, annotation_edges) = detect_annotations(state, &root, &repo_id, nodes).await?;
+    edges.extend(annotation_edges);
+    let component_edges;
+    (nodes, component_edges) = detect_components(state, &root, &repo_id, nodes).await?;
+    edges.extend(component_edges);
+    if state.embedded_sql {
+        let sql_edges;
+        (nodes, sql_edges) = detect_sql(state, &root, &repo_id, nodes).await?;
+        edges.extend(sql_edges);
+    }
+    if let Some(rules) = &state.tasks {
+        let task_edges;
+        (nodes, task_edges) = detect_tasks(state, &root, &repo_id, nodes, rules.clone()).await?;
+        edges.extend(task_edges);
+    }
+    if state.interface_repos.contains(&repo_id) {
+        let interface_edges;
+        (nodes, interface_edges) = detect_interfaces(&root, &repo_id, nodes, &edges).await?;
+        edges.extend(interface_edges);
+    }
+    if let Some(redaction) = &state.redaction {
+        redaction.apply(&repo_id, &mut nodes, &mut edges);
+    }
+    if root != repo_path {
+        buffers::rebase(&mut nodes, &mut edges, &root, &repo_path);
+    }
+    let derived = derived_edges(state, &repo_id, &nodes, &edges, true).await?;
+    edges.extend(derived);
+    state.node_ids.apply(&repo_path, &mut nodes, &mut edges);
+    state.path_map.apply(&mut nodes);
+    embed(state, &repo_id, &mut nodes).await;
 
-    repos.set_status_tx(state.tx.clone()).await;
+    let paths = stored_paths(&repo_path, &file, &state.path_map);
+    let fresh: BTreeMap<&str, &NodeRecord> = nodes
+        .iter()
+        .filter(|n| paths.contains(&n.file))
+        .map(|n| (n.id.as_str(), n))
+        .collect();
+    let mut stored = HashSet::new();
+    for path in &paths {
+        let ids = state
+            .storage
+            .file_node_ids(&repo_id, path)
+            .await
+            .map_err(MeshError::Storage)?;
+        stored.extend(ids);
+    }
 
-    let btree_graph = repos
-        .build_graphs_inner::<ast::lang::graphs::BTreeMapGraph>()
+    let vanished: Vec<String> = stored
+        .iter()
+        .filter(|k| !fresh.contains_key(k.as_str()))
+        .cloned()
+        .collect();
+    if let Some(mut budget) = graph_budget(state, &repo_id, &[file.clone()]).await? {
+        // the file's stored nodes are rewritten in place; its stored edges
+        // can't be told apart, so they count as new
+        let new = fresh.keys().filter(|id| !stored.contains(**id)).count();
+        if let Err(reason) = budget.take(new, edges.len()) {
+            let rollback = budget.started_empty();
+            return Err(too_large(state, &progress, &repo_id, rollback, reason).await);
+        }
+    }
+    let tx = state.storage.begin().await.map_err(MeshError::Storage)?;
+    write_file(tx, &repo_id, &nodes, &edges, &vanished)
         .await
-        .map_err(|e| anyhow::anyhow!("Graph build failed: {}", e))?;
-    info!(
-        "\n\n ==>>Building BTreeMapGraph took {:.2?} \n\n",
-        start_build.elapsed()
-    );
-    let mut graph_ops = GraphOps::new();
-    graph_ops.connect().await?;
+        .map_err(MeshError::Storage)?;
+    let removed = vanished.len();
+    if body.content.is_some() {
+        mark_buffered(state, &body.repo, &repo_url, &repo_id, &file).await?;
+    }
+    state.storage.flush().await.map_err(MeshError::Storage)?;
+    state.metrics.files_parsed.inc();
+    state.metrics.nodes_written.add(nodes.len() as u64);
+    state.metrics.edges_written.add(edges.len() as u64);
+    progress.finish(format!("Reprocessed {}", file));
 
-    let start_upload = Instant::now();
+    let mut added = 0;
+    for (id, node) in &fresh {
+        if !stored.contains(*id) {
+            added += 1;
+            send_status(
+                state,
+                &repo_id,
+                "node_added",
+                format!("{} {} added in {}", node.kind, node.name, file),
+            );
+        }
+    }
 
-    graph_ops.graph.clear().await?;
+    info!(
+        "Reprocessed {} in {:.2?}: {} added, {} removed",
+        file,
+        start.elapsed(),
+        added,
+        removed
+    );
 
-    let (nodes, edges) = graph_ops.upload_btreemap_to_neo4j(&btree_graph).await?;
-    graph_ops.graph.create_indexes().await?;
+    Ok(ProcessFileResponse {
+        status: "success".to_string(),
+        unchanged: fresh.len() - added,
+        file,
+        added,
+        removed,
+    })
+}
 
-    info!(
-        "\n\n ==>> Uploading to Neo4j took {:.2?} \n\n",
-        start_upload.elapsed()
+/// [`buffers::checkout`] of `content` as `file` of the repo at `repo_path`.
+async fn buffer_checkout(repo_path: &str, file: &str, content: &str) -> Result<TempDir> {
+    let (root, file, content) = (
+        PathBuf::from(repo_path),
+        file.to_string(),
+        content.to_string(),
     );
+    let scratch = tokio::task::spawn_blocking(move || buffers::checkout(&root, &file, &content))
+        .await
+        .map_err(|e| anyhow::anyhow!("Writing the buffer panicked: {}", e))??;
+    Ok(scratch)
+}
 
-    info!(
-        "\n\n ==>> Total ingest time: {:.2?} \n\n",
-        start_total.elapsed()
+/// Makes the next ingest from disk parse `file` again, though neither it
+/// nor the repo's commit has changed since: the graph now holds a buffer of
+/// it instead.
+async fn mark_buffered(
+    state: &AppState,
+    body: &ProcessBody,
+    repo_url: &str,
+    repo_id: &str,
+    file: &str,
+) -> Result<()> {
+    state
+        .storage
+        .set_file_hashes(
+            repo_id,
+            &[(file.to_string(), buffers::BUFFER_HASH.to_string())],
+        )
+        .await
+        .map_err(MeshError::Storage)?;
+    let hash_key = subdir_hash_key(
+        &storage::with_ref(repo_url, body.git_ref.as_deref()),
+        body.subdir.as_deref(),
     );
+    state
+        .storage
+        .set_repo_hash(&hash_key, buffers::BUFFER_HASH)
+        .await
+        .map_err(MeshError::Storage)
+}
 
+/// Clears one repo's subgraph when `repo_id` is given, otherwise everything.
+/// With a `ref` only that ref's graph goes; the repo's other refs stay.
+/// The counts returned are what remains in the cleared scope.
+/// Nothing is cleared without a confirmation: `confirm` naming the repo
+/// again, or `ALL`, or a token from [`clear_token`] for the same scope.
+pub async fn clear_graph(
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<ClearBody>>,
+) -> Result<Json<ProcessResponse>> {
+    let body = body.map(|b| b.0).unwrap_or_default();
+    let repo_id = ref_scope(body.repo_id.as_deref(), body.git_ref.as_deref(), "repo_id")?;
+    if let Some(repo_id) = &repo_id {
+        audit::note_repo(repo_id);
+    }
+    // taken before a token is redeemed, so one refused here can be used again
+    let _lock = match state.repo_locks.try_write(repo_id.as_deref()) {
+        Some(lock) => lock,
+        None if body.wait => state.repo_locks.write(repo_id.as_deref()).await,
+        None => {
+            return Err(MeshError::Conflict(format!(
+            "{} is being ingested or cleared; retry once it's done, or set 'wait' to wait for it",
+            repo_id.as_deref().unwrap_or("a repo")
+        )))
+        }
+    };
+    let expected = body.repo_id.as_deref().unwrap_or(confirm::ALL);
+    match (&body.confirm, &body.token) {
+        (Some(confirm), _) if confirm == expected => {}
+        (Some(confirm), _) => {
+            return Err(MeshError::Validation(format!(
+                "'confirm' is '{}', but clearing {} needs it to be '{}'",
+                confirm,
+                repo_id.as_deref().unwrap_or("every repo"),
+                expected
+            )))
+        }
+        (None, Some(token)) if state.clear_tokens.redeem(token, repo_id.as_deref()) => {}
+        (None, Some(_)) => {
+            return Err(MeshError::validation(
+                "the confirmation token is unknown, used, expired or for another repo",
+            ))
+        }
+        (None, None) => {
+            return Err(MeshError::Validation(format!(
+                "clearing needs a confirmation: set 'confirm' to '{}', or 'token' to one from GET /clear",
+                expected
+            )))
+        }
+    }
+    let (nodes, edges) = state
+        .storage
+        .clear(repo_id.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    if let Some(repo_id) = &repo_id {
+        checkpoints::finish(state.storage.as_ref(), repo_id)
+            .await
+            .map_err(MeshError::Storage)?;
+    }
+    let message = match &repo_id {
+        Some(repo_id) => format!("Graph cleared for {}", repo_id),
+        None => "Graph cleared".to_string(),
+    };
     Ok(Json(ProcessResponse {
         status: "success".to_string(),
-        message: "Repository ingested fully".to_string(),
-        nodes: nodes as usize,
-        edges: edges as usize,
+        message,
+        nodes,
+        edges,
     }))
 }
 
-fn env_not_empty(key: &str) -> Option<String> {
-    std::env::var(key).ok().filter(|v| !v.is_empty())
+/// A token confirming a clear of `repo_id`, or of everything, for
+/// [`confirm::TOKEN_TTL`].
+pub async fn clear_token(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ClearTokenQuery>,
+) -> Result<Json<ClearTokenResponse>> {
+    let repo_id = ref_scope(
+        query.repo_id.as_deref(),
+        query.git_ref.as_deref(),
+        "repo_id",
+    )?;
+    Ok(Json(ClearTokenResponse {
+        token: state.clear_tokens.issue(repo_id.as_deref()),
+        repo_id,
+        expires_in_secs: confirm::TOKEN_TTL.as_secs(),
+    }))
 }
-fn resolve_repo(body: &ProcessBody) -> Result<(String, String, Option<String>, Option<String>)> {
-    let repo_path = body
-        .repo_path
-        .clone()
-        .or_else(|| env_not_empty("REPO_PATH"));
-    let repo_url = body.repo_url.clone().or_else(|| env_not_empty("REPO_URL"));
-    let username = body.username.clone().or_else(|| env_not_empty("USERNAME"));
-    let pat = body.pat.clone().or_else(|| env_not_empty("PAT"));
 
-    if repo_path.is_none() && repo_url.is_none() {
-        return Err(AppError::Anyhow(anyhow::anyhow!(
-            "Neither REPO_PATH nor REPO_URL is set in the body or environment"
+/// Checks a repo's graph for dangling edges, orphan nodes and duplicated
+/// node ids, and with `repair` removes the dangling edges and duplicates.
+pub async fn validate(
+    State(state): State<Arc<AppState>>,
+    body: Json<ValidateBody>,
+) -> Result<Json<ConsistencyReport>> {
+    let repo_id = storage::with_ref(&body.repo_id, body.git_ref.as_deref());
+    let report = consistency::check(
+        state.storage.as_ref(),
+        &repo_id,
+        body.repair,
+        consistency::DEFAULT_CHUNK,
+    )
+    .await
+    .map_err(MeshError::Storage)?;
+    if let Some(repaired) = report.repaired {
+        send_status(
+            &state,
+            &repo_id,
+            "repaired",
+            format!(
+                "Removed {} dangling edges and {} duplicate nodes",
+                repaired.edges_removed, repaired.nodes_removed
+            ),
+        );
+    }
+    Ok(Json(report))
+}
+
+/// Every repo with a stored ingest or one started since startup, with the
+/// size of its graph and whether an ingest of it is running now.
+pub async fn list_repos(State(state): State<Arc<AppState>>) -> Result<Json<ReposResponse>> {
+    let mut records: BTreeMap<String, RepoRecord> = state
+        .storage
+        .repos()
+        .await
+        .map_err(MeshError::Storage)?
+        .into_iter()
+        .map(|record| (record.repo_id.clone(), record))
+        .collect();
+    for repo_id in state.ingests.repo_ids() {
+        records
+            .entry(repo_id.clone())
+            .or_insert_with(|| RepoRecord {
+                repo_id,
+                ..RepoRecord::default()
+            });
+    }
+    let mut repos = Vec::new();
+    for (repo_id, record) in records {
+        let (nodes, edges) = state
+            .storage
+            .graph_size(Some(&repo_id))
+            .await
+            .map_err(MeshError::Storage)?;
+        let status = state.ingests.status(&repo_id);
+        repos.push(RepoSummary {
+            repo_id,
+            url: Some(record.url).filter(|url| !url.is_empty()),
+            git_ref: record.git_ref,
+            commit: record.commit,
+            last_ingested_at: Some(record.ingested_at).filter(|at| *at > 0),
+            nodes,
+            edges,
+            status,
+        });
+    }
+    Ok(Json(ReposResponse { repos }))
+}
+
+/// The events of the last ingest of a repo, for a client that wasn't
+/// following `/events` while it ran: those sent since this server started,
+/// or else the log stored when the ingest completed. With
+/// `Accept: application/x-ndjson` they're streamed one per line.
+pub async fn repo_log(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<RepoLogParams>,
+) -> Result<Response> {
+    let events: Vec<serde_json::Value> = match state.tx.ingest_log(&params.repo) {
+        Some(events) => events.iter().map(StatusEvent::to_log_json).collect(),
+        None => state
+            .storage
+            .ingest_log(&params.repo)
+            .await
+            .map_err(MeshError::Storage)?
+            .iter()
+            .filter_map(|event| serde_json::from_str(event).ok())
+            .collect(),
+    };
+    if events.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No ingest log kept for {}",
+            params.repo
+        )));
+    }
+    let streamed = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(NDJSON));
+    if streamed {
+        let lines = events
+            .into_iter()
+            .map(|event| Ok::<_, std::convert::Infallible>(format!("{}\n", event)));
+        let body = Body::from_stream(stream::iter(lines));
+        return Ok(([(header::CONTENT_TYPE, NDJSON)], body).into_response());
+    }
+    let status = state.ingests.status(&params.repo);
+    Ok(Json(RepoLogResponse {
+        repo: params.repo,
+        status,
+        events,
+    })
+    .into_response())
+}
+
+/// Stops the running ingest of a repo at its next file boundary.
+pub async fn cancel(
+    State(state): State<Arc<AppState>>,
+    body: Json<CancelBody>,
+) -> Result<Json<CancelResponse>> {
+    match state.ingests.cancel(&body.repo_id) {
+        Cancel::Cancelled => Ok(Json(CancelResponse {
+            status: "cancelling".to_string(),
+            repo_id: body.repo_id.clone(),
+        })),
+        Cancel::Finished => Err(MeshError::Conflict(format!(
+            "No ingest of {} is running",
+            body.repo_id
+        ))),
+        Cancel::Unknown => Err(MeshError::NotFound(format!(
+            "No ingest of {} was started",
+            body.repo_id
+        ))),
+    }
+}
+
+/// Re-ingests a repo every `interval_secs`, fetching it first as `/process`
+/// does, or stops doing so with `remove`. Registering a repo again replaces
+/// its interval.
+pub async fn schedule(
+    State(state): State<Arc<AppState>>,
+    body: Json<ScheduleBody>,
+) -> Result<Json<ScheduleResponse>> {
+    let (repo_path, repo_url, _) = resolve_repo(&state, &body.repo)?;
+    let repo_id = scoped_repo_id(&body.repo, &repo_url, &repo_path);
+    if body.remove {
+        if !state.schedules.remove(&repo_id) {
+            return Err(MeshError::NotFound(format!("{} is not scheduled", repo_id)));
+        }
+        return Ok(Json(ScheduleResponse {
+            status: "unscheduled".to_string(),
+            repo_id,
+            interval_secs: None,
+        }));
+    }
+    let secs = body
+        .interval_secs
+        .ok_or_else(|| MeshError::validation("'interval_secs' is needed unless removing"))?;
+    let interval = Duration::from_secs(secs);
+    if interval < schedule::MIN_INTERVAL {
+        return Err(MeshError::validation(format!(
+            "'interval_secs' must be at least {}",
+            schedule::MIN_INTERVAL.as_secs()
+        )));
+    }
+    let token = state.schedules.insert(&repo_id, &state.shutdown);
+    tokio::spawn(run_schedule(
+        state.clone(),
+        body.repo.clone(),
+        repo_id.clone(),
+        interval,
+        token,
+    ));
+    Ok(Json(ScheduleResponse {
+        status: "scheduled".to_string(),
+        repo_id,
+        interval_secs: Some(secs),
+    }))
+}
+
+/// Re-ingests `repo` every `interval` until `token` is cancelled. Runs are
+/// awaited one at a time, so ticks that pass during one are dropped rather
+/// than queued, and a tick is skipped while the repo is ingesting anyway, for
+/// a push or a `/process` call.
+async fn run_schedule(
+    state: Arc<AppState>,
+    repo: ProcessBody,
+    repo_id: String,
+    interval: Duration,
+    token: CancellationToken,
+) {
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => return,
+            _ = ticks.tick() => {}
+        }
+        if state.ingests.is_running(&repo_id) {
+            info!(
+                "{} is already ingesting, skipping its scheduled run",
+                repo_id
+            );
+            continue;
+        }
+        let Ok(_permit) = limits::ingest_permit(&state.ingest_slots) else {
+            info!(
+                "no ingest slot free, skipping the scheduled run of {}",
+                repo_id
+            );
+            continue;
+        };
+        let timer = state.metrics.ingest_timer();
+        match logging::ingest(
+            &repo_id,
+            repo.git_ref.as_deref(),
+            process_repo(&state, &repo),
+        )
+        .await
+        {
+            Ok(_) => timer.succeeded(),
+            Err(e) => {
+                error!("scheduled re-ingest of {} failed: {}", repo_id, e);
+                send_status(&state, &repo_id, "schedule_failed", e.to_string());
+            }
+        }
+    }
+}
+
+/// Registers every repo of a manifest and ingests them in the background,
+/// as many at once as the ingest cap allows. An entry that can't be ingested
+/// as given is rejected on its own and the rest are still queued. Each
+/// queued repo's ingest ends with a `bulk_ingested` or `bulk_failed` event
+/// for it, counting how many of the manifest are done.
+pub async fn bulk_register(
+    State(state): State<Arc<AppState>>,
+    body: Json<BulkRegisterBody>,
+) -> Result<(StatusCode, Json<BulkRegisterResponse>)> {
+    if body.repos.is_empty() {
+        return Err(MeshError::validation("the manifest lists no repos"));
+    }
+    let mut repos = Vec::new();
+    let mut queue: Vec<(String, ProcessBody)> = Vec::new();
+    for entry in &body.repos {
+        let repo = ProcessBody {
+            repo_url: Some(entry.url.clone()),
+            credential: entry.credential.clone(),
+            include_langs: entry.include_langs.clone(),
+            exclude_globs: entry.exclude_globs.clone(),
+            subdir: entry.subdir.clone(),
+            git_ref: entry.git_ref.clone(),
+            ..Default::default()
+        };
+        let checked = resolve_repo(&state, &repo).and_then(|(path, url, _)| {
+            file_filter(&state, &repo)?;
+            Ok(scoped_repo_id(&repo, &url, &path))
+        });
+        let (repo_id, error) = match checked {
+            Ok(repo_id) if queue.iter().any(|(id, _)| *id == repo_id) => {
+                let error = format!("{} is in the manifest more than once", repo_id);
+                (repo_id, Some(error))
+            }
+            Ok(repo_id) => {
+                queue.push((repo_id.clone(), repo));
+                (repo_id, None)
+            }
+            Err(e) => (String::new(), Some(clone::redact_urls(&e.to_string()))),
+        };
+        repos.push(RegisteredRepo {
+            url: clone::without_credentials(&entry.url),
+            repo_id,
+            status: if error.is_some() {
+                "rejected"
+            } else {
+                "queued"
+            }
+            .to_string(),
+            error,
+        });
+    }
+    let queued = queue.len();
+    tokio::spawn(logging::propagate(run_manifest(state.clone(), queue)));
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(BulkRegisterResponse { queued, repos }),
+    ))
+}
+
+/// Ingests the queued repos of a manifest, each once an ingest slot is free,
+/// so a long manifest waits its turn rather than being refused.
+async fn run_manifest(state: Arc<AppState>, queue: Vec<(String, ProcessBody)>) {
+    let total = queue.len();
+    let done = AtomicUsize::new(0);
+    let runs = queue.iter().map(|(repo_id, repo)| {
+        let state = &state;
+        let done = &done;
+        async move {
+            let permit = tokio::select! {
+                permit = state.ingest_slots.clone().acquire_owned() => permit,
+                _ = state.shutdown.cancelled() => return,
+            };
+            let Ok(_permit) = permit else {
+                return;
+            };
+            let timer = state.metrics.ingest_timer();
+            let ingested =
+                logging::ingest(repo_id, repo.git_ref.as_deref(), process_repo(state, repo)).await;
+            let completed = done.fetch_add(1, Ordering::Relaxed) + 1;
+            let (status, message) = match ingested {
+                Ok(response) => {
+                    timer.succeeded();
+                    let message = format!(
+                        "Ingested {}, {} of {} repos done: {}",
+                        repo_id, completed, total, response.message
+                    );
+                    ("bulk_ingested", message)
+                }
+                Err(e) => {
+                    error!("ingest of {} from the manifest failed: {}", repo_id, e);
+                    let message = format!(
+                        "Ingest of {} failed, {} of {} repos done: {}",
+                        repo_id, completed, total, e
+                    );
+                    ("bulk_failed", message)
+                }
+            };
+            let mut event =
+                StatusEvent::new(status, clone::redact_urls(&message)).for_repo(repo_id);
+            event.total = Some(total);
+            event.completed = Some(completed);
+            state.tx.send(event);
+        }
+    });
+    join_all(runs).await;
+}
+
+pub async fn fetch_repo(
+    State(state): State<Arc<AppState>>,
+    body: Json<FetchRepoBody>,
+) -> Result<Json<FetchRepoResponse>> {
+    if let Some(url) = &body.repo_url {
+        audit::note_repo(&clone::without_credentials(url));
+        clone::check_sparse_paths(&body.sparse_paths).map_err(MeshError::Validation)?;
+        let scope = clone::CloneScope {
+            depth: match body.depth {
+                Some(0) => None,
+                depth => Some(depth.unwrap_or(clone::DEFAULT_DEPTH)),
+            },
+            sparse_paths: body.sparse_paths.clone(),
+            submodules: body.recurse_submodules,
+            ..Default::default()
+        };
+        let _permit = limits::ingest_permit(&state.ingest_slots)?;
+        let mut dest = state.clones.path_for(url).map_err(MeshError::Validation)?;
+        if let Some(git_ref) = &body.git_ref {
+            dest = clone::ref_path(&dest, git_ref);
+        }
+        let credentials = git_credentials(
+            &state,
+            body.credential.as_deref(),
+            body.username.clone(),
+            body.pat.clone(),
+        )?;
+        let fetched = clone_into(
+            &state,
+            url,
+            Path::new(&dest),
+            &credentials,
+            body.git_ref.as_deref(),
+            &scope,
+        )
+        .await?;
+        let provenance = Provenance {
+            commit: fetched.commit,
+            git_ref: fetched.git_ref,
+            default_branch: fetched.default_branch,
+            remote_url: clone::without_credentials(url),
+            fetched_at: unix_now(),
+        };
+        send_status(
+            &state,
+            &storage::with_ref(&storage::repo_id(url, &dest), body.git_ref.as_deref()),
+            "cloned",
+            format!(
+                "Cloned {} {}{} at {} into {} (fetched at {})",
+                provenance.remote_url,
+                provenance.git_ref,
+                match &provenance.default_branch {
+                    Some(_) => " (default branch)",
+                    None => "",
+                },
+                provenance.commit,
+                dest,
+                provenance.fetched_at
+            ),
+        );
+        return Ok(Json(FetchRepoResponse {
+            status: "success".to_string(),
+            repo_name: body.repo_name.clone(),
+            fetched: Some(provenance),
+        }));
+    }
+    audit::note_repo(&body.repo_name);
+    let repo_node = state
+        .storage
+        .find_repo(&body.repo_name)
+        .await
+        .map_err(MeshError::Storage)?
+        .ok_or_else(|| MeshError::NotFound(format!("Repository {} not found", body.repo_name)))?;
+    Ok(Json(FetchRepoResponse {
+        status: "success".to_string(),
+        repo_name: repo_node.name,
+        fetched: None,
+    }))
+}
+
+/// Loads a repo's whole graph into memory, so until the repo is next written
+/// to, `/graph/query` templates, `/call-graph`, `/references` and the other
+/// reads of the repo's graph are answered without the backend. The least
+/// recently used graphs make room for it within `MESH_GRAPH_CACHE_BYTES`.
+pub async fn warm(
+    State(state): State<Arc<AppState>>,
+    body: Json<WarmBody>,
+) -> Result<Json<WarmResponse>> {
+    let since = state.graph_cache.generation();
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&body.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            body.repo
+        )));
+    }
+    let (node_count, edge_count) = (nodes.len(), edges.len());
+    match state
+        .graph_cache
+        .insert(&body.repo, CachedGraph::new(nodes, edges), since)
+    {
+        Inserted::Cached { bytes, evicted } => Ok(Json(WarmResponse {
+            repo: body.repo.clone(),
+            nodes: node_count,
+            edges: edge_count,
+            bytes,
+            evicted,
+        })),
+        Inserted::TooLarge { bytes, budget } => Err(MeshError::TooLarge(format!(
+            "The graph of {} takes about {} bytes, more than the cache's {}; raise MESH_GRAPH_CACHE_BYTES",
+            body.repo, bytes, budget
+        ))),
+        Inserted::Stale => Err(MeshError::Conflict(format!(
+            "{} was written to while it was being loaded; warm it again",
+            body.repo
+        ))),
+    }
+}
+
+/// Keeps a repo, or one ref of it, however long it goes unused, or lets
+/// `MESH_RETENTION_TTL_SECS` apply to it again. Pins are stored in the
+/// backend, so they outlast a restart and hold for every server on it.
+pub async fn pin(
+    State(state): State<Arc<AppState>>,
+    body: Json<PinBody>,
+) -> Result<Json<PinResponse>> {
+    if body.repo_id.is_empty() {
+        return Err(MeshError::validation("'repo_id' is empty"));
+    }
+    let repo_id = storage::with_ref(&body.repo_id, body.git_ref.as_deref());
+    let pinned = body.pinned.unwrap_or(true);
+    state
+        .storage
+        .set_pinned(&repo_id, pinned)
+        .await
+        .map_err(MeshError::Storage)?;
+    state.retention.remember_pin(&repo_id, pinned);
+    // unpinning lets go of a `MESH_PINNED_REPOS` pin too
+    if !pinned {
+        state.retention.pin(&repo_id, false);
+    }
+    let repos = state.storage.repos().await.map_err(MeshError::Storage)?;
+    let expires_at = repos
+        .iter()
+        .find(|repo| repo.repo_id == repo_id)
+        .and_then(|repo| state.retention.expires_at(repo));
+    Ok(Json(PinResponse {
+        repo_id,
+        pinned,
+        expires_at,
+    }))
+}
+
+/// The latest entries of the audit log, newest first. Only an admin key
+/// opens it.
+pub async fn audit_log(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<AuditParams>,
+) -> Result<Json<AuditResponse>> {
+    let Some(log) = &state.audit else {
+        return Err(MeshError::NotFound(
+            "There is no audit log; set MESH_AUDIT_LOG".to_string(),
+        ));
+    };
+    let limit = params
+        .limit
+        .unwrap_or(audit::DEFAULT_LIMIT)
+        .clamp(1, audit::MAX_LIMIT);
+    let entries = log
+        .recent(state.storage.as_ref(), limit)
+        .await
+        .map_err(MeshError::Storage)?;
+    Ok(Json(AuditResponse { entries }))
+}
+
+/// Compacts the storage backend, see [`storage::Storage::compact`].
+/// Ingests, clears and queries of every repo wait until it's done, rather
+/// than running against a database being rewritten.
+pub async fn compact(State(state): State<Arc<AppState>>) -> Result<Json<CompactResponse>> {
+    let _lock = state.repo_locks.write(None).await;
+    let started = Instant::now();
+    let compaction = state.storage.compact().await.map_err(MeshError::Storage)?;
+    Ok(Json(CompactResponse {
+        backend: state.storage.backend().to_string(),
+        compaction,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    }))
+}
+
+/// Takes a named snapshot of a repo's graph as it is now, see
+/// [`snapshots`]. The repo's ingests wait for the copy.
+pub async fn snapshot(
+    State(state): State<Arc<AppState>>,
+    body: Json<SnapshotBody>,
+) -> Result<Json<SnapshotResponse>> {
+    let repo_id = storage::with_ref(&body.repo, body.git_ref.as_deref());
+    audit::note_repo(&repo_id);
+    let _lock = state.repo_locks.read(Some(&repo_id)).await;
+    let snapshot = snapshots::create(state.storage.as_ref(), &repo_id, &body.name).await?;
+    Ok(Json(snapshot_response(snapshot)))
+}
+
+/// The snapshots taken of a repo, oldest first.
+pub async fn list_snapshots(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SnapshotsQuery>,
+) -> Result<Json<SnapshotsResponse>> {
+    let repo_id = storage::with_ref(&query.repo, query.git_ref.as_deref());
+    let snapshots = state
+        .storage
+        .snapshots(&repo_id)
+        .await
+        .map_err(MeshError::Storage)?;
+    Ok(Json(SnapshotsResponse {
+        repo_id,
+        snapshots: snapshots.into_iter().map(snapshot_response).collect(),
+    }))
+}
+
+/// Removes a snapshot and its graph.
+pub async fn delete_snapshot(
+    State(state): State<Arc<AppState>>,
+    body: Json<SnapshotBody>,
+) -> Result<Json<SnapshotResponse>> {
+    let repo_id = storage::with_ref(&body.repo, body.git_ref.as_deref());
+    audit::note_repo(&repo_id);
+    let graph = storage::with_snapshot(&repo_id, &body.name);
+    let _lock = state.repo_locks.write(Some(&graph)).await;
+    let snapshot = snapshots::delete(state.storage.as_ref(), &repo_id, &body.name).await?;
+    Ok(Json(snapshot_response(snapshot)))
+}
+
+/// Rolls a repo's graph back to one of its snapshots, waiting for the
+/// repo's ingests and reads to finish first.
+pub async fn restore_snapshot(
+    State(state): State<Arc<AppState>>,
+    body: Json<SnapshotBody>,
+) -> Result<Json<ProcessResponse>> {
+    let repo_id = storage::with_ref(&body.repo, body.git_ref.as_deref());
+    audit::note_repo(&repo_id);
+    let _lock = state.repo_locks.write(Some(&repo_id)).await;
+    let (nodes, edges) = snapshots::restore(state.storage.as_ref(), &repo_id, &body.name).await?;
+    Ok(Json(ProcessResponse {
+        status: "success".to_string(),
+        message: format!("Graph of {} restored from snapshot {}", repo_id, body.name),
+        nodes,
+        edges,
+    }))
+}
+
+/// Derives a repo's `IMPORTS`, `EXTENDS`, `IMPLEMENTS` and `TESTS` edges
+/// again from its stored nodes and the edges parsing gave them, as an ingest
+/// does in [`derived_edges`], and swaps them for the stored ones without
+/// parsing anything, so a graph picks up changed resolution rules cheaply.
+/// Nodes are left as they are; dead code is found from the edges when asked,
+/// so it follows.
+pub async fn reanalyze(
+    State(state): State<Arc<AppState>>,
+    body: Json<ReanalyzeBody>,
+) -> Result<Json<ReanalyzeResponse>> {
+    let repo_id = storage::with_ref(&body.repo, body.git_ref.as_deref());
+    audit::note_repo(&repo_id);
+    let _lock = state.repo_locks.write(Some(&repo_id)).await;
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&repo_id))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "Repository {} not found",
+            repo_id
         )));
     }
+    let files: HashSet<&str> = nodes
+        .iter()
+        .filter(|n| n.kind == "File")
+        .map(|n| n.id.as_str())
+        .collect();
+    // `IMPORTS` edges from an import rather than a file are parsed ones
+    let (stored, parsed): (Vec<EdgeRecord>, Vec<EdgeRecord>) =
+        edges.into_iter().partition(|e| match e.kind.as_str() {
+            "IMPORTS" => files.contains(e.source.as_str()),
+            kind => [hierarchy::EXTENDS, hierarchy::IMPLEMENTS, coverage::TESTS].contains(&kind),
+        });
+    let derived = derived_edges(&state, &repo_id, &nodes, &parsed, false).await?;
+    let key = |e: &EdgeRecord| (e.kind.clone(), e.source.clone(), e.target.clone());
+    let before: HashSet<_> = stored.iter().map(key).collect();
+    let after: HashSet<_> = derived.iter().map(key).collect();
+    let removed: Vec<EdgeRecord> = stored
+        .into_iter()
+        .filter(|e| !after.contains(&key(e)))
+        .collect();
+    let added: Vec<EdgeRecord> = derived
+        .into_iter()
+        .filter(|e| !before.contains(&key(e)))
+        .collect();
+    state
+        .storage
+        .delete_edges(&removed)
+        .await
+        .map_err(MeshError::Storage)?;
+    state
+        .storage
+        .upsert_edges(&added)
+        .await
+        .map_err(MeshError::Storage)?;
+    info!(
+        "Reanalyzed {}: {} derived edges removed, {} added",
+        repo_id,
+        removed.len(),
+        added.len()
+    );
+    Ok(Json(ReanalyzeResponse {
+        repo_id,
+        removed: removed.len(),
+        added: added.len(),
+    }))
+}
+
+fn snapshot_response(snapshot: SnapshotRecord) -> SnapshotResponse {
+    SnapshotResponse {
+        graph: storage::with_snapshot(&snapshot.repo_id, &snapshot.name),
+        snapshot,
+    }
+}
+
+/// Runs one of the vetted query templates, or a raw statement in the backend's
+/// query language when explicitly allowed. With `Accept: application/x-ndjson`
+/// the rows are streamed instead, see [`stream_rows`]; with
+/// `Accept: application/msgpack` they come as MessagePack.
+pub async fn query(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Json<QueryBody>,
+) -> Result<Response> {
+    let scope = ref_scope(body.repo.as_deref(), body.git_ref.as_deref(), "repo")?;
+    let streamed = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(NDJSON));
+    if streamed && (body.limit.is_some() || body.cursor.is_some()) {
+        return Err(MeshError::validation(
+            "'limit' and 'cursor' page whole results; leave them out to stream",
+        ));
+    }
+    let files = match &body.project {
+        Some(project) => Some(project_files(&state, scope.as_deref(), project).await?),
+        None => None,
+    };
+    // a request may only give up sooner than the server would
+    let timeout = match (state.query_timeout, body.timeout_ms) {
+        (Some(server), Some(ms)) => Some(server.min(Duration::from_millis(ms))),
+        (server, ms) => server.or(ms.map(Duration::from_millis)),
+    };
+    let cancel = CancellationToken::new();
+    let (name, rows) = match (&body.query, &body.cypher) {
+        (Some(key), None) => {
+            let template = query::find_template(key).ok_or_else(|| {
+                MeshError::validation(format!("unknown query template '{}'", key))
+            })?;
+            query::validate(template, &body.params).map_err(MeshError::Validation)?;
+            let params = query::scoped_params(&body.params, scope.as_deref());
+            let started = Instant::now();
+            if streamed {
+                let rows = by_deadline(
+                    started,
+                    timeout,
+                    state.storage.query_stream(template, &params),
+                )
+                .await;
+                note_query_time(&state, template.key, &params, started.elapsed());
+                let rows = rows?.map_err(MeshError::Storage)?;
+                return stream_rows(rows, started, timeout, &body.node_kinds, files).await;
+            }
+            let rows = within_timeout(
+                timeout,
+                &cancel,
+                state.storage.query_cancellable(template, &params, &cancel),
+            )
+            .await;
+            note_query_time(&state, template.key, &params, started.elapsed());
+            (template.key.to_string(), rows?)
+        }
+        (None, Some(_)) if scope.is_some() => {
+            return Err(MeshError::validation(
+                "'repo' and 'ref' only scope query templates",
+            ))
+        }
+        (None, Some(statement)) if state.allow_raw_cypher => {
+            // a raw statement can write or drop anything, whatever the route
+            if auth::refuses(&state, Scope::Admin, &headers) {
+                return Err(MeshError::Unauthorized(
+                    "Raw statements need an admin key".to_string(),
+                ));
+            }
+            if state.readonly && query::writes(statement) {
+                return Err(read_only().await);
+            }
+            let started = Instant::now();
+            if streamed {
+                let rows = by_deadline(
+                    started,
+                    timeout,
+                    state.storage.query_raw_stream(statement, &body.params),
+                )
+                .await;
+                note_query_time(&state, "raw", &body.params, started.elapsed());
+                let rows = rows?.map_err(MeshError::Storage)?;
+                return stream_rows(rows, started, timeout, &body.node_kinds, files).await;
+            }
+            let rows = within_timeout(
+                timeout,
+                &cancel,
+                state
+                    .storage
+                    .query_raw_cancellable(statement, &body.params, &cancel),
+            )
+            .await;
+            note_query_time(&state, "raw", &body.params, started.elapsed());
+            ("raw".to_string(), rows?)
+        }
+        (None, Some(_)) => {
+            return Err(MeshError::validation(
+                "raw cypher is disabled; set MESH_ALLOW_RAW_CYPHER=true to enable it",
+            ))
+        }
+        _ => {
+            return Err(MeshError::validation(
+                "provide exactly one of 'query' or 'cypher'",
+            ))
+        }
+    };
+    let rows = query::filter_kinds(rows, &body.node_kinds).map_err(MeshError::Validation)?;
+    let rows = query::filter_files(rows, files.as_ref()).map_err(MeshError::Validation)?;
+    let format = Format::of(&headers);
+    if body.limit.is_none() && body.cursor.is_none() {
+        return Ok(format.respond(&QueryResponse {
+            query: name,
+            rows,
+            next_cursor: None,
+        }));
+    }
+    let request = serde_json::json!([
+        name,
+        body.cypher,
+        body.params,
+        body.node_kinds,
+        scope,
+        body.project
+    ])
+    .to_string();
+    let page = query::paginate(&request, rows, body.limit, body.cursor.as_deref()).map_err(
+        |e| match e {
+            PageError::Invalid(message) => MeshError::Validation(message),
+            PageError::Changed => MeshError::Conflict(
+                "The graph changed since the cursor was issued; start again without it".to_string(),
+            ),
+        },
+    )?;
+    Ok(format.respond(&QueryResponse {
+        query: name,
+        rows: page.rows,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+const NDJSON: &str = "application/x-ndjson";
+
+/// Runs `work`, a query given `cancel`, for at most `timeout`. Past that
+/// `cancel` is fired and the query awaited until the backend has let go of
+/// it, so a timed-out query doesn't keep a connection busy behind the 504.
+async fn within_timeout<T>(
+    timeout: Option<Duration>,
+    cancel: &CancellationToken,
+    work: impl std::future::Future<Output = anyhow::Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return work.await.map_err(MeshError::Storage);
+    };
+    let started = Instant::now();
+    tokio::pin!(work);
+    tokio::select! {
+        done = &mut work => done.map_err(MeshError::Storage),
+        _ = tokio::time::sleep(timeout) => {
+            cancel.cancel();
+            let _ = work.await;
+            Err(timed_out(started, timeout))
+        }
+    }
+}
+
+/// Runs `work` until `timeout` after `started`, and drops it past that. A
+/// stream can't be cancelled as [`within_timeout`] cancels a query, but
+/// dropping it lets go of the backend all the same.
+async fn by_deadline<T>(
+    started: Instant,
+    timeout: Option<Duration>,
+    work: impl std::future::Future<Output = T>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return Ok(work.await);
+    };
+    let deadline = tokio::time::Instant::from_std(started + timeout);
+    tokio::time::timeout_at(deadline, work)
+        .await
+        .map_err(|_| timed_out(started, timeout))
+}
+
+fn timed_out(started: Instant, timeout: Duration) -> MeshError {
+    MeshError::Timeout {
+        message: format!("query timed out after {} ms", timeout.as_millis()),
+        elapsed: started.elapsed(),
+    }
+}
+
+/// Logs a query that kept the backend `elapsed`, when that's past
+/// `MESH_SLOW_QUERY_MS`, and counts it. A streamed query is timed to its
+/// first row, as that's all it is waited on for; `raw` stands for the
+/// template of a raw statement.
+fn note_query_time(
+    state: &AppState,
+    key: &str,
+    params: &serde_json::Map<String, serde_json::Value>,
+    elapsed: Duration,
+) {
+    let Some(threshold) = state.slow_query else {
+        return;
+    };
+    if elapsed < threshold {
+        return;
+    }
+    state.metrics.slow_queries.inc();
+    warn!(
+        query = key,
+        params = %serde_json::Value::Object(params.clone()),
+        duration_ms = elapsed.as_millis() as u64,
+        "slow query"
+    );
+}
+
+/// Streams `rows` as newline-delimited JSON, one object per row, as the
+/// backend hands them over. The first row is awaited before responding, so a
+/// query that fails outright, or doesn't start by `timeout` after `started`,
+/// still gets an error status; a failure after that, including running past
+/// the timeout, ends the 200 response with a line `{"error": "..."}` and
+/// drops the rest of the rows.
+async fn stream_rows(
+    mut rows: RowStream,
+    started: Instant,
+    timeout: Option<Duration>,
+    node_kinds: &[String],
+    files: Option<HashSet<String>>,
+) -> Result<Response> {
+    let first = by_deadline(started, timeout, rows.next())
+        .await?
+        .transpose()
+        .map_err(MeshError::Storage)?;
+    let rest = stream::unfold(Some(rows), move |rows| async move {
+        let mut rows = rows?;
+        match by_deadline(started, timeout, rows.next()).await {
+            Ok(row) => Some((row?, Some(rows))),
+            Err(e) => Some((Err(anyhow::anyhow!("{}", e)), None)),
+        }
+    });
+    let kinds = node_kinds.to_vec();
+    let lines = stream::iter(first.map(Ok))
+        .chain(rest)
+        .map(move |row| {
+            let row = row.map_err(|e| format!("{:#}", e))?;
+            let kept = query::keeps_kind(&row, &kinds)? && query::keeps_file(&row, files.as_ref())?;
+            Ok::<_, String>(kept.then_some(row))
+        })
+        .scan(false, |failed, line| {
+            // nothing follows an error line
+            if *failed {
+                return ready(None);
+            }
+            let line = match line {
+                Ok(None) => String::new(),
+                Ok(Some(row)) => format!("{}\n", row),
+                Err(e) => {
+                    *failed = true;
+                    format!("{}\n", serde_json::json!({ "error": e }))
+                }
+            };
+            ready(Some(line))
+        })
+        .filter(|line| ready(!line.is_empty()))
+        .map(Ok::<_, std::convert::Infallible>);
+    Ok(([(header::CONTENT_TYPE, NDJSON)], Body::from_stream(lines)).into_response())
+}
+
+/// The stored paths of the files in `repo_id`'s sub-project rooted at `project`.
+async fn project_files(
+    state: &AppState,
+    repo_id: Option<&str>,
+    project: &str,
+) -> Result<HashSet<String>> {
+    let repo_id = repo_id.ok_or_else(|| MeshError::validation("'project' needs 'repo'"))?;
+    let (nodes, _) = state
+        .storage
+        .load_graph(Some(repo_id))
+        .await
+        .map_err(MeshError::Storage)?;
+    let known = nodes.iter().any(|n| {
+        n.kind == projects::PROJECT_KIND && n.meta.get("root").is_some_and(|r| r == project)
+    });
+    if !known {
+        return Err(MeshError::NotFound(format!(
+            "No project rooted at {} in {}",
+            project, repo_id
+        )));
+    }
+    Ok(nodes
+        .into_iter()
+        .filter(|n| n.kind == "File")
+        .filter(|n| {
+            n.meta
+                .get(projects::PROJECT_META)
+                .is_some_and(|p| p == project)
+        })
+        .map(|n| n.file)
+        .collect())
+}
+
+/// Caller -> callee adjacency with calls resolved across files.
+pub async fn call_graph(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Json<CallGraphBody>,
+) -> Result<Response> {
+    if body.cross_repo && body.repo.is_none() {
+        return Err(MeshError::validation("cross_repo needs a repo"));
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let mut graph = callgraph::resolve_calls(&nodes, &edges);
+    if let (true, Some(repo)) = (body.cross_repo, &body.repo) {
+        let index = symbol_index(&state, repo).await?;
+        callgraph::link_across_repos(&mut graph, &nodes, &index);
+    }
+    if let Some(root) = &body.root {
+        graph = graph.reachable_from(root);
+    }
+    graph.cap_fan_out(state.edge_cap);
+    Ok(Format::of(&headers).respond(&graph))
+}
+
+/// Every call of a function, grouped by the definition it resolves to.
+pub async fn references(
+    State(state): State<Arc<AppState>>,
+    body: Json<ReferencesBody>,
+) -> Result<Json<ReferencesResponse>> {
+    let (nodes, edges) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let graph = callgraph::resolve_calls(&nodes, &edges);
+    let mut definitions = callgraph::references(&graph, &nodes, &body.name);
+    if let Some(id) = &body.id {
+        definitions.retain(|d| &d.id == id);
+    }
+    if definitions.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No function named {}",
+            body.name
+        )));
+    }
+    callgraph::cap_references(&mut definitions, &graph, state.edge_cap);
+    Ok(Json(ReferencesResponse {
+        name: body.name.clone(),
+        definitions,
+    }))
+}
+
+/// The functions calling a function, and those calling them, out to `depth`.
+pub async fn callers(
+    State(state): State<Arc<AppState>>,
+    body: Json<CallChainBody>,
+) -> Result<Json<CallChainResponse>> {
+    call_chain(&state, &body, callgraph::Direction::Callers).await
+}
+
+/// The functions a function calls, and those they call, out to `depth`.
+pub async fn callees(
+    State(state): State<Arc<AppState>>,
+    body: Json<CallChainBody>,
+) -> Result<Json<CallChainResponse>> {
+    call_chain(&state, &body, callgraph::Direction::Callees).await
+}
+
+async fn call_chain(
+    state: &AppState,
+    body: &CallChainBody,
+    direction: callgraph::Direction,
+) -> Result<Json<CallChainResponse>> {
+    let depth = body.depth.unwrap_or(callgraph::DEFAULT_CHAIN_DEPTH);
+    if depth == 0 || depth > callgraph::MAX_CHAIN_DEPTH {
+        return Err(MeshError::validation(format!(
+            "depth must be between 1 and {}",
+            callgraph::MAX_CHAIN_DEPTH
+        )));
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let graph = callgraph::resolve_calls(&nodes, &edges);
+    let mut definitions = callgraph::call_chains(&graph, &body.name, direction, depth);
+    if let Some(id) = &body.id {
+        definitions.retain(|d| &d.id == id);
+    }
+    if definitions.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No function named {}",
+            body.name
+        )));
+    }
+    Ok(Json(CallChainResponse {
+        name: body.name.clone(),
+        depth,
+        definitions,
+    }))
+}
+
+/// The external packages a repo's imports name, from the imports that don't
+/// resolve to a file of it, without reading any manifest.
+pub async fn dependencies(
+    State(state): State<Arc<AppState>>,
+    body: Json<DependenciesBody>,
+) -> Result<Json<DependenciesResponse>> {
+    let (nodes, _) = state
+        .storage
+        .load_graph(Some(&body.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "Repository {} not found",
+            body.repo
+        )));
+    }
+    let mut resolved = imports::resolve_imports(&nodes);
+    let mut linked = Vec::new();
+    if body.cross_repo {
+        let index = symbol_index(&state, &body.repo).await?;
+        imports::link_imports(&mut resolved, &index);
+        linked = resolved
+            .iter()
+            .filter(|i| matches!(i.target, imports::Target::Repo { .. }))
+            .cloned()
+            .collect();
+    }
+    Ok(Json(DependenciesResponse {
+        repo: body.repo.clone(),
+        dependencies: imports::dependencies(&resolved),
+        linked,
+    }))
+}
+
+/// The definitions of every repo but `repo_id`, for linking its names to
+/// them. Built again only once a graph has been written to since, or every
+/// time by a backend that keeps no [`storage::Storage::graph_versions`].
+async fn symbol_index(state: &AppState, repo_id: &str) -> Result<Arc<SymbolIndex>> {
+    let versions = state.storage.graph_versions().await.ok();
+    if let Some(index) = versions
+        .as_ref()
+        .and_then(|versions| state.symbol_indexes.get(repo_id, versions))
+    {
+        return Ok(index);
+    }
+    let (all, _) = state
+        .storage
+        .load_graph(None)
+        .await
+        .map_err(MeshError::Storage)?;
+    let index = Arc::new(SymbolIndex::new(&all, repo_id));
+    if let Some(versions) = versions {
+        state
+            .symbol_indexes
+            .insert(repo_id, versions, index.clone());
+    }
+    Ok(index)
+}
+
+/// The symbols stored under a fully qualified name, the exact counterpart
+/// of `/search`. Several come back only when they share it, as overloads
+/// do, or when no `repo` is given and more than one repo defines it.
+pub async fn resolve(
+    State(state): State<Arc<AppState>>,
+    body: Json<ResolveBody>,
+) -> Result<Json<ResolveResponse>> {
+    let (nodes, _) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let mut found: Vec<NodeRecord> = nodes
+        .into_iter()
+        .filter(|n| n.meta.get(symbols::FQN) == Some(&body.fqn))
+        .collect();
+    if found.is_empty() {
+        return Err(MeshError::NotFound(format!("No symbol named {}", body.fqn)));
+    }
+    found.sort_by(|a, b| (&a.repo_id, &a.file, a.start).cmp(&(&b.repo_id, &b.file, b.start)));
+    Ok(Json(ResolveResponse {
+        fqn: body.fqn.clone(),
+        nodes: found,
+    }))
+}
+
+/// The tests that exercise a function, directly or through other calls.
+pub async fn tests_for(
+    State(state): State<Arc<AppState>>,
+    body: Json<CoverageBody>,
+) -> Result<Json<TestsForResponse>> {
+    let (nodes, edges) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let mut definitions = coverage::tests_for(&nodes, &edges, &body.name);
+    if let Some(id) = &body.id {
+        definitions.retain(|d| &d.symbol.id == id);
+    }
+    if definitions.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No function named {}",
+            body.name
+        )));
+    }
+    Ok(Json(TestsForResponse {
+        name: body.name.clone(),
+        definitions,
+    }))
+}
+
+/// The functions a test exercises, the inverse of [`tests_for`].
+pub async fn covered_by(
+    State(state): State<Arc<AppState>>,
+    body: Json<CoverageBody>,
+) -> Result<Json<CoveredByResponse>> {
+    let (nodes, edges) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let mut tests = coverage::covered_by(&nodes, &edges, &body.name);
+    if let Some(id) = &body.id {
+        tests.retain(|t| &t.symbol.id == id);
+    }
+    if tests.is_empty() {
+        return Err(MeshError::NotFound(format!("No test named {}", body.name)));
+    }
+    Ok(Json(CoveredByResponse {
+        name: body.name.clone(),
+        tests,
+    }))
+}
+
+/// What a type derives from and what derives from it, across files.
+pub async fn hierarchy(
+    State(state): State<Arc<AppState>>,
+    body: Json<HierarchyBody>,
+) -> Result<Json<HierarchyResponse>> {
+    let (nodes, edges) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let types = hierarchy::hierarchy(&nodes, &edges, &body.name);
+    if types.is_empty() {
+        return Err(MeshError::NotFound(format!("No type named {}", body.name)));
+    }
+    Ok(Json(HierarchyResponse {
+        name: body.name.clone(),
+        types,
+    }))
+}
+
+/// Files related to one being edited, ranked by how closely the graph, and
+/// the checkout's history when there is one, ties them to it.
+pub async fn related(
+    State(state): State<Arc<AppState>>,
+    body: Json<RelatedBody>,
+) -> Result<Json<RelatedResponse>> {
+    let (mut nodes, edges) = state
+        .storage
+        .load_graph(Some(&body.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            body.repo
+        )));
+    }
+    // stored paths may include the checkout directory, the history needs them
+    let on_disk: HashMap<String, String> = nodes
+        .iter()
+        .filter(|n| n.kind == "File")
+        .map(|n| {
+            (
+                repo_relative(&n.file, &body.repo).to_string(),
+                n.file.clone(),
+            )
+        })
+        .collect();
+    let Some(stored) = on_disk.get(&body.file).cloned() else {
+        return Err(MeshError::NotFound(format!(
+            "No file {} in {}",
+            body.file, body.repo
+        )));
+    };
+    let co_changes = if body.weights.co_changes != 0.0 {
+        let others: Vec<String> = on_disk.into_values().collect();
+        let counts = tokio::task::spawn_blocking(move || clone::co_changes(&stored, &others))
+            .await
+            .map_err(|e| anyhow::anyhow!("History walk panicked: {}", e))?;
+        counts
+            .into_iter()
+            .map(|(file, count)| (repo_relative(&file, &body.repo).to_string(), count))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    for node in &mut nodes {
+        node.file = repo_relative(&node.file, &body.repo).to_string();
+    }
+    let mut files = analysis::relatedness(&nodes, &edges, &body.file, &co_changes, &body.weights);
+    files.truncate(body.limit.unwrap_or(analysis::DEFAULT_RELATED_LIMIT));
+    Ok(Json(RelatedResponse {
+        repo: body.repo.clone(),
+        file: body.file.clone(),
+        files,
+    }))
+}
+
+/// The subgraph within `depth` hops of a node, for expanding it in a graph
+/// view.
+pub async fn neighborhood(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Json<NeighborhoodBody>,
+) -> Result<Response> {
+    let depth = body.depth.unwrap_or(neighborhood::DEFAULT_DEPTH);
+    if depth > neighborhood::MAX_DEPTH {
+        return Err(MeshError::validation(format!(
+            "depth must be at most {}",
+            neighborhood::MAX_DEPTH
+        )));
+    }
+    let max_nodes = body.max_nodes.unwrap_or(neighborhood::DEFAULT_MAX_NODES);
+    if max_nodes == 0 {
+        return Err(MeshError::validation("max_nodes must be at least 1"));
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&body.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    let scope = neighborhood::Scope {
+        depth,
+        edge_kinds: body.edge_kinds.clone(),
+        max_nodes,
+        max_edges_per_node: state.edge_cap,
+    };
+    let subgraph = neighborhood::neighborhood(&nodes, &edges, &body.id, &scope)
+        .ok_or_else(|| MeshError::NotFound(format!("No node {} in {}", body.id, body.repo)))?;
+    Ok(Format::of(&headers).respond(&NeighborhoodResponse {
+        repo: body.repo.clone(),
+        id: body.id.clone(),
+        depth,
+        subgraph,
+    }))
+}
+
+/// A node's source with the lines around it, read from the file it was
+/// ingested from, or from the repo's checkout when that path is gone.
+pub async fn snippet(
+    State(state): State<Arc<AppState>>,
+    body: Json<SnippetBody>,
+) -> Result<Json<SnippetResponse>> {
+    let context = body.context.unwrap_or(snippet::DEFAULT_CONTEXT_LINES);
+    if context > snippet::MAX_CONTEXT_LINES {
+        return Err(MeshError::validation(format!(
+            "context must be at most {} lines",
+            snippet::MAX_CONTEXT_LINES
+        )));
+    }
+    let node = state
+        .storage
+        .node(&body.repo, &body.id)
+        .await
+        .map_err(MeshError::Storage)?
+        .ok_or_else(|| MeshError::NotFound(format!("No node {} in {}", body.id, body.repo)))?;
+    let file = repo_relative(&node.file, &body.repo).to_string();
+    let mut paths = vec![PathBuf::from(&node.file)];
+    let mut roots = state.allowed_roots.clone();
+    let repos = state.storage.repos().await.map_err(MeshError::Storage)?;
+    if let Some(repo) = repos
+        .iter()
+        .find(|r| r.repo_id == body.repo && !r.url.is_empty())
+    {
+        if let Ok(checkout) = state.clones.path_for(&repo.url) {
+            let checkout = match &repo.git_ref {
+                Some(git_ref) => clone::ref_path(&checkout, git_ref),
+                None => checkout,
+            };
+            paths.push(Path::new(&checkout).join(&file));
+            roots.push(PathBuf::from(checkout));
+        }
+    }
+    let doc = node.meta.get(docs::DOC).cloned();
+    let sources = state.sources.clone();
+    let snippet = tokio::task::spawn_blocking(move || {
+        // only files under the repo's checkout or a root local ingests may
+        // read from, so a stored path can't point anywhere else on the host
+        let roots: Vec<PathBuf> = roots.iter().filter_map(|r| r.canonicalize().ok()).collect();
+        let paths: Vec<PathBuf> = paths
+            .iter()
+            .filter_map(|path| local::confined_file(path, &roots))
+            .collect();
+        let source = sources.read(&paths)?;
+        snippet::snippet(&node, &source, context)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Snippet read panicked: {}", e))?
+    .ok_or_else(|| MeshError::NotFound(format!("Source of {} is not available", file)))?;
+    Ok(Json(SnippetResponse {
+        repo: body.repo.clone(),
+        id: body.id.clone(),
+        file,
+        doc,
+        snippet,
+    }))
+}
+
+/// The syntax tree tree-sitter parses `file` into, with the nodes an ingest
+/// would extract from it, for seeing what the grammar made of a file that
+/// indexes wrongly. Nothing is stored. Given `source`, that is parsed as
+/// `file` in a scratch directory instead of the repo's copy.
+pub async fn parse_tree(
+    State(state): State<Arc<AppState>>,
+    body: Json<ParseTreeBody>,
+) -> Result<Json<ParseTreeResponse>> {
+    let file = relative_file(&body.file)?;
+    let path = Path::new(&file);
+    let grammar = match state.languages.resolve(path) {
+        Some(plugin) => plugin.grammar(),
+        None => path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| GRAMMARS.iter().find(|g| g.extensions.contains(&ext)))
+            .map(|g| g.language())
+            .ok_or_else(|| MeshError::Validation(format!("No grammar parses {}", file)))?,
+    };
+
+    let scratch;
+    let (repo_path, repo_url, credentials) = match &body.source {
+        Some(source) => {
+            scratch = tempfile::tempdir()?;
+            let path = scratch.path().join(&file);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, source)?;
+            let root = scratch.path().to_string_lossy().into_owned();
+            (root, String::new(), Credentials::default())
+        }
+        None => resolve_repo(&state, &body.repo)?,
+    };
+    let source = match &body.source {
+        Some(source) => source.clone(),
+        None => encoding::read(&Path::new(&repo_path).join(&file))
+            .map(|decoded| decoded.text)
+            .map_err(|_| MeshError::NotFound(format!("{} is not in {}", file, repo_path)))?,
+    };
+    let repo_id = scoped_repo_id(&body.repo, &repo_url, &repo_path);
+    let graph_source = graph_source(&state, &body.repo, &repo_url, &repo_path);
+
+    let parsed = source.clone();
+    let timeout = state.languages.parse_timeout();
+    let sexp = tokio::task::spawn_blocking(move || {
+        crate::lang::parse(&grammar, &parsed, timeout)
+            .map(|tree| tree.map(|t| t.root_node().to_sexp()))
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Parse panicked: {}", e))??
+    .unwrap_or_default();
+
+    let (nodes, diagnostics) = file_nodes(
+        &state,
+        &repo_id,
+        graph_source,
+        &repo_path,
+        &credentials,
+        &file,
+    )
+    .await?;
+    Ok(Json(ParseTreeResponse {
+        file,
+        sexp,
+        nodes,
+        diagnostics,
+    }))
+}
+
+/// The nodes of one file as an ingest would store them, grammar and plugins
+/// alike, with what the plugins reported about it.
+async fn file_nodes(
+    state: &AppState,
+    repo_id: &str,
+    graph_source: &str,
+    repo_path: &str,
+    credentials: &Credentials,
+    file: &str,
+) -> Result<(Vec<NodeRecord>, Vec<Diagnostic>)> {
+    let progress = Progress::new(state.tx.clone(), None, Some(1));
+    let graph = build_graph(
+        state,
+        &progress,
+        &state.shutdown,
+        graph_source,
+        repo_path,
+        credentials,
+        vec![file.to_string()],
+    )
+    .await?;
+    let (nodes, _) = records_from_graph(&graph, repo_id);
+    let mut nodes = enrich(state, repo_path, nodes).await?;
+    let extracted = extract_plugins(state, repo_path, &[file.to_string()]).await?;
+    let (plugin_nodes, _) = records_from_plugins(&extracted, repo_id, &nodes, repo_path);
+    nodes.extend(plugin_nodes);
+    nodes.retain(|n| same_file(&n.file, file, repo_path));
+    if let Some(redaction) = &state.redaction {
+        redaction.apply(repo_id, &mut nodes, &mut Vec::new());
+    }
+    Ok((nodes, extracted.diagnostics))
+}
+
+/// Parses the request body, piped in from a CLI, as a file of `lang` and
+/// streams its nodes back as `/export/json` dump lines. Nothing is cloned or
+/// stored.
+pub async fn parse_stream(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ParseStreamParams>,
+    source: String,
+) -> Result<Response> {
+    let ext = filter::extension_for(&params.lang, &state.languages)
+        .ok_or_else(|| MeshError::Validation(format!("Unknown language '{}'", params.lang)))?;
+    let file = match &params.file {
+        Some(file) => relative_file(file)?,
+        None => format!("stdin.{}", ext),
+    };
+    let scratch = tempfile::tempdir()?;
+    let path = scratch.path().join(&file);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, &source)?;
+    let root = scratch.path().to_string_lossy().into_owned();
+    let repo_id = params.repo.unwrap_or_default();
+    let (nodes, _) =
+        file_nodes(&state, &repo_id, "", &root, &Credentials::default(), &file).await?;
+    let lines = export::ndjson_lines(nodes, Vec::new()).map(Ok::<_, std::convert::Infallible>);
+    Ok((
+        [(header::CONTENT_TYPE, NDJSON)],
+        Body::from_stream(stream::iter(lines)),
+    )
+        .into_response())
+}
+
+/// Functions nothing in the repo calls, leaving out entry points, public API
+/// and tests.
+pub async fn dead_code(
+    State(state): State<Arc<AppState>>,
+    body: Json<DeadCodeBody>,
+) -> Result<Json<DeadCodeResponse>> {
+    let entry_points = EntryPoints::new(analysis::DEFAULT_RULES, &body.entry_points)
+        .map_err(|e| MeshError::Validation(format!("{:#}", e)))?;
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&body.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            body.repo
+        )));
+    }
+    Ok(Json(DeadCodeResponse {
+        repo: body.repo.clone(),
+        symbols: analysis::find_unreferenced(&nodes, &edges, &entry_points),
+    }))
+}
+
+/// The import cycles among a repo's files, see [`analysis::cycles`].
+pub async fn cycles(
+    State(state): State<Arc<AppState>>,
+    body: Json<CyclesBody>,
+) -> Result<Json<CyclesResponse>> {
+    let limit = body.limit.unwrap_or(analysis::DEFAULT_CYCLE_LIMIT);
+    if limit == 0 || limit > analysis::MAX_CYCLE_LIMIT {
+        return Err(MeshError::validation(format!(
+            "'limit' must be between 1 and {}",
+            analysis::MAX_CYCLE_LIMIT
+        )));
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&body.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            body.repo
+        )));
+    }
+    let (cycles, truncated) = analysis::cycles(&nodes, &edges, limit);
+    Ok(Json(CyclesResponse {
+        repo: body.repo.clone(),
+        cycles,
+        truncated,
+    }))
+}
+
+/// Symbols and edges added, removed or changed between two ingested refs of a
+/// repo, or snapshots of them, grouped by file.
+pub async fn diff(
+    State(state): State<Arc<AppState>>,
+    body: Json<DiffBody>,
+) -> Result<Json<DiffResponse>> {
+    let mut sides = Vec::new();
+    for (git_ref, snapshot) in [
+        (&body.base, &body.base_snapshot),
+        (&body.head, &body.head_snapshot),
+    ] {
+        let mut repo_id = storage::with_ref(&body.repo, git_ref.as_deref());
+        if let Some(name) = snapshot {
+            snapshots::find(state.storage.as_ref(), &repo_id, name).await?;
+            repo_id = storage::with_snapshot(&repo_id, name);
+        }
+        let (mut nodes, edges) = state
+            .storage
+            .load_graph(Some(&repo_id))
+            .await
+            .map_err(MeshError::Storage)?;
+        if nodes.is_empty() {
+            return Err(MeshError::NotFound(format!(
+                "No graph stored for {}",
+                repo_id
+            )));
+        }
+        // each ref is parsed in its own checkout, which stored paths may include
+        let checkout = match git_ref {
+            Some(git_ref) => clone::ref_path(&body.repo, git_ref),
+            None => body.repo.clone(),
+        };
+        for node in &mut nodes {
+            node.file = repo_relative(&node.file, &checkout).to_string();
+        }
+        sides.push((nodes, edges));
+    }
+    let (base, head) = (&sides[0], &sides[1]);
+    Ok(Json(DiffResponse {
+        repo: body.repo.clone(),
+        base: body.base.clone(),
+        head: body.head.clone(),
+        base_snapshot: body.base_snapshot.clone(),
+        head_snapshot: body.head_snapshot.clone(),
+        files: analysis::diff((&base.0, &base.1), (&head.0, &head.1)),
+    }))
+}
+
+/// The symbols a commit range added, modified, moved or deleted, worked out
+/// from the repo's checkout with git diff and blame against the stored line
+/// ranges. Unlike `/diff` only the end of the range needs to be ingested;
+/// deletions are named when the start was ingested as a ref too.
+pub async fn changed_symbols(
+    State(state): State<Arc<AppState>>,
+    body: Json<ChangedSymbolsBody>,
+) -> Result<Json<ChangedSymbolsResponse>> {
+    let (mut head, _) = state
+        .storage
+        .load_graph(Some(&body.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if head.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            body.repo
+        )));
+    }
+    let repos = state.storage.repos().await.map_err(MeshError::Storage)?;
+    let record = repos.into_iter().find(|r| r.repo_id == body.repo);
+    let checkout = checkout_of(&state, record.as_ref(), &head)
+        .ok_or_else(|| MeshError::NotFound(format!("No checkout of {} on disk", body.repo)))?;
+    let (mut base, _) = state
+        .storage
+        .load_graph(Some(&storage::with_ref(&body.repo, Some(&body.base))))
+        .await
+        .map_err(MeshError::Storage)?;
+
+    let built_from = record.and_then(|r| r.commit);
+    let revs = (
+        body.base.clone(),
+        body.head
+            .clone()
+            .or_else(|| built_from.clone())
+            .unwrap_or_else(|| "HEAD".to_string()),
+    );
+    let (base_commit, head_commit, changes) = tokio::task::spawn_blocking(move || {
+        let resolve = |rev: &str| {
+            clone::resolve_commit(&checkout, rev).ok_or_else(|| {
+                MeshError::Validation(format!(
+                    "No commit {} in the checkout; a shallow clone may not reach it",
+                    rev
+                ))
+            })
+        };
+        let (base, head) = (resolve(&revs.0)?, resolve(&revs.1)?);
+        let changes = clone::range_changes(&checkout, &base, &head).map_err(MeshError::Git)?;
+        Ok::<_, MeshError>((base, head, changes))
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("History walk panicked: {}", e))??;
+    if let Some(commit) = built_from.filter(|c| *c != head_commit) {
+        return Err(MeshError::Conflict(format!(
+            "The graph of {} was built from {}, not {}",
+            body.repo, commit, head_commit
+        )));
+    }
+
+    let base_checkout = clone::ref_path(&body.repo, &body.base);
+    for node in &mut head {
+        node.file = checkout_relative(&node.file, &changes.root, &body.repo);
+    }
+    for node in &mut base {
+        node.file = checkout_relative(&node.file, &changes.root, &base_checkout);
+    }
+    Ok(Json(ChangedSymbolsResponse {
+        repo: body.repo.clone(),
+        base: base_commit,
+        head: head_commit,
+        symbols: analysis::range_diff(&changes, &head, &base),
+        deletions_known: !base.is_empty(),
+    }))
+}
+
+/// Where the checkout a repo's graph was built from is: its clone, or the
+/// directory its files were ingested from.
+fn checkout_of(
+    state: &AppState,
+    record: Option<&RepoRecord>,
+    nodes: &[NodeRecord],
+) -> Option<PathBuf> {
+    let clone = record.filter(|r| !r.url.is_empty()).and_then(|r| {
+        let path = state.clones.path_for(&r.url).ok()?;
+        Some(PathBuf::from(match &r.git_ref {
+            Some(git_ref) => clone::ref_path(&path, git_ref),
+            None => path,
+        }))
+    });
+    let ingested = nodes
+        .iter()
+        .map(|n| Path::new(&n.file))
+        .filter(|file| file.is_absolute())
+        .filter_map(|file| file.parent().map(Path::to_path_buf));
+    clone.into_iter().chain(ingested).find(|dir| dir.is_dir())
+}
+
+/// `file` relative to the checkout `root`, or failing that to the `checkout`
+/// directory it was stored under.
+fn checkout_relative(file: &str, root: &Path, checkout: &str) -> String {
+    match Path::new(file).strip_prefix(root) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => repo_relative(file, checkout).to_string(),
+    }
+}
+
+/// "Go to symbol": functions, classes and variables ranked against the query.
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    body: Json<SearchBody>,
+) -> Result<Json<SearchResponse>> {
+    if body.query.trim().is_empty() {
+        return Err(MeshError::validation("query must not be empty"));
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let limit = body.limit.unwrap_or(search::DEFAULT_LIMIT);
+    let hits = search::search(&nodes, &edges, &body.query, limit);
+    Ok(Json(SearchResponse {
+        query: body.query.clone(),
+        hits,
+    }))
+}
+
+/// The overview of [`stats::repo_stats`] for one repo, or for each of them.
+pub async fn stats(
+    State(state): State<Arc<AppState>>,
+    body: Json<StatsBody>,
+) -> Result<Json<StatsResponse>> {
+    let (nodes, edges) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    if let (Some(repo), true) = (&body.repo, nodes.is_empty()) {
+        return Err(MeshError::NotFound(format!("No graph stored for {}", repo)));
+    }
+    let mut by_repo: BTreeMap<String, (Vec<NodeRecord>, Vec<EdgeRecord>)> = BTreeMap::new();
+    for node in nodes {
+        by_repo
+            .entry(node.repo_id.clone())
+            .or_default()
+            .0
+            .push(node);
+    }
+    for edge in edges {
+        if let Some((_, edges)) = by_repo.get_mut(&edge.repo_id) {
+            edges.push(edge);
+        }
+    }
+    let top = body.top.unwrap_or(stats::DEFAULT_TOP);
+    let repos = by_repo
+        .iter()
+        .map(|(repo_id, (nodes, edges))| stats::repo_stats(repo_id, nodes, edges, top))
+        .collect();
+    Ok(Json(StatsResponse { repos }))
+}
+
+/// Lines of the comments and string literals indexed with `MESH_INDEX_TEXT`
+/// that contain `pattern`, or match it as a regex.
+pub async fn grep(
+    State(state): State<Arc<AppState>>,
+    body: Json<GrepBody>,
+) -> Result<Json<GrepResponse>> {
+    let matcher = grep::Matcher::new(&body.pattern, body.regex)
+        .map_err(|e| MeshError::Validation(format!("{:#}", e)))?;
+    let (nodes, _) = state
+        .storage
+        .load_graph(body.repo.as_deref())
+        .await
+        .map_err(MeshError::Storage)?;
+    let limit = body
+        .limit
+        .unwrap_or(grep::DEFAULT_LIMIT)
+        .min(grep::MAX_LIMIT);
+    let (hits, truncated) = tokio::task::spawn_blocking(move || {
+        grep::grep(&nodes, &matcher, limit, grep::SEARCH_TIMEOUT)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Grep panicked: {}", e))?;
+    Ok(Json(GrepResponse {
+        pattern: body.pattern.clone(),
+        hits,
+        truncated,
+    }))
+}
+
+/// One repo's nodes and edges as newline-delimited JSON, written out line by
+/// line as the response body is sent. Tagged like [`export_dot`].
+pub async fn export_json(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<ExportJsonParams>,
+) -> Result<Response> {
+    let etag = graph_etag(&state, &params.repo).await?;
+    if not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&params.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            params.repo
+        )));
+    }
+    let lines = export::ndjson_lines(nodes, edges).map(Ok::<_, std::convert::Infallible>);
+    let body = Body::from_stream(stream::iter(lines));
+    Ok((
+        [(header::CONTENT_TYPE, NDJSON)],
+        [(header::ETAG, etag)],
+        body,
+    )
+        .into_response())
+}
+
+/// Restores a dump from `/export/json`. Nothing is written unless every line
+/// parses and every edge references a node in the dump.
+pub async fn import_json(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ImportJsonParams>,
+    body: String,
+) -> Result<Json<ProcessResponse>> {
+    let (nodes, edges) = export::parse_ndjson(&body, params.repo.as_deref())
+        .map_err(|e| MeshError::Validation(e.to_string()))?;
+    for node in &nodes {
+        state
+            .storage
+            .upsert_node(node)
+            .await
+            .map_err(MeshError::Storage)?;
+    }
+    for edge in &edges {
+        state
+            .storage
+            .upsert_edge(edge)
+            .await
+            .map_err(MeshError::Storage)?;
+    }
+    state.storage.flush().await.map_err(MeshError::Storage)?;
+    Ok(Json(ProcessResponse {
+        status: "success".to_string(),
+        message: "Graph imported".to_string(),
+        nodes: nodes.len(),
+        edges: edges.len(),
+    }))
+}
+
+/// One repo's graph as GraphViz DOT, optionally limited to some node kinds.
+/// The `ETag` is the repo's graph version, so a poller sending it back in
+/// `If-None-Match` gets a 304 until the graph is written to.
+pub async fn export_dot(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<ExportDotParams>,
+) -> Result<Response> {
+    let etag = graph_etag(&state, &params.repo).await?;
+    if not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&params.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            params.repo
+        )));
+    }
+    let options = DotOptions {
+        kinds: kind_list(params.kinds.as_deref()),
+        max_nodes: params.max_nodes.unwrap_or(export::DEFAULT_MAX_NODES),
+    };
+    let dot = export::to_dot(&nodes, &edges, &options);
+    Ok((
+        [(header::CONTENT_TYPE, "text/vnd.graphviz")],
+        [(header::ETAG, etag)],
+        dot,
+    )
+        .into_response())
+}
+
+/// One repo's graph, or the neighborhood of one symbol in it, as Cytoscape.js
+/// JSON, with node kinds mapped to style classes. Tagged like [`export_dot`].
+pub async fn export_cytoscape(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<ExportCytoscapeParams>,
+) -> Result<Response> {
+    let etag = graph_etag(&state, &params.repo).await?;
+    if not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&params.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes.is_empty() {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            params.repo
+        )));
+    }
+    let options = CytoscapeOptions {
+        kinds: kind_list(params.kinds.as_deref()),
+        max_nodes: params.max_nodes.unwrap_or(export::DEFAULT_MAX_NODES),
+        around: params.around.clone(),
+        radius: params.radius.unwrap_or(export::DEFAULT_RADIUS),
+    };
+    let graph = export::to_cytoscape(&nodes, &edges, &options).ok_or_else(|| {
+        MeshError::NotFound(format!(
+            "No symbol named {} in {}",
+            params.around.as_deref().unwrap_or_default(),
+            params.repo
+        ))
+    })?;
+    let body = Format::of(&headers).respond(&graph);
+    Ok(([(header::ETAG, etag)], body).into_response())
+}
+
+/// The call graph or type hierarchy around one symbol as a Mermaid
+/// flowchart, to paste into docs and PRs. Tagged like [`export_dot`].
+pub async fn export_mermaid(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<ExportMermaidParams>,
+) -> Result<Response> {
+    let etag = graph_etag(&state, &params.repo).await?;
+    if not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    let (nodes, edges) = state
+        .storage
+        .load_graph(Some(&params.repo))
+        .await
+        .map_err(MeshError::Storage)?;
+    let options = MermaidOptions {
+        graph: params.graph.unwrap_or_default(),
+        around: params.around.clone(),
+        direction: params.direction.unwrap_or_default(),
+        max_nodes: params.max_nodes.unwrap_or(export::DEFAULT_MERMAID_NODES),
+        radius: params.radius.unwrap_or(export::DEFAULT_RADIUS),
+    };
+    let diagram = export::to_mermaid(&nodes, &edges, &options).ok_or_else(|| {
+        MeshError::NotFound(format!(
+            "No symbol named {} in {}",
+            params.around, params.repo
+        ))
+    })?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        [(header::ETAG, etag)],
+        diagram,
+    )
+        .into_response())
+}
+
+/// The parse diagnostics and skipped files stored for a repo by its last
+/// ingests, narrowed by `severity` and `file`. JSON by default; `format=text`
+/// or `format=sarif` downloads them as a report, the latter for CI to show
+/// indexing problems alongside its other findings.
+pub async fn diagnostics(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DiagnosticsParams>,
+) -> Result<Response> {
+    let format: ReportFormat = params
+        .format
+        .as_deref()
+        .unwrap_or("json")
+        .parse()
+        .map_err(MeshError::Validation)?;
+    let severities = kind_list(params.severity.as_deref())
+        .iter()
+        .map(|s| s.parse::<Severity>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(MeshError::Validation)?;
+    let template = query::find_template("parse-diagnostics").expect("built-in template");
+    let rows = state
+        .storage
+        .query(
+            template,
+            &query::scoped_params(&serde_json::Map::new(), Some(&params.repo)),
+        )
+        .await
+        .map_err(MeshError::Storage)?;
+    let mut diagnostics = Vec::with_capacity(rows.len());
+    for row in rows {
+        let diagnostic: Diagnostic = serde_json::from_value(row)
+            .map_err(|e| MeshError::Storage(anyhow::anyhow!("unreadable diagnostic: {}", e)))?;
+        if report::matches(&diagnostic, &severities, params.file.as_deref()) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    let download = |extension: &str| {
+        format!(
+            "attachment; filename=\"{}-diagnostics.{}\"",
+            params.repo.replace(['/', '@'], "-"),
+            extension
+        )
+    };
+    Ok(match format {
+        ReportFormat::Json => Json(DiagnosticsResponse {
+            repo: params.repo.clone(),
+            diagnostics,
+        })
+        .into_response(),
+        ReportFormat::Text => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "text/plain; charset=utf-8".to_string(),
+                ),
+                (header::CONTENT_DISPOSITION, download("txt")),
+            ],
+            report::to_text(&params.repo, &diagnostics),
+        )
+            .into_response(),
+        ReportFormat::Sarif => (
+            [
+                (header::CONTENT_TYPE, report::SARIF_CONTENT_TYPE.to_string()),
+                (header::CONTENT_DISPOSITION, download("sarif")),
+            ],
+            report::to_sarif(&diagnostics).to_string(),
+        )
+            .into_response(),
+    })
+}
+
+/// A comma-separated `kinds` parameter, blanks dropped.
+fn kind_list(kinds: Option<&str>) -> Vec<String> {
+    kinds
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `repo`'s graph version as an entity tag. It is read before the graph, so
+/// a write in between makes the tag older than the body, and the next
+/// request downloads the graph again rather than keeping a stale one.
+async fn graph_etag(state: &AppState, repo: &str) -> Result<String> {
+    let version = state
+        .storage
+        .graph_version(repo)
+        .await
+        .map_err(MeshError::Storage)?;
+    Ok(format!("\"{}\"", version))
+}
+
+/// Whether `If-None-Match` lists `etag`, weakly or not, or is `*`.
+fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Ingests a repo named by a JSON [`ProcessBody`], or a `.tar.gz` of a source
+/// tree uploaded as `application/gzip` or as the `archive` field of a
+/// `multipart/form-data` form, for runners that can't clone.
+#[axum::debug_handler]
+pub async fn ingest(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+) -> Result<Json<ProcessResponse>> {
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if content_type.starts_with("multipart/form-data") {
+        let multipart = Multipart::from_request(request, &state)
+            .await
+            .map_err(|e| MeshError::Validation(e.body_text()))?;
+        let (archive, repo_id) = archive_form(multipart).await?;
+        return ingest_archive(&state, archive, repo_id).await.map(Json);
+    }
+    if content_type.starts_with("application/gzip")
+        || content_type.starts_with("application/x-gzip")
+    {
+        let Query(params) = Query::<ArchiveParams>::try_from_uri(request.uri())
+            .map_err(|e| MeshError::Validation(e.body_text()))?;
+        let archive = Bytes::from_request(request, &state)
+            .await
+            .map_err(|e| MeshError::Validation(e.body_text()))?;
+        return ingest_archive(&state, archive, params.repo_id)
+            .await
+            .map(Json);
+    }
+    let Json(body) = Json::<ProcessBody>::from_request(request, &state)
+        .await
+        .map_err(|e| MeshError::Validation(e.body_text()))?;
+    let repo_id = ingest_id(&state, &body);
+    audit::note_repo(&repo_id);
+    logging::ingest(
+        &repo_id,
+        body.git_ref.as_deref(),
+        ingest_repo(&state, &body),
+    )
+    .await
+    .map(Json)
+}
+
+#[cfg_attr(
+    feature = "otel",
+    tracing::instrument(
+        skip_all,
+        fields(
+            repo_url = ?body.repo_url.as_deref().map(clone::without_credentials),
+            repo_path = ?body.repo_path
+        )
+    )
+)]
+async fn ingest_repo(state: &AppState, body: &ProcessBody) -> Result<ProcessResponse> {
+    let _permit = limits::ingest_permit(&state.ingest_slots)?;
+    let timer = state.metrics.ingest_timer();
+    let start_total = Instant::now();
+    let (final_repo_path, final_repo_url, credentials) = resolve_repo(state, body)?;
+    let mut filter = file_filter(state, body)?;
+    let repo_id = scoped_repo_id(body, &final_repo_url, &final_repo_path);
+    let _lease = state.clones.lease(&final_repo_path);
+    fetch_checkout(state, body, &final_repo_url, &final_repo_path, &credentials).await?;
+
+    let start_build = Instant::now();
+
+    let (files, skipped) = select_files(
+        state,
+        &mut filter,
+        &repo_id,
+        &final_repo_url,
+        &final_repo_path,
+        &credentials,
+        Vec::new(),
+    )
+    .await?;
+    if files.is_empty() && !filter.is_empty() {
+        return Err(MeshError::validation("no files match the filters"));
+    }
+
+    // a repo that still has to be cloned can't be counted up front
+    let total = if files.is_empty() {
+        count_files(&final_repo_path).await?
+    } else {
+        Some(files.len())
+    };
+    let progress = Progress::new(state.tx.clone(), Some(&repo_id), total);
+    let _lock = state.repo_locks.write(Some(&repo_id)).await;
+    let ingest = state.ingests.start(&repo_id, &state.shutdown);
+
+    let btree_graph = build_graph(
+        state,
+        &progress,
+        ingest.token(),
+        graph_source(state, body, &final_repo_url, &final_repo_path),
+        &final_repo_path,
+        &credentials,
+        files.clone(),
+    )
+    .await?;
+    info!(
+        "\n\n ==>>Building BTreeMapGraph took {:.2?} \n\n",
+        start_build.elapsed()
+    );
+
+    let start_upload = Instant::now();
+
+    if !body.dry_run {
+        state
+            .storage
+            .clear(Some(&repo_id))
+            .await
+            .map_err(MeshError::Storage)?;
+    }
+
+    let written = write_graph(
+        state,
+        &progress,
+        ingest.token(),
+        &btree_graph,
+        &final_repo_path,
+        &repo_id,
+        &files,
+        &skipped,
+        &KindFilter::new(&body.node_kinds),
+        body.dry_run,
+        None,
+    )
+    .await?;
+    if body.dry_run {
+        timer.discard();
+        ingest.succeeded();
+        return Ok(dry_run_response(written.nodes, written.edges));
+    }
+    finish_ingest(
+        state,
+        &progress,
+        ingest,
+        &repo_id,
+        &final_repo_url,
+        &final_repo_path,
+        &written,
+        start_total,
+    )
+    .await?;
+
+    info!(
+        "\n\n ==>> Uploading to {} took {:.2?} \n\n",
+        state.storage.backend(),
+        start_upload.elapsed()
+    );
+
+    info!(
+        "\n\n ==>> Total ingest time: {:.2?} \n\n",
+        start_total.elapsed()
+    );
+    timer.succeeded();
+
+    Ok(ProcessResponse {
+        status: "success".to_string(),
+        message: stored_message("Repository ingested fully", &written.failed),
+        nodes: written.nodes,
+        edges: written.edges,
+    })
+}
+
+/// Ingests a directory already on disk, without cloning and without needing git.
+#[axum::debug_handler]
+#[cfg_attr(
+    feature = "otel",
+    tracing::instrument(skip_all, fields(path = %body.path))
+)]
+pub async fn ingest_path(
+    State(state): State<Arc<AppState>>,
+    body: Json<IngestPathBody>,
+) -> Result<Json<ProcessResponse>> {
+    let _permit = limits::ingest_permit(&state.ingest_slots)?;
+    let timer = state.metrics.ingest_timer();
+    let root = local::check_allowed(&body.path, &state.allowed_roots)
+        .map_err(|e| MeshError::Validation(format!("{:#}", e)))?;
+    let root = root.to_string_lossy().to_string();
+    let repo_id = body
+        .repo_id
+        .clone()
+        .unwrap_or_else(|| storage::repo_id("", &root));
+    audit::note_repo(&repo_id);
+    let written =
+        logging::ingest(&repo_id, None, ingest_dir(&state, &root, &repo_id, false)).await?;
+    timer.succeeded();
+
+    Ok(Json(ProcessResponse {
+        status: "success".to_string(),
+        message: stored_message("Directory ingested fully", &written.failed),
+        nodes: written.nodes,
+        edges: written.edges,
+    }))
+}
+
+/// Unpacks an uploaded source tarball into a temporary directory and ingests
+/// it like `/ingest-path` would. The directory is removed once the ingest is
+/// done, whether or not it succeeded.
+#[cfg_attr(feature = "otel", tracing::instrument(skip_all))]
+async fn ingest_archive(
+    state: &AppState,
+    archive: Bytes,
+    repo_id: Option<String>,
+) -> Result<ProcessResponse> {
+    let _permit = limits::ingest_permit(&state.ingest_slots)?;
+    let timer = state.metrics.ingest_timer();
+    // the temp dir's name means nothing, so there is no default to fall back on
+    let repo_id = repo_id
+        .filter(|id| !id.trim().is_empty())
+        .ok_or_else(|| MeshError::validation("an uploaded archive needs a repo_id"))?;
+    audit::note_repo(&repo_id);
+    let dir = tempfile::tempdir().map_err(|e| anyhow::anyhow!("cannot create temp dir: {}", e))?;
+    let dest = dir.path().to_path_buf();
+    let root = tokio::task::spawn_blocking(move || -> anyhow::Result<PathBuf> {
+        Ok(archive::unpack(&archive, &dest)?.canonicalize()?)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Archive unpacking panicked: {}", e))?
+    .map_err(|e| MeshError::Validation(format!("{:#}", e)))?;
+    let root = root.to_string_lossy().to_string();
+    // the unpacked copy is ours, so its files can be transcoded in place
+    let written = logging::ingest(&repo_id, None, ingest_dir(state, &root, &repo_id, true)).await?;
+    timer.succeeded();
+    drop(dir);
+
+    Ok(ProcessResponse {
+        status: "success".to_string(),
+        message: stored_message("Archive ingested fully", &written.failed),
+        nodes: written.nodes,
+        edges: written.edges,
+    })
+}
+
+/// The `archive` file and `repo_id` fields of a multipart upload.
+async fn archive_form(mut multipart: Multipart) -> Result<(Bytes, Option<String>)> {
+    let (mut archive, mut repo_id) = (None, None);
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| MeshError::Validation(e.body_text()))?
+    {
+        match field.name() {
+            Some("archive") => {
+                archive = Some(
+                    field
+                        .bytes()
+                        .await
+                        .map_err(|e| MeshError::Validation(e.body_text()))?,
+                )
+            }
+            Some("repo_id") => {
+                repo_id = Some(
+                    field
+                        .text()
+                        .await
+                        .map_err(|e| MeshError::Validation(e.body_text()))?,
+                )
+            }
+            _ => {}
+        }
+    }
+    let archive = archive.ok_or_else(|| MeshError::validation("no archive field in the form"))?;
+    Ok((archive, repo_id))
+}
+
+/// Walks, parses and stores a directory on disk, replacing whatever was
+/// stored under `repo_id` before. `transcode` as for [`skip_unparseable`].
+async fn ingest_dir(
+    state: &AppState,
+    root: &str,
+    repo_id: &str,
+    transcode: bool,
+) -> Result<Written> {
+    let start_total = Instant::now();
+    // taken before anything of the repo is stored, the skipped files first
+    let _lock = state.repo_locks.write(Some(repo_id)).await;
+    let walk_root = PathBuf::from(root);
+    let mut files = tokio::task::spawn_blocking(move || local::walk(&walk_root))
+        .await
+        .map_err(|e| anyhow::anyhow!("Directory walk panicked: {}", e))??;
+    let skipped = skip_unparseable(state, repo_id, Path::new(root), &mut files, transcode).await?;
+    if files.is_empty() {
+        return Err(MeshError::Validation(format!(
+            "no files to ingest under {}",
+            root
+        )));
+    }
+
+    let progress = Progress::new(state.tx.clone(), Some(repo_id), Some(files.len()));
+    let ingest = state.ingests.start(repo_id, &state.shutdown);
+    let graph = build_graph(
+        state,
+        &progress,
+        ingest.token(),
+        "",
+        root,
+        &Credentials::default(),
+        files.clone(),
+    )
+    .await?;
+
+    state
+        .storage
+        .clear(Some(repo_id))
+        .await
+        .map_err(MeshError::Storage)?;
+    let result = write_graph(
+        state,
+        &progress,
+        ingest.token(),
+        &graph,
+        root,
+        repo_id,
+        &files,
+        &skipped,
+        &KindFilter::default(),
+        false,
+        None,
+    )
+    .await?;
+    finish_ingest(
+        state,
+        &progress,
+        ingest,
+        repo_id,
+        "",
+        root,
+        &result,
+        start_total,
+    )
+    .await?;
+
+    info!(
+        "\n\n ==>> Total ingest time for {}: {:.2?} \n\n",
+        root,
+        start_total.elapsed()
+    );
+    Ok(result)
+}
+
+/// Detects and parses the repo, or only `files` when given, streaming status to `/events`.
+/// Parsing is dropped as soon as `cancel` fires.
+#[cfg_attr(
+    feature = "otel",
+    tracing::instrument(
+        skip_all,
+        fields(repo_url = %clone::without_credentials(repo_url), repo_path, files = files.len())
+    )
+)]
+async fn build_graph(
+    state: &AppState,
+    progress: &Arc<Progress>,
+    cancel: &CancellationToken,
+    repo_url: &str,
+    repo_path: &str,
+    credentials: &Credentials,
+    files: Vec<String>,
+) -> Result<BTreeMapGraph> {
+    let mut repos = if repo_url.is_empty() {
+        Repo::new_multi_detect(repo_path, None, files, Vec::new(), None).await
+    } else {
+        let (username, token) = (credentials.username.clone(), credentials.token.clone());
+        Repo::new_clone_multi_detect(repo_url, username, token, files, Vec::new(), None).await
+    }
+    .map_err(|e| MeshError::Git(credentials.redact(&format!("Repo detection failed: {}", e))))?;
+
+    repos
+        .set_status_tx(progress.forwarder(state.event_capacity, credentials))
+        .await;
+
+    let start = Instant::now();
+    let graph = tokio::select! {
+        graph = repos.build_graphs_inner::<BTreeMapGraph>() => graph,
+        _ = cancel.cancelled() => return Err(stopped(state, progress)),
+    };
+    let graph = graph.map_err(|e| MeshError::Parse {
+        file: repo_path.to_string(),
+        message: format!("Graph build failed: {}", e),
+    })?;
+    // `ast` parses the files together, so each one is charged an equal share
+    let parsed = graph
+        .nodes
+        .values()
+        .filter(|n| matches!(n.node_type, NodeType::File))
+        .count() as u64;
+    if parsed > 0 {
+        state
+            .metrics
+            .parse_duration
+            .observe_n(start.elapsed() / parsed as u32, parsed);
+    }
+    Ok(graph)
+}
+
+/// Stores the `ast` graph plus any language plugin nodes under `repo_id` and
+/// returns the size of that repo's graph.
+/// With `dry_run` nothing is written at all: the records are summarized as
+/// `dry_run` status updates instead, and their counts returned.
+/// Progress advances by one for every `File` node written. When `cancel` fires
+/// the write stops between files and reports `aborted` on shutdown or
+/// `cancelled` through `/cancel`; the repo hash is left unset so the next
+/// `/process` picks the work up again.
+/// Each file is written in its own transaction. One whose writes fail is
+/// rolled back, reported as an `error` status and listed in `failed`, and
+/// the other files are still written. With `checkpoint`, the hashes of the
+/// files being parsed, each file is checkpointed as it commits; see
+/// [`checkpoints`].
+/// Only the node kinds `kinds` allows are stored, or counted by a dry run.
+/// Why the `skipped` files weren't parsed is stored with the diagnostics.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(
+    feature = "otel",
+    tracing::instrument(
+        skip_all,
+        fields(
+            backend = state.storage.backend(),
+            repo_id,
+            nodes = graph.nodes.len(),
+            files = files.len()
+        )
+    )
+)]
+async fn write_graph(
+    state: &AppState,
+    progress: &Progress,
+    cancel: &CancellationToken,
+    graph: &BTreeMapGraph,
+    repo_path: &str,
+    repo_id: &str,
+    files: &[String],
+    skipped: &[Diagnostic],
+    kinds: &KindFilter,
+    dry_run: bool,
+    checkpoint: Option<&HashMap<String, String>>,
+) -> Result<Written> {
+    let (mut nodes, mut edges) = records_from_graph(graph, repo_id);
+    let extracted = extract_plugins(state, repo_path, files).await?;
+    let (plugin_nodes, plugin_edges) = records_from_plugins(&extracted, repo_id, &nodes, repo_path);
+    nodes.extend(plugin_nodes);
+    edges.extend(plugin_edges);
+    let project_edges;
+    (nodes, project_edges) = detect_projects(repo_path, repo_id, nodes).await?;
+    edges.extend(project_edges);
+    let annotation_edges;
+    (nodes, annotation_edges) = detect_annotations(state, repo_path, repo_id, nodes).await?;
+    edges.extend(annotation_edges);
+    let component_edges;
+    (nodes, component_edges) = detect_components(state, repo_path, repo_id, nodes).await?;
+    edges.extend(component_edges);
+    if state.embedded_sql {
+        let sql_edges;
+        (nodes, sql_edges) = detect_sql(state, repo_path, repo_id, nodes).await?;
+        edges.extend(sql_edges);
+    }
+    if let Some(rules) = &state.tasks {
+        let task_edges;
+        (nodes, task_edges) = detect_tasks(state, repo_path, repo_id, nodes, rules.clone()).await?;
+        edges.extend(task_edges);
+    }
+    if state.interface_repos.iter().any(|r| r == repo_id) {
+        let interface_edges;
+        (nodes, interface_edges) = detect_interfaces(repo_path, repo_id, nodes, &edges).await?;
+        edges.extend(interface_edges);
+    }
+    if let Some(redaction) = &state.redaction {
+        redaction.apply(repo_id, &mut nodes, &mut edges);
+    }
+    let derived = derived_edges(state, repo_id, &nodes, &edges, !files.is_empty()).await?;
+    edges.extend(derived);
+    state.node_ids.apply(repo_path, &mut nodes, &mut edges);
+    // taken before `kinds` can drop the `File` nodes
+    let parsed: HashSet<String> = nodes
+        .iter()
+        .filter(|n| n.kind == "File")
+        .map(|n| n.file.clone())
+        .collect();
+    if dry_run {
+        let summary = DryRun::new(state, repo_path, &nodes, &extracted.diagnostics).await?;
+        kinds.apply(&mut nodes, &mut edges);
+        summary.report(state, progress, repo_id, nodes.len(), edges.len());
+        return Ok(Written {
+            nodes: nodes.len(),
+            edges: edges.len(),
+            parsed: parsed.len(),
+            ..Default::default()
+        });
+    }
+    kinds.apply(&mut nodes, &mut edges);
+    embed(state, repo_id, &mut nodes).await;
+    state.metrics.files_parsed.add(parsed.len() as u64);
+    report_timeouts(state, repo_id, &extracted.timed_out);
+    store_diagnostics(state, repo_id, files, &extracted.diagnostics).await?;
+    store_skipped(state, repo_id, skipped).await?;
+
+    let mut budget = graph_budget(state, repo_id, files).await?;
+    let mut failed = Vec::new();
+    // files rolled back, which the edges of later files may point into
+    let mut lost = HashSet::new();
+    let mut total = Added::default();
+    // files are measured as the writer takes them, up to `write_queue` ahead
+    // of it, so a slow backend holds the parsing back rather than letting
+    // finished files pile up
+    let root = repo_path.to_string();
+    let paths = state.path_map.clone();
+    let timeout = state.languages.parse_timeout();
+    let mut batches = Pipeline::spawn(state.write_queue, move |feed| {
+        for (path, (mut nodes, edges)) in by_file(nodes, edges) {
+            enrich_file(&root, &path, &mut nodes, timeout);
+            paths.apply(&mut nodes);
+            if !feed.send((path, nodes, edges)) {
+                break;
+            }
+        }
+    });
+    while let Some((path, nodes, edges)) = batches.next().await {
+        if cancel.is_cancelled() {
+            return Err(stopped(state, progress));
+        }
+        let file = repo_relative(&path, repo_path);
+        let edges: Vec<EdgeRecord> = edges
+            .into_iter()
+            .filter(|(other, _)| other.as_ref().map_or(true, |f| !lost.contains(f)))
+            .map(|(_, edge)| edge)
+            .collect();
+        if let Some(budget) = &mut budget {
+            if let Err(reason) = budget.take(nodes.len(), edges.len()) {
+                let rollback = budget.started_empty();
+                return Err(too_large(state, progress, repo_id, rollback, reason).await);
+            }
+        }
+        let tx = state.storage.begin().await.map_err(MeshError::Storage)?;
+        if let Err(e) = write_file(tx, repo_id, &nodes, &edges, &[]).await {
+            error!("Failed to store {}: {:#}", file, e);
+            send_status(
+                state,
+                repo_id,
+                "error",
+                format!("Failed to store {}, nothing of it was kept: {:#}", file, e),
+            );
+            failed.push(file.to_string());
+            lost.insert(path);
+            continue;
+        }
+        if let Some(hash) = checkpoint.and_then(|hashes| hashes.get(file)) {
+            checkpoints::stored(
+                state.storage.as_ref(),
+                repo_id,
+                &[(file.to_string(), hash.clone())],
+            )
+            .await
+            .map_err(MeshError::Storage)?;
+        }
+        state.metrics.nodes_written.add(nodes.len() as u64);
+        state.metrics.edges_written.add(edges.len() as u64);
+        let mut added = Added {
+            nodes: nodes.len(),
+            edges: edges.len(),
+            ..Default::default()
+        };
+        for node in &nodes {
+            *added.by_kind.entry(node.kind.clone()).or_insert(0) += 1;
+        }
+        total.extend(&added);
+        added.by_kind.clear();
+        if parsed.contains(&path) {
+            progress.advance(1, "uploading", format!("Stored {}", file));
+        }
+        progress.stored(file, added);
+    }
+    batches.finish().await?;
+    let (node_count, edge_count) = state
+        .storage
+        .graph_size(Some(repo_id))
+        .await
+        .map_err(MeshError::Storage)?;
+    Ok(Written {
+        nodes: node_count,
+        edges: edge_count,
+        parsed: parsed.len(),
+        skipped: progress
+            .total()
+            .map_or(0, |total| total.saturating_sub(parsed.len())),
+        failed,
+        added: total,
+    })
+}
+
+/// Running totals for the repo's [`GraphLimit`](limits::GraphLimit), `None`
+/// when there is none. An ingest of some `files` adds to what is stored,
+/// whose old records for those files are gone by now; a full one rewrites
+/// everything, so it starts from nothing.
+async fn graph_budget(
+    state: &AppState,
+    repo_id: &str,
+    files: &[String],
+) -> Result<Option<limits::GraphBudget>> {
+    if state.graph_limit.is_unlimited() {
+        return Ok(None);
+    }
+    let (nodes, edges) = if files.is_empty() {
+        (0, 0)
+    } else {
+        state
+            .storage
+            .graph_size(Some(repo_id))
+            .await
+            .map_err(MeshError::Storage)?
+    };
+    Ok(Some(state.graph_limit.budget(nodes, edges)))
+}
+
+/// Stops an ingest that would outgrow the repo's limit. With `rollback`,
+/// when everything stored is the ingest's own, the repo is cleared; a
+/// partial ingest keeps the files it stored so far, with the repo hash left
+/// where it was so the next ingest diffs them again. Either way the ingest
+/// counts as failed, which `/repos` shows.
+async fn too_large(
+    state: &AppState,
+    progress: &Progress,
+    repo_id: &str,
+    rollback: bool,
+    reason: String,
+) -> MeshError {
+    let message = if !rollback {
+        format!(
+            "Ingest of {} stopped, leaving it incomplete: {}",
+            repo_id, reason
+        )
+    } else if let Err(e) = state.storage.clear(Some(repo_id)).await {
+        error!("Failed to remove the partial graph of {}: {:#}", repo_id, e);
+        format!(
+            "Ingest of {} stopped, leaving part of it stored: {}",
+            repo_id, reason
+        )
+    } else {
+        // nothing is left to resume
+        if let Err(e) = checkpoints::finish(state.storage.as_ref(), repo_id).await {
+            error!("Failed to drop the checkpoint of {}: {:#}", repo_id, e);
+        }
+        format!("Ingest of {} stopped and rolled back: {}", repo_id, reason)
+    };
+    progress.too_large(message.clone());
+    MeshError::TooLarge(message)
+}
+
+/// What `write_graph` stored.
+#[derive(Default)]
+struct Written {
+    nodes: usize,
+    edges: usize,
+    /// Files a parser produced nodes for.
+    parsed: usize,
+    /// Files counted for the ingest that no parser produced nodes for.
+    skipped: usize,
+    /// Repo-relative files whose writes failed and were rolled back.
+    failed: Vec<String>,
+    /// What was written, kinds included.
+    added: Added,
+}
+
+/// A file's nodes and its edges, each with the file of its other end.
+type FileRecords = (Vec<NodeRecord>, Vec<(Option<String>, EdgeRecord)>);
+
+/// Each file's nodes, with the edges to write in the same transaction. An
+/// edge goes with whichever of its endpoints' files is written last, so both
+/// ends are stored by then, and one between nodes of earlier ingests goes
+/// under "", written first. Each edge carries the file of its other end,
+/// when that's written earlier in the ingest, so the writer can leave it out
+/// if that file failed.
+fn by_file(nodes: Vec<NodeRecord>, edges: Vec<EdgeRecord>) -> BTreeMap<String, FileRecords> {
+    let file_of: HashMap<&str, &str> = nodes
+        .iter()
+        .map(|n| (n.id.as_str(), n.file.as_str()))
+        .collect();
+    let mut files: BTreeMap<String, FileRecords> = BTreeMap::new();
+    for edge in edges {
+        let source = file_of.get(edge.source.as_str()).copied();
+        let target = file_of.get(edge.target.as_str()).copied();
+        let file = source.max(target).unwrap_or_default();
+        let other = match (source, target) {
+            (Some(source), Some(target)) => Some(source.min(target).to_string()),
+            _ => None,
+        };
+        files
+            .entry(file.to_string())
+            .or_default()
+            .1
+            .push((other, edge));
+    }
+    for node in nodes {
+        files.entry(node.file.clone()).or_default().0.push(node);
+    }
+    files
+}
+
+/// Writes one file's records, and deletes the `vanished` nodes it no longer
+/// has, in a transaction rolled back if any write fails, so a failed file
+/// leaves nothing behind and loses nothing.
+async fn write_file(
+    mut tx: Box<dyn Transaction>,
+    repo_id: &str,
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    vanished: &[String],
+) -> anyhow::Result<()> {
+    let written = match tx.upsert_nodes(nodes).await {
+        Ok(()) => match tx.upsert_edges(edges).await {
+            Ok(()) if !vanished.is_empty() => tx.delete_nodes(repo_id, vanished).await,
+            written => written,
+        },
+        Err(e) => Err(e),
+    };
+    match written {
+        Ok(()) => tx.commit().await,
+        Err(e) => {
+            if let Err(rollback) = tx.rollback().await {
+                error!("Rolling back failed writes failed too: {:#}", rollback);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// The response to a dry run, with the counts a real run would have written.
+fn dry_run_response(nodes: usize, edges: usize) -> ProcessResponse {
+    ProcessResponse {
+        status: "dry_run".to_string(),
+        message: "Nothing was written; see the dry_run events for a summary".to_string(),
+        nodes,
+        edges,
+    }
+}
+
+// how many skipped files a dry run names before just counting the rest
+const DRY_RUN_LISTED: usize = 20;
+
+/// What a dry run found, worked out from the records a real run would write.
+struct DryRun {
+    files: usize,
+    /// Parsed files per language, by name.
+    languages: BTreeMap<String, usize>,
+    /// Files on disk that `ast` and the plugins produced nothing for.
+    skipped: Vec<String>,
+    diagnostics: usize,
+}
+
+impl DryRun {
+    async fn new(
+        state: &AppState,
+        repo_path: &str,
+        nodes: &[NodeRecord],
+        diagnostics: &[Diagnostic],
+    ) -> Result<Self> {
+        let parsed: HashSet<&str> = nodes
+            .iter()
+            .filter(|n| n.kind == "File")
+            .map(|n| repo_relative(&n.file, repo_path))
+            .collect();
+        let mut languages = BTreeMap::new();
+        for file in &parsed {
+            let language = local::detect_language(Path::new(repo_path), file)
+                .map(str::to_string)
+                .or_else(|| {
+                    state
+                        .languages
+                        .resolve(Path::new(file))
+                        .map(|p| p.name().to_string())
+                })
+                .unwrap_or_else(|| "other".to_string());
+            *languages.entry(language).or_insert(0) += 1;
+        }
+        let root = PathBuf::from(repo_path);
+        let on_disk = tokio::task::spawn_blocking(move || {
+            if root.is_dir() {
+                local::walk(&root)
+            } else {
+                Ok(Vec::new())
+            }
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Directory walk panicked: {}", e))??;
+        let skipped = on_disk
+            .into_iter()
+            .filter(|f| !parsed.contains(f.as_str()))
+            .collect();
+        Ok(DryRun {
+            files: parsed.len(),
+            languages,
+            skipped,
+            diagnostics: diagnostics.len(),
+        })
+    }
+
+    fn report(
+        &self,
+        state: &AppState,
+        progress: &Progress,
+        repo_id: &str,
+        nodes: usize,
+        edges: usize,
+    ) {
+        let languages: Vec<String> = self
+            .languages
+            .iter()
+            .map(|(language, files)| format!("{} ({})", language, files))
+            .collect();
+        let mut skipped = self.skipped[..self.skipped.len().min(DRY_RUN_LISTED)].join(", ");
+        if self.skipped.len() > DRY_RUN_LISTED {
+            skipped.push_str(&format!(
+                " and {} more",
+                self.skipped.len() - DRY_RUN_LISTED
+            ));
+        }
+        let lines = [
+            format!("Would parse {} files", self.files),
+            format!("Languages detected: {}", languages.join(", ")),
+            format!("Skipped {} files: {}", self.skipped.len(), skipped),
+            format!("{} parse diagnostics", self.diagnostics),
+        ];
+        for line in lines {
+            send_status(state, repo_id, "dry_run", line);
+        }
+        progress.finish(format!(
+            "Dry run: would store {} nodes and {} edges",
+            nodes, edges
+        ));
+    }
+}
+
+/// Reports why an ingest stopped early and returns the matching error.
+fn stopped(state: &AppState, progress: &Progress) -> MeshError {
+    if state.shutdown.is_cancelled() {
+        progress.abort("Shutting down, ingest stopped before completion".to_string());
+        MeshError::Aborted("ingest aborted by shutdown".to_string())
+    } else {
+        progress.cancel("Ingest cancelled".to_string());
+        MeshError::Cancelled("ingest cancelled".to_string())
+    }
+}
+
+/// File-to-file `IMPORTS` edges, `EXTENDS` and `IMPLEMENTS` edges between
+/// types, and `TESTS` edges from tests to what they call. A `partial` batch
+/// only holds some of the repo's files, so it is resolved against what is
+/// already stored too, which also restores edges into files that were just
+/// re-parsed.
+async fn derived_edges(
+    state: &AppState,
+    repo_id: &str,
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    partial: bool,
+) -> Result<Vec<EdgeRecord>> {
+    let derive = |nodes: &[NodeRecord], edges: &[EdgeRecord]| {
+        let mut derived = imports::import_edges(nodes, repo_id);
+        derived.extend(hierarchy::inheritance_edges(nodes, repo_id));
+        derived.extend(coverage::test_edges(nodes, edges, repo_id));
+        derived
+    };
+    if !partial {
+        return Ok(derive(nodes, edges));
+    }
+    let (mut known, mut known_edges) = state
+        .storage
+        .load_graph(Some(repo_id))
+        .await
+        .map_err(MeshError::Storage)?;
+    // the stored copies of re-parsed nodes would make their names ambiguous
+    let fresh: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+    known.retain(|n| !fresh.contains(n.id.as_str()));
+    known.extend(nodes.iter().cloned());
+    known_edges.retain(|e| !fresh.contains(e.source.as_str()));
+    known_edges.extend(edges.iter().cloned());
+    Ok(derive(&known, &known_edges))
+}
+
+/// What the commit an ingest of `subdir` got to is stored under, so
+/// ingesting the whole repo, or another subdir, later isn't taken as done.
+fn subdir_hash_key(key: &str, subdir: Option<&str>) -> String {
+    match subdir {
+        Some(subdir) => format!("{}#{}", key, subdir),
+        None => key.to_string(),
+    }
+}
+
+fn file_filter(state: &AppState, body: &ProcessBody) -> Result<FileFilter> {
+    let mut filter = FileFilter::new(&body.include_langs, &body.exclude_globs, &state.languages)
+        .map_err(|e| MeshError::Validation(format!("{:#}", e)))?;
+    if let Some(subdir) = &body.subdir {
+        filter.within(subdir);
+    }
+    Ok(filter)
+}
+
+/// Narrows `candidates` to what `filter` and the repo's `.meshignore` allow,
+/// leaving out files too large to parse, that aren't text or that aren't
+/// UTF-8 when the checkout isn't a clone of the server's own; when
+/// `candidates` is empty (the whole repo) the matching files are listed from
+/// disk, cloning first if the repo isn't there yet. The ignore file and the
+/// skipped files join `filter`, so callers can tell a narrowed selection from
+/// the whole repo. Returns `candidates` untouched when nothing narrows them,
+/// and the diagnostics of the files skipped.
+#[allow(clippy::too_many_arguments)]
+async fn select_files(
+    state: &AppState,
+    filter: &mut FileFilter,
+    repo_id: &str,
+    repo_url: &str,
+    repo_path: &str,
+    credentials: &Credentials,
+    candidates: Vec<String>,
+) -> Result<(Vec<String>, Vec<Diagnostic>)> {
+    let root = Path::new(repo_path);
+    if candidates.is_empty() && !root.is_dir() && !repo_url.is_empty() {
+        clone_into(
+            state,
+            repo_url,
+            root,
+            credentials,
+            None,
+            &clone::CloneScope::full(),
+        )
+        .await?;
+    }
+    if let Some(subdir) = filter.subdir().filter(|_| root.is_dir()) {
+        local::check_subdir(root, subdir).map_err(|e| MeshError::Validation(format!("{:#}", e)))?;
+    }
+    filter
+        .ignore_file(root)
+        .map_err(|e| MeshError::Validation(format!("{:#}", e)))?;
+    let listed = if !candidates.is_empty() {
+        candidates.clone()
+    } else if root.is_dir() {
+        let walk_root = root.to_path_buf();
+        let subdir = filter.subdir().unwrap_or_default().to_string();
+        tokio::task::spawn_blocking(move || local::walk_under(&walk_root, &subdir))
+            .await
+            .map_err(|e| anyhow::anyhow!("Directory walk panicked: {}", e))??
+    } else {
+        // nothing on disk to narrow; `ast` reports the missing checkout
+        return Ok((candidates, Vec::new()));
+    };
+    let mut files = if filter.is_empty() {
+        listed
+    } else {
+        filter.apply_in(root, listed)
+    };
+    let transcode = is_clone(state, repo_url, repo_path);
+    let skipped = skip_unparseable(state, repo_id, root, &mut files, transcode).await?;
+    filter.skip(skipped.iter().map(|d| d.file.clone()));
+    if filter.is_empty() {
+        return Ok((candidates, skipped));
+    }
+    Ok((files, skipped))
+}
+
+/// Takes the files over `max_file_bytes`, or that aren't text, out of
+/// `files` and reports each one as a `warning`. Text in another encoding is
+/// rewritten as UTF-8 when `transcode` is set, for checkouts the server made
+/// itself, and reported as `transcoded`; otherwise it is skipped too, since a
+/// directory on disk isn't the server's to change. Returns a diagnostic for
+/// each file taken out, for [`write_graph`] to store.
+async fn skip_unparseable(
+    state: &AppState,
+    repo_id: &str,
+    root: &Path,
+    files: &mut Vec<String>,
+    transcode: bool,
+) -> Result<Vec<Diagnostic>> {
+    let root = root.to_path_buf();
+    let max_bytes = state.max_file_bytes;
+    let listed = std::mem::take(files);
+    let (kept, skipped, transcoded) = tokio::task::spawn_blocking(move || {
+        let (mut skipped, mut transcoded) = (Vec::new(), Vec::new());
+        let kept = listed
+            .into_iter()
+            .filter(|file| {
+                let checked = match local::unparseable(&root, file, max_bytes) {
+                    Some(reason) => Err(reason),
+                    None => local::check_encoding(&root, file, transcode),
+                };
+                match checked {
+                    Ok(Some(encoding)) => {
+                        transcoded.push((file.clone(), encoding));
+                        true
+                    }
+                    Ok(None) => true,
+                    Err(reason) => {
+                        skipped.push((reason.diagnostic(&root, file), reason));
+                        false
+                    }
+                }
+            })
+            .collect();
+        (kept, skipped, transcoded)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("File check panicked: {}", e))?;
+    *files = kept;
+    for (file, encoding) in &transcoded {
+        send_status(
+            state,
+            repo_id,
+            "transcoded",
+            format!("Transcoded {} from {} to UTF-8", file, encoding),
+        );
+    }
+    for (diagnostic, reason) in &skipped {
+        send_status(
+            state,
+            repo_id,
+            "warning",
+            format!("Skipped {}: {}", diagnostic.file, reason),
+        );
+    }
+    Ok(skipped
+        .into_iter()
+        .map(|(diagnostic, _)| diagnostic)
+        .collect())
+}
+
+/// What's left to parse once unchanged files are taken out.
+struct Cached {
+    /// The files to parse; empty still means the whole repo.
+    parse: Vec<String>,
+    /// Files whose content matches the hash stored when they were last parsed.
+    unchanged: HashSet<String>,
+    /// Hashes of the files being parsed, and of the directories of the
+    /// checkout, to store once they are written.
+    hashes: Vec<(String, String)>,
+}
+
+/// Hashes the files about to be parsed, or every file of the checkout when
+/// `files` is empty, and takes out those whose content hasn't changed since
+/// they were last parsed, reporting each one as `cached`. With `force` nothing
+/// is taken out, but the hashes are still recorded for next time. A repo
+/// `ast` still has to clone isn't cached.
+///
+/// When the whole checkout is walked, directories whose
+/// [`local::directory_hashes`] match those stored are skipped first, without
+/// walking or hashing what's under them, and reported as `cached` once each.
+async fn skip_cached(
+    state: &AppState,
+    repo_id: &str,
+    repo_path: &str,
+    files: Vec<String>,
+    force: bool,
+    pristine: bool,
+) -> Result<Cached> {
+    let root = PathBuf::from(repo_path);
+    if !root.is_dir() {
+        return Ok(Cached {
+            parse: files,
+            unchanged: HashSet::new(),
+            hashes: Vec::new(),
+        });
+    }
+    let stored = if force {
+        HashMap::new()
+    } else {
+        state
+            .storage
+            .file_hashes(repo_id)
+            .await
+            .map_err(MeshError::Storage)?
+    };
+    let candidates = files.clone();
+    let known = stored.clone();
+    let (candidates, mut hashes, skipped) = tokio::task::spawn_blocking(move || {
+        if !candidates.is_empty() {
+            let hashes = local::hash_files(&root, &candidates);
+            return anyhow::Ok((candidates, hashes, BTreeSet::new()));
+        }
+        let directories = local::directory_hashes(&root, pristine);
+        // the outermost directories unchanged since they were last stored
+        let mut skipped: BTreeSet<String> = BTreeSet::new();
+        for (dir, hash) in &directories {
+            let within = skipped.iter().any(|s| local::covers(s, dir));
+            if !within && known.get(dir) == Some(hash) {
+                skipped.insert(dir.clone());
+            }
+        }
+        let candidates = local::walk_except(&root, &skipped)?;
+        let mut hashes = local::hash_files(&root, &candidates);
+        hashes.extend(directories);
+        anyhow::Ok((candidates, hashes, skipped))
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("File hashing panicked: {}", e))??;
+
+    let mut unchanged: HashSet<String> = hashes
+        .iter()
+        .filter(|(file, hash)| !file.ends_with('/') && stored.get(file) == Some(hash))
+        .map(|(file, _)| file.clone())
+        .collect();
+    for dir in &skipped {
+        let files: Vec<&String> = stored
+            .keys()
+            .filter(|f| !f.ends_with('/') && local::covers(dir, f))
+            .collect();
+        send_status(
+            state,
+            repo_id,
+            "cached",
+            format!(
+                "{} is unchanged, reusing the graph of its {} files",
+                dir,
+                files.len()
+            ),
+        );
+        unchanged.extend(files.into_iter().cloned());
+    }
+    // skipped directories keep the hashes already stored for them
+    hashes.retain(|(key, _)| !skipped.iter().any(|s| local::covers(s, key)));
+    if unchanged.is_empty() {
+        return Ok(Cached {
+            parse: files,
+            unchanged,
+            hashes,
+        });
+    }
+    for file in unchanged
+        .iter()
+        .filter(|f| !skipped.iter().any(|s| local::covers(s, f)))
+    {
+        send_status(
+            state,
+            repo_id,
+            "cached",
+            format!("{} is unchanged, reusing its graph", file),
+        );
+    }
+    let parse = candidates
+        .into_iter()
+        .filter(|f| !unchanged.contains(f))
+        .collect();
+    let hashes = hashes
+        .into_iter()
+        .filter(|(f, _)| !unchanged.contains(f))
+        .collect();
+    Ok(Cached {
+        parse,
+        unchanged,
+        hashes,
+    })
+}
+
+/// The file-count pass that fixes `total` before parsing starts; `None` when
+/// the repo isn't on disk yet.
+async fn count_files(repo_path: &str) -> Result<Option<usize>> {
+    let root = repo_path.to_string();
+    let count = tokio::task::spawn_blocking(move || {
+        if !Path::new(&root).is_dir() {
+            return None;
+        }
+        let files = ignore::WalkBuilder::new(&root)
+            .add_custom_ignore_filename(local::IGNORE_FILE)
+            .build()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .count();
+        Some(files)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("File count panicked: {}", e))?;
+    Ok(count)
+}
+
+/// Runs the registered language plugins and custom queries over `files`, or
+/// the whole repo when `files` is empty. Listed files neither claims, or that
+/// no longer exist, are skipped without being read.
+async fn extract_plugins(
+    state: &AppState,
+    repo_path: &str,
+    files: &[String],
+) -> Result<Extraction> {
+    if state.languages.is_empty() {
+        return Ok(Extraction::default());
+    }
+    let languages = state.languages.clone();
+    let root = repo_path.to_string();
+    let files = files.to_vec();
+    let extracted = tokio::task::spawn_blocking(move || {
+        if files.is_empty() {
+            return languages.extract_dir(&root);
+        }
+        let files: Vec<(PathBuf, String)> = files
+            .into_iter()
+            .map(|file| (Path::new(&root).join(&file), file))
+            .filter(|(path, _)| languages.claims(path) && path.is_file())
+            .collect();
+        languages.extract_files(&files)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Plugin extraction panicked: {}", e))??;
+    Ok(extracted)
+}
+
+/// [`enrich_file`] for every file of `nodes`.
+async fn enrich(
+    state: &AppState,
+    repo_path: &str,
+    mut nodes: Vec<NodeRecord>,
+) -> Result<Vec<NodeRecord>> {
+    let root = repo_path.to_string();
+    let timeout = state.languages.parse_timeout();
+    let nodes = tokio::task::spawn_blocking(move || {
+        nodes.sort_by(|a, b| a.file.cmp(&b.file));
+        for file in nodes.chunk_by_mut(|a, b| a.file == b.file) {
+            let path = file[0].file.clone();
+            enrich_file(&root, &path, file, timeout);
+        }
+        nodes
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Complexity measurement panicked: {}", e))?;
+    Ok(nodes)
+}
+
+/// Fills in the spans `ast` leaves out, by finding each node's body on its
+/// start line in the checkout, and stores the measures of
+/// [`complexity::annotate`] in the function and file nodes of `file`, the
+/// written types of [`typing::annotate`], the visibility of
+/// [`visibility::annotate`], the documentation of [`docs::annotate`] and the
+/// [`symbols::qualified_name`] in its symbols, and its language in its node,
+/// reading and parsing it once. A file that can't be read any more is left
+/// as it is; one that doesn't parse within `timeout` only gets what needs no
+/// syntax tree.
+fn enrich_file(root: &str, file: &str, nodes: &mut [NodeRecord], timeout: Option<Duration>) {
+    let rel = repo_relative(file, root);
+    let Ok(source) = encoding::read(&Path::new(root).join(rel)).map(|d| d.text) else {
+        return;
+    };
+    for node in nodes.iter_mut() {
+        if node.span.is_none() {
+            node.span = Span::locate(&source, node.start, &node.body);
+        }
+        if let Some(fqn) = symbols::qualified_name(rel, node) {
+            node.meta.insert(symbols::FQN.to_string(), fqn);
+        }
+        if let Some(version) = grammars::version_for(Path::new(rel)) {
+            node.meta
+                .insert(grammars::GRAMMAR_VERSION.to_string(), version.to_string());
+        }
+    }
+    let tree = match Grammar::for_path(Path::new(rel)).map(|g| g.parse(&source, timeout)) {
+        Some(Ok(Some(tree))) => Some(tree),
+        Some(Ok(None)) => {
+            warn!("Parsing {} timed out, so its symbols are left bare", rel);
+            None
+        }
+        Some(Err(e)) => {
+            warn!("Failed to parse {}: {:#}", rel, e);
+            None
+        }
+        None => None,
+    };
+    let tree = tree.as_ref();
+    let language = filter::detect_language(Path::new(rel), source.as_bytes());
+    let mut measured: Vec<&mut NodeRecord> = nodes
+        .iter_mut()
+        .filter(|n| n.kind == "Function" || n.kind == "File")
+        .collect();
+    for node in measured.iter_mut().filter(|n| n.kind == "File") {
+        let language = language.unwrap_or(stats::OTHER).to_string();
+        node.meta.insert(stats::LANGUAGE.to_string(), language);
+    }
+    if let Err(e) = complexity::annotate(rel, &source, tree, measured) {
+        warn!("Failed to measure the complexity of {}: {:#}", rel, e);
+    }
+    if let Err(e) = typing::annotate(rel, &source, tree, nodes.iter_mut()) {
+        warn!("Failed to read the types of {}: {:#}", rel, e);
+    }
+    if let Err(e) = visibility::annotate(rel, &source, tree, nodes.iter_mut()) {
+        warn!("Failed to read the visibility of {}: {:#}", rel, e);
+    }
+    if let Err(e) = docs::annotate(rel, &source, tree, nodes.iter_mut()) {
+        warn!("Failed to read the documentation of {}: {:#}", rel, e);
+    }
+}
+
+/// Stores the embeddings of [`AppState::embedder`] in `nodes`, when there is
+/// one. Symbols it fails on are kept without theirs, and one `warning` says
+/// how many there were.
+async fn embed(state: &AppState, repo_id: &str, nodes: &mut [NodeRecord]) {
+    let Some(embedder) = &state.embedder else {
+        return;
+    };
+    let report =
+        embeddings::embed_nodes(embedder.as_ref(), nodes, embeddings::DEFAULT_CONCURRENCY).await;
+    if let Some(error) = report.first_error {
+        send_status(
+            state,
+            repo_id,
+            "warning",
+            format!(
+                "Embedded {} symbols; {} were kept without an embedding: {}",
+                report.embedded,
+                report.failed.len(),
+                error
+            ),
+        );
+    }
+}
+
+/// Adds the monorepo's sub-projects of [`projects::detect`] to `nodes`,
+/// tagging their files, and returns the edges to those files.
+async fn detect_projects(
+    repo_path: &str,
+    repo_id: &str,
+    mut nodes: Vec<NodeRecord>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let root = repo_path.to_string();
+    let repo_id = repo_id.to_string();
+    let detected = tokio::task::spawn_blocking(move || {
+        let files = nodes
+            .iter_mut()
+            .filter(|n| n.kind == "File")
+            .map(|n| {
+                let rel = repo_relative(&n.file, &root).to_string();
+                (n, rel)
+            })
+            .collect();
+        let submodules = clone::submodules(Path::new(&root));
+        let (projects, edges) = projects::detect(Path::new(&root), &repo_id, files, &submodules);
+        nodes.extend(projects);
+        (nodes, edges)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Project detection panicked: {}", e))?;
+    Ok(detected)
+}
+
+/// Adds the decorators, annotations and attributes of
+/// [`annotations::detect`] to `nodes`, and returns the `ANNOTATED_BY` edges
+/// to them.
+async fn detect_annotations(
+    state: &AppState,
+    repo_path: &str,
+    repo_id: &str,
+    mut nodes: Vec<NodeRecord>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let root = PathBuf::from(repo_path);
+    let repo_id = repo_id.to_string();
+    let timeout = state.languages.parse_timeout();
+    let detected = tokio::task::spawn_blocking(move || {
+        let (annotations, edges) = annotations::detect(&root, &repo_id, &nodes, timeout);
+        nodes.extend(annotations);
+        (nodes, edges)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Annotation detection panicked: {}", e))?;
+    Ok(detected)
+}
+
+/// Adds the components of [`components::detect`] to `nodes`, and returns
+/// the edges between them.
+async fn detect_components(
+    state: &AppState,
+    repo_path: &str,
+    repo_id: &str,
+    mut nodes: Vec<NodeRecord>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let root = PathBuf::from(repo_path);
+    let repo_id = repo_id.to_string();
+    let timeout = state.languages.parse_timeout();
+    let detected = tokio::task::spawn_blocking(move || {
+        let (components, edges) = components::detect(&root, &repo_id, &nodes, timeout);
+        nodes.extend(components);
+        (nodes, edges)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Component detection panicked: {}", e))?;
+    Ok(detected)
+}
+
+/// Adds the tables and columns of [`sql::detect`] to `nodes`, and returns
+/// the edges to them from the code querying them.
+async fn detect_sql(
+    state: &AppState,
+    repo_path: &str,
+    repo_id: &str,
+    mut nodes: Vec<NodeRecord>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let root = PathBuf::from(repo_path);
+    let repo_id = repo_id.to_string();
+    let timeout = state.languages.parse_timeout();
+    let detected = tokio::task::spawn_blocking(move || {
+        let (tables, edges) = sql::detect(&root, &repo_id, &nodes, timeout);
+        nodes.extend(tables);
+        (nodes, edges)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("SQL detection panicked: {}", e))?;
+    Ok(detected)
+}
+
+/// Adds the task markers of [`tasks::detect`] to `nodes`, and returns the
+/// `HAS_TASK` edges to them.
+async fn detect_tasks(
+    state: &AppState,
+    repo_path: &str,
+    repo_id: &str,
+    mut nodes: Vec<NodeRecord>,
+    rules: Arc<TaskRules>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let root = PathBuf::from(repo_path);
+    let repo_id = repo_id.to_string();
+    let timeout = state.languages.parse_timeout();
+    let detected = tokio::task::spawn_blocking(move || {
+        let (found, edges) = tasks::detect(&root, &repo_id, &nodes, &rules, timeout);
+        nodes.extend(found);
+        (nodes, edges)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task detection panicked: {}", e))?;
+    Ok(detected)
+}
+
+/// Adds the operations and messages of [`interfaces::detect`] to `nodes`,
+/// and returns the edges between them and to them from their handlers.
+async fn detect_interfaces(
+    repo_path: &str,
+    repo_id: &str,
+    mut nodes: Vec<NodeRecord>,
+    edges: &[EdgeRecord],
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let root = PathBuf::from(repo_path);
+    let repo_id = repo_id.to_string();
+    let edges = edges.to_vec();
+    let detected = tokio::task::spawn_blocking(move || {
+        let (definitions, edges) = interfaces::detect(&root, &repo_id, &nodes, &edges);
+        nodes.extend(definitions);
+        (nodes, edges)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Interface detection panicked: {}", e))?;
+    Ok(detected)
+}
+
+/// Warns about the files skipped for parsing too long; the rest of the repo
+/// is written as usual.
+fn report_timeouts(state: &AppState, repo_id: &str, files: &[String]) {
+    for file in files {
+        send_status(
+            state,
+            repo_id,
+            "warning",
+            format!("Skipped {}: parsing timed out", file),
+        );
+    }
+}
+
+/// Reports each diagnostic as a `diagnostic` status and stores them in place
+/// of those left by the last parse of `files`, or of the whole repo.
+async fn store_diagnostics(
+    state: &AppState,
+    repo_id: &str,
+    files: &[String],
+    diagnostics: &[Diagnostic],
+) -> Result<()> {
+    for d in diagnostics {
+        send_status(
+            state,
+            repo_id,
+            "diagnostic",
+            format!(
+                "{}: {} at bytes {}..{}: {}",
+                d.file,
+                d.severity.as_str(),
+                d.start,
+                d.end,
+                d.message
+            ),
+        );
+    }
+    state
+        .storage
+        .replace_diagnostics(repo_id, files, diagnostics)
+        .await
+        .map_err(MeshError::Storage)
+}
+
+/// Stores why each file [`skip_unparseable`] took out wasn't parsed, in place
+/// of what it had before. Each was already reported as a `warning`.
+async fn store_skipped(state: &AppState, repo_id: &str, skipped: &[Diagnostic]) -> Result<()> {
+    if skipped.is_empty() {
+        return Ok(());
+    }
+    let files: Vec<String> = skipped.iter().map(|d| d.file.clone()).collect();
+    state
+        .storage
+        .replace_diagnostics(repo_id, &files, skipped)
+        .await
+        .map_err(MeshError::Storage)
+}
+
+/// Marks `ingest` as succeeded and records what it was made from for `/repos`:
+/// the remote and whatever the checkout at `repo_path` has out. Then sends
+/// the ingest's one `complete` event, summing up what was `written` since
+/// `started`.
+#[allow(clippy::too_many_arguments)]
+async fn finish_ingest(
+    state: &AppState,
+    progress: &Progress,
+    ingest: IngestGuard,
+    repo_id: &str,
+    repo_url: &str,
+    repo_path: &str,
+    written: &Written,
+    started: Instant,
+) -> Result<()> {
+    let path = PathBuf::from(repo_path);
+    let checked_out = tokio::task::spawn_blocking(move || clone::checked_out(&path))
+        .await
+        .ok()
+        .flatten();
+    let record = RepoRecord {
+        repo_id: repo_id.to_string(),
+        url: clone::without_credentials(repo_url),
+        git_ref: checked_out.as_ref().map(|c| c.git_ref.clone()),
+        commit: checked_out.map(|c| c.commit),
+        ingested_at: unix_now(),
+    };
+    state
+        .storage
+        .record_ingest(&record)
+        .await
+        .map_err(MeshError::Storage)?;
+    grammars::record(state.storage.as_ref(), repo_id)
+        .await
+        .map_err(MeshError::Storage)?;
+    ingest.succeeded();
+    let summary = IngestSummary {
+        files_parsed: written.parsed,
+        files_skipped: written.skipped,
+        files_failed: written.failed.len(),
+        nodes: written.nodes,
+        edges: written.edges,
+        duration_ms: started.elapsed().as_millis() as u64,
+        commit: record.commit,
+    };
+    progress.finish_ingest(
+        format!("Stored {} nodes and {} edges", written.nodes, written.edges),
+        written.added.clone(),
+        summary,
+    );
+    // kept past a restart; an ingest that fails is only logged in memory
+    if let Some(events) = state.tx.ingest_log(repo_id) {
+        let events: Vec<String> = events.iter().map(|e| e.to_log_json().to_string()).collect();
+        if let Err(e) = state.storage.record_ingest_log(repo_id, &events).await {
+            warn!("failed to store the ingest log of {}: {:#}", repo_id, e);
+        }
+    }
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Sends a status update for `repo_id`, with any credentials in URLs in the
+/// message dropped.
+fn send_status(state: &AppState, repo_id: &str, status: &str, message: String) {
+    let message = clone::redact_urls(&message);
+    state
+        .tx
+        .send(StatusEvent::new(status, message).for_repo(repo_id));
+}
+
+/// Removes what's stored for the repo-relative `file` of the checkout at
+/// `root`, under each path [`stored_paths`] says its nodes can have, and
+/// returns how many nodes went.
+async fn delete_stored_file(
+    state: &AppState,
+    repo_id: &str,
+    root: &str,
+    file: &str,
+) -> Result<usize> {
+    let mut removed = 0;
+    for path in stored_paths(root, file, &state.path_map) {
+        removed += state
+            .storage
+            .delete_file(repo_id, &path)
+            .await
+            .map_err(MeshError::Storage)?;
+    }
+    Ok(removed)
+}
+
+fn relative_file(file: &str) -> Result<String> {
+    let path = Path::new(file);
+    let escapes = path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if file.is_empty() || escapes {
+        return Err(MeshError::Validation(format!(
+            "file must be a path relative to the repo root: {}",
+            file
+        )));
+    }
+    Ok(file.trim_start_matches("./").to_string())
+}
+
+fn env_not_empty(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|v| !v.is_empty())
+}
+/// Where a ref's graph is stored, see [`storage::with_ref`].
+/// The repo id an ingest of `body` stores its graph under, for its logs; empty
+/// when the body names no repo, which the ingest itself then reports.
+fn ingest_id(state: &AppState, body: &ProcessBody) -> String {
+    resolve_repo(state, body)
+        .map(|(path, url, _)| scoped_repo_id(body, &url, &path))
+        .unwrap_or_default()
+}
+
+fn scoped_repo_id(body: &ProcessBody, repo_url: &str, repo_path: &str) -> String {
+    storage::with_ref(
+        &storage::repo_id(repo_url, repo_path),
+        body.git_ref.as_deref(),
+    )
+}
+
+/// `repo` narrowed to one of its refs; a ref on its own names nothing.
+fn ref_scope(repo: Option<&str>, git_ref: Option<&str>, field: &str) -> Result<Option<String>> {
+    match (repo, git_ref) {
+        (Some(repo), git_ref) => Ok(Some(storage::with_ref(repo, git_ref))),
+        (None, Some(_)) => Err(MeshError::validation(format!("'ref' needs '{}'", field))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Checks `body.git_ref` out into its own directory at `repo_path`, or
+/// brings a clone of the default branch in the clone dir up to date. A
+/// local checkout is left as it is.
+async fn fetch_checkout(
+    state: &AppState,
+    body: &ProcessBody,
+    repo_url: &str,
+    repo_path: &str,
+    credentials: &Credentials,
+) -> Result<()> {
+    if body.git_ref.is_none() && !is_clone(state, repo_url, repo_path) {
+        return Ok(());
+    }
+    clone_into(
+        state,
+        repo_url,
+        Path::new(repo_path),
+        credentials,
+        body.git_ref.as_deref(),
+        &clone::CloneScope::full(),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Whether `repo_path` is where `repo_url` is cloned to, rather than a
+/// checkout the request pointed at.
+fn is_clone(state: &AppState, repo_url: &str, repo_path: &str) -> bool {
+    !repo_url.is_empty() && Path::new(repo_path).starts_with(state.clones.root())
+}
+
+/// Clones or fetches `url` into `dest`, once the clone dir has room for it.
+async fn clone_into(
+    state: &AppState,
+    url: &str,
+    dest: &Path,
+    credentials: &Credentials,
+    git_ref: Option<&str>,
+    scope: &clone::CloneScope,
+) -> Result<clone::Fetched> {
+    state.clones.check_space().map_err(MeshError::NoSpace)?;
+    let scope = clone::CloneScope {
+        default_branches: state.default_branches.clone(),
+        ..scope.clone()
+    };
+    clone::clone_repo(
+        url,
+        dest,
+        credentials,
+        git_ref,
+        &scope,
+        state.clone_retry,
+        &state.tx,
+    )
+    .await
+    .map_err(|e| {
+        if e.auth {
+            MeshError::GitAuth(e)
+        } else {
+            MeshError::Clone(e)
+        }
+    })
+}
+
+/// What a clone authenticates with: the server credential `name` refers to,
+/// or else the token given, falling back to `USERNAME` and `PAT` from the
+/// environment.
+fn git_credentials(
+    state: &AppState,
+    name: Option<&str>,
+    username: Option<String>,
+    pat: Option<String>,
+) -> Result<Credentials> {
+    let Some(name) = name else {
+        return Ok(Credentials::token(
+            username.or_else(|| env_not_empty("USERNAME")),
+            pat.or_else(|| env_not_empty("PAT")),
+        ));
+    };
+    if pat.is_some() {
+        return Err(MeshError::validation(
+            "'credential' and 'pat' can't both be given",
+        ));
+    }
+    state
+        .git_credentials
+        .get(name)
+        .cloned()
+        .ok_or_else(|| MeshError::Validation(format!("No git credential named {}", name)))
+}
+
+/// The URL `build_graph` gets: a fetched ref, or a clone already in the
+/// clone dir, is parsed from its checkout, where `ast` would clone the
+/// default branch again somewhere else.
+fn graph_source<'a>(
+    state: &AppState,
+    body: &ProcessBody,
+    repo_url: &'a str,
+    repo_path: &str,
+) -> &'a str {
+    if body.git_ref.is_some()
+        || (is_clone(state, repo_url, repo_path) && Path::new(repo_path).is_dir())
+    {
+        ""
+    } else {
+        repo_url
+    }
+}
+
+/// Where a request may ask for a repo to be read from on disk.
+fn local_roots(state: &AppState) -> Vec<PathBuf> {
+    let mut roots = state.allowed_roots.clone();
+    roots.extend(state.clones.root().canonicalize().ok());
+    roots
+}
+
+fn resolve_repo(state: &AppState, body: &ProcessBody) -> Result<(String, String, Credentials)> {
+    let repo_path = match &body.repo_path {
+        // REPO_PATH is the operator's own; a body's may only name a directory
+        // under MESH_ALLOWED_ROOTS, as `/ingest-path`'s may, or a checkout
+        // the server cloned
+        Some(path) => Some(
+            local::check_allowed(path, &local_roots(state))
+                .map_err(|e| MeshError::Validation(format!("{:#}", e)))?
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        None => env_not_empty("REPO_PATH"),
+    };
+    let repo_url = body.repo_url.clone().or_else(|| env_not_empty("REPO_URL"));
+    let credentials = git_credentials(
+        state,
+        body.credential.as_deref(),
+        body.username.clone(),
+        body.pat.clone(),
+    )?;
+
+    if repo_path.is_none() && repo_url.is_none() {
+        return Err(MeshError::validation(
+            "Neither REPO_PATH nor REPO_URL is set in the body or environment",
+        ));
+    }
+    if let Some(git_ref) = &body.git_ref {
+        // a local checkout is ingested as it is, never switched to another ref
+        let url = repo_url.ok_or_else(|| MeshError::validation("'ref' needs a repo_url"))?;
+        let path = state.clones.path_for(&url).map_err(MeshError::Validation)?;
+        return Ok((clone::ref_path(&path, git_ref), url, credentials));
+    }
 
     if let Some(path) = repo_path {
-        Ok((path, repo_url.unwrap_or_default(), username, pat))
+        Ok((path, repo_url.unwrap_or_default(), credentials))
     } else {
         let url = repo_url.unwrap();
-        let tmp_path = Repo::get_path_from_url(&url)?;
-        Ok((tmp_path, url, username, pat))
+        let path = state.clones.path_for(&url).map_err(MeshError::Validation)?;
+        Ok((path, url, credentials))
     }
 }
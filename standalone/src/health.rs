@@ -0,0 +1,59 @@
+use crate::AppState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long `/readyz` waits on the backend.
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HealthResponse {
+    pub status: String,
+    pub backend: String,
+    /// Why the server is not ready.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Liveness: the process is up and serving requests.
+pub async fn healthz(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok".to_string(),
+        backend: state.storage.backend().to_string(),
+        reason: None,
+    })
+}
+
+/// Readiness: the storage backend answers a trivial query within the timeout,
+/// and the server isn't shutting down.
+pub async fn readyz(State(state): State<Arc<AppState>>) -> Response {
+    let reason = if state.shutdown.is_cancelled() {
+        Some("shutting down".to_string())
+    } else {
+        let timeout = state.ready_timeout;
+        match tokio::time::timeout(timeout, state.storage.ping()).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(format!("{:#}", e)),
+            Err(_) => Some(format!("backend did not answer within {:?}", timeout)),
+        }
+    };
+    let status = match reason {
+        None => StatusCode::OK,
+        Some(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let body = HealthResponse {
+        status: if reason.is_none() {
+            "ready"
+        } else {
+            "unavailable"
+        }
+        .to_string(),
+        backend: state.storage.backend().to_string(),
+        reason,
+    };
+    (status, Json(body)).into_response()
+}
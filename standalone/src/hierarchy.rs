@@ -0,0 +1,479 @@
+use crate::storage::{EdgeRecord, NodeRecord};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub const EXTENDS: &str = "EXTENDS";
+pub const IMPLEMENTS: &str = "IMPLEMENTS";
+
+/// Node kinds that can take part in a hierarchy.
+const TYPE_KINDS: &[&str] = &["Class", "Trait", "Interface", "DataModel"];
+/// Type kinds that are implemented rather than extended.
+const INTERFACE_KINDS: &[&str] = &["Trait", "Interface"];
+
+/// A type reached by following `EXTENDS` and `IMPLEMENTS` edges.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Related {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub file: String,
+    /// `EXTENDS` or `IMPLEMENTS`, from the derived type to its base.
+    pub relation: String,
+    /// Steps away from the type asked about; 1 for direct bases and subtypes.
+    pub depth: usize,
+    /// The id of the type one step closer that this one was reached through,
+    /// so each chain can be followed back.
+    pub via: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TypeHierarchy {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub file: String,
+    /// Bases, their bases and so on, nearest first.
+    pub ancestors: Vec<Related>,
+    /// Subtypes, their subtypes and so on, nearest first.
+    pub descendants: Vec<Related>,
+    /// Bases named in the declaration that don't resolve to a type in the
+    /// graph: library or external types, or names defined more than once.
+    pub unresolved: Vec<String>,
+}
+
+/// How a declaration relates a type to a base it names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Relation {
+    Extends,
+    Implements,
+    /// `class A : B` in Kotlin or Swift; which one depends on what `B` is.
+    Either,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Syntax {
+    Python,
+    /// Java, JavaScript and TypeScript: `extends` and `implements`.
+    Keywords,
+    /// Kotlin and Swift: one `:` list for both.
+    Colon,
+    Cpp,
+    Ruby,
+    Rust,
+}
+
+impl Syntax {
+    fn of(file: &str) -> Option<Self> {
+        let ext = Path::new(file).extension()?.to_str()?;
+        Some(match ext {
+            "py" => Syntax::Python,
+            "java" | "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => Syntax::Keywords,
+            "kt" | "kts" | "swift" => Syntax::Colon,
+            "cpp" | "cc" | "cxx" | "hpp" | "hh" | "h" => Syntax::Cpp,
+            "rb" => Syntax::Ruby,
+            "rs" => Syntax::Rust,
+            _ => return None,
+        })
+    }
+}
+
+/// `EXTENDS` and `IMPLEMENTS` edges from every type to the bases its
+/// declaration names, read from the type nodes' bodies (and, for Rust's
+/// `impl Trait for Type`, from the files'). A base resolves to a type of that
+/// name in the same file, else in a file the declaring file imports, else to
+/// the only one in the repo; a name that stays ambiguous gets no edge.
+pub fn inheritance_edges(nodes: &[NodeRecord], repo_id: &str) -> Vec<EdgeRecord> {
+    let types = TypeIndex::new(nodes);
+    let mut edges = BTreeSet::new();
+    for (derived, bases) in declared_bases(nodes, &types) {
+        for (name, relation) in bases {
+            let Some(base) = types.resolve(&name, derived) else {
+                continue;
+            };
+            if base.id == derived.id {
+                continue;
+            }
+            let kind = match relation {
+                Relation::Extends => EXTENDS,
+                Relation::Implements => IMPLEMENTS,
+                Relation::Either if INTERFACE_KINDS.contains(&base.kind.as_str()) => IMPLEMENTS,
+                Relation::Either => EXTENDS,
+            };
+            edges.insert((derived.id.clone(), base.id.clone(), kind));
+        }
+    }
+    edges
+        .into_iter()
+        .map(|(source, target, kind)| EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind: kind.to_string(),
+            source,
+            target,
+        })
+        .collect()
+}
+
+/// The ancestors and descendants of every type named `name`, following
+/// `EXTENDS` and `IMPLEMENTS` edges breadth first. A type reached along two
+/// paths, as with diamond inheritance, is listed once, at its nearest depth.
+pub fn hierarchy(nodes: &[NodeRecord], edges: &[EdgeRecord], name: &str) -> Vec<TypeHierarchy> {
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut bases: HashMap<&str, Vec<&EdgeRecord>> = HashMap::new();
+    let mut derived: HashMap<&str, Vec<&EdgeRecord>> = HashMap::new();
+    for edge in edges
+        .iter()
+        .filter(|e| e.kind == EXTENDS || e.kind == IMPLEMENTS)
+    {
+        bases.entry(edge.source.as_str()).or_default().push(edge);
+        derived.entry(edge.target.as_str()).or_default().push(edge);
+    }
+    let types = TypeIndex::new(nodes);
+    let mut declared: HashMap<&str, Vec<(String, Relation)>> = HashMap::new();
+    for (node, bases) in declared_bases(nodes, &types) {
+        declared.entry(node.id.as_str()).or_default().extend(bases);
+    }
+
+    nodes
+        .iter()
+        .filter(|n| n.name == name && TYPE_KINDS.contains(&n.kind.as_str()))
+        .map(|node| {
+            let ancestors = walk(node, &by_id, &bases, |e| &e.target);
+            let descendants = walk(node, &by_id, &derived, |e| &e.source);
+            let unresolved = declared
+                .get(node.id.as_str())
+                .into_iter()
+                .flatten()
+                .filter(|(base, _)| types.resolve(base, node).is_none())
+                .map(|(base, _)| base.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            TypeHierarchy {
+                id: node.id.clone(),
+                name: node.name.clone(),
+                kind: node.kind.clone(),
+                file: node.file.clone(),
+                ancestors,
+                descendants,
+                unresolved,
+            }
+        })
+        .collect()
+}
+
+fn walk<'a>(
+    start: &NodeRecord,
+    by_id: &HashMap<&str, &'a NodeRecord>,
+    next: &HashMap<&str, Vec<&'a EdgeRecord>>,
+    other_end: impl Fn(&'a EdgeRecord) -> &'a String,
+) -> Vec<Related> {
+    let mut seen = HashSet::from([start.id.as_str()]);
+    let mut queue = VecDeque::from([(start.id.as_str(), 0)]);
+    let mut out = Vec::new();
+    while let Some((id, depth)) = queue.pop_front() {
+        for edge in next.get(id).into_iter().flatten() {
+            let related = other_end(edge).as_str();
+            if !seen.insert(related) {
+                continue;
+            }
+            let Some(node) = by_id.get(related) else {
+                continue;
+            };
+            out.push(Related {
+                id: node.id.clone(),
+                name: node.name.clone(),
+                kind: node.kind.clone(),
+                file: node.file.clone(),
+                relation: edge.kind.clone(),
+                depth: depth + 1,
+                via: id.to_string(),
+            });
+            queue.push_back((related, depth + 1));
+        }
+    }
+    out
+}
+
+/// Every type with the bases its declaration names.
+fn declared_bases<'a>(
+    nodes: &'a [NodeRecord],
+    types: &TypeIndex<'a>,
+) -> Vec<(&'a NodeRecord, Vec<(String, Relation)>)> {
+    let mut out = Vec::new();
+    for node in nodes {
+        let Some(syntax) = Syntax::of(&node.file) else {
+            continue;
+        };
+        if syntax == Syntax::Rust {
+            if node.kind == "File" {
+                out.extend(rust_impls(node, types));
+            }
+            continue;
+        }
+        if !TYPE_KINDS.contains(&node.kind.as_str()) {
+            continue;
+        }
+        let bases = bases(syntax, &node.name, &node.body);
+        if !bases.is_empty() {
+            out.push((node, bases));
+        }
+    }
+    out
+}
+
+/// `impl Trait for Type` blocks in a Rust file, as `IMPLEMENTS` from the type.
+fn rust_impls<'a>(
+    file: &'a NodeRecord,
+    types: &TypeIndex<'a>,
+) -> Vec<(&'a NodeRecord, Vec<(String, Relation)>)> {
+    static IMPL: OnceLock<Regex> = OnceLock::new();
+    let re = IMPL.get_or_init(|| {
+        let impl_for = concat!(
+            r"(?m)^[ \t]*(?:unsafe\s+)?impl(?:\s*<[^{]*?>)?",
+            r"\s+([\w:]+)(?:<[^{]*?>)?\s+for\s+([\w:]+)"
+        );
+        Regex::new(impl_for).unwrap()
+    });
+    let mut out = Vec::new();
+    for capture in re.captures_iter(&file.body) {
+        let Some(derived) = types.resolve(last_segment(&capture[2]), file) else {
+            continue;
+        };
+        let base = last_segment(&capture[1]).to_string();
+        out.push((derived, vec![(base, Relation::Implements)]));
+    }
+    out
+}
+
+/// The bases named in the declaration of the type `name` in `body`.
+fn bases(syntax: Syntax, name: &str, body: &str) -> Vec<(String, Relation)> {
+    static DECLARATION: OnceLock<Regex> = OnceLock::new();
+    let re = DECLARATION.get_or_init(|| {
+        Regex::new(r"\b(?:class|interface|struct|protocol|object)\s+([A-Za-z_$][\w$]*)").unwrap()
+    });
+    let Some(start) = re
+        .captures_iter(body)
+        .find(|c| &c[1] == name)
+        .and_then(|c| c.get(0))
+    else {
+        return Vec::new();
+    };
+    let rest = &body[start.end()..];
+    match syntax {
+        Syntax::Python => {
+            let Some(list) = rest.trim_start().strip_prefix('(').and_then(inside_parens) else {
+                return Vec::new();
+            };
+            split_list(list)
+                .into_iter()
+                // `metaclass=Meta` and the like are keyword arguments, not bases
+                .filter(|item| !item.contains('='))
+                .filter_map(type_name)
+                .filter(|base| base != "object")
+                .map(|base| (base, Relation::Extends))
+                .collect()
+        }
+        Syntax::Keywords => {
+            let header = header(rest);
+            let mut out = Vec::new();
+            if let Some((_, after)) = split_keyword(header, "extends") {
+                let list = split_keyword(after, "implements").map_or(after, |(list, _)| list);
+                // an interface extending interfaces is still `extends`
+                out.extend(
+                    split_list(list)
+                        .into_iter()
+                        .filter_map(type_name)
+                        .map(|base| (base, Relation::Extends)),
+                );
+            }
+            if let Some((_, list)) = split_keyword(header, "implements") {
+                out.extend(
+                    split_list(list)
+                        .into_iter()
+                        .filter_map(type_name)
+                        .map(|base| (base, Relation::Implements)),
+                );
+            }
+            out
+        }
+        Syntax::Colon | Syntax::Cpp => {
+            let header = strip_groups(header(rest));
+            let header = split_keyword(&header, "where").map_or(header.as_str(), |(h, _)| h);
+            let Some((_, list)) = header.split_once(':') else {
+                return Vec::new();
+            };
+            let relation = if syntax == Syntax::Cpp {
+                Relation::Extends
+            } else {
+                Relation::Either
+            };
+            split_list(list)
+                .into_iter()
+                .filter_map(|item| {
+                    let item = item
+                        .split_whitespace()
+                        .filter(|w| !matches!(*w, "public" | "protected" | "private" | "virtual"))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    type_name(&item)
+                })
+                .map(|base| (base, relation))
+                .collect()
+        }
+        Syntax::Ruby => {
+            let line = rest.lines().next().unwrap_or_default();
+            line.trim_start()
+                .strip_prefix('<')
+                .and_then(type_name)
+                .map(|base| vec![(base, Relation::Extends)])
+                .unwrap_or_default()
+        }
+        Syntax::Rust => Vec::new(),
+    }
+}
+
+/// The declaration up to where its body opens.
+fn header(rest: &str) -> &str {
+    rest.split('{').next().unwrap_or(rest)
+}
+
+/// The text up to the `)` closing an already opened `(`.
+fn inside_parens(s: &str) -> Option<&str> {
+    let mut depth = 1;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&s[..i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Drops parenthesized and generic groups, e.g. Kotlin constructor parameters,
+/// calls like `Base(x)` and bounds like `<T : Comparable<T>>`, which may hold
+/// colons of their own.
+fn strip_groups(s: &str) -> String {
+    let mut out = String::new();
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '(' | '<' => depth += 1,
+            ')' | '>' => depth -= 1,
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// The text before and after `keyword` as a whole word, outside generics.
+fn split_keyword<'a>(s: &'a str, keyword: &str) -> Option<(&'a str, &'a str)> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            _ => {}
+        }
+        if depth != 0 || !s[i..].starts_with(keyword) {
+            continue;
+        }
+        let before = s[..i].chars().next_back();
+        let after = s[i + keyword.len()..].chars().next();
+        let boundary = |c: Option<char>| !c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if boundary(before) && boundary(after) {
+            return Some((&s[..i], &s[i + keyword.len()..]));
+        }
+    }
+    None
+}
+
+/// Splits on commas outside generics and brackets.
+fn split_list(s: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                out.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&s[start..]);
+    out
+}
+
+/// `pkg.Base<T>` -> `Base`.
+fn type_name(item: &str) -> Option<String> {
+    static NAME: OnceLock<Regex> = OnceLock::new();
+    let re = NAME.get_or_init(|| {
+        Regex::new(r"^\s*([A-Za-z_$][\w$]*(?:(?:\.|::)[A-Za-z_$][\w$]*)*)").unwrap()
+    });
+    let path = re.captures(item)?.get(1)?.as_str();
+    Some(last_segment(path).to_string())
+}
+
+fn last_segment(path: &str) -> &str {
+    path.rsplit(['.', ':']).next().unwrap_or(path)
+}
+
+/// Type nodes by name, with each file's import statements for resolving a
+/// name to the right definition.
+struct TypeIndex<'a> {
+    by_name: HashMap<&'a str, Vec<&'a NodeRecord>>,
+    imports: HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> TypeIndex<'a> {
+    fn new(nodes: &'a [NodeRecord]) -> Self {
+        let mut by_name: HashMap<&str, Vec<&NodeRecord>> = HashMap::new();
+        let mut imports: HashMap<&str, Vec<&str>> = HashMap::new();
+        for node in nodes {
+            if TYPE_KINDS.contains(&node.kind.as_str()) {
+                by_name.entry(node.name.as_str()).or_default().push(node);
+            } else if node.kind == "Import" {
+                imports
+                    .entry(node.file.as_str())
+                    .or_default()
+                    .push(&node.body);
+            }
+        }
+        TypeIndex { by_name, imports }
+    }
+
+    fn resolve(&self, name: &str, from: &NodeRecord) -> Option<&'a NodeRecord> {
+        let definitions = self.by_name.get(name)?;
+        let only = |found: Vec<&'a NodeRecord>| (found.len() == 1).then(|| found[0]);
+        let same_file = definitions.iter().copied().filter(|d| d.file == from.file);
+        if let Some(found) = only(same_file.collect()) {
+            return Some(found);
+        }
+        let imports = self.imports.get(from.file.as_str());
+        let imported = definitions.iter().copied().filter(|d| {
+            let stem = Path::new(&d.file)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default();
+            !stem.is_empty()
+                && imports.is_some_and(|bodies| bodies.iter().any(|b| b.contains(stem)))
+        });
+        if let Some(found) = only(imported.collect()) {
+            return Some(found);
+        }
+        only(definitions.clone())
+    }
+}
@@ -0,0 +1,242 @@
+use crate::types::MeshError;
+use axum::body::{to_bytes, Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// The request header naming a retry-safe request.
+pub const HEADER: &str = "idempotency-key";
+/// Set on a response replayed for a repeated key.
+pub const REPLAYED: &str = "idempotent-replayed";
+/// How long a result is kept for repeats of its key.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+pub const MAX_KEY_LEN: usize = 255;
+/// Largest request body read to tell a retry from another request with the
+/// same key; axum's default body limit.
+const MAX_BODY: usize = 2 * 1024 * 1024;
+
+/// A finished response, kept to be replayed.
+struct Stored {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+type Outcome = watch::Receiver<Option<Arc<Stored>>>;
+
+struct Entry {
+    /// Tells a runner's own entry from one claimed after it was given up.
+    id: u64,
+    /// Digest of the request body.
+    request: [u8; 32],
+    /// When the result stops being replayed; `None` while it is running.
+    expires: Option<Instant>,
+    outcome: Outcome,
+}
+
+/// Results of the requests sent with an `Idempotency-Key`, by route, API key
+/// and idempotency key. A repeat while the first is still running waits for
+/// it; a repeat within the TTL after gets its response again.
+pub struct Idempotency {
+    ttl: Duration,
+    next_id: AtomicU64,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+enum Claim {
+    Run(Runner),
+    Wait(Outcome),
+    /// The key was used for a request with a different body.
+    Mismatch,
+}
+
+/// The first request with a key. Dropped without [`Runner::finish`], say
+/// when its client goes away, it gives the key up for a repeat to run.
+struct Runner {
+    cache: Arc<Idempotency>,
+    scope: String,
+    id: u64,
+    outcome: watch::Sender<Option<Arc<Stored>>>,
+}
+
+impl Default for Idempotency {
+    fn default() -> Self {
+        Idempotency::new(DEFAULT_TTL)
+    }
+}
+
+impl Idempotency {
+    pub fn new(ttl: Duration) -> Self {
+        Idempotency {
+            ttl,
+            next_id: AtomicU64::new(0),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn claim(self: &Arc<Self>, scope: &str, request: [u8; 32]) -> Claim {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, e| e.expires.map_or(true, |t| t > now));
+        if let Some(entry) = entries.get(scope) {
+            if entry.request != request {
+                return Claim::Mismatch;
+            }
+            return Claim::Wait(entry.outcome.clone());
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (outcome, receiver) = watch::channel(None);
+        entries.insert(
+            scope.to_string(),
+            Entry {
+                id,
+                request,
+                expires: None,
+                outcome: receiver,
+            },
+        );
+        Claim::Run(Runner {
+            cache: self.clone(),
+            scope: scope.to_string(),
+            id,
+            outcome,
+        })
+    }
+}
+
+impl Runner {
+    /// Keeps `response` for repeats of the key, unless it asks the client to
+    /// try again later, and hands it back.
+    async fn finish(self, response: Response) -> Response {
+        let (parts, body) = response.into_parts();
+        let body = match to_bytes(body, usize::MAX).await {
+            Ok(body) => body,
+            Err(e) => {
+                return MeshError::Internal(anyhow::anyhow!("failed to read response: {}", e))
+                    .into_response()
+            }
+        };
+        let retry = parts.status == StatusCode::TOO_MANY_REQUESTS || parts.status.is_server_error();
+        if retry {
+            // dropping the runner gives the key up
+            return Response::from_parts(parts, Body::from(body));
+        }
+        let stored = Arc::new(Stored {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        });
+        if let Some(entry) = self.cache.entries.lock().unwrap().get_mut(&self.scope) {
+            if entry.id == self.id {
+                entry.expires = Some(Instant::now() + self.cache.ttl);
+            }
+        }
+        self.outcome.send_replace(Some(stored.clone()));
+        replay(&stored, false)
+    }
+}
+
+impl Drop for Runner {
+    fn drop(&mut self) {
+        if self.outcome.borrow().is_some() {
+            return;
+        }
+        let mut entries = self.cache.entries.lock().unwrap();
+        if entries.get(&self.scope).is_some_and(|e| e.id == self.id) {
+            entries.remove(&self.scope);
+        }
+    }
+}
+
+fn replay(stored: &Stored, replayed: bool) -> Response {
+    let mut response = Response::new(Body::from(stored.body.clone()));
+    *response.status_mut() = stored.status;
+    *response.headers_mut() = stored.headers.clone();
+    if replayed {
+        response
+            .headers_mut()
+            .insert(REPLAYED, HeaderValue::from_static("true"));
+    }
+    response
+}
+
+/// Middleware for the routes that start ingests. A request without an
+/// `Idempotency-Key` passes straight through; one with a key already seen
+/// gets that request's response, or a 409 when its body differs. Responses
+/// that ask for a retry, 429s and 5xxs, aren't kept.
+pub async fn dedupe(
+    State(cache): State<Arc<Idempotency>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = request.headers().get(HEADER) else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => key.to_string(),
+        _ => {
+            return MeshError::Validation(format!(
+                "Idempotency-Key must be 1 to {} visible ASCII characters",
+                MAX_KEY_LEN
+            ))
+            .into_response()
+        }
+    };
+    // keys are per client: the API key is part of the scope, hashed so it
+    // isn't held in the clear
+    let mut scope = Sha256::new();
+    for part in [
+        request.uri().path().as_bytes(),
+        request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .map_or(&[][..], |v| v.as_bytes()),
+        key.as_bytes(),
+    ] {
+        scope.update((part.len() as u64).to_le_bytes());
+        scope.update(part);
+    }
+    let scope = hex::encode(scope.finalize());
+
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_BODY).await {
+        Ok(body) => body,
+        Err(e) => return MeshError::Validation(format!("unreadable body: {}", e)).into_response(),
+    };
+    let digest: [u8; 32] = Sha256::digest(&body).into();
+    loop {
+        match cache.claim(&scope, digest) {
+            Claim::Run(runner) => {
+                let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+                return runner.finish(response).await;
+            }
+            Claim::Wait(mut outcome) => {
+                let stored = outcome
+                    .wait_for(Option::is_some)
+                    .await
+                    .ok()
+                    .and_then(|s| s.clone());
+                match stored {
+                    Some(stored) => return replay(&stored, true),
+                    // the first request gave up or asked for a retry: run
+                    // this one instead
+                    None => continue,
+                }
+            }
+            Claim::Mismatch => {
+                return MeshError::Conflict(format!(
+                    "Idempotency-Key {} was already used for a different request",
+                    key
+                ))
+                .into_response()
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::storage::{EdgeRecord, NodeRecord};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// The id a node is stored under, unique within its repo.
+pub type NodeId = String;
+
+/// How the ids of nodes are made.
+///
+/// `position` ids, the default, are those of [`node_key`](crate::storage::node_key):
+/// readable, but they hold the line a symbol starts on, so a symbol that only
+/// moved gets a new id on the next ingest, and the edges to its old one are
+/// gone with it. `stable` ids are [`id_for`]'s, which leave the line out.
+/// Switching schemes re-keys every node of a repo on its next ingest, so
+/// clear it first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdScheme {
+    #[default]
+    Position,
+    Stable,
+}
+
+impl IdScheme {
+    /// Re-keys `nodes`, read from the checkout at `root`, under this scheme,
+    /// and the ends of `edges` with them. Edges to nodes not in `nodes`, those
+    /// stored before, are left as they are.
+    pub fn apply(self, root: &str, nodes: &mut [NodeRecord], edges: &mut [EdgeRecord]) {
+        if self == IdScheme::Stable {
+            rekey(root, nodes, edges);
+        }
+    }
+}
+
+impl FromStr for IdScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "position" => Ok(IdScheme::Position),
+            "stable" => Ok(IdScheme::Stable),
+            other => Err(format!("expected position or stable, got '{}'", other)),
+        }
+    }
+}
+
+/// The stable id of `node`: its lowercased kind and a hash of its repo,
+/// which names the ref too for all but the default branch (see
+/// [`with_ref`](crate::storage::with_ref)), its file, its
+/// [`symbol_path`] and its kind. Where in the file the symbol is doesn't
+/// count, so it keeps the id when code around it moves; renaming it, moving
+/// it to another file or into another class gives it a new one.
+pub fn id_for(node: &NodeRecord) -> NodeId {
+    stable_id(&node.repo_id, &node.file, &symbol_path(node), &node.kind)
+}
+
+/// The name a symbol is known by in its file: `Class.method` for a method,
+/// and an endpoint's route with its verb, `GET /users`.
+pub fn symbol_path(node: &NodeRecord) -> String {
+    let name = match node.meta.get("operand") {
+        Some(operand) if !operand.is_empty() => format!("{}.{}", operand, node.name),
+        _ => node.name.clone(),
+    };
+    match node.meta.get("verb") {
+        Some(verb) if !verb.is_empty() => format!("{} {}", verb.to_uppercase(), name),
+        _ => name,
+    }
+}
+
+fn stable_id(repo_id: &str, file: &str, path: &str, kind: &str) -> NodeId {
+    let mut hasher = Sha256::new();
+    for part in [repo_id, file, path, kind] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!(
+        "{}-{}",
+        kind.to_lowercase(),
+        hex::encode(&hasher.finalize()[..12])
+    )
+}
+
+/// [`id_for`] over every node, with files and `File` names taken relative to
+/// `root` so the checkout's location doesn't count. Symbols that would share
+/// an id, like overloads, are told apart by their order in the file: the
+/// second gets `-2` and so on.
+fn rekey(root: &str, nodes: &mut [NodeRecord], edges: &mut [EdgeRecord]) {
+    let mut order: Vec<usize> = (0..nodes.len()).collect();
+    order.sort_by_key(|&i| (nodes[i].start, nodes[i].end));
+    let mut seen: HashMap<NodeId, usize> = HashMap::new();
+    let mut renamed: HashMap<NodeId, NodeId> = HashMap::new();
+    for i in order {
+        let node = &nodes[i];
+        let file = relative(&node.file, root);
+        let mut path = symbol_path(node);
+        if node.kind == "File" {
+            path = relative(&path, root).to_string();
+        }
+        let mut id = stable_id(&node.repo_id, file, &path, &node.kind);
+        let count = seen.entry(id.clone()).or_default();
+        *count += 1;
+        if *count > 1 {
+            id = format!("{}-{}", id, count);
+        }
+        renamed.entry(node.id.clone()).or_insert_with(|| id.clone());
+        nodes[i].id = id;
+    }
+    for edge in edges {
+        if let Some(id) = renamed.get(&edge.source) {
+            edge.source = id.clone();
+        }
+        if let Some(id) = renamed.get(&edge.target) {
+            edge.target = id.clone();
+        }
+    }
+}
+
+/// `file` without whatever leads up to the checkout at `root`.
+fn relative<'a>(file: &'a str, root: &str) -> &'a str {
+    let root = format!("{}/", root.trim_end_matches('/'));
+    match file.find(&root) {
+        Some(at) => &file[at + root.len()..],
+        None => file,
+    }
+}
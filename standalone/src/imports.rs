@@ -0,0 +1,503 @@
+use crate::storage::{EdgeRecord, NodeRecord};
+use crate::symbols::SymbolIndex;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", content = "path", rename_all = "snake_case")]
+pub enum Target {
+    /// A file in the repo, as stored on its `File` node.
+    File(String),
+    /// Nothing in the repo matches, i.e. a standard library or third-party module.
+    External(String),
+    /// A file ingested for another repo of the mesh, from [`link_imports`].
+    Repo { repo_id: String, file: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResolvedImport {
+    pub file: String,
+    /// The module as written, e.g. `./util` or `crate::storage`.
+    pub module: String,
+    pub target: Target,
+}
+
+/// Maps every import statement in the `Import` nodes to the repo file it
+/// refers to. Relative imports resolve against the importing file's directory,
+/// everything else against the repo root (or `src/` and `lib/` under it);
+/// re-exports (`pub use`, `export ... from`) are followed like imports.
+pub fn resolve_imports(nodes: &[NodeRecord]) -> Vec<ResolvedImport> {
+    let files = FileIndex::new(nodes);
+    let mut resolved = Vec::new();
+    let mut seen = BTreeSet::new();
+    for import in nodes.iter().filter(|n| n.kind == "Import") {
+        let Some(lang) = Lang::of(&import.file) else {
+            continue;
+        };
+        for module in modules(lang, &import.body) {
+            if !seen.insert((import.file.clone(), module.clone())) {
+                continue;
+            }
+            let target = match files.resolve(lang, &import.file, &module) {
+                Some(file) => Target::File(file),
+                None => Target::External(module.clone()),
+            };
+            resolved.push(ResolvedImport {
+                file: import.file.clone(),
+                module,
+                target,
+            });
+        }
+    }
+    resolved
+}
+
+/// An external package the repo imports, for `/dependencies`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Dependency {
+    /// As named by the language's package, e.g. `serde`, `requests`,
+    /// `@angular/core` or `github.com/gin-gonic/gin`.
+    pub name: String,
+    /// Files importing anything of it.
+    pub files: usize,
+    /// The modules of it imported, as written.
+    pub modules: Vec<String>,
+}
+
+/// The external packages `imports` name, by how many files use each, most
+/// used first. Imports that are relative, or that name the repo itself as
+/// `crate::` and the like do, aren't dependencies even when they don't
+/// resolve; nor are those [`link_imports`] found in another repo.
+pub fn dependencies(imports: &[ResolvedImport]) -> Vec<Dependency> {
+    let mut packages: BTreeMap<String, (BTreeSet<&str>, BTreeSet<String>)> = BTreeMap::new();
+    for import in imports {
+        let Target::External(module) = &import.target else {
+            continue;
+        };
+        let Some(package) = Lang::of(&import.file).and_then(|lang| package(lang, module)) else {
+            continue;
+        };
+        let (files, modules) = packages.entry(package).or_default();
+        files.insert(&import.file);
+        modules.insert(module.clone());
+    }
+    let mut dependencies: Vec<Dependency> = packages
+        .into_iter()
+        .map(|(name, (files, modules))| Dependency {
+            name,
+            files: files.len(),
+            modules: modules.into_iter().collect(),
+        })
+        .collect();
+    dependencies.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.name.cmp(&b.name)));
+    dependencies
+}
+
+/// The package an external `module` belongs to, or `None` when it's one of
+/// the repo's own.
+fn package(lang: Lang, module: &str) -> Option<String> {
+    if module.starts_with('.') {
+        return None;
+    }
+    let first = |separator: &str| module.split(separator).next().map(str::to_string);
+    match lang {
+        Lang::Rust => {
+            first("::").filter(|root| !["crate", "self", "super"].contains(&root.as_str()))
+        }
+        Lang::Python => first("."),
+        Lang::Script | Lang::Ruby => {
+            // aliases of the repo root, as `resolve` takes them
+            if module.starts_with("@/") || module.starts_with("~/") {
+                return None;
+            }
+            let parts = if module.starts_with('@') { 2 } else { 1 };
+            Some(
+                module
+                    .splitn(parts + 1, '/')
+                    .take(parts)
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            )
+        }
+        // `host/owner/repo` is the module path of most remote packages
+        Lang::Go if first("/").is_some_and(|host| host.contains('.')) => {
+            Some(module.splitn(4, '/').take(3).collect::<Vec<_>>().join("/"))
+        }
+        Lang::Go => Some(module.to_string()),
+        // the package, without the class or `*` imported from it
+        Lang::Jvm => {
+            let parts: Vec<&str> = module
+                .split('.')
+                .take_while(|p| !p.starts_with(char::is_uppercase) && *p != "*")
+                .collect();
+            (!parts.is_empty()).then(|| parts.join("."))
+        }
+    }
+}
+
+/// Points the external imports in `imports` that name a module of another
+/// repo in `index` at that repo's file; the rest stay external.
+pub fn link_imports(imports: &mut [ResolvedImport], index: &SymbolIndex) {
+    for import in imports {
+        let Target::External(module) = &import.target else {
+            continue;
+        };
+        if let Some(file) = index.module(module) {
+            import.target = Target::Repo {
+                repo_id: file.repo_id.clone(),
+                file: file.file.clone(),
+            };
+        }
+    }
+}
+
+/// `IMPORTS` edges between `File` nodes for every import that resolved in the repo.
+pub fn import_edges(nodes: &[NodeRecord], repo_id: &str) -> Vec<EdgeRecord> {
+    let file_ids: BTreeMap<&str, &str> = nodes
+        .iter()
+        .filter(|n| n.kind == "File")
+        .map(|n| (n.file.as_str(), n.id.as_str()))
+        .collect();
+    let mut edges = BTreeSet::new();
+    for import in resolve_imports(nodes) {
+        let Target::File(target) = &import.target else {
+            continue;
+        };
+        if target == &import.file {
+            continue;
+        }
+        if let (Some(source), Some(target)) = (
+            file_ids.get(import.file.as_str()),
+            file_ids.get(target.as_str()),
+        ) {
+            edges.insert((source.to_string(), target.to_string()));
+        }
+    }
+    edges
+        .into_iter()
+        .map(|(source, target)| EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind: "IMPORTS".to_string(),
+            source,
+            target,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lang {
+    Rust,
+    Python,
+    Script,
+    Go,
+    Ruby,
+    Jvm,
+}
+
+impl Lang {
+    fn of(file: &str) -> Option<Self> {
+        let ext = Path::new(file).extension()?.to_str()?;
+        Some(match ext {
+            "rs" => Lang::Rust,
+            "py" => Lang::Python,
+            "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => Lang::Script,
+            "go" => Lang::Go,
+            "rb" => Lang::Ruby,
+            "java" | "kt" | "kts" => Lang::Jvm,
+            _ => return None,
+        })
+    }
+
+    /// Candidate files for a module path without extension, in preference order.
+    fn candidates(&self, base: &Path) -> Vec<PathBuf> {
+        let with = |ext: &str| {
+            let mut path = base.as_os_str().to_owned();
+            path.push(ext);
+            PathBuf::from(path)
+        };
+        match self {
+            Lang::Rust => vec![with(".rs"), base.join("mod.rs")],
+            Lang::Python => vec![with(".py"), base.join("__init__.py")],
+            Lang::Script => {
+                let mut out = vec![base.to_path_buf()];
+                for ext in [".ts", ".tsx", ".js", ".jsx", ".mjs", ".cjs"] {
+                    out.push(with(ext));
+                }
+                for index in ["index.ts", "index.tsx", "index.js", "index.jsx"] {
+                    out.push(base.join(index));
+                }
+                out
+            }
+            Lang::Ruby => vec![with(".rb")],
+            Lang::Jvm => vec![with(".java"), with(".kt")],
+            Lang::Go => Vec::new(),
+        }
+    }
+}
+
+/// How a pattern's captures become a module path.
+#[derive(Clone, Copy)]
+enum Form {
+    Path,
+    /// `mod foo;` declares a child module of the current file.
+    RustMod,
+    /// `import a, b.c`
+    PythonList,
+    /// `require` or `require_relative`, then the path.
+    Ruby,
+}
+
+/// Module paths named by the import statements in `body`.
+fn modules(lang: Lang, body: &str) -> Vec<String> {
+    static PATTERNS: OnceLock<Vec<(Lang, Form, Regex)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            (
+                Lang::Rust,
+                Form::Path,
+                r"(?m)^[ \t]*(?:pub(?:\([^)]*\))?\s+)?use\s+([A-Za-z_][\w:]*)",
+            ),
+            (
+                Lang::Rust,
+                Form::RustMod,
+                r"(?m)^[ \t]*(?:pub(?:\([^)]*\))?\s+)?mod\s+(\w+)\s*;",
+            ),
+            (
+                Lang::Python,
+                Form::Path,
+                r"(?m)^[ \t]*from\s+(\.*[\w.]*)\s+import\b",
+            ),
+            (
+                Lang::Python,
+                Form::PythonList,
+                r"(?m)^[ \t]*import\s+([\w.]+(?:[ \t]*,[ \t]*[\w.]+)*)",
+            ),
+            (
+                Lang::Script,
+                Form::Path,
+                r#"\b(?:import|export)(?:[^'"`;]*?\bfrom)?\s*['"]([^'"]+)['"]"#,
+            ),
+            (
+                Lang::Script,
+                Form::Path,
+                r#"\brequire\(\s*['"]([^'"]+)['"]\s*\)"#,
+            ),
+            (
+                Lang::Go,
+                Form::Path,
+                r#"(?m)^[ \t]*(?:import\s+)?(?:[\w.]+\s+)?"([^"]+)""#,
+            ),
+            (
+                Lang::Ruby,
+                Form::Ruby,
+                r#"(?m)^[ \t]*(require(?:_relative)?)\s*\(?\s*['"]([^'"]+)['"]"#,
+            ),
+            (
+                Lang::Jvm,
+                Form::Path,
+                r"(?m)^[ \t]*import\s+(?:static\s+)?([\w.]+)",
+            ),
+        ]
+        .into_iter()
+        .map(|(lang, form, re)| (lang, form, Regex::new(re).unwrap()))
+        .collect()
+    });
+
+    let mut out = Vec::new();
+    for (_, form, re) in patterns.iter().filter(|(l, _, _)| *l == lang) {
+        for capture in re.captures_iter(body) {
+            match form {
+                // leading dots are Python's relative imports, keep them
+                Form::Path if lang == Lang::Python => out.push(capture[1].to_string()),
+                // `use a::{b, c}` and `import a.b.*` leave a trailing separator
+                Form::Path => out.push(capture[1].trim_end_matches([':', '.']).to_string()),
+                Form::RustMod => out.push(format!("self::{}", &capture[1])),
+                Form::PythonList => {
+                    let names = capture[1].split(',').map(|m| m.trim().to_string());
+                    out.extend(names);
+                }
+                Form::Ruby if &capture[1] == "require_relative" => {
+                    out.push(format!("./{}", &capture[2]))
+                }
+                Form::Ruby => out.push(capture[2].to_string()),
+            }
+        }
+    }
+    out.retain(|m| !m.is_empty());
+    out
+}
+
+/// Stored file paths, looked up exactly or by their repo-relative suffix since
+/// paths may carry the clone root.
+struct FileIndex {
+    files: BTreeSet<String>,
+}
+
+impl FileIndex {
+    fn new(nodes: &[NodeRecord]) -> Self {
+        let files = nodes
+            .iter()
+            .filter(|n| n.kind == "File")
+            .map(|n| n.file.clone())
+            .collect();
+        FileIndex { files }
+    }
+
+    fn resolve(&self, lang: Lang, from: &str, module: &str) -> Option<String> {
+        let dir = Path::new(from).parent().unwrap_or(Path::new(""));
+        match lang {
+            Lang::Rust => self.resolve_rust(from, dir, module),
+            Lang::Python => {
+                let dots = module.chars().take_while(|c| *c == '.').count();
+                let rest = module[dots..].replace('.', "/");
+                if dots > 0 {
+                    let mut base = dir.to_path_buf();
+                    for _ in 1..dots {
+                        base.pop();
+                    }
+                    return self.exact(lang, &base.join(rest));
+                }
+                self.from_root(lang, &rest)
+            }
+            Lang::Script | Lang::Ruby => {
+                if module.starts_with("./") || module.starts_with("../") {
+                    return self.exact(lang, &dir.join(module));
+                }
+                let module = module
+                    .strip_prefix("@/")
+                    .or_else(|| module.strip_prefix("~/"))
+                    .unwrap_or(module);
+                self.from_root(lang, module)
+            }
+            Lang::Jvm => self.from_root(lang, &module.replace('.', "/")),
+            Lang::Go => self.resolve_go(module),
+        }
+    }
+
+    /// `crate::`, `self::` and `super::` paths, trying shorter prefixes since
+    /// the tail is usually an item rather than a module.
+    fn resolve_rust(&self, from: &str, dir: &Path, module: &str) -> Option<String> {
+        let mut parts: Vec<&str> = module.split("::").collect();
+        let base = match parts.first().copied() {
+            Some("crate") => {
+                parts.remove(0);
+                self.crate_root(from)?
+            }
+            Some("self") | Some("super") => {
+                let name = Path::new(from).file_stem()?.to_str()?;
+                // a file's submodules live next to it for mod.rs/lib.rs/main.rs,
+                // otherwise in a directory named after it
+                let mut base = if matches!(name, "mod" | "lib" | "main") {
+                    dir.to_path_buf()
+                } else {
+                    dir.join(name)
+                };
+                while parts.first() == Some(&"super") {
+                    parts.remove(0);
+                    base.pop();
+                }
+                if parts.first() == Some(&"self") {
+                    parts.remove(0);
+                }
+                base
+            }
+            // 2018-style paths may start at a module of the crate root
+            _ => self.crate_root(from)?,
+        };
+        while !parts.is_empty() {
+            let path = parts.iter().fold(base.clone(), |p, part| p.join(part));
+            if let Some(file) = self.exact(Lang::Rust, &path) {
+                return Some(file);
+            }
+            parts.pop();
+        }
+        // a bare `super` or `self`: the module file itself
+        if module.starts_with("super") || module.starts_with("self") {
+            return self.exact(Lang::Rust, &base);
+        }
+        None
+    }
+
+    /// The `src` directory holding the crate root above `from`.
+    fn crate_root(&self, from: &str) -> Option<PathBuf> {
+        let mut dir = Path::new(from).parent();
+        while let Some(d) = dir {
+            if ["lib.rs", "main.rs"]
+                .iter()
+                .any(|root| self.files.contains(&path_str(&d.join(root))))
+            {
+                return Some(d.to_path_buf());
+            }
+            dir = d.parent();
+        }
+        None
+    }
+
+    /// Go imports name a package directory; its first file stands in for it.
+    fn resolve_go(&self, module: &str) -> Option<String> {
+        let parts: Vec<&str> = module.split('/').collect();
+        for start in 0..parts.len() {
+            let suffix = parts[start..].join("/");
+            let found = self.files.iter().find(|f| {
+                f.ends_with(".go")
+                    && !f.ends_with("_test.go")
+                    && Path::new(f.as_str()).parent().is_some_and(|d| {
+                        path_str(d) == suffix || path_str(d).ends_with(&format!("/{}", suffix))
+                    })
+            });
+            if let Some(file) = found {
+                return Some(file.clone());
+            }
+        }
+        None
+    }
+
+    fn exact(&self, lang: Lang, base: &Path) -> Option<String> {
+        let base = normalize(base);
+        lang.candidates(&base)
+            .into_iter()
+            .map(|p| path_str(&p))
+            .find(|p| self.files.contains(p))
+    }
+
+    /// Shortest stored path ending in the module, trying `src/` and `lib/` too.
+    fn from_root(&self, lang: Lang, module: &str) -> Option<String> {
+        for prefix in ["", "src/", "lib/"] {
+            for candidate in lang.candidates(Path::new(&format!("{}{}", prefix, module))) {
+                let candidate = path_str(&candidate);
+                let suffix = format!("/{}", candidate);
+                let found = self
+                    .files
+                    .iter()
+                    .filter(|f| **f == candidate || f.ends_with(&suffix))
+                    .min_by_key(|f| f.len());
+                if let Some(file) = found {
+                    return Some(file.clone());
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Resolves `.` and `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
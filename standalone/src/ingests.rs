@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// What `cancel` found for a repo id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cancel {
+    /// The running ingest was signalled and stops at its next file boundary.
+    Cancelled,
+    /// The repo was ingested, but nothing is running for it now.
+    Finished,
+    Unknown,
+}
+
+/// Where a repo's ingests stand, as `/repos` reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestStatus {
+    Idle,
+    Ingesting,
+    /// The last ingest stopped before it finished, on an error or cancelled.
+    Failed,
+}
+
+enum Entry {
+    /// `running` counts concurrent ingests of the same repo, which share a
+    /// token; `failed` is set once one of them stops without succeeding.
+    Running {
+        token: CancellationToken,
+        running: usize,
+        failed: bool,
+    },
+    Finished {
+        failed: bool,
+    },
+}
+
+/// The ingests in progress, by repo id, so `/cancel` can stop one.
+#[derive(Default)]
+pub struct Ingests {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl Ingests {
+    /// Registers an ingest of `repo_id` until the returned guard is dropped. Its
+    /// token is a child of `shutdown`, so it is also cancelled on shutdown.
+    /// The ingest counts as failed unless [`IngestGuard::succeeded`] is called.
+    pub fn start(self: &Arc<Self>, repo_id: &str, shutdown: &CancellationToken) -> IngestGuard {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .entry(repo_id.to_string())
+            .or_insert(Entry::Finished { failed: false });
+        let token = match entry {
+            Entry::Running { token, running, .. } => {
+                *running += 1;
+                token.clone()
+            }
+            Entry::Finished { .. } => {
+                let token = shutdown.child_token();
+                *entry = Entry::Running {
+                    token: token.clone(),
+                    running: 1,
+                    failed: false,
+                };
+                token
+            }
+        };
+        IngestGuard {
+            ingests: self.clone(),
+            repo_id: repo_id.to_string(),
+            token,
+            succeeded: false,
+        }
+    }
+
+    pub fn cancel(&self, repo_id: &str) -> Cancel {
+        match self.entries.lock().unwrap().get(repo_id) {
+            Some(Entry::Running { token, .. }) => {
+                token.cancel();
+                Cancel::Cancelled
+            }
+            Some(Entry::Finished { .. }) => Cancel::Finished,
+            None => Cancel::Unknown,
+        }
+    }
+
+    /// How many ingests are running, over all repos.
+    pub fn active(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| match entry {
+                Entry::Running { running, .. } => *running,
+                Entry::Finished { .. } => 0,
+            })
+            .sum()
+    }
+
+    pub fn is_running(&self, repo_id: &str) -> bool {
+        matches!(
+            self.entries.lock().unwrap().get(repo_id),
+            Some(Entry::Running { .. })
+        )
+    }
+
+    pub fn status(&self, repo_id: &str) -> IngestStatus {
+        match self.entries.lock().unwrap().get(repo_id) {
+            Some(Entry::Running { .. }) => IngestStatus::Ingesting,
+            Some(Entry::Finished { failed: true }) => IngestStatus::Failed,
+            _ => IngestStatus::Idle,
+        }
+    }
+
+    /// Every repo id ingested since startup, running or not.
+    pub fn repo_ids(&self) -> Vec<String> {
+        self.entries.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn finish(&self, repo_id: &str, succeeded: bool) {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(repo_id) else {
+            return;
+        };
+        if let Entry::Running {
+            running, failed, ..
+        } = entry
+        {
+            *running -= 1;
+            *failed |= !succeeded;
+            if *running == 0 {
+                *entry = Entry::Finished { failed: *failed };
+            }
+        }
+    }
+}
+
+/// Keeps an ingest registered while it runs.
+pub struct IngestGuard {
+    ingests: Arc<Ingests>,
+    repo_id: String,
+    token: CancellationToken,
+    succeeded: bool,
+}
+
+impl IngestGuard {
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    pub fn succeeded(mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for IngestGuard {
+    fn drop(&mut self) {
+        self.ingests.finish(&self.repo_id, self.succeeded);
+    }
+}
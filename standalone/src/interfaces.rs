@@ -0,0 +1,581 @@
+//! The operations and messages of a repo's interface definitions, OpenAPI
+//! (or Swagger) documents and protobuf files, as nodes of its graph, linked
+//! to the handlers implementing them where that can be told from the code:
+//! an `Endpoint` or a route annotation with the operation's method and path,
+//! or a function named after an rpc that takes its request message. Repos
+//! opt in with `MESH_INTERFACE_REPOS`, since finding the definitions reads
+//! every YAML, JSON and proto file of the checkout.
+
+use crate::annotations::{ANNOTATED_BY, ANNOTATION_KIND, ARGUMENTS};
+use crate::storage::{kind_key, EdgeRecord, NodeRecord};
+use anyhow::Result;
+use regex::Regex;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub const OPERATION_KIND: &str = "Operation";
+pub const MESSAGE_KIND: &str = "Message";
+/// From a handler to the operation it implements.
+pub const SERVES: &str = "SERVES";
+/// From an operation to the message it is sent, and to those it answers
+/// with.
+pub const ACCEPTS: &str = "ACCEPTS";
+pub const RETURNS: &str = "RETURNS";
+/// The meta keys of an `Operation`: `openapi` or `proto`, its method and
+/// path, or its service and the message types an rpc takes and returns,
+/// and the id a document gave it.
+pub const INTERFACE: &str = "interface";
+pub const METHOD: &str = "method";
+pub const PATH: &str = "path";
+pub const SERVICE: &str = "service";
+pub const REQUEST: &str = "request";
+pub const RESPONSE: &str = "response";
+pub const OPERATION_ID: &str = "operation_id";
+
+/// Definitions larger than this are left out, as generated ones can be.
+const MAX_DEFINITION_BYTES: u64 = 4 << 20;
+
+const HTTP_METHODS: &[&str] = &["get", "put", "post", "delete", "options", "head", "patch"];
+
+/// Annotations naming a route without saying its method, served to `GET`
+/// unless their arguments name another.
+const ROUTE_ANNOTATIONS: &[&str] = &["route", "requestmapping", "api_route"];
+
+/// The operations and messages of every interface definition in the
+/// checkout at `root`, and the `SERVES` edges to them from the handlers
+/// among `nodes` and `edges`. A definition that can't be read or parsed is
+/// left out.
+pub fn detect(
+    root: &Path,
+    repo_id: &str,
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let files = match crate::local::walk(root) {
+        Ok(files) => files,
+        Err(e) => {
+            tracing::warn!("Failed to list the interface definitions: {:#}", e);
+            return (Vec::new(), Vec::new());
+        }
+    };
+    // stored the way the repo's other files are, with whatever prefix
+    // they have
+    let root_prefix = format!("{}/", root.display());
+    let prefix = nodes
+        .iter()
+        .find_map(|n| {
+            n.file
+                .find(&root_prefix)
+                .map(|at| &n.file[..at + root_prefix.len()])
+        })
+        .unwrap_or_default();
+    let mut definitions = (Vec::new(), Vec::new());
+    for rel in files {
+        if definition_type(&rel).is_none() {
+            continue;
+        }
+        let path = root.join(&rel);
+        if path
+            .metadata()
+            .map_or(true, |m| m.len() > MAX_DEFINITION_BYTES)
+        {
+            continue;
+        }
+        let Ok(decoded) = crate::encoding::read(&path) else {
+            continue;
+        };
+        let file = format!("{}{}", prefix, rel);
+        match parse(&file, &decoded.text, repo_id) {
+            Ok(Some((nodes, edges))) => {
+                definitions.0.extend(nodes);
+                definitions.1.extend(edges);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to read the interface definition {}: {:#}", rel, e),
+        }
+    }
+    let served = link(&definitions.0, nodes, edges);
+    definitions.1.extend(served);
+    definitions
+}
+
+fn definition_type(file: &str) -> Option<&'static str> {
+    match Path::new(file).extension()?.to_str()? {
+        "yaml" | "yml" | "json" => Some("openapi"),
+        "proto" => Some("proto"),
+        _ => None,
+    }
+}
+
+/// The operations and messages `source` defines, with the edges between
+/// them, or `None` when `file` isn't an interface definition, as most YAML
+/// and JSON isn't.
+pub fn parse(
+    file: &str,
+    source: &str,
+    repo_id: &str,
+) -> Result<Option<(Vec<NodeRecord>, Vec<EdgeRecord>)>> {
+    match definition_type(file) {
+        Some("openapi") => openapi(file, source, repo_id),
+        Some("proto") => Ok(Some(proto(file, source, repo_id))),
+        _ => Ok(None),
+    }
+}
+
+/// An OpenAPI 3 or Swagger 2 document: an `Operation` for each method of
+/// each path, named `GET /users/{id}`, and a `Message` for each schema of
+/// its components, or definitions, that operations refer to with `$ref`.
+fn openapi(
+    file: &str,
+    source: &str,
+    repo_id: &str,
+) -> Result<Option<(Vec<NodeRecord>, Vec<EdgeRecord>)>> {
+    // cheap enough to tell most configuration apart without parsing it
+    if !source.contains("openapi") && !source.contains("swagger") {
+        return Ok(None);
+    }
+    let Ok(document) = serde_yaml::from_str::<Value>(source) else {
+        return Ok(None);
+    };
+    if document.get("openapi").is_none() && document.get("swagger").is_none() {
+        return Ok(None);
+    }
+    let lines: Vec<&str> = source.lines().collect();
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+
+    let schemas = document
+        .get("components")
+        .and_then(|c| c.get("schemas"))
+        .or_else(|| document.get("definitions"))
+        .and_then(Value::as_mapping);
+    let mut messages = BTreeMap::new();
+    let mut after = 0;
+    for (name, _) in schemas.into_iter().flatten() {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let start = key_line(&lines, name, after).unwrap_or(after);
+        after = start;
+        let node = definition_node(repo_id, MESSAGE_KIND, name, file, &lines, start);
+        messages.insert(name.to_string(), node.id.clone());
+        nodes.push(node);
+    }
+
+    let paths = document.get("paths").and_then(Value::as_mapping);
+    for (path, item) in paths.into_iter().flatten() {
+        let (Some(path), Some(item)) = (path.as_str(), item.as_mapping()) else {
+            continue;
+        };
+        let path_line = key_line(&lines, path, 0).unwrap_or(0);
+        for (method, operation) in item {
+            let Some(method) = method.as_str().filter(|m| HTTP_METHODS.contains(m)) else {
+                continue;
+            };
+            let start = key_line(&lines, method, path_line).unwrap_or(path_line);
+            let name = format!("{} {}", method.to_uppercase(), path);
+            let mut node = definition_node(repo_id, OPERATION_KIND, &name, file, &lines, start);
+            node.meta
+                .insert(INTERFACE.to_string(), "openapi".to_string());
+            node.meta.insert(METHOD.to_string(), method.to_uppercase());
+            node.meta.insert(PATH.to_string(), path.to_string());
+            if let Some(id) = operation.get("operationId").and_then(Value::as_str) {
+                node.meta.insert(OPERATION_ID.to_string(), id.to_string());
+            }
+            let sent = ["requestBody", "parameters"]
+                .iter()
+                .filter_map(|key| operation.get(key));
+            for (kind, values) in [
+                (ACCEPTS, sent.collect::<Vec<_>>()),
+                (RETURNS, operation.get("responses").into_iter().collect()),
+            ] {
+                let mut refs = BTreeSet::new();
+                for value in values {
+                    schema_refs(value, &mut refs);
+                }
+                for target in refs.iter().filter_map(|r| messages.get(r)) {
+                    edges.push(edge(repo_id, kind, &node.id, target));
+                }
+            }
+            nodes.push(node);
+        }
+    }
+    Ok(Some((nodes, edges)))
+}
+
+/// The names of the schemas `value` refers to with `$ref`, at any depth.
+fn schema_refs(value: &Value, refs: &mut BTreeSet<String>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                match (key.as_str(), value.as_str()) {
+                    (Some("$ref"), Some(target)) => {
+                        if let Some(name) = target.rsplit('/').next() {
+                            refs.insert(name.to_string());
+                        }
+                    }
+                    _ => schema_refs(value, refs),
+                }
+            }
+        }
+        Value::Sequence(values) => {
+            for value in values {
+                schema_refs(value, refs);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The first line from `after` on that holds the key `key`, quoted or not.
+fn key_line(lines: &[&str], key: &str, after: usize) -> Option<usize> {
+    let spellings = [
+        format!("{}:", key),
+        format!("\"{}\":", key),
+        format!("'{}':", key),
+    ];
+    (after..lines.len()).find(|&i| {
+        let line = lines[i].trim_start();
+        spellings.iter().any(|s| line.starts_with(s.as_str()))
+    })
+}
+
+/// The line before the next one indented no deeper than `start`, the end
+/// of the block `start` opens.
+fn block_end(lines: &[&str], start: usize) -> usize {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let Some(first) = lines.get(start) else {
+        return start;
+    };
+    let depth = indent(first);
+    let mut end = start;
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indent(line) <= depth {
+            break;
+        }
+        end = i;
+    }
+    end
+}
+
+fn definition_node(
+    repo_id: &str,
+    kind: &str,
+    name: &str,
+    file: &str,
+    lines: &[&str],
+    start: usize,
+) -> NodeRecord {
+    let end = block_end(lines, start);
+    NodeRecord {
+        repo_id: repo_id.to_string(),
+        id: kind_key(kind, name, file, 0, None),
+        kind: kind.to_string(),
+        name: name.to_string(),
+        file: file.to_string(),
+        start,
+        end,
+        body: lines.get(start..=end).unwrap_or_default().join("\n"),
+        meta: Default::default(),
+        span: None,
+    }
+}
+
+fn edge(repo_id: &str, kind: &str, source: &str, target: &str) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: repo_id.to_string(),
+        kind: kind.to_string(),
+        source: source.to_string(),
+        target: target.to_string(),
+    }
+}
+
+fn rpc_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r"^rpc\s+(\w+)\s*\(\s*(?:stream\s+)?([\w.]+)\s*\)\s*returns\s*\(\s*(?:stream\s+)?([\w.]+)\s*\)",
+        )
+        .unwrap()
+    })
+}
+
+/// A protobuf file: a `Message` for each message, nested ones named after
+/// their parents as `Outer.Inner`, and an `Operation` for each rpc of each
+/// service, named `Service.Rpc`, that `ACCEPTS` its request and `RETURNS`
+/// its response.
+fn proto(file: &str, source: &str, repo_id: &str) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut nodes: Vec<NodeRecord> = Vec::new();
+    // (name, line it opens on, depth inside it) of the open blocks
+    let mut open: Vec<(Option<(&str, String)>, usize, usize)> = Vec::new();
+    let mut rpcs = Vec::new();
+    let mut depth = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let code = line.split("//").next().unwrap_or_default().trim();
+        let mut words = code.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(keyword @ ("message" | "service")), Some(name)) => {
+                let name = name.trim_end_matches('{');
+                let parents: Vec<&str> = open
+                    .iter()
+                    .filter_map(|(block, _, _)| block.as_ref())
+                    .filter(|(keyword, _)| *keyword == "message")
+                    .map(|(_, name)| name.as_str())
+                    .collect();
+                let name = if keyword == "message" && !parents.is_empty() {
+                    format!("{}.{}", parents.last().unwrap(), name)
+                } else {
+                    name.to_string()
+                };
+                open.push((Some((keyword, name)), i, depth + 1));
+            }
+            (Some("rpc"), _) => {
+                let service = open
+                    .iter()
+                    .rev()
+                    .filter_map(|(block, _, _)| block.as_ref())
+                    .find(|(keyword, _)| *keyword == "service");
+                if let (Some((_, service)), Some(rpc)) = (service, rpc_pattern().captures(code)) {
+                    rpcs.push((
+                        i,
+                        service.clone(),
+                        rpc[1].to_string(),
+                        rpc[2].to_string(),
+                        rpc[3].to_string(),
+                    ));
+                }
+            }
+            _ => {}
+        }
+        for c in code.chars() {
+            match c {
+                '{' => {
+                    depth += 1;
+                    // braces of options and the like open no named block
+                    if open.last().map_or(true, |(_, _, at)| *at != depth) {
+                        open.push((None, i, depth));
+                    }
+                }
+                '}' => {
+                    if let Some((block, start, _)) = open.pop() {
+                        if let Some(("message", name)) = block.as_ref().map(|(k, n)| (*k, n)) {
+                            let mut node =
+                                definition_node(repo_id, MESSAGE_KIND, name, file, &lines, start);
+                            node.end = i;
+                            node.body = lines[start..=i].join("\n");
+                            nodes.push(node);
+                        }
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+                _ => {}
+            }
+        }
+    }
+    let message = |name: &str| {
+        let name = name.trim_start_matches('.');
+        nodes
+            .iter()
+            .find(|n| n.name == name || name.ends_with(&format!(".{}", n.name)))
+            .map(|n| n.id.clone())
+    };
+    let mut edges = Vec::new();
+    let mut operations = Vec::new();
+    for (line, service, rpc, request, response) in rpcs {
+        let name = format!("{}.{}", service, rpc);
+        let mut node = definition_node(repo_id, OPERATION_KIND, &name, file, &lines, line);
+        node.end = line;
+        node.body = lines[line].trim().to_string();
+        node.meta.insert(INTERFACE.to_string(), "proto".to_string());
+        node.meta.insert(SERVICE.to_string(), service);
+        node.meta.insert(OPERATION_ID.to_string(), rpc);
+        node.meta.insert(REQUEST.to_string(), request.clone());
+        node.meta.insert(RESPONSE.to_string(), response.clone());
+        for (kind, target) in [(ACCEPTS, message(&request)), (RETURNS, message(&response))] {
+            if let Some(target) = target {
+                edges.push(edge(repo_id, kind, &node.id, &target));
+            }
+        }
+        operations.push(node);
+    }
+    nodes.sort_by_key(|n| n.start);
+    nodes.extend(operations);
+    (nodes, edges)
+}
+
+/// `SERVES` edges from the handlers among `nodes` to the `definitions` they
+/// implement:
+///
+/// - the function an `Endpoint` with an operation's method and path hands
+///   its requests to, or the endpoint itself when it has no handler;
+/// - a symbol annotated with a route to the method and path, such as
+///   `@app.get("/users/{id}")`, `#[get("/users/{id}")]` or
+///   `@GetMapping("/users/{id}")`;
+/// - a function named after an rpc, in any case, whose body names its
+///   request message.
+///
+/// Paths match whatever their parameters are called and however they're
+/// written: `{id}`, `:id` and `<int:id>` are all one parameter.
+pub fn link(
+    definitions: &[NodeRecord],
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+) -> Vec<EdgeRecord> {
+    let operations: Vec<&NodeRecord> = definitions
+        .iter()
+        .filter(|n| n.kind == OPERATION_KIND)
+        .collect();
+    if operations.is_empty() {
+        return Vec::new();
+    }
+    let mut routes: BTreeMap<(String, String), Vec<&NodeRecord>> = BTreeMap::new();
+    for op in &operations {
+        if let (Some(method), Some(path)) = (op.meta.get(METHOD), op.meta.get(PATH)) {
+            routes
+                .entry((method.to_uppercase(), route_key(path)))
+                .or_default()
+                .push(op);
+        }
+    }
+    let by_id: BTreeMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut served = BTreeSet::new();
+
+    for endpoint in nodes.iter().filter(|n| n.kind == "Endpoint") {
+        let method = endpoint
+            .meta
+            .get("verb")
+            .map_or("GET".to_string(), |v| v.to_uppercase());
+        let Some(ops) = routes.get(&(method, route_key(&endpoint.name))) else {
+            continue;
+        };
+        let mut handlers: Vec<&str> = edges
+            .iter()
+            .filter(|e| e.kind == "HANDLER" && e.source == endpoint.id)
+            .map(|e| e.target.as_str())
+            .collect();
+        if handlers.is_empty() {
+            handlers.push(&endpoint.id);
+        }
+        for handler in handlers {
+            for op in ops {
+                served.insert((handler.to_string(), op.id.clone()));
+            }
+        }
+    }
+
+    for edge in edges.iter().filter(|e| e.kind == ANNOTATED_BY) {
+        let Some(annotation) = by_id
+            .get(edge.target.as_str())
+            .filter(|n| n.kind == ANNOTATION_KIND)
+        else {
+            continue;
+        };
+        let Some((method, path)) = annotated_route(annotation) else {
+            continue;
+        };
+        for op in routes
+            .get(&(method, route_key(&path)))
+            .into_iter()
+            .flatten()
+        {
+            served.insert((edge.source.clone(), op.id.clone()));
+        }
+    }
+
+    for op in operations.iter().filter(|op| op.meta.contains_key(SERVICE)) {
+        let rpc = op
+            .meta
+            .get(OPERATION_ID)
+            .map(String::as_str)
+            .unwrap_or_default();
+        let request = op
+            .meta
+            .get(REQUEST)
+            .and_then(|r| r.rsplit('.').next())
+            .unwrap_or_default();
+        for function in nodes.iter().filter(|n| n.kind == "Function") {
+            let takes_request = function.body.contains(request);
+            if normalized(&function.name) == normalized(rpc) && takes_request {
+                served.insert((function.id.clone(), op.id.clone()));
+            }
+        }
+    }
+
+    let repo_id = operations[0].repo_id.as_str();
+    served
+        .into_iter()
+        .map(|(source, target)| edge(repo_id, SERVES, &source, &target))
+        .collect()
+}
+
+/// The method and path a route annotation serves, from its name and the
+/// first string it was given: `("GET", "/users")` for `app.get("/users")`.
+fn annotated_route(annotation: &NodeRecord) -> Option<(String, String)> {
+    let arguments = annotation.meta.get(ARGUMENTS)?;
+    let path = first_string(arguments)?;
+    if !path.starts_with('/') {
+        return None;
+    }
+    let name = annotation
+        .name
+        .rsplit(|c| c == '.' || c == ':')
+        .next()?
+        .to_lowercase();
+    let method = name.strip_suffix("mapping").unwrap_or(&name);
+    if HTTP_METHODS.contains(&method) {
+        return Some((method.to_uppercase(), path));
+    }
+    if ROUTE_ANNOTATIONS.contains(&name.as_str()) {
+        let upper = arguments.to_uppercase();
+        let method = HTTP_METHODS
+            .iter()
+            .map(|m| m.to_uppercase())
+            .find(|m| upper.contains(&format!("\"{}\"", m)) || upper.contains(&format!(".{}", m)))
+            .unwrap_or_else(|| "GET".to_string());
+        return Some((method, path));
+    }
+    None
+}
+
+/// The first quoted string in `text`, without its quotes.
+fn first_string(text: &str) -> Option<String> {
+    let start = text.find(|c| c == '"' || c == '\'')?;
+    let quote = text[start..].chars().next()?;
+    let rest = &text[start + 1..];
+    let end = rest.find(quote)?;
+    Some(rest[..end].to_string())
+}
+
+/// `path` with every parameter written as `{}` and no trailing slash, so
+/// the ways frameworks spell routes compare equal.
+pub fn route_key(path: &str) -> String {
+    let key: Vec<String> = path
+        .trim_end_matches('/')
+        .split('/')
+        .map(|segment| {
+            let parameter = (segment.starts_with('{') && segment.ends_with('}'))
+                || (segment.starts_with('<') && segment.ends_with('>'))
+                || segment.starts_with(':');
+            if parameter {
+                "{}".to_string()
+            } else {
+                segment.to_lowercase()
+            }
+        })
+        .collect();
+    key.join("/")
+}
+
+/// `GetUser`, `get_user` and `getUser` all as `getuser`.
+fn normalized(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
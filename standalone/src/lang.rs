@@ -0,0 +1,537 @@
+use crate::captures::{CustomEdge, CustomNode, CustomQueries};
+use crate::encoding;
+use crate::source::{self, ChunkedFile, Source};
+use crate::storage::Span;
+use anyhow::{Context, Result};
+use ast::lang::NodeType;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Node, ParseOptions, Parser, Query, QueryCursor, Tree};
+
+/// How long a registered plugin may spend parsing one file.
+pub const DEFAULT_PARSE_TIMEOUT_MS: u64 = 30_000;
+
+/// A tree-sitter grammar that can be registered at startup, in addition to the
+/// languages `ast` already knows about.
+pub trait LanguagePlugin: Send + Sync {
+    fn name(&self) -> &str;
+    fn grammar(&self) -> tree_sitter::Language;
+    /// Extensions without the leading dot, e.g. `["dsl"]`.
+    fn file_extensions(&self) -> &[&str];
+    fn node_queries(&self) -> &QuerySet;
+}
+
+/// The queries a plugin runs over every parsed file. Each query captures the
+/// symbol's name as `@name` and, optionally, the full definition as `@definition`.
+#[derive(Default)]
+pub struct QuerySet {
+    queries: Vec<(NodeType, String)>,
+}
+
+impl QuerySet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with(mut self, node_type: NodeType, query: &str) -> Self {
+        self.queries.push((node_type, query.to_string()));
+        self
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &(NodeType, String)> {
+        self.queries.iter()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtractedNode {
+    pub node_type: NodeType,
+    pub name: String,
+    pub file: String,
+    pub body: String,
+    pub start: usize,
+    pub end: usize,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Text the grammar couldn't make sense of, skipped over by the parser.
+    Error,
+    /// A token the parser had to assume was there, e.g. a closing brace.
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Severity::Error),
+            "warning" => Ok(Severity::Warning),
+            other => Err(format!(
+                "unknown severity '{}'; expected error or warning",
+                other
+            )),
+        }
+    }
+}
+
+/// A part of a file that didn't parse. The rest of the file is still
+/// extracted; these say why some of it may be missing from the graph.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub file: String,
+    /// Byte offsets into the file, end exclusive.
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+    pub severity: Severity,
+}
+
+/// What parsing files yielded: the valid nodes, and what couldn't be parsed.
+#[derive(Debug, Clone, Default)]
+pub struct Extraction {
+    pub nodes: Vec<ExtractedNode>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Files skipped for running past the parse timeout; each also has a
+    /// diagnostic saying so.
+    pub timed_out: Vec<String>,
+    /// What the custom queries matched.
+    pub custom: Vec<CustomNode>,
+    /// What the custom edge queries matched.
+    pub custom_edges: Vec<CustomEdge>,
+}
+
+impl Extraction {
+    pub fn extend(&mut self, other: Extraction) {
+        self.nodes.extend(other.nodes);
+        self.custom.extend(other.custom);
+        self.custom_edges.extend(other.custom_edges);
+        self.diagnostics.extend(other.diagnostics);
+        self.timed_out.extend(other.timed_out);
+    }
+}
+
+/// The fallback for files no plugin claims: they are skipped, never an error.
+pub struct PlainText;
+
+impl PlainText {
+    pub fn extract(&self, _file: &str, _source: &str) -> Result<Vec<ExtractedNode>> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Default)]
+pub struct LanguageRegistry {
+    plugins: Vec<Arc<dyn LanguagePlugin>>,
+    by_extension: HashMap<String, usize>,
+    parse_timeout: Option<Duration>,
+    parse_workers: usize,
+    stream_bytes: Option<u64>,
+    custom: CustomQueries,
+}
+
+impl LanguageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses running longer than `timeout` are stopped and their file
+    /// skipped; `None`, the default, never stops one.
+    pub fn set_parse_timeout(&mut self, timeout: Option<Duration>) {
+        self.parse_timeout = timeout;
+    }
+
+    /// How long a parse may run, as [`LanguageRegistry::set_parse_timeout`]
+    /// left it.
+    pub fn parse_timeout(&self) -> Option<Duration> {
+        self.parse_timeout
+    }
+
+    /// Files are parsed on up to `workers` threads; the default is one.
+    pub fn set_parse_workers(&mut self, workers: usize) {
+        self.parse_workers = workers;
+    }
+
+    /// Files larger than `bytes` are parsed from a [`ChunkedFile`] rather
+    /// than read into memory first; `None`, the default, reads every file
+    /// whole.
+    pub fn set_stream_bytes(&mut self, bytes: Option<u64>) {
+        self.stream_bytes = bytes;
+    }
+
+    /// Runs `queries` over the files of their languages, after whatever
+    /// plugin claims them.
+    pub fn set_custom_queries(&mut self, queries: CustomQueries) {
+        self.custom = queries;
+    }
+
+    /// Compiles the plugin's queries up front so a bad query fails at startup
+    /// rather than halfway through an ingest.
+    pub fn register(&mut self, plugin: Arc<dyn LanguagePlugin>) -> Result<()> {
+        let grammar = plugin.grammar();
+        for (node_type, query) in plugin.node_queries().iter() {
+            Query::new(&grammar, query).with_context(|| {
+                format!("invalid {:?} query for plugin {}", node_type, plugin.name())
+            })?;
+        }
+        let idx = self.plugins.len();
+        for ext in plugin.file_extensions() {
+            self.by_extension
+                .insert(ext.trim_start_matches('.').to_string(), idx);
+        }
+        self.plugins.push(plugin);
+        Ok(())
+    }
+
+    /// Whether there are neither plugins nor custom queries, so there is
+    /// nothing to extract.
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty() && self.custom.is_empty()
+    }
+
+    /// Whether a plugin or a custom query extracts from `path`.
+    pub fn claims(&self, path: &Path) -> bool {
+        self.resolve(path).is_some() || self.custom.claims(path)
+    }
+
+    /// Extensions claimed by the plugin called `name`, ignoring case.
+    pub fn extensions_for(&self, name: &str) -> Option<Vec<String>> {
+        let plugin = self
+            .plugins
+            .iter()
+            .find(|p| p.name().eq_ignore_ascii_case(name))?;
+        Some(
+            plugin
+                .file_extensions()
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_string())
+                .collect(),
+        )
+    }
+
+    pub fn resolve(&self, path: &Path) -> Option<&dyn LanguagePlugin> {
+        let ext = path.extension()?.to_str()?;
+        self.by_extension
+            .get(ext)
+            .map(|idx| self.plugins[*idx].as_ref())
+    }
+
+    pub fn extract(&self, file: &str, source: &str) -> Result<Extraction> {
+        self.extract_source(file, source)
+    }
+
+    /// [`LanguageRegistry::extract`] from any [`Source`], such as a
+    /// [`ChunkedFile`].
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(skip(self, source), fields(bytes = source.len()))
+    )]
+    pub fn extract_source<S: Source + ?Sized>(&self, file: &str, source: &S) -> Result<Extraction> {
+        let mut extraction = match self.resolve(Path::new(file)) {
+            Some(plugin) => extract_with(plugin, file, source, self.parse_timeout)?,
+            // what PlainText makes of it
+            None => Extraction::default(),
+        };
+        let (custom, custom_edges, diagnostic) =
+            self.custom.extract(file, source, self.parse_timeout)?;
+        extraction.custom = custom;
+        extraction.custom_edges = custom_edges;
+        extraction.diagnostics.extend(diagnostic);
+        Ok(extraction)
+    }
+
+    /// Walks `root` and extracts nodes from every file a registered plugin or
+    /// custom query claims.
+    pub fn extract_dir(&self, root: &str) -> Result<Extraction> {
+        if self.is_empty() {
+            return Ok(Extraction::default());
+        }
+        let mut files = Vec::new();
+        for entry in ignore::WalkBuilder::new(root)
+            .add_custom_ignore_filename(crate::local::IGNORE_FILE)
+            .build()
+        {
+            let entry = entry?;
+            let path = entry.path();
+            if !path.is_file() || !self.claims(path) {
+                continue;
+            }
+            let rel = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+            files.push((path.to_path_buf(), rel.into_owned()));
+        }
+        self.extract_files(&files)
+    }
+
+    /// Reads and extracts each `(path, rel)` pair as [`extract_file`] does,
+    /// spread over the parse workers. Workers take the next file as they
+    /// free up, but the results are merged in the order of `files`, so the
+    /// extraction is the same however many there are. The first file to fail
+    /// stops the others from starting new ones.
+    ///
+    /// [`extract_file`]: LanguageRegistry::extract_file
+    pub fn extract_files(&self, files: &[(PathBuf, String)]) -> Result<Extraction> {
+        let workers = self.parse_workers.clamp(1, files.len().max(1));
+        let mut extraction = Extraction::default();
+        if workers == 1 {
+            for (path, rel) in files {
+                extraction.extend(self.extract_file(path, rel)?);
+            }
+            return Ok(extraction);
+        }
+
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let (tx, rx) = mpsc::channel();
+        let mut results: Vec<Option<Result<Extraction>>> = Vec::new();
+        results.resize_with(files.len(), || None);
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                let tx = tx.clone();
+                let (next, failed) = (&next, &failed);
+                scope.spawn(move || {
+                    while !failed.load(Ordering::Relaxed) {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((path, rel)) = files.get(i) else {
+                            break;
+                        };
+                        let result = self.extract_file(path, rel);
+                        if result.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                        if tx.send((i, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(tx);
+            for (i, result) in rx {
+                results[i] = Some(result);
+            }
+        });
+        // files after a failure may never have been started
+        for result in results.into_iter().map_while(|r| r) {
+            extraction.extend(result?);
+        }
+        Ok(extraction)
+    }
+
+    /// Reads and extracts one file, `rel` being how the graph names it,
+    /// transcoded to UTF-8 as [`encoding::decode`] does. A file whose
+    /// encoding can't be told yields a diagnostic rather than an error. One
+    /// over [`LanguageRegistry::set_stream_bytes`] that is UTF-8 already is
+    /// parsed a chunk at a time instead, without being read whole.
+    pub fn extract_file(&self, path: &Path, rel: &str) -> Result<Extraction> {
+        if let Some(limit) = self.stream_bytes {
+            let file = ChunkedFile::open(path, source::DEFAULT_CHUNK_BYTES)?;
+            if file.len() as u64 > limit && file.is_plain_utf8()? {
+                return self.extract_source(rel, &file);
+            }
+        }
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        match encoding::decode(&bytes) {
+            Ok(decoded) => self.extract(rel, &decoded.text),
+            Err(reason) => Ok(Extraction {
+                nodes: Vec::new(),
+                diagnostics: vec![Diagnostic {
+                    file: rel.to_string(),
+                    start: std::str::from_utf8(&bytes).map_or_else(|e| e.valid_up_to(), |_| 0),
+                    end: bytes.len(),
+                    message: format!("file skipped, {}", reason),
+                    severity: Severity::Error,
+                }],
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+fn extract_with<S: Source + ?Sized>(
+    plugin: &dyn LanguagePlugin,
+    file: &str,
+    source: &S,
+    timeout: Option<Duration>,
+) -> Result<Extraction> {
+    let grammar = plugin.grammar();
+    let parsed = parse_source(&grammar, source, timeout)
+        .with_context(|| format!("{} failed to parse {}", plugin.name(), file))?;
+    let tree = match (parsed, timeout) {
+        (Some(tree), _) => tree,
+        (None, Some(timeout)) => return Ok(timed_out(file, source, timeout)),
+        (None, None) => unreachable!("only a parse with a deadline stops early"),
+    };
+
+    let mut nodes = Vec::new();
+    for (node_type, query) in plugin.node_queries().iter() {
+        let query = Query::new(&grammar, query)?;
+        let names = query.capture_names();
+        let mut cursor = QueryCursor::new();
+        let mut matches = cursor.matches(&query, tree.root_node(), text_provider(source));
+        while let Some(m) = matches.next() {
+            let mut name = None;
+            let mut definition = None;
+            for capture in m.captures {
+                match names[capture.index as usize] {
+                    "name" => name = Some(capture.node),
+                    "definition" => definition = Some(capture.node),
+                    _ => {}
+                }
+            }
+            let Some(name) = name else { continue };
+            let span = definition.unwrap_or(name);
+            nodes.push(ExtractedNode {
+                node_type: node_type.clone(),
+                name: source.text(name.byte_range())?.into_owned(),
+                file: file.to_string(),
+                body: source.text(span.byte_range())?.into_owned(),
+                start: span.start_position().row,
+                end: span.end_position().row,
+                span: Span {
+                    start_column: span.start_position().column,
+                    end_column: span.end_position().column,
+                    start_byte: span.start_byte(),
+                    end_byte: span.end_byte(),
+                },
+            });
+        }
+    }
+    Ok(Extraction {
+        nodes,
+        diagnostics: diagnostics(file, source, tree.root_node()),
+        ..Default::default()
+    })
+}
+
+/// Parses `source`, or returns `None` when that runs past `timeout`.
+pub(crate) fn parse(
+    grammar: &tree_sitter::Language,
+    source: &str,
+    timeout: Option<Duration>,
+) -> Result<Option<Tree>> {
+    parse_source(grammar, source, timeout)
+}
+
+/// [`parse`] from any [`Source`], handing tree-sitter one of its chunks at a
+/// time. A chunk that can't be read fails the parse.
+pub(crate) fn parse_source<S: Source + ?Sized>(
+    grammar: &tree_sitter::Language,
+    source: &S,
+    timeout: Option<Duration>,
+) -> Result<Option<Tree>> {
+    let mut parser = Parser::new();
+    parser.set_language(grammar)?;
+    // checked by tree-sitter as it goes, so a parse past its deadline is
+    // stopped where it is rather than left running in the background
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut progress = |_: &tree_sitter::ParseState| {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    };
+    let mut failed = None;
+    let tree = parser.parse_with_options(
+        &mut |offset, _| match source.chunk(offset) {
+            Ok(chunk) => chunk,
+            // ends the text for tree-sitter, which then gives up on it
+            Err(e) => {
+                failed = Some(e);
+                Cow::Borrowed(&[][..])
+            }
+        },
+        None,
+        Some(ParseOptions::new().progress_callback(&mut progress)),
+    );
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    match tree {
+        Some(tree) => Ok(Some(tree)),
+        None if deadline.is_some_and(|d| Instant::now() >= d) => Ok(None),
+        None => anyhow::bail!("tree-sitter gave up"),
+    }
+}
+
+/// The text of each node, for the predicates of a query to compare.
+pub(crate) fn text_provider<S: Source + ?Sized>(
+    source: &S,
+) -> impl FnMut(Node) -> std::iter::Once<Cow<'_, [u8]>> + '_ {
+    |node| std::iter::once(source.bytes(node.byte_range()).unwrap_or_default())
+}
+
+fn timed_out<S: Source + ?Sized>(file: &str, source: &S, timeout: Duration) -> Extraction {
+    Extraction {
+        nodes: Vec::new(),
+        diagnostics: vec![Diagnostic {
+            file: file.to_string(),
+            start: 0,
+            end: source.len(),
+            message: format!(
+                "parsing took longer than {} ms, file skipped",
+                timeout.as_millis()
+            ),
+            severity: Severity::Warning,
+        }],
+        timed_out: vec![file.to_string()],
+        ..Default::default()
+    }
+}
+
+// how much of the unparsable text a message quotes
+const SNIPPET_CHARS: usize = 40;
+// enough to hold that many characters of UTF-8
+const SNIPPET_BYTES: usize = SNIPPET_CHARS * 4;
+
+/// The `ERROR` and `MISSING` nodes tree-sitter recovered with, outermost
+/// first; an error's own children aren't reported again.
+fn diagnostics<S: Source + ?Sized>(file: &str, source: &S, root: Node) -> Vec<Diagnostic> {
+    let mut found = Vec::new();
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        let (message, severity) = if node.is_error() {
+            let start = node.start_byte();
+            let text = source
+                .text(start..node.end_byte().min(start + SNIPPET_BYTES))
+                .unwrap_or_default();
+            let line = text.lines().next().unwrap_or_default().trim();
+            let snippet: String = line.chars().take(SNIPPET_CHARS).collect();
+            (format!("syntax error at `{}`", snippet), Severity::Error)
+        } else if node.is_missing() {
+            (format!("missing `{}`", node.kind()), Severity::Warning)
+        } else {
+            if node.has_error() {
+                let mut cursor = node.walk();
+                pending.extend(node.children(&mut cursor));
+            }
+            continue;
+        };
+        found.push(Diagnostic {
+            file: file.to_string(),
+            start: node.start_byte(),
+            end: node.end_byte(),
+            message,
+            severity,
+        });
+    }
+    found.sort_by_key(|d| (d.start, d.end));
+    found
+}
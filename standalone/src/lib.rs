@@ -0,0 +1,581 @@
+pub mod analysis;
+pub mod annotations;
+pub mod archive;
+pub mod assets;
+pub mod audit;
+pub mod auth;
+pub mod buffers;
+pub mod callgraph;
+pub mod captures;
+pub mod checkpoints;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod clone;
+pub mod clones;
+pub mod complexity;
+pub mod components;
+pub mod config;
+pub mod confirm;
+pub mod consistency;
+pub mod cors;
+pub mod coverage;
+pub mod docs;
+pub mod embeddings;
+pub mod encoding;
+pub mod events;
+pub mod export;
+pub mod fanout;
+pub mod filter;
+pub mod grammars;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod grep;
+#[cfg(any(feature = "neo4j", feature = "sqlite"))]
+pub mod handlers;
+pub mod health;
+pub mod hierarchy;
+pub mod idempotency;
+pub mod ids;
+pub mod imports;
+pub mod ingests;
+pub mod interfaces;
+pub mod lang;
+pub mod limits;
+pub mod local;
+pub mod locks;
+pub mod logging;
+pub mod metrics;
+pub mod msgpack;
+pub mod neighborhood;
+pub mod outbound;
+pub mod paths;
+pub mod pipeline;
+pub mod projects;
+pub mod query;
+pub mod redact;
+pub mod report;
+pub mod retention;
+pub mod schedule;
+pub mod search;
+pub mod server;
+pub mod shutdown;
+pub mod snapshots;
+pub mod snippet;
+pub mod source;
+pub mod sql;
+pub mod stats;
+pub mod storage;
+pub mod symbols;
+pub mod tasks;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod types;
+pub mod typing;
+pub mod visibility;
+pub mod webhook;
+pub mod workspaces;
+#[cfg(any(feature = "neo4j", feature = "sqlite"))]
+pub mod ws;
+
+use audit::AuditLog;
+use auth::Auth;
+#[cfg(any(feature = "neo4j", feature = "sqlite"))]
+use auth::Scope;
+use axum::http::HeaderValue;
+#[cfg(any(feature = "neo4j", feature = "sqlite"))]
+use axum::{
+    extract::DefaultBodyLimit, middleware, response::Html, routing::get, routing::post,
+    routing::MethodRouter, Router,
+};
+use captures::CustomQueries;
+use clone::{Credentials, RetryPolicy};
+use clones::CloneDir;
+use config::Config;
+use confirm::ConfirmTokens;
+use embeddings::Embedder;
+use events::{EventSender, EventStats, KeepAliveConfig};
+use idempotency::Idempotency;
+use ids::IdScheme;
+use ingests::Ingests;
+use lang::LanguageRegistry;
+use limits::{GraphLimit, RateLimiter};
+use locks::RepoLocks;
+use metrics::Metrics;
+use outbound::Outbound;
+use paths::PathMap;
+use redact::Redaction;
+use retention::Retention;
+use schedule::Schedules;
+use snippet::SourceCache;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::access::AccessTracking;
+use storage::cache::{CachingStorage, GraphCache};
+use storage::Storage;
+use symbols::IndexCache;
+use tasks::TaskRules;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+#[cfg(any(feature = "neo4j", feature = "sqlite"))]
+use tower_http::compression::CompressionLayer;
+#[cfg(any(feature = "neo4j", feature = "sqlite"))]
+use tower_http::services::ServeFile;
+use webhook::WebhookConfig;
+
+/// Graph dumps are far larger than the default request limit.
+pub const IMPORT_BODY_LIMIT: usize = 512 * 1024 * 1024;
+/// So are the source tarballs uploaded to `/ingest`.
+pub const ARCHIVE_BODY_LIMIT: usize = 256 * 1024 * 1024;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub tx: EventSender,
+    pub event_capacity: usize,
+    pub event_stats: Arc<EventStats>,
+    /// Counters and timings scraped from `/metrics`.
+    pub metrics: Arc<Metrics>,
+    pub languages: Arc<LanguageRegistry>,
+    pub storage: Arc<dyn Storage>,
+    /// Cancelled when the server starts shutting down.
+    pub shutdown: CancellationToken,
+    /// Running ingests by repo id, for `/cancel`.
+    pub ingests: Arc<Ingests>,
+    /// One permit per ingest allowed to run at once.
+    pub ingest_slots: Arc<Semaphore>,
+    /// API keys and the routes that need one; every route is open when `None`.
+    pub auth: Option<Arc<Auth>>,
+    /// Per-client limit on the mutating routes; unlimited when `None`.
+    pub rate_limit: Option<Arc<RateLimiter>>,
+    /// Secret and repos for `/webhook`; deliveries are refused when `None`.
+    pub webhook: Option<Arc<WebhookConfig>>,
+    /// Where `/ingest-path` may read from.
+    pub allowed_roots: Vec<PathBuf>,
+    /// Files over this many bytes are skipped rather than parsed; no limit
+    /// when `None`.
+    pub max_file_bytes: Option<u64>,
+    /// How large an ingest may make a repo's graph.
+    pub graph_limit: GraphLimit,
+    /// Parsed files that may wait on the storage writer; see [`pipeline`].
+    pub write_queue: usize,
+    /// Origins CORS allows; any origin when `None`.
+    pub cors_origins: Option<Vec<HeaderValue>>,
+    /// Where the UI is served from; a placeholder page when `None`.
+    pub static_dir: Option<PathBuf>,
+    /// How clones and fetches are retried.
+    pub clone_retry: RetryPolicy,
+    /// Branches a fetch of no ref falls back to; see [`clone::CloneScope`].
+    pub default_branches: Vec<String>,
+    /// The server's own git credentials, by the name requests refer to them by.
+    pub git_credentials: Arc<BTreeMap<String, Credentials>>,
+    /// Where remote repos are checked out, and how much disk they may take.
+    pub clones: Arc<CloneDir>,
+    /// Whether `/graph/query` accepts raw Cypher as well as its templates,
+    /// from callers with an admin key.
+    pub allow_raw_cypher: bool,
+    /// Whether the routes that write are left out of [`router`], for a
+    /// server reading from a replica.
+    pub readonly: bool,
+    /// How long `/readyz` waits on the backend.
+    pub ready_timeout: Duration,
+    /// What idle `/events` streams send.
+    pub keep_alive: KeepAliveConfig,
+    /// One permit per `/events` stream allowed open at once; as many as
+    /// connect when `None`.
+    pub sse_slots: Option<Arc<Semaphore>>,
+    /// How often each repo's progress may go out on an `/events` stream;
+    /// every update is sent when `None`.
+    pub sse_coalesce: Option<Duration>,
+    /// Files recently read for `/snippet`.
+    pub sources: Arc<SourceCache>,
+    /// Repos re-ingested on a timer, from `/schedule`.
+    pub schedules: Arc<Schedules>,
+    /// Tokens from `GET /clear` not yet used.
+    pub clear_tokens: Arc<ConfirmTokens>,
+    /// Recent results of requests sent with an `Idempotency-Key`.
+    pub idempotency: Arc<Idempotency>,
+    /// The graphs of repos warmed through `/warm`, which `storage` answers
+    /// reads of those repos from.
+    pub graph_cache: Arc<GraphCache>,
+    /// What cross-repo lookups resolve names of the other repos with.
+    pub symbol_indexes: Arc<IndexCache>,
+    /// How the ids of ingested nodes are made.
+    pub node_ids: IdScheme,
+    /// What the paths of ingested nodes are stored as.
+    pub path_map: PathMap,
+    /// Whether SQL in string literals is linked to the tables it queries.
+    pub embedded_sql: bool,
+    /// Repos whose interface definitions are ingested.
+    pub interface_repos: Vec<String>,
+    /// How task markers in comments are read; none are stored when `None`.
+    pub tasks: Option<Arc<TaskRules>>,
+    /// Secrets taken out of nodes before they're stored; see [`redact`].
+    pub redaction: Option<Arc<Redaction>>,
+    /// Edges of one node the traversals return at most; see [`fanout`].
+    pub edge_cap: Option<usize>,
+    /// How long a query may take before it's logged as slow; none are when
+    /// `None`.
+    pub slow_query: Option<Duration>,
+    /// How long a query or traversal may run before it's stopped; as long as
+    /// it takes when `None`.
+    pub query_timeout: Option<Duration>,
+    /// What the source of ingested symbols is embedded with; none are
+    /// when `None`.
+    pub embedder: Option<Arc<dyn Embedder>>,
+    /// The services outside the server it calls, for `/metrics` to report
+    /// on.
+    pub outbound: Vec<Arc<Outbound>>,
+    /// Taken by ingests and clears of a repo, and by queries of it, so they
+    /// take turns.
+    pub repo_locks: Arc<RepoLocks>,
+    /// When repos were last used, for clearing those left unused.
+    pub retention: Arc<Retention>,
+    /// Where ingests, fetches and clears are recorded; they aren't when
+    /// `None`.
+    pub audit: Option<Arc<AuditLog>>,
+}
+
+impl AppState {
+    /// Creates the event channel with room for `event_capacity` undelivered
+    /// updates, with everything else as [`Config::default`] has it apart from
+    /// local ingest, which has no allowed roots.
+    pub fn new(
+        storage: Arc<dyn Storage>,
+        languages: LanguageRegistry,
+        event_capacity: usize,
+    ) -> Self {
+        AppState::with_static_dir(storage, languages, event_capacity, assets::static_dir(None))
+    }
+
+    fn with_static_dir(
+        storage: Arc<dyn Storage>,
+        languages: LanguageRegistry,
+        event_capacity: usize,
+        static_dir: Option<PathBuf>,
+    ) -> Self {
+        let graph_cache = Arc::new(GraphCache::default());
+        let retention = Arc::new(Retention::default());
+        let storage = Arc::new(CachingStorage::new(storage, graph_cache.clone()));
+        AppState {
+            tx: EventSender::new(event_capacity, events::DEFAULT_REPLAY_BUFFER),
+            event_capacity,
+            event_stats: Arc::new(EventStats::default()),
+            metrics: Arc::new(Metrics::default()),
+            languages: Arc::new(languages),
+            storage: Arc::new(AccessTracking::new(storage, retention.clone())),
+            shutdown: CancellationToken::new(),
+            ingests: Arc::new(Ingests::default()),
+            ingest_slots: Arc::new(Semaphore::new(limits::DEFAULT_MAX_CONCURRENT_INGESTS)),
+            auth: None,
+            rate_limit: None,
+            webhook: None,
+            allowed_roots: Vec::new(),
+            max_file_bytes: Some(local::DEFAULT_MAX_FILE_BYTES),
+            graph_limit: GraphLimit::default(),
+            write_queue: pipeline::DEFAULT_WRITE_QUEUE,
+            cors_origins: None,
+            static_dir,
+            clone_retry: RetryPolicy::default(),
+            default_branches: clone::DEFAULT_BRANCHES.map(String::from).to_vec(),
+            git_credentials: Arc::default(),
+            clones: Arc::new(CloneDir::default()),
+            allow_raw_cypher: false,
+            readonly: false,
+            ready_timeout: health::DEFAULT_READY_TIMEOUT,
+            keep_alive: KeepAliveConfig::default(),
+            sse_slots: Some(Arc::new(Semaphore::new(events::DEFAULT_MAX_SSE_CLIENTS))),
+            sse_coalesce: None,
+            sources: Arc::new(SourceCache::default()),
+            schedules: Arc::new(Schedules::default()),
+            clear_tokens: Arc::new(ConfirmTokens::default()),
+            idempotency: Arc::new(Idempotency::default()),
+            graph_cache,
+            symbol_indexes: Arc::new(IndexCache::default()),
+            node_ids: IdScheme::default(),
+            path_map: PathMap::default(),
+            embedded_sql: false,
+            interface_repos: Vec::new(),
+            tasks: Some(Arc::new(TaskRules::default())),
+            redaction: None,
+            edge_cap: Some(fanout::DEFAULT_MAX_EDGES_PER_NODE),
+            slow_query: Some(Duration::from_millis(query::DEFAULT_SLOW_QUERY_MS)),
+            query_timeout: Some(Duration::from_millis(query::DEFAULT_QUERY_TIMEOUT_MS)),
+            embedder: None,
+            outbound: Vec::new(),
+            repo_locks: Arc::new(RepoLocks::default()),
+            retention,
+            audit: None,
+        }
+    }
+
+    /// The state `config` describes. Fails when the event id file can't be
+    /// read, a CORS origin can't be parsed, a custom query doesn't compile,
+    /// the embedding client can't be built or the audit log can't be opened.
+    pub fn from_config(
+        storage: Arc<dyn Storage>,
+        mut languages: LanguageRegistry,
+        config: &Config,
+    ) -> anyhow::Result<Self> {
+        languages.set_parse_timeout(config.parse_timeout());
+        languages.set_parse_workers(config.parse_workers());
+        languages.set_stream_bytes(config.stream_file_bytes());
+        let mut queries = match &config.query_dir {
+            Some(dir) => CustomQueries::load(dir)?,
+            None => CustomQueries::default(),
+        };
+        if config.index_text {
+            queries.index_text()?;
+        }
+        queries.define_edges(&config.custom_edges)?;
+        languages.set_custom_queries(queries);
+        let static_dir = assets::static_dir(config.static_dir.as_deref());
+        let mut state =
+            AppState::with_static_dir(storage, languages, config.event_buffer, static_dir);
+        // so clients holding a `Last-Event-ID` from before a restart aren't
+        // handed reused ids
+        state.tx = match &config.event_id_file {
+            Some(path) => EventSender::persisted(config.event_buffer, config.event_replay, path)?,
+            None => EventSender::new(config.event_buffer, config.event_replay),
+        };
+        state.ingest_slots = Arc::new(Semaphore::new(config.max_concurrent_ingests));
+        state.sse_slots = config
+            .max_sse_clients()
+            .map(|max| Arc::new(Semaphore::new(max)));
+        state.sse_coalesce = config.sse_coalesce();
+        state.auth = config.auth().map(Arc::new);
+        state.rate_limit = config.rate_limit().map(Arc::new);
+        state.webhook = config.webhook().map(Arc::new);
+        state.allowed_roots = local::allowed_roots(&config.allowed_roots);
+        state.max_file_bytes = config.max_file_bytes();
+        state.graph_limit = config.graph_limit();
+        state.write_queue = config.write_queue;
+        state.graph_cache.set_budget(config.graph_cache_bytes);
+        state.node_ids = config.node_ids;
+        state.path_map = config.path_map();
+        state.embedded_sql = config.embedded_sql;
+        state.interface_repos = config.interface_repos.clone();
+        state.tasks = config.task_rules()?.map(Arc::new);
+        state.redaction = config.redaction()?.map(Arc::new);
+        state.edge_cap = config.edge_cap();
+        state.slow_query = config.slow_query();
+        state.query_timeout = config.query_timeout();
+        let embeddings = Arc::new(config.embedding_calls());
+        state.embedder = config.embedder(embeddings.clone())?;
+        if state.embedder.is_some() {
+            state.outbound.push(embeddings);
+        }
+        state.retention.set_ttl(config.retention_ttl());
+        for repo_id in &config.pinned_repos {
+            state.retention.pin(repo_id, true);
+        }
+        state.cors_origins = config.cors_origins()?;
+        state.clone_retry = config.clone_retry();
+        state.default_branches = config.default_branches.clone();
+        state.git_credentials = Arc::new(config.git_credentials.clone());
+        state.clones = Arc::new(config.clone_dir());
+        state.allow_raw_cypher = config.allow_raw_cypher;
+        state.readonly = config.readonly;
+        state.ready_timeout = config.ready_timeout();
+        state.keep_alive = config.keep_alive();
+        state.idempotency = Arc::new(Idempotency::new(config.idempotency_ttl()));
+        state.audit = config.audit_log()?.map(Arc::new);
+        Ok(state)
+    }
+}
+
+#[cfg(any(feature = "neo4j", feature = "sqlite"))]
+pub fn router(app_state: Arc<AppState>) -> Router {
+    let cors_layer = cors::layer(app_state.cors_origins.as_deref());
+    let require_key = |scope: Scope| {
+        middleware::from_fn_with_state((app_state.clone(), scope), auth::require_key)
+    };
+    // retries of the routes that start an ingest or a clone don't start another
+    let idempotent =
+        || middleware::from_fn_with_state(app_state.idempotency.clone(), idempotency::dedupe);
+    let audited = |operation: &'static str| {
+        middleware::from_fn_with_state((app_state.clone(), operation), audit::record)
+    };
+    // every write is audited under the operation named next to its path
+    let keyed: Vec<(&str, &'static str, MethodRouter<Arc<AppState>>)> = vec![
+        (
+            "/process",
+            "process",
+            post(handlers::process).layer(idempotent()),
+        ),
+        (
+            "/clear",
+            "clear",
+            post(handlers::clear_graph).get(handlers::clear_token),
+        ),
+        (
+            "/process-file",
+            "process-file",
+            post(handlers::process_file),
+        ),
+        (
+            "/ingest",
+            "ingest",
+            post(handlers::ingest).layer(DefaultBodyLimit::max(ARCHIVE_BODY_LIMIT)),
+        ),
+        ("/ingest-path", "ingest-path", post(handlers::ingest_path)),
+        (
+            "/fetch-repo",
+            "fetch-repo",
+            post(handlers::fetch_repo).layer(idempotent()),
+        ),
+        (
+            "/import/json",
+            "import-json",
+            post(handlers::import_json).layer(DefaultBodyLimit::max(IMPORT_BODY_LIMIT)),
+        ),
+        ("/cancel", "cancel", post(handlers::cancel)),
+        ("/schedule", "schedule", post(handlers::schedule)),
+        ("/register", "register", post(handlers::bulk_register)),
+        ("/validate", "validate", post(handlers::validate)),
+        // reads and may clone the repo like an ingest, so it takes the same key
+        ("/parse-tree", "parse-tree", post(handlers::parse_tree)),
+        ("/warm", "warm", post(handlers::warm)),
+        ("/retention/pin", "retention-pin", post(handlers::pin)),
+        ("/snapshots", "snapshot", post(handlers::snapshot)),
+        (
+            "/snapshots/delete",
+            "delete-snapshot",
+            post(handlers::delete_snapshot),
+        ),
+        (
+            "/snapshots/restore",
+            "restore-snapshot",
+            post(handlers::restore_snapshot),
+        ),
+        ("/reanalyze", "reanalyze", post(handlers::reanalyze)),
+    ];
+    let writes = keyed
+        .into_iter()
+        .map(|(path, operation, route)| {
+            let route = route
+                .layer(audited(operation))
+                .route_layer(require_key(Scope::Mutating));
+            (path, route)
+        })
+        .chain([
+            // deliveries are signed with the webhook secret instead of a key
+            ("/webhook", post(handlers::webhook)),
+            (
+                "/admin/compact",
+                post(handlers::compact)
+                    .layer(audited("compact"))
+                    .route_layer(require_key(Scope::Admin)),
+            ),
+        ]);
+    // a replica takes no writes, so a read-only server routes every path that
+    // writes to a refusal instead
+    let mutating = writes
+        .fold(Router::new(), |router, (path, route)| {
+            let route = if app_state.readonly {
+                post(handlers::read_only)
+            } else {
+                route
+            };
+            router.route(path, route)
+        })
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            limits::rate_limit,
+        ));
+    // what reads the graph waits out an ingest or a clear of its repo
+    let read_lock = || middleware::from_fn_with_state(app_state.clone(), locks::hold_read);
+    // traversals walk as far as the graph goes, so they're stopped like queries
+    let traversals = Router::new()
+        .route("/call-graph", post(handlers::call_graph))
+        .route("/references", post(handlers::references))
+        .route("/callers", post(handlers::callers))
+        .route("/callees", post(handlers::callees))
+        .route("/tests-for", post(handlers::tests_for))
+        .route("/covered-by", post(handlers::covered_by))
+        .route("/hierarchy", post(handlers::hierarchy))
+        .route("/neighborhood", post(handlers::neighborhood))
+        .route("/cycles", post(handlers::cycles))
+        .route_layer(read_lock())
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            limits::request_timeout,
+        ));
+    let reads = Router::new()
+        .route("/repos", get(handlers::list_repos))
+        .route("/repos/log", get(handlers::repo_log))
+        .route("/graph/query", post(handlers::query))
+        .route("/related", post(handlers::related))
+        .route("/snippet", post(handlers::snippet))
+        .route("/resolve", post(handlers::resolve))
+        .route("/dependencies", post(handlers::dependencies))
+        .route("/dead-code", post(handlers::dead_code))
+        .route("/diff", post(handlers::diff))
+        .route("/snapshots", get(handlers::list_snapshots))
+        .route("/changed-symbols", post(handlers::changed_symbols))
+        .route("/search", post(handlers::search))
+        .route("/grep", post(handlers::grep))
+        .route("/stats", post(handlers::stats))
+        .route("/export/dot", get(handlers::export_dot))
+        .route("/export/cytoscape", get(handlers::export_cytoscape))
+        .route("/export/mermaid", get(handlers::export_mermaid))
+        .route("/export/json", get(handlers::export_json))
+        .route("/diagnostics", get(handlers::diagnostics));
+    #[cfg(feature = "graphql")]
+    let reads = reads.route(
+        "/graphql",
+        post(graphql::handler).layer(axum::Extension(graphql::schema())),
+    );
+    let api = Router::new()
+        .merge(mutating)
+        .merge(traversals)
+        .merge(reads.route_layer(read_lock()))
+        // parses what it's sent without cloning or storing anything
+        .route("/parse-stream", post(handlers::parse_stream))
+        .route(
+            "/events/stats",
+            get(events::stats).route_layer(require_key(Scope::Events)),
+        )
+        .route(
+            "/audit",
+            get(handlers::audit_log).route_layer(require_key(Scope::Admin)),
+        )
+        .route("/metrics", get(metrics::handler))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz));
+    let api = api
+        // gzip or brotli, whichever the client's `Accept-Encoding` prefers
+        .layer(CompressionLayer::new().gzip(true).br(true));
+    // the encoder holds output back until it has a block's worth, which would
+    // stall the event streams
+    let router = Router::new()
+        .merge(api)
+        .route(
+            "/events",
+            get(events::sse_handler).route_layer(require_key(Scope::Events)),
+        )
+        .route(
+            "/ws",
+            get(ws::handler).route_layer(require_key(Scope::Events)),
+        );
+    let ui = match &app_state.static_dir {
+        Some(dir) => assets::STATIC_FILES
+            .iter()
+            .fold(Router::new(), |router, file| {
+                let path = if *file == "index.html" {
+                    "/".to_string()
+                } else {
+                    format!("/{}", file)
+                };
+                router.route_service(&path, ServeFile::new(dir.join(file)))
+            }),
+        None => Router::new().route("/", get(|| async { Html(assets::FALLBACK_INDEX) })),
+    };
+    let router = router.merge(ui.route_layer(require_key(Scope::Static)));
+    router
+        .with_state(app_state)
+        .layer(middleware::from_fn(logging::request_id))
+        .layer(cors_layer)
+}
@@ -0,0 +1,186 @@
+use crate::types::MeshError;
+use crate::AppState;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub const DEFAULT_MAX_CONCURRENT_INGESTS: usize = 4;
+pub const DEFAULT_RATE_LIMIT_PER_MIN: f64 = 60.0;
+pub const DEFAULT_RATE_LIMIT_BURST: f64 = 10.0;
+
+/// What a client rejected for a full ingest cap is told to wait.
+pub const INGEST_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+// idle buckets are only pruned once this many clients are tracked
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// The most nodes and edges one repo's graph may hold; unlimited when `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GraphLimit {
+    pub max_nodes: Option<usize>,
+    pub max_edges: Option<usize>,
+}
+
+impl GraphLimit {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_nodes.is_none() && self.max_edges.is_none()
+    }
+
+    /// Counts an ingest's writes against the limit, on top of the `nodes` and
+    /// `edges` already stored.
+    pub fn budget(self, nodes: usize, edges: usize) -> GraphBudget {
+        GraphBudget {
+            limit: self,
+            nodes,
+            edges,
+            started_empty: nodes == 0 && edges == 0,
+        }
+    }
+}
+
+/// Running totals of a repo's graph while an ingest writes it, so the limit
+/// is kept without counting what is stored after every file.
+pub struct GraphBudget {
+    limit: GraphLimit,
+    nodes: usize,
+    edges: usize,
+    started_empty: bool,
+}
+
+impl GraphBudget {
+    /// Whether the ingest started from an empty graph, so everything stored
+    /// is its own.
+    pub fn started_empty(&self) -> bool {
+        self.started_empty
+    }
+
+    /// Adds one file's records, or says which ceiling they would go over and
+    /// leaves the totals as they were.
+    pub fn take(&mut self, nodes: usize, edges: usize) -> Result<(), String> {
+        let over = |what: &str, total: usize, max: Option<usize>| match max {
+            Some(max) if total > max => Err(format!(
+                "the graph would hold {} {}, over the limit of {} per repo",
+                total, what, max
+            )),
+            _ => Ok(()),
+        };
+        over("nodes", self.nodes + nodes, self.limit.max_nodes)?;
+        over("edges", self.edges + edges, self.limit.max_edges)?;
+        self.nodes += nodes;
+        self.edges += edges;
+        Ok(())
+    }
+}
+
+/// Takes one of the ingest slots for as long as the permit is held, or fails
+/// straight away with 429 when they are all in use.
+pub fn ingest_permit(slots: &Arc<Semaphore>) -> Result<OwnedSemaphorePermit, MeshError> {
+    slots
+        .clone()
+        .try_acquire_owned()
+        .map_err(|_| MeshError::TooManyRequests {
+            message: "Too many ingests running, try again later".to_string(),
+            retry_after: INGEST_RETRY_AFTER,
+        })
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// A token bucket per client IP: each request takes a token, and tokens come
+/// back at `per_sec` up to `burst`.
+pub struct RateLimiter {
+    per_sec: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_sec: f64, burst: f64) -> Self {
+        RateLimiter {
+            per_sec,
+            burst: burst.max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for `client`, or says how long until one is available.
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            let (per_sec, burst) = (self.per_sec, self.burst);
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * per_sec < burst
+            });
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_sec).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_sec,
+            ))
+        }
+    }
+}
+
+/// Middleware for the mutating routes. Clients are told apart by the peer
+/// address from `ConnectInfo`; without one every request shares a bucket.
+pub async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(limiter) = &state.rate_limit {
+        let client = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        if let Err(retry_after) = limiter.check(client) {
+            return MeshError::TooManyRequests {
+                message: "Rate limit exceeded".to_string(),
+                retry_after,
+            }
+            .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Answers with a 504 once the request has run past `MESH_QUERY_TIMEOUT_MS`.
+/// What the handler was waiting on is dropped with it, which for the
+/// backends releases the connection.
+pub async fn request_timeout(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(timeout) = state.query_timeout else {
+        return next.run(request).await;
+    };
+    let started = Instant::now();
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => MeshError::Timeout {
+            message: format!("request timed out after {} ms", timeout.as_millis()),
+            elapsed: started.elapsed(),
+        }
+        .into_response(),
+    }
+}
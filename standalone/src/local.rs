@@ -0,0 +1,350 @@
+use crate::encoding::{self, Encoding};
+use crate::lang::{Diagnostic, Severity};
+use crate::source::{self, ChunkedFile};
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+
+/// The directories `/ingest-path` and a request's `repo_path` may read from,
+/// resolved so paths can be checked against them. Those that don't exist are skipped with a warning;
+/// none at all means no local ingest.
+pub fn allowed_roots(roots: &[PathBuf]) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .filter_map(|p| match p.canonicalize() {
+            Ok(p) => Some(p),
+            Err(e) => {
+                warn!("ignoring allowed root {}: {}", p.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Resolves `path` and checks it lies under one of `roots`. Resolving first
+/// means `..` and symlinked directories can't be used to step outside.
+pub fn check_allowed(path: &str, roots: &[PathBuf]) -> Result<PathBuf> {
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        anyhow::bail!("path must be absolute: {}", path.display());
+    }
+    let resolved = path
+        .canonicalize()
+        .with_context(|| format!("cannot read {}", path.display()))?;
+    if !resolved.is_dir() {
+        anyhow::bail!("not a directory: {}", path.display());
+    }
+    if !roots.iter().any(|root| resolved.starts_with(root)) {
+        anyhow::bail!("{} is outside MESH_ALLOWED_ROOTS", resolved.display());
+    }
+    Ok(resolved)
+}
+
+/// Checks that `subdir`, relative to the checkout at `root`, is a directory
+/// inside it once resolved, so neither `..` nor a symlinked directory can be
+/// used to step outside.
+pub fn check_subdir(root: &Path, subdir: &str) -> Result<()> {
+    let escapes = Path::new(subdir)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if subdir.is_empty() || escapes {
+        anyhow::bail!(
+            "subdir must be a path relative to the repo root: {}",
+            subdir
+        );
+    }
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("cannot read {}", root.display()))?;
+    let resolved = root
+        .join(subdir)
+        .canonicalize()
+        .with_context(|| format!("subdir {} is not in the repo", subdir))?;
+    if !resolved.starts_with(&root) {
+        anyhow::bail!("subdir {} leads outside the repo", subdir);
+    }
+    if !resolved.is_dir() {
+        anyhow::bail!("subdir {} is not a directory", subdir);
+    }
+    Ok(())
+}
+
+/// `path` resolved, if it's a file under one of `roots`, which are resolved
+/// already; `None` when it isn't, or can't be read.
+pub fn confined_file(path: &Path, roots: &[PathBuf]) -> Option<PathBuf> {
+    let resolved = path.canonicalize().ok()?;
+    (resolved.is_file() && roots.iter().any(|root| resolved.starts_with(root))).then_some(resolved)
+}
+
+/// Hex SHA-256 of a file's contents, compared between ingests to skip
+/// re-parsing files that didn't change.
+pub fn content_hash(contents: &[u8]) -> String {
+    hex::encode(Sha256::digest(contents))
+}
+
+/// `files`, relative to `root`, paired with their content hashes. Files that
+/// can't be read, e.g. ones a diff lists as deleted, are left out. Each is
+/// hashed as it is read, rather than read into memory first.
+pub fn hash_files(root: &Path, files: &[String]) -> Vec<(String, String)> {
+    files
+        .iter()
+        .filter_map(|file| {
+            let mut contents = std::fs::File::open(root.join(file)).ok()?;
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut contents, &mut hasher).ok()?;
+            Some((file.clone(), hex::encode(hasher.finalize())))
+        })
+        .collect()
+}
+
+/// The directories of the checkout at `root`, as `dir/` with a trailing slash
+/// so they can sit among the file hashes, paired with a hash of everything
+/// under them: the id of their tree at HEAD, which git keeps up to date, so
+/// none of their files is read or looked at. The checkout's own root isn't
+/// listed, as the commit stands for it. Unless the checkout is `pristine`,
+/// as one the server cloned and left alone is, directories holding a file
+/// that differs from HEAD, or isn't tracked, are left out. Empty when `root`
+/// isn't the top of a git checkout with a commit.
+pub fn directory_hashes(root: &Path, pristine: bool) -> BTreeMap<String, String> {
+    let mut found = BTreeMap::new();
+    let Ok(repo) = git2::Repository::open(root) else {
+        return found;
+    };
+    let is_top = match (repo.workdir().map(Path::canonicalize), root.canonicalize()) {
+        (Some(Ok(workdir)), Ok(root)) => workdir == root,
+        _ => false,
+    };
+    let Some(tree) = repo
+        .head()
+        .ok()
+        .and_then(|head| head.peel_to_tree().ok())
+        .filter(|_| is_top)
+    else {
+        return found;
+    };
+    let _ = tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() == Some(git2::ObjectType::Tree) {
+            let name = entry.name().unwrap_or_default();
+            found.insert(format!("{}{}/", dir, name), entry.id().to_string());
+        }
+        git2::TreeWalkResult::Ok
+    });
+    if pristine {
+        return found;
+    }
+    let mut options = git2::StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .exclude_submodules(true);
+    let Ok(statuses) = repo.statuses(Some(&mut options)) else {
+        return BTreeMap::new();
+    };
+    for status in statuses.iter() {
+        let Some(path) = status.path() else {
+            return BTreeMap::new();
+        };
+        let mut end = 0;
+        while let Some(slash) = path[end..].find('/') {
+            end += slash + 1;
+            found.remove(&path[..end]);
+        }
+    }
+    found
+}
+
+/// Whether `key`, a file or a `dir/` of [`directory_hashes`], is `file` or
+/// holds it.
+pub fn covers(key: &str, file: &str) -> bool {
+    key == file || (key.ends_with('/') && file.starts_with(key))
+}
+
+/// How much of a file [`detect_language`] reads: enough for a shebang line
+/// and a header's first declarations.
+pub const HEAD_BYTES: u64 = 1024;
+
+/// [`crate::filter::detect_language`] for `file` under `root`, reading at most
+/// its first [`HEAD_BYTES`]. A file that can't be read is judged on its name.
+pub fn detect_language(root: &Path, file: &str) -> Option<&'static str> {
+    let mut head = Vec::new();
+    if let Ok(f) = std::fs::File::open(root.join(file)) {
+        let _ = f.take(HEAD_BYTES).read_to_end(&mut head);
+    }
+    crate::filter::detect_language(Path::new(file), &head)
+}
+
+/// The largest file that is parsed.
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// How much of a file is searched for a NUL byte to tell it's binary; the
+/// same amount git looks at.
+pub const SNIFF_BYTES: u64 = 8000;
+
+/// Why a file is left out before it reaches the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unparseable {
+    TooLarge {
+        bytes: u64,
+        limit: u64,
+    },
+    Binary,
+    /// Text that isn't UTF-8, or can't be transcoded to it; see
+    /// [`check_encoding`].
+    Encoding(String),
+}
+
+impl std::fmt::Display for Unparseable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unparseable::TooLarge { bytes, limit } => {
+                write!(f, "{} bytes is over the {} byte limit", bytes, limit)
+            }
+            Unparseable::Binary => write!(f, "not a text file"),
+            Unparseable::Encoding(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl Unparseable {
+    /// The warning stored for `file` under `root` when it's skipped for
+    /// this, spanning the whole file as the parser's own skips do.
+    pub fn diagnostic(&self, root: &Path, file: &str) -> Diagnostic {
+        let bytes = std::fs::metadata(root.join(file)).map_or(0, |m| m.len());
+        Diagnostic {
+            file: file.to_string(),
+            start: 0,
+            end: bytes as usize,
+            message: format!("file skipped, {}", self),
+            severity: Severity::Warning,
+        }
+    }
+}
+
+/// Whether `file` under `root` should be kept from the parser: it is over
+/// `max_bytes`, judged from its metadata without reading it, or its first
+/// [`SNIFF_BYTES`] hold a NUL byte other than UTF-16's. A file that can't be
+/// read is left for the parser to report.
+pub fn unparseable(root: &Path, file: &str, max_bytes: Option<u64>) -> Option<Unparseable> {
+    let path = root.join(file);
+    let bytes = std::fs::metadata(&path).ok()?.len();
+    if let Some(limit) = max_bytes.filter(|limit| bytes > *limit) {
+        return Some(Unparseable::TooLarge { bytes, limit });
+    }
+    let mut head = Vec::new();
+    std::fs::File::open(&path)
+        .ok()?
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    (head.contains(&0) && !encoding::is_utf16(&head)).then_some(Unparseable::Binary)
+}
+
+/// Makes sure the parsers see `file` under `root` as the same UTF-8 text
+/// the graph's offsets are into. With `transcode`, for checkouts of the
+/// server's own, a file in another encoding [`encoding::decode`] can tell,
+/// or with a BOM, is rewritten as plain UTF-8 and its old encoding returned;
+/// a directory on disk isn't written to, so such a file is refused instead.
+/// `None` for a file that is UTF-8 already or can't be read, which is left
+/// for the parser to report. Only files that aren't are read whole.
+pub fn check_encoding(
+    root: &Path,
+    file: &str,
+    transcode: bool,
+) -> Result<Option<Encoding>, Unparseable> {
+    let path = root.join(file);
+    let plain =
+        ChunkedFile::open(&path, source::DEFAULT_CHUNK_BYTES).and_then(|file| file.is_plain_utf8());
+    if plain.unwrap_or(true) {
+        return Ok(None);
+    }
+    let Ok(bytes) = std::fs::read(&path) else {
+        return Ok(None);
+    };
+    let decoded = encoding::decode(&bytes).map_err(Unparseable::Encoding)?;
+    match decoded.encoding {
+        Encoding::Utf8 => Ok(None),
+        encoding if !transcode => Err(Unparseable::Encoding(format!(
+            "it is {}, and only UTF-8 is parsed in place",
+            encoding
+        ))),
+        encoding => {
+            std::fs::write(&path, decoded.text).map_err(|e| {
+                Unparseable::Encoding(format!("it couldn't be rewritten as UTF-8: {}", e))
+            })?;
+            Ok(Some(encoding))
+        }
+    }
+}
+
+/// Gitignore-syntax patterns for paths left out of the graph, on top of
+/// `.gitignore`. Its patterns take precedence, so `!` can bring back files
+/// `.gitignore` excludes.
+pub const IGNORE_FILE: &str = ".meshignore";
+
+/// Files under `root`, relative to it and sorted. `.gitignore` and
+/// [`IGNORE_FILE`] are honored whether or not the directory is a git
+/// checkout, and symlinks that resolve outside `root` are skipped.
+pub fn walk(root: &Path) -> Result<Vec<String>> {
+    walk_under(root, "")
+}
+
+/// [`walk`] of just the directory `dir` of `root`, with the files still
+/// relative to `root` and the ignore files above `dir` still honored.
+pub fn walk_under(root: &Path, dir: &str) -> Result<Vec<String>> {
+    walk_filtered(root, dir, &BTreeSet::new())
+}
+
+/// [`walk`] that doesn't descend into `skip`, directories of `root` written
+/// as `dir/` the way [`directory_hashes`] lists them.
+pub fn walk_except(root: &Path, skip: &BTreeSet<String>) -> Result<Vec<String>> {
+    walk_filtered(root, "", skip)
+}
+
+fn walk_filtered(root: &Path, dir: &str, skip: &BTreeSet<String>) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut builder = ignore::WalkBuilder::new(root.join(dir));
+    builder
+        .require_git(false)
+        .follow_links(false)
+        .add_custom_ignore_filename(IGNORE_FILE);
+    if !skip.is_empty() {
+        let (root, skip) = (root.to_path_buf(), skip.clone());
+        builder.filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            let Ok(rel) = entry.path().strip_prefix(&root) else {
+                return true;
+            };
+            !is_dir || !skip.contains(&format!("{}/", rel.to_string_lossy()))
+        });
+    }
+    for entry in builder.build() {
+        let entry = entry?;
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if file_type.is_symlink() {
+            match path.canonicalize() {
+                Ok(target) if target.starts_with(root) && target.is_file() => {}
+                Ok(target) if !target.starts_with(root) => {
+                    warn!(
+                        "skipping {}: links outside {}",
+                        path.display(),
+                        root.display()
+                    );
+                    continue;
+                }
+                _ => continue,
+            }
+        } else if !file_type.is_file() {
+            continue;
+        }
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        files.push(rel.to_string_lossy().to_string());
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,120 @@
+use crate::storage;
+use crate::types::MeshError;
+use crate::AppState;
+use axum::body::{to_bytes, Body};
+use axum::extract::{Query, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+/// Largest request body read for the repo it names; axum's default body
+/// limit.
+const MAX_BODY: usize = 2 * 1024 * 1024;
+
+/// Read and write locks on each repo's graph, so an ingest and a clear of
+/// the same repo take turns rather than interleaving, while work on other
+/// repos goes on. Clearing every repo locks all of them at once. Locks are
+/// taken on the whole graph before a repo's, so two callers never wait on
+/// each other.
+#[derive(Default)]
+pub struct RepoLocks {
+    all: Arc<RwLock<()>>,
+    /// Dropped once no guard holds them, and pruned when another is made.
+    repos: Mutex<HashMap<String, Weak<RwLock<()>>>>,
+}
+
+/// Holds the locks of [`RepoLocks`] it was taken with until dropped.
+pub struct RepoGuard {
+    _read: Vec<OwnedRwLockReadGuard<()>>,
+    _write: Option<OwnedRwLockWriteGuard<()>>,
+}
+
+impl RepoLocks {
+    fn repo(&self, repo_id: &str) -> Arc<RwLock<()>> {
+        let mut repos = self.repos.lock().unwrap();
+        if let Some(lock) = repos.get(repo_id).and_then(Weak::upgrade) {
+            return lock;
+        }
+        repos.retain(|_, lock| lock.strong_count() > 0);
+        let lock = Arc::new(RwLock::new(()));
+        repos.insert(repo_id.to_string(), Arc::downgrade(&lock));
+        lock
+    }
+
+    /// Shares `repo_id`, or every repo when `None`, with other readers and
+    /// with writers to other repos.
+    pub async fn read(&self, repo_id: Option<&str>) -> RepoGuard {
+        let mut read = vec![self.all.clone().read_owned().await];
+        if let Some(repo_id) = repo_id {
+            read.push(self.repo(repo_id).read_owned().await);
+        }
+        RepoGuard {
+            _read: read,
+            _write: None,
+        }
+    }
+
+    /// Has `repo_id`, or every repo when `None`, to itself, waiting until
+    /// whoever holds it is done.
+    pub async fn write(&self, repo_id: Option<&str>) -> RepoGuard {
+        match repo_id {
+            Some(repo_id) => RepoGuard {
+                _read: vec![self.all.clone().read_owned().await],
+                _write: Some(self.repo(repo_id).write_owned().await),
+            },
+            None => RepoGuard {
+                _read: Vec::new(),
+                _write: Some(self.all.clone().write_owned().await),
+            },
+        }
+    }
+
+    /// [`RepoLocks::write`], unless someone holds the lock now.
+    pub fn try_write(&self, repo_id: Option<&str>) -> Option<RepoGuard> {
+        Some(match repo_id {
+            Some(repo_id) => RepoGuard {
+                _read: vec![self.all.clone().try_read_owned().ok()?],
+                _write: Some(self.repo(repo_id).try_write_owned().ok()?),
+            },
+            None => RepoGuard {
+                _read: Vec::new(),
+                _write: Some(self.all.clone().try_write_owned().ok()?),
+            },
+        })
+    }
+}
+
+/// Holds the read lock of the repo a request names, in its JSON body or its
+/// query string as `repo` or `repo_id` with an optional `ref`, until the
+/// response is ready. A request naming no repo only waits out a clear of
+/// every repo.
+pub async fn hold_read(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_BODY).await {
+        Ok(body) => body,
+        Err(e) => return MeshError::Validation(format!("unreadable body: {}", e)).into_response(),
+    };
+    let mut named: HashMap<String, String> = Query::try_from_uri(&parts.uri)
+        .map(|Query(query)| query)
+        .unwrap_or_default();
+    if let Ok(Value::Object(fields)) = serde_json::from_slice::<Value>(&body) {
+        for (field, value) in fields {
+            if let Value::String(value) = value {
+                named.insert(field, value);
+            }
+        }
+    }
+    let repo_id = named
+        .get("repo")
+        .or_else(|| named.get("repo_id"))
+        .map(|repo| storage::with_ref(repo, named.get("ref").map(String::as_str)));
+    let _lock = state.repo_locks.read(repo_id.as_deref()).await;
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
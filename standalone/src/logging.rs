@@ -0,0 +1,117 @@
+use crate::types::Result;
+use axum::extract::Request;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use futures::future::Either;
+use std::future::Future;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+use tracing::{info, info_span, warn, Instrument};
+
+/// Names the request in its response, and in every log line written while
+/// it is handled. A client's own id is kept when it sends one.
+pub const REQUEST_ID: &str = "x-request-id";
+/// Longer ids sent by a client are replaced rather than logged.
+pub const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: String;
+}
+
+/// The id of the request being handled, if any; see [`propagate`] for
+/// background work started by one.
+pub fn current() -> Option<String> {
+    CURRENT.try_with(String::clone).ok()
+}
+
+/// `work` run under the current request's id, for spawned tasks, which
+/// don't inherit it.
+pub fn propagate<F: Future>(work: F) -> impl Future<Output = F::Output> {
+    match current() {
+        Some(id) => Either::Left(CURRENT.scope(id, work)),
+        None => Either::Right(work),
+    }
+}
+
+/// Middleware giving every request an id, in a `request` span around its
+/// handling and in the `X-Request-Id` response header.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(new_id);
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path()
+    );
+    let mut response = CURRENT
+        .scope(id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+    response
+}
+
+/// 16 hex digits, unique within the process and unguessable across restarts.
+fn new_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    static KEY: OnceLock<RandomState> = OnceLock::new();
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    format!("{:016x}", KEY.get_or_init(RandomState::new).hash_one(n))
+}
+
+/// Runs `work` in an `ingest` span carrying `repo_id`, `ref` and the request
+/// id, so every line logged during the ingest can be tied back to it, then
+/// logs one `ingest finished` line with its duration and outcome: `succeeded`,
+/// or the error kind it failed with.
+pub async fn ingest<T>(
+    repo_id: &str,
+    git_ref: Option<&str>,
+    work: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let git_ref = git_ref.unwrap_or_default();
+    let request_id = current().unwrap_or_default();
+    let span = info_span!(
+        "ingest",
+        repo_id,
+        "ref" = git_ref,
+        request_id = request_id.as_str()
+    );
+    let start = Instant::now();
+    let result = work.instrument(span.clone()).await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match &result {
+        Ok(_) => info!(
+            parent: &span,
+            repo_id,
+            "ref" = git_ref,
+            duration_ms,
+            outcome = "succeeded",
+            "ingest finished"
+        ),
+        Err(e) => warn!(
+            parent: &span,
+            repo_id,
+            "ref" = git_ref,
+            duration_ms,
+            outcome = e.kind(),
+            error = %e,
+            "ingest finished"
+        ),
+    }
+    result
+}
@@ -1,127 +1,153 @@
-#[cfg(feature = "neo4j")]
-mod handlers;
-mod types;
+use standalone::types::Result;
 
-use ast::repo::StatusUpdate;
-use axum::extract::State;
-use axum::response::sse::{Event, KeepAlive, Sse};
-use axum::response::IntoResponse;
-use axum::{routing::get, routing::post, Router};
-use broadcast::error::RecvError;
-use futures::stream;
-use std::convert::Infallible;
-use std::time::Duration;
-
-use std::sync::Arc;
-use tokio::sync::broadcast;
-use tower_http::cors::CorsLayer;
-use tower_http::services::ServeFile;
-use tracing_subscriber::{filter::LevelFilter, EnvFilter};
-use types::Result;
-
-#[derive(Clone)]
-struct AppState {
-    tx: broadcast::Sender<StatusUpdate>,
-}
-
-#[cfg(feature = "neo4j")]
+#[cfg(any(feature = "neo4j", feature = "sqlite"))]
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
+    use standalone::config::Config;
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::unconfigured::Unconfigured;
+    use standalone::{shutdown, storage, AppState};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::{filter::LevelFilter, EnvFilter, Layer};
+
+    // the level filter only applies to the log output; exported spans are sampled by the collector
     let filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .from_env_lossy();
-    tracing_subscriber::fmt()
+    let logs = tracing_subscriber::fmt::layer()
         .with_target(false)
-        .with_env_filter(filter)
-        .init();
+        .with_filter(filter);
+    let registry = tracing_subscriber::registry().with(logs);
+    #[cfg(feature = "otel")]
+    let provider = standalone::telemetry::otlp_provider()?;
+    #[cfg(feature = "otel")]
+    let registry = registry.with(standalone::telemetry::layer(&provider));
+    registry.init();
 
-    let (tx, _rx) = broadcast::channel(10000);
+    // a bad setting stops startup rather than being replaced by its default
+    let config = Config::load()?;
 
-    let mut dummy_rx = tx.subscribe();
-    tokio::spawn(async move {
-        while let Ok(_) = dummy_rx.recv().await {
-            // Just consume messages, don't do anything
-            // this is required to keep the msgs fast. weird.
-        }
-    });
+    // without a backend the server still starts, so /readyz can say why it isn't ready
+    let unavailable = |e: anyhow::Error| -> Arc<dyn storage::Storage> {
+        tracing::error!("storage unavailable: {:#}", e);
+        Arc::new(Unconfigured::new(format!("{:#}", e)))
+    };
+    let storage = storage::connect(&config).await.unwrap_or_else(unavailable);
+    println!("=> using {} storage", storage.backend());
 
-    let app_state = Arc::new(AppState { tx: tx });
+    // custom grammars are registered here, before the state is shared; every
+    // workspace gets a registry of its own from it
+    let languages = LanguageRegistry::new;
 
-    let cors_layer = CorsLayer::permissive();
-    let app = Router::new()
-        .route("/process", post(handlers::process))
-        .route("/clear", post(handlers::clear_graph))
-        .route("/ingest", post(handlers::ingest))
-        .route("/fetch-repo", post(handlers::fetch_repo))
-        .route("/events", get(sse_handler))
-        .route_service("/", static_file("index.html"))
-        .route_service("/styles.css", static_file("styles.css"))
-        .route_service("/app.js", static_file("app.js"))
-        .route_service("/utils.js", static_file("utils.js"))
-        .with_state(app_state)
-        .layer(cors_layer);
+    let app_state = AppState::from_config(storage, languages(), &config)?;
+    if app_state.cors_origins.is_none() {
+        tracing::warn!("no CORS origins are configured, allowing requests from any origin");
+    }
+    if app_state.readonly {
+        println!("=> read-only, the routes that write answer 405");
+    } else if app_state.auth.is_none() {
+        tracing::warn!("no API keys are configured, mutating routes need no API key");
+    }
+    // clones are removed when their ingest ends, so any still here were left by a crash
+    let swept = app_state.clones.sweep();
+    if swept > 0 {
+        tracing::info!(
+            "removed {} orphaned clones from {}",
+            swept,
+            app_state.clones.root().display()
+        );
+    }
+    match standalone::grammars::drifted(app_state.storage.as_ref()).await {
+        Ok(drifted) => {
+            for (repo_id, changed) in drifted {
+                let changed: Vec<String> = changed.iter().map(|m| m.to_string()).collect();
+                tracing::warn!(
+                    "grammars changed since {} was parsed ({}); its next ingest parses every file again",
+                    repo_id,
+                    changed.join(", ")
+                );
+            }
+        }
+        Err(e) => tracing::warn!(
+            "failed to check the grammar versions of stored repos: {:#}",
+            e
+        ),
+    }
+    let app_state = Arc::new(app_state);
+    // expired repos are the writer's to delete
+    if !config.readonly {
+        standalone::retention::spawn_sweeper(app_state.clone(), config.retention_sweep());
+    }
 
-    let port = std::env::var("PORT").unwrap_or_else(|_| "7777".to_string());
-    let bind = format!("0.0.0.0:{}", port);
+    let token = app_state.shutdown.clone();
+    let mut workspaces = BTreeMap::new();
+    for name in &config.workspaces {
+        let storage = storage::connect_workspace(&config, name)
+            .await
+            .unwrap_or_else(unavailable);
+        let mut state = AppState::from_config(storage, languages(), &config)?;
+        // one signal winds down the ingests of every workspace
+        state.shutdown = token.clone();
+        let state = Arc::new(state);
+        if !config.readonly {
+            standalone::retention::spawn_sweeper(state.clone(), config.retention_sweep());
+        }
+        println!("=> serving workspace {} under /workspaces/{}", name, name);
+        workspaces.insert(name.clone(), state);
+    }
+    let app = standalone::workspaces::router(app_state, workspaces);
+
+    let bind = format!("0.0.0.0:{}", config.port);
+    #[cfg(feature = "tls")]
+    if let Some((cert, key)) = config.tls() {
+        let tls = standalone::tls::load(cert, key).await?;
+        standalone::tls::reload_on_sighup(tls.clone(), cert.to_path_buf(), key.to_path_buf());
+        let listener = std::net::TcpListener::bind(bind)?;
+        println!("=> listening on https://{}", listener.local_addr()?);
+        let server = standalone::tls::serve(
+            listener,
+            tls,
+            app,
+            config.http(),
+            shutdown::signal(token.clone()),
+            config.grace_period(),
+        );
+        tokio::select! {
+            res = server => res?,
+            _ = shutdown::deadline(token, config.grace_period()) => {}
+        }
+        #[cfg(feature = "otel")]
+        if let Err(e) = provider.shutdown() {
+            eprintln!("failed to flush spans: {}", e);
+        }
+        return Ok(());
+    }
     let listener = tokio::net::TcpListener::bind(bind).await.unwrap();
     println!("=> listening on http://{}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    let server = standalone::server::serve(
+        listener,
+        app,
+        config.http(),
+        shutdown::signal(token.clone()),
+    );
+    tokio::select! {
+        res = server => res?,
+        _ = shutdown::deadline(token, config.grace_period()) => {}
+    }
+    #[cfg(feature = "otel")]
+    if let Err(e) = provider.shutdown() {
+        eprintln!("failed to flush spans: {}", e);
+    }
     Ok(())
 }
 
-fn static_file(path: &str) -> ServeFile {
-    ServeFile::new(format!("standalone/static/{}", path))
-}
-
-async fn sse_handler(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
-    let rx = app_state.tx.subscribe();
-
-    let stream = stream::unfold(rx, move |mut rx| async move {
-        loop {
-            match rx.recv().await {
-                Ok(msg) => {
-                    let data = msg.as_json_str();
-                    let millis = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis();
-                    let event = Event::default().data(data).id(format!("{}", millis));
-                    return Some((Ok::<Event, Infallible>(event), rx));
-                }
-                Err(RecvError::Lagged(skipped)) => {
-                    println!("SSE receiver lagged, skipped {} messages", skipped);
-                    continue;
-                }
-                Err(RecvError::Closed) => {
-                    return None;
-                }
-            }
-        }
-    });
-
-    let headers = [
-        ("Cache-Control", "no-cache, no-store, must-revalidate"),
-        ("Connection", "keep-alive"),
-        ("Content-Type", "text/event-stream"),
-        ("X-Accel-Buffering", "no"), // nginx
-        ("X-Proxy-Buffering", "no"), // other proxies
-        ("Access-Control-Allow-Origin", "*"),
-        ("Access-Control-Allow-Headers", "Cache-Control"),
-    ];
-    (
-        headers,
-        Sse::new(stream).keep_alive(
-            KeepAlive::new()
-                .interval(Duration::from_millis(500))
-                .text("ping"),
-        ),
-    )
-}
-
-#[cfg(not(feature = "neo4j"))]
+#[cfg(not(any(feature = "neo4j", feature = "sqlite")))]
 fn main() -> Result<()> {
     println!(
-        "The 'neo4j' feature must be enabled to build this binary. Use: cargo run --features neo4j"
+        "A storage feature must be enabled to build this binary. Use: cargo run --features neo4j (or sqlite)"
     );
     Ok(())
 }
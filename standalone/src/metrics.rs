@@ -0,0 +1,238 @@
+use crate::outbound;
+use crate::AppState;
+use axum::extract::State;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds, in seconds, of the per-file parse time buckets.
+pub const PARSE_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+/// Upper bounds, in seconds, of the full-ingest time buckets.
+pub const INGEST_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0];
+
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct Observations {
+    /// Per bucket, not cumulative; the last one is `+Inf`.
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+pub struct Histogram {
+    bounds: &'static [f64],
+    observed: Mutex<Observations>,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            observed: Mutex::new(Observations {
+                counts: vec![0; bounds.len() + 1],
+                sum: 0.0,
+            }),
+        }
+    }
+
+    pub fn observe(&self, value: Duration) {
+        self.observe_n(value, 1);
+    }
+
+    /// Records `n` observations of the same value at once.
+    pub fn observe_n(&self, value: Duration, n: u64) {
+        let secs = value.as_secs_f64();
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(self.bounds.len());
+        let mut observed = self.observed.lock().unwrap();
+        observed.counts[bucket] += n;
+        observed.sum += secs * n as f64;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.observed.lock().unwrap().counts.iter().sum()
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let observed = self.observed.lock().unwrap();
+        header_lines(out, name, help, "histogram");
+        let mut cumulative = 0;
+        for (i, count) in observed.counts.iter().enumerate() {
+            cumulative += count;
+            let le = match self.bounds.get(i) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+        }
+        let _ = writeln!(out, "{}_sum {}", name, observed.sum);
+        let _ = writeln!(out, "{}_count {}", name, cumulative);
+    }
+}
+
+/// What the server has done since it started, for `/metrics`. Gauges are read
+/// from the state they describe when scraped rather than kept here.
+pub struct Metrics {
+    pub repos_ingested: Counter,
+    pub ingest_failures: Counter,
+    pub files_parsed: Counter,
+    pub nodes_written: Counter,
+    pub edges_written: Counter,
+    pub slow_queries: Counter,
+    pub parse_duration: Histogram,
+    pub ingest_duration: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            repos_ingested: Counter::default(),
+            ingest_failures: Counter::default(),
+            files_parsed: Counter::default(),
+            nodes_written: Counter::default(),
+            edges_written: Counter::default(),
+            slow_queries: Counter::default(),
+            parse_duration: Histogram::new(PARSE_BUCKETS),
+            ingest_duration: Histogram::new(INGEST_BUCKETS),
+        }
+    }
+}
+
+impl Metrics {
+    /// Times an ingest from now. It counts as failed unless
+    /// [`IngestTimer::succeeded`] is called before the timer is dropped, so
+    /// every early return on an error is covered.
+    pub fn ingest_timer(&self) -> IngestTimer<'_> {
+        IngestTimer {
+            metrics: self,
+            start: Instant::now(),
+            succeeded: false,
+        }
+    }
+
+    /// The Prometheus text exposition of every metric, `gauges` included as
+    /// `(name, help, value)`.
+    pub fn render(&self, gauges: &[(&str, &str, f64)]) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "mesh_repos_ingested_total",
+                "Ingests that completed",
+                &self.repos_ingested,
+            ),
+            (
+                "mesh_ingest_failures_total",
+                "Ingests that ended in an error, cancellations included",
+                &self.ingest_failures,
+            ),
+            (
+                "mesh_files_parsed_total",
+                "Files parsed into the graph",
+                &self.files_parsed,
+            ),
+            (
+                "mesh_nodes_written_total",
+                "Nodes written to storage",
+                &self.nodes_written,
+            ),
+            (
+                "mesh_edges_written_total",
+                "Edges written to storage",
+                &self.edges_written,
+            ),
+            (
+                "mesh_slow_queries_total",
+                "Queries that took longer than MESH_SLOW_QUERY_MS",
+                &self.slow_queries,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            header_lines(&mut out, name, help, "counter");
+            let _ = writeln!(out, "{} {}", name, counter.get());
+        }
+        self.parse_duration.render(
+            &mut out,
+            "mesh_parse_duration_seconds",
+            "Time spent parsing a file, averaged over the files of one graph build",
+        );
+        self.ingest_duration.render(
+            &mut out,
+            "mesh_ingest_duration_seconds",
+            "Time a completed ingest took from start to finish",
+        );
+        for (name, help, value) in gauges {
+            header_lines(&mut out, name, help, "gauge");
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
+
+pub(crate) fn header_lines(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+pub struct IngestTimer<'a> {
+    metrics: &'a Metrics,
+    start: Instant,
+    succeeded: bool,
+}
+
+impl IngestTimer<'_> {
+    pub fn succeeded(mut self) {
+        self.succeeded = true;
+        self.metrics.repos_ingested.inc();
+        self.metrics.ingest_duration.observe(self.start.elapsed());
+    }
+
+    /// Records nothing, for runs such as dry runs that ingest nothing.
+    pub fn discard(mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for IngestTimer<'_> {
+    fn drop(&mut self) {
+        if !self.succeeded {
+            self.metrics.ingest_failures.inc();
+        }
+    }
+}
+
+pub async fn handler(State(state): State<Arc<AppState>>) -> Response {
+    let events = state.event_stats.snapshot(state.event_capacity);
+    let gauges = [
+        (
+            "mesh_active_ingests",
+            "Ingests running now",
+            state.ingests.active() as f64,
+        ),
+        (
+            "mesh_sse_subscribers",
+            "Open /events connections",
+            events.subscribers as f64,
+        ),
+    ];
+    let mut out = state.metrics.render(&gauges);
+    outbound::render(&mut out, &state.outbound);
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out).into_response()
+}
@@ -0,0 +1,62 @@
+//! MessagePack as an alternative to JSON for the larger responses, for
+//! programmatic clients that spend more time decoding JSON than waiting on
+//! the graph. Asked for with `Accept: application/msgpack`; anything else,
+//! browsers included, gets JSON. Structs are written as maps keyed by field
+//! name, so both bodies decode to the same structure. Errors stay JSON.
+
+use crate::types::MeshError;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+pub const MSGPACK: &str = "application/msgpack";
+/// The type used before `application/msgpack` was registered, still sent by
+/// some clients.
+const MSGPACK_LEGACY: &str = "application/x-msgpack";
+
+/// How a response body is serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Format {
+    /// MessagePack when `Accept` names it, JSON otherwise.
+    pub fn of(headers: &HeaderMap) -> Self {
+        let accepts = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|range| range.split(';').next().unwrap_or_default().trim())
+            .any(|range| {
+                range.eq_ignore_ascii_case(MSGPACK) || range.eq_ignore_ascii_case(MSGPACK_LEGACY)
+            });
+        if accepts {
+            Format::MessagePack
+        } else {
+            Format::Json
+        }
+    }
+
+    /// `value` as a response body in this format. Responses say they vary by
+    /// `Accept`, so a cache doesn't hand one format to a client asking for
+    /// the other.
+    pub fn respond<T: Serialize>(self, value: &T) -> Response {
+        let vary = [(header::VARY, HeaderValue::from_static("accept"))];
+        match self {
+            Format::Json => (vary, Json(value)).into_response(),
+            Format::MessagePack => match rmp_serde::to_vec_named(value) {
+                Ok(body) => (vary, [(header::CONTENT_TYPE, MSGPACK)], body).into_response(),
+                Err(e) => MeshError::Internal(anyhow::anyhow!(
+                    "Failed to serialize the response as MessagePack: {}",
+                    e
+                ))
+                .into_response(),
+            },
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::fanout;
+use crate::storage::{EdgeRecord, NodeRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+pub const DEFAULT_DEPTH: usize = 1;
+/// Hops a neighborhood may reach; a few more and most graphs are pulled in
+/// whole through their `File` and `Directory` nodes.
+pub const MAX_DEPTH: usize = 4;
+pub const DEFAULT_MAX_NODES: usize = 500;
+
+/// What of the graph around a node to take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    pub depth: usize,
+    /// Edge kinds followed and returned, e.g. `CALLS`; every kind when empty.
+    pub edge_kinds: Vec<String>,
+    /// Nodes returned at most, the node asked about included.
+    pub max_nodes: usize,
+    /// Neighbors of any one node followed at most; see [`crate::fanout`].
+    pub max_edges_per_node: Option<usize>,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Scope {
+            depth: DEFAULT_DEPTH,
+            edge_kinds: Vec::new(),
+            max_nodes: DEFAULT_MAX_NODES,
+            max_edges_per_node: Some(fanout::DEFAULT_MAX_EDGES_PER_NODE),
+        }
+    }
+}
+
+/// A node within reach of the one asked about.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Neighbor {
+    #[serde(flatten)]
+    pub node: NodeRecord,
+    /// Hops from the node asked about, whichever way the edges point; 0 for
+    /// that node itself.
+    pub hops: usize,
+    /// Neighbors of this node not followed because of the edge cap.
+    #[serde(default, skip_serializing_if = "fanout::is_zero")]
+    pub omitted: usize,
+}
+
+/// The subgraph induced by the nodes within reach of a node.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Subgraph {
+    /// Nearest first, starting with the node asked about.
+    pub nodes: Vec<Neighbor>,
+    /// Every edge of a followed kind between two of `nodes`.
+    pub edges: Vec<EdgeRecord>,
+    /// Whether `max_nodes` or the edge cap left out some nodes within reach.
+    pub truncated: bool,
+}
+
+/// The nodes `scope.depth` hops or fewer from the node with `id`, following
+/// edges either way, and the edges between them. Nodes are taken breadth
+/// first, so when `scope.max_nodes` cuts the walk short the nearest ones are
+/// kept. Of a node with more neighbors than `scope.max_edges_per_node`,
+/// those with the most edges of their own, the more central ones, are
+/// followed. `None` when no node has `id`.
+pub fn neighborhood(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    id: &str,
+    scope: &Scope,
+) -> Option<Subgraph> {
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let root = by_id.get(id)?;
+    let followed: Vec<&EdgeRecord> = edges
+        .iter()
+        .filter(|e| scope.edge_kinds.is_empty() || scope.edge_kinds.contains(&e.kind))
+        .collect();
+    let mut degrees: HashMap<&str, usize> = HashMap::new();
+    for edge in &followed {
+        *degrees.entry(edge.source.as_str()).or_default() += 1;
+        *degrees.entry(edge.target.as_str()).or_default() += 1;
+    }
+    let degree = |id: &str| degrees.get(id).copied().unwrap_or_default();
+    let mut adjacent: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in &followed {
+        adjacent
+            .entry(edge.source.as_str())
+            .or_default()
+            .push(edge.target.as_str());
+        adjacent
+            .entry(edge.target.as_str())
+            .or_default()
+            .push(edge.source.as_str());
+    }
+
+    let mut subgraph = Subgraph::default();
+    let mut seen = HashSet::from([id]);
+    let mut queue = VecDeque::from([(*root, 0)]);
+    while let Some((node, hops)) = queue.pop_front() {
+        if hops == scope.depth {
+            subgraph.nodes.push(Neighbor {
+                node: node.clone(),
+                hops,
+                omitted: 0,
+            });
+            continue;
+        }
+        // an edge to a node of another repo, or one not stored, leads nowhere
+        let mut listed = HashSet::new();
+        let mut unseen: Vec<&NodeRecord> = adjacent
+            .get(node.id.as_str())
+            .into_iter()
+            .flatten()
+            .filter(|next| !seen.contains(*next) && listed.insert(*next))
+            .filter_map(|next| by_id.get(next).copied())
+            .collect();
+        let omitted = fanout::cap(&mut unseen, scope.max_edges_per_node, |a, b| {
+            degree(&b.id)
+                .cmp(&degree(&a.id))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        subgraph.truncated |= omitted > 0;
+        subgraph.nodes.push(Neighbor {
+            node: node.clone(),
+            hops,
+            omitted,
+        });
+        for neighbor in unseen {
+            if seen.len() >= scope.max_nodes {
+                subgraph.truncated = true;
+                break;
+            }
+            seen.insert(neighbor.id.as_str());
+            queue.push_back((neighbor, hops + 1));
+        }
+    }
+
+    let kept: HashSet<&str> = subgraph.nodes.iter().map(|n| n.node.id.as_str()).collect();
+    let mut unique = HashSet::new();
+    subgraph.edges = followed
+        .into_iter()
+        .filter(|e| kept.contains(e.source.as_str()) && kept.contains(e.target.as_str()))
+        .filter(|e| unique.insert((&e.source, &e.target, &e.kind)))
+        .cloned()
+        .collect();
+    Some(subgraph)
+}
@@ -0,0 +1,276 @@
+//! Calls the server makes to services outside it, such as the embedding
+//! provider. Each goes through an [`Outbound`] that times it out, retries it
+//! and, once the service has failed enough times in a row, stops calling it
+//! for a while, so a service that is down costs an ingest a quick error per
+//! call rather than a timeout.
+
+use crate::clone::RetryPolicy;
+use crate::metrics::{header_lines, Counter};
+use anyhow::{anyhow, Result};
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(200);
+/// Failures in a row that open the breaker.
+pub const DEFAULT_BREAKER_FAILURES: u32 = 5;
+pub const DEFAULT_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail straight away until the cooldown is over.
+    Open,
+    /// The cooldown is over and one call is let through to see whether the
+    /// service is back.
+    HalfOpen,
+}
+
+impl BreakerState {
+    /// How `/metrics` reports the state.
+    pub fn gauge(self) -> f64 {
+        match self {
+            BreakerState::Closed => 0.0,
+            BreakerState::HalfOpen => 1.0,
+            BreakerState::Open => 2.0,
+        }
+    }
+}
+
+struct Tripped {
+    failures: u32,
+    opened_at: Option<Instant>,
+    /// Whether the half-open call is out, so no other is let through too.
+    probing: bool,
+}
+
+/// Opens after `failures` failed calls in a row and stays open for
+/// `cooldown`. The first call after it is the only one let through until it
+/// either succeeds, closing the breaker, or fails, opening it again.
+pub struct Breaker {
+    failures: u32,
+    cooldown: Duration,
+    tripped: Mutex<Tripped>,
+    /// How many times it has opened.
+    pub trips: Counter,
+}
+
+impl Breaker {
+    pub fn new(failures: u32, cooldown: Duration) -> Self {
+        Breaker {
+            failures: failures.max(1),
+            cooldown,
+            tripped: Mutex::new(Tripped {
+                failures: 0,
+                opened_at: None,
+                probing: false,
+            }),
+            trips: Counter::default(),
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        let tripped = self.tripped.lock().unwrap();
+        match tripped.opened_at {
+            None => BreakerState::Closed,
+            Some(at) if !tripped.probing && at.elapsed() < self.cooldown => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    /// Whether a call may be made now; when the breaker is half-open, only
+    /// the first caller is told yes.
+    pub fn allow(&self) -> bool {
+        let mut tripped = self.tripped.lock().unwrap();
+        match tripped.opened_at {
+            None => true,
+            Some(at) if tripped.probing || at.elapsed() < self.cooldown => false,
+            Some(_) => {
+                tripped.probing = true;
+                true
+            }
+        }
+    }
+
+    /// How long until the cooldown is over; zero when the breaker isn't open.
+    pub fn remaining(&self) -> Duration {
+        let tripped = self.tripped.lock().unwrap();
+        tripped
+            .opened_at
+            .map(|at| self.cooldown.saturating_sub(at.elapsed()))
+            .unwrap_or_default()
+    }
+
+    pub fn succeeded(&self) {
+        let mut tripped = self.tripped.lock().unwrap();
+        tripped.failures = 0;
+        tripped.opened_at = None;
+        tripped.probing = false;
+    }
+
+    pub fn failed(&self) {
+        let mut tripped = self.tripped.lock().unwrap();
+        tripped.failures = tripped.failures.saturating_add(1);
+        // a failed probe opens it again for another cooldown
+        if tripped.probing || (tripped.opened_at.is_none() && tripped.failures >= self.failures) {
+            tripped.opened_at = Some(Instant::now());
+            tripped.probing = false;
+            self.trips.inc();
+        }
+    }
+}
+
+/// How every [`Outbound`] times out, retries and trips its breaker.
+#[derive(Debug, Clone, Copy)]
+pub struct OutboundPolicy {
+    /// How long one attempt may take.
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+    pub breaker_failures: u32,
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for OutboundPolicy {
+    fn default() -> Self {
+        OutboundPolicy {
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy {
+                max_attempts: DEFAULT_ATTEMPTS,
+                base_delay: DEFAULT_BASE_DELAY,
+            },
+            breaker_failures: DEFAULT_BREAKER_FAILURES,
+            breaker_cooldown: DEFAULT_BREAKER_COOLDOWN,
+        }
+    }
+}
+
+/// The calls to one service, named for `/metrics`.
+pub struct Outbound {
+    name: &'static str,
+    timeout: Duration,
+    retry: RetryPolicy,
+    pub breaker: Breaker,
+    /// Attempts that failed or timed out.
+    pub failures: Counter,
+    /// Calls refused because the breaker was open.
+    pub rejected: Counter,
+}
+
+impl Outbound {
+    pub fn new(name: &'static str, policy: OutboundPolicy) -> Self {
+        Outbound {
+            name,
+            timeout: policy.timeout,
+            retry: policy.retry,
+            breaker: Breaker::new(policy.breaker_failures, policy.breaker_cooldown),
+            failures: Counter::default(),
+            rejected: Counter::default(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Runs `call` until it succeeds, up to the policy's attempts, each cut
+    /// off after its timeout. Fails without calling when the breaker is open,
+    /// and stops retrying once it opens.
+    pub async fn call<T, F, Fut>(&self, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            if !self.breaker.allow() {
+                self.rejected.inc();
+                return Err(anyhow!(
+                    "{} is failing, so it isn't called for another {}s",
+                    self.name,
+                    self.breaker.remaining().as_secs()
+                ));
+            }
+            let error = match tokio::time::timeout(self.timeout, call()).await {
+                Ok(Ok(value)) => {
+                    self.breaker.succeeded();
+                    return Ok(value);
+                }
+                Ok(Err(e)) => e,
+                Err(_) => anyhow!("{} took longer than {:?}", self.name, self.timeout),
+            };
+            self.failures.inc();
+            self.breaker.failed();
+            if attempt >= self.retry.max_attempts || self.breaker.state() == BreakerState::Open {
+                return Err(error);
+            }
+            attempt += 1;
+            warn!(
+                "{} failed, attempt {} of {} in {:?}: {:#}",
+                self.name,
+                attempt,
+                self.retry.max_attempts,
+                self.retry.delay(attempt),
+                error
+            );
+            tokio::time::sleep(self.retry.delay(attempt)).await;
+        }
+    }
+}
+
+/// The breaker state and counts of each of `calls`, in the Prometheus text
+/// exposition, labelled by service.
+pub fn render(out: &mut String, calls: &[Arc<Outbound>]) {
+    if calls.is_empty() {
+        return;
+    }
+    header_lines(
+        out,
+        "mesh_outbound_breaker_state",
+        "0 while calls to the service go through, 1 while one is let through to test it, 2 while none are",
+        "gauge",
+    );
+    for outbound in calls {
+        let _ = writeln!(
+            out,
+            "mesh_outbound_breaker_state{{service=\"{}\"}} {}",
+            outbound.name,
+            outbound.breaker.state().gauge()
+        );
+    }
+    let counters = [
+        (
+            "mesh_outbound_breaker_trips_total",
+            "Times the service failed often enough to stop calling it",
+        ),
+        (
+            "mesh_outbound_failures_total",
+            "Calls to the service that failed or timed out, retries included",
+        ),
+        (
+            "mesh_outbound_rejected_total",
+            "Calls not made because the service's breaker was open",
+        ),
+    ];
+    for (i, (name, help)) in counters.into_iter().enumerate() {
+        header_lines(out, name, help, "counter");
+        for outbound in calls {
+            let counts = [
+                &outbound.breaker.trips,
+                &outbound.failures,
+                &outbound.rejected,
+            ];
+            let _ = writeln!(
+                out,
+                "{}{{service=\"{}\"}} {}",
+                name,
+                outbound.name,
+                counts[i].get()
+            );
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::storage::NodeRecord;
+use std::path::Path;
+
+/// Rewrites the paths nodes are stored under, so a graph ingested from one
+/// checkout links back to files in another: a leading `strip`, such as the
+/// clone directory, is taken off, then `add`, such as where editors keep the
+/// repo, is put in front. Paths without the `strip` prefix only get `add`.
+/// Applied once a file has been read for the last time, just before it is
+/// stored, so ids and everything read from the checkout are as before.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathMap {
+    strip: Option<String>,
+    add: Option<String>,
+}
+
+impl PathMap {
+    pub fn new(strip: Option<&str>, add: Option<&str>) -> Self {
+        PathMap {
+            strip: strip.map(str::to_string),
+            add: add.map(|add| add.trim_end_matches('/').to_string()),
+        }
+    }
+
+    /// Whether it leaves every path as it is.
+    pub fn is_identity(&self) -> bool {
+        self.strip.is_none() && self.add.is_none()
+    }
+
+    /// `path` as it is stored. Only whole components are stripped, so
+    /// `/srv/app` leaves `/srv/apps/main.rs` be.
+    pub fn map(&self, path: &str) -> String {
+        let rest = match &self.strip {
+            Some(strip) => Path::new(path)
+                .strip_prefix(strip)
+                .ok()
+                .and_then(Path::to_str)
+                .unwrap_or(path),
+            None => path,
+        };
+        match &self.add {
+            Some(add) if rest.is_empty() => add.clone(),
+            Some(add) => format!("{}/{}", add, rest.trim_start_matches('/')),
+            None => rest.to_string(),
+        }
+    }
+
+    /// Maps the file of each of `nodes`, and the name of a `File` node that
+    /// is named by its path.
+    pub fn apply(&self, nodes: &mut [NodeRecord]) {
+        if self.is_identity() {
+            return;
+        }
+        for node in nodes {
+            let file = self.map(&node.file);
+            if node.kind == "File" && node.name == node.file {
+                node.name = file.clone();
+            }
+            node.file = file;
+        }
+    }
+}
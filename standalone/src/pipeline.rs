@@ -0,0 +1,52 @@
+use std::future::Future;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Files prepared for storage that may wait on the writer before the
+/// parsing that prepares them blocks.
+pub const DEFAULT_WRITE_QUEUE: usize = 16;
+
+/// Work produced on a blocking thread and consumed on an async task, joined
+/// by a channel holding at most `bound` items. Once that many are waiting
+/// the producer blocks in [`Feed::send`] until the consumer takes one, so
+/// however far a slow consumer falls behind, no more than `bound` items are
+/// held between the two.
+pub struct Pipeline<T> {
+    rx: mpsc::Receiver<T>,
+    producer: JoinHandle<()>,
+}
+
+/// The producer's end of a [`Pipeline`].
+pub struct Feed<T>(mpsc::Sender<T>);
+
+impl<T> Feed<T> {
+    /// Hands `item` to the consumer, waiting for room first. `false` once
+    /// the consumer is gone, when the producer should stop.
+    pub fn send(&self, item: T) -> bool {
+        self.0.blocking_send(item).is_ok()
+    }
+}
+
+impl<T: Send + 'static> Pipeline<T> {
+    /// Starts `produce` on a blocking thread. A consumer that stops early
+    /// drops the pipeline, which makes the producer's next send fail.
+    pub fn spawn(bound: usize, produce: impl FnOnce(Feed<T>) + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::channel(bound.max(1));
+        let producer = tokio::task::spawn_blocking(move || produce(Feed(tx)));
+        Pipeline { rx, producer }
+    }
+
+    /// The next item, or `None` once the producer is done.
+    pub fn next(&mut self) -> impl Future<Output = Option<T>> + '_ {
+        self.rx.recv()
+    }
+
+    /// Waits for the producer to return, failing if it panicked, which also
+    /// ends the items early.
+    pub async fn finish(self) -> anyhow::Result<()> {
+        drop(self.rx);
+        self.producer
+            .await
+            .map_err(|e| anyhow::anyhow!("Pipeline producer panicked: {}", e))
+    }
+}
@@ -0,0 +1,125 @@
+use crate::clone::Submodule;
+use crate::storage::{edge_kind, kind_key, EdgeRecord, NodeRecord};
+use ast::lang::graphs::EdgeType;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Files whose directory is the root of a project, in the order they're
+/// looked for when a directory has several.
+pub const MANIFESTS: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "go.mod",
+    "pyproject.toml",
+    "setup.py",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "Gemfile",
+];
+
+pub const PROJECT_KIND: &str = "Project";
+/// The meta key of a `File` naming its project's root, as the `Project`
+/// node's own `root` does.
+pub const PROJECT_META: &str = "project";
+/// The root of a project at the top of the repo.
+pub const TOP_LEVEL: &str = ".";
+/// The meta keys of a `Project` in a submodule, naming where the submodule
+/// is cloned from and the commit it has out.
+pub const SUBMODULE_REMOTE: &str = "remote";
+pub const SUBMODULE_COMMIT: &str = "commit";
+
+/// The sub-projects of a monorepo, each the files under a directory with a
+/// manifest that aren't under a deeper one. `files` pairs each `File` node
+/// with its path relative to `root`, the checkout they were read from.
+/// Every file with a project is tagged with its root in its meta, and each
+/// project becomes a `Project` node that `CONTAINS` its files. Files above
+/// every manifest belong to no project.
+///
+/// The directory of each of `submodules` is a project's root too, manifest or
+/// not, and every project in a submodule keeps its [`SUBMODULE_REMOTE`] and
+/// [`SUBMODULE_COMMIT`], so its code can be told from the repo's own.
+pub fn detect(
+    root: &Path,
+    repo_id: &str,
+    files: Vec<(&mut NodeRecord, String)>,
+    submodules: &[Submodule],
+) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    // directory -> its manifest, `None` when it has none
+    let mut manifests: HashMap<String, Option<&'static str>> = HashMap::new();
+    let mut manifest_of = |dir: &str| {
+        *manifests.entry(dir.to_string()).or_insert_with(|| {
+            MANIFESTS
+                .iter()
+                .copied()
+                .find(|m| root.join(dir).join(m).is_file())
+        })
+    };
+    let mut projects: BTreeMap<String, NodeRecord> = BTreeMap::new();
+    let mut edges = Vec::new();
+    for (file, rel) in files {
+        // the file's directory first, the checkout itself, "", last
+        let found = Path::new(&rel).ancestors().skip(1).find_map(|dir| {
+            let dir = dir.to_string_lossy().into_owned();
+            let manifest = manifest_of(&dir);
+            (manifest.is_some() || submodules.iter().any(|s| s.path == dir))
+                .then_some((dir, manifest))
+        });
+        let Some((dir, manifest)) = found else {
+            continue;
+        };
+        let project_root = if dir.is_empty() {
+            TOP_LEVEL.to_string()
+        } else {
+            dir.clone()
+        };
+        let project = projects.entry(project_root.clone()).or_insert_with(|| {
+            // stored the way the file is, with whatever prefix that has
+            let prefix = file.file.strip_suffix(rel.as_str()).unwrap_or_default();
+            // a submodule without a manifest is filed under its directory
+            let manifest_path = match (dir.as_str(), manifest) {
+                ("", Some(manifest)) => format!("{}{}", prefix, manifest),
+                (dir, Some(manifest)) => format!("{}{}/{}", prefix, dir, manifest),
+                (dir, None) => format!("{}{}", prefix, dir),
+            };
+            let name = match dir.rsplit('/').next() {
+                Some(name) if !name.is_empty() => name.to_string(),
+                _ => repo_id.rsplit('/').next().unwrap_or(repo_id).to_string(),
+            };
+            let mut meta = BTreeMap::from([("root".to_string(), project_root.clone())]);
+            if let Some(manifest) = manifest {
+                meta.insert("manifest".to_string(), manifest.to_string());
+            }
+            // the innermost submodule the project is in
+            let submodule = submodules
+                .iter()
+                .filter(|s| dir == s.path || dir.starts_with(&format!("{}/", s.path)))
+                .max_by_key(|s| s.path.len());
+            if let Some(submodule) = submodule {
+                meta.insert(SUBMODULE_REMOTE.to_string(), submodule.remote.clone());
+                meta.insert(SUBMODULE_COMMIT.to_string(), submodule.commit.clone());
+            }
+            NodeRecord {
+                repo_id: repo_id.to_string(),
+                id: kind_key(PROJECT_KIND, &name, &manifest_path, 0, None),
+                kind: PROJECT_KIND.to_string(),
+                name,
+                file: manifest_path,
+                start: 0,
+                end: 0,
+                body: String::new(),
+                meta,
+                span: None,
+            }
+        });
+        file.meta
+            .insert(PROJECT_META.to_string(), project_root.clone());
+        edges.push(EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind: edge_kind(&EdgeType::Contains),
+            source: project.id.clone(),
+            target: file.id.clone(),
+        });
+    }
+    (projects.into_values().collect(), edges)
+}
@@ -0,0 +1,738 @@
+use crate::storage::{EdgeRecord, NodeRecord};
+use crate::tasks::{ASSIGNEE, ISSUE, MARKER, TASK_KIND};
+use crate::typing::TYPE;
+use crate::visibility::VISIBILITY;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamType {
+    String,
+    Integer,
+    Boolean,
+    StringList,
+}
+
+impl ParamType {
+    pub fn accepts(&self, value: &Value) -> bool {
+        match self {
+            ParamType::String => value.is_string(),
+            ParamType::Integer => value.is_i64() || value.is_u64(),
+            ParamType::Boolean => value.is_boolean(),
+            ParamType::StringList => value
+                .as_array()
+                .is_some_and(|items| items.iter().all(Value::is_string)),
+        }
+    }
+}
+
+/// A vetted, read-only statement and the parameters it needs, written once per
+/// storage backend. Both forms must return the same columns, and both take a
+/// `repo_id` parameter on top of `params` that scopes them to one repo's graph,
+/// or to all of them when it is empty.
+pub struct QueryTemplate {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub params: &'static [(&'static str, ParamType)],
+    pub cypher: &'static str,
+    pub sql: &'static str,
+    /// The same query over one repo's graph held in memory, for repos in
+    /// the [`GraphCache`](crate::storage::cache::GraphCache); `None` for
+    /// what the graph doesn't hold, like diagnostics.
+    pub cached: Option<CachedQuery>,
+}
+
+/// A template's query run over a repo's nodes and edges, returning its rows.
+pub type CachedQuery = fn(&[NodeRecord], &[EdgeRecord], &Map<String, Value>) -> Vec<Value>;
+
+/// The scoping parameter added by [`scoped_params`]; callers can't set it.
+pub const REPO_PARAM: &str = "repo_id";
+
+const TEMPLATES: &[QueryTemplate] = &[
+    QueryTemplate {
+        key: "callers-of-function",
+        description: "Functions that call the named function",
+        params: &[("name", ParamType::String)],
+        cypher: "MATCH (caller:Function)-[:CALLS]->(f:Function {name: $name})
+                 WHERE $repo_id = '' OR f.repo_id = $repo_id
+                 RETURN caller.name AS name, caller.file AS file, caller.start AS start,
+                        caller.end AS end, caller.start_col AS start_column,
+                        caller.end_col AS end_column, caller.start_byte AS start_byte,
+                        caller.end_byte AS end_byte
+                 ORDER BY file, start",
+        sql: "SELECT c.name AS name, c.file AS file, c.start_line AS start,
+                     c.end_line AS \"end\", c.start_col AS start_column,
+                     c.end_col AS end_column, c.start_byte AS start_byte,
+                     c.end_byte AS end_byte
+              FROM edges e
+              JOIN nodes c ON c.repo_id = e.repo_id AND c.id = e.source
+              JOIN nodes f ON f.repo_id = e.repo_id AND f.id = e.target
+              WHERE e.kind = 'CALLS' AND c.kind = 'Function' AND f.kind = 'Function'
+                AND f.name = :name AND (:repo_id = '' OR e.repo_id = :repo_id)
+              ORDER BY file, start",
+        cached: Some(callers_of_function),
+    },
+    QueryTemplate {
+        key: "call-graph-from",
+        description: "Every function reachable from the named function through calls",
+        params: &[("name", ParamType::String)],
+        cypher: "MATCH (f:Function {name: $name})-[:CALLS*1..]->(c:Function)
+                 WHERE $repo_id = '' OR f.repo_id = $repo_id
+                 RETURN DISTINCT c.name AS name, c.file AS file, c.start AS start,
+                        c.end AS end, c.start_col AS start_column, c.end_col AS end_column,
+                        c.start_byte AS start_byte, c.end_byte AS end_byte
+                 ORDER BY file, start",
+        sql: "WITH RECURSIVE reachable(repo_id, id) AS (
+                  SELECT e.repo_id, e.target FROM edges e
+                  JOIN nodes f ON f.repo_id = e.repo_id AND f.id = e.source
+                  WHERE e.kind = 'CALLS' AND f.kind = 'Function' AND f.name = :name
+                    AND (:repo_id = '' OR e.repo_id = :repo_id)
+                  UNION
+                  SELECT e.repo_id, e.target FROM edges e
+                  JOIN reachable r ON r.repo_id = e.repo_id AND r.id = e.source
+                  WHERE e.kind = 'CALLS'
+              )
+              SELECT DISTINCT c.name AS name, c.file AS file, c.start_line AS start,
+                     c.end_line AS \"end\", c.start_col AS start_column,
+                     c.end_col AS end_column, c.start_byte AS start_byte,
+                     c.end_byte AS end_byte
+              FROM reachable r
+              JOIN nodes c ON c.repo_id = r.repo_id AND c.id = r.id
+              WHERE c.kind = 'Function'
+              ORDER BY file, start",
+        cached: Some(call_graph_from),
+    },
+    QueryTemplate {
+        key: "files-importing-module",
+        description: "Files with an import statement mentioning the module",
+        params: &[("module", ParamType::String)],
+        cypher: "MATCH (f:File)-[:CONTAINS]->(i:Import)
+                 WHERE i.body CONTAINS $module AND ($repo_id = '' OR f.repo_id = $repo_id)
+                 RETURN DISTINCT f.name AS name, f.file AS file
+                 ORDER BY file",
+        sql: "SELECT DISTINCT f.name AS name, f.file AS file
+              FROM edges e
+              JOIN nodes f ON f.repo_id = e.repo_id AND f.id = e.source
+              JOIN nodes i ON i.repo_id = e.repo_id AND i.id = e.target
+              WHERE e.kind = 'CONTAINS' AND f.kind = 'File' AND i.kind = 'Import'
+                AND instr(i.body, :module) > 0 AND (:repo_id = '' OR e.repo_id = :repo_id)
+              ORDER BY file",
+        cached: Some(files_importing_module),
+    },
+    QueryTemplate {
+        key: "file-dependencies",
+        description: "Files in the repo that the file imports, directly",
+        params: &[("file", ParamType::String)],
+        cypher: "MATCH (f:File)-[:IMPORTS]->(t:File)
+                 WHERE (f.file = $file OR f.file ENDS WITH '/' + $file)
+                   AND ($repo_id = '' OR f.repo_id = $repo_id)
+                 RETURN DISTINCT t.name AS name, t.file AS file
+                 ORDER BY file",
+        sql: "SELECT DISTINCT t.name AS name, t.file AS file
+              FROM edges e
+              JOIN nodes f ON f.repo_id = e.repo_id AND f.id = e.source
+              JOIN nodes t ON t.repo_id = e.repo_id AND t.id = e.target
+              WHERE e.kind = 'IMPORTS' AND f.kind = 'File' AND t.kind = 'File'
+                AND (f.file = :file OR f.file LIKE '%/' || :file)
+                AND (:repo_id = '' OR e.repo_id = :repo_id)
+              ORDER BY file",
+        cached: Some(file_dependencies),
+    },
+    QueryTemplate {
+        key: "symbols-in-file",
+        description: "Functions, classes and variables defined in a file",
+        params: &[("file", ParamType::String)],
+        cypher: "MATCH (n:Data_Bank)
+                 WHERE (n.file = $file OR n.file ENDS WITH '/' + $file)
+                   AND (n:Function OR n:Class OR n:Var OR n:Trait OR n:DataModel)
+                   AND ($repo_id = '' OR n.repo_id = $repo_id)
+                 RETURN [l IN labels(n) WHERE l <> 'Data_Bank'][0] AS kind,
+                        n.name AS name, n.start AS start, n.end AS end,
+                        n.start_col AS start_column, n.end_col AS end_column,
+                        n.start_byte AS start_byte, n.end_byte AS end_byte
+                 ORDER BY start",
+        sql: "SELECT kind, name, start_line AS start, end_line AS \"end\",
+                     start_col AS start_column, end_col AS end_column,
+                     start_byte, end_byte
+              FROM nodes
+              WHERE (file = :file OR file LIKE '%/' || :file)
+                AND kind IN ('Function', 'Class', 'Var', 'Trait', 'DataModel')
+                AND (:repo_id = '' OR repo_id = :repo_id)
+              ORDER BY start",
+        cached: Some(symbols_in_file),
+    },
+    QueryTemplate {
+        key: "complexity-hotspots",
+        description: "Functions and files by cyclomatic complexity, most complex first",
+        params: &[],
+        cypher: "MATCH (n:Data_Bank)
+                 WHERE (n:Function OR n:File) AND n.complexity IS NOT NULL
+                   AND ($repo_id = '' OR n.repo_id = $repo_id)
+                 RETURN [l IN labels(n) WHERE l <> 'Data_Bank'][0] AS kind,
+                        n.name AS name, n.file AS file, n.start AS start, n.end AS end,
+                        toInteger(n.complexity) AS complexity,
+                        toInteger(n.lines) AS lines, toInteger(n.nesting) AS nesting
+                 ORDER BY complexity DESC, nesting DESC, file, start",
+        sql: "SELECT kind, name, file, start_line AS start, end_line AS \"end\",
+                     CAST(json_extract(meta, '$.complexity') AS INTEGER) AS complexity,
+                     CAST(json_extract(meta, '$.lines') AS INTEGER) AS lines,
+                     CAST(json_extract(meta, '$.nesting') AS INTEGER) AS nesting
+              FROM nodes
+              WHERE kind IN ('Function', 'File')
+                AND json_extract(meta, '$.complexity') IS NOT NULL
+                AND (:repo_id = '' OR repo_id = :repo_id)
+              ORDER BY complexity DESC, nesting DESC, file, start",
+        cached: Some(complexity_hotspots),
+    },
+    QueryTemplate {
+        key: "functions-returning-type",
+        description: "Functions whose written return type is the given type",
+        params: &[("type", ParamType::String)],
+        cypher: "MATCH (f:Function)
+                 WHERE f.type = $type AND ($repo_id = '' OR f.repo_id = $repo_id)
+                 RETURN f.name AS name, f.file AS file, f.start AS start, f.end AS end,
+                        f.start_col AS start_column, f.end_col AS end_column,
+                        f.start_byte AS start_byte, f.end_byte AS end_byte
+                 ORDER BY file, start",
+        sql: "SELECT name, file, start_line AS start, end_line AS \"end\",
+                     start_col AS start_column, end_col AS end_column,
+                     start_byte, end_byte
+              FROM nodes
+              WHERE kind = 'Function' AND json_extract(meta, '$.type') = :type
+                AND (:repo_id = '' OR repo_id = :repo_id)
+              ORDER BY file, start",
+        cached: Some(functions_returning_type),
+    },
+    QueryTemplate {
+        key: "public-api",
+        description: "Public and exported symbols defined in a file",
+        params: &[("file", ParamType::String)],
+        cypher: "MATCH (n:Data_Bank)
+                 WHERE (n.file = $file OR n.file ENDS WITH '/' + $file)
+                   AND n.visibility IN ['public', 'exported']
+                   AND ($repo_id = '' OR n.repo_id = $repo_id)
+                 RETURN [l IN labels(n) WHERE l <> 'Data_Bank'][0] AS kind,
+                        n.name AS name, n.visibility AS visibility, n.start AS start,
+                        n.end AS end
+                 ORDER BY start",
+        sql: "SELECT kind, name, json_extract(meta, '$.visibility') AS visibility,
+                     start_line AS start, end_line AS \"end\"
+              FROM nodes
+              WHERE (file = :file OR file LIKE '%/' || :file)
+                AND json_extract(meta, '$.visibility') IN ('public', 'exported')
+                AND (:repo_id = '' OR repo_id = :repo_id)
+              ORDER BY start",
+        cached: Some(public_api),
+    },
+    QueryTemplate {
+        key: "open-tasks",
+        description: "TODO, FIXME and other task markers left in comments",
+        params: &[],
+        cypher: "MATCH (t:Task)
+                 WHERE $repo_id = '' OR t.repo_id = $repo_id
+                 RETURN t.marker AS marker, t.name AS text, t.assignee AS assignee,
+                        t.issue AS issue, t.file AS file, t.start AS start
+                 ORDER BY file, start",
+        sql: "SELECT json_extract(meta, '$.marker') AS marker, name AS text,
+                     json_extract(meta, '$.assignee') AS assignee,
+                     json_extract(meta, '$.issue') AS issue, file, start_line AS start
+              FROM nodes
+              WHERE kind = 'Task' AND (:repo_id = '' OR repo_id = :repo_id)
+              ORDER BY file, start",
+        cached: Some(open_tasks),
+    },
+    QueryTemplate {
+        key: "parse-diagnostics",
+        description: "Parts of files that failed to parse during the last ingest",
+        params: &[],
+        cypher: "MATCH (d:Mesh_Diagnostic)
+                 WHERE $repo_id = '' OR d.repo_id = $repo_id
+                 RETURN d.file AS file, d.start AS start, d.end AS end,
+                        d.severity AS severity, d.message AS message
+                 ORDER BY file, start",
+        sql: "SELECT file, start_byte AS start, end_byte AS \"end\", severity, message
+              FROM diagnostics
+              WHERE :repo_id = '' OR repo_id = :repo_id
+              ORDER BY file, start",
+        cached: None,
+    },
+];
+
+/// The columns the location templates return for `node`.
+fn location(node: &NodeRecord) -> Value {
+    let span = node.span.as_ref();
+    json!({
+        "name": node.name,
+        "file": node.file,
+        "start": node.start,
+        "end": node.end,
+        "start_column": span.map(|s| s.start_column),
+        "end_column": span.map(|s| s.end_column),
+        "start_byte": span.map(|s| s.start_byte),
+        "end_byte": span.map(|s| s.end_byte),
+    })
+}
+
+fn string_param<'a>(params: &'a Map<String, Value>, name: &str) -> &'a str {
+    params.get(name).and_then(Value::as_str).unwrap_or_default()
+}
+
+/// `file` is the stored path, or its end after a `/`, as the templates match it.
+fn same_path(stored: &str, file: &str) -> bool {
+    stored == file || stored.ends_with(&format!("/{}", file))
+}
+
+fn by_file_and_start(nodes: &mut [&NodeRecord]) {
+    nodes.sort_by(|a, b| (&a.file, a.start).cmp(&(&b.file, b.start)));
+}
+
+fn callers_of_function(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    params: &Map<String, Value>,
+) -> Vec<Value> {
+    let name = string_param(params, "name");
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let function = |id: &str| by_id.get(id).copied().filter(|n| n.kind == "Function");
+    let mut callers: Vec<&NodeRecord> = edges
+        .iter()
+        .filter(|e| e.kind == "CALLS")
+        .filter(|e| function(&e.target).is_some_and(|f| f.name == name))
+        .filter_map(|e| function(&e.source))
+        .collect();
+    by_file_and_start(&mut callers);
+    callers.into_iter().map(location).collect()
+}
+
+fn call_graph_from(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    params: &Map<String, Value>,
+) -> Vec<Value> {
+    let name = string_param(params, "name");
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut calls: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.kind == "CALLS") {
+        calls
+            .entry(edge.source.as_str())
+            .or_default()
+            .push(&edge.target);
+    }
+    let mut queue: VecDeque<&str> = nodes
+        .iter()
+        .filter(|n| n.kind == "Function" && n.name == name)
+        .map(|n| n.id.as_str())
+        .collect();
+    let mut reached = BTreeSet::new();
+    let mut seen = HashSet::new();
+    while let Some(id) = queue.pop_front() {
+        for target in calls.get(id).into_iter().flatten() {
+            if seen.insert(*target) {
+                reached.insert(*target);
+                queue.push_back(*target);
+            }
+        }
+    }
+    let mut found: Vec<&NodeRecord> = reached
+        .into_iter()
+        .filter_map(|id| by_id.get(id).copied())
+        .filter(|n| n.kind == "Function")
+        .collect();
+    by_file_and_start(&mut found);
+    found.into_iter().map(location).collect()
+}
+
+fn files_importing_module(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    params: &Map<String, Value>,
+) -> Vec<Value> {
+    let module = string_param(params, "module");
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let files: BTreeSet<(&str, &str)> = edges
+        .iter()
+        .filter(|e| e.kind == "CONTAINS")
+        .filter_map(|e| Some((by_id.get(e.source.as_str())?, by_id.get(e.target.as_str())?)))
+        .filter(|(f, i)| f.kind == "File" && i.kind == "Import" && i.body.contains(module))
+        .map(|(f, _)| (f.file.as_str(), f.name.as_str()))
+        .collect();
+    files
+        .into_iter()
+        .map(|(file, name)| json!({ "name": name, "file": file }))
+        .collect()
+}
+
+fn file_dependencies(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    params: &Map<String, Value>,
+) -> Vec<Value> {
+    let file = string_param(params, "file");
+    let by_id: HashMap<&str, &NodeRecord> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let files: BTreeSet<(&str, &str)> = edges
+        .iter()
+        .filter(|e| e.kind == "IMPORTS")
+        .filter_map(|e| Some((by_id.get(e.source.as_str())?, by_id.get(e.target.as_str())?)))
+        .filter(|(f, t)| f.kind == "File" && t.kind == "File" && same_path(&f.file, file))
+        .map(|(_, t)| (t.file.as_str(), t.name.as_str()))
+        .collect();
+    files
+        .into_iter()
+        .map(|(file, name)| json!({ "name": name, "file": file }))
+        .collect()
+}
+
+fn symbols_in_file(
+    nodes: &[NodeRecord],
+    _edges: &[EdgeRecord],
+    params: &Map<String, Value>,
+) -> Vec<Value> {
+    const KINDS: &[&str] = &["Function", "Class", "Var", "Trait", "DataModel"];
+    let file = string_param(params, "file");
+    let mut symbols: Vec<&NodeRecord> = nodes
+        .iter()
+        .filter(|n| KINDS.contains(&n.kind.as_str()) && same_path(&n.file, file))
+        .collect();
+    symbols.sort_by_key(|n| n.start);
+    symbols
+        .into_iter()
+        .map(|n| {
+            let mut row = location(n);
+            row["kind"] = json!(n.kind);
+            row.as_object_mut().unwrap().remove("file");
+            row
+        })
+        .collect()
+}
+
+fn complexity_hotspots(
+    nodes: &[NodeRecord],
+    _edges: &[EdgeRecord],
+    _params: &Map<String, Value>,
+) -> Vec<Value> {
+    let measure = |n: &NodeRecord, key: &str| n.meta.get(key).and_then(|v| v.parse::<i64>().ok());
+    let mut measured: Vec<(&NodeRecord, i64, Option<i64>)> = nodes
+        .iter()
+        .filter(|n| n.kind == "Function" || n.kind == "File")
+        .filter_map(|n| Some((n, measure(n, "complexity")?, measure(n, "nesting"))))
+        .collect();
+    measured.sort_by(|(a, ac, an), (b, bc, bn)| {
+        (bc, bn, &a.file, a.start).cmp(&(ac, an, &b.file, b.start))
+    });
+    measured
+        .into_iter()
+        .map(|(n, complexity, nesting)| {
+            json!({
+                "kind": n.kind,
+                "name": n.name,
+                "file": n.file,
+                "start": n.start,
+                "end": n.end,
+                "complexity": complexity,
+                "lines": measure(n, "lines"),
+                "nesting": nesting,
+            })
+        })
+        .collect()
+}
+
+fn open_tasks(
+    nodes: &[NodeRecord],
+    _edges: &[EdgeRecord],
+    _params: &Map<String, Value>,
+) -> Vec<Value> {
+    let mut tasks: Vec<&NodeRecord> = nodes.iter().filter(|n| n.kind == TASK_KIND).collect();
+    by_file_and_start(&mut tasks);
+    tasks
+        .into_iter()
+        .map(|t| {
+            json!({
+                "marker": t.meta.get(MARKER),
+                "text": t.name,
+                "assignee": t.meta.get(ASSIGNEE),
+                "issue": t.meta.get(ISSUE),
+                "file": t.file,
+                "start": t.start,
+            })
+        })
+        .collect()
+}
+
+fn functions_returning_type(
+    nodes: &[NodeRecord],
+    _edges: &[EdgeRecord],
+    params: &Map<String, Value>,
+) -> Vec<Value> {
+    let ty = string_param(params, "type");
+    let mut functions: Vec<&NodeRecord> = nodes
+        .iter()
+        .filter(|n| n.kind == "Function" && n.meta.get(TYPE).is_some_and(|t| t == ty))
+        .collect();
+    by_file_and_start(&mut functions);
+    functions.into_iter().map(location).collect()
+}
+
+fn public_api(
+    nodes: &[NodeRecord],
+    _edges: &[EdgeRecord],
+    params: &Map<String, Value>,
+) -> Vec<Value> {
+    let file = string_param(params, "file");
+    let mut symbols: Vec<&NodeRecord> = nodes
+        .iter()
+        .filter(|n| same_path(&n.file, file))
+        .filter(|n| {
+            n.meta
+                .get(VISIBILITY)
+                .is_some_and(|v| v == "public" || v == "exported")
+        })
+        .collect();
+    symbols.sort_by_key(|n| n.start);
+    symbols
+        .into_iter()
+        .map(|n| {
+            json!({
+                "kind": n.kind,
+                "name": n.name,
+                "visibility": n.meta[VISIBILITY],
+                "start": n.start,
+                "end": n.end,
+            })
+        })
+        .collect()
+}
+
+pub fn templates() -> &'static [QueryTemplate] {
+    TEMPLATES
+}
+
+pub fn find_template(key: &str) -> Option<&'static QueryTemplate> {
+    TEMPLATES.iter().find(|t| t.key == key)
+}
+
+/// Checks the supplied parameters against the template before anything is sent
+/// to the database. Unknown parameters are rejected too, to catch typos.
+pub fn validate(template: &QueryTemplate, params: &Map<String, Value>) -> Result<(), String> {
+    for (name, ty) in template.params {
+        match params.get(*name) {
+            None | Some(Value::Null) => {
+                return Err(format!(
+                    "missing parameter '{}' for query '{}'",
+                    name, template.key
+                ))
+            }
+            Some(value) if !ty.accepts(value) => {
+                return Err(format!(
+                    "parameter '{}' for query '{}' must be {:?}",
+                    name, template.key, ty
+                ))
+            }
+            _ => {}
+        }
+    }
+    if let Some(extra) = params
+        .keys()
+        .find(|k| !template.params.iter().any(|(name, _)| name == k))
+    {
+        return Err(format!(
+            "unknown parameter '{}' for query '{}'",
+            extra, template.key
+        ));
+    }
+    Ok(())
+}
+
+/// `params` plus the `repo_id` every template takes. Without a `repo_id` an
+/// existing scope is kept, and otherwise the template runs over every repo.
+pub fn scoped_params(params: &Map<String, Value>, repo_id: Option<&str>) -> Map<String, Value> {
+    let mut params = params.clone();
+    match repo_id {
+        Some(repo_id) => {
+            params.insert(REPO_PARAM.to_string(), repo_id.into());
+        }
+        None => {
+            params
+                .entry(REPO_PARAM)
+                .or_insert_with(|| Value::String(String::new()));
+        }
+    }
+    params
+}
+
+/// How long a `/query` may wait on the backend before it is logged as slow,
+/// by default.
+pub const DEFAULT_SLOW_QUERY_MS: u64 = 1_000;
+
+/// How long a query or traversal may run before it's stopped with a 504, by
+/// default.
+pub const DEFAULT_QUERY_TIMEOUT_MS: u64 = 30_000;
+
+/// Rows per page when a cursor is given without a `limit`.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Keeps the rows whose `kind` column is one of `kinds`; all of them when
+/// `kinds` is empty. Fails for a query that returns no `kind` to filter on.
+pub fn filter_kinds(rows: Vec<Value>, kinds: &[String]) -> Result<Vec<Value>, String> {
+    if kinds.is_empty() {
+        return Ok(rows);
+    }
+    let mut kept = Vec::new();
+    for row in rows {
+        if keeps_kind(&row, kinds)? {
+            kept.push(row);
+        }
+    }
+    Ok(kept)
+}
+
+/// [`filter_kinds`] for a single row, for rows that are streamed.
+pub fn keeps_kind(row: &Value, kinds: &[String]) -> Result<bool, String> {
+    if kinds.is_empty() {
+        return Ok(true);
+    }
+    let kind = row
+        .get("kind")
+        .ok_or("node_kinds needs a query whose rows have a 'kind' column")?;
+    Ok(kind
+        .as_str()
+        .is_some_and(|kind| kinds.iter().any(|k| k == kind)))
+}
+
+/// Keeps the rows whose `file` column is one of `files`; all of them when
+/// `files` is `None`.
+pub fn filter_files(
+    rows: Vec<Value>,
+    files: Option<&HashSet<String>>,
+) -> Result<Vec<Value>, String> {
+    let mut kept = Vec::new();
+    for row in rows {
+        if keeps_file(&row, files)? {
+            kept.push(row);
+        }
+    }
+    Ok(kept)
+}
+
+/// [`filter_files`] for a single row. Fails for a query that returns no
+/// `file` to filter on.
+pub fn keeps_file(row: &Value, files: Option<&HashSet<String>>) -> Result<bool, String> {
+    let Some(files) = files else {
+        return Ok(true);
+    };
+    let file = row
+        .get("file")
+        .ok_or("project needs a query whose rows have a 'file' column")?;
+    Ok(file.as_str().is_some_and(|file| files.contains(file)))
+}
+
+/// The words of Cypher and SQL that write, or can: a procedure `CALL` and a
+/// `PRAGMA` are counted too, whatever they'd do.
+const WRITE_WORDS: &[&str] = &[
+    "CREATE", "MERGE", "DELETE", "DETACH", "SET", "REMOVE", "DROP", "CALL", "LOAD", "INSERT",
+    "UPDATE", "REPLACE", "UPSERT", "ALTER", "ATTACH", "PRAGMA", "VACUUM", "REINDEX",
+];
+
+/// Whether a raw `statement` may write. It's a look at its words outside
+/// string literals and quoted names rather than a parse, so a read that names
+/// one of [`WRITE_WORDS`] bare, e.g. as a column, counts as a write.
+pub fn writes(statement: &str) -> bool {
+    let mut words = String::new();
+    let mut quote = None;
+    for c in statement.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if matches!(c, '\'' | '"' | '`') => quote = Some(c),
+            None if c.is_alphanumeric() || c == '_' => words.push(c),
+            None => words.push(' '),
+        }
+    }
+    words
+        .split_whitespace()
+        .any(|word| WRITE_WORDS.iter().any(|w| w.eq_ignore_ascii_case(word)))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PageError {
+    /// A cursor that doesn't decode, or was issued for a different query.
+    Invalid(String),
+    /// The query's results changed since the cursor was issued.
+    Changed,
+}
+
+pub struct Page {
+    pub rows: Vec<Value>,
+    /// Hand back to fetch the next page; `None` on the last one.
+    pub next_cursor: Option<String>,
+}
+
+/// One page of `rows`. `request` identifies the query and its parameters, so
+/// a cursor only continues the query that issued it. Rows are ordered by
+/// their serialized form, which makes the order total whatever the backend
+/// returned; the cursor also carries a digest of the whole result set, and a
+/// page is refused once that no longer matches.
+pub fn paginate(
+    request: &str,
+    mut rows: Vec<Value>,
+    limit: Option<usize>,
+    cursor: Option<&str>,
+) -> Result<Page, PageError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(PageError::Invalid(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    rows.sort_by_cached_key(Value::to_string);
+    let request = digest([request]);
+    let results = digest(rows.iter().map(Value::to_string));
+
+    let offset = match cursor {
+        None => 0,
+        Some(cursor) => {
+            let (offset, cursor_request, cursor_results) = decode_cursor(cursor)
+                .ok_or_else(|| PageError::Invalid("malformed cursor".to_string()))?;
+            if cursor_request != request {
+                return Err(PageError::Invalid(
+                    "cursor was issued for a different query".to_string(),
+                ));
+            }
+            if cursor_results != results {
+                return Err(PageError::Changed);
+            }
+            offset
+        }
+    };
+    let end = offset.saturating_add(limit).min(rows.len());
+    let next_cursor =
+        (end < rows.len()).then(|| hex::encode(format!("{}.{}.{}", end, request, results)));
+    let rows = rows
+        .into_iter()
+        .skip(offset)
+        .take(end.saturating_sub(offset))
+        .collect();
+    Ok(Page { rows, next_cursor })
+}
+
+fn decode_cursor(cursor: &str) -> Option<(usize, String, String)> {
+    let decoded = String::from_utf8(hex::decode(cursor).ok()?).ok()?;
+    let mut parts = decoded.splitn(3, '.');
+    let offset = parts.next()?.parse().ok()?;
+    Some((offset, parts.next()?.to_string(), parts.next()?.to_string()))
+}
+
+fn digest<I, S>(parts: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_ref().as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(&hasher.finalize()[..16])
+}
@@ -0,0 +1,123 @@
+//! Redaction of secrets before anything is stored, for repos whose string
+//! literals and comments hold credentials that mustn't end up in a shared
+//! graph. Each match of a pattern in a node's name, body or meta is replaced
+//! with [`clone::REDACTED`]; in the repos listed, comments and strings keep
+//! none of their text at all. A redacted node is still stored, marked
+//! [`REDACTED`], so the structure around it is intact.
+
+use crate::clone;
+use crate::grep::{COMMENT_KIND, STRING_KIND};
+use crate::storage::{kind_key, EdgeRecord, NodeRecord};
+use crate::tasks::TASK_KIND;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// The meta key set to `true` on a node something was taken out of.
+pub const REDACTED: &str = "redacted";
+
+/// The kinds that are nothing but the text of a comment or string, whose
+/// text isn't kept in the repos of [`Redaction::new`].
+const TEXT_KINDS: &[&str] = &[COMMENT_KIND, STRING_KIND, TASK_KIND];
+
+/// What's taken out of the nodes of an ingest before they're stored.
+#[derive(Debug, Clone)]
+pub struct Redaction {
+    patterns: Vec<Regex>,
+    text_repos: Vec<String>,
+}
+
+impl Redaction {
+    /// Replaces what `patterns` match everywhere, and drops the text of
+    /// comments and strings in `text_repos`, by repo id. Fails on a pattern
+    /// that doesn't compile.
+    pub fn new(patterns: &[String], text_repos: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|p| Regex::new(p).with_context(|| format!("invalid redact pattern '{}'", p)))
+            .collect::<Result<_>>()?;
+        Ok(Redaction {
+            patterns,
+            text_repos: text_repos.to_vec(),
+        })
+    }
+
+    /// Whether the text of comments and strings of `repo_id`, or any of its
+    /// refs, is dropped.
+    pub fn hides_text(&self, repo_id: &str) -> bool {
+        self.text_repos.iter().any(|r| {
+            repo_id == r
+                || repo_id
+                    .strip_prefix(r.as_str())
+                    .is_some_and(|rest| rest.starts_with('@'))
+        })
+    }
+
+    /// `text` with every match of the patterns replaced, or `None` when
+    /// nothing matched.
+    pub fn scrub(&self, text: &str) -> Option<String> {
+        let mut scrubbed: Option<String> = None;
+        for pattern in &self.patterns {
+            let current = scrubbed.as_deref().unwrap_or(text);
+            if pattern.is_match(current) {
+                scrubbed = Some(pattern.replace_all(current, clone::REDACTED).into_owned());
+            }
+        }
+        scrubbed
+    }
+
+    /// Takes the secrets out of `nodes` of `repo_id`. A node whose name
+    /// changed gets an id from the new one, since the old id was made from
+    /// the secret, and the ends of `edges` are moved with it.
+    pub fn apply(&self, repo_id: &str, nodes: &mut [NodeRecord], edges: &mut [EdgeRecord]) {
+        let hide_text = self.hides_text(repo_id);
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut renamed: HashMap<String, String> = HashMap::new();
+        for node in nodes.iter_mut() {
+            let mut redacted = false;
+            let mut new_name = false;
+            if hide_text && TEXT_KINDS.contains(&node.kind.as_str()) {
+                node.name = clone::REDACTED.to_string();
+                node.body.clear();
+                new_name = true;
+            }
+            if let Some(name) = self.scrub(&node.name) {
+                node.name = name;
+                new_name = true;
+            }
+            if let Some(body) = self.scrub(&node.body) {
+                node.body = body;
+                redacted = true;
+            }
+            for value in node.meta.values_mut() {
+                if let Some(scrubbed) = self.scrub(value) {
+                    *value = scrubbed;
+                    redacted = true;
+                }
+            }
+            if !redacted && !new_name {
+                continue;
+            }
+            node.meta.insert(REDACTED.to_string(), "true".to_string());
+            if new_name {
+                let mut id = kind_key(&node.kind, &node.name, &node.file, node.start, None);
+                let count = seen.entry(id.clone()).or_default();
+                *count += 1;
+                if *count > 1 {
+                    id = format!("{}-{}", id, count);
+                }
+                if id != node.id {
+                    renamed.insert(std::mem::replace(&mut node.id, id.clone()), id);
+                }
+            }
+        }
+        for edge in edges {
+            if let Some(id) = renamed.get(&edge.source) {
+                edge.source = id.clone();
+            }
+            if let Some(id) = renamed.get(&edge.target) {
+                edge.target = id.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::lang::{Diagnostic, Severity};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// The SARIF version [`to_sarif`] writes.
+pub const SARIF_VERSION: &str = "2.1.0";
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+pub const SARIF_CONTENT_TYPE: &str = "application/sarif+json";
+
+/// How `/diagnostics` answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    /// One line per diagnostic, as the `diagnostic` events word them.
+    Text,
+    Sarif,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ReportFormat::Json),
+            "text" => Ok(ReportFormat::Text),
+            "sarif" => Ok(ReportFormat::Sarif),
+            other => Err(format!(
+                "unknown format '{}'; expected json, text or sarif",
+                other
+            )),
+        }
+    }
+}
+
+/// Whether `diagnostic` is one asked for: of one of `severities`, all when
+/// empty, and in `file` or, when it names a directory, under it.
+pub fn matches(diagnostic: &Diagnostic, severities: &[Severity], file: Option<&str>) -> bool {
+    if !severities.is_empty() && !severities.contains(&diagnostic.severity) {
+        return false;
+    }
+    let Some(file) = file.map(|f| f.trim_end_matches('/')) else {
+        return true;
+    };
+    diagnostic.file == file
+        || diagnostic
+            .file
+            .strip_prefix(file)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+pub fn to_text(repo_id: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut out = format!("Diagnostics for {}: {}\n", repo_id, diagnostics.len());
+    for d in diagnostics {
+        let _ = writeln!(
+            out,
+            "{}: {} at bytes {}..{}: {}",
+            d.file,
+            d.severity.as_str(),
+            d.start,
+            d.end,
+            d.message
+        );
+    }
+    out
+}
+
+/// A SARIF log with one run of one result per diagnostic, located by byte
+/// offsets into its file, for CI to show where files failed to index.
+/// Skipped files are the `skipped-file` rule, the rest are by severity.
+pub fn to_sarif(diagnostics: &[Diagnostic]) -> Value {
+    let mut rules: BTreeMap<&str, &str> = BTreeMap::new();
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|d| {
+            let (rule, description) = rule(d);
+            rules.insert(rule, description);
+            json!({
+                "ruleId": rule,
+                "level": d.severity.as_str(),
+                "message": { "text": d.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": d.file },
+                        "region": {
+                            "byteOffset": d.start,
+                            "byteLength": d.end.saturating_sub(d.start),
+                        },
+                    },
+                }],
+            })
+        })
+        .collect();
+    let rules: Vec<Value> = rules
+        .into_iter()
+        .map(|(id, description)| json!({ "id": id, "shortDescription": { "text": description } }))
+        .collect();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "mesh",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn rule(diagnostic: &Diagnostic) -> (&'static str, &'static str) {
+    if diagnostic.message.contains("file skipped") {
+        return ("skipped-file", "A file was left out of the graph");
+    }
+    match diagnostic.severity {
+        Severity::Error => ("syntax-error", "Text the parser couldn't make sense of"),
+        Severity::Warning => ("missing-token", "A token the parser had to assume"),
+    }
+}
@@ -0,0 +1,196 @@
+use crate::events::StatusEvent;
+use crate::storage::{RepoRecord, RetentionRecord};
+use crate::AppState;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+
+pub const DEFAULT_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How much later than the last one stored a use has to be to be stored
+/// again, so a busy repo isn't written to on every read.
+pub const ACCESS_RESOLUTION: Duration = Duration::from_secs(60);
+
+/// When repos were last used, and which of them are kept however long ago
+/// that was. A repo is used when it is ingested or read from, see
+/// [`AccessTracking`](crate::storage::access::AccessTracking). Uses and
+/// `/retention/pin` pins are stored in the backend, see
+/// [`Storage::record_access`](crate::storage::Storage::record_access), so
+/// they outlast a restart and count whichever server saw them; [`sweep`]
+/// reads them back before it looks for repos to clear.
+#[derive(Default)]
+pub struct Retention {
+    /// How long a repo may go unused before [`sweep`] clears it; never when
+    /// `None`.
+    ttl: Mutex<Option<Duration>>,
+    /// Unix seconds of each repo's last use seen here, by repo id.
+    accessed: Mutex<HashMap<String, u64>>,
+    /// Pins of `MESH_PINNED_REPOS`, kept in memory only.
+    pinned: Mutex<HashSet<String>>,
+    /// What the backend had stored when last read, by repo id.
+    stored: Mutex<HashMap<String, RetentionRecord>>,
+}
+
+impl Retention {
+    pub fn ttl(&self) -> Option<Duration> {
+        *self.ttl.lock().unwrap()
+    }
+
+    pub fn set_ttl(&self, ttl: Option<Duration>) {
+        *self.ttl.lock().unwrap() = ttl.filter(|ttl| !ttl.is_zero());
+    }
+
+    /// Notes a use of `repo_id` now, returning the time to store for it
+    /// when the last one noted is more than [`ACCESS_RESOLUTION`] ago.
+    pub fn touch(&self, repo_id: &str) -> Option<u64> {
+        if repo_id.is_empty() {
+            return None;
+        }
+        let now = unix_now();
+        let previous = self
+            .accessed
+            .lock()
+            .unwrap()
+            .insert(repo_id.to_string(), now);
+        let due = previous.map_or(true, |at| at + ACCESS_RESOLUTION.as_secs() <= now);
+        due.then_some(now)
+    }
+
+    /// The latest use of `repo_id`, seen here or stored.
+    pub fn last_access(&self, repo_id: &str) -> Option<u64> {
+        let seen = self.accessed.lock().unwrap().get(repo_id).copied();
+        let stored = self
+            .stored
+            .lock()
+            .unwrap()
+            .get(repo_id)
+            .and_then(|r| r.last_access);
+        seen.max(stored)
+    }
+
+    /// Takes `stored`, as [`Storage::retention`](crate::storage::Storage::retention)
+    /// read it, in place of what was read before.
+    pub fn remember(&self, stored: HashMap<String, RetentionRecord>) {
+        *self.stored.lock().unwrap() = stored;
+    }
+
+    /// Notes a pin just stored, until the stored pins are read again.
+    pub fn remember_pin(&self, repo_id: &str, pinned: bool) {
+        self.stored
+            .lock()
+            .unwrap()
+            .entry(repo_id.to_string())
+            .or_default()
+            .pinned = pinned;
+    }
+
+    /// Exempts `repo_id`, and each of its refs, from [`sweep`], or makes it
+    /// sweepable again, in memory only, as `MESH_PINNED_REPOS` does.
+    pub fn pin(&self, repo_id: &str, pinned: bool) {
+        let mut pins = self.pinned.lock().unwrap();
+        if pinned {
+            pins.insert(repo_id.to_string());
+        } else {
+            pins.remove(repo_id);
+        }
+    }
+
+    pub fn is_pinned(&self, repo_id: &str) -> bool {
+        let base = repo_id.split_once('@').map_or(repo_id, |(base, _)| base);
+        let pins = self.pinned.lock().unwrap();
+        let stored = self.stored.lock().unwrap();
+        let stored = |id: &str| stored.get(id).is_some_and(|r| r.pinned);
+        pins.contains(repo_id) || pins.contains(base) || stored(repo_id) || stored(base)
+    }
+
+    /// Unix seconds after which `repo` is swept, unless it is used before.
+    pub fn expires_at(&self, repo: &RepoRecord) -> Option<u64> {
+        let ttl = self.ttl()?;
+        if self.is_pinned(&repo.repo_id) {
+            return None;
+        }
+        let used = self.last_access(&repo.repo_id).unwrap_or_default();
+        Some(used.max(repo.ingested_at) + ttl.as_secs())
+    }
+
+    /// The repos of `repos` gone unused past the TTL at `now`, in unix
+    /// seconds.
+    pub fn expired<'a>(&self, repos: &'a [RepoRecord], now: u64) -> Vec<&'a RepoRecord> {
+        repos
+            .iter()
+            .filter(|repo| self.expires_at(repo).is_some_and(|at| at <= now))
+            .collect()
+    }
+
+    fn forget(&self, repo_id: &str) {
+        self.accessed.lock().unwrap().remove(repo_id);
+    }
+}
+
+/// Clears the graph of every repo gone unused past the TTL at `now`, other
+/// than pinned ones and those being ingested, sending a `swept` status for
+/// each. Returns the repo ids cleared.
+pub async fn sweep(state: &AppState, now: u64) -> Result<Vec<String>> {
+    if state.retention.ttl().is_none() {
+        return Ok(Vec::new());
+    }
+    match state.storage.retention().await {
+        Ok(stored) => state.retention.remember(stored),
+        Err(e) => warn!(
+            "Sweeping by the uses and pins seen here, as the stored ones can't be read: {:#}",
+            e
+        ),
+    }
+    let repos = state.storage.repos().await?;
+    let mut swept = Vec::new();
+    for repo in state.retention.expired(&repos, now) {
+        // one being ingested or cleared now is in use
+        let Some(_lock) = state.repo_locks.try_write(Some(&repo.repo_id)) else {
+            continue;
+        };
+        let (nodes, edges) = state.storage.graph_size(Some(&repo.repo_id)).await?;
+        state.storage.clear(Some(&repo.repo_id)).await?;
+        state.retention.forget(&repo.repo_id);
+        let message = format!(
+            "Cleared {} ({} nodes, {} edges), unused since it was last ingested or read",
+            repo.repo_id, nodes, edges
+        );
+        info!("{}", message);
+        state
+            .tx
+            .send(StatusEvent::new("swept", message).for_repo(&repo.repo_id));
+        swept.push(repo.repo_id.clone());
+    }
+    Ok(swept)
+}
+
+/// Runs [`sweep`] every `interval` until the server shuts down. Does
+/// nothing without a TTL.
+pub fn spawn_sweeper(state: Arc<AppState>, interval: Duration) {
+    if state.retention.ttl().is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            tokio::select! {
+                _ = state.shutdown.cancelled() => return,
+                _ = ticks.tick() => {}
+            }
+            if let Err(e) = sweep(&state, unix_now()).await {
+                error!("retention sweep failed: {:#}", e);
+            }
+        }
+    });
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// The shortest interval a repo can be re-ingested at.
+pub const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The repos re-ingested on a timer, by repo id. Each has a task of its own,
+/// stopped through its token when the schedule is removed or replaced.
+#[derive(Default)]
+pub struct Schedules {
+    tokens: Mutex<HashMap<String, CancellationToken>>,
+}
+
+impl Schedules {
+    /// Registers a schedule of `repo_id`, whose task runs until the returned
+    /// token is cancelled: on [`Schedules::remove`], on the next `insert` for
+    /// the same repo, or when `shutdown` is.
+    pub fn insert(&self, repo_id: &str, shutdown: &CancellationToken) -> CancellationToken {
+        let token = shutdown.child_token();
+        let previous = self
+            .tokens
+            .lock()
+            .unwrap()
+            .insert(repo_id.to_string(), token.clone());
+        if let Some(previous) = previous {
+            previous.cancel();
+        }
+        token
+    }
+
+    /// Whether `repo_id` was scheduled.
+    pub fn remove(&self, repo_id: &str) -> bool {
+        match self.tokens.lock().unwrap().remove(repo_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_scheduled(&self, repo_id: &str) -> bool {
+        self.tokens.lock().unwrap().contains_key(repo_id)
+    }
+}
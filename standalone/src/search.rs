@@ -0,0 +1,150 @@
+use crate::storage::{EdgeRecord, NodeRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Node kinds offered by "go to symbol".
+pub const SYMBOL_KINDS: &[&str] = &["Function", "Class", "Var", "Trait", "DataModel"];
+
+pub const DEFAULT_LIMIT: usize = 20;
+
+// Match tiers are spaced further apart than the length and importance bonuses
+// can add up to, so a better kind of match always ranks first.
+const EXACT: f32 = 1.0;
+const PREFIX: f32 = 0.8;
+const WORD_INITIALS: f32 = 0.6;
+const SUBSTRING: f32 = 0.4;
+const SUBSEQUENCE: f32 = 0.2;
+const MAX_LENGTH_BONUS: f32 = 0.05;
+const MAX_IMPORTANCE: f32 = 0.1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub file: String,
+    pub start: usize,
+    pub end: usize,
+    /// Callers of the symbol in the graph.
+    pub callers: usize,
+    pub score: f32,
+}
+
+/// Symbols whose name matches `query`, best first. Exact and prefix matches
+/// rank above word-initial matches (`gfu` for `getFileUrl` or `get_file_url`),
+/// which rank above plain substrings and then scattered subsequences. Within a
+/// tier, names closer to the query's length and symbols with more callers win.
+pub fn search(
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    query: &str,
+    limit: usize,
+) -> Vec<SearchHit> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut callers: HashMap<&str, usize> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.kind == "CALLS") {
+        *callers.entry(edge.target.as_str()).or_default() += 1;
+    }
+
+    let mut hits: Vec<SearchHit> = nodes
+        .iter()
+        .filter(|n| SYMBOL_KINDS.contains(&n.kind.as_str()))
+        .filter_map(|node| {
+            let tier = match_tier(&query, &node.name)?;
+            let length = query.chars().count() as f32 / node.name.chars().count().max(1) as f32;
+            let callers = callers.get(node.id.as_str()).copied().unwrap_or(0);
+            let importance = (0.03 * (callers as f32).ln_1p()).min(MAX_IMPORTANCE);
+            Some(SearchHit {
+                id: node.id.clone(),
+                name: node.name.clone(),
+                kind: node.kind.clone(),
+                file: node.file.clone(),
+                start: node.start,
+                end: node.end,
+                callers,
+                score: tier + MAX_LENGTH_BONUS * length.min(1.0) + importance,
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.file.cmp(&b.file))
+            .then_with(|| a.start.cmp(&b.start))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+/// `query` must already be lowercase.
+fn match_tier(query: &str, name: &str) -> Option<f32> {
+    let lower = name.to_lowercase();
+    if lower == query {
+        Some(EXACT)
+    } else if lower.starts_with(query) {
+        Some(PREFIX)
+    } else if initials_match(query.as_bytes(), &words(name)) {
+        Some(WORD_INITIALS)
+    } else if lower.contains(query) {
+        Some(SUBSTRING)
+    } else if is_subsequence(query, &lower) {
+        Some(SUBSEQUENCE)
+    } else {
+        None
+    }
+}
+
+/// Lowercased words of a camelCase, PascalCase or snake_case name.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut prev: Option<char> = None;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev = None;
+            continue;
+        }
+        let boundary = prev.is_some_and(|p| {
+            (c.is_uppercase() && p.is_lowercase()) || (c.is_ascii_digit() != p.is_ascii_digit())
+        });
+        if boundary && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        current.extend(c.to_lowercase());
+        prev = Some(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Whether `query` splits into non-empty prefixes of `words`, in order,
+/// skipping words as needed.
+fn initials_match(query: &[u8], words: &[String]) -> bool {
+    if query.is_empty() {
+        return true;
+    }
+    words.iter().enumerate().any(|(i, word)| {
+        let common = word
+            .bytes()
+            .zip(query.iter())
+            .take_while(|(w, q)| w == *q)
+            .count();
+        (1..=common)
+            .rev()
+            .any(|k| initials_match(&query[k..], &words[i + 1..]))
+    })
+}
+
+fn is_subsequence(query: &str, name: &str) -> bool {
+    let mut chars = name.chars();
+    query.chars().all(|q| chars.any(|c| c == q))
+}
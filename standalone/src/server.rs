@@ -0,0 +1,137 @@
+//! The connection handling of the server: HTTP/1.1 and HTTP/2 on the same
+//! port, told apart by the preface a client opens with. Over HTTP/2 a
+//! browser multiplexes every `/events` stream of an origin onto one
+//! connection, rather than being held to the handful of connections it opens
+//! per origin over HTTP/1.1. Plain HTTP takes HTTP/2 from clients that speak
+//! it from the start (h2c with prior knowledge); TLS offers it through ALPN.
+
+use axum::extract::Request;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tower::{Service, ServiceExt};
+use tracing::debug;
+
+/// Streams a client may have open at once on one HTTP/2 connection.
+pub const DEFAULT_MAX_STREAMS: u32 = 256;
+pub const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(20);
+pub const DEFAULT_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How connections are served, whether over plain HTTP or TLS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HttpConfig {
+    /// Whether HTTP/2 is offered at all; HTTP/1.1 always is.
+    pub http2: bool,
+    pub max_concurrent_streams: u32,
+    /// How often an HTTP/2 connection is pinged to keep it open through
+    /// proxies and notice a peer that went away; `None` never pings.
+    pub keep_alive: Option<Duration>,
+    /// How long a ping may go unanswered before the connection is closed.
+    pub keep_alive_timeout: Duration,
+    /// Whether an HTTP/1.1 connection is kept open for further requests.
+    pub http1_keep_alive: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            http2: true,
+            max_concurrent_streams: DEFAULT_MAX_STREAMS,
+            keep_alive: Some(DEFAULT_KEEPALIVE),
+            keep_alive_timeout: DEFAULT_KEEPALIVE_TIMEOUT,
+            http1_keep_alive: true,
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Applies the settings to `builder`, the one the TLS server uses too.
+    pub fn configure(&self, builder: &mut Builder<TokioExecutor>) {
+        builder.http1().keep_alive(self.http1_keep_alive);
+        if !self.http2 {
+            *builder = builder.clone().http1_only();
+            return;
+        }
+        builder
+            .http2()
+            // the timer runs the keep-alive pings
+            .timer(TokioTimer::new())
+            .max_concurrent_streams(self.max_concurrent_streams)
+            .keep_alive_interval(self.keep_alive)
+            .keep_alive_timeout(self.keep_alive_timeout);
+    }
+}
+
+/// Serves `app` on `listener` until `signal` resolves, then stops accepting
+/// and waits for the connections it has to finish their requests, as
+/// `axum::serve` does, for the caller to cut short after the grace period.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    config: HttpConfig,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    let mut builder = Builder::new(TokioExecutor::new());
+    config.configure(&mut builder);
+    // the peer address keys the per-client rate limit
+    let mut make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+    // every connection holds a receiver; all of them are gone once the
+    // last connection has closed
+    let (stop_tx, stop_rx) = watch::channel(());
+    tokio::pin!(signal);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                // e.g. out of file descriptors; the listener itself is fine
+                Err(e) => {
+                    debug!("failed to accept a connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+            },
+            _ = &mut signal => break,
+        };
+        let service = match make_service.call(peer).await {
+            Ok(service) => service,
+            Err(never) => match never {},
+        };
+        let service = hyper::service::service_fn(move |request: Request<Incoming>| {
+            service.clone().oneshot(request)
+        });
+        let builder = builder.clone();
+        let mut stop_rx = stop_rx.clone();
+        tokio::spawn(async move {
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            tokio::pin!(connection);
+            let mut stopping = false;
+            loop {
+                tokio::select! {
+                    result = connection.as_mut() => {
+                        if let Err(e) = result {
+                            debug!("connection from {} failed: {}", peer, e);
+                        }
+                        break;
+                    }
+                    _ = stop_rx.changed(), if !stopping => {
+                        stopping = true;
+                        connection.as_mut().graceful_shutdown();
+                    }
+                }
+            }
+        });
+    }
+
+    drop(listener);
+    let _ = stop_tx.send(());
+    drop(stop_rx);
+    stop_tx.closed().await;
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Named snapshots of a repo's graph, such as the graph as of the last
+//! release, to query and diff against after the graph has moved on, or to
+//! roll it back to. A snapshot's graph is kept under its own repo id, see
+//! [`storage::with_snapshot`], so every read takes it like a repo and later
+//! ingests never touch it. Neither backend can share stored records between
+//! graphs, so taking one copies the graph inside the backend, which SQLite
+//! does without reading it out.
+
+use crate::storage::{self, SnapshotRecord, Storage};
+use crate::types::{MeshError, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_NAME_LEN: usize = 128;
+
+/// Fails unless `name` is letters, digits, `.`, `-` and `_`, so it can't
+/// be taken for a ref or another snapshot in the id it is stored under.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if !valid {
+        return Err(MeshError::Validation(format!(
+            "snapshot name '{}' must be 1 to {} letters, digits, '.', '-' and '_'",
+            name, MAX_NAME_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// The snapshot of `repo_id` called `name`.
+pub async fn find(storage: &dyn Storage, repo_id: &str, name: &str) -> Result<SnapshotRecord> {
+    storage
+        .snapshots(repo_id)
+        .await
+        .map_err(MeshError::Storage)?
+        .into_iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| MeshError::NotFound(format!("No snapshot of {} named {}", repo_id, name)))
+}
+
+/// Copies `repo_id`'s graph as it is now to a snapshot called `name`.
+pub async fn create(storage: &dyn Storage, repo_id: &str, name: &str) -> Result<SnapshotRecord> {
+    validate_name(name)?;
+    if repo_id.contains('#') {
+        return Err(MeshError::Validation(format!(
+            "{} is a snapshot; snapshots are taken of repos",
+            repo_id
+        )));
+    }
+    let existing = storage
+        .snapshots(repo_id)
+        .await
+        .map_err(MeshError::Storage)?;
+    if existing.iter().any(|s| s.name == name) {
+        return Err(MeshError::Conflict(format!(
+            "{} already has a snapshot named {}; delete it first to take it again",
+            repo_id, name
+        )));
+    }
+    let (nodes, _) = storage
+        .graph_size(Some(repo_id))
+        .await
+        .map_err(MeshError::Storage)?;
+    if nodes == 0 {
+        return Err(MeshError::NotFound(format!(
+            "No graph stored for {}",
+            repo_id
+        )));
+    }
+    let id = storage::with_snapshot(repo_id, name);
+    // left behind by a snapshot whose copy failed part way
+    storage.clear(Some(&id)).await.map_err(MeshError::Storage)?;
+    let (nodes, edges) = storage
+        .copy_graph(repo_id, &id)
+        .await
+        .map_err(MeshError::Storage)?;
+    let snapshot = SnapshotRecord {
+        repo_id: repo_id.to_string(),
+        name: name.to_string(),
+        created_at: unix_now(),
+        nodes,
+        edges,
+    };
+    storage
+        .record_snapshot(&snapshot)
+        .await
+        .map_err(MeshError::Storage)?;
+    Ok(snapshot)
+}
+
+/// Replaces `repo_id`'s graph with a copy of its snapshot `name`, which is
+/// kept. The repo's file hashes go with the graph it replaces, so the next
+/// ingest of a new commit, or a forced one, parses every file again.
+pub async fn restore(storage: &dyn Storage, repo_id: &str, name: &str) -> Result<(usize, usize)> {
+    find(storage, repo_id, name).await?;
+    let record = storage
+        .repos()
+        .await
+        .map_err(MeshError::Storage)?
+        .into_iter()
+        .find(|r| r.repo_id == repo_id);
+    storage
+        .clear(Some(repo_id))
+        .await
+        .map_err(MeshError::Storage)?;
+    let restored = storage
+        .copy_graph(&storage::with_snapshot(repo_id, name), repo_id)
+        .await
+        .map_err(MeshError::Storage)?;
+    // cleared with the graph, but the repo is still the one that was ingested
+    if let Some(record) = record {
+        storage
+            .record_ingest(&record)
+            .await
+            .map_err(MeshError::Storage)?;
+    }
+    Ok(restored)
+}
+
+/// Removes the snapshot `name` of `repo_id` and its graph.
+pub async fn delete(storage: &dyn Storage, repo_id: &str, name: &str) -> Result<SnapshotRecord> {
+    let snapshot = find(storage, repo_id, name).await?;
+    storage
+        .clear(Some(&storage::with_snapshot(repo_id, name)))
+        .await
+        .map_err(MeshError::Storage)?;
+    storage
+        .remove_snapshot(repo_id, name)
+        .await
+        .map_err(MeshError::Storage)?;
+    Ok(snapshot)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
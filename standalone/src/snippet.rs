@@ -0,0 +1,98 @@
+use crate::encoding;
+use crate::storage::NodeRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Lines shown either side of a node when the request doesn't say.
+pub const DEFAULT_CONTEXT_LINES: usize = 3;
+pub const MAX_CONTEXT_LINES: usize = 100;
+/// How many files [`SourceCache`] keeps.
+pub const CACHED_FILES: usize = 64;
+
+/// A node's source, as it is in the file now.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snippet {
+    /// The node's own text: its span when it has one, else its lines.
+    pub text: String,
+    /// 1-based, inclusive.
+    pub start_line: usize,
+    pub end_line: usize,
+    /// The node's lines with the context around them.
+    pub context: String,
+    /// 1-based line number of the first line of `context`.
+    pub context_start_line: usize,
+}
+
+/// The source of `node` in `source`, with up to `context` lines either side.
+/// A span that no longer fits the file, because it changed since the ingest,
+/// falls back to the node's lines; `None` when those are gone too.
+pub fn snippet(node: &NodeRecord, source: &str, context: usize) -> Option<Snippet> {
+    let lines: Vec<&str> = source.lines().collect();
+    if node.start >= lines.len() {
+        return None;
+    }
+    let end = node.end.clamp(node.start, lines.len() - 1);
+    let text = node
+        .span
+        .and_then(|span| source.get(span.start_byte..span.end_byte))
+        .map(str::to_string)
+        .unwrap_or_else(|| lines[node.start..=end].join("\n"));
+    let first = node.start.saturating_sub(context);
+    let last = (end + context).min(lines.len() - 1);
+    Some(Snippet {
+        text,
+        start_line: node.start + 1,
+        end_line: end + 1,
+        context: lines[first..=last].join("\n"),
+        context_start_line: first + 1,
+    })
+}
+
+struct Cached {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    source: Arc<str>,
+}
+
+/// The files snippets were last read from, so paging through a file's
+/// results reads it once. A file is read again once its modification time
+/// changes.
+#[derive(Default)]
+pub struct SourceCache {
+    files: Mutex<VecDeque<Cached>>,
+}
+
+impl SourceCache {
+    /// The contents of the first of `paths` that can be read.
+    pub fn read(&self, paths: &[PathBuf]) -> Option<Arc<str>> {
+        paths.iter().find_map(|path| self.read_file(path).ok())
+    }
+
+    fn read_file(&self, path: &Path) -> std::io::Result<Arc<str>> {
+        let modified = std::fs::metadata(path)?.modified().ok();
+        let mut files = self.files.lock().unwrap();
+        if let Some(i) = files.iter().position(|f| f.path == path) {
+            let cached = files.remove(i).unwrap();
+            if cached.modified.is_some() && cached.modified == modified {
+                let source = cached.source.clone();
+                files.push_front(cached);
+                return Ok(source);
+            }
+        }
+        // the lock isn't held while reading
+        drop(files);
+        let source: Arc<str> = encoding::read(path)?.text.into();
+        let mut files = self.files.lock().unwrap();
+        files.retain(|f| f.path != path);
+        files.push_front(Cached {
+            path: path.to_path_buf(),
+            modified,
+            source: source.clone(),
+        });
+        files.truncate(CACHED_FILES);
+        Ok(source)
+    }
+}
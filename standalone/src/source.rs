@@ -0,0 +1,162 @@
+//! What the parsers read a file's text from: all of it in memory, or a
+//! [`ChunkedFile`] read from disk a chunk at a time, so a large file isn't
+//! held in full next to its syntax tree.
+
+use crate::encoding;
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// How much of a [`ChunkedFile`] is read at a time.
+pub const DEFAULT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Files larger than this are parsed from a [`ChunkedFile`] unless
+/// `MESH_STREAM_FILE_BYTES` says otherwise.
+pub const DEFAULT_STREAM_BYTES: u64 = 1024 * 1024;
+
+/// UTF-8 text to parse. Byte offsets are into the text, as tree-sitter's are.
+pub trait Source {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The bytes of `range`, cut short at the end of the text.
+    fn bytes(&self, range: Range<usize>) -> Result<Cow<'_, [u8]>>;
+
+    /// The bytes from `offset` on that are handed to the parser at once;
+    /// none at the end of the text.
+    fn chunk(&self, offset: usize) -> Result<Cow<'_, [u8]>>;
+
+    /// [`Source::bytes`] as text; a character `range` cuts in two is
+    /// replaced.
+    fn text(&self, range: Range<usize>) -> Result<Cow<'_, str>> {
+        Ok(match self.bytes(range)? {
+            Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+            Cow::Owned(bytes) => Cow::Owned(
+                String::from_utf8(bytes)
+                    .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()),
+            ),
+        })
+    }
+}
+
+impl Source for str {
+    fn len(&self) -> usize {
+        str::len(self)
+    }
+
+    fn bytes(&self, range: Range<usize>) -> Result<Cow<'_, [u8]>> {
+        let end = range.end.min(self.len());
+        Ok(Cow::Borrowed(
+            self.as_bytes()
+                .get(range.start.min(end)..end)
+                .unwrap_or_default(),
+        ))
+    }
+
+    fn chunk(&self, offset: usize) -> Result<Cow<'_, [u8]>> {
+        self.bytes(offset..self.len())
+    }
+}
+
+/// A file read as the parser gets to each part of it, rather than up front.
+/// Only files [`ChunkedFile::is_plain_utf8`] says are read as they are on
+/// disk; others are decoded in memory by [`encoding::decode`].
+pub struct ChunkedFile {
+    file: File,
+    path: PathBuf,
+    len: usize,
+    chunk_bytes: usize,
+    reads: Cell<usize>,
+    largest_read: Cell<usize>,
+}
+
+impl ChunkedFile {
+    /// Opens `path` to be read `chunk_bytes` at a time.
+    pub fn open(path: &Path, chunk_bytes: usize) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let len = file
+            .metadata()
+            .with_context(|| format!("failed to read {}", path.display()))?
+            .len();
+        Ok(ChunkedFile {
+            file,
+            path: path.to_path_buf(),
+            len: usize::try_from(len).unwrap_or(usize::MAX),
+            // room for a whole character, and for a BOM to be told apart
+            chunk_bytes: chunk_bytes.max(4),
+            reads: Cell::new(0),
+            largest_read: Cell::new(0),
+        })
+    }
+
+    /// Whether the file is UTF-8 that [`encoding::decode`] would leave as it
+    /// is: without a BOM, and without the NUL bytes UTF-16 is told by. Reads
+    /// it through once, a chunk at a time.
+    pub fn is_plain_utf8(&self) -> Result<bool> {
+        let mut pending = Vec::new();
+        let mut offset = 0;
+        loop {
+            let chunk = self.chunk(offset)?;
+            if chunk.is_empty() {
+                // a character the file ends halfway through
+                return Ok(pending.is_empty());
+            }
+            if (offset == 0 && chunk.starts_with(encoding::UTF8_BOM)) || chunk.contains(&0) {
+                return Ok(false);
+            }
+            offset += chunk.len();
+            pending.extend_from_slice(&chunk);
+            match std::str::from_utf8(&pending) {
+                Ok(_) => pending.clear(),
+                // cut off by the end of the chunk, finished by the next
+                Err(e) if e.error_len().is_none() => {
+                    pending.drain(..e.valid_up_to());
+                }
+                Err(_) => return Ok(false),
+            }
+        }
+    }
+
+    /// How many reads of the file were made.
+    pub fn reads(&self) -> usize {
+        self.reads.get()
+    }
+
+    /// The most bytes one read took; with no node larger than a chunk, what
+    /// the file ever takes up in memory at once.
+    pub fn largest_read(&self) -> usize {
+        self.largest_read.get()
+    }
+}
+
+impl Source for ChunkedFile {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn bytes(&self, range: Range<usize>) -> Result<Cow<'_, [u8]>> {
+        let end = range.end.min(self.len);
+        let start = range.start.min(end);
+        let mut buffer = Vec::with_capacity(end - start);
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(start as u64))
+            .and_then(|_| file.take((end - start) as u64).read_to_end(&mut buffer))
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        self.reads.set(self.reads.get() + 1);
+        self.largest_read
+            .set(self.largest_read.get().max(buffer.len()));
+        Ok(Cow::Owned(buffer))
+    }
+
+    fn chunk(&self, offset: usize) -> Result<Cow<'_, [u8]>> {
+        self.bytes(offset..offset.saturating_add(self.chunk_bytes))
+    }
+}
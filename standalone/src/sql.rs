@@ -0,0 +1,368 @@
+use crate::captures::GRAMMARS;
+use crate::grep::{TextRule, TEXT_RULES};
+use crate::storage::{kind_key, EdgeRecord, NodeRecord};
+use anyhow::{Context, Result};
+use sqlparser::ast::{Expr, ObjectName, Query, TableFactor, Visit, Visitor};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Query as TreeQuery, QueryCursor};
+
+pub const TABLE_KIND: &str = "Table";
+pub const COLUMN_KIND: &str = "Column";
+pub const QUERIES_TABLE: &str = "QUERIES_TABLE";
+pub const QUERIES_COLUMN: &str = "QUERIES_COLUMN";
+
+/// Node kinds whose string literals are looked at.
+const SYMBOL_KINDS: &[&str] = &[
+    "Function",
+    "Class",
+    "Var",
+    "Endpoint",
+    "UnitTest",
+    "IntegrationTest",
+    "E2eTest",
+];
+
+/// How a statement is spelled at the start of a literal, and a clause it
+/// can't do without. Prose that happens to start with "select" or "update"
+/// rarely has both.
+const STATEMENTS: &[(&str, &str)] = &[
+    ("select", "from"),
+    ("with", "select"),
+    ("insert", "into"),
+    ("update", "set"),
+    ("delete", "from"),
+];
+
+/// What the SQL in one string literal refers to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct References {
+    /// As written, schema included, e.g. `public.users`; common table
+    /// expressions are left out.
+    pub tables: Vec<String>,
+    /// `(table, column)` for each column the statement names that could be
+    /// told apart by its table: qualified by a table or its alias, or in a
+    /// statement over one table.
+    pub columns: Vec<(String, String)>,
+}
+
+/// Whether `text` looks enough like a SQL statement to be worth parsing:
+/// it starts with a statement keyword, written all in upper or all in lower
+/// case, has the clause that statement needs, and doesn't end a sentence.
+pub fn looks_like_sql(text: &str) -> bool {
+    let text = text.trim();
+    let first = text
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default();
+    let cased = first == first.to_ascii_uppercase() || first == first.to_ascii_lowercase();
+    let Some((_, clause)) = STATEMENTS
+        .iter()
+        .find(|(keyword, _)| first.eq_ignore_ascii_case(keyword))
+    else {
+        return false;
+    };
+    cased
+        && !text.ends_with(&['.', '!'][..])
+        && text
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .skip(1)
+            .any(|word| word.eq_ignore_ascii_case(clause))
+}
+
+/// The tables and columns `sql` refers to, or `None` when it isn't SQL a
+/// generic dialect parses.
+pub fn references(sql: &str) -> Option<References> {
+    let statements = Parser::parse_sql(&GenericDialect {}, sql).ok()?;
+    if statements.is_empty() {
+        return None;
+    }
+    let mut collector = Collector::default();
+    let _ = statements.visit(&mut collector);
+
+    let mut tables = Vec::new();
+    let mut seen = BTreeSet::new();
+    for table in &collector.tables {
+        let key = table.to_lowercase();
+        if !collector.ctes.contains(&key) && seen.insert(key) {
+            tables.push(table.clone());
+        }
+    }
+    let by_name: HashMap<String, &String> = tables
+        .iter()
+        .map(|t| (last_part(t).to_lowercase(), t))
+        .chain(tables.iter().map(|t| (t.to_lowercase(), t)))
+        .collect();
+    let mut columns = Vec::new();
+    for (qualifier, column) in collector.columns {
+        let table = match qualifier {
+            Some(qualifier) => {
+                let qualifier = qualifier.to_lowercase();
+                let named = collector.aliases.get(&qualifier).unwrap_or(&qualifier);
+                by_name.get(&named.to_lowercase()).copied()
+            }
+            None if tables.len() == 1 => tables.first(),
+            None => None,
+        };
+        if let Some(table) = table {
+            let column = (table.clone(), column);
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+    }
+    Some(References { tables, columns })
+}
+
+#[derive(Default)]
+struct Collector {
+    tables: Vec<String>,
+    /// Lowercased names of common table expressions.
+    ctes: BTreeSet<String>,
+    /// Lowercased alias to the table it stands for.
+    aliases: HashMap<String, String>,
+    columns: Vec<(Option<String>, String)>,
+}
+
+impl Visitor for Collector {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                self.ctes.insert(cte.alias.name.value.to_lowercase());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<()> {
+        self.tables.push(object_name(relation));
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, factor: &TableFactor) -> ControlFlow<()> {
+        if let TableFactor::Table {
+            name,
+            alias: Some(alias),
+            ..
+        } = factor
+        {
+            self.aliases
+                .insert(alias.name.value.to_lowercase(), object_name(name));
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<()> {
+        match expr {
+            Expr::Identifier(column) => self.columns.push((None, column.value.clone())),
+            Expr::CompoundIdentifier(parts) if parts.len() >= 2 => {
+                let qualifier = parts[..parts.len() - 1]
+                    .iter()
+                    .map(|p| p.value.as_str())
+                    .collect::<Vec<_>>()
+                    .join(".");
+                let column = parts[parts.len() - 1].value.clone();
+                self.columns.push((Some(qualifier), column));
+            }
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+fn object_name(name: &ObjectName) -> String {
+    name.0
+        .iter()
+        .map(|part| part.value.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn last_part(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or(name)
+}
+
+/// The text of a string literal as tree-sitter gives it, without its
+/// quotes, prefix and escapes. Interpolations, and placeholders like `%s`,
+/// become `?` so the statement around them still parses.
+pub fn unquote(literal: &str) -> String {
+    static PLACEHOLDER: OnceLock<regex::Regex> = OnceLock::new();
+    let placeholder =
+        PLACEHOLDER.get_or_init(|| regex::Regex::new(r"[$#]?\{[^{}]*\}|%\(\w+\)s|%[sd]").unwrap());
+    let inner = literal.trim_start_matches(|c: char| c.is_ascii_alphabetic() || c == '#');
+    let inner = inner.trim_end_matches('#');
+    let quote = inner.chars().next().unwrap_or('"');
+    let delimiter = if inner.len() >= 6 && inner.starts_with(&quote.to_string().repeat(3)) {
+        3
+    } else {
+        1
+    };
+    let inner = inner
+        .get(delimiter..inner.len().saturating_sub(delimiter))
+        .unwrap_or_default();
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 't' | 'r') | None => text.push(' '),
+            // a line continuation
+            Some('\n') => {}
+            Some(escaped) => text.push(escaped),
+        }
+    }
+    placeholder.replace_all(&text, "?").into_owned()
+}
+
+/// `Table` and `Column` nodes for what the SQL in string literals refers to,
+/// and `QUERIES_TABLE` and `QUERIES_COLUMN` edges to them from the symbol
+/// holding the literal, or from the file outside any symbol. A literal only
+/// counts when [`looks_like_sql`] and it parses. The nodes belong to the
+/// file of the literal, one per table or column it refers to, so a table is
+/// found across files by name. Files are read from the checkout at `root`;
+/// one that can't be read any more, or doesn't parse within `timeout`, is
+/// left out.
+pub fn detect(
+    root: &Path,
+    repo_id: &str,
+    nodes: &[NodeRecord],
+    timeout: Option<Duration>,
+) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let mut by_file: BTreeMap<&str, Vec<&NodeRecord>> = BTreeMap::new();
+    for node in nodes.iter().filter(|n| n.kind == "File") {
+        by_file.entry(node.file.as_str()).or_default().push(node);
+    }
+    for node in nodes
+        .iter()
+        .filter(|n| SYMBOL_KINDS.contains(&n.kind.as_str()))
+    {
+        by_file.entry(node.file.as_str()).or_default().push(node);
+    }
+    let root_prefix = format!("{}/", root.display());
+    let mut found = (Vec::new(), Vec::new());
+    for (file, symbols) in by_file {
+        if rule_for(file).is_none() {
+            continue;
+        }
+        let rel = file
+            .find(&root_prefix)
+            .map_or(file, |at| &file[at + root_prefix.len()..]);
+        let Ok(decoded) = crate::encoding::read(&root.join(rel)) else {
+            continue;
+        };
+        match link(file, &decoded.text, repo_id, &symbols, timeout) {
+            Ok((nodes, edges)) => {
+                found.0.extend(nodes);
+                found.1.extend(edges);
+            }
+            Err(e) => tracing::warn!("Failed to read the SQL of {}: {:#}", rel, e),
+        }
+    }
+    found
+}
+
+fn rule_for(file: &str) -> Option<&'static TextRule> {
+    let ext = Path::new(file).extension()?.to_str()?;
+    let grammar = GRAMMARS.iter().find(|g| g.extensions.contains(&ext))?;
+    TEXT_RULES.iter().find(|r| r.grammar == grammar.name)
+}
+
+/// [`detect`] for the one `file`, whose text is `source` and whose symbols,
+/// its `File` node among them, are `symbols`.
+pub fn link(
+    file: &str,
+    source: &str,
+    repo_id: &str,
+    symbols: &[&NodeRecord],
+    timeout: Option<Duration>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let Some(rule) = rule_for(file) else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let grammar = GRAMMARS.iter().find(|g| g.name == rule.grammar).unwrap();
+    let language = grammar.language();
+    let Some(tree) = crate::lang::parse(&language, source, timeout)? else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let query = TreeQuery::new(&language, &TextRule::query(rule.strings))
+        .with_context(|| format!("invalid string query for {}", rule.grammar))?;
+
+    let bytes = source.as_bytes();
+    let mut nodes: BTreeMap<String, NodeRecord> = BTreeMap::new();
+    let mut edges = BTreeSet::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&query, tree.root_node(), bytes);
+    while let Some(m) = matches.next() {
+        let Some(literal) = m.captures.first().map(|c| c.node) else {
+            continue;
+        };
+        let sql = unquote(literal.utf8_text(bytes)?);
+        if !looks_like_sql(&sql) {
+            continue;
+        }
+        let Some(references) = references(&sql) else {
+            continue;
+        };
+        let line = literal.start_position().row;
+        let Some(symbol) = enclosing(symbols, line) else {
+            continue;
+        };
+        let mut add = |kind: &str, name: String, edge: &str| {
+            let id = kind_key(kind, &name, file, 0, None);
+            nodes.entry(id.clone()).or_insert_with(|| NodeRecord {
+                repo_id: repo_id.to_string(),
+                id: id.clone(),
+                kind: kind.to_string(),
+                name,
+                file: file.to_string(),
+                start: line,
+                end: literal.end_position().row,
+                body: sql.trim().to_string(),
+                meta: Default::default(),
+                span: None,
+            });
+            edges.insert((symbol.id.clone(), id, edge.to_string()));
+        };
+        for table in references.tables {
+            add(TABLE_KIND, table, QUERIES_TABLE);
+        }
+        for (table, column) in references.columns {
+            add(COLUMN_KIND, format!("{}.{}", table, column), QUERIES_COLUMN);
+        }
+    }
+    let mut nodes: Vec<NodeRecord> = nodes.into_values().collect();
+    nodes.sort_by_key(|n| n.start);
+    let edges = edges
+        .into_iter()
+        .map(|(source, target, kind)| EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind,
+            source,
+            target,
+        })
+        .collect();
+    Ok((nodes, edges))
+}
+
+/// The innermost of `symbols` spanning `line`, or the file's node when none
+/// does. A variable only counts outside functions, so a query built up in
+/// locals is still put down to the function.
+pub(crate) fn enclosing<'a>(symbols: &[&'a NodeRecord], line: usize) -> Option<&'a NodeRecord> {
+    symbols
+        .iter()
+        .filter(|s| s.kind != "File" && s.start <= line && line <= s.end)
+        .min_by_key(|s| (s.kind == "Var", s.end - s.start))
+        .or_else(|| symbols.iter().find(|s| s.kind == "File"))
+        .copied()
+}
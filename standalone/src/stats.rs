@@ -0,0 +1,100 @@
+use crate::complexity::LINES;
+use crate::storage::{EdgeRecord, NodeRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// The meta key a `File` node's language is stored under, as named by
+/// [`detect_language`](crate::filter::detect_language).
+pub const LANGUAGE: &str = "language";
+/// The language of files nothing recognizes.
+pub const OTHER: &str = "other";
+/// How many of the most-called functions are listed by default.
+pub const DEFAULT_TOP: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LanguageStats {
+    pub files: usize,
+    pub lines: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalledFunction {
+    pub id: String,
+    pub name: String,
+    pub file: String,
+    /// `CALLS` edges into the function.
+    pub callers: usize,
+}
+
+/// A repo's overview, aggregated from what its ingest stored.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RepoStats {
+    pub repo_id: String,
+    pub languages: BTreeMap<String, LanguageStats>,
+    /// Nodes per kind, `File` nodes excluded.
+    pub symbols: BTreeMap<String, usize>,
+    /// Most callers first, ties by name.
+    pub most_called: Vec<CalledFunction>,
+}
+
+/// The stats of one repo's graph. A file's language and lines come from its
+/// `File` node's meta; a file stored before its language was recorded is
+/// judged on its name, and one without a line count counts none.
+pub fn repo_stats(
+    repo_id: &str,
+    nodes: &[NodeRecord],
+    edges: &[EdgeRecord],
+    top: usize,
+) -> RepoStats {
+    let mut stats = RepoStats {
+        repo_id: repo_id.to_string(),
+        ..Default::default()
+    };
+    for node in nodes {
+        if node.kind != "File" {
+            *stats.symbols.entry(node.kind.clone()).or_insert(0) += 1;
+            continue;
+        }
+        let language = node
+            .meta
+            .get(LANGUAGE)
+            .map(String::as_str)
+            .or_else(|| crate::filter::detect_language(Path::new(&node.file), &[]))
+            .unwrap_or(OTHER);
+        let entry = stats.languages.entry(language.to_string()).or_default();
+        entry.files += 1;
+        entry.lines += node
+            .meta
+            .get(LINES)
+            .and_then(|l| l.parse::<usize>().ok())
+            .unwrap_or(0);
+    }
+
+    let mut callers: HashMap<&str, usize> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.kind == "CALLS") {
+        *callers.entry(edge.target.as_str()).or_default() += 1;
+    }
+    let mut called: Vec<CalledFunction> = nodes
+        .iter()
+        .filter(|n| n.kind == "Function")
+        .filter_map(|n| {
+            let callers = *callers.get(n.id.as_str())?;
+            Some(CalledFunction {
+                id: n.id.clone(),
+                name: n.name.clone(),
+                file: n.file.clone(),
+                callers,
+            })
+        })
+        .collect();
+    called.sort_by(|a, b| {
+        b.callers
+            .cmp(&a.callers)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    called.truncate(top);
+    stats.most_called = called;
+    stats
+}
@@ -0,0 +1,303 @@
+use super::{
+    Compaction, EdgeRecord, NodeRecord, RepoRecord, RetentionRecord, RowStream, SnapshotRecord,
+    Storage, Transaction,
+};
+use crate::audit::AuditEntry;
+use crate::lang::Diagnostic;
+use crate::query::{QueryTemplate, REPO_PARAM};
+use crate::retention::Retention;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// Notes in `retention` each repo an ingest finishes for or a read is scoped
+/// to, and hands everything to the wrapped backend. Reads of every repo at
+/// once don't count as a use of any of them. It wraps the
+/// [`CachingStorage`](super::cache::CachingStorage), so reads answered
+/// from memory count too.
+pub struct AccessTracking {
+    inner: Arc<dyn Storage>,
+    retention: Arc<Retention>,
+}
+
+impl AccessTracking {
+    pub fn new(inner: Arc<dyn Storage>, retention: Arc<Retention>) -> Self {
+        AccessTracking { inner, retention }
+    }
+
+    /// Stores the use too, at most every
+    /// [`ACCESS_RESOLUTION`](crate::retention::ACCESS_RESOLUTION). A backend
+    /// that can't take it, such as a read-only one, leaves it in memory; the
+    /// read goes ahead either way.
+    async fn touch(&self, repo_id: Option<&str>) {
+        let Some(repo_id) = repo_id else {
+            return;
+        };
+        let Some(at) = self.retention.touch(repo_id) else {
+            return;
+        };
+        if let Err(e) = self.inner.record_access(repo_id, at).await {
+            debug!("Could not store the use of {}: {:#}", repo_id, e);
+        }
+    }
+
+    async fn touch_params(&self, params: &Map<String, Value>) {
+        self.touch(params.get(REPO_PARAM).and_then(Value::as_str))
+            .await;
+    }
+}
+
+#[async_trait]
+impl Storage for AccessTracking {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        self.inner.upsert_node(node).await
+    }
+
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        self.inner.upsert_edge(edge).await
+    }
+
+    async fn upsert_nodes(&self, nodes: &[NodeRecord]) -> Result<()> {
+        self.inner.upsert_nodes(nodes).await
+    }
+
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<()> {
+        self.inner.upsert_edges(edges).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        self.inner.begin().await
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        self.inner.file_node_ids(repo_id, file).await
+    }
+
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.inner.delete_nodes(repo_id, ids).await
+    }
+
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        self.inner.delete_file(repo_id, file).await
+    }
+
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.inner.clear(repo_id).await
+    }
+
+    // `/repos` sizes up every repo, which is no use of any of them
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.inner.graph_size(repo_id).await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        self.inner.graph_version(repo_id).await
+    }
+
+    async fn graph_versions(&self) -> Result<HashMap<String, u64>> {
+        self.inner.graph_versions().await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        self.inner.repo_hash(repo_url).await
+    }
+
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        self.inner.set_repo_hash(repo_url, hash).await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        self.inner.file_hashes(repo_id).await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        self.inner.set_file_hashes(repo_id, hashes).await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.inner
+            .replace_diagnostics(repo_id, files, diagnostics)
+            .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        self.inner.find_repo(name).await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        self.touch(Some(repo_id)).await;
+        self.inner.node(repo_id, id).await
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        self.touch(Some(&repo.repo_id)).await;
+        self.inner.record_ingest(repo).await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.inner.repos().await
+    }
+
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<()> {
+        self.inner.append_audit(entry).await
+    }
+
+    async fn audit_entries(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        self.inner.audit_entries(limit).await
+    }
+
+    async fn compact(&self) -> Result<Compaction> {
+        self.inner.compact().await
+    }
+
+    async fn copy_graph(&self, from: &str, to: &str) -> Result<(usize, usize)> {
+        self.inner.copy_graph(from, to).await
+    }
+
+    async fn record_snapshot(&self, snapshot: &SnapshotRecord) -> Result<()> {
+        self.inner.record_snapshot(snapshot).await
+    }
+
+    async fn snapshots(&self, repo_id: &str) -> Result<Vec<SnapshotRecord>> {
+        self.inner.snapshots(repo_id).await
+    }
+
+    async fn remove_snapshot(&self, repo_id: &str, name: &str) -> Result<bool> {
+        self.inner.remove_snapshot(repo_id, name).await
+    }
+
+    async fn record_ingest_log(&self, repo_id: &str, events: &[String]) -> Result<()> {
+        self.inner.record_ingest_log(repo_id, events).await
+    }
+
+    async fn ingest_log(&self, repo_id: &str) -> Result<Vec<String>> {
+        self.inner.ingest_log(repo_id).await
+    }
+
+    async fn record_access(&self, repo_id: &str, at: u64) -> Result<()> {
+        self.inner.record_access(repo_id, at).await
+    }
+
+    async fn set_pinned(&self, repo_id: &str, pinned: bool) -> Result<()> {
+        self.inner.set_pinned(repo_id, pinned).await
+    }
+
+    async fn retention(&self) -> Result<HashMap<String, RetentionRecord>> {
+        self.inner.retention().await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        self.touch(repo_id).await;
+        self.inner.load_graph(repo_id).await
+    }
+
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.inner.dangling_edges(repo_id, after, limit).await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.inner.orphan_nodes(repo_id, after, limit).await
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.inner.duplicate_nodes(repo_id, after, limit).await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        self.inner.delete_edges(edges).await
+    }
+
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.inner.dedupe_nodes(repo_id, ids).await
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.touch_params(params).await;
+        self.inner.query(template, params).await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        self.inner.query_raw(statement, params).await
+    }
+
+    async fn query_stream(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        self.touch_params(params).await;
+        self.inner.query_stream(template, params).await
+    }
+
+    async fn query_raw_stream(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        self.inner.query_raw_stream(statement, params).await
+    }
+
+    async fn query_cancellable(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        self.touch_params(params).await;
+        self.inner.query_cancellable(template, params, cancel).await
+    }
+
+    async fn query_raw_cancellable(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        self.inner
+            .query_raw_cancellable(statement, params, cancel)
+            .await
+    }
+}
@@ -0,0 +1,392 @@
+use super::{
+    Compaction, EdgeRecord, NodeRecord, RepoRecord, RetentionRecord, RowStream, SnapshotRecord,
+    Storage, Transaction,
+};
+use crate::audit::AuditEntry;
+use crate::lang::Diagnostic;
+use crate::query::QueryTemplate;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// How many node and edge writes are buffered before a flush.
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+#[derive(Default)]
+struct Pending {
+    nodes: Vec<NodeRecord>,
+    edges: Vec<EdgeRecord>,
+}
+
+impl Pending {
+    fn len(&self) -> usize {
+        self.nodes.len() + self.edges.len()
+    }
+}
+
+/// Buffers upserts and hands them to the wrapped backend's `upsert_nodes` and
+/// `upsert_edges` in batches. Nodes are always written before edges, so an edge
+/// can't arrive ahead of its endpoints, and every other operation flushes
+/// first, so deletes and reads see everything written before them. Whatever is
+/// still buffered when the wrapper is dropped is written from a background task.
+pub struct BatchingStorage {
+    inner: Arc<dyn Storage>,
+    capacity: usize,
+    // held across the flush so concurrent writers can't reorder nodes and edges
+    pending: Mutex<Pending>,
+}
+
+impl BatchingStorage {
+    pub fn new(inner: Arc<dyn Storage>, capacity: usize) -> Self {
+        BatchingStorage {
+            inner,
+            capacity: capacity.max(1),
+            pending: Mutex::new(Pending::default()),
+        }
+    }
+
+    async fn flush_pending(&self, pending: &mut Pending) -> Result<()> {
+        let pending = std::mem::take(pending);
+        write(self.inner.as_ref(), pending).await
+    }
+}
+
+/// A failed batch is retried one record at a time, so the error names the
+/// record the backend rejected rather than the batch as a whole.
+async fn write(inner: &dyn Storage, pending: Pending) -> Result<()> {
+    if !pending.nodes.is_empty() && inner.upsert_nodes(&pending.nodes).await.is_err() {
+        for node in &pending.nodes {
+            inner.upsert_node(node).await.with_context(|| {
+                format!(
+                    "failed to write node {} ({} in {})",
+                    node.id, node.kind, node.file
+                )
+            })?;
+        }
+    }
+    if !pending.edges.is_empty() && inner.upsert_edges(&pending.edges).await.is_err() {
+        for edge in &pending.edges {
+            inner.upsert_edge(edge).await.with_context(|| {
+                format!(
+                    "failed to write {} edge {} -> {}",
+                    edge.kind, edge.source, edge.target
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+impl Drop for BatchingStorage {
+    fn drop(&mut self) {
+        let pending = std::mem::take(self.pending.get_mut());
+        if pending.len() == 0 {
+            return;
+        }
+        let count = pending.len();
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let inner = self.inner.clone();
+                handle.spawn(async move {
+                    if let Err(e) = write(inner.as_ref(), pending).await {
+                        tracing::error!("failed to flush {} buffered writes: {:#}", count, e);
+                    }
+                });
+            }
+            Err(_) => tracing::error!("dropped {} buffered writes outside a runtime", count),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for BatchingStorage {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        self.upsert_nodes(std::slice::from_ref(node)).await
+    }
+
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        self.upsert_edges(std::slice::from_ref(edge)).await
+    }
+
+    async fn upsert_nodes(&self, nodes: &[NodeRecord]) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+        pending.nodes.extend_from_slice(nodes);
+        if pending.len() >= self.capacity {
+            self.flush_pending(&mut pending).await?;
+        }
+        Ok(())
+    }
+
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+        pending.edges.extend_from_slice(edges);
+        if pending.len() >= self.capacity {
+            self.flush_pending(&mut pending).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+        self.flush_pending(&mut pending).await?;
+        self.inner.flush().await
+    }
+
+    /// Transactions already write their records in one go, so they go
+    /// straight to the wrapped backend, after whatever is buffered.
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        self.flush().await?;
+        self.inner.begin().await
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        self.flush().await?;
+        self.inner.file_node_ids(repo_id, file).await
+    }
+
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.flush().await?;
+        self.inner.delete_nodes(repo_id, ids).await
+    }
+
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        self.flush().await?;
+        self.inner.delete_file(repo_id, file).await
+    }
+
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.flush().await?;
+        self.inner.clear(repo_id).await
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.flush().await?;
+        self.inner.graph_size(repo_id).await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        self.flush().await?;
+        self.inner.graph_version(repo_id).await
+    }
+
+    async fn graph_versions(&self) -> Result<HashMap<String, u64>> {
+        self.flush().await?;
+        self.inner.graph_versions().await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        self.inner.repo_hash(repo_url).await
+    }
+
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        // the hash marks the repo as fully written
+        self.flush().await?;
+        self.inner.set_repo_hash(repo_url, hash).await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        self.inner.file_hashes(repo_id).await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        // like the repo hash, a file hash vouches for nodes already written
+        self.flush().await?;
+        self.inner.set_file_hashes(repo_id, hashes).await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.inner
+            .replace_diagnostics(repo_id, files, diagnostics)
+            .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        self.flush().await?;
+        self.inner.find_repo(name).await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        self.flush().await?;
+        self.inner.node(repo_id, id).await
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        // like the repo hash, the record vouches for the ingest's writes
+        self.flush().await?;
+        self.inner.record_ingest(repo).await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.inner.repos().await
+    }
+
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<()> {
+        self.inner.append_audit(entry).await
+    }
+
+    async fn audit_entries(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        self.inner.audit_entries(limit).await
+    }
+
+    async fn compact(&self) -> Result<Compaction> {
+        // so what is still held is compacted too
+        self.flush().await?;
+        self.inner.compact().await
+    }
+
+    async fn copy_graph(&self, from: &str, to: &str) -> Result<(usize, usize)> {
+        self.flush().await?;
+        self.inner.copy_graph(from, to).await
+    }
+
+    async fn record_snapshot(&self, snapshot: &SnapshotRecord) -> Result<()> {
+        self.inner.record_snapshot(snapshot).await
+    }
+
+    async fn snapshots(&self, repo_id: &str) -> Result<Vec<SnapshotRecord>> {
+        self.inner.snapshots(repo_id).await
+    }
+
+    async fn remove_snapshot(&self, repo_id: &str, name: &str) -> Result<bool> {
+        self.inner.remove_snapshot(repo_id, name).await
+    }
+
+    async fn record_ingest_log(&self, repo_id: &str, events: &[String]) -> Result<()> {
+        self.inner.record_ingest_log(repo_id, events).await
+    }
+
+    async fn ingest_log(&self, repo_id: &str) -> Result<Vec<String>> {
+        self.inner.ingest_log(repo_id).await
+    }
+
+    async fn record_access(&self, repo_id: &str, at: u64) -> Result<()> {
+        self.inner.record_access(repo_id, at).await
+    }
+
+    async fn set_pinned(&self, repo_id: &str, pinned: bool) -> Result<()> {
+        self.inner.set_pinned(repo_id, pinned).await
+    }
+
+    async fn retention(&self) -> Result<HashMap<String, RetentionRecord>> {
+        self.inner.retention().await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        self.flush().await?;
+        self.inner.load_graph(repo_id).await
+    }
+
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.flush().await?;
+        self.inner.dangling_edges(repo_id, after, limit).await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.flush().await?;
+        self.inner.orphan_nodes(repo_id, after, limit).await
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.flush().await?;
+        self.inner.duplicate_nodes(repo_id, after, limit).await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        self.flush().await?;
+        self.inner.delete_edges(edges).await
+    }
+
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.flush().await?;
+        self.inner.dedupe_nodes(repo_id, ids).await
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.flush().await?;
+        self.inner.query(template, params).await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        self.flush().await?;
+        self.inner.query_raw(statement, params).await
+    }
+
+    async fn query_stream(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        self.flush().await?;
+        self.inner.query_stream(template, params).await
+    }
+
+    async fn query_raw_stream(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        self.flush().await?;
+        self.inner.query_raw_stream(statement, params).await
+    }
+
+    async fn query_cancellable(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        self.flush().await?;
+        self.inner.query_cancellable(template, params, cancel).await
+    }
+
+    async fn query_raw_cancellable(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        self.flush().await?;
+        self.inner
+            .query_raw_cancellable(statement, params, cancel)
+            .await
+    }
+}
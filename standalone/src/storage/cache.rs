@@ -0,0 +1,575 @@
+use super::{
+    Compaction, EdgeRecord, NodeRecord, RepoRecord, RetentionRecord, RowStream, SnapshotRecord,
+    Storage, Transaction,
+};
+use crate::audit::AuditEntry;
+use crate::lang::Diagnostic;
+use crate::query::{QueryTemplate, REPO_PARAM};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// How much memory warmed graphs may take up, in bytes, before the least
+/// recently used is evicted.
+pub const DEFAULT_GRAPH_CACHE_BYTES: usize = 256 << 20;
+
+/// A repo's whole graph, as [`Storage::load_graph`] returned it.
+pub struct CachedGraph {
+    pub nodes: Vec<NodeRecord>,
+    pub edges: Vec<EdgeRecord>,
+    /// Roughly what the records take up in memory.
+    pub bytes: usize,
+}
+
+impl CachedGraph {
+    pub fn new(nodes: Vec<NodeRecord>, edges: Vec<EdgeRecord>) -> Self {
+        let bytes = nodes.iter().map(node_bytes).sum::<usize>()
+            + edges.iter().map(edge_bytes).sum::<usize>();
+        CachedGraph {
+            nodes,
+            edges,
+            bytes,
+        }
+    }
+}
+
+fn node_bytes(node: &NodeRecord) -> usize {
+    std::mem::size_of::<NodeRecord>()
+        + node.repo_id.len()
+        + node.id.len()
+        + node.kind.len()
+        + node.name.len()
+        + node.file.len()
+        + node.body.len()
+        + node
+            .meta
+            .iter()
+            .map(|(k, v)| k.len() + v.len())
+            .sum::<usize>()
+}
+
+fn edge_bytes(edge: &EdgeRecord) -> usize {
+    std::mem::size_of::<EdgeRecord>()
+        + edge.repo_id.len()
+        + edge.kind.len()
+        + edge.source.len()
+        + edge.target.len()
+}
+
+/// What [`GraphCache::insert`] did with a graph.
+#[derive(Debug, PartialEq)]
+pub enum Inserted {
+    /// Cached, evicting the repos listed, least recently used first.
+    Cached { bytes: usize, evicted: Vec<String> },
+    /// Bigger than the whole budget, so not cached.
+    TooLarge { bytes: usize, budget: usize },
+    /// The repo was written to while its graph was being loaded.
+    Stale,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Most recently used first.
+    warmed: VecDeque<(String, Arc<CachedGraph>)>,
+    used: usize,
+    /// Goes up with every invalidation, so a graph loaded before one isn't
+    /// cached after it.
+    generation: u64,
+}
+
+/// The graphs of the repos `/warm` was asked for, held in memory so the
+/// reads [`CachingStorage`] can answer from them don't reach the backend.
+/// Any write to a repo through it drops the repo's graph; writes made to the
+/// backend some other way, as by another server, aren't seen.
+pub struct GraphCache {
+    budget: AtomicUsize,
+    entries: Mutex<Entries>,
+}
+
+impl Default for GraphCache {
+    fn default() -> Self {
+        GraphCache::new(DEFAULT_GRAPH_CACHE_BYTES)
+    }
+}
+
+impl GraphCache {
+    pub fn new(budget: usize) -> Self {
+        GraphCache {
+            budget: AtomicUsize::new(budget),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget.load(Ordering::Relaxed)
+    }
+
+    /// Changes the budget, evicting what no longer fits; `0` turns the
+    /// cache off.
+    pub fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        evict(&mut entries, budget, 0);
+    }
+
+    /// To pass to [`insert`](Self::insert) once a graph is loaded.
+    pub fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    /// Caches `graph` as `repo_id`'s unless something was invalidated since
+    /// `since`, evicting the least recently used graphs to make room.
+    pub fn insert(&self, repo_id: &str, graph: CachedGraph, since: u64) -> Inserted {
+        let budget = self.budget();
+        if graph.bytes > budget {
+            return Inserted::TooLarge {
+                bytes: graph.bytes,
+                budget,
+            };
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.generation != since {
+            return Inserted::Stale;
+        }
+        remove(&mut entries, repo_id);
+        let bytes = graph.bytes;
+        let evicted = evict(&mut entries, budget, bytes);
+        entries.used += bytes;
+        entries
+            .warmed
+            .push_front((repo_id.to_string(), Arc::new(graph)));
+        Inserted::Cached { bytes, evicted }
+    }
+
+    /// `repo_id`'s graph, if it's warm, which makes it the most recently used.
+    pub fn get(&self, repo_id: &str) -> Option<Arc<CachedGraph>> {
+        let mut entries = self.entries.lock().unwrap();
+        let i = entries.warmed.iter().position(|(id, _)| id == repo_id)?;
+        let entry = entries.warmed.remove(i).unwrap();
+        let graph = entry.1.clone();
+        entries.warmed.push_front(entry);
+        Some(graph)
+    }
+
+    /// The repos warm now, most recently used first.
+    pub fn repos(&self) -> Vec<String> {
+        let entries = self.entries.lock().unwrap();
+        entries.warmed.iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    pub fn invalidate(&self, repo_id: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        remove(&mut entries, repo_id);
+    }
+
+    pub fn invalidate_all(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        let generation = entries.generation + 1;
+        *entries = Entries {
+            generation,
+            ..Default::default()
+        };
+    }
+}
+
+fn remove(entries: &mut Entries, repo_id: &str) {
+    if let Some(i) = entries.warmed.iter().position(|(id, _)| id == repo_id) {
+        let (_, graph) = entries.warmed.remove(i).unwrap();
+        entries.used -= graph.bytes;
+    }
+}
+
+/// Drops the least recently used graphs until `room` more bytes fit.
+fn evict(entries: &mut Entries, budget: usize, room: usize) -> Vec<String> {
+    let mut evicted = Vec::new();
+    while entries.used + room > budget {
+        let Some((id, graph)) = entries.warmed.pop_back() else {
+            break;
+        };
+        entries.used -= graph.bytes;
+        evicted.push(id);
+    }
+    evicted
+}
+
+/// Answers `load_graph`, `node` and the query templates with an in-memory
+/// form for a repo from `cache` when it's warm there, and hands everything
+/// else to the wrapped backend. Every write drops the graphs of the repos it
+/// touches once the backend has it; a raw statement, which may write
+/// anything, drops them all.
+pub struct CachingStorage {
+    inner: Arc<dyn Storage>,
+    cache: Arc<GraphCache>,
+}
+
+impl CachingStorage {
+    pub fn new(inner: Arc<dyn Storage>, cache: Arc<GraphCache>) -> Self {
+        CachingStorage { inner, cache }
+    }
+
+    fn cached(&self, repo_id: Option<&str>) -> Option<Arc<CachedGraph>> {
+        self.cache.get(repo_id.filter(|id| !id.is_empty())?)
+    }
+
+    /// Rows of `template` from memory, when its repo is warm and it has an
+    /// in-memory form.
+    fn cached_rows(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Option<Vec<Value>> {
+        let run = template.cached?;
+        let graph = self.cached(params.get(REPO_PARAM).and_then(Value::as_str))?;
+        Some(run(&graph.nodes, &graph.edges, params))
+    }
+}
+
+/// Drops the graph of every repo `repo_ids` names.
+fn invalidate<'a>(cache: &GraphCache, repo_ids: impl IntoIterator<Item = &'a str>) {
+    let repo_ids: BTreeSet<&str> = repo_ids.into_iter().collect();
+    for repo_id in repo_ids {
+        cache.invalidate(repo_id);
+    }
+}
+
+#[async_trait]
+impl Storage for CachingStorage {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        let written = self.inner.upsert_node(node).await;
+        self.cache.invalidate(&node.repo_id);
+        written
+    }
+
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        let written = self.inner.upsert_edge(edge).await;
+        self.cache.invalidate(&edge.repo_id);
+        written
+    }
+
+    async fn upsert_nodes(&self, nodes: &[NodeRecord]) -> Result<()> {
+        let written = self.inner.upsert_nodes(nodes).await;
+        invalidate(&self.cache, nodes.iter().map(|n| n.repo_id.as_str()));
+        written
+    }
+
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<()> {
+        let written = self.inner.upsert_edges(edges).await;
+        invalidate(&self.cache, edges.iter().map(|e| e.repo_id.as_str()));
+        written
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        Ok(Box::new(CachingTransaction {
+            inner: self.inner.begin().await?,
+            cache: self.cache.clone(),
+            repo_ids: BTreeSet::new(),
+        }))
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        self.inner.file_node_ids(repo_id, file).await
+    }
+
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        let deleted = self.inner.delete_nodes(repo_id, ids).await;
+        self.cache.invalidate(repo_id);
+        deleted
+    }
+
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        let deleted = self.inner.delete_file(repo_id, file).await;
+        self.cache.invalidate(repo_id);
+        deleted
+    }
+
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        let cleared = self.inner.clear(repo_id).await;
+        match repo_id {
+            Some(repo_id) => self.cache.invalidate(repo_id),
+            None => self.cache.invalidate_all(),
+        }
+        cleared
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.inner.graph_size(repo_id).await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        self.inner.graph_version(repo_id).await
+    }
+
+    async fn graph_versions(&self) -> Result<HashMap<String, u64>> {
+        self.inner.graph_versions().await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        self.inner.repo_hash(repo_url).await
+    }
+
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        self.inner.set_repo_hash(repo_url, hash).await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        self.inner.file_hashes(repo_id).await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        self.inner.set_file_hashes(repo_id, hashes).await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.inner
+            .replace_diagnostics(repo_id, files, diagnostics)
+            .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        self.inner.find_repo(name).await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        match self.cached(Some(repo_id)) {
+            Some(graph) => Ok(graph.nodes.iter().find(|n| n.id == id).cloned()),
+            None => self.inner.node(repo_id, id).await,
+        }
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        self.inner.record_ingest(repo).await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.inner.repos().await
+    }
+
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<()> {
+        self.inner.append_audit(entry).await
+    }
+
+    async fn audit_entries(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        self.inner.audit_entries(limit).await
+    }
+
+    async fn compact(&self) -> Result<Compaction> {
+        self.inner.compact().await
+    }
+
+    async fn copy_graph(&self, from: &str, to: &str) -> Result<(usize, usize)> {
+        let copied = self.inner.copy_graph(from, to).await;
+        self.cache.invalidate(to);
+        copied
+    }
+
+    async fn record_snapshot(&self, snapshot: &SnapshotRecord) -> Result<()> {
+        self.inner.record_snapshot(snapshot).await
+    }
+
+    async fn snapshots(&self, repo_id: &str) -> Result<Vec<SnapshotRecord>> {
+        self.inner.snapshots(repo_id).await
+    }
+
+    async fn remove_snapshot(&self, repo_id: &str, name: &str) -> Result<bool> {
+        self.inner.remove_snapshot(repo_id, name).await
+    }
+
+    async fn record_ingest_log(&self, repo_id: &str, events: &[String]) -> Result<()> {
+        self.inner.record_ingest_log(repo_id, events).await
+    }
+
+    async fn ingest_log(&self, repo_id: &str) -> Result<Vec<String>> {
+        self.inner.ingest_log(repo_id).await
+    }
+
+    async fn record_access(&self, repo_id: &str, at: u64) -> Result<()> {
+        self.inner.record_access(repo_id, at).await
+    }
+
+    async fn set_pinned(&self, repo_id: &str, pinned: bool) -> Result<()> {
+        self.inner.set_pinned(repo_id, pinned).await
+    }
+
+    async fn retention(&self) -> Result<HashMap<String, RetentionRecord>> {
+        self.inner.retention().await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        match self.cached(repo_id) {
+            Some(graph) => Ok((graph.nodes.clone(), graph.edges.clone())),
+            None => self.inner.load_graph(repo_id).await,
+        }
+    }
+
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.inner.dangling_edges(repo_id, after, limit).await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.inner.orphan_nodes(repo_id, after, limit).await
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.inner.duplicate_nodes(repo_id, after, limit).await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        let deleted = self.inner.delete_edges(edges).await;
+        invalidate(&self.cache, edges.iter().map(|e| e.repo_id.as_str()));
+        deleted
+    }
+
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        let deduped = self.inner.dedupe_nodes(repo_id, ids).await;
+        self.cache.invalidate(repo_id);
+        deduped
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        match self.cached_rows(template, params) {
+            Some(rows) => Ok(rows),
+            None => self.inner.query(template, params).await,
+        }
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        let rows = self.inner.query_raw(statement, params).await;
+        self.cache.invalidate_all();
+        rows
+    }
+
+    async fn query_stream(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        match self.cached_rows(template, params) {
+            Some(rows) => Ok(stream::iter(rows.into_iter().map(Ok)).boxed()),
+            None => self.inner.query_stream(template, params).await,
+        }
+    }
+
+    async fn query_raw_stream(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        // the statement may change the graph at any point while it's read
+        self.cache.invalidate_all();
+        self.inner.query_raw_stream(statement, params).await
+    }
+
+    async fn query_cancellable(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        match self.cached_rows(template, params) {
+            Some(rows) => Ok(rows),
+            None => self.inner.query_cancellable(template, params, cancel).await,
+        }
+    }
+
+    async fn query_raw_cancellable(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        let rows = self
+            .inner
+            .query_raw_cancellable(statement, params, cancel)
+            .await;
+        self.cache.invalidate_all();
+        rows
+    }
+}
+
+/// A transaction of the wrapped backend that drops the graphs of the repos
+/// it wrote to once it commits.
+struct CachingTransaction {
+    inner: Box<dyn Transaction>,
+    cache: Arc<GraphCache>,
+    repo_ids: BTreeSet<String>,
+}
+
+#[async_trait]
+impl Transaction for CachingTransaction {
+    async fn upsert_nodes(&mut self, nodes: &[NodeRecord]) -> Result<()> {
+        self.repo_ids
+            .extend(nodes.iter().map(|n| n.repo_id.clone()));
+        self.inner.upsert_nodes(nodes).await
+    }
+
+    async fn upsert_edges(&mut self, edges: &[EdgeRecord]) -> Result<()> {
+        self.repo_ids
+            .extend(edges.iter().map(|e| e.repo_id.clone()));
+        self.inner.upsert_edges(edges).await
+    }
+
+    async fn delete_nodes(&mut self, repo_id: &str, ids: &[String]) -> Result<()> {
+        self.repo_ids.insert(repo_id.to_string());
+        self.inner.delete_nodes(repo_id, ids).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        let CachingTransaction {
+            inner,
+            cache,
+            repo_ids,
+        } = *self;
+        let committed = inner.commit().await;
+        invalidate(&cache, repo_ids.iter().map(String::as_str));
+        committed
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        self.inner.rollback().await
+    }
+}
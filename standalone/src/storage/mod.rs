@@ -0,0 +1,783 @@
+pub mod access;
+pub mod batch;
+pub mod cache;
+#[cfg(feature = "neo4j")]
+pub mod neo4j;
+pub mod reconnect;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod unconfigured;
+
+use crate::audit::AuditEntry;
+use crate::captures::{CustomNode, Endpoint};
+use crate::config::Config;
+use crate::lang::{Diagnostic, Extraction};
+use crate::paths::PathMap;
+use crate::query::QueryTemplate;
+use anyhow::Result;
+use ast::lang::graphs::{BTreeMapGraph, EdgeType};
+use ast::lang::{Node, NodeType};
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeRecord {
+    /// The `owner/name` this node was ingested for; ids are only unique within it.
+    #[serde(default)]
+    pub repo_id: String,
+    /// Same scheme as the `node_key` property `ast` writes to neo4j.
+    pub id: String,
+    pub kind: String,
+    pub name: String,
+    pub file: String,
+    pub start: usize,
+    pub end: usize,
+    pub body: String,
+    #[serde(default)]
+    pub meta: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+}
+
+/// Exactly where a node sits in its file, for jumping to it and hovering. The
+/// lines are the record's `start` and `end`; columns and offsets count bytes,
+/// as tree-sitter does, with both ends exclusive.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start_column: usize,
+    pub end_column: usize,
+    pub start_byte: usize,
+    pub end_byte: usize,
+}
+
+impl Span {
+    /// Where `body` is in `source`, given that it starts on the 0-based line
+    /// `start_line`. `None` when it isn't there, e.g. the file has changed
+    /// since it was parsed.
+    pub fn locate(source: &str, start_line: usize, body: &str) -> Option<Span> {
+        if body.is_empty() {
+            return None;
+        }
+        let line_start = line_offset(source, start_line)?;
+        let rest = &source[line_start..];
+        let at = rest.find(body)?;
+        if rest[..at].contains('\n') {
+            return None;
+        }
+        let start_byte = line_start + at;
+        let end_byte = start_byte + body.len();
+        let end_line_start = source[..end_byte].rfind('\n').map_or(0, |i| i + 1);
+        Some(Span {
+            start_column: at,
+            end_column: end_byte - end_line_start,
+            start_byte,
+            end_byte,
+        })
+    }
+}
+
+fn line_offset(source: &str, line: usize) -> Option<usize> {
+    if line == 0 {
+        return Some(0);
+    }
+    source.match_indices('\n').nth(line - 1).map(|(i, _)| i + 1)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EdgeRecord {
+    #[serde(default)]
+    pub repo_id: String,
+    pub kind: String,
+    pub source: String,
+    pub target: String,
+}
+
+/// What the last completed ingest of a repo was made from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RepoRecord {
+    pub repo_id: String,
+    /// The remote, without credentials; empty for a directory on disk.
+    pub url: String,
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    pub commit: Option<String>,
+    /// Unix seconds when the ingest finished.
+    pub ingested_at: u64,
+}
+
+/// How [`crate::retention`] last saw a repo used, as the backend keeps it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RetentionRecord {
+    /// Unix seconds of the last use stored.
+    pub last_access: Option<u64>,
+    /// Pinned through `/retention/pin`.
+    pub pinned: bool,
+}
+
+/// What [`Storage::compact`] did to the backend.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Compaction {
+    /// Bytes the backend took up before and after; `None` where it doesn't
+    /// say.
+    pub before_bytes: Option<u64>,
+    pub after_bytes: Option<u64>,
+    /// The maintenance run, in order.
+    pub steps: Vec<String>,
+}
+
+/// A copy of a repo's graph taken at one point in time; see
+/// [`crate::snapshots`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotRecord {
+    /// The repo id it was copied from, ref included.
+    pub repo_id: String,
+    pub name: String,
+    /// Unix seconds when it was taken.
+    pub created_at: u64,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+/// The graph persistence operations the handlers rely on. Everything written
+/// is tagged with its record's `repo_id`, and reads and deletes are scoped to
+/// one repo, so ingests of different repos never touch each other's subgraph.
+/// `None` for a repo id means every repo.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    fn backend(&self) -> &'static str;
+
+    /// A trivial round trip to the backend, for readiness checks.
+    async fn ping(&self) -> Result<()>;
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()>;
+    /// Edges whose endpoints are not stored in the edge's repo are ignored.
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()>;
+    /// Backends that can write many records in one round trip override these.
+    async fn upsert_nodes(&self, nodes: &[NodeRecord]) -> Result<()> {
+        for node in nodes {
+            self.upsert_node(node).await?;
+        }
+        Ok(())
+    }
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<()> {
+        for edge in edges {
+            self.upsert_edge(edge).await?;
+        }
+        Ok(())
+    }
+    /// Writes out anything a batching layer is still holding; a no-op otherwise.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Starts a transaction whose writes are stored together on `commit`, or
+    /// not at all. Other writes don't wait for it.
+    async fn begin(&self) -> Result<Box<dyn Transaction>>;
+
+    /// Ids of the nodes stored under the path `file`, compared whole; see
+    /// [`stored_paths`] for the paths a repo-relative file is stored under.
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>>;
+    /// Removes the nodes along with every edge touching them.
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize>;
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize>;
+
+    /// Removes the repo's subgraph and its [`RepoRecord`], or everything, and
+    /// returns what is left of it.
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)>;
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)>;
+    /// Goes up with every write to the repo's nodes or edges, and never
+    /// down, so two reads at the same version saw the same graph; 0 for a
+    /// repo never written. Writes made through raw neo4j statements aren't
+    /// counted.
+    async fn graph_version(&self, repo_id: &str) -> Result<u64>;
+    /// The [`Storage::graph_version`] of every repo ever written, by repo id.
+    async fn graph_versions(&self) -> Result<HashMap<String, u64>> {
+        anyhow::bail!("the {} backend lists no graph versions", self.backend())
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>>;
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()>;
+    /// Content hash of each repo-relative file as of the ingest that last
+    /// parsed it. `delete_file` and `clear` drop the hashes with the nodes.
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>>;
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()>;
+    /// Drops the diagnostics stored for `files`, or for the whole repo when
+    /// it's empty, and stores `diagnostics` in their place. `delete_file` and
+    /// `clear` drop them too; the `parse-diagnostics` query lists them.
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()>;
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>>;
+    /// The node with `id` in `repo_id`'s graph.
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>>;
+    /// Stores `repo` in place of the record of the repo's previous ingest.
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()>;
+    /// The record of every repo ingested, ordered by repo id.
+    async fn repos(&self) -> Result<Vec<RepoRecord>>;
+    /// Adds `entry` to the audit log kept in the backend. Entries are never
+    /// changed or removed, not even by a clear of every repo.
+    async fn append_audit(&self, _entry: &AuditEntry) -> Result<()> {
+        anyhow::bail!("the {} backend keeps no audit log", self.backend())
+    }
+    /// The last `limit` entries [`Storage::append_audit`] added, newest first.
+    async fn audit_entries(&self, _limit: usize) -> Result<Vec<AuditEntry>> {
+        anyhow::bail!("the {} backend keeps no audit log", self.backend())
+    }
+
+    /// Rebuilds indexes, refreshes the statistics queries are planned with
+    /// and hands back the space deleted records left behind, however the
+    /// backend does that. Slow on a large graph; callers keep writes away
+    /// while it runs.
+    async fn compact(&self) -> Result<Compaction> {
+        anyhow::bail!("the {} backend can't be compacted", self.backend())
+    }
+
+    /// Copies every node and edge of `from` to `to`, which should hold
+    /// nothing yet, and returns how many of each were copied. File hashes,
+    /// diagnostics and the ingest record stay with `from`.
+    async fn copy_graph(&self, from: &str, to: &str) -> Result<(usize, usize)> {
+        let (mut nodes, mut edges) = self.load_graph(Some(from)).await?;
+        for node in &mut nodes {
+            node.repo_id = to.to_string();
+        }
+        for edge in &mut edges {
+            edge.repo_id = to.to_string();
+        }
+        let mut tx = self.begin().await?;
+        tx.upsert_nodes(&nodes).await?;
+        tx.upsert_edges(&edges).await?;
+        tx.commit().await?;
+        Ok((nodes.len(), edges.len()))
+    }
+    /// Keeps `snapshot` in the list [`Storage::snapshots`] returns,
+    /// replacing one of the same repo and name.
+    async fn record_snapshot(&self, _snapshot: &SnapshotRecord) -> Result<()> {
+        anyhow::bail!("the {} backend keeps no snapshots", self.backend())
+    }
+    /// The snapshots taken of `repo_id`, oldest first.
+    async fn snapshots(&self, _repo_id: &str) -> Result<Vec<SnapshotRecord>> {
+        anyhow::bail!("the {} backend keeps no snapshots", self.backend())
+    }
+    /// Drops a snapshot from the list, leaving its graph where it is; false
+    /// when there was no such snapshot.
+    async fn remove_snapshot(&self, _repo_id: &str, _name: &str) -> Result<bool> {
+        anyhow::bail!("the {} backend keeps no snapshots", self.backend())
+    }
+    /// Keeps `events`, each the JSON of one event of the last ingest of
+    /// `repo_id`, in place of those of the ingest before.
+    async fn record_ingest_log(&self, _repo_id: &str, _events: &[String]) -> Result<()> {
+        anyhow::bail!("the {} backend keeps no ingest logs", self.backend())
+    }
+    /// What [`Storage::record_ingest_log`] kept for `repo_id`, oldest first;
+    /// empty when there's nothing.
+    async fn ingest_log(&self, _repo_id: &str) -> Result<Vec<String>> {
+        anyhow::bail!("the {} backend keeps no ingest logs", self.backend())
+    }
+    /// Notes `repo_id` as used at `at`, unix seconds, unless a later use is
+    /// stored already.
+    async fn record_access(&self, _repo_id: &str, _at: u64) -> Result<()> {
+        anyhow::bail!("the {} backend keeps no access times", self.backend())
+    }
+    /// Pins `repo_id` against [`crate::retention::sweep`], or unpins it.
+    async fn set_pinned(&self, _repo_id: &str, _pinned: bool) -> Result<()> {
+        anyhow::bail!("the {} backend keeps no pins", self.backend())
+    }
+    /// What the two above stored, by repo id. A repo is kept there through
+    /// a clear, so its pin outlasts it.
+    async fn retention(&self) -> Result<HashMap<String, RetentionRecord>> {
+        anyhow::bail!("the {} backend keeps no access times", self.backend())
+    }
+
+    /// Every stored node and edge, for analyses that run outside the database.
+    async fn load_graph(&self, repo_id: Option<&str>)
+        -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)>;
+
+    /// Up to `limit` of the repo's edges with a missing endpoint, ordered by
+    /// source, target and kind, starting after `after`.
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>>;
+    /// Up to `limit` ids, in order after `after`, of nodes with no edges.
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>>;
+    /// Up to `limit` ids, in order after `after`, stored for more than one node.
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>>;
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize>;
+    /// Removes all but one node for each of `ids`, returning how many went.
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize>;
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>>;
+    /// Runs a statement in the backend's native query language.
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>>;
+
+    /// [`Storage::query`], handing rows over as the backend's cursor yields
+    /// them. A failure after the first row ends the stream with its error.
+    /// Backends without a cursor run the whole query first.
+    async fn query_stream(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        let rows = self.query(template, params).await?;
+        Ok(stream::iter(rows.into_iter().map(Ok)).boxed())
+    }
+    /// [`Storage::query_raw`], streamed like [`Storage::query_stream`].
+    async fn query_raw_stream(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        let rows = self.query_raw(statement, params).await?;
+        Ok(stream::iter(rows.into_iter().map(Ok)).boxed())
+    }
+
+    /// [`Storage::query`], stopped in the backend once `cancel` fires rather
+    /// than left to run on, and an error then, whatever the backend made of
+    /// being interrupted. Backends that can't stop a statement only stop
+    /// waiting for it.
+    async fn query_cancellable(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        tokio::select! {
+            rows = self.query(template, params) => rows,
+            _ = cancel.cancelled() => Err(anyhow::anyhow!("query cancelled")),
+        }
+    }
+
+    /// [`Storage::query_raw`], cancelled like [`Storage::query_cancellable`].
+    async fn query_raw_cancellable(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        tokio::select! {
+            rows = self.query_raw(statement, params) => rows,
+            _ = cancel.cancelled() => Err(anyhow::anyhow!("query cancelled")),
+        }
+    }
+}
+
+/// Query rows as [`Storage::query_stream`] yields them.
+pub type RowStream = BoxStream<'static, Result<Value>>;
+
+/// Writes that land together or not at all, from [`Storage::begin`].
+#[async_trait]
+pub trait Transaction: Send {
+    async fn upsert_nodes(&mut self, nodes: &[NodeRecord]) -> Result<()>;
+    /// Edges whose endpoints are neither stored nor written earlier in the
+    /// transaction are ignored, as by [`Storage::upsert_edge`].
+    async fn upsert_edges(&mut self, edges: &[EdgeRecord]) -> Result<()>;
+    /// Removes the nodes with `ids` from `repo_id`'s graph, with their
+    /// edges, as [`Storage::delete_nodes`] does.
+    async fn delete_nodes(&mut self, repo_id: &str, ids: &[String]) -> Result<()>;
+    async fn commit(self: Box<Self>) -> Result<()>;
+    /// Discards every write made through the transaction.
+    async fn rollback(self: Box<Self>) -> Result<()>;
+}
+
+/// Opens the configured backend (`neo4j` or `sqlite`), defaulting to
+/// whichever one was compiled in. Neo4j's address and credentials are still
+/// read from the environment, where `ast` looks for them too; its connection
+/// is re-established when it drops.
+pub async fn connect(config: &Config) -> Result<Arc<dyn Storage>> {
+    open(config, None).await
+}
+
+/// Opens the backend a workspace keeps its graphs in, apart from the
+/// default one and every other workspace's: the Neo4j database named after
+/// it, or a SQLite file next to `sqlite_path`; see [`crate::workspaces`].
+pub async fn connect_workspace(config: &Config, workspace: &str) -> Result<Arc<dyn Storage>> {
+    open(config, Some(workspace)).await
+}
+
+#[cfg_attr(
+    not(any(feature = "neo4j", feature = "sqlite")),
+    allow(unused_variables)
+)]
+async fn open(config: &Config, workspace: Option<&str>) -> Result<Arc<dyn Storage>> {
+    let backend = config.backend.as_deref().unwrap_or(default_backend());
+    match backend {
+        #[cfg(feature = "neo4j")]
+        "neo4j" => {
+            let pool = config.storage_pool();
+            let connector: reconnect::Connector = {
+                let pool = pool.clone();
+                let database = workspace.map(str::to_string);
+                Box::new(move || {
+                    let pool = pool.clone();
+                    let database = database.clone();
+                    Box::pin(async move {
+                        let neo4j =
+                            neo4j::Neo4jStorage::connect_to(&pool, database.as_deref()).await?;
+                        Ok(Arc::new(neo4j) as Arc<dyn Storage>)
+                    })
+                })
+            };
+            let neo4j = Arc::new(reconnect::ReconnectingStorage::connect(connector, &pool).await?);
+            Ok(Arc::new(batch::BatchingStorage::new(
+                neo4j,
+                config.write_batch,
+            )))
+        }
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = match workspace {
+                Some(name) => crate::workspaces::sqlite_path(&config.sqlite_path, name),
+                None => config.sqlite_path.clone(),
+            };
+            let path = path.to_string_lossy();
+            Ok(Arc::new(sqlite::SqliteStorage::open(&path)?))
+        }
+        other => anyhow::bail!(
+            "MESH_BACKEND '{}' is not available in this build (enable the neo4j or sqlite feature)",
+            other
+        ),
+    }
+}
+
+fn default_backend() -> &'static str {
+    if cfg!(feature = "neo4j") {
+        "neo4j"
+    } else {
+        "sqlite"
+    }
+}
+
+/// Flattens a graph built by `ast` into storable records for `repo_id`.
+pub fn records_from_graph(
+    graph: &BTreeMapGraph,
+    repo_id: &str,
+) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let nodes = graph
+        .nodes
+        .values()
+        .map(|node| NodeRecord::from_node(node, repo_id))
+        .collect();
+    let edges = graph
+        .to_array_graph_edges()
+        .iter()
+        .map(|edge| EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind: edge_kind(&edge.edge),
+            source: node_key(
+                &edge.source.node_type,
+                &edge.source.node_data.name,
+                &edge.source.node_data.file,
+                edge.source.node_data.start,
+                edge.source.node_data.verb.as_deref(),
+            ),
+            target: node_key(
+                &edge.target.node_type,
+                &edge.target.node_data.name,
+                &edge.target.node_data.file,
+                edge.target.node_data.start,
+                edge.target.node_data.verb.as_deref(),
+            ),
+        })
+        .collect();
+    (nodes, edges)
+}
+
+/// Records for the nodes of language plugins and custom queries, each
+/// contained by a `File` node. The `File` nodes already in `graph`, those
+/// `ast` made for the languages it parses, are reused, so custom nodes in
+/// those files are filed alongside its own. The custom edges come last, each
+/// between the nodes enclosing what its query captured; one whose ends are
+/// the same node, or can't be found, is dropped. `root` is the checkout the
+/// files were parsed in.
+pub fn records_from_plugins(
+    extraction: &Extraction,
+    repo_id: &str,
+    graph: &[NodeRecord],
+    root: &str,
+) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let files: Vec<&NodeRecord> = graph.iter().filter(|n| n.kind == "File").collect();
+    // (id, stored path) of the `File` node per extracted file
+    let mut containers: HashMap<String, (String, String)> = HashMap::new();
+    let mut nodes: Vec<NodeRecord> = Vec::new();
+    let mut edges = Vec::new();
+    let plugin = extraction.nodes.iter().map(|node| CustomNode {
+        kind: node_kind(&node.node_type),
+        name: node.name.clone(),
+        file: node.file.clone(),
+        body: node.body.clone(),
+        start: node.start,
+        end: node.end,
+        span: node.span,
+    });
+    for node in plugin.chain(extraction.custom.iter().cloned()) {
+        let file = &node.file;
+        let (file_id, path) = containers
+            .entry(file.clone())
+            .or_insert_with(
+                || match files.iter().find(|n| same_file(&n.file, file, root)) {
+                    Some(existing) => (existing.id.clone(), existing.file.clone()),
+                    None => {
+                        let id = node_key(&NodeType::File, file, file, 0, None);
+                        nodes.push(NodeRecord {
+                            repo_id: repo_id.to_string(),
+                            id: id.clone(),
+                            kind: node_kind(&NodeType::File),
+                            name: file.clone(),
+                            file: file.clone(),
+                            start: 0,
+                            end: 0,
+                            body: String::new(),
+                            meta: BTreeMap::new(),
+                            span: None,
+                        });
+                        (id, file.clone())
+                    }
+                },
+            )
+            .clone();
+        let record = NodeRecord {
+            repo_id: repo_id.to_string(),
+            id: kind_key(&node.kind, &node.name, &node.file, node.start, None),
+            kind: node.kind,
+            name: node.name,
+            file: path,
+            start: node.start,
+            end: node.end,
+            body: node.body,
+            meta: BTreeMap::new(),
+            span: Some(node.span),
+        };
+        edges.push(EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind: edge_kind(&EdgeType::Contains),
+            source: file_id,
+            target: record.id.clone(),
+        });
+        nodes.push(record);
+    }
+    let every: Vec<&NodeRecord> = graph.iter().chain(&nodes).collect();
+    let mut seen = HashSet::new();
+    for edge in &extraction.custom_edges {
+        let ends = (
+            resolve_endpoint(&every, &edge.file, root, &edge.source),
+            resolve_endpoint(&every, &edge.file, root, &edge.target),
+        );
+        let (Some(source), Some(target)) = ends else {
+            continue;
+        };
+        if source.id == target.id || !seen.insert((&edge.kind, &source.id, &target.id)) {
+            continue;
+        }
+        edges.push(EdgeRecord {
+            repo_id: repo_id.to_string(),
+            kind: edge.kind.clone(),
+            source: source.id.clone(),
+            target: target.id.clone(),
+        });
+    }
+    (nodes, edges)
+}
+
+/// The node of `file` that most tightly encloses `end`, by its span or, for
+/// a node without one, its lines. When only the file's own node does, the
+/// one node anywhere named by the captured text is taken instead, so an
+/// edge can point into another file, and the file's node when there isn't
+/// exactly one.
+fn resolve_endpoint<'a>(
+    nodes: &[&'a NodeRecord],
+    file: &str,
+    root: &str,
+    end: &Endpoint,
+) -> Option<&'a NodeRecord> {
+    let encloses = |node: &NodeRecord| match node.span {
+        Some(span) => span.start_byte <= end.span.start_byte && end.span.end_byte <= span.end_byte,
+        None => node.start <= end.start && end.end <= node.end,
+    };
+    let innermost = nodes
+        .iter()
+        .copied()
+        .filter(|n| n.kind != "File" && same_file(&n.file, file, root) && encloses(n))
+        .min_by_key(|n| {
+            let bytes = n.span.map_or(usize::MAX, |s| s.end_byte - s.start_byte);
+            (n.end.saturating_sub(n.start), bytes)
+        });
+    if let Some(node) = innermost {
+        return Some(node);
+    }
+    let mut named = nodes
+        .iter()
+        .copied()
+        .filter(|n| n.kind != "File" && n.name == end.text);
+    if let (Some(node), None) = (named.next(), named.next()) {
+        return Some(node);
+    }
+    nodes
+        .iter()
+        .copied()
+        .find(|n| n.kind == "File" && same_file(&n.file, file, root))
+}
+
+impl NodeRecord {
+    pub fn from_node(node: &Node, repo_id: &str) -> Self {
+        let data = &node.node_data;
+        NodeRecord {
+            repo_id: repo_id.to_string(),
+            id: node_key(
+                &node.node_type,
+                &data.name,
+                &data.file,
+                data.start,
+                data.meta.get("verb").map(String::as_str),
+            ),
+            kind: node_kind(&node.node_type),
+            name: data.name.clone(),
+            file: data.file.clone(),
+            start: data.start,
+            end: data.end,
+            body: data.body.clone(),
+            meta: data.meta.clone().into_iter().collect(),
+            // `ast` keeps lines only; see `Span::locate`
+            span: None,
+        }
+    }
+}
+
+pub fn node_kind(node_type: &NodeType) -> String {
+    format!("{:?}", node_type)
+}
+
+/// `ParentOf` -> `PARENT_OF`, matching the relationship types in neo4j.
+pub fn edge_kind(edge_type: &EdgeType) -> String {
+    let mut kind = String::new();
+    for (i, c) in format!("{:?}", edge_type).chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            kind.push('_');
+        }
+        kind.push(c.to_ascii_uppercase());
+    }
+    kind
+}
+
+pub fn node_key(
+    node_type: &NodeType,
+    name: &str,
+    file: &str,
+    start: usize,
+    verb: Option<&str>,
+) -> String {
+    kind_key(&node_kind(node_type), name, file, start, verb)
+}
+
+/// [`node_key`] for a kind given by name, as custom queries give them.
+pub fn kind_key(kind: &str, name: &str, file: &str, start: usize, verb: Option<&str>) -> String {
+    let start = start.to_string();
+    let mut parts = vec![kind, name, file, start.as_str()];
+    if let Some(verb) = verb {
+        parts.push(verb);
+    }
+    parts
+        .iter()
+        .map(|p| {
+            p.to_lowercase()
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// `owner/name` for a repo, from its URL when there is one, else from the last
+/// two components of its checkout path (clones live at `.../owner/name`).
+pub fn repo_id(repo_url: &str, repo_path: &str) -> String {
+    let source = if repo_url.is_empty() {
+        repo_path
+    } else {
+        repo_url
+    };
+    let trimmed = source.trim_end_matches('/').trim_end_matches(".git");
+    let parts: Vec<&str> = trimmed
+        .rsplit(|c| c == '/' || c == ':')
+        .filter(|p| !p.is_empty())
+        .take(2)
+        .collect();
+    match parts.as_slice() {
+        [name, owner] => format!("{}/{}", owner, name),
+        [name] => name.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// The id a repo's graph is stored under for one ref, `owner/name@ref`, so
+/// refs of the same repo don't overwrite each other. The default branch keeps
+/// the bare `owner/name`. Also used to key repo hashes by URL and ref.
+pub fn with_ref(repo_id: &str, git_ref: Option<&str>) -> String {
+    match git_ref {
+        Some(git_ref) => format!("{}@{}", repo_id, git_ref),
+        None => repo_id.to_string(),
+    }
+}
+
+/// The id a snapshot of `repo_id` is stored under, `owner/name#snapshot`
+/// (or `owner/name@ref#snapshot`), which every read takes like a repo id.
+pub fn with_snapshot(repo_id: &str, name: &str) -> String {
+    format!("{}#{}", repo_id, name)
+}
+
+/// Whether a stored path refers to the repo-relative `file`: it's the same
+/// path, or is once `root`, the checkout `ast` parsed it in, is taken off the
+/// front. Paths are compared whole, so `main.rs` is never `src/main.rs`.
+pub fn same_file(stored: &str, file: &str, root: &str) -> bool {
+    stored == file
+        || Path::new(stored)
+            .strip_prefix(root)
+            .is_ok_and(|rest| rest == Path::new(file))
+}
+
+/// The paths the nodes of the repo-relative `file` of the checkout at `root`
+/// can be stored under, as [`Storage::file_node_ids`] and
+/// [`Storage::delete_file`] take them: as given, as an import stores it, or
+/// below `root`, as `ast` writes it, either one as `paths` maps it.
+pub fn stored_paths(root: &str, file: &str, paths: &PathMap) -> Vec<String> {
+    let rooted = format!("{}/{}", root.trim_end_matches('/'), file);
+    let mut stored = vec![file.to_string()];
+    for path in [paths.map(file), rooted.clone(), paths.map(&rooted)] {
+        if !stored.contains(&path) {
+            stored.push(path);
+        }
+    }
+    stored
+}
+
+/// `file` without whatever leads up to the `owner/name` checkout directory.
+pub fn repo_relative<'a>(file: &'a str, checkout: &str) -> &'a str {
+    let root = format!("{}/", checkout);
+    match file.find(&root) {
+        Some(at) => &file[at + root.len()..],
+        None => file,
+    }
+}
@@ -0,0 +1,1072 @@
+use super::reconnect::PoolConfig;
+use super::{
+    Compaction, EdgeRecord, NodeRecord, RepoRecord, RetentionRecord, RowStream, SnapshotRecord,
+    Storage, Transaction,
+};
+use crate::audit::AuditEntry;
+use crate::lang::Diagnostic;
+use crate::query::{scoped_params, QueryTemplate};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use neo4rs::{query, BoltType, ConfigBuilder, Graph, Query, Txn};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+// whole paths only; see `stored_paths` for the ones a file is stored under
+const FILE_MATCH: &str = "n.file = $file";
+// keyset paging on (source, target, kind); `$first` when there is no previous page
+const AFTER_EDGE: &str = "($first OR source > $source
+     OR (source = $source AND (target > $target OR (target = $target AND kind > $kind))))";
+const BUMP_VERSION: &str = "UNWIND $repos AS repo
+     MERGE (v:Mesh_GraphVersion {repo_id: repo})
+     SET v.version = coalesce(v.version, 0) + 1";
+
+pub struct Neo4jStorage {
+    graph: Graph,
+}
+
+impl Neo4jStorage {
+    /// Connects with the same environment as the `ast` connection, keeping
+    /// up to the pool's connections open.
+    pub async fn connect(pool: &PoolConfig) -> Result<Self> {
+        Self::connect_to(pool, None).await
+    }
+
+    /// Connects to the named database rather than the server's default one,
+    /// which needs a Neo4j edition that hosts more than one.
+    pub async fn connect_to(pool: &PoolConfig, database: Option<&str>) -> Result<Self> {
+        let mut config = ConfigBuilder::default()
+            .uri(env_or("NEO4J_URI", "bolt://localhost:7687"))
+            .user(env_or("NEO4J_USER", "neo4j"))
+            .password(env_or("NEO4J_PASSWORD", "testtest"))
+            .max_connections(pool.max_connections);
+        if let Some(database) = database {
+            config = config.db(database);
+        }
+        let config = config.build()?;
+        let graph = Graph::connect(config).await?;
+        graph
+            .run(query(
+                "CREATE INDEX data_bank_node_key_index IF NOT EXISTS FOR (n:Data_Bank) ON (n.node_key)",
+            ))
+            .await?;
+        Ok(Self { graph })
+    }
+
+    async fn rows<T: DeserializeOwned>(&self, q: Query) -> Result<Vec<T>> {
+        let mut rows = self.graph.execute(q).await?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next().await? {
+            out.push(row.to::<T>()?);
+        }
+        Ok(out)
+    }
+
+    /// The `key` column of every row.
+    async fn keys(&self, q: Query) -> Result<Vec<String>> {
+        let mut rows = self.graph.execute(q).await?;
+        let mut keys = Vec::new();
+        while let Some(row) = rows.next().await? {
+            keys.push(row.get::<String>("key")?);
+        }
+        Ok(keys)
+    }
+
+    /// Terminates the transactions running a statement tagged `tag`.
+    async fn terminate(&self, tag: &str) -> Result<()> {
+        let running = self
+            .keys(
+                query(
+                    "SHOW TRANSACTIONS YIELD transactionId, currentQuery
+                     WHERE currentQuery CONTAINS $tag
+                     RETURN transactionId AS key",
+                )
+                .param("tag", tag),
+            )
+            .await?;
+        if !running.is_empty() {
+            self.graph
+                .run(query("TERMINATE TRANSACTIONS $ids").param("ids", running))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn count(&self, q: Query) -> Result<usize> {
+        let mut rows = self.graph.execute(q).await?;
+        match rows.next().await? {
+            Some(row) => Ok(row.get::<i64>("count")? as usize),
+            None => Ok(0),
+        }
+    }
+
+    /// Bumps the [`Storage::graph_version`] of each of `repos`.
+    async fn bump(&self, repos: Vec<String>) -> Result<()> {
+        self.graph
+            .run(query(BUMP_VERSION).param("repos", repos))
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for Neo4jStorage {
+    fn backend(&self) -> &'static str {
+        "neo4j"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.count(query("RETURN 1 AS count")).await?;
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(backend = "neo4j", repo_id = %node.repo_id, file = %node.file, kind = %node.kind)))]
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        let q = format!(
+            "MERGE (n:Data_Bank {{node_key: $id, repo_id: $repo}})
+             SET n:{}, n.name = $name, n.file = $file, n.body = $body,
+                 n.start = $start, n.end = $end
+             SET n += $meta
+             SET n += $span",
+            label(&node.kind)
+        );
+        let meta: HashMap<String, String> = node.meta.clone().into_iter().collect();
+        let q = query(&q)
+            .param("id", node.id.as_str())
+            .param("repo", node.repo_id.as_str())
+            .param("name", node.name.as_str())
+            .param("file", node.file.as_str())
+            .param("body", node.body.as_str())
+            .param("start", node.start as i64)
+            .param("end", node.end as i64)
+            .param("meta", meta)
+            .param("span", span_props(node));
+        self.graph.run(q).await?;
+        self.bump(vec![node.repo_id.clone()]).await
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(backend = "neo4j", repo_id = %edge.repo_id, kind = %edge.kind)))]
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        let q = format!(
+            "MATCH (s:Data_Bank {{node_key: $source, repo_id: $repo}}),
+                   (t:Data_Bank {{node_key: $target, repo_id: $repo}})
+             MERGE (s)-[r:{}]->(t)
+             SET r.repo_id = $repo",
+            label(&edge.kind)
+        );
+        let q = query(&q)
+            .param("source", edge.source.as_str())
+            .param("target", edge.target.as_str())
+            .param("repo", edge.repo_id.as_str());
+        self.graph.run(q).await?;
+        self.bump(vec![edge.repo_id.clone()]).await
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(backend = "neo4j", nodes = nodes.len())))]
+    async fn upsert_nodes(&self, nodes: &[NodeRecord]) -> Result<()> {
+        for q in node_queries(nodes) {
+            self.graph.run(q).await?;
+        }
+        self.bump(repo_ids(nodes, |n| &n.repo_id)).await
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(backend = "neo4j", edges = edges.len())))]
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<()> {
+        for q in edge_queries(edges) {
+            self.graph.run(q).await?;
+        }
+        self.bump(repo_ids(edges, |e| &e.repo_id)).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        Ok(Box::new(Neo4jTransaction {
+            txn: self.graph.start_txn().await?,
+            repos: Vec::new(),
+        }))
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        let q = format!(
+            "MATCH (n:Data_Bank {{repo_id: $repo}}) WHERE {} RETURN n.node_key AS key",
+            FILE_MATCH
+        );
+        let q = query(&q).param("repo", repo_id).param("file", file);
+        self.keys(q).await
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(backend = "neo4j", repo_id, nodes = ids.len())))]
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        let q = "UNWIND $keys AS key
+                 MATCH (n:Data_Bank {node_key: key, repo_id: $repo})
+                 DETACH DELETE n
+                 RETURN count(*) AS count";
+        let q = query(q).param("keys", ids.to_vec()).param("repo", repo_id);
+        let deleted = self.count(q).await?;
+        if deleted > 0 {
+            self.bump(vec![repo_id.to_string()]).await?;
+        }
+        Ok(deleted)
+    }
+
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(skip(self), fields(backend = "neo4j"))
+    )]
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        let q = format!(
+            "MATCH (n) WHERE (n:Mesh_FileHash OR n:Mesh_Diagnostic) AND n.repo_id = $repo
+               AND {} DELETE n",
+            FILE_MATCH
+        );
+        self.graph
+            .run(query(&q).param("repo", repo_id).param("file", file))
+            .await?;
+        let q = format!(
+            "MATCH (n:Data_Bank {{repo_id: $repo}}) WHERE {}
+             DETACH DELETE n RETURN count(*) AS count",
+            FILE_MATCH
+        );
+        let deleted = self
+            .count(query(&q).param("repo", repo_id).param("file", file))
+            .await?;
+        if deleted > 0 {
+            self.bump(vec![repo_id.to_string()]).await?;
+        }
+        Ok(deleted)
+    }
+
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(skip(self), fields(backend = "neo4j"))
+    )]
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        match repo_id {
+            Some(repo_id) => {
+                let q = query("MATCH (n:Data_Bank {repo_id: $repo}) DETACH DELETE n")
+                    .param("repo", repo_id);
+                self.graph.run(q).await?;
+                let q = query(
+                    "MATCH (n) WHERE (n:Mesh_FileHash OR n:Mesh_Diagnostic OR n:Mesh_Ingest)
+                       AND n.repo_id = $repo
+                     DELETE n",
+                )
+                .param("repo", repo_id);
+                self.graph.run(q).await?;
+                self.bump(vec![repo_id.to_string()]).await?;
+            }
+            // the versions stay, so a cleared graph never reuses one, and so
+            // does the audit log
+            None => {
+                self.graph
+                    .run(query(
+                        "MATCH (n)
+                         WHERE NOT (n:Mesh_GraphVersion OR n:Mesh_Audit OR n:Mesh_AuditSeq)
+                         DETACH DELETE n",
+                    ))
+                    .await?;
+                self.graph
+                    .run(query(
+                        "MATCH (v:Mesh_GraphVersion) SET v.version = v.version + 1",
+                    ))
+                    .await?;
+            }
+        }
+        self.graph_size(repo_id).await
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        let Some(repo_id) = repo_id else {
+            let nodes = self
+                .count(query(
+                    "MATCH (n) WHERE NOT n:Mesh_GraphVersion RETURN count(n) AS count",
+                ))
+                .await?;
+            let edges = self
+                .count(query("MATCH ()-[r]->() RETURN count(r) AS count"))
+                .await?;
+            return Ok((nodes, edges));
+        };
+        let nodes = self
+            .count(
+                query("MATCH (n:Data_Bank {repo_id: $repo}) RETURN count(n) AS count")
+                    .param("repo", repo_id),
+            )
+            .await?;
+        let edges = self
+            .count(
+                query("MATCH ()-[r {repo_id: $repo}]->() RETURN count(r) AS count")
+                    .param("repo", repo_id),
+            )
+            .await?;
+        Ok((nodes, edges))
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        let q = query("MATCH (v:Mesh_GraphVersion {repo_id: $repo}) RETURN v.version AS count")
+            .param("repo", repo_id);
+        Ok(self.count(q).await? as u64)
+    }
+
+    async fn graph_versions(&self) -> Result<HashMap<String, u64>> {
+        let q = query("MATCH (v:Mesh_GraphVersion) RETURN v.repo_id AS repo, v.version AS version");
+        let mut rows = self.graph.execute(q).await?;
+        let mut versions = HashMap::new();
+        while let Some(row) = rows.next().await? {
+            versions.insert(
+                row.get::<String>("repo")?,
+                row.get::<i64>("version")? as u64,
+            );
+        }
+        Ok(versions)
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        let q =
+            query("MATCH (r:Mesh_Repo {url: $url}) RETURN r.hash AS hash").param("url", repo_url);
+        let mut rows = self.graph.execute(q).await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.get::<String>("hash")?)),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(skip(self), fields(backend = "neo4j"))
+    )]
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        let q = query("MERGE (r:Mesh_Repo {url: $url}) SET r.hash = $hash")
+            .param("url", repo_url)
+            .param("hash", hash);
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        let q =
+            query("MATCH (h:Mesh_FileHash {repo_id: $repo}) RETURN h.file AS file, h.hash AS hash")
+                .param("repo", repo_id);
+        let mut rows = self.graph.execute(q).await?;
+        let mut hashes = HashMap::new();
+        while let Some(row) = rows.next().await? {
+            hashes.insert(row.get::<String>("file")?, row.get::<String>("hash")?);
+        }
+        Ok(hashes)
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        let rows: Vec<BoltType> = hashes
+            .iter()
+            .map(|(file, hash)| {
+                let row: HashMap<String, BoltType> = HashMap::from([
+                    ("file".to_string(), file.as_str().into()),
+                    ("hash".to_string(), hash.as_str().into()),
+                ]);
+                row.into()
+            })
+            .collect();
+        let q = query(
+            "UNWIND $rows AS row
+             MERGE (h:Mesh_FileHash {repo_id: $repo, file: row.file})
+             SET h.hash = row.hash",
+        )
+        .param("repo", repo_id)
+        .param("rows", rows);
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        let q = query(
+            "MATCH (d:Mesh_Diagnostic {repo_id: $repo})
+             WHERE size($files) = 0 OR d.file IN $files
+             DELETE d",
+        )
+        .param("repo", repo_id)
+        .param("files", files.to_vec());
+        self.graph.run(q).await?;
+        let rows: Vec<BoltType> = diagnostics
+            .iter()
+            .map(|d| {
+                let row: HashMap<String, BoltType> = HashMap::from([
+                    ("file".to_string(), d.file.as_str().into()),
+                    ("start".to_string(), (d.start as i64).into()),
+                    ("end".to_string(), (d.end as i64).into()),
+                    ("severity".to_string(), d.severity.as_str().into()),
+                    ("message".to_string(), d.message.as_str().into()),
+                ]);
+                row.into()
+            })
+            .collect();
+        let q = query(
+            "UNWIND $rows AS row
+             CREATE (d:Mesh_Diagnostic {repo_id: $repo, file: row.file, start: row.start,
+                                        end: row.end, severity: row.severity,
+                                        message: row.message})",
+        )
+        .param("repo", repo_id)
+        .param("rows", rows);
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        let q = query(
+            "MATCH (n:Repository) WHERE n.name = $name
+             RETURN n.node_key AS id, coalesce(n.repo_id, '') AS repo_id,
+                    'Repository' AS kind, n.name AS name,
+                    coalesce(n.file, '') AS file, coalesce(n.start, 0) AS start,
+                    coalesce(n.end, 0) AS end, coalesce(n.body, '') AS body
+             LIMIT 1",
+        )
+        .param("name", name);
+        let mut rows = self.graph.execute(q).await?;
+        match rows.next().await? {
+            Some(row) => Ok(Some(row.to::<NodeRecord>()?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        let nodes: Vec<NodeRecord> = self
+            .rows(
+                query(
+                    "MATCH (n:Data_Bank {node_key: $id}) WHERE n.repo_id = $repo
+                     RETURN n.node_key AS id, n.repo_id AS repo_id,
+                            [l IN labels(n) WHERE l <> 'Data_Bank'][0] AS kind,
+                            n.name AS name, coalesce(n.file, '') AS file,
+                            coalesce(n.start, 0) AS start, coalesce(n.end, 0) AS end,
+                            coalesce(n.body, '') AS body,
+                            CASE WHEN n.start_byte IS NULL THEN null
+                                 ELSE {start_column: n.start_col, end_column: n.end_col,
+                                       start_byte: n.start_byte, end_byte: n.end_byte}
+                            END AS span
+                     LIMIT 1",
+                )
+                .param("id", id)
+                .param("repo", repo_id),
+            )
+            .await?;
+        Ok(nodes.into_iter().next())
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        let q = query(
+            "MERGE (i:Mesh_Ingest {repo_id: $repo})
+             SET i.url = $url, i.ref = $ref, i.commit = $commit, i.ingested_at = $at",
+        )
+        .param("repo", repo.repo_id.as_str())
+        .param("url", repo.url.as_str())
+        .param("ref", repo.git_ref.clone())
+        .param("commit", repo.commit.clone())
+        .param("at", repo.ingested_at as i64);
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.rows(query(
+            "MATCH (i:Mesh_Ingest)
+             RETURN i.repo_id AS repo_id, i.url AS url, i.ref AS `ref`,
+                    i.commit AS commit, i.ingested_at AS ingested_at
+             ORDER BY repo_id",
+        ))
+        .await
+    }
+
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<()> {
+        // the sequence keeps entries of the same second in order
+        let q = query(
+            "MERGE (s:Mesh_AuditSeq) SET s.next = coalesce(s.next, 0) + 1
+             CREATE (a:Mesh_Audit {seq: s.next, timestamp: $at, request_id: $request,
+                                   identity: $identity, operation: $operation,
+                                   repo: $repo, outcome: $outcome})",
+        )
+        .param("at", entry.timestamp as i64)
+        .param("request", entry.request_id.clone())
+        .param("identity", entry.identity.as_str())
+        .param("operation", entry.operation.as_str())
+        .param("repo", entry.repo.clone())
+        .param("outcome", entry.outcome.as_str());
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn audit_entries(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        self.rows(
+            query(
+                "MATCH (a:Mesh_Audit)
+                 RETURN a.timestamp AS timestamp, a.request_id AS request_id,
+                        a.identity AS identity, a.operation AS operation,
+                        a.repo AS repo, a.outcome AS outcome
+                 ORDER BY a.seq DESC LIMIT $limit",
+            )
+            .param("limit", limit as i64),
+        )
+        .await
+    }
+
+    /// Neo4j reclaims the space of deleted records itself as it reuses their
+    /// ids, so this only brings index statistics up to date and drops plans
+    /// cached for the old graph. Nothing is locked meanwhile.
+    async fn compact(&self) -> Result<Compaction> {
+        self.graph
+            .run(query("CALL db.prepareForReplanning()"))
+            .await?;
+        Ok(Compaction {
+            before_bytes: None,
+            after_bytes: None,
+            steps: vec![
+                "resampled the index statistics".to_string(),
+                "cleared the cached query plans".to_string(),
+            ],
+        })
+    }
+
+    async fn record_snapshot(&self, snapshot: &SnapshotRecord) -> Result<()> {
+        let q = query(
+            "MERGE (s:Mesh_Snapshot {repo_id: $repo, name: $name})
+             SET s.created_at = $at, s.nodes = $nodes, s.edges = $edges",
+        )
+        .param("repo", snapshot.repo_id.as_str())
+        .param("name", snapshot.name.as_str())
+        .param("at", snapshot.created_at as i64)
+        .param("nodes", snapshot.nodes as i64)
+        .param("edges", snapshot.edges as i64);
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn snapshots(&self, repo_id: &str) -> Result<Vec<SnapshotRecord>> {
+        self.rows(
+            query(
+                "MATCH (s:Mesh_Snapshot {repo_id: $repo})
+                 RETURN s.repo_id AS repo_id, s.name AS name, s.created_at AS created_at,
+                        s.nodes AS nodes, s.edges AS edges
+                 ORDER BY created_at, name",
+            )
+            .param("repo", repo_id),
+        )
+        .await
+    }
+
+    async fn remove_snapshot(&self, repo_id: &str, name: &str) -> Result<bool> {
+        let q = query(
+            "MATCH (s:Mesh_Snapshot {repo_id: $repo, name: $name})
+             DELETE s RETURN count(*) AS removed",
+        )
+        .param("repo", repo_id)
+        .param("name", name);
+        let mut rows = self.graph.execute(q).await?;
+        let removed = match rows.next().await? {
+            Some(row) => row.get::<i64>("removed")?,
+            None => 0,
+        };
+        Ok(removed > 0)
+    }
+
+    async fn record_ingest_log(&self, repo_id: &str, events: &[String]) -> Result<()> {
+        let q = query(
+            "MERGE (l:Mesh_IngestLog {repo_id: $repo})
+             SET l.events = $events",
+        )
+        .param("repo", repo_id)
+        .param("events", events.to_vec());
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn ingest_log(&self, repo_id: &str) -> Result<Vec<String>> {
+        let q = query("MATCH (l:Mesh_IngestLog {repo_id: $repo}) RETURN l.events AS events")
+            .param("repo", repo_id);
+        let mut rows = self.graph.execute(q).await?;
+        match rows.next().await? {
+            Some(row) => Ok(row.get::<Vec<String>>("events")?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn record_access(&self, repo_id: &str, at: u64) -> Result<()> {
+        let q = query(
+            "MERGE (r:Mesh_Retention {repo_id: $repo})
+             SET r.last_access = CASE WHEN coalesce(r.last_access, 0) > $at
+                                      THEN r.last_access ELSE $at END",
+        )
+        .param("repo", repo_id)
+        .param("at", at as i64);
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn set_pinned(&self, repo_id: &str, pinned: bool) -> Result<()> {
+        let q = query("MERGE (r:Mesh_Retention {repo_id: $repo}) SET r.pinned = $pinned")
+            .param("repo", repo_id)
+            .param("pinned", pinned);
+        self.graph.run(q).await?;
+        Ok(())
+    }
+
+    async fn retention(&self) -> Result<HashMap<String, RetentionRecord>> {
+        let q = query(
+            "MATCH (r:Mesh_Retention)
+             RETURN r.repo_id AS repo, coalesce(r.last_access, -1) AS last_access,
+                    coalesce(r.pinned, false) AS pinned",
+        );
+        let mut rows = self.graph.execute(q).await?;
+        let mut stored = HashMap::new();
+        while let Some(row) = rows.next().await? {
+            let at = row.get::<i64>("last_access")?;
+            let record = RetentionRecord {
+                last_access: (at >= 0).then_some(at as u64),
+                pinned: row.get::<bool>("pinned")?,
+            };
+            stored.insert(row.get::<String>("repo")?, record);
+        }
+        Ok(stored)
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        let nodes = self
+            .rows(
+                query(
+                    "MATCH (n:Data_Bank) WHERE $repo IS NULL OR n.repo_id = $repo
+                     RETURN n.node_key AS id, coalesce(n.repo_id, '') AS repo_id,
+                            [l IN labels(n) WHERE l <> 'Data_Bank'][0] AS kind,
+                            n.name AS name, coalesce(n.file, '') AS file,
+                            coalesce(n.start, 0) AS start, coalesce(n.end, 0) AS end,
+                            coalesce(n.body, '') AS body,
+                            CASE WHEN n.start_byte IS NULL THEN null
+                                 ELSE {start_column: n.start_col, end_column: n.end_col,
+                                       start_byte: n.start_byte, end_byte: n.end_byte}
+                            END AS span",
+                )
+                .param("repo", repo_id),
+            )
+            .await?;
+        let edges = self
+            .rows(
+                query(
+                    "MATCH (s:Data_Bank)-[r]->(t:Data_Bank)
+                     WHERE $repo IS NULL OR r.repo_id = $repo
+                     RETURN coalesce(r.repo_id, '') AS repo_id, type(r) AS kind,
+                            s.node_key AS source, t.node_key AS target",
+                )
+                .param("repo", repo_id),
+            )
+            .await?;
+        Ok((nodes, edges))
+    }
+
+    /// Relationships can't outlive their endpoints in neo4j, so these are the
+    /// repo's edges left pointing at a node that belongs to another repo or is
+    /// no longer part of any graph.
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        let q = format!(
+            "MATCH (s)-[r {{repo_id: $repo}}]->(t)
+             WHERE NOT (s:Data_Bank AND s.repo_id = $repo AND t:Data_Bank AND t.repo_id = $repo)
+             WITH r.repo_id AS repo_id, type(r) AS kind,
+                  coalesce(s.node_key, '') AS source, coalesce(t.node_key, '') AS target
+             WHERE {}
+             RETURN repo_id, kind, source, target
+             ORDER BY source, target, kind LIMIT $limit",
+            AFTER_EDGE
+        );
+        let q = query(&q)
+            .param("repo", repo_id)
+            .param("first", after.is_none())
+            .param("source", after.map_or("", |e| e.source.as_str()))
+            .param("target", after.map_or("", |e| e.target.as_str()))
+            .param("kind", after.map_or("", |e| e.kind.as_str()))
+            .param("limit", limit as i64);
+        self.rows(q).await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let q = query(
+            "MATCH (n:Data_Bank {repo_id: $repo})
+             WHERE ($after IS NULL OR n.node_key > $after) AND NOT (n)--()
+             RETURN n.node_key AS key ORDER BY key LIMIT $limit",
+        )
+        .param("repo", repo_id)
+        .param("after", after)
+        .param("limit", limit as i64);
+        self.keys(q).await
+    }
+
+    /// Concurrent `MERGE`s without a uniqueness constraint can each create
+    /// the node, leaving copies that share a key.
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let q = query(
+            "MATCH (n:Data_Bank {repo_id: $repo})
+             WHERE $after IS NULL OR n.node_key > $after
+             WITH n.node_key AS key, count(n) AS copies WHERE copies > 1
+             RETURN key ORDER BY key LIMIT $limit",
+        )
+        .param("repo", repo_id)
+        .param("after", after)
+        .param("limit", limit as i64);
+        self.keys(q).await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        let rows: Vec<BoltType> = edges
+            .iter()
+            .map(|edge| {
+                let row: HashMap<String, BoltType> = HashMap::from([
+                    ("source".to_string(), edge.source.as_str().into()),
+                    ("target".to_string(), edge.target.as_str().into()),
+                    ("kind".to_string(), edge.kind.as_str().into()),
+                    ("repo".to_string(), edge.repo_id.as_str().into()),
+                ]);
+                row.into()
+            })
+            .collect();
+        let q = "UNWIND $rows AS row
+                 MATCH (s)-[r {repo_id: row.repo}]->(t)
+                 WHERE type(r) = row.kind AND coalesce(s.node_key, '') = row.source
+                   AND coalesce(t.node_key, '') = row.target
+                 DELETE r
+                 RETURN count(*) AS count";
+        let deleted = self.count(query(q).param("rows", rows)).await?;
+        if deleted > 0 {
+            self.bump(repo_ids(edges, |e| &e.repo_id)).await?;
+        }
+        Ok(deleted)
+    }
+
+    /// Keeps the oldest copy of each key. Copies are written by the same
+    /// upserts, so they carry the same edges and dropping the rest with their
+    /// edges loses nothing.
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        let q = "UNWIND $keys AS key
+                 MATCH (n:Data_Bank {node_key: key, repo_id: $repo})
+                 WITH key, n ORDER BY id(n)
+                 WITH key, collect(n) AS copies
+                 UNWIND copies[1..] AS extra
+                 DETACH DELETE extra
+                 RETURN count(*) AS count";
+        let q = query(q).param("keys", ids.to_vec()).param("repo", repo_id);
+        let deleted = self.count(q).await?;
+        if deleted > 0 {
+            self.bump(vec![repo_id.to_string()]).await?;
+        }
+        Ok(deleted)
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.query_raw(template.cypher, &scoped_params(params, None))
+            .await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        let mut q = query(statement);
+        for (key, value) in params {
+            q = bind(q, key, value)?;
+        }
+        self.rows(q).await
+    }
+
+    async fn query_stream(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        self.query_raw_stream(template.cypher, &scoped_params(params, None))
+            .await
+    }
+
+    /// Rows are pulled from the bolt cursor as the stream is read.
+    async fn query_raw_stream(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        let mut q = query(statement);
+        for (key, value) in params {
+            q = bind(q, key, value)?;
+        }
+        let rows = self.graph.execute(q).await?;
+        // `None` once the cursor is done or has failed
+        let rows = stream::unfold(Some(rows), |rows| async move {
+            let mut rows = rows?;
+            match rows.next().await {
+                Ok(Some(row)) => Some((row.to::<Value>().map_err(Into::into), Some(rows))),
+                Ok(None) => None,
+                Err(e) => Some((Err(e.into()), None)),
+            }
+        });
+        Ok(rows.boxed())
+    }
+
+    async fn query_cancellable(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        self.query_raw_cancellable(template.cypher, &scoped_params(params, None), cancel)
+            .await
+    }
+
+    /// The statement is tagged with a comment, so once `cancel` fires the
+    /// transaction running it can be found among the server's and
+    /// terminated there rather than left to run after its connection is
+    /// dropped.
+    async fn query_raw_cancellable(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        let tag = query_tag();
+        let mut q = query(&format!("/* {} */ {}", tag, statement));
+        for (key, value) in params {
+            q = bind(q, key, value)?;
+        }
+        tokio::select! {
+            rows = self.rows(q) => rows,
+            _ = cancel.cancelled() => {
+                if let Err(e) = self.terminate(&tag).await {
+                    warn!("failed to terminate cancelled query {}: {:#}", tag, e);
+                }
+                anyhow::bail!("query cancelled")
+            }
+        }
+    }
+}
+
+/// Unique to one statement of this process, and to this process among
+/// others sharing the server, by when it first tagged one.
+fn query_tag() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    static STARTED: OnceLock<u128> = OnceLock::new();
+    let started = STARTED.get_or_init(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos())
+    });
+    format!(
+        "mesh-query:{}:{}:{}",
+        started,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// A neo4j transaction; nothing it writes is visible until `commit`.
+struct Neo4jTransaction {
+    txn: Txn,
+    /// Repos written to, whose versions are bumped on commit.
+    repos: Vec<String>,
+}
+
+#[async_trait]
+impl Transaction for Neo4jTransaction {
+    async fn upsert_nodes(&mut self, nodes: &[NodeRecord]) -> Result<()> {
+        for q in node_queries(nodes) {
+            self.txn.run(q).await?;
+        }
+        self.repos.extend(repo_ids(nodes, |n| &n.repo_id));
+        Ok(())
+    }
+
+    async fn upsert_edges(&mut self, edges: &[EdgeRecord]) -> Result<()> {
+        for q in edge_queries(edges) {
+            self.txn.run(q).await?;
+        }
+        self.repos.extend(repo_ids(edges, |e| &e.repo_id));
+        Ok(())
+    }
+
+    async fn delete_nodes(&mut self, repo_id: &str, ids: &[String]) -> Result<()> {
+        let q = "UNWIND $keys AS key
+                 MATCH (n:Data_Bank {node_key: key, repo_id: $repo})
+                 DETACH DELETE n";
+        self.txn
+            .run(query(q).param("keys", ids.to_vec()).param("repo", repo_id))
+            .await?;
+        self.repos.push(repo_id.to_string());
+        Ok(())
+    }
+
+    async fn commit(mut self: Box<Self>) -> Result<()> {
+        self.repos.sort();
+        self.repos.dedup();
+        let repos = std::mem::take(&mut self.repos);
+        self.txn
+            .run(query(BUMP_VERSION).param("repos", repos))
+            .await?;
+        Ok(self.txn.commit().await?)
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        Ok(self.txn.rollback().await?)
+    }
+}
+
+/// One `UNWIND` statement per node kind, since labels can't be parameters.
+fn node_queries(nodes: &[NodeRecord]) -> Vec<Query> {
+    by_kind(nodes, |n| n.kind.as_str())
+        .into_iter()
+        .map(|(kind, group)| {
+            let q = format!(
+                "UNWIND $rows AS row
+                 MERGE (n:Data_Bank {{node_key: row.id, repo_id: row.repo}})
+                 SET n:{}, n.name = row.name, n.file = row.file, n.body = row.body,
+                     n.start = row.start, n.end = row.end
+                 SET n += row.meta
+                 SET n += row.span",
+                label(kind)
+            );
+            let rows: Vec<BoltType> = group
+                .iter()
+                .map(|node| {
+                    let meta: HashMap<String, String> = node.meta.clone().into_iter().collect();
+                    let row: HashMap<String, BoltType> = HashMap::from([
+                        ("id".to_string(), node.id.as_str().into()),
+                        ("repo".to_string(), node.repo_id.as_str().into()),
+                        ("name".to_string(), node.name.as_str().into()),
+                        ("file".to_string(), node.file.as_str().into()),
+                        ("body".to_string(), node.body.as_str().into()),
+                        ("start".to_string(), (node.start as i64).into()),
+                        ("end".to_string(), (node.end as i64).into()),
+                        ("meta".to_string(), meta.into()),
+                        ("span".to_string(), span_props(node).into()),
+                    ]);
+                    row.into()
+                })
+                .collect();
+            query(&q).param("rows", rows)
+        })
+        .collect()
+}
+
+/// One `UNWIND` statement per edge kind, as for nodes.
+fn edge_queries(edges: &[EdgeRecord]) -> Vec<Query> {
+    by_kind(edges, |e| e.kind.as_str())
+        .into_iter()
+        .map(|(kind, group)| {
+            let q = format!(
+                "UNWIND $rows AS row
+                 MATCH (s:Data_Bank {{node_key: row.source, repo_id: row.repo}}),
+                       (t:Data_Bank {{node_key: row.target, repo_id: row.repo}})
+                 MERGE (s)-[r:{}]->(t)
+                 SET r.repo_id = row.repo",
+                label(kind)
+            );
+            let rows: Vec<BoltType> = group
+                .iter()
+                .map(|edge| {
+                    let row: HashMap<String, BoltType> = HashMap::from([
+                        ("source".to_string(), edge.source.as_str().into()),
+                        ("target".to_string(), edge.target.as_str().into()),
+                        ("repo".to_string(), edge.repo_id.as_str().into()),
+                    ]);
+                    row.into()
+                })
+                .collect();
+            query(&q).param("rows", rows)
+        })
+        .collect()
+}
+
+/// The span as node properties, named like the sqlite columns; empty when
+/// the node has none, since null parameters can't be bound.
+fn span_props(node: &NodeRecord) -> HashMap<String, i64> {
+    let Some(span) = node.span else {
+        return HashMap::new();
+    };
+    HashMap::from([
+        ("start_col".to_string(), span.start_column as i64),
+        ("end_col".to_string(), span.end_column as i64),
+        ("start_byte".to_string(), span.start_byte as i64),
+        ("end_byte".to_string(), span.end_byte as i64),
+    ])
+}
+
+/// Labels and relationship types can't be parameterized, so keep them to identifier characters.
+/// The distinct repo ids of `records`.
+fn repo_ids<T>(records: &[T], repo_id: impl Fn(&T) -> &String) -> Vec<String> {
+    let mut ids: Vec<String> = records.iter().map(|r| repo_id(r).clone()).collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+fn label(kind: &str) -> String {
+    kind.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect()
+}
+
+/// Records grouped by kind, keeping the order in which each kind first appears.
+fn by_kind<'a, T>(records: &'a [T], kind: impl Fn(&T) -> &str) -> Vec<(&'a str, Vec<&'a T>)> {
+    let mut groups: Vec<(&str, Vec<&T>)> = Vec::new();
+    for record in records {
+        let k = kind(record);
+        match groups.iter_mut().find(|(g, _)| *g == k) {
+            Some((_, group)) => group.push(record),
+            None => groups.push((k, vec![record])),
+        }
+    }
+    groups
+}
+
+fn bind(q: Query, key: &str, value: &Value) -> Result<Query> {
+    Ok(match value {
+        Value::String(s) => q.param(key, s.as_str()),
+        Value::Bool(b) => q.param(key, *b),
+        Value::Number(n) if n.is_i64() || n.is_u64() => {
+            q.param(key, n.as_i64().unwrap_or(i64::MAX))
+        }
+        Value::Number(n) => q.param(key, n.as_f64().unwrap_or_default()),
+        Value::Array(items) if items.iter().all(Value::is_string) => {
+            let items: Vec<String> = items
+                .iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect();
+            q.param(key, items)
+        }
+        other => anyhow::bail!("unsupported value for parameter '{}': {}", key, other),
+    })
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    std::env::var(key)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
@@ -0,0 +1,470 @@
+use super::{
+    Compaction, EdgeRecord, NodeRecord, RepoRecord, RetentionRecord, RowStream, SnapshotRecord,
+    Storage, Transaction,
+};
+use crate::audit::AuditEntry;
+use crate::clone::RetryPolicy;
+use crate::lang::Diagnostic;
+use crate::query::QueryTemplate;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+pub const DEFAULT_POOL_SIZE: usize = 16;
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(200);
+/// What a client is told to wait when a request failed on a backend that
+/// stayed unreachable through every retry.
+pub const UNAVAILABLE_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// How connections to a networked backend are pooled and re-established.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolConfig {
+    /// Connections the backend's driver keeps open at most.
+    pub max_connections: usize,
+    /// How long opening a connection, health check included, may take.
+    pub connect_timeout: Duration,
+    /// Attempts at each operation, and at connecting, when the connection is
+    /// what failed.
+    pub retry: RetryPolicy,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_connections: DEFAULT_POOL_SIZE,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            retry: RetryPolicy {
+                max_attempts: DEFAULT_ATTEMPTS,
+                base_delay: DEFAULT_BASE_DELAY,
+            },
+        }
+    }
+}
+
+/// Opens a new connection to the wrapped backend.
+pub type Connector = Box<dyn Fn() -> BoxFuture<'static, Result<Arc<dyn Storage>>> + Send + Sync>;
+
+struct Connection {
+    /// Goes up with every reconnect, so callers that saw the same failure
+    /// reconnect once between them.
+    generation: u64,
+    storage: Arc<dyn Storage>,
+}
+
+/// Re-establishes the wrapped backend's connection when it drops. An
+/// operation failing for a reason [`is_transient`] accepts is retried, after
+/// a backoff and a fresh connection, up to the policy's attempts; any other
+/// failure, and the last transient one, is returned as it is. Every write is
+/// an upsert or a delete, so running one twice is harmless. A transaction is
+/// only retried as far as `begin`, and a streamed query as far as its first
+/// row, since what came after can't be replayed.
+pub struct ReconnectingStorage {
+    connector: Connector,
+    pool: PoolConfig,
+    current: RwLock<Connection>,
+    // held while a new connection is opened, so a burst of failures opens one
+    reconnecting: tokio::sync::Mutex<()>,
+}
+
+impl ReconnectingStorage {
+    /// Opens the first connection, retried like any other.
+    pub async fn connect(connector: Connector, pool: &PoolConfig) -> Result<Self> {
+        let mut attempt = 1;
+        let storage = loop {
+            match open(&connector, pool.connect_timeout).await {
+                Ok(storage) => break storage,
+                Err(e) if attempt < pool.retry.max_attempts && is_transient(&e) => {
+                    attempt += 1;
+                    warn!(
+                        "failed to connect to storage, retrying (attempt {} of {}): {:#}",
+                        attempt, pool.retry.max_attempts, e
+                    );
+                    tokio::time::sleep(pool.retry.delay(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        Ok(ReconnectingStorage {
+            connector,
+            pool: pool.clone(),
+            current: RwLock::new(Connection {
+                generation: 0,
+                storage,
+            }),
+            reconnecting: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    fn current(&self) -> (u64, Arc<dyn Storage>) {
+        let current = self.current.read().unwrap();
+        (current.generation, current.storage.clone())
+    }
+
+    /// Replaces the connection of `generation`, unless another caller
+    /// already has.
+    async fn reconnect(&self, generation: u64) -> Result<()> {
+        let _guard = self.reconnecting.lock().await;
+        if self.current().0 != generation {
+            return Ok(());
+        }
+        let storage = open(&self.connector, self.pool.connect_timeout).await?;
+        *self.current.write().unwrap() = Connection {
+            generation: generation + 1,
+            storage,
+        };
+        Ok(())
+    }
+
+    async fn run<T, F, Fut>(&self, op: F) -> Result<T>
+    where
+        F: Fn(Arc<dyn Storage>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let max_attempts = self.pool.retry.max_attempts;
+        let mut attempt = 1;
+        loop {
+            let (generation, storage) = self.current();
+            let err = match op(storage).await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            if attempt >= max_attempts || !is_transient(&err) {
+                return Err(err);
+            }
+            attempt += 1;
+            warn!(
+                "storage connection failed, reconnecting (attempt {} of {}): {:#}",
+                attempt, max_attempts, err
+            );
+            tokio::time::sleep(self.pool.retry.delay(attempt)).await;
+            // a failed reconnect leaves the old connection, whose next
+            // failure uses up the attempt
+            if let Err(e) = self.reconnect(generation).await {
+                warn!("failed to reconnect to storage: {:#}", e);
+            }
+        }
+    }
+}
+
+/// A connection from `connector` that has answered a ping.
+async fn open(connector: &Connector, timeout: Duration) -> Result<Arc<dyn Storage>> {
+    tokio::time::timeout(timeout, async {
+        let storage = connector().await?;
+        storage.ping().await?;
+        Ok::<_, anyhow::Error>(storage)
+    })
+    .await
+    .with_context(|| format!("timed out connecting to storage after {:?}", timeout))?
+}
+
+/// Whether `err` came from the connection rather than the operation, so the
+/// same operation may succeed on a new one.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io.kind(),
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::ConnectionRefused
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::NotConnected
+                    | ErrorKind::TimedOut
+                    | ErrorKind::UnexpectedEof
+            );
+        }
+        #[cfg(feature = "neo4j")]
+        if let Some(neo4j) = cause.downcast_ref::<neo4rs::Error>() {
+            return matches!(
+                neo4j,
+                neo4rs::Error::IOError { .. } | neo4rs::Error::ConnectionError
+            );
+        }
+        cause.is::<tokio::time::error::Elapsed>()
+    })
+}
+
+#[async_trait]
+impl Storage for ReconnectingStorage {
+    fn backend(&self) -> &'static str {
+        self.current().1.backend()
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.run(|s| async move { s.ping().await }).await
+    }
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        self.run(|s| async move { s.upsert_node(node).await }).await
+    }
+
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        self.run(|s| async move { s.upsert_edge(edge).await }).await
+    }
+
+    async fn upsert_nodes(&self, nodes: &[NodeRecord]) -> Result<()> {
+        self.run(|s| async move { s.upsert_nodes(nodes).await })
+            .await
+    }
+
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<()> {
+        self.run(|s| async move { s.upsert_edges(edges).await })
+            .await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.run(|s| async move { s.flush().await }).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        self.run(|s| async move { s.begin().await }).await
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        self.run(|s| async move { s.file_node_ids(repo_id, file).await })
+            .await
+    }
+
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.run(|s| async move { s.delete_nodes(repo_id, ids).await })
+            .await
+    }
+
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        self.run(|s| async move { s.delete_file(repo_id, file).await })
+            .await
+    }
+
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.run(|s| async move { s.clear(repo_id).await }).await
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.run(|s| async move { s.graph_size(repo_id).await })
+            .await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        self.run(|s| async move { s.graph_version(repo_id).await })
+            .await
+    }
+
+    async fn graph_versions(&self) -> Result<HashMap<String, u64>> {
+        self.run(|s| async move { s.graph_versions().await }).await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        self.run(|s| async move { s.repo_hash(repo_url).await })
+            .await
+    }
+
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        self.run(|s| async move { s.set_repo_hash(repo_url, hash).await })
+            .await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        self.run(|s| async move { s.file_hashes(repo_id).await })
+            .await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        self.run(|s| async move { s.set_file_hashes(repo_id, hashes).await })
+            .await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.run(|s| async move { s.replace_diagnostics(repo_id, files, diagnostics).await })
+            .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        self.run(|s| async move { s.find_repo(name).await }).await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        self.run(|s| async move { s.node(repo_id, id).await }).await
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        self.run(|s| async move { s.record_ingest(repo).await })
+            .await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.run(|s| async move { s.repos().await }).await
+    }
+
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<()> {
+        self.run(|s| async move { s.append_audit(entry).await })
+            .await
+    }
+
+    async fn audit_entries(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        self.run(|s| async move { s.audit_entries(limit).await })
+            .await
+    }
+
+    async fn compact(&self) -> Result<Compaction> {
+        self.run(|s| async move { s.compact().await }).await
+    }
+
+    async fn copy_graph(&self, from: &str, to: &str) -> Result<(usize, usize)> {
+        self.run(|s| async move { s.copy_graph(from, to).await })
+            .await
+    }
+
+    async fn record_snapshot(&self, snapshot: &SnapshotRecord) -> Result<()> {
+        self.run(|s| async move { s.record_snapshot(snapshot).await })
+            .await
+    }
+
+    async fn snapshots(&self, repo_id: &str) -> Result<Vec<SnapshotRecord>> {
+        self.run(|s| async move { s.snapshots(repo_id).await })
+            .await
+    }
+
+    async fn remove_snapshot(&self, repo_id: &str, name: &str) -> Result<bool> {
+        self.run(|s| async move { s.remove_snapshot(repo_id, name).await })
+            .await
+    }
+
+    async fn record_ingest_log(&self, repo_id: &str, events: &[String]) -> Result<()> {
+        self.run(|s| async move { s.record_ingest_log(repo_id, events).await })
+            .await
+    }
+
+    async fn ingest_log(&self, repo_id: &str) -> Result<Vec<String>> {
+        self.run(|s| async move { s.ingest_log(repo_id).await })
+            .await
+    }
+
+    async fn record_access(&self, repo_id: &str, at: u64) -> Result<()> {
+        self.run(|s| async move { s.record_access(repo_id, at).await })
+            .await
+    }
+
+    async fn set_pinned(&self, repo_id: &str, pinned: bool) -> Result<()> {
+        self.run(|s| async move { s.set_pinned(repo_id, pinned).await })
+            .await
+    }
+
+    async fn retention(&self) -> Result<HashMap<String, RetentionRecord>> {
+        self.run(|s| async move { s.retention().await }).await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        self.run(|s| async move { s.load_graph(repo_id).await })
+            .await
+    }
+
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.run(|s| async move { s.dangling_edges(repo_id, after, limit).await })
+            .await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.run(|s| async move { s.orphan_nodes(repo_id, after, limit).await })
+            .await
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.run(|s| async move { s.duplicate_nodes(repo_id, after, limit).await })
+            .await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        self.run(|s| async move { s.delete_edges(edges).await })
+            .await
+    }
+
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.run(|s| async move { s.dedupe_nodes(repo_id, ids).await })
+            .await
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.run(|s| async move { s.query(template, params).await })
+            .await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        self.run(|s| async move { s.query_raw(statement, params).await })
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        self.run(|s| async move { s.query_stream(template, params).await })
+            .await
+    }
+
+    async fn query_raw_stream(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        self.run(|s| async move { s.query_raw_stream(statement, params).await })
+            .await
+    }
+
+    async fn query_cancellable(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        self.run(|s| async move { s.query_cancellable(template, params, cancel).await })
+            .await
+    }
+
+    async fn query_raw_cancellable(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        self.run(|s| async move { s.query_raw_cancellable(statement, params, cancel).await })
+            .await
+    }
+}
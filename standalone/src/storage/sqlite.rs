@@ -0,0 +1,1221 @@
+use super::{
+    Compaction, EdgeRecord, NodeRecord, RepoRecord, RetentionRecord, RowStream, SnapshotRecord,
+    Span, Storage, Transaction,
+};
+use crate::audit::AuditEntry;
+use crate::lang::Diagnostic;
+use crate::query::{scoped_params, QueryTemplate};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS nodes (
+    repo_id TEXT NOT NULL DEFAULT '',
+    id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    file TEXT NOT NULL,
+    start_line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    meta TEXT NOT NULL DEFAULT '{}',
+    start_col INTEGER,
+    end_col INTEGER,
+    start_byte INTEGER,
+    end_byte INTEGER,
+    PRIMARY KEY (repo_id, id)
+);
+CREATE INDEX IF NOT EXISTS nodes_file ON nodes(repo_id, file);
+CREATE INDEX IF NOT EXISTS nodes_kind_name ON nodes(kind, name);
+CREATE TABLE IF NOT EXISTS edges (
+    repo_id TEXT NOT NULL DEFAULT '',
+    kind TEXT NOT NULL,
+    source TEXT NOT NULL,
+    target TEXT NOT NULL,
+    PRIMARY KEY (repo_id, source, target, kind),
+    FOREIGN KEY (repo_id, source) REFERENCES nodes(repo_id, id) ON DELETE CASCADE,
+    FOREIGN KEY (repo_id, target) REFERENCES nodes(repo_id, id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS edges_target ON edges(repo_id, target);
+CREATE TABLE IF NOT EXISTS repos (
+    url TEXT PRIMARY KEY,
+    hash TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS ingests (
+    repo_id TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    git_ref TEXT,
+    commit_hash TEXT,
+    ingested_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS file_hashes (
+    repo_id TEXT NOT NULL,
+    file TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (repo_id, file)
+);
+CREATE TABLE IF NOT EXISTS diagnostics (
+    repo_id TEXT NOT NULL,
+    file TEXT NOT NULL,
+    start_byte INTEGER NOT NULL,
+    end_byte INTEGER NOT NULL,
+    severity TEXT NOT NULL,
+    message TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS diagnostics_file ON diagnostics(repo_id, file);
+CREATE TABLE IF NOT EXISTS graph_versions (
+    repo_id TEXT PRIMARY KEY,
+    version INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS snapshots (
+    repo_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    nodes INTEGER NOT NULL,
+    edges INTEGER NOT NULL,
+    PRIMARY KEY (repo_id, name)
+);
+CREATE TABLE IF NOT EXISTS retention (
+    repo_id TEXT PRIMARY KEY,
+    last_access INTEGER,
+    pinned INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS ingest_logs (
+    repo_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    PRIMARY KEY (repo_id, seq)
+);
+CREATE TABLE IF NOT EXISTS audit_log (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    at INTEGER NOT NULL,
+    request_id TEXT,
+    identity TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    repo_id TEXT,
+    outcome TEXT NOT NULL
+);
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+";
+
+/// Tables whose every row change bumps the repo's `graph_versions` row, so
+/// writes in transactions and raw statements are counted too.
+const VERSIONED_TABLES: &[&str] = &["nodes", "edges"];
+
+/// Columns added to `nodes` since its first release, for databases created
+/// before them.
+const ADDED_NODE_COLUMNS: &[&str] = &["start_col", "end_col", "start_byte", "end_byte"];
+
+// whole paths only; see `stored_paths` for the ones a file is stored under
+const FILE_MATCH: &str = "file = :file";
+
+/// What the database takes up, free pages included, leaving out the
+/// write-ahead log.
+fn database_bytes(conn: &Connection) -> Result<u64> {
+    let pages: i64 = conn.query_row("PRAGMA page_count", [], |r| r.get(0))?;
+    let size: i64 = conn.query_row("PRAGMA page_size", [], |r| r.get(0))?;
+    Ok((pages * size) as u64)
+}
+
+/// A single-file graph store for local use and CI, where running neo4j is overkill.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("failed to open {}", path))?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")?;
+        conn.execute_batch(SCHEMA)?;
+        migrate(&conn)?;
+        create_version_triggers(&conn)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs blocking sqlite work off the async runtime.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("sqlite connection poisoned"))?;
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+fn insert_node(conn: &Connection, node: &NodeRecord) -> Result<()> {
+    conn.execute(
+        "INSERT INTO nodes (id, kind, name, file, start_line, end_line, body, meta, repo_id,
+                            start_col, end_col, start_byte, end_byte)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+         ON CONFLICT(repo_id, id) DO UPDATE SET
+             kind = excluded.kind, name = excluded.name, file = excluded.file,
+             start_line = excluded.start_line, end_line = excluded.end_line,
+             body = excluded.body, meta = excluded.meta,
+             start_col = excluded.start_col, end_col = excluded.end_col,
+             start_byte = excluded.start_byte, end_byte = excluded.end_byte",
+        params![
+            node.id,
+            node.kind,
+            node.name,
+            node.file,
+            node.start as i64,
+            node.end as i64,
+            node.body,
+            serde_json::to_string(&node.meta)?,
+            node.repo_id,
+            node.span.map(|s| s.start_column as i64),
+            node.span.map(|s| s.end_column as i64),
+            node.span.map(|s| s.start_byte as i64),
+            node.span.map(|s| s.end_byte as i64),
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_edge(conn: &Connection, edge: &EdgeRecord) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO edges (kind, source, target, repo_id)
+         SELECT ?1, ?2, ?3, ?4
+         WHERE EXISTS (SELECT 1 FROM nodes WHERE repo_id = ?4 AND id = ?2)
+           AND EXISTS (SELECT 1 FROM nodes WHERE repo_id = ?4 AND id = ?3)",
+        params![edge.kind, edge.source, edge.target, edge.repo_id],
+    )?;
+    Ok(())
+}
+
+/// Holds a transaction's records until `commit` writes them inside one
+/// sqlite transaction, so the shared connection isn't tied up, and other
+/// writers blocked, while the caller is still producing them. Deletes go
+/// last, after the records are written.
+struct SqliteTransaction {
+    storage: SqliteStorage,
+    nodes: Vec<NodeRecord>,
+    edges: Vec<EdgeRecord>,
+    deleted: Vec<(String, String)>,
+}
+
+#[async_trait]
+impl Transaction for SqliteTransaction {
+    async fn upsert_nodes(&mut self, nodes: &[NodeRecord]) -> Result<()> {
+        self.nodes.extend_from_slice(nodes);
+        Ok(())
+    }
+
+    async fn upsert_edges(&mut self, edges: &[EdgeRecord]) -> Result<()> {
+        self.edges.extend_from_slice(edges);
+        Ok(())
+    }
+
+    async fn delete_nodes(&mut self, repo_id: &str, ids: &[String]) -> Result<()> {
+        self.deleted
+            .extend(ids.iter().map(|id| (repo_id.to_string(), id.clone())));
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        let SqliteTransaction {
+            storage,
+            nodes,
+            edges,
+            deleted,
+        } = *self;
+        storage
+            .with_conn(move |conn| {
+                // dropping it unfinished on an error rolls back
+                let tx = conn.transaction()?;
+                for node in &nodes {
+                    insert_node(&tx, node)?;
+                }
+                for edge in &edges {
+                    insert_edge(&tx, edge)?;
+                }
+                if !deleted.is_empty() {
+                    let mut stmt =
+                        tx.prepare("DELETE FROM nodes WHERE repo_id = ?1 AND id = ?2")?;
+                    for (repo_id, id) in &deleted {
+                        stmt.execute([repo_id, id])?;
+                    }
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    fn backend(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.with_conn(|conn| {
+            conn.query_row("SELECT 1", [], |r| r.get::<_, i64>(0))?;
+            Ok(())
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(backend = "sqlite", repo_id = %node.repo_id, file = %node.file, kind = %node.kind)))]
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        let node = node.clone();
+        self.with_conn(move |conn| insert_node(conn, &node)).await
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(backend = "sqlite", repo_id = %edge.repo_id, kind = %edge.kind)))]
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        let edge = edge.clone();
+        self.with_conn(move |conn| insert_edge(conn, &edge)).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        Ok(Box::new(SqliteTransaction {
+            storage: self.clone(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            deleted: Vec::new(),
+        }))
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        let (repo_id, file) = (repo_id.to_string(), file.to_string());
+        self.with_conn(move |conn| {
+            let sql = format!(
+                "SELECT id FROM nodes WHERE repo_id = :repo AND {}",
+                FILE_MATCH
+            );
+            let mut stmt = conn.prepare(&sql)?;
+            let ids = stmt
+                .query_map(&[(":repo", &repo_id), (":file", &file)], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(ids)
+        })
+        .await
+    }
+
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(backend = "sqlite", repo_id, nodes = ids.len())))]
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        let (repo_id, ids) = (repo_id.to_string(), ids.to_vec());
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let mut deleted = 0;
+            {
+                let mut stmt = tx.prepare("DELETE FROM nodes WHERE repo_id = ?1 AND id = ?2")?;
+                for id in &ids {
+                    deleted += stmt.execute([&repo_id, id])?;
+                }
+            }
+            tx.commit()?;
+            Ok(deleted)
+        })
+        .await
+    }
+
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(skip(self), fields(backend = "sqlite"))
+    )]
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        let (repo_id, file) = (repo_id.to_string(), file.to_string());
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let sql = format!(
+                "DELETE FROM file_hashes WHERE repo_id = :repo AND {}",
+                FILE_MATCH
+            );
+            tx.execute(&sql, &[(":repo", &repo_id), (":file", &file)])?;
+            let sql = format!(
+                "DELETE FROM diagnostics WHERE repo_id = :repo AND {}",
+                FILE_MATCH
+            );
+            tx.execute(&sql, &[(":repo", &repo_id), (":file", &file)])?;
+            let sql = format!("DELETE FROM nodes WHERE repo_id = :repo AND {}", FILE_MATCH);
+            let removed = tx.execute(&sql, &[(":repo", &repo_id), (":file", &file)])?;
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+    }
+
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(skip(self), fields(backend = "sqlite"))
+    )]
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        let repo_id = repo_id.map(str::to_string);
+        let scope = repo_id.clone();
+        self.with_conn(move |conn| {
+            match &scope {
+                Some(repo_id) => {
+                    let tx = conn.transaction()?;
+                    tx.execute("DELETE FROM edges WHERE repo_id = ?1", [repo_id])?;
+                    tx.execute("DELETE FROM nodes WHERE repo_id = ?1", [repo_id])?;
+                    tx.execute("DELETE FROM file_hashes WHERE repo_id = ?1", [repo_id])?;
+                    tx.execute("DELETE FROM diagnostics WHERE repo_id = ?1", [repo_id])?;
+                    tx.execute("DELETE FROM ingests WHERE repo_id = ?1", [repo_id])?;
+                    tx.execute("DELETE FROM ingest_logs WHERE repo_id = ?1", [repo_id])?;
+                    tx.commit()?;
+                }
+                None => conn.execute_batch(
+                    "DELETE FROM edges; DELETE FROM nodes; DELETE FROM repos;
+                     DELETE FROM file_hashes; DELETE FROM diagnostics; DELETE FROM ingests;
+                     DELETE FROM snapshots; DELETE FROM ingest_logs;",
+                )?,
+            }
+            Ok(())
+        })
+        .await?;
+        self.graph_size(repo_id.as_deref()).await
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        let repo_id = repo_id.map(str::to_string);
+        self.with_conn(move |conn| {
+            let count = |table: &str| -> rusqlite::Result<i64> {
+                let sql = format!(
+                    "SELECT count(*) FROM {} WHERE ?1 IS NULL OR repo_id = ?1",
+                    table
+                );
+                conn.query_row(&sql, [&repo_id], |r| r.get(0))
+            };
+            Ok((count("nodes")? as usize, count("edges")? as usize))
+        })
+        .await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        let repo_id = repo_id.to_string();
+        self.with_conn(move |conn| {
+            let version: Option<i64> = conn
+                .query_row(
+                    "SELECT version FROM graph_versions WHERE repo_id = ?1",
+                    [&repo_id],
+                    |r| r.get(0),
+                )
+                .optional()?;
+            Ok(version.unwrap_or(0) as u64)
+        })
+        .await
+    }
+
+    async fn graph_versions(&self) -> Result<HashMap<String, u64>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT repo_id, version FROM graph_versions")?;
+            let versions = stmt
+                .query_map([], |r| {
+                    Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)? as u64))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(versions)
+        })
+        .await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        let url = repo_url.to_string();
+        self.with_conn(move |conn| {
+            Ok(conn
+                .query_row("SELECT hash FROM repos WHERE url = ?1", [url], |r| r.get(0))
+                .optional()?)
+        })
+        .await
+    }
+
+    #[cfg_attr(
+        feature = "otel",
+        tracing::instrument(skip(self), fields(backend = "sqlite"))
+    )]
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        let (url, hash) = (repo_url.to_string(), hash.to_string());
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO repos (url, hash) VALUES (?1, ?2)
+                 ON CONFLICT(url) DO UPDATE SET hash = excluded.hash",
+                params![url, hash],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        let repo_id = repo_id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare("SELECT file, hash FROM file_hashes WHERE repo_id = ?1")?;
+            let rows = stmt.query_map([repo_id], |r| Ok((r.get(0)?, r.get(1)?)))?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        })
+        .await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        let (repo_id, hashes) = (repo_id.to_string(), hashes.to_vec());
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO file_hashes (repo_id, file, hash) VALUES (?1, ?2, ?3)
+                     ON CONFLICT(repo_id, file) DO UPDATE SET hash = excluded.hash",
+                )?;
+                for (file, hash) in &hashes {
+                    stmt.execute(params![repo_id, file, hash])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        let (repo_id, files, diagnostics) =
+            (repo_id.to_string(), files.to_vec(), diagnostics.to_vec());
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            if files.is_empty() {
+                tx.execute("DELETE FROM diagnostics WHERE repo_id = ?1", [&repo_id])?;
+            }
+            for file in &files {
+                tx.execute(
+                    "DELETE FROM diagnostics WHERE repo_id = ?1 AND file = ?2",
+                    params![repo_id, file],
+                )?;
+            }
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO diagnostics (repo_id, file, start_byte, end_byte, severity, message)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?;
+                for d in &diagnostics {
+                    stmt.execute(params![
+                        repo_id,
+                        d.file,
+                        d.start as i64,
+                        d.end as i64,
+                        d.severity.as_str(),
+                        d.message
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        let name = name.to_string();
+        self.with_conn(move |conn| {
+            let node = conn
+                .query_row(
+                    "SELECT id, kind, name, file, start_line, end_line, body, meta, repo_id,
+                            start_col, end_col, start_byte, end_byte
+                     FROM nodes WHERE kind = 'Repository' AND name = ?1 LIMIT 1",
+                    [name],
+                    node_from_row,
+                )
+                .optional()?;
+            Ok(node)
+        })
+        .await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        let (repo_id, id) = (repo_id.to_string(), id.to_string());
+        self.with_conn(move |conn| {
+            let node = conn
+                .query_row(
+                    "SELECT id, kind, name, file, start_line, end_line, body, meta, repo_id,
+                            start_col, end_col, start_byte, end_byte
+                     FROM nodes WHERE repo_id = ?1 AND id = ?2",
+                    [repo_id, id],
+                    node_from_row,
+                )
+                .optional()?;
+            Ok(node)
+        })
+        .await
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        let repo = repo.clone();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO ingests (repo_id, url, git_ref, commit_hash, ingested_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(repo_id) DO UPDATE SET
+                     url = excluded.url, git_ref = excluded.git_ref,
+                     commit_hash = excluded.commit_hash, ingested_at = excluded.ingested_at",
+                params![
+                    repo.repo_id,
+                    repo.url,
+                    repo.git_ref,
+                    repo.commit,
+                    repo.ingested_at as i64
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT repo_id, url, git_ref, commit_hash, ingested_at
+                 FROM ingests ORDER BY repo_id",
+            )?;
+            let rows = stmt.query_map([], |r| {
+                Ok(RepoRecord {
+                    repo_id: r.get(0)?,
+                    url: r.get(1)?,
+                    git_ref: r.get(2)?,
+                    commit: r.get(3)?,
+                    ingested_at: r.get::<_, i64>(4)? as u64,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        })
+        .await
+    }
+
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<()> {
+        let entry = entry.clone();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO audit_log (at, request_id, identity, operation, repo_id, outcome)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    entry.timestamp as i64,
+                    entry.request_id,
+                    entry.identity,
+                    entry.operation,
+                    entry.repo,
+                    entry.outcome
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn audit_entries(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT at, request_id, identity, operation, repo_id, outcome
+                 FROM audit_log ORDER BY seq DESC LIMIT ?1",
+            )?;
+            let rows = stmt.query_map([limit as i64], |r| {
+                Ok(AuditEntry {
+                    timestamp: r.get::<_, i64>(0)? as u64,
+                    request_id: r.get(1)?,
+                    identity: r.get(2)?,
+                    operation: r.get(3)?,
+                    repo: r.get(4)?,
+                    outcome: r.get(5)?,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        })
+        .await
+    }
+
+    /// `VACUUM` rewrites the whole database, so every other read and write
+    /// waits on the connection until it's done.
+    async fn compact(&self) -> Result<Compaction> {
+        self.with_conn(|conn| {
+            let before = database_bytes(conn)?;
+            let mut steps = Vec::new();
+            for (statement, step) in [
+                ("REINDEX", "rebuilt every index"),
+                ("ANALYZE", "refreshed the query planner's statistics"),
+                ("VACUUM", "rewrote the database without its free pages"),
+                (
+                    "PRAGMA wal_checkpoint(TRUNCATE)",
+                    "moved the write-ahead log into the database",
+                ),
+            ] {
+                // the checkpoint returns a row, the others none
+                conn.query_row(statement, [], |_| Ok(()))
+                    .optional()
+                    .with_context(|| format!("{} failed", statement))?;
+                steps.push(step.to_string());
+            }
+            Ok(Compaction {
+                before_bytes: Some(before),
+                after_bytes: Some(database_bytes(conn)?),
+                steps,
+            })
+        })
+        .await
+    }
+
+    /// Copied with one statement per table, without reading the rows out.
+    async fn copy_graph(&self, from: &str, to: &str) -> Result<(usize, usize)> {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let nodes = tx.execute(
+                "INSERT INTO nodes (repo_id, id, kind, name, file, start_line, end_line, body,
+                                    meta, start_col, end_col, start_byte, end_byte)
+                 SELECT ?2, id, kind, name, file, start_line, end_line, body,
+                        meta, start_col, end_col, start_byte, end_byte
+                 FROM nodes WHERE repo_id = ?1",
+                [&from, &to],
+            )?;
+            let edges = tx.execute(
+                "INSERT INTO edges (repo_id, kind, source, target)
+                 SELECT ?2, kind, source, target FROM edges WHERE repo_id = ?1",
+                [&from, &to],
+            )?;
+            tx.commit()?;
+            Ok((nodes, edges))
+        })
+        .await
+    }
+
+    async fn record_snapshot(&self, snapshot: &SnapshotRecord) -> Result<()> {
+        let snapshot = snapshot.clone();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO snapshots (repo_id, name, created_at, nodes, edges)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    snapshot.repo_id,
+                    snapshot.name,
+                    snapshot.created_at as i64,
+                    snapshot.nodes as i64,
+                    snapshot.edges as i64
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn snapshots(&self, repo_id: &str) -> Result<Vec<SnapshotRecord>> {
+        let repo_id = repo_id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT repo_id, name, created_at, nodes, edges FROM snapshots
+                 WHERE repo_id = ?1 ORDER BY created_at, name",
+            )?;
+            let rows = stmt.query_map([&repo_id], |r| {
+                Ok(SnapshotRecord {
+                    repo_id: r.get(0)?,
+                    name: r.get(1)?,
+                    created_at: r.get::<_, i64>(2)? as u64,
+                    nodes: r.get::<_, i64>(3)? as usize,
+                    edges: r.get::<_, i64>(4)? as usize,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        })
+        .await
+    }
+
+    async fn remove_snapshot(&self, repo_id: &str, name: &str) -> Result<bool> {
+        let (repo_id, name) = (repo_id.to_string(), name.to_string());
+        self.with_conn(move |conn| {
+            let removed = conn.execute(
+                "DELETE FROM snapshots WHERE repo_id = ?1 AND name = ?2",
+                [&repo_id, &name],
+            )?;
+            Ok(removed > 0)
+        })
+        .await
+    }
+
+    async fn record_ingest_log(&self, repo_id: &str, events: &[String]) -> Result<()> {
+        let (repo_id, events) = (repo_id.to_string(), events.to_vec());
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM ingest_logs WHERE repo_id = ?1", [&repo_id])?;
+            {
+                let mut stmt = tx
+                    .prepare("INSERT INTO ingest_logs (repo_id, seq, event) VALUES (?1, ?2, ?3)")?;
+                for (seq, event) in events.iter().enumerate() {
+                    stmt.execute(params![repo_id, seq as i64, event])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn ingest_log(&self, repo_id: &str) -> Result<Vec<String>> {
+        let repo_id = repo_id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT event FROM ingest_logs WHERE repo_id = ?1 ORDER BY seq")?;
+            let rows = stmt.query_map([&repo_id], |r| r.get(0))?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        })
+        .await
+    }
+
+    async fn record_access(&self, repo_id: &str, at: u64) -> Result<()> {
+        let repo_id = repo_id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO retention (repo_id, last_access) VALUES (?1, ?2)
+                 ON CONFLICT (repo_id) DO UPDATE
+                 SET last_access = max(coalesce(last_access, 0), excluded.last_access)",
+                params![repo_id, at as i64],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_pinned(&self, repo_id: &str, pinned: bool) -> Result<()> {
+        let repo_id = repo_id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO retention (repo_id, pinned) VALUES (?1, ?2)
+                 ON CONFLICT (repo_id) DO UPDATE SET pinned = excluded.pinned",
+                params![repo_id, pinned],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn retention(&self) -> Result<HashMap<String, RetentionRecord>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT repo_id, last_access, pinned FROM retention")?;
+            let rows = stmt.query_map([], |r| {
+                let record = RetentionRecord {
+                    last_access: r.get::<_, Option<i64>>(1)?.map(|at| at as u64),
+                    pinned: r.get(2)?,
+                };
+                Ok((r.get(0)?, record))
+            })?;
+            Ok(rows.collect::<rusqlite::Result<_>>()?)
+        })
+        .await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        let repo_id = repo_id.map(str::to_string);
+        self.with_conn(move |conn| {
+            let nodes = conn
+                .prepare(
+                    "SELECT id, kind, name, file, start_line, end_line, body, meta, repo_id,
+                            start_col, end_col, start_byte, end_byte
+                     FROM nodes WHERE ?1 IS NULL OR repo_id = ?1",
+                )?
+                .query_map([&repo_id], node_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            let edges = conn
+                .prepare(
+                    "SELECT kind, source, target, repo_id
+                     FROM edges WHERE ?1 IS NULL OR repo_id = ?1",
+                )?
+                .query_map([&repo_id], |row| {
+                    Ok(EdgeRecord {
+                        kind: row.get(0)?,
+                        source: row.get(1)?,
+                        target: row.get(2)?,
+                        repo_id: row.get(3)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok((nodes, edges))
+        })
+        .await
+    }
+
+    /// Foreign keys keep these from being written here, but a database
+    /// written with them off, by an older release or another tool, can have
+    /// some.
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        let repo_id = repo_id.to_string();
+        let first = after.is_none();
+        let (source, target, kind) = after
+            .map(|e| (e.source.clone(), e.target.clone(), e.kind.clone()))
+            .unwrap_or_default();
+        self.with_conn(move |conn| {
+            let edges = conn
+                .prepare(
+                    "SELECT kind, source, target FROM edges e
+                     WHERE repo_id = ?1
+                       AND (?2 OR (source, target, kind) > (?3, ?4, ?5))
+                       AND (NOT EXISTS (SELECT 1 FROM nodes WHERE repo_id = ?1 AND id = e.source)
+                         OR NOT EXISTS (SELECT 1 FROM nodes WHERE repo_id = ?1 AND id = e.target))
+                     ORDER BY source, target, kind LIMIT ?6",
+                )?
+                .query_map(
+                    params![repo_id, first, source, target, kind, limit as i64],
+                    |row| {
+                        Ok(EdgeRecord {
+                            kind: row.get(0)?,
+                            source: row.get(1)?,
+                            target: row.get(2)?,
+                            repo_id: repo_id.clone(),
+                        })
+                    },
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(edges)
+        })
+        .await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let (repo_id, after) = (repo_id.to_string(), after.map(str::to_string));
+        self.with_conn(move |conn| {
+            let ids = conn
+                .prepare(
+                    "SELECT id FROM nodes n
+                     WHERE repo_id = ?1 AND (?2 IS NULL OR id > ?2)
+                       AND NOT EXISTS (SELECT 1 FROM edges WHERE repo_id = ?1 AND source = n.id)
+                       AND NOT EXISTS (SELECT 1 FROM edges WHERE repo_id = ?1 AND target = n.id)
+                     ORDER BY id LIMIT ?3",
+                )?
+                .query_map(params![repo_id, after, limit as i64], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(ids)
+        })
+        .await
+    }
+
+    /// Node ids are the primary key, so there are never any.
+    async fn duplicate_nodes(
+        &self,
+        _repo_id: &str,
+        _after: Option<&str>,
+        _limit: usize,
+    ) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        let edges = edges.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let mut deleted = 0;
+            {
+                let mut stmt = tx.prepare(
+                    "DELETE FROM edges
+                     WHERE repo_id = ?1 AND source = ?2 AND target = ?3 AND kind = ?4",
+                )?;
+                for e in &edges {
+                    deleted += stmt.execute(params![e.repo_id, e.source, e.target, e.kind])?;
+                }
+            }
+            tx.commit()?;
+            Ok(deleted)
+        })
+        .await
+    }
+
+    async fn dedupe_nodes(&self, _repo_id: &str, _ids: &[String]) -> Result<usize> {
+        Ok(0)
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.query_raw(template.sql, &scoped_params(params, None))
+            .await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        let statement = statement.to_string();
+        let params = params.clone();
+        self.with_conn(move |conn| {
+            let mut out = Vec::new();
+            each_row(conn, &statement, &params, |row| {
+                out.push(row);
+                true
+            })?;
+            Ok(out)
+        })
+        .await
+    }
+
+    async fn query_stream(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        self.query_raw_stream(template.sql, &scoped_params(params, None))
+            .await
+    }
+
+    /// Rows are read on a blocking thread at most [`STREAM_BUFFER`] ahead of
+    /// the consumer, which keeps the connection until it has read them all
+    /// or dropped the stream. A dropped stream interrupts the statement, as
+    /// [`Storage::query_raw_cancellable`] does, so one stuck before its next
+    /// row lets go too.
+    async fn query_raw_stream(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+    ) -> Result<RowStream> {
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+        let (handle_tx, handle_rx) = tokio::sync::oneshot::channel();
+        let watcher = {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let Ok(handle) = handle_rx.await else {
+                    return;
+                };
+                tx.closed().await;
+                handle.interrupt();
+            })
+        };
+        let conn = self.conn.clone();
+        let statement = statement.to_string();
+        let params = params.clone();
+        tokio::task::spawn_blocking(move || {
+            let read = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("sqlite connection poisoned"))
+                .and_then(|conn| {
+                    let _ = handle_tx.send(conn.get_interrupt_handle());
+                    // a send only fails once the consumer is gone
+                    let read = each_row(&conn, &statement, &params, |row| {
+                        tx.blocking_send(Ok(row)).is_ok()
+                    });
+                    // stopped while the connection's still held, so the next
+                    // statement on it isn't interrupted
+                    watcher.abort();
+                    read
+                });
+            if let Err(e) = read {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+        let rows = stream::unfold(rx, |mut rx| async move {
+            let row = rx.recv().await?;
+            Some((row, rx))
+        });
+        Ok(rows.boxed())
+    }
+
+    async fn query_cancellable(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        self.query_raw_cancellable(template.sql, &scoped_params(params, None), cancel)
+            .await
+    }
+
+    /// The statement is interrupted through the connection's interrupt
+    /// handle, so the connection is let go of as soon as `cancel` fires,
+    /// even in the middle of a step that yields no rows.
+    async fn query_raw_cancellable(
+        &self,
+        statement: &str,
+        params: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        let (handle_tx, handle_rx) = tokio::sync::oneshot::channel();
+        let watcher = {
+            let cancel = cancel.clone();
+            tokio::spawn(async move {
+                let Ok(handle) = handle_rx.await else {
+                    return;
+                };
+                cancel.cancelled().await;
+                handle.interrupt();
+            })
+        };
+        let statement = statement.to_string();
+        let params = params.clone();
+        let stop = cancel.clone();
+        let rows = self
+            .with_conn(move |conn| {
+                let _ = handle_tx.send(conn.get_interrupt_handle());
+                let mut out = Vec::new();
+                each_row(conn, &statement, &params, |row| {
+                    out.push(row);
+                    !stop.is_cancelled()
+                })?;
+                if stop.is_cancelled() {
+                    anyhow::bail!("query cancelled");
+                }
+                Ok(out)
+            })
+            .await;
+        watcher.abort();
+        rows
+    }
+}
+
+/// Rows a streaming query reads ahead of its consumer.
+pub const STREAM_BUFFER: usize = 64;
+
+/// Runs `statement` and hands `emit` each row as an object keyed by column
+/// name, until the rows run out or `emit` returns false.
+fn each_row(
+    conn: &Connection,
+    statement: &str,
+    params: &Map<String, Value>,
+    mut emit: impl FnMut(Value) -> bool,
+) -> Result<()> {
+    let mut stmt = conn.prepare(statement)?;
+    let bound: Vec<(String, SqlValue)> = params
+        .iter()
+        .map(|(k, v)| (format!(":{}", k), to_sql_value(v)))
+        .collect();
+    let named: Vec<(&str, &dyn ToSql)> = bound
+        .iter()
+        .map(|(k, v)| (k.as_str(), v as &dyn ToSql))
+        .collect();
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query(named.as_slice())?;
+    while let Some(row) = rows.next()? {
+        let mut obj = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            obj.insert(column.clone(), from_sql_value(row.get_ref(i)?));
+        }
+        if !emit(Value::Object(obj)) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn node_from_row(row: &rusqlite::Row) -> rusqlite::Result<NodeRecord> {
+    let meta: String = row.get(7)?;
+    Ok(NodeRecord {
+        repo_id: row.get(8)?,
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        name: row.get(2)?,
+        file: row.get(3)?,
+        start: row.get::<_, i64>(4)? as usize,
+        end: row.get::<_, i64>(5)? as usize,
+        body: row.get(6)?,
+        meta: serde_json::from_str(&meta).unwrap_or_default(),
+        span: span_from_row(row)?,
+    })
+}
+
+/// Set only when all four span columns are.
+fn span_from_row(row: &rusqlite::Row) -> rusqlite::Result<Option<Span>> {
+    let columns: [Option<i64>; 4] = [row.get(9)?, row.get(10)?, row.get(11)?, row.get(12)?];
+    let [Some(start_column), Some(end_column), Some(start_byte), Some(end_byte)] = columns else {
+        return Ok(None);
+    };
+    Ok(Some(Span {
+        start_column: start_column as usize,
+        end_column: end_column as usize,
+        start_byte: start_byte as usize,
+        end_byte: end_byte as usize,
+    }))
+}
+
+fn migrate(conn: &Connection) -> Result<()> {
+    let existing: Vec<String> = conn
+        .prepare("SELECT name FROM pragma_table_info('nodes')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    for column in ADDED_NODE_COLUMNS {
+        if !existing.iter().any(|c| c == column) {
+            conn.execute_batch(&format!("ALTER TABLE nodes ADD COLUMN {} INTEGER", column))?;
+        }
+    }
+    Ok(())
+}
+
+fn create_version_triggers(conn: &Connection) -> Result<()> {
+    for table in VERSIONED_TABLES {
+        for (event, row) in [("insert", "NEW"), ("update", "NEW"), ("delete", "OLD")] {
+            conn.execute_batch(&format!(
+                "CREATE TRIGGER IF NOT EXISTS {table}_{event}_version AFTER {keyword} ON {table}
+                 BEGIN
+                     INSERT INTO graph_versions (repo_id, version) VALUES ({row}.repo_id, 1)
+                     ON CONFLICT (repo_id) DO UPDATE SET version = version + 1;
+                 END;",
+                table = table,
+                event = event,
+                keyword = event.to_uppercase(),
+                row = row,
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+fn to_sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn from_sql_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(t) => Value::from(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => Value::from(b.to_vec()),
+    }
+}
@@ -0,0 +1,228 @@
+use super::{
+    Compaction, EdgeRecord, NodeRecord, RepoRecord, RetentionRecord, SnapshotRecord, Storage,
+    Transaction,
+};
+use crate::audit::AuditEntry;
+use crate::lang::Diagnostic;
+use crate::query::QueryTemplate;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Stands in when no backend could be set up, so the server still answers
+/// `/healthz` and `/readyz` explains why it isn't ready. Every operation fails
+/// with that reason.
+pub struct Unconfigured {
+    reason: String,
+}
+
+impl Unconfigured {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Unconfigured {
+            reason: reason.into(),
+        }
+    }
+
+    fn fail<T>(&self) -> Result<T> {
+        bail!("storage is not configured: {}", self.reason)
+    }
+}
+
+#[async_trait]
+impl Storage for Unconfigured {
+    fn backend(&self) -> &'static str {
+        "unconfigured"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.fail()
+    }
+
+    async fn upsert_node(&self, _node: &NodeRecord) -> Result<()> {
+        self.fail()
+    }
+
+    async fn upsert_edge(&self, _edge: &EdgeRecord) -> Result<()> {
+        self.fail()
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        self.fail()
+    }
+
+    async fn file_node_ids(&self, _repo_id: &str, _file: &str) -> Result<Vec<String>> {
+        self.fail()
+    }
+
+    async fn delete_nodes(&self, _repo_id: &str, _ids: &[String]) -> Result<usize> {
+        self.fail()
+    }
+
+    async fn delete_file(&self, _repo_id: &str, _file: &str) -> Result<usize> {
+        self.fail()
+    }
+
+    async fn clear(&self, _repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.fail()
+    }
+
+    async fn graph_size(&self, _repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.fail()
+    }
+
+    async fn graph_version(&self, _repo_id: &str) -> Result<u64> {
+        self.fail()
+    }
+
+    async fn graph_versions(&self) -> Result<HashMap<String, u64>> {
+        self.fail()
+    }
+
+    async fn repo_hash(&self, _repo_url: &str) -> Result<Option<String>> {
+        self.fail()
+    }
+
+    async fn set_repo_hash(&self, _repo_url: &str, _hash: &str) -> Result<()> {
+        self.fail()
+    }
+
+    async fn file_hashes(&self, _repo_id: &str) -> Result<HashMap<String, String>> {
+        self.fail()
+    }
+
+    async fn set_file_hashes(&self, _repo_id: &str, _hashes: &[(String, String)]) -> Result<()> {
+        self.fail()
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        _repo_id: &str,
+        _files: &[String],
+        _diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.fail()
+    }
+
+    async fn find_repo(&self, _name: &str) -> Result<Option<NodeRecord>> {
+        self.fail()
+    }
+
+    async fn node(&self, _repo_id: &str, _id: &str) -> Result<Option<NodeRecord>> {
+        self.fail()
+    }
+
+    async fn record_ingest(&self, _repo: &RepoRecord) -> Result<()> {
+        self.fail()
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.fail()
+    }
+
+    async fn append_audit(&self, _entry: &AuditEntry) -> Result<()> {
+        self.fail()
+    }
+
+    async fn audit_entries(&self, _limit: usize) -> Result<Vec<AuditEntry>> {
+        self.fail()
+    }
+
+    async fn compact(&self) -> Result<Compaction> {
+        self.fail()
+    }
+
+    async fn copy_graph(&self, _from: &str, _to: &str) -> Result<(usize, usize)> {
+        self.fail()
+    }
+
+    async fn record_snapshot(&self, _snapshot: &SnapshotRecord) -> Result<()> {
+        self.fail()
+    }
+
+    async fn snapshots(&self, _repo_id: &str) -> Result<Vec<SnapshotRecord>> {
+        self.fail()
+    }
+
+    async fn remove_snapshot(&self, _repo_id: &str, _name: &str) -> Result<bool> {
+        self.fail()
+    }
+
+    async fn record_ingest_log(&self, _repo_id: &str, _events: &[String]) -> Result<()> {
+        self.fail()
+    }
+
+    async fn ingest_log(&self, _repo_id: &str) -> Result<Vec<String>> {
+        self.fail()
+    }
+
+    async fn record_access(&self, _repo_id: &str, _at: u64) -> Result<()> {
+        self.fail()
+    }
+
+    async fn set_pinned(&self, _repo_id: &str, _pinned: bool) -> Result<()> {
+        self.fail()
+    }
+
+    async fn retention(&self) -> Result<HashMap<String, RetentionRecord>> {
+        self.fail()
+    }
+
+    async fn load_graph(
+        &self,
+        _repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        self.fail()
+    }
+
+    async fn dangling_edges(
+        &self,
+        _repo_id: &str,
+        _after: Option<&EdgeRecord>,
+        _limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.fail()
+    }
+
+    async fn orphan_nodes(
+        &self,
+        _repo_id: &str,
+        _after: Option<&str>,
+        _limit: usize,
+    ) -> Result<Vec<String>> {
+        self.fail()
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        _repo_id: &str,
+        _after: Option<&str>,
+        _limit: usize,
+    ) -> Result<Vec<String>> {
+        self.fail()
+    }
+
+    async fn delete_edges(&self, _edges: &[EdgeRecord]) -> Result<usize> {
+        self.fail()
+    }
+
+    async fn dedupe_nodes(&self, _repo_id: &str, _ids: &[String]) -> Result<usize> {
+        self.fail()
+    }
+
+    async fn query(
+        &self,
+        _template: &QueryTemplate,
+        _params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.fail()
+    }
+
+    async fn query_raw(
+        &self,
+        _statement: &str,
+        _params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.fail()
+    }
+}
@@ -0,0 +1,302 @@
+use crate::storage::NodeRecord;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Node kinds a call or an import can name in another repo.
+const SYMBOL_KINDS: &[&str] = &["Function", "Class", "Trait", "Interface", "DataModel"];
+/// Source roots and module files that don't show up in a module's name.
+const ROOT_DIRS: &[&str] = &["src", "lib"];
+const MODULE_FILES: &[&str] = &["mod", "lib", "index", "__init__", "main"];
+
+/// The meta key of a symbol's fully qualified name; see [`qualified_name`].
+pub const FQN: &str = "fqn";
+/// Node kinds given a fully qualified name.
+const QUALIFIED_KINDS: &[&str] = &[
+    "Function",
+    "Class",
+    "Trait",
+    "Interface",
+    "DataModel",
+    "Var",
+];
+
+/// How one language writes a fully qualified name.
+struct NameStyle {
+    extensions: &'static [&'static str],
+    /// What the module path starts with, e.g. Rust's `crate`.
+    prefix: Option<&'static str>,
+    /// Between the segments of the module path.
+    module_separator: &'static str,
+    /// Between the module and the symbol, and a class and its members.
+    member_separator: &'static str,
+    /// Directories a module path starts after, like `src`.
+    roots: &'static [&'static str],
+    /// Whether the file is part of the module path; in Go and Java a
+    /// package is a directory.
+    names_file: bool,
+}
+
+const NAME_STYLES: &[NameStyle] = &[
+    NameStyle {
+        extensions: &["rs"],
+        prefix: Some("crate"),
+        module_separator: "::",
+        member_separator: "::",
+        roots: &["src"],
+        names_file: true,
+    },
+    NameStyle {
+        extensions: &["go"],
+        prefix: None,
+        module_separator: "/",
+        member_separator: ".",
+        roots: &[],
+        names_file: false,
+    },
+    NameStyle {
+        extensions: &["java", "kt", "kts", "scala"],
+        prefix: None,
+        module_separator: ".",
+        member_separator: ".",
+        roots: &["java", "kotlin", "scala"],
+        names_file: false,
+    },
+    NameStyle {
+        extensions: &["js", "jsx", "mjs", "cjs", "ts", "tsx"],
+        prefix: None,
+        module_separator: "/",
+        member_separator: ".",
+        roots: &[],
+        names_file: true,
+    },
+    // python, and the dotted default for everything else
+    NameStyle {
+        extensions: &[],
+        prefix: None,
+        module_separator: ".",
+        member_separator: ".",
+        roots: &["src", "lib"],
+        names_file: true,
+    },
+];
+
+/// The fully qualified name of `node` defined in `file`, relative to its
+/// repo, as its language writes one: `crate::text::slugify` in Rust,
+/// `app.models.User.save` in Python, `internal/text.Slugify` in Go,
+/// `com.acme.User.save` in Java and `src/text.slugify` in JavaScript. The
+/// module is the file's path, with the source root and module files like
+/// `mod.rs` or `__init__.py` dropped; a method is named under its class.
+/// `None` for nodes that aren't symbols.
+pub fn qualified_name(file: &str, node: &NodeRecord) -> Option<String> {
+    if !QUALIFIED_KINDS.contains(&node.kind.as_str()) || node.name.is_empty() {
+        return None;
+    }
+    let path = Path::new(file);
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let style = NAME_STYLES
+        .iter()
+        .find(|s| s.extensions.contains(&ext))
+        .unwrap_or(&NAME_STYLES[NAME_STYLES.len() - 1]);
+    let stem = path.with_extension("");
+    let mut parts: Vec<&str> = stem
+        .iter()
+        .filter_map(|p| p.to_str())
+        .filter(|p| !p.is_empty() && *p != "/" && *p != ".")
+        .collect();
+    if !style.names_file || parts.last().is_some_and(|p| MODULE_FILES.contains(p)) {
+        parts.pop();
+    }
+    if let Some(root) = parts.iter().rposition(|p| style.roots.contains(p)) {
+        parts.drain(..=root);
+    }
+    let mut module: Vec<&str> = style.prefix.into_iter().collect();
+    module.extend(parts);
+    let mut symbol: Vec<&str> = Vec::new();
+    if let Some(operand) = node.meta.get("operand").filter(|o| !o.is_empty()) {
+        symbol.push(operand);
+    }
+    symbol.push(&node.name);
+    let symbol = symbol.join(style.member_separator);
+    if module.is_empty() {
+        return Some(symbol);
+    }
+    Some(format!(
+        "{}{}{}",
+        module.join(style.module_separator),
+        style.member_separator,
+        symbol
+    ))
+}
+
+/// A definition another repo can link to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub repo_id: String,
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub file: String,
+}
+
+/// The definitions of every repo in the mesh but one, keyed by fully
+/// qualified name: the repo's name, the path of the file under its source
+/// root, then the symbol's name, e.g. `shared.text.slugify` for `slugify` in
+/// `acme/shared`'s `src/text.rs`. Each suffix of two or more segments is a
+/// key too, since an importer names a module however its package manager
+/// mounts it; a key that several definitions share resolves to none of them.
+#[derive(Default)]
+pub struct SymbolIndex {
+    symbols: HashMap<String, Vec<Symbol>>,
+    modules: HashMap<String, Vec<Symbol>>,
+}
+
+impl SymbolIndex {
+    /// Indexes `nodes`, leaving out those of `repo_id`, whose own names are
+    /// resolved within the repo.
+    pub fn new(nodes: &[NodeRecord], repo_id: &str) -> Self {
+        let mut index = SymbolIndex::default();
+        for node in nodes.iter().filter(|n| n.repo_id != repo_id) {
+            let mut path = module_path(&node.repo_id, &node.file);
+            let table = if node.kind == "File" {
+                &mut index.modules
+            } else if SYMBOL_KINDS.contains(&node.kind.as_str()) {
+                path.push(node.name.clone());
+                &mut index.symbols
+            } else {
+                continue;
+            };
+            let symbol = Symbol {
+                repo_id: node.repo_id.clone(),
+                id: node.id.clone(),
+                name: node.name.clone(),
+                kind: node.kind.clone(),
+                file: node.file.clone(),
+            };
+            for start in 0..path.len().saturating_sub(1) {
+                table
+                    .entry(path[start..].join("."))
+                    .or_default()
+                    .push(symbol.clone());
+            }
+        }
+        index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty() && self.modules.is_empty()
+    }
+
+    /// The definition `name` refers to when imported from `module`, as written
+    /// in the importing file: `shared.text`, `shared::text::slugify` or
+    /// `@acme/shared/text`.
+    pub fn symbol(&self, module: &str, name: &str) -> Option<&Symbol> {
+        let mut path = segments(module)?;
+        if path.last().map(String::as_str) != Some(name) {
+            path.push(name.to_string());
+        }
+        lookup(&self.symbols, &path)
+    }
+
+    /// The file `module` refers to.
+    pub fn module(&self, module: &str) -> Option<&Symbol> {
+        lookup(&self.modules, &segments(module)?)
+    }
+}
+
+/// The [`SymbolIndex`] built for each repo, kept until any graph is written
+/// to, so cross-repo lookups don't reindex every repo on every request.
+#[derive(Default)]
+pub struct IndexCache {
+    built: Mutex<Built>,
+}
+
+#[derive(Default)]
+struct Built {
+    /// [`crate::storage::Storage::graph_versions`] as of the indexes.
+    versions: HashMap<String, u64>,
+    indexes: HashMap<String, Arc<SymbolIndex>>,
+}
+
+impl IndexCache {
+    /// The index of `repo_id` built when the graphs were at `versions`.
+    pub fn get(&self, repo_id: &str, versions: &HashMap<String, u64>) -> Option<Arc<SymbolIndex>> {
+        let built = self.built.lock().unwrap();
+        if &built.versions != versions {
+            return None;
+        }
+        built.indexes.get(repo_id).cloned()
+    }
+
+    /// Keeps `index` for `repo_id`, dropping those built at other versions.
+    pub fn insert(&self, repo_id: &str, versions: HashMap<String, u64>, index: Arc<SymbolIndex>) {
+        let mut built = self.built.lock().unwrap();
+        if built.versions != versions {
+            built.versions = versions;
+            built.indexes.clear();
+        }
+        built.indexes.insert(repo_id.to_string(), index);
+    }
+}
+
+/// The longest suffix of `path` that is a key wins, provided one definition
+/// has it.
+fn lookup<'a>(table: &'a HashMap<String, Vec<Symbol>>, path: &[String]) -> Option<&'a Symbol> {
+    for start in 0..path.len().saturating_sub(1) {
+        match table.get(&path[start..].join(".")).map(Vec::as_slice) {
+            Some([symbol]) => return Some(symbol),
+            Some(_) => return None,
+            None => {}
+        }
+    }
+    None
+}
+
+/// The segments of a module as an importer writes it; `None` for relative
+/// imports, which never leave the repo.
+fn segments(module: &str) -> Option<Vec<String>> {
+    if module.starts_with('.') {
+        return None;
+    }
+    let path: Vec<String> = module
+        .split(['.', '/', ':'])
+        .map(|s| normalize(s.trim_start_matches('@')))
+        .filter(|s| !s.is_empty() && !matches!(s.as_str(), "crate" | "self" | "super"))
+        .collect();
+    (!path.is_empty()).then_some(path)
+}
+
+/// The repo's name followed by the file's path with its extension, source
+/// root and module file names dropped.
+fn module_path(repo_id: &str, file: &str) -> Vec<String> {
+    // `owner/name@ref` is stored as `name`
+    let repo = repo_id.split('@').next().unwrap_or(repo_id);
+    let name = repo.rsplit('/').next().unwrap_or(repo);
+    let file = Path::new(file).with_extension("");
+    let mut parts: Vec<String> = file
+        .iter()
+        .filter_map(|p| p.to_str())
+        .filter(|p| !p.is_empty() && *p != "/")
+        .map(normalize)
+        .collect();
+    if let Some(root) = parts.iter().rposition(|p| ROOT_DIRS.contains(&p.as_str())) {
+        parts.drain(..=root);
+    }
+    if parts
+        .last()
+        .is_some_and(|p| MODULE_FILES.contains(&p.as_str()))
+    {
+        parts.pop();
+    }
+    let mut path = vec![normalize(name)];
+    path.extend(parts);
+    path
+}
+
+/// Package names use `-` where module names can't.
+fn normalize(segment: &str) -> String {
+    segment.replace('-', "_")
+}
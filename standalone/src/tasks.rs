@@ -0,0 +1,241 @@
+//! Task markers in comments, `TODO`, `FIXME` and the like, as `Task` nodes,
+//! so what's been left to do is a query over the graph rather than a grep.
+
+use crate::captures::GRAMMARS;
+use crate::grep::{TextRule, TEXT_RULES};
+use crate::storage::{kind_key, EdgeRecord, NodeRecord};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Query, QueryCursor};
+
+pub const TASK_KIND: &str = "Task";
+pub const HAS_TASK: &str = "HAS_TASK";
+/// The meta keys of a task: the marker it was written with, and who it's
+/// for and what it's tracked by, when the comment says.
+pub const MARKER: &str = "marker";
+pub const ASSIGNEE: &str = "assignee";
+pub const ISSUE: &str = "issue";
+
+pub const DEFAULT_MARKERS: &[&str] = &["TODO", "FIXME", "HACK", "XXX"];
+/// `TODO(alice)` or `TODO: ask @alice`.
+pub const DEFAULT_ASSIGNEE_PATTERN: &str = r"^\(\s*@?([\w.-]+)\s*\)|@([\w.-]+)";
+/// `#123` or `PROJ-123`.
+pub const DEFAULT_ISSUE_PATTERN: &str = r"#(\d+)\b|\b([A-Z][A-Z0-9]+-\d+)\b";
+
+/// Node kinds a task can belong to; outside them it belongs to its file.
+const SYMBOL_KINDS: &[&str] = &[
+    "Function",
+    "Class",
+    "Trait",
+    "Interface",
+    "DataModel",
+    "Endpoint",
+    "UnitTest",
+    "IntegrationTest",
+    "E2eTest",
+];
+
+/// One task marker as written on a line of a comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    pub marker: String,
+    pub assignee: Option<String>,
+    pub issue: Option<String>,
+    /// What follows the marker and its parenthesised assignee or issue.
+    pub text: String,
+}
+
+/// The markers recognised, and the patterns finding a task's assignee and
+/// issue in what follows its marker. A pattern's value is its first capture
+/// group that took part in the match, or the whole match when it has none.
+#[derive(Debug, Clone)]
+pub struct TaskRules {
+    markers: Regex,
+    assignee: Regex,
+    issue: Regex,
+}
+
+impl Default for TaskRules {
+    fn default() -> Self {
+        let markers: Vec<String> = DEFAULT_MARKERS.iter().map(|m| m.to_string()).collect();
+        TaskRules::new(&markers, DEFAULT_ASSIGNEE_PATTERN, DEFAULT_ISSUE_PATTERN).unwrap()
+    }
+}
+
+impl TaskRules {
+    /// Fails when there are no markers or a pattern doesn't compile.
+    pub fn new(markers: &[String], assignee: &str, issue: &str) -> Result<Self> {
+        if markers.is_empty() {
+            bail!("no task markers to look for");
+        }
+        let alternatives: Vec<String> = markers.iter().map(|m| regex::escape(m)).collect();
+        Ok(TaskRules {
+            markers: Regex::new(&format!(r"\b({})\b", alternatives.join("|")))?,
+            assignee: Regex::new(assignee)
+                .with_context(|| format!("invalid task assignee pattern {}", assignee))?,
+            issue: Regex::new(issue)
+                .with_context(|| format!("invalid task issue pattern {}", issue))?,
+        })
+    }
+
+    /// The task marked on `line` of a comment, if there is one. An assignee
+    /// that's also the issue, as in `TODO(PROJ-12)`, is taken as the issue.
+    pub fn parse(&self, line: &str) -> Option<Task> {
+        let marker = self.markers.find(line)?;
+        let rest = &line[marker.end()..];
+        let issue = first_group(&self.issue, rest);
+        let assignee = first_group(&self.assignee, rest).filter(|a| Some(a) != issue.as_ref());
+        let text = match rest.strip_prefix('(').and_then(|r| r.split_once(')')) {
+            Some((_, after)) => after,
+            None => rest,
+        };
+        let text = text
+            .trim_start_matches(|c: char| c == ':' || c == '-' || c.is_whitespace())
+            .trim_end()
+            .trim_end_matches("*/")
+            .trim_end();
+        Some(Task {
+            marker: marker.as_str().to_string(),
+            assignee,
+            issue,
+            text: text.to_string(),
+        })
+    }
+}
+
+fn first_group(pattern: &Regex, text: &str) -> Option<String> {
+    let captures = pattern.captures(text)?;
+    let value = captures
+        .iter()
+        .skip(1)
+        .flatten()
+        .next()
+        .or_else(|| captures.get(0))?;
+    Some(value.as_str().to_string())
+}
+
+/// `Task` nodes for the task markers in comments, with a `HAS_TASK` edge to
+/// each from the innermost symbol holding it, or from its file. A task is
+/// named by its text and keeps its [`MARKER`], [`ASSIGNEE`] and [`ISSUE`] in
+/// its meta. Files are read from the checkout at `root`; one that can't be
+/// read any more, or doesn't parse within `timeout`, gets no tasks.
+pub fn detect(
+    root: &Path,
+    repo_id: &str,
+    nodes: &[NodeRecord],
+    rules: &TaskRules,
+    timeout: Option<Duration>,
+) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let mut by_file: BTreeMap<&str, Vec<&NodeRecord>> = BTreeMap::new();
+    for node in nodes
+        .iter()
+        .filter(|n| n.kind == "File" || SYMBOL_KINDS.contains(&n.kind.as_str()))
+    {
+        by_file.entry(node.file.as_str()).or_default().push(node);
+    }
+    let root_prefix = format!("{}/", root.display());
+    let mut found = (Vec::new(), Vec::new());
+    for (file, symbols) in by_file {
+        if rule_for(file).is_none() {
+            continue;
+        }
+        let rel = file
+            .find(&root_prefix)
+            .map_or(file, |at| &file[at + root_prefix.len()..]);
+        let Ok(decoded) = crate::encoding::read(&root.join(rel)) else {
+            continue;
+        };
+        match extract(file, &decoded.text, repo_id, &symbols, rules, timeout) {
+            Ok((nodes, edges)) => {
+                found.0.extend(nodes);
+                found.1.extend(edges);
+            }
+            Err(e) => tracing::warn!("Failed to read the tasks of {}: {:#}", rel, e),
+        }
+    }
+    found
+}
+
+fn rule_for(file: &str) -> Option<&'static TextRule> {
+    let ext = Path::new(file).extension()?.to_str()?;
+    let grammar = GRAMMARS.iter().find(|g| g.extensions.contains(&ext))?;
+    TEXT_RULES.iter().find(|r| r.grammar == grammar.name)
+}
+
+/// [`detect`] for the one `file`, whose text is `source` and whose symbols,
+/// its `File` node among them, are `symbols`. A task with nothing to belong
+/// to still gets its node.
+pub fn extract(
+    file: &str,
+    source: &str,
+    repo_id: &str,
+    symbols: &[&NodeRecord],
+    rules: &TaskRules,
+    timeout: Option<Duration>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+    let Some(rule) = rule_for(file) else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let grammar = GRAMMARS.iter().find(|g| g.name == rule.grammar).unwrap();
+    let language = grammar.language();
+    let Some(tree) = crate::lang::parse(&language, source, timeout)? else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let query = Query::new(&language, &TextRule::query(rule.comments))
+        .with_context(|| format!("invalid comment query for {}", rule.grammar))?;
+
+    let bytes = source.as_bytes();
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&query, tree.root_node(), bytes);
+    while let Some(m) = matches.next() {
+        let Some(comment) = m.captures.first().map(|c| c.node) else {
+            continue;
+        };
+        let first = comment.start_position().row;
+        for (i, line) in comment.utf8_text(bytes)?.lines().enumerate() {
+            let Some(task) = rules.parse(line) else {
+                continue;
+            };
+            let start = first + i;
+            let id = kind_key(TASK_KIND, &task.marker, file, start, None);
+            let mut meta = BTreeMap::from([(MARKER.to_string(), task.marker.clone())]);
+            if let Some(assignee) = task.assignee {
+                meta.insert(ASSIGNEE.to_string(), assignee);
+            }
+            if let Some(issue) = task.issue {
+                meta.insert(ISSUE.to_string(), issue);
+            }
+            if let Some(symbol) = crate::sql::enclosing(symbols, start) {
+                edges.push(EdgeRecord {
+                    repo_id: repo_id.to_string(),
+                    kind: HAS_TASK.to_string(),
+                    source: symbol.id.clone(),
+                    target: id.clone(),
+                });
+            }
+            nodes.push(NodeRecord {
+                repo_id: repo_id.to_string(),
+                id,
+                kind: TASK_KIND.to_string(),
+                name: if task.text.is_empty() {
+                    task.marker
+                } else {
+                    task.text
+                },
+                file: file.to_string(),
+                start,
+                end: start,
+                body: line.trim().to_string(),
+                meta,
+                span: None,
+            });
+        }
+    }
+    Ok((nodes, edges))
+}
@@ -0,0 +1,99 @@
+use crate::server::HttpConfig;
+use anyhow::{Context, Result};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use std::future::Future;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// The certificate chain and private key at `cert` and `key`, both PEM.
+/// Fails naming the file that is missing or holds nothing usable.
+pub async fn load(cert: &Path, key: &Path) -> Result<RustlsConfig> {
+    // one provider for the process, whatever else rustls was built with
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let (cert_pem, key_pem) = read(cert, key).await?;
+    RustlsConfig::from_pem(cert_pem, key_pem)
+        .await
+        .with_context(|| {
+            format!(
+                "invalid TLS certificate {} or key {}",
+                cert.display(),
+                key.display()
+            )
+        })
+}
+
+async fn read(cert: &Path, key: &Path) -> Result<(Vec<u8>, Vec<u8>)> {
+    let cert_pem = tokio::fs::read(cert)
+        .await
+        .with_context(|| format!("failed to read TLS certificate {}", cert.display()))?;
+    let key_pem = tokio::fs::read(key)
+        .await
+        .with_context(|| format!("failed to read TLS key {}", key.display()))?;
+    Ok((cert_pem, key_pem))
+}
+
+/// Loads the certificate and key again on every SIGHUP, so a renewed
+/// certificate is served without a restart. One that fails to load is
+/// logged and the one before kept.
+pub fn reload_on_sighup(config: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                error!(
+                    "failed to listen for SIGHUP, TLS certificates won't reload: {}",
+                    e
+                );
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            let reloaded = match read(&cert, &key).await {
+                Ok((cert_pem, key_pem)) => config
+                    .reload_from_pem(cert_pem, key_pem)
+                    .await
+                    .context("invalid TLS certificate or key"),
+                Err(e) => Err(e),
+            };
+            match reloaded {
+                Ok(()) => info!("reloaded TLS certificate {}", cert.display()),
+                Err(e) => error!("kept the previous TLS certificate: {:#}", e),
+            }
+        }
+    });
+    #[cfg(not(unix))]
+    let _ = (config, cert, key);
+}
+
+/// Serves `app` over HTTPS on `listener` until `signal` resolves, then
+/// gives in-flight requests `grace` to finish, as the plain HTTP server does.
+/// HTTP/2 is agreed on through ALPN, when `http` allows it.
+pub async fn serve(
+    listener: TcpListener,
+    config: RustlsConfig,
+    app: Router,
+    http: HttpConfig,
+    signal: impl Future<Output = ()> + Send + 'static,
+    grace: Duration,
+) -> std::io::Result<()> {
+    let handle = Handle::new();
+    let shutdown = handle.clone();
+    tokio::spawn(async move {
+        signal.await;
+        shutdown.graceful_shutdown(Some(grace));
+    });
+    listener.set_nonblocking(true)?;
+    let mut server = axum_server::from_tcp_rustls(listener, config);
+    http.configure(server.http_builder());
+    server
+        .handle(handle)
+        // the peer address keys the per-client rate limit
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+}
@@ -1,17 +1,85 @@
+use crate::analysis::{
+    Cycle, FileDiff, RelatedFile, RelatednessWeights, SymbolChange, Unreferenced,
+};
+use crate::audit::AuditEntry;
+use crate::clone::CloneError;
+use crate::export::{Direction, MermaidGraph};
+use crate::grep::GrepHit;
+use crate::ingests::IngestStatus;
+use crate::lang::Diagnostic;
+use crate::neighborhood::Subgraph;
+use crate::search::SearchHit;
+use crate::snippet::Snippet;
+use crate::stats::RepoStats;
+use crate::storage::{reconnect, Compaction, NodeRecord, SnapshotRecord};
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
+    Extension, Json,
 };
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-pub type Result<T> = std::result::Result<T, AppError>;
+pub type Result<T> = std::result::Result<T, MeshError>;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct ProcessBody {
     pub repo_url: Option<String>,
+    /// A checkout on disk, under `MESH_ALLOWED_ROOTS` or the clone directory.
     pub repo_path: Option<String>,
     pub username: Option<String>,
+    /// A token to clone with over HTTPS; never logged or echoed back.
     pub pat: Option<String>,
+    /// Names one of the server's `git_credentials` to clone with instead.
+    #[serde(default)]
+    pub credential: Option<String>,
+    /// Languages to parse, e.g. `["rust", "python"]`; every supported language when empty.
+    #[serde(default)]
+    pub include_langs: Vec<String>,
+    /// Globs over repo-relative paths to skip, e.g. `vendor/**`.
+    #[serde(default)]
+    pub exclude_globs: Vec<String>,
+    /// A directory of the repo, e.g. `services/foo`, to ingest instead of all
+    /// of it. Paths are still stored relative to the repo root, so a later
+    /// ingest of more of it links up with this one. `/ingest` still replaces
+    /// the repo's whole graph with it.
+    #[serde(default)]
+    pub subdir: Option<String>,
+    /// Node kinds to store, e.g. `["Function"]`, with only the edges between
+    /// them; every kind when empty. Files stored this way count as parsed, so
+    /// `force` is needed to store their other kinds later.
+    #[serde(default)]
+    pub node_kinds: Vec<String>,
+    /// Branch, tag or commit to ingest instead of the default branch; stored
+    /// as `owner/name@ref` alongside the other refs. Needs `repo_url`.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Re-parse every file, even those whose content hash is unchanged.
+    #[serde(default)]
+    pub force: bool,
+    /// Parse as usual but write nothing, reporting what would have been
+    /// stored as `dry_run` status updates.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Removes what an ingest of the repo that was cut short had stored,
+    /// rather than resuming it from its checkpoint.
+    #[serde(default)]
+    pub discard_interrupted: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct IngestPathBody {
+    /// Absolute directory under one of `MESH_ALLOWED_ROOTS`; need not be a git checkout.
+    pub path: String,
+    /// Defaults to the last two components of `path`.
+    #[serde(default)]
+    pub repo_id: Option<String>,
+}
+/// Query string of an `application/gzip` upload to `/ingest`; a multipart
+/// upload sends `repo_id` as a form field instead.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ArchiveParams {
+    /// `owner/name` to store the uploaded tree under.
+    pub repo_id: Option<String>,
 }
 #[derive(Serialize, Deserialize)]
 pub struct ProcessResponse {
@@ -20,34 +88,953 @@ pub struct ProcessResponse {
     pub nodes: usize,
     pub edges: usize,
 }
+#[derive(Serialize, Deserialize, Default)]
+pub struct ClearBody {
+    /// `owner/name`; the whole graph when omitted.
+    #[serde(default)]
+    pub repo_id: Option<String>,
+    /// Clears just this ref of `repo_id`.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// `repo_id` again, or `ALL` for the whole graph; otherwise `token` is needed.
+    #[serde(default)]
+    pub confirm: Option<String>,
+    /// From `GET /clear` with the same `repo_id` and `ref`.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Waits for an ingest of the repo to finish, rather than failing with a
+    /// 409 while one runs.
+    #[serde(default)]
+    pub wait: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ClearTokenQuery {
+    /// `owner/name`; the whole graph when omitted.
+    #[serde(default)]
+    pub repo_id: Option<String>,
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ClearTokenResponse {
+    pub token: String,
+    /// What a clear with the token removes; everything when omitted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo_id: Option<String>,
+    pub expires_in_secs: u64,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ValidateBody {
+    /// `owner/name` of the graph to check.
+    pub repo_id: String,
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Removes the dangling edges and duplicate nodes found.
+    #[serde(default)]
+    pub repair: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CancelBody {
+    /// `owner/name` of the running ingest.
+    pub repo_id: String,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CancelResponse {
+    pub status: String,
+    pub repo_id: String,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ScheduleBody {
+    /// The repo as `/process` takes it.
+    #[serde(flatten)]
+    pub repo: ProcessBody,
+    /// Seconds between re-ingests; needed unless `remove` is set.
+    pub interval_secs: Option<u64>,
+    /// Stop re-ingesting the repo instead.
+    #[serde(default)]
+    pub remove: bool,
+}
+/// One repo of a `/register` manifest.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ManifestEntry {
+    pub url: String,
+    /// Branch, tag or commit to ingest instead of the default branch.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Names one of the server's `git_credentials` to clone with.
+    #[serde(default)]
+    pub credential: Option<String>,
+    /// As for `/process`.
+    #[serde(default)]
+    pub include_langs: Vec<String>,
+    #[serde(default)]
+    pub exclude_globs: Vec<String>,
+    #[serde(default)]
+    pub subdir: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct BulkRegisterBody {
+    pub repos: Vec<ManifestEntry>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RegisteredRepo {
+    /// The entry's url, without any credentials in it.
+    pub url: String,
+    /// Empty when the url names no repo.
+    pub repo_id: String,
+    /// `queued`, or `rejected` with `error` saying why.
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkRegisterResponse {
+    /// How many of `repos` were queued.
+    pub queued: usize,
+    /// Each entry of the manifest, in its order.
+    pub repos: Vec<RegisteredRepo>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ScheduleResponse {
+    /// `scheduled` or `unscheduled`.
+    pub status: String,
+    pub repo_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct RepoSummary {
+    pub repo_id: String,
+    /// The remote, without credentials; `None` for a directory on disk.
+    pub url: Option<String>,
+    /// The branch, tag or commit last ingested.
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    pub commit: Option<String>,
+    /// Unix seconds when the last ingest finished; `None` until one has.
+    pub last_ingested_at: Option<u64>,
+    pub nodes: usize,
+    pub edges: usize,
+    pub status: IngestStatus,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ReposResponse {
+    pub repos: Vec<RepoSummary>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct RepoLogParams {
+    /// `owner/name` of the repo, `@ref` included for a ref other than the
+    /// default branch.
+    pub repo: String,
+}
+#[derive(Serialize, Deserialize)]
+pub struct RepoLogResponse {
+    pub repo: String,
+    pub status: IngestStatus,
+    /// The events of the repo's last ingest, oldest first, each as `/events`
+    /// sent it along with its `id`.
+    pub events: Vec<serde_json::Value>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ProcessFileBody {
+    #[serde(flatten)]
+    pub repo: ProcessBody,
+    /// Path of the changed file, relative to the repo root.
+    pub file: String,
+    #[serde(default)]
+    pub deleted: bool,
+    /// The file's text as an editor has it, saved or not, to parse instead
+    /// of what's on disk. It stands for the file until the next ingest from
+    /// disk.
+    #[serde(default)]
+    pub content: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ProcessFileResponse {
+    pub status: String,
+    pub file: String,
+    pub added: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+#[derive(Serialize, Deserialize)]
+pub struct QueryBody {
+    /// Key of a built-in template, e.g. `callers-of-function`.
+    pub query: Option<String>,
+    /// Raw Cypher, only honored when `MESH_ALLOW_RAW_CYPHER=true`.
+    pub cypher: Option<String>,
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+    /// Only rows whose `kind` is one of these, e.g. `["Function"]`.
+    #[serde(default)]
+    pub node_kinds: Vec<String>,
+    /// Page size; results are paged whenever this or `cursor` is set.
+    pub limit: Option<usize>,
+    /// `next_cursor` from the previous page.
+    pub cursor: Option<String>,
+    /// Scopes a template to one repo's graph, `owner/name`.
+    pub repo: Option<String>,
+    /// Scopes it further to one ingested ref of `repo`.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Only rows in the files of this sub-project of `repo`, by its root
+    /// directory, e.g. `crates/core`, or `.` for the one at the top.
+    pub project: Option<String>,
+    /// Gives up on the query sooner than the server's `MESH_QUERY_TIMEOUT_MS`;
+    /// never later.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct QueryResponse {
+    pub query: String,
+    pub rows: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CallGraphBody {
+    /// `owner/name`; all repos when omitted.
+    pub repo: Option<String>,
+    /// Limit the result to functions reachable from this symbol.
+    pub root: Option<String>,
+    /// Also link calls `repo` leaves unresolved to definitions in the other
+    /// ingested repos; needs `repo`.
+    #[serde(default)]
+    pub cross_repo: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ReferencesBody {
+    /// Name of the function, e.g. `render`.
+    pub name: String,
+    /// `owner/name`; all repos when omitted.
+    pub repo: Option<String>,
+    /// Only the usages of the definition with this node id, when the name has several.
+    pub id: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ReferencesResponse {
+    pub name: String,
+    /// One entry per definition of that name, with its callers.
+    pub definitions: Vec<crate::callgraph::Usages>,
+}
+/// Names a function for `/callers` or `/callees`.
+#[derive(Serialize, Deserialize)]
+pub struct CallChainBody {
+    /// Name of the function, e.g. `render`.
+    pub name: String,
+    /// `owner/name`; all repos when omitted.
+    pub repo: Option<String>,
+    /// Only the definition with this node id, when the name has several.
+    pub id: Option<String>,
+    /// Calls to go out from it; `1` by default, at most `10`.
+    pub depth: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CallChainResponse {
+    pub name: String,
+    pub depth: usize,
+    /// One entry per definition of that name.
+    pub definitions: Vec<crate::callgraph::CallChain>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DependenciesBody {
+    /// `owner/name` the repo was ingested as.
+    pub repo: String,
+    /// Also look for the modules `repo` imports in the other ingested repos,
+    /// listing those found under `linked` rather than as dependencies.
+    #[serde(default)]
+    pub cross_repo: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DependenciesResponse {
+    pub repo: String,
+    /// Most used first.
+    pub dependencies: Vec<crate::imports::Dependency>,
+    /// Imports of another repo's files; only with `cross_repo`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linked: Vec<crate::imports::ResolvedImport>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ReanalyzeBody {
+    /// `owner/name` the repo was ingested as.
+    pub repo: String,
+    /// The ref of `repo` to reanalyze; the graph ingested without a ref when
+    /// omitted.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ReanalyzeResponse {
+    pub repo_id: String,
+    /// Derived edges stored before that aren't derived any more.
+    pub removed: usize,
+    /// Derived edges that weren't stored before.
+    pub added: usize,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ResolveBody {
+    /// As stored on the symbol, e.g. `crate::text::slugify` or
+    /// `app.models.User.save`.
+    pub fqn: String,
+    /// `owner/name`; all repos when omitted.
+    pub repo: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ResolveResponse {
+    pub fqn: String,
+    pub nodes: Vec<NodeRecord>,
+}
+/// Names a function for `/tests-for`, or a test for `/covered-by`.
+#[derive(Serialize, Deserialize)]
+pub struct CoverageBody {
+    /// Name of the function or test, e.g. `parse` or `test_parse`.
+    pub name: String,
+    /// `owner/name`; all repos when omitted.
+    pub repo: Option<String>,
+    /// Only the definition with this node id, when the name has several.
+    pub id: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct TestsForResponse {
+    pub name: String,
+    /// One entry per definition of that name, with the tests reaching it.
+    pub definitions: Vec<crate::coverage::Tested>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CoveredByResponse {
+    pub name: String,
+    /// One entry per test of that name, with what it exercises.
+    pub tests: Vec<crate::coverage::TestCoverage>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct HierarchyBody {
+    /// Name of the class, interface or trait, e.g. `Shape`.
+    pub name: String,
+    /// `owner/name`; all repos when omitted.
+    pub repo: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct HierarchyResponse {
+    pub name: String,
+    /// One entry per type of that name, e.g. in different files.
+    pub types: Vec<crate::hierarchy::TypeHierarchy>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct RelatedBody {
+    /// `owner/name` the file was ingested for.
+    pub repo: String,
+    /// Path of the file within the repo, e.g. `src/storage/mod.rs`.
+    pub file: String,
+    /// Overrides for any of the default weights.
+    #[serde(default)]
+    pub weights: RelatednessWeights,
+    pub limit: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct RelatedResponse {
+    pub repo: String,
+    pub file: String,
+    /// Most related first.
+    pub files: Vec<RelatedFile>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct NeighborhoodBody {
+    /// `owner/name` the node was ingested for.
+    pub repo: String,
+    /// The node's id, as query and search results give it.
+    pub id: String,
+    /// Hops to go out from the node; `1` by default, at most `4`.
+    pub depth: Option<usize>,
+    /// Only follow and return edges of these kinds, e.g. `CALLS`; every kind
+    /// when empty.
+    #[serde(default)]
+    pub edge_kinds: Vec<String>,
+    /// Nodes returned at most; `500` by default.
+    pub max_nodes: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct NeighborhoodResponse {
+    pub repo: String,
+    pub id: String,
+    pub depth: usize,
+    #[serde(flatten)]
+    pub subgraph: Subgraph,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SnippetBody {
+    /// `owner/name` the node was ingested for.
+    pub repo: String,
+    /// The node's id, as query and search results give it.
+    pub id: String,
+    /// Lines either side of the node; `3` by default.
+    pub context: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SnippetResponse {
+    pub repo: String,
+    pub id: String,
+    /// Path of the node's file within the repo.
+    pub file: String,
+    /// The documentation written for the node, when it has any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    #[serde(flatten)]
+    pub snippet: Snippet,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ParseTreeBody {
+    /// The repo to read `file` from; ignored when `source` is given.
+    #[serde(flatten)]
+    pub repo: ProcessBody,
+    /// Path of the file, relative to the repo root. With `source` it only
+    /// picks the language.
+    pub file: String,
+    /// Text to parse instead of the file on disk.
+    #[serde(default)]
+    pub source: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ParseTreeResponse {
+    pub file: String,
+    /// The tree-sitter syntax tree, as an S-expression.
+    pub sexp: String,
+    /// What an ingest would store for the file.
+    pub nodes: Vec<NodeRecord>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ParseStreamParams {
+    /// The `include_langs` name of the language the body is written in.
+    pub lang: String,
+    /// Path the nodes are given as being in; `stdin` with the language's
+    /// extension by default.
+    pub file: Option<String>,
+    /// `owner/name` to give the nodes, so the output can be fed to
+    /// `/import/json`.
+    pub repo: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DeadCodeBody {
+    /// `owner/name` of the graph to analyse.
+    pub repo: String,
+    /// Extra regexes over function names to treat as entry points, e.g. `^handle_`.
+    #[serde(default)]
+    pub entry_points: Vec<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DeadCodeResponse {
+    pub repo: String,
+    pub symbols: Vec<Unreferenced>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CyclesBody {
+    /// `owner/name` of the graph to analyse.
+    pub repo: String,
+    /// Cycles returned at most, up to `MAX_CYCLE_LIMIT`.
+    pub limit: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CyclesResponse {
+    pub repo: String,
+    /// Largest first.
+    pub cycles: Vec<Cycle>,
+    /// Whether there were more cycles than `limit`.
+    pub truncated: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DiffBody {
+    /// `owner/name` of the repo both refs were ingested for.
+    pub repo: String,
+    /// The ref to diff from; the graph ingested without a ref when omitted.
+    #[serde(default)]
+    pub base: Option<String>,
+    #[serde(default)]
+    pub head: Option<String>,
+    /// Diffs from this snapshot of `base` rather than its graph as it is now.
+    #[serde(default)]
+    pub base_snapshot: Option<String>,
+    #[serde(default)]
+    pub head_snapshot: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DiffResponse {
+    pub repo: String,
+    pub base: Option<String>,
+    pub head: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_snapshot: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head_snapshot: Option<String>,
+    /// Only files with a change, sorted by path.
+    pub files: Vec<FileDiff>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ChangedSymbolsBody {
+    /// `owner/name` of an ingested repo whose checkout is still on disk.
+    pub repo: String,
+    /// The commit, branch or tag the range starts after.
+    pub base: String,
+    /// Where the range ends; the commit the graph was built from when
+    /// omitted, and it must be that commit if given.
+    #[serde(default)]
+    pub head: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ChangedSymbolsResponse {
+    pub repo: String,
+    /// Both ends as full commit ids.
+    pub base: String,
+    pub head: String,
+    pub symbols: Vec<SymbolChange>,
+    /// Whether a graph of `base` was stored, without which deleted symbols
+    /// can't be named.
+    pub deletions_known: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct WarmBody {
+    /// `owner/name` of an ingested repo.
+    pub repo: String,
+}
+#[derive(Serialize, Deserialize)]
+pub struct WarmResponse {
+    pub repo: String,
+    pub nodes: usize,
+    pub edges: usize,
+    /// Roughly what the graph takes up in memory.
+    pub bytes: usize,
+    /// Repos dropped from the cache to make room, least recently used first.
+    pub evicted: Vec<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct PinBody {
+    pub repo_id: String,
+    /// Pins only this ref of the repo, rather than all of them.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// `false` unpins the repo again; pinned when omitted.
+    #[serde(default)]
+    pub pinned: Option<bool>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct PinResponse {
+    pub repo_id: String,
+    pub pinned: bool,
+    /// Unix seconds the repo is swept after, unless it is used first; none
+    /// when pinned, without `MESH_RETENTION_TTL_SECS` or when not ingested.
+    pub expires_at: Option<u64>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct AuditParams {
+    /// How many of the latest entries to list; 100 when omitted, and at most
+    /// 1000.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct AuditResponse {
+    /// Newest first.
+    pub entries: Vec<AuditEntry>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct CompactResponse {
+    /// `sqlite` or `neo4j`.
+    pub backend: String,
+    #[serde(flatten)]
+    pub compaction: Compaction,
+    pub elapsed_ms: u64,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotBody {
+    /// `owner/name` of the repo the snapshot is of.
+    pub repo: String,
+    /// The ref of `repo` it is of; the graph ingested without a ref when omitted.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    pub name: String,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotsQuery {
+    pub repo: String,
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotResponse {
+    #[serde(flatten)]
+    pub snapshot: SnapshotRecord,
+    /// The repo id reads of the snapshot take, such as `repo` for
+    /// `/graph/query` or `/export/json`.
+    pub graph: String,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotsResponse {
+    pub repo_id: String,
+    /// Oldest first.
+    pub snapshots: Vec<SnapshotResponse>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SearchBody {
+    pub query: String,
+    /// `owner/name`; all repos when omitted.
+    pub repo: Option<String>,
+    pub limit: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub query: String,
+    pub hits: Vec<SearchHit>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct StatsBody {
+    /// `owner/name`; every repo when omitted.
+    pub repo: Option<String>,
+    /// How many of the most-called functions to list per repo.
+    pub top: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct StatsResponse {
+    /// Ordered by repo id.
+    pub repos: Vec<RepoStats>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct GrepBody {
+    /// A substring, or a regex with `regex` set.
+    pub pattern: String,
+    #[serde(default)]
+    pub regex: bool,
+    /// `owner/name`; all repos when omitted.
+    pub repo: Option<String>,
+    pub limit: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct GrepResponse {
+    pub pattern: String,
+    pub hits: Vec<GrepHit>,
+    /// Whether there may be more hits than were returned.
+    pub truncated: bool,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DiagnosticsParams {
+    /// `owner/name` of the repo to report on.
+    pub repo: String,
+    /// Comma-separated severities, e.g. `error`; all when omitted.
+    pub severity: Option<String>,
+    /// Only the diagnostics of this file, or of the files under this
+    /// directory, relative to the repo.
+    pub file: Option<String>,
+    /// `json`, the default, `text` or `sarif`.
+    pub format: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DiagnosticsResponse {
+    pub repo: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ExportDotParams {
+    /// `owner/name` of the graph to render.
+    pub repo: String,
+    /// Comma-separated node kinds, e.g. `Function,File`; all kinds when omitted.
+    pub kinds: Option<String>,
+    pub max_nodes: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ExportCytoscapeParams {
+    /// `owner/name` of the graph to export.
+    pub repo: String,
+    /// Comma-separated node kinds, e.g. `Function,File`; all kinds when omitted.
+    pub kinds: Option<String>,
+    pub max_nodes: Option<usize>,
+    /// Name or id of the symbol to export the neighborhood of.
+    pub around: Option<String>,
+    /// Edges to follow from `around`.
+    pub radius: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ExportMermaidParams {
+    /// `owner/name` of the graph to render.
+    pub repo: String,
+    /// Name or id of the function or type to draw around.
+    pub around: String,
+    /// `calls`, the default, or `hierarchy`.
+    pub graph: Option<MermaidGraph>,
+    /// `LR`, the default, `TB`, `BT` or `RL`.
+    pub direction: Option<Direction>,
+    pub max_nodes: Option<usize>,
+    /// Calls to follow from `around`.
+    pub radius: Option<usize>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ExportJsonParams {
+    /// `owner/name` of the graph to dump.
+    pub repo: String,
+}
+#[derive(Serialize, Deserialize)]
+pub struct ImportJsonParams {
+    /// Stores every record under this `owner/name` instead of the one in the dump.
+    pub repo: Option<String>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct WebhookResponse {
+    /// `queued`, or `ignored` for deliveries there's nothing to do for.
+    pub status: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo_id: Option<String>,
+    /// Files the push changed, when it is applied file by file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<usize>,
+}
 #[derive(Serialize, Deserialize)]
 pub struct FetchRepoBody {
     pub repo_name: String,
+    /// When set, the repo is cloned (with retries) before it is looked up.
+    #[serde(default)]
+    pub repo_url: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// A token to clone with over HTTPS; never logged or echoed back.
+    #[serde(default)]
+    pub pat: Option<String>,
+    /// Names one of the server's `git_credentials` to clone with instead.
+    #[serde(default)]
+    pub credential: Option<String>,
+    /// Branch, tag or commit to check out, into its own directory.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Commits of history to clone; `0` clones all of it. Defaults to 1.
+    #[serde(default)]
+    pub depth: Option<u32>,
+    /// Subtrees to check out, relative to the repo root. Defaults to all.
+    #[serde(default)]
+    pub sparse_paths: Vec<String>,
+    /// Also clones the repo's submodules, recursively; each becomes a
+    /// project of its own when the repo is ingested.
+    #[serde(default)]
+    pub recurse_submodules: bool,
 }
 #[derive(Serialize, Deserialize)]
 pub struct FetchRepoResponse {
     pub status: String,
     pub repo_name: String,
+    /// What was cloned, when `repo_url` was given.
+    #[serde(flatten)]
+    pub fetched: Option<Provenance>,
+}
+/// Which code a clone holds, so a graph built from it can be traced back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Provenance {
+    /// Full SHA of the commit checked out, resolved even when a branch was asked for.
+    pub commit: String,
+    /// The branch checked out, or the tag or commit when HEAD is detached.
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// The remote's default branch, resolved from its HEAD, when no ref was
+    /// asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+    /// The remote, without any credentials in it.
+    pub remote_url: String,
+    /// Seconds since the Unix epoch when the fetch finished.
+    pub fetched_at: u64,
 }
 
 #[derive(Debug)]
-pub enum AppError {
-    Anyhow(anyhow::Error),
+pub enum MeshError {
+    /// Source that could not be parsed into a graph.
+    Parse {
+        file: String,
+        message: String,
+    },
+    /// The storage backend failed or is unreachable; sent as
+    /// [`MeshError::Unavailable`] when [`reconnect::is_transient`] puts it
+    /// down to the connection.
+    Storage(anyhow::Error),
+    Git(String),
+    /// A clone that kept failing after every retry.
+    Clone(CloneError),
+    /// A clone the remote refused the credentials for, or needed some for.
+    GitAuth(CloneError),
+    /// The request itself is invalid.
+    Validation(String),
+    NotFound(String),
+    /// The request can't apply to the resource in its current state.
+    Conflict(String),
+    /// A missing or invalid API key, or a webhook delivery whose signature
+    /// doesn't match the configured secret.
+    Unauthorized(String),
+    /// Work interrupted by a server shutdown.
+    Aborted(String),
+    /// A write sent to a server started with `MESH_READONLY`.
+    ReadOnly(String),
+    /// Work stopped through `/cancel`.
+    Cancelled(String),
+    /// An ingest that would take a repo's graph past its size limit.
+    TooLarge(String),
+    /// Too little free disk space to clone into.
+    NoSpace(String),
+    /// A rate limit or the ingest cap was hit; sent with `Retry-After`.
+    TooManyRequests {
+        message: String,
+        retry_after: Duration,
+    },
+    /// The server has no room for another such request right now; sent
+    /// with `Retry-After` like [`MeshError::TooManyRequests`].
+    Unavailable {
+        message: String,
+        retry_after: Duration,
+    },
+    /// A query or traversal that ran past the request timeout, and was
+    /// stopped after `elapsed`.
+    Timeout {
+        message: String,
+        elapsed: Duration,
+    },
+    Internal(anyhow::Error),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
+impl MeshError {
+    pub fn kind(&self) -> &'static str {
         match self {
-            AppError::Anyhow(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+            MeshError::Parse { .. } => "parse",
+            MeshError::Storage(_) => "storage",
+            MeshError::Git(_) => "git",
+            MeshError::Clone(_) => "clone",
+            MeshError::GitAuth(_) => "git_auth",
+            MeshError::Validation(_) => "validation",
+            MeshError::NotFound(_) => "not_found",
+            MeshError::Conflict(_) => "conflict",
+            MeshError::Unauthorized(_) => "unauthorized",
+            MeshError::Aborted(_) => "aborted",
+            MeshError::ReadOnly(_) => "read_only",
+            MeshError::Cancelled(_) => "cancelled",
+            MeshError::TooLarge(_) => "too_large",
+            MeshError::NoSpace(_) => "no_space",
+            MeshError::TooManyRequests { .. } => "too_many_requests",
+            MeshError::Unavailable { .. } => "unavailable",
+            MeshError::Timeout { .. } => "timeout",
+            MeshError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            MeshError::Parse { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            MeshError::Storage(_) | MeshError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            MeshError::Git(_) | MeshError::Clone(_) => StatusCode::BAD_GATEWAY,
+            MeshError::Validation(_) => StatusCode::BAD_REQUEST,
+            MeshError::NotFound(_) => StatusCode::NOT_FOUND,
+            MeshError::Unauthorized(_) | MeshError::GitAuth(_) => StatusCode::UNAUTHORIZED,
+            MeshError::Conflict(_) | MeshError::Cancelled(_) => StatusCode::CONFLICT,
+            MeshError::Aborted(_) | MeshError::Unavailable { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            MeshError::ReadOnly(_) => StatusCode::METHOD_NOT_ALLOWED,
+            MeshError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            MeshError::NoSpace(_) => StatusCode::INSUFFICIENT_STORAGE,
+            MeshError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            MeshError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        MeshError::Validation(message.into())
+    }
+}
+
+impl std::fmt::Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshError::Parse { file, message } => {
+                write!(f, "failed to parse {}: {}", file, message)
+            }
+            MeshError::Storage(err) => write!(f, "storage error: {:#}", err),
+            MeshError::Git(message) => write!(f, "git error: {}", message),
+            MeshError::Clone(err) => write!(
+                f,
+                "clone of {} failed after {} attempts: {}",
+                err.url, err.attempts, err.message
+            ),
+            MeshError::GitAuth(err) => {
+                write!(f, "authentication to {} failed: {}", err.url, err.message)
+            }
+            MeshError::Validation(message)
+            | MeshError::NotFound(message)
+            | MeshError::Conflict(message)
+            | MeshError::Unauthorized(message)
+            | MeshError::Aborted(message)
+            | MeshError::ReadOnly(message)
+            | MeshError::Cancelled(message)
+            | MeshError::TooLarge(message)
+            | MeshError::NoSpace(message)
+            | MeshError::TooManyRequests { message, .. }
+            | MeshError::Unavailable { message, .. }
+            | MeshError::Timeout { message, .. } => write!(f, "{}", message),
+            MeshError::Internal(err) => write!(f, "{:#}", err),
+        }
+    }
+}
+
+/// In the extensions of every error response, for middleware to tell how a
+/// request failed without reading the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorKind(pub &'static str);
+
+impl IntoResponse for MeshError {
+    fn into_response(self) -> Response {
+        // a backend that went away is worth retrying later, unlike a query
+        // it rejected, and its errors tell the client nothing it can use
+        let this = match self {
+            MeshError::Storage(err) if reconnect::is_transient(&err) => {
+                tracing::warn!("storage unavailable after retries: {:#}", err);
+                MeshError::Unavailable {
+                    message: "The storage backend is unavailable, try again shortly".to_string(),
+                    retry_after: reconnect::UNAVAILABLE_RETRY_AFTER,
+                }
+            }
+            other => other,
+        };
+        let kind = ErrorKind(this.kind());
+        let mut body = serde_json::json!({
+            "error": this.to_string(),
+            "kind": this.kind(),
+        });
+        match &this {
+            MeshError::Parse { file, .. } => body["file"] = file.clone().into(),
+            MeshError::Clone(err) => {
+                body["url"] = err.url.clone().into();
+                body["attempts"] = err.attempts.into();
+            }
+            MeshError::GitAuth(err) => body["url"] = err.url.clone().into(),
+            MeshError::Timeout { elapsed, .. } => {
+                body["elapsed_ms"] = (elapsed.as_millis() as u64).into();
+            }
+            MeshError::TooManyRequests { retry_after, .. }
+            | MeshError::Unavailable { retry_after, .. } => {
+                // whole seconds, rounded up so a client never retries too early
+                let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                body["retry_after"] = secs.into();
+                return (
+                    this.status_code(),
+                    [(header::RETRY_AFTER, secs.to_string())],
+                    Extension(kind),
+                    Json(body),
+                )
+                    .into_response();
+            }
+            _ => {}
         }
+        (this.status_code(), Extension(kind), Json(body)).into_response()
     }
 }
 
-impl<E> From<E> for AppError
+/// Anything not classified at the call site is an internal error.
+impl<E> From<E> for MeshError
 where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self::Anyhow(err.into())
+        Self::Internal(err.into())
     }
 }
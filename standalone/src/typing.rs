@@ -0,0 +1,336 @@
+use crate::captures::GRAMMARS;
+use crate::storage::{NodeRecord, Span};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tree_sitter::{Node, Tree};
+
+/// The meta key of the type written for a node: a function's return type,
+/// or a variable's, constant's or field's declared type. Absent when none is
+/// written and none follows from a literal.
+pub const TYPE: &str = "type";
+/// The meta key of a function's parameter types, as a JSON object of each
+/// annotated parameter's name to its type. Parameters without one are left
+/// out.
+pub const PARAM_TYPES: &str = "param_types";
+
+/// Steps up from the node a span covers to the definition it belongs to, for
+/// spans that only cover a name.
+const MAX_DEPTH: usize = 3;
+
+/// A node kind that is written with a type.
+#[derive(Debug, Clone, Copy)]
+pub struct Typed {
+    pub kind: &'static str,
+    /// The field naming it; empty when the name is its first named child.
+    pub name: &'static str,
+    /// The field holding the type.
+    pub type_field: &'static str,
+    /// The field holding its value, whose literal gives the type when none
+    /// is written; empty when it has none.
+    pub value: &'static str,
+}
+
+const fn typed(
+    kind: &'static str,
+    name: &'static str,
+    type_field: &'static str,
+    value: &'static str,
+) -> Typed {
+    Typed {
+        kind,
+        name,
+        type_field,
+        value,
+    }
+}
+
+/// Where one language writes types, in the node kinds and fields of its
+/// grammar.
+#[derive(Debug, Clone)]
+pub struct TypeRule {
+    /// A name of [`GRAMMARS`].
+    pub grammar: &'static str,
+    /// Function definitions and the field holding their return type.
+    pub functions: &'static [(&'static str, &'static str)],
+    /// The field of a function holding its parameters.
+    pub parameters: &'static str,
+    /// Parameters that may have a type.
+    pub typed_parameters: &'static [Typed],
+    /// Variables, constants and fields.
+    pub declarations: &'static [Typed],
+    /// Literal kinds and the type they always have. Literals whose type
+    /// depends on where they're used, like a Rust integer, aren't listed.
+    pub literals: &'static [(&'static str, &'static str)],
+}
+
+pub const DEFAULT_RULES: &[TypeRule] = &[
+    TypeRule {
+        grammar: "rust",
+        functions: &[
+            ("function_item", "return_type"),
+            ("function_signature_item", "return_type"),
+        ],
+        parameters: "parameters",
+        typed_parameters: &[typed("parameter", "pattern", "type", "")],
+        declarations: &[
+            typed("let_declaration", "pattern", "type", "value"),
+            typed("const_item", "name", "type", "value"),
+            typed("static_item", "name", "type", "value"),
+            typed("field_declaration", "name", "type", ""),
+        ],
+        literals: &[
+            ("string_literal", "&str"),
+            ("raw_string_literal", "&str"),
+            ("char_literal", "char"),
+            ("boolean_literal", "bool"),
+        ],
+    },
+    TypeRule {
+        grammar: "python",
+        functions: &[("function_definition", "return_type")],
+        parameters: "parameters",
+        typed_parameters: &[
+            typed("typed_parameter", "", "type", ""),
+            typed("typed_default_parameter", "name", "type", ""),
+        ],
+        declarations: &[typed("assignment", "left", "type", "right")],
+        literals: &[
+            ("string", "str"),
+            ("integer", "int"),
+            ("float", "float"),
+            ("true", "bool"),
+            ("false", "bool"),
+        ],
+    },
+    TypeRule {
+        grammar: "go",
+        functions: &[
+            ("function_declaration", "result"),
+            ("method_declaration", "result"),
+        ],
+        parameters: "parameters",
+        typed_parameters: &[typed("parameter_declaration", "name", "type", "")],
+        declarations: &[
+            typed("var_spec", "name", "type", ""),
+            typed("const_spec", "name", "type", ""),
+            typed("field_declaration", "name", "type", ""),
+        ],
+        literals: &[],
+    },
+    TypeRule {
+        grammar: "typescript",
+        functions: TS_FUNCTIONS,
+        parameters: "parameters",
+        typed_parameters: TS_PARAMETERS,
+        declarations: TS_DECLARATIONS,
+        literals: TS_LITERALS,
+    },
+    TypeRule {
+        grammar: "tsx",
+        functions: TS_FUNCTIONS,
+        parameters: "parameters",
+        typed_parameters: TS_PARAMETERS,
+        declarations: TS_DECLARATIONS,
+        literals: TS_LITERALS,
+    },
+    TypeRule {
+        grammar: "java",
+        functions: &[("method_declaration", "type")],
+        parameters: "parameters",
+        typed_parameters: &[typed("formal_parameter", "name", "type", "")],
+        declarations: &[
+            typed("field_declaration", "declarator", "type", ""),
+            typed("local_variable_declaration", "declarator", "type", ""),
+        ],
+        literals: &[],
+    },
+];
+
+const TS_FUNCTIONS: &[(&str, &str)] = &[
+    ("function_declaration", "return_type"),
+    ("method_definition", "return_type"),
+    ("arrow_function", "return_type"),
+    ("function_signature", "return_type"),
+    ("method_signature", "return_type"),
+];
+const TS_PARAMETERS: &[Typed] = &[
+    typed("required_parameter", "pattern", "type", ""),
+    typed("optional_parameter", "pattern", "type", ""),
+];
+const TS_DECLARATIONS: &[Typed] = &[
+    typed("variable_declarator", "name", "type", "value"),
+    typed("public_field_definition", "name", "type", "value"),
+    typed("property_signature", "name", "type", ""),
+];
+const TS_LITERALS: &[(&str, &str)] = &[
+    ("string", "string"),
+    ("template_string", "string"),
+    ("number", "number"),
+    ("true", "boolean"),
+    ("false", "boolean"),
+];
+
+impl TypeRule {
+    pub fn for_path(path: &Path) -> Option<&'static TypeRule> {
+        let ext = path.extension()?.to_str()?;
+        let grammar = GRAMMARS.iter().find(|g| g.extensions.contains(&ext))?;
+        DEFAULT_RULES.iter().find(|r| r.grammar == grammar.name)
+    }
+
+    /// The return type of `function` and the types of its parameters.
+    pub fn function_types(
+        &self,
+        function: Node,
+        source: &str,
+    ) -> (Option<String>, BTreeMap<String, String>) {
+        let returns = self
+            .functions
+            .iter()
+            .find(|(kind, _)| *kind == function.kind())
+            .and_then(|(_, field)| function.child_by_field_name(field))
+            .and_then(|node| text(node, source));
+        let mut parameters = BTreeMap::new();
+        if let Some(list) = function.child_by_field_name(self.parameters) {
+            let mut cursor = list.walk();
+            for parameter in list.named_children(&mut cursor) {
+                let Some(rule) = self
+                    .typed_parameters
+                    .iter()
+                    .find(|t| t.kind == parameter.kind())
+                else {
+                    continue;
+                };
+                let Some(ty) = parameter
+                    .child_by_field_name(rule.type_field)
+                    .and_then(|node| text(node, source))
+                else {
+                    continue;
+                };
+                // `a, b int` gives both names the one type
+                for name in names(parameter, rule, source) {
+                    parameters.insert(name, ty.clone());
+                }
+            }
+        }
+        (returns, parameters)
+    }
+
+    /// The type written for `declaration`, or the one its literal value
+    /// always has.
+    pub fn declared_type(&self, declaration: Node, source: &str) -> Option<String> {
+        let rule = self
+            .declarations
+            .iter()
+            .find(|t| t.kind == declaration.kind())?;
+        if let Some(ty) = declaration.child_by_field_name(rule.type_field) {
+            return text(ty, source);
+        }
+        let value = declaration.child_by_field_name(rule.value)?;
+        self.literals
+            .iter()
+            .find(|(kind, _)| *kind == value.kind())
+            .map(|(_, ty)| ty.to_string())
+    }
+
+    fn is_function(&self, kind: &str) -> bool {
+        self.functions.iter().any(|(k, _)| *k == kind)
+    }
+
+    fn is_declaration(&self, kind: &str) -> bool {
+        self.declarations.iter().any(|t| t.kind == kind)
+    }
+}
+
+fn names(parameter: Node, rule: &Typed, source: &str) -> Vec<String> {
+    if rule.name.is_empty() {
+        return parameter
+            .named_child(0)
+            .and_then(|node| text(node, source))
+            .into_iter()
+            .collect();
+    }
+    let mut cursor = parameter.walk();
+    let names: Vec<String> = parameter
+        .children_by_field_name(rule.name, &mut cursor)
+        .filter_map(|node| text(node, source))
+        .collect();
+    names
+}
+
+/// A node's text with its whitespace collapsed, and without the `:` a
+/// type annotation starts with in some grammars.
+fn text(node: Node, source: &str) -> Option<String> {
+    let text = node.utf8_text(source.as_bytes()).ok()?;
+    let text = text
+        .trim_start_matches(':')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// The node of `tree` at `span`, or the nearest one above it that `wanted`
+/// says is the definition.
+pub(crate) fn definition<'t>(
+    tree: &'t Tree,
+    span: &Span,
+    wanted: impl Fn(&str) -> bool,
+) -> Option<Node<'t>> {
+    let mut node = tree
+        .root_node()
+        .descendant_for_byte_range(span.start_byte, span.end_byte)?;
+    for _ in 0..=MAX_DEPTH {
+        if wanted(node.kind()) {
+            return Some(node);
+        }
+        node = node.parent()?;
+    }
+    None
+}
+
+/// Stores the written types of the nodes of `file` in their meta: [`TYPE`]
+/// and [`PARAM_TYPES`] for functions, [`TYPE`] for other symbols that
+/// declare one, read from `tree`, the syntax tree of `source`. Nodes are
+/// found through their spans, so those without one, and every node of a
+/// language without a [`TypeRule`] or of a file without a tree, are left as
+/// they are. Nothing is inferred beyond literals whose type is fixed.
+pub fn annotate<'a>(
+    file: &str,
+    source: &str,
+    tree: Option<&Tree>,
+    nodes: impl IntoIterator<Item = &'a mut NodeRecord>,
+) -> Result<()> {
+    let (Some(rule), Some(tree)) = (TypeRule::for_path(Path::new(file)), tree) else {
+        return Ok(());
+    };
+    for node in nodes {
+        let Some(span) = node.span else {
+            continue;
+        };
+        match node.kind.as_str() {
+            "File" => {}
+            "Function" => {
+                let Some(function) = definition(tree, &span, |k| rule.is_function(k)) else {
+                    continue;
+                };
+                let (returns, parameters) = rule.function_types(function, source);
+                if let Some(returns) = returns {
+                    node.meta.insert(TYPE.to_string(), returns);
+                }
+                if !parameters.is_empty() {
+                    node.meta
+                        .insert(PARAM_TYPES.to_string(), serde_json::to_string(&parameters)?);
+                }
+            }
+            _ => {
+                let ty = definition(tree, &span, |k| rule.is_declaration(k))
+                    .and_then(|declaration| rule.declared_type(declaration, source));
+                if let Some(ty) = ty {
+                    node.meta.insert(TYPE.to_string(), ty);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,358 @@
+use crate::captures::GRAMMARS;
+use crate::storage::NodeRecord;
+use crate::typing::definition;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tree_sitter::{Node, Tree};
+
+/// The meta key of a symbol's [`Visibility`], as its lowercase name.
+pub const VISIBILITY: &str = "visibility";
+
+/// Who can use a symbol from outside where it's defined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Marked public, or public by where it's defined, like a trait's method.
+    Public,
+    /// Exported from its module: an `export`, or a capitalized Go name.
+    Exported,
+    /// Visible within its crate or package only, e.g. `pub(crate)` or Java's
+    /// package-private.
+    Internal,
+    Protected,
+    Private,
+}
+
+impl Visibility {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Exported => "exported",
+            Visibility::Internal => "internal",
+            Visibility::Protected => "protected",
+            Visibility::Private => "private",
+        }
+    }
+
+    /// Whether it's part of the API its module offers the rest of the world.
+    pub fn is_external(self) -> bool {
+        matches!(self, Visibility::Public | Visibility::Exported)
+    }
+}
+
+/// How one language says who can use a definition, in the node kinds of its
+/// grammar. Languages without modifiers go by their naming convention.
+#[derive(Debug, Clone)]
+pub struct VisibilityRule {
+    /// A name of [`GRAMMARS`].
+    pub grammar: &'static str,
+    /// The definitions whose visibility is read.
+    pub definitions: &'static [&'static str],
+    /// Reads the visibility of a definition, given it, its name and the
+    /// source of its file.
+    pub read: fn(Node, &str, &str) -> Visibility,
+}
+
+pub const DEFAULT_RULES: &[VisibilityRule] = &[
+    VisibilityRule {
+        grammar: "rust",
+        definitions: &[
+            "function_item",
+            "function_signature_item",
+            "struct_item",
+            "enum_item",
+            "union_item",
+            "trait_item",
+            "type_item",
+            "const_item",
+            "static_item",
+            "mod_item",
+            "field_declaration",
+        ],
+        read: rust,
+    },
+    VisibilityRule {
+        grammar: "python",
+        definitions: &[
+            "function_definition",
+            "class_definition",
+            "decorated_definition",
+            "assignment",
+        ],
+        read: python,
+    },
+    VisibilityRule {
+        grammar: "go",
+        definitions: &[
+            "function_declaration",
+            "method_declaration",
+            "type_spec",
+            "var_spec",
+            "const_spec",
+            "field_declaration",
+        ],
+        read: go,
+    },
+    VisibilityRule {
+        grammar: "typescript",
+        definitions: TS_DEFINITIONS,
+        read: typescript,
+    },
+    VisibilityRule {
+        grammar: "tsx",
+        definitions: TS_DEFINITIONS,
+        read: typescript,
+    },
+    VisibilityRule {
+        grammar: "java",
+        definitions: &[
+            "class_declaration",
+            "interface_declaration",
+            "enum_declaration",
+            "record_declaration",
+            "annotation_type_declaration",
+            "method_declaration",
+            "constructor_declaration",
+            "field_declaration",
+            "constant_declaration",
+        ],
+        read: java,
+    },
+    VisibilityRule {
+        grammar: "ruby",
+        definitions: &["method", "singleton_method", "class", "module"],
+        read: ruby,
+    },
+];
+
+const TS_DEFINITIONS: &[&str] = &[
+    "function_declaration",
+    "generator_function_declaration",
+    "class_declaration",
+    "abstract_class_declaration",
+    "interface_declaration",
+    "type_alias_declaration",
+    "enum_declaration",
+    "lexical_declaration",
+    "variable_declarator",
+    "arrow_function",
+    "method_definition",
+    "method_signature",
+    "abstract_method_signature",
+    "public_field_definition",
+    "property_signature",
+];
+
+impl VisibilityRule {
+    pub fn for_path(path: &Path) -> Option<&'static VisibilityRule> {
+        let ext = path.extension()?.to_str()?;
+        let grammar = GRAMMARS.iter().find(|g| g.extensions.contains(&ext))?;
+        DEFAULT_RULES.iter().find(|r| r.grammar == grammar.name)
+    }
+}
+
+fn child_of_kind<'t>(node: Node<'t>, kind: &str) -> Option<Node<'t>> {
+    let mut cursor = node.walk();
+    let found = node.children(&mut cursor).find(|c| c.kind() == kind);
+    found
+}
+
+fn text<'s>(node: Node, source: &'s str) -> &'s str {
+    node.utf8_text(source.as_bytes()).unwrap_or_default()
+}
+
+/// `pub` is public and any `pub(...)` internal. Without one, the items of a
+/// trait, and of an impl of one, are as public as the trait.
+fn rust(definition: Node, _name: &str, source: &str) -> Visibility {
+    if let Some(modifier) = child_of_kind(definition, "visibility_modifier") {
+        return match text(modifier, source) {
+            "pub" => Visibility::Public,
+            _ => Visibility::Internal,
+        };
+    }
+    let owner = definition
+        .parent()
+        .filter(|p| p.kind() == "declaration_list")
+        .and_then(|list| list.parent());
+    match owner {
+        Some(o) if o.kind() == "trait_item" => Visibility::Public,
+        Some(o) if o.kind() == "impl_item" && o.child_by_field_name("trait").is_some() => {
+            Visibility::Public
+        }
+        _ => Visibility::Private,
+    }
+}
+
+/// A leading underscore makes a name private, unless it's a dunder like
+/// `__init__`.
+fn python(_definition: Node, name: &str, _source: &str) -> Visibility {
+    let dunder = name.len() > 4 && name.starts_with("__") && name.ends_with("__");
+    if name.starts_with('_') && !dunder {
+        Visibility::Private
+    } else {
+        Visibility::Public
+    }
+}
+
+/// A capitalized name is exported from its package.
+fn go(_definition: Node, name: &str, _source: &str) -> Visibility {
+    if name.starts_with(|c: char| c.is_uppercase()) {
+        Visibility::Exported
+    } else {
+        Visibility::Private
+    }
+}
+
+/// Class members are public unless an accessibility modifier or a `#` name
+/// says otherwise, and interface members always are. Anything else is
+/// exported by an `export` around it and private to its module without one.
+fn typescript(definition: Node, _name: &str, source: &str) -> Visibility {
+    const MEMBERS: &[&str] = &[
+        "method_definition",
+        "method_signature",
+        "abstract_method_signature",
+        "public_field_definition",
+        "property_signature",
+    ];
+    // a class field holding an arrow function is a member like any other
+    let definition = match definition.parent() {
+        Some(p) if MEMBERS.contains(&p.kind()) => p,
+        _ => definition,
+    };
+    if MEMBERS.contains(&definition.kind()) {
+        if let Some(modifier) = child_of_kind(definition, "accessibility_modifier") {
+            return match text(modifier, source) {
+                "private" => Visibility::Private,
+                "protected" => Visibility::Protected,
+                _ => Visibility::Public,
+            };
+        }
+        let private_name = definition
+            .child_by_field_name("name")
+            .is_some_and(|n| n.kind() == "private_property_identifier");
+        return if private_name {
+            Visibility::Private
+        } else {
+            Visibility::Public
+        };
+    }
+    let mut node = definition.parent();
+    while let Some(n) = node {
+        match n.kind() {
+            "export_statement" => return Visibility::Exported,
+            "program" | "statement_block" | "class_body" => break,
+            _ => node = n.parent(),
+        }
+    }
+    Visibility::Private
+}
+
+/// The modifiers written, or else public in an interface and
+/// package-private anywhere else.
+fn java(definition: Node, _name: &str, source: &str) -> Visibility {
+    let modifiers = child_of_kind(definition, "modifiers").map(|m| text(m, source));
+    for word in modifiers.into_iter().flat_map(str::split_whitespace) {
+        match word {
+            "public" => return Visibility::Public,
+            "protected" => return Visibility::Protected,
+            "private" => return Visibility::Private,
+            _ => {}
+        }
+    }
+    let in_interface = definition
+        .parent()
+        .is_some_and(|p| p.kind() == "interface_body" || p.kind() == "annotation_type_body");
+    if in_interface {
+        Visibility::Public
+    } else {
+        Visibility::Internal
+    }
+}
+
+/// Methods are public unless made otherwise by `private def`, a bare
+/// `private` or `protected` above them in their class, or a `private :name`
+/// anywhere in it.
+fn ruby(definition: Node, name: &str, source: &str) -> Visibility {
+    let of_keyword = |keyword: &str| match keyword {
+        "private" => Some(Visibility::Private),
+        "protected" => Some(Visibility::Protected),
+        "public" => Some(Visibility::Public),
+        _ => None,
+    };
+    if definition.kind() != "method" {
+        return Visibility::Public;
+    }
+    // `private def name`
+    let call = definition
+        .parent()
+        .filter(|p| p.kind() == "argument_list")
+        .and_then(|list| list.parent())
+        .filter(|c| c.kind() == "call");
+    if let Some(call) = call {
+        let keyword = call.child_by_field_name("method").map(|m| text(m, source));
+        if let Some(visibility) = keyword.and_then(of_keyword) {
+            return visibility;
+        }
+    }
+    // `private :name`
+    let symbol = format!(":{}", name);
+    let mut sibling = definition.parent().and_then(|p| p.named_child(0));
+    while let Some(s) = sibling {
+        if s.kind() == "call" {
+            let keyword = s.child_by_field_name("method").map(|m| text(m, source));
+            let names = s.child_by_field_name("arguments").is_some_and(|args| {
+                let mut cursor = args.walk();
+                let named = args
+                    .named_children(&mut cursor)
+                    .any(|a| text(a, source) == symbol);
+                named
+            });
+            if let (Some(visibility), true) = (keyword.and_then(of_keyword), names) {
+                return visibility;
+            }
+        }
+        sibling = s.next_named_sibling();
+    }
+    // a bare `private` or `protected` line above it
+    let mut above = definition.prev_named_sibling();
+    while let Some(a) = above {
+        if a.kind() == "identifier" {
+            if let Some(visibility) = of_keyword(text(a, source)) {
+                return visibility;
+            }
+        }
+        above = a.prev_named_sibling();
+    }
+    Visibility::Public
+}
+
+/// Stores the [`VISIBILITY`] of the symbols of `file` in their meta, read
+/// from `tree`, the syntax tree of `source`. Nodes are found through their
+/// spans, so those without one, and every node of a language without a
+/// [`VisibilityRule`] or of a file without a tree, are left as they are.
+pub fn annotate<'a>(
+    file: &str,
+    source: &str,
+    tree: Option<&Tree>,
+    nodes: impl IntoIterator<Item = &'a mut NodeRecord>,
+) -> Result<()> {
+    let (Some(rule), Some(tree)) = (VisibilityRule::for_path(Path::new(file)), tree) else {
+        return Ok(());
+    };
+    for node in nodes {
+        let Some(span) = node.span else {
+            continue;
+        };
+        if node.kind == "File" {
+            continue;
+        }
+        let Some(found) = definition(tree, &span, |k| rule.definitions.contains(&k)) else {
+            continue;
+        };
+        let visibility = (rule.read)(found, &node.name, source);
+        node.meta
+            .insert(VISIBILITY.to_string(), visibility.as_str().to_string());
+    }
+    Ok(())
+}
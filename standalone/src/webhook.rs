@@ -0,0 +1,176 @@
+use crate::auth::constant_time_eq;
+use crate::storage;
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashSet};
+
+/// Pushes touching more files than this are re-processed as a whole repo.
+pub const MAX_INCREMENTAL_FILES: usize = 100;
+
+/// Shared secret and the repos (`owner/name`) pushes are accepted for.
+pub struct WebhookConfig {
+    secret: Vec<u8>,
+    repos: HashSet<String>,
+}
+
+impl WebhookConfig {
+    pub fn new(secret: &str, repos: &[&str]) -> Self {
+        WebhookConfig {
+            secret: secret.as_bytes().to_vec(),
+            repos: repos.iter().map(|r| r.trim().to_lowercase()).collect(),
+        }
+    }
+
+    pub fn is_configured(&self, repo_id: &str) -> bool {
+        self.repos.contains(&repo_id.to_lowercase())
+    }
+
+    /// Checks GitHub's `X-Hub-Signature-256` HMAC of the raw body, or GitLab's
+    /// `X-Gitlab-Token`, against the secret. Both comparisons take constant time.
+    pub fn verify(&self, headers: &HeaderMap, body: &[u8]) -> bool {
+        if let Some(signature) = header(headers, "x-hub-signature-256") {
+            let Some(hex_digest) = signature.strip_prefix("sha256=") else {
+                return false;
+            };
+            let Ok(digest) = hex::decode(hex_digest) else {
+                return false;
+            };
+            let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(&self.secret) else {
+                return false;
+            };
+            mac.update(body);
+            return mac.verify_slice(&digest).is_ok();
+        }
+        if let Some(token) = header(headers, "x-gitlab-token") {
+            return constant_time_eq(token.as_bytes(), &self.secret);
+        }
+        false
+    }
+}
+
+/// `X-Hub-Signature-256` for `body`, as GitHub would send it.
+pub fn github_signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// What a push changed, from either provider's payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Push {
+    pub repo_id: String,
+    pub url: String,
+    pub branch: String,
+    pub default_branch: Option<String>,
+    /// Files added or modified, net of later removals.
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    /// False when the payload can't be trusted to list every changed file:
+    /// force pushes, or commit lists the provider truncated.
+    pub complete: bool,
+}
+
+impl Push {
+    /// Whether the push can be applied file by file rather than by
+    /// re-processing the repo.
+    pub fn incremental(&self) -> bool {
+        self.complete && self.changed.len() + self.removed.len() <= MAX_INCREMENTAL_FILES
+    }
+}
+
+/// What a webhook delivery asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delivery {
+    Push(Push),
+    /// Pings, tag pushes, branch deletions and other events there's nothing to do for.
+    Ignored(String),
+}
+
+/// Reads a GitHub or GitLab delivery; the event type comes from the headers.
+pub fn parse(headers: &HeaderMap, body: &[u8]) -> Result<Delivery, String> {
+    let event = header(headers, "x-github-event")
+        .map(|e| (e, e == "push"))
+        .or_else(|| header(headers, "x-gitlab-event").map(|e| (e, e == "Push Hook")))
+        .ok_or("not a GitHub or GitLab webhook delivery")?;
+    if !event.1 {
+        return Ok(Delivery::Ignored(format!("{} events are ignored", event.0)));
+    }
+    let payload: Value =
+        serde_json::from_slice(body).map_err(|e| format!("invalid payload: {}", e))?;
+    let text = |v: &Value| v.as_str().map(str::to_string);
+
+    let Some(branch) = payload["ref"]
+        .as_str()
+        .and_then(|r| r.strip_prefix("refs/heads/"))
+    else {
+        return Ok(Delivery::Ignored(
+            "only branch pushes are ingested".to_string(),
+        ));
+    };
+    if payload["after"]
+        .as_str()
+        .is_some_and(|sha| sha.bytes().all(|b| b == b'0'))
+    {
+        return Ok(Delivery::Ignored(format!("branch {} was deleted", branch)));
+    }
+
+    // GitHub puts the repo under `repository`, GitLab under `project`
+    let github = payload
+        .get("repository")
+        .filter(|r| r.get("full_name").is_some());
+    let (url, default_branch) = match github {
+        Some(repo) => (text(&repo["clone_url"]), text(&repo["default_branch"])),
+        None => (
+            text(&payload["project"]["git_http_url"]),
+            text(&payload["project"]["default_branch"]),
+        ),
+    };
+    let url = url.ok_or("payload has no repository URL")?;
+
+    let commits = payload["commits"].as_array().cloned().unwrap_or_default();
+    let mut changed = BTreeSet::new();
+    let mut removed = BTreeSet::new();
+    for commit in &commits {
+        for key in ["added", "modified"] {
+            for file in commit[key]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(text)
+            {
+                removed.remove(&file);
+                changed.insert(file);
+            }
+        }
+        for file in commit["removed"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(text)
+        {
+            changed.remove(&file);
+            removed.insert(file);
+        }
+    }
+    let truncated = payload["total_commits_count"]
+        .as_u64()
+        .is_some_and(|total| total as usize > commits.len());
+    let forced = payload["forced"].as_bool().unwrap_or(false);
+
+    Ok(Delivery::Push(Push {
+        repo_id: storage::repo_id(&url, ""),
+        url,
+        branch: branch.to_string(),
+        default_branch,
+        changed: changed.into_iter().collect(),
+        removed: removed.into_iter().collect(),
+        complete: !forced && !truncated && !commits.is_empty(),
+    }))
+}
@@ -0,0 +1,122 @@
+//! Named workspaces served by one process, such as `prod` and
+//! `experiments`, each with its own repos kept in its own backend database
+//! (a Neo4j database, or a SQLite file) and its own state, so its locks,
+//! caches and event streams are its own too. A request picks one with the
+//! `/workspaces/{name}` path prefix before any route, or with the
+//! `X-Mesh-Workspace` header; without either it reaches the default graph.
+//! `/clear` with `ALL` clears every repo of one workspace and none of another.
+
+use crate::types::MeshError;
+use crate::AppState;
+use axum::extract::{Path as UrlPath, Request, State};
+use axum::http::Uri;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum::Router;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tower::Layer;
+
+pub const HEADER: &str = "x-mesh-workspace";
+/// What a workspace's routes are served under, followed by its name.
+pub const PREFIX: &str = "/workspaces";
+const MAX_NAME_LEN: usize = 64;
+
+/// Fails unless `name` can be used as a path segment, a Neo4j database name
+/// and part of a file name: lowercase letters, digits, `-` and `_`,
+/// starting with a letter.
+pub fn validate_name(name: &str) -> anyhow::Result<()> {
+    let valid = name.len() <= MAX_NAME_LEN
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        anyhow::bail!(
+            "workspace name '{}' must start with a lowercase letter and hold only lowercase letters, digits, '-' and '_' (at most {} characters)",
+            name,
+            MAX_NAME_LEN
+        );
+    }
+    Ok(())
+}
+
+/// The SQLite file a workspace is kept in, next to the default one:
+/// `mesh.db` is `mesh.prod.db` for `prod`.
+pub fn sqlite_path(default: &Path, name: &str) -> PathBuf {
+    let stem = default
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "mesh".to_string());
+    let file = match default.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, name, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, name),
+    };
+    default.with_file_name(file)
+}
+
+/// The routes of `default`, with those of each workspace nested under its
+/// prefix. Requests naming a workspace that isn't among them get a 404.
+pub fn router(default: Arc<AppState>, workspaces: BTreeMap<String, Arc<AppState>>) -> Router {
+    let names: Arc<BTreeSet<String>> = Arc::new(workspaces.keys().cloned().collect());
+    let unknown_workspace = format!("{}/:workspace", PREFIX);
+    let mut router = crate::router(default)
+        .route(&unknown_workspace, any(unknown))
+        .route(&format!("{}/*rest", unknown_workspace), any(unknown));
+    for (name, state) in workspaces {
+        router = router.nest(&format!("{}/{}", PREFIX, name), crate::router(state));
+    }
+    // the header is turned into the prefix before routing, which layers
+    // added to the router itself would run after
+    let selected = middleware::from_fn_with_state(names, select).layer(router);
+    Router::new().fallback_service(selected)
+}
+
+async fn unknown(UrlPath(params): UrlPath<BTreeMap<String, String>>) -> MeshError {
+    let name = params.get("workspace").map(String::as_str).unwrap_or("");
+    MeshError::NotFound(format!("No workspace named '{}'", name))
+}
+
+/// Moves a request naming its workspace in the header under that
+/// workspace's prefix.
+async fn select(
+    State(names): State<Arc<BTreeSet<String>>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(value) = request.headers().get(HEADER) else {
+        return next.run(request).await;
+    };
+    let name = match value.to_str() {
+        Ok(name) => name.trim().to_string(),
+        Err(_) => return MeshError::validation("X-Mesh-Workspace must be text").into_response(),
+    };
+    if let Err(e) = validate_name(&name) {
+        return MeshError::validation(e.to_string()).into_response();
+    }
+    if !names.contains(&name) {
+        return MeshError::NotFound(format!("No workspace named '{}'", name)).into_response();
+    }
+    let path = request.uri().path();
+    if path == PREFIX || path.starts_with(&format!("{}/", PREFIX)) {
+        return MeshError::validation(
+            "A request names its workspace with either X-Mesh-Workspace or the path, not both",
+        )
+        .into_response();
+    }
+    let mut uri = format!("{}/{}", PREFIX, name);
+    if path != "/" {
+        uri.push_str(path);
+    }
+    if let Some(query) = request.uri().query() {
+        uri.push('?');
+        uri.push_str(query);
+    }
+    match uri.parse::<Uri>() {
+        Ok(uri) => *request.uri_mut() = uri,
+        Err(e) => return MeshError::validation(format!("Invalid path: {}", e)).into_response(),
+    }
+    next.run(request).await
+}
@@ -0,0 +1,172 @@
+use crate::events::StatusEvent;
+use crate::AppState;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// What a `/ws` client sends, as JSON text frames tagged by `type`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Adds a repo to those followed. Until the first one, every repo is.
+    Subscribe {
+        repo_id: String,
+    },
+    Unsubscribe {
+        repo_id: String,
+    },
+    /// Re-sends the buffered events after `since`, like SSE's `Last-Event-ID`.
+    Replay {
+        since: u64,
+    },
+    /// Stops live events until `resume`; a `replay` from the last id seen
+    /// catches up on what was skipped.
+    Pause,
+    Resume,
+}
+
+/// What the server sends besides events, which go out as the `/events` data
+/// plus `"type": "event"` and their `id`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The repos followed after a subscribe or unsubscribe; empty means all.
+    Subscribed {
+        repo_ids: Vec<String>,
+    },
+    Paused,
+    Resumed,
+    Error {
+        message: String,
+    },
+}
+
+/// One connection's choices. Events that aren't tied to a repo are sent
+/// whatever the subscriptions.
+#[derive(Default)]
+struct Connection {
+    repos: BTreeSet<String>,
+    paused: bool,
+    /// Highest event id sent, so the live stream doesn't repeat a replay.
+    seen: u64,
+}
+
+impl Connection {
+    fn follows(&self, event: &StatusEvent) -> bool {
+        match &event.repo_id {
+            Some(repo) => self.repos.is_empty() || self.repos.contains(repo),
+            None => true,
+        }
+    }
+
+    /// The frames answering `text`.
+    fn handle(&mut self, state: &AppState, text: &str) -> Vec<String> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(e) => {
+                return vec![reply(ServerMessage::Error {
+                    message: format!("invalid message: {}", e),
+                })]
+            }
+        };
+        match message {
+            ClientMessage::Subscribe { repo_id } => {
+                self.repos.insert(repo_id);
+                vec![self.subscribed()]
+            }
+            ClientMessage::Unsubscribe { repo_id } => {
+                self.repos.remove(&repo_id);
+                vec![self.subscribed()]
+            }
+            ClientMessage::Replay { since } => {
+                let events: Vec<StatusEvent> = state
+                    .tx
+                    .since(since)
+                    .into_iter()
+                    .filter(|e| self.follows(e))
+                    .collect();
+                if let Some(last) = events.last() {
+                    self.seen = self.seen.max(last.id);
+                }
+                events.iter().map(event_frame).collect()
+            }
+            ClientMessage::Pause => {
+                self.paused = true;
+                vec![reply(ServerMessage::Paused)]
+            }
+            ClientMessage::Resume => {
+                self.paused = false;
+                vec![reply(ServerMessage::Resumed)]
+            }
+        }
+    }
+
+    fn subscribed(&self) -> String {
+        reply(ServerMessage::Subscribed {
+            repo_ids: self.repos.iter().cloned().collect(),
+        })
+    }
+}
+
+fn reply(message: ServerMessage) -> String {
+    serde_json::to_string(&message).unwrap_or_default()
+}
+
+fn event_frame(event: &StatusEvent) -> String {
+    let mut value = event.to_json();
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("type".to_string(), "event".into());
+        obj.insert("id".to_string(), event.id.into());
+    }
+    value.to_string()
+}
+
+/// The `/events` stream over a WebSocket, which the client can also steer:
+/// follow some repos only, pause, or ask for a replay. See [`ClientMessage`].
+pub async fn handler(State(state): State<Arc<AppState>>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| serve(socket, state))
+}
+
+async fn serve(mut socket: WebSocket, state: Arc<AppState>) {
+    let mut rx = state.tx.subscribe();
+    let _subscription = state.event_stats.subscribe();
+    let mut connection = Connection::default();
+    loop {
+        let frames = tokio::select! {
+            // closing on shutdown lets graceful shutdown complete, as for SSE
+            _ = state.shutdown.cancelled() => break,
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => connection.handle(&state, &text),
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // pings are answered by axum
+                Some(Ok(_)) => continue,
+            },
+            received = rx.recv() => match received {
+                Ok(event) if event.id <= connection.seen => continue,
+                Ok(event) if connection.paused || !connection.follows(&event) => continue,
+                Ok(event) => {
+                    state.event_stats.record_backlog(rx.len());
+                    connection.seen = event.id;
+                    vec![event_frame(&event)]
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    state.event_stats.record_lag(skipped);
+                    warn!("WebSocket receiver lagged, skipped {} messages", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+        for frame in frames {
+            if socket.send(Message::Text(frame)).await.is_err() {
+                return;
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
@@ -0,0 +1,371 @@
+mod common;
+
+use standalone::analysis::{
+    cycles, diff, find_unreferenced, relatedness, Change, EntryPoints, RelatednessWeights,
+    DEFAULT_RULES,
+};
+use standalone::storage::{EdgeRecord, NodeRecord};
+use std::collections::HashMap;
+
+fn function(name: &str, file: &str, body: &str) -> NodeRecord {
+    NodeRecord {
+        body: body.to_string(),
+        ..common::node("Function", name, file)
+    }
+}
+
+fn sample() -> Vec<NodeRecord> {
+    vec![
+        function("main", "src/main.rs", "fn main() {\n    run();\n}"),
+        function("run", "src/main.rs", "fn run() {\n    run();\n}"),
+        function(
+            "forgotten",
+            "src/main.rs",
+            "fn forgotten() -> u32 {\n    1\n}",
+        ),
+        function("render", "src/lib.rs", "pub fn render() -> String {}"),
+        function("on_click", "src/lib.rs", "fn on_click() {}"),
+        function(
+            "register",
+            "src/lib.rs",
+            "fn register() {\n    button.bind(on_click);\n}",
+        ),
+        function("test_render", "tests/render.rs", "fn test_render() {}"),
+        function("Serve", "server.go", "func Serve() {}"),
+        function("parse", "server.go", "func parse() {}"),
+    ]
+}
+
+fn dead_names(entry_points: &EntryPoints) -> Vec<String> {
+    find_unreferenced(&sample(), &[], entry_points)
+        .into_iter()
+        .map(|u| u.name)
+        .collect()
+}
+
+#[test]
+fn test_private_dead_function_is_reported() {
+    let dead = find_unreferenced(&sample(), &[], &EntryPoints::default());
+    let forgotten = dead.iter().find(|u| u.name == "forgotten").unwrap();
+    assert!(!forgotten.uncertain);
+    assert!(forgotten.reason.is_none());
+    assert!(dead.iter().any(|u| u.name == "parse"));
+    // recursion alone doesn't count as a caller, but main calls run
+    assert!(!dead.iter().any(|u| u.name == "run"));
+}
+
+#[test]
+fn test_entry_points_and_public_api_are_excluded() {
+    let dead = dead_names(&EntryPoints::default());
+    for kept in ["main", "render", "test_render", "Serve"] {
+        assert!(!dead.contains(&kept.to_string()), "{} reported", kept);
+    }
+}
+
+#[test]
+fn test_function_passed_as_value_is_uncertain() {
+    let dead = find_unreferenced(&sample(), &[], &EntryPoints::default());
+    let on_click = dead.iter().find(|u| u.name == "on_click").unwrap();
+    assert!(on_click.uncertain);
+    assert!(on_click.reason.as_deref().unwrap().contains("callback"));
+}
+
+#[test]
+fn test_extra_entry_points() {
+    let extra = vec!["^on_".to_string(), "^regist".to_string()];
+    let dead = dead_names(&EntryPoints::new(DEFAULT_RULES, &extra).unwrap());
+    assert_eq!(dead, vec!["parse", "forgotten"]);
+    assert!(EntryPoints::new(DEFAULT_RULES, &["(".to_string()]).is_err());
+}
+
+fn at(mut node: NodeRecord, start: usize) -> NodeRecord {
+    node.id = format!("{}-{}", node.id, start);
+    node.start = start;
+    node.end = start + node.body.lines().count();
+    node
+}
+
+fn calls(source: &NodeRecord, target: &NodeRecord) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: "CALLS".to_string(),
+        source: source.id.clone(),
+        target: target.id.clone(),
+    }
+}
+
+#[test]
+fn test_diff_reports_added_and_removed_functions() {
+    let main = at(
+        function("main", "src/main.rs", "fn main() {\n    old();\n}"),
+        1,
+    );
+    let old = at(function("old", "src/main.rs", "fn old() {}"), 5);
+    let base = vec![main.clone(), old.clone()];
+    let base_edges = vec![calls(&main, &old)];
+
+    let main_after = at(
+        function("main", "src/main.rs", "fn main() {\n    new();\n}"),
+        1,
+    );
+    let new = at(function("new", "src/util.rs", "fn new() {}"), 1);
+    let head = vec![main_after.clone(), new.clone()];
+    let head_edges = vec![calls(&main_after, &new)];
+
+    let files = diff((&base, &base_edges), (&head, &head_edges));
+    let names: Vec<&str> = files.iter().map(|f| f.file.as_str()).collect();
+    assert_eq!(names, vec!["src/main.rs", "src/util.rs"]);
+    let main_rs = &files[0];
+    assert_eq!(main_rs.removed.len(), 1);
+    assert_eq!(main_rs.removed[0].name, "old");
+    assert_eq!(main_rs.changed[0].changes, vec![Change::Body]);
+    assert_eq!(main_rs.removed_edges[0].target, "old");
+    assert_eq!(main_rs.added_edges[0].target_file, "src/util.rs");
+    assert_eq!(files[1].added[0].name, "new");
+    assert!(files[1].removed.is_empty());
+}
+
+#[test]
+fn test_diff_reports_signature_change() {
+    let base = vec![at(
+        function("render", "src/lib.rs", "pub fn render() -> String {}"),
+        3,
+    )];
+    let head = vec![at(
+        function(
+            "render",
+            "src/lib.rs",
+            "pub fn render(width: usize) -> String {}",
+        ),
+        3,
+    )];
+    let files = diff((&base, &[]), (&head, &[]));
+    assert_eq!(files.len(), 1);
+    let changed = &files[0].changed[0];
+    assert_eq!(changed.changes, vec![Change::Signature]);
+    let (before, after) = changed.signature.as_ref().unwrap();
+    assert_eq!(before, "pub fn render() -> String");
+    assert_eq!(after, "pub fn render(width: usize) -> String");
+    assert!(files[0].added.is_empty() && files[0].removed.is_empty());
+}
+
+#[test]
+fn test_diff_keeps_identity_of_moved_function() {
+    let body = "def helper(x):\n    return x";
+    let base = vec![at(function("helper", "app.py", body), 2)];
+    let head = vec![at(function("helper", "app.py", body), 12)];
+    let files = diff((&base, &[]), (&head, &[]));
+    let changed = &files[0].changed[0];
+    assert_eq!(changed.changes, vec![Change::Moved]);
+    assert_eq!((changed.base.start, changed.head.start), (2, 12));
+    assert!(changed.signature.is_none());
+
+    assert!(diff((&base, &[]), (&base, &[])).is_empty());
+}
+
+fn import(file: &str, statement: &str) -> NodeRecord {
+    NodeRecord {
+        kind: "Import".to_string(),
+        id: format!("import-{}-{}", statement, file).to_lowercase(),
+        ..function(statement, file, statement)
+    }
+}
+
+fn file(path: &str) -> NodeRecord {
+    NodeRecord {
+        kind: "File".to_string(),
+        id: format!("file-{}", path),
+        ..function(path, path, "")
+    }
+}
+
+fn web_app() -> Vec<NodeRecord> {
+    let mut nodes: Vec<NodeRecord> = ["views", "models", "store", "admin", "unrelated"]
+        .iter()
+        .map(|name| file(&format!("app/{}.py", name)))
+        .collect();
+    nodes.extend([
+        import("app/views.py", "from app.models import Article"),
+        import("app/views.py", "import json"),
+        function(
+            "show",
+            "app/views.py",
+            "def show(pk):\n    return render(load(pk))",
+        ),
+        function("Article", "app/models.py", "class Article:\n    pass"),
+        function("load", "app/store.py", "def load(pk):\n    return None"),
+        import("app/admin.py", "import json"),
+        function("configure", "app/admin.py", "def configure():\n    pass"),
+        function(
+            "unrelated",
+            "app/unrelated.py",
+            "def unrelated():\n    pass",
+        ),
+    ]);
+    nodes
+}
+
+#[test]
+fn test_imported_file_ranks_above_unrelated_ones() {
+    let related = relatedness(
+        &web_app(),
+        &[],
+        "app/views.py",
+        &HashMap::new(),
+        &RelatednessWeights::default(),
+    );
+    let files: Vec<&str> = related.iter().map(|r| r.file.as_str()).collect();
+    assert_eq!(files, vec!["app/models.py", "app/store.py", "app/admin.py"]);
+    assert_eq!(related[0].imports, 1);
+    assert_eq!(related[1].calls, 1);
+    // both import `json`
+    assert_eq!(related[2].shared_imports, 1);
+    assert!(!files.contains(&"app/unrelated.py"));
+}
+
+#[test]
+fn test_relatedness_weights_and_co_changes() {
+    let co_changes = HashMap::from([
+        ("app/unrelated.py".to_string(), 10),
+        ("app/views.py".to_string(), 3),
+    ]);
+    let weights = RelatednessWeights {
+        calls: 10.0,
+        ..Default::default()
+    };
+    let related = relatedness(&web_app(), &[], "app/views.py", &co_changes, &weights);
+    let files: Vec<&str> = related.iter().map(|r| r.file.as_str()).collect();
+    assert_eq!(
+        files,
+        vec![
+            "app/store.py",
+            "app/unrelated.py",
+            "app/models.py",
+            "app/admin.py"
+        ]
+    );
+    assert_eq!(related[1].co_changes, 10);
+    assert_eq!(related[1].score, 10.0);
+
+    let imports_only = RelatednessWeights {
+        imports: 1.0,
+        shared_imports: 0.0,
+        calls: 0.0,
+        co_changes: 0.0,
+    };
+    let related = relatedness(&web_app(), &[], "app/views.py", &co_changes, &imports_only);
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].file, "app/models.py");
+}
+
+fn imports(from: &str, to: &str) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: "IMPORTS".to_string(),
+        source: format!("file-{}", from),
+        target: format!("file-{}", to),
+    }
+}
+
+fn tangled() -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let nodes = ["a.py", "b.py", "c.py", "d.py", "e.py", "f.py", "g.py"]
+        .iter()
+        .map(|f| file(f))
+        .collect();
+    let edges = vec![
+        imports("a.py", "b.py"),
+        imports("b.py", "a.py"),
+        imports("c.py", "d.py"),
+        imports("d.py", "e.py"),
+        imports("e.py", "c.py"),
+        // a shortcut, so the loop reported is the short way round
+        imports("c.py", "e.py"),
+        imports("f.py", "f.py"),
+        // into cycles but out of none
+        imports("g.py", "a.py"),
+        imports("g.py", "c.py"),
+    ];
+    (nodes, edges)
+}
+
+#[test]
+fn test_import_cycles_are_reported_largest_first() {
+    let (nodes, edges) = tangled();
+    let (found, truncated) = cycles(&nodes, &edges, 10);
+    assert!(!truncated);
+    let files: Vec<Vec<&str>> = found
+        .iter()
+        .map(|c| c.files.iter().map(String::as_str).collect())
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            vec!["c.py", "d.py", "e.py"],
+            vec!["a.py", "b.py"],
+            vec!["f.py"],
+        ]
+    );
+    assert_eq!(found[0].path, ["c.py", "e.py", "c.py"]);
+    assert_eq!(found[1].path, ["a.py", "b.py", "a.py"]);
+    assert_eq!(found[2].path, ["f.py", "f.py"]);
+
+    let (found, truncated) = cycles(&nodes, &edges, 1);
+    assert!(truncated);
+    assert_eq!(found.len(), 1);
+    // without the loops there's nothing to report
+    let acyclic: Vec<EdgeRecord> = edges
+        .into_iter()
+        .filter(|e| e.source.ends_with("g.py"))
+        .collect();
+    assert!(cycles(&nodes, &acyclic, 10).0.is_empty());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_cycles_endpoint_reads_the_stored_imports() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let (nodes, edges) = tangled();
+    for node in &nodes {
+        storage.upsert_node(node).await.unwrap();
+    }
+    for edge in &edges {
+        storage.upsert_edge(edge).await.unwrap();
+    }
+    let app = standalone::router(Arc::new(AppState::new(
+        storage,
+        LanguageRegistry::new(),
+        16,
+    )));
+    let send = |body: Value| {
+        let request = Request::post("/cycles")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+
+    let (status, body) = send(json!({ "repo": "acme/app", "limit": 2 })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["cycles"][1]["files"], json!(["a.py", "b.py"]));
+    assert_eq!(body["truncated"], true);
+    let (status, _) = send(json!({ "repo": "acme/app", "limit": 0 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(json!({ "repo": "acme/missing" })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::symbol;
+use standalone::annotations::{annotate, detect, parse, ANNOTATED_BY, ANNOTATION_KIND, ARGUMENTS};
+
+#[test]
+fn test_decorated_function_yields_an_annotation_and_edge() {
+    let source = "@app.route(\"/users\", methods=[\"GET\"])\n@login_required\ndef users():\n    return []\n\ndef plain():\n    pass\n";
+    let users = symbol("Function", "users", "app.py", 2, 3);
+    let plain = symbol("Function", "plain", "app.py", 5, 6);
+    let (nodes, edges) = annotate("app.py", source, "acme/app", &[&users, &plain], None).unwrap();
+
+    let names: Vec<&str> = nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, ["app.route", "login_required"]);
+    assert!(nodes.iter().all(|n| n.kind == ANNOTATION_KIND));
+    assert_eq!(
+        nodes[0].meta.get(ARGUMENTS).map(String::as_str),
+        Some("(\"/users\", methods=[\"GET\"])")
+    );
+    assert!(nodes[1].meta.is_empty());
+    let span = nodes[0].span.unwrap();
+    assert_eq!(&source[span.start_byte..span.end_byte], nodes[0].body);
+
+    assert_eq!(edges.len(), 2);
+    for (edge, node) in edges.iter().zip(&nodes) {
+        assert_eq!(edge.kind, ANNOTATED_BY);
+        assert_eq!(edge.source, users.id);
+        assert_eq!(edge.target, node.id);
+    }
+}
+
+#[test]
+fn test_attributes_and_java_annotations() {
+    let rust = "#[derive(Debug, Clone)]\n// a comment in between\npub struct User;\n\n#[test]\nfn creates() {}\n";
+    let user = symbol("DataModel", "User", "lib.rs", 2, 2);
+    let creates = symbol("Function", "creates", "lib.rs", 5, 5);
+    let (nodes, edges) = annotate("lib.rs", rust, "acme/app", &[&user, &creates], None).unwrap();
+    let found: Vec<(&str, &str)> = nodes
+        .iter()
+        .zip(&edges)
+        .map(|(n, e)| (n.name.as_str(), e.source.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [("derive", "datamodel-User"), ("test", "function-creates")]
+    );
+
+    let java = "class Api {\n    @GetMapping(\"/users\")\n    public List<User> users(@PathVariable String id) {\n        return null;\n    }\n}\n";
+    let users = symbol("Function", "users", "Api.java", 1, 4);
+    let (nodes, edges) = annotate("Api.java", java, "acme/app", &[&users], None).unwrap();
+    // the parameter's annotation isn't the method's
+    let names: Vec<&str> = nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, ["GetMapping"]);
+    assert_eq!(edges[0].source, "function-users");
+}
+
+#[test]
+fn test_detect_reads_the_checkout() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    std::fs::write(root.join("app.py"), "@cached\ndef users():\n    pass\n").unwrap();
+    std::fs::write(root.join("main.go"), "func main() {}\n").unwrap();
+    let file = root.join("app.py").to_string_lossy().into_owned();
+    let nodes = vec![
+        symbol("File", "app.py", &file, 0, 2),
+        symbol("Function", "users", &file, 1, 2),
+        symbol("Function", "main", "main.go", 0, 0),
+    ];
+
+    let (annotations, edges) = detect(root, "acme/app", &nodes, None);
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0].name, "cached");
+    assert_eq!(annotations[0].file, file);
+    assert_eq!(edges[0].source, "function-users");
+}
+
+#[test]
+fn test_parse_names_and_arguments() {
+    assert_eq!(parse("#[derive(Debug)]"), ("derive", Some("(Debug)")));
+    assert_eq!(parse("#[path = \"x.rs\"]"), ("path", Some("= \"x.rs\"")));
+    assert_eq!(parse("@Override"), ("Override", None));
+    assert_eq!(
+        parse("@Component({ selector: 'app' })"),
+        ("Component", Some("({ selector: 'app' })"))
+    );
+}
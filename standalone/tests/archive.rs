@@ -0,0 +1,130 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use standalone::archive::unpack;
+use std::fs;
+
+const SOURCE: &str = "fn main() {\n    greet();\n}\n\nfn greet() {}\n";
+
+fn tarball(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        // written by hand, as `set_path` refuses the paths these tests need
+        let name = &mut header.as_old_mut().name;
+        name.fill(0);
+        name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_cksum();
+        builder.append(&header, contents.as_bytes()).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+#[test]
+fn test_single_top_level_dir_is_the_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = tarball(&[("app/src/main.rs", SOURCE), ("app/./README.md", "hi")]);
+    let root = unpack(&archive, dir.path()).unwrap();
+    assert_eq!(root, dir.path().join("app"));
+    assert_eq!(
+        fs::read_to_string(root.join("src/main.rs")).unwrap(),
+        SOURCE
+    );
+    assert!(root.join("README.md").is_file());
+}
+
+#[test]
+fn test_entries_outside_the_archive_are_rejected() {
+    for path in ["../escape.rs", "src/../../escape.rs", "/tmp/escape.rs"] {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = tarball(&[("src/main.rs", SOURCE), (path, "pwned")]);
+        let err = unpack(&archive, dir.path()).unwrap_err();
+        assert!(err.to_string().contains(path), "{}: {:#}", path, err);
+    }
+    assert!(unpack(b"not a tarball", tempfile::tempdir().unwrap().path()).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::{tarball, SOURCE};
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn app(storage: Arc<SqliteStorage>) -> axum::Router {
+        standalone::router(Arc::new(AppState::new(
+            storage,
+            LanguageRegistry::new(),
+            1024,
+        )))
+    }
+
+    #[tokio::test]
+    async fn test_uploaded_tarball_is_ingested() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let archive = tarball(&[("sample/src/main.rs", SOURCE)]);
+        let request = Request::post("/ingest?repo_id=acme/upload")
+            .header(header::CONTENT_TYPE, "application/gzip")
+            .body(Body::from(archive))
+            .unwrap();
+        let response = app(storage.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (nodes, _) = storage.load_graph(Some("acme/upload")).await.unwrap();
+        let functions: Vec<&str> = nodes
+            .iter()
+            .filter(|n| n.kind == "Function")
+            .map(|n| n.name.as_str())
+            .collect();
+        assert!(functions.contains(&"greet"), "{:?}", functions);
+        assert!(nodes.iter().any(|n| n.file.ends_with("src/main.rs")));
+    }
+
+    #[tokio::test]
+    async fn test_multipart_upload_is_ingested() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let archive = tarball(&[("src/main.rs", SOURCE)]);
+        let boundary = "mesh-boundary";
+        let mut body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"repo_id\"\r\n\r\nacme/form\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"archive\"; filename=\"src.tar.gz\"\r\n\
+             Content-Type: application/gzip\r\n\r\n",
+            b = boundary
+        )
+        .into_bytes();
+        body.extend_from_slice(&archive);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        let request = Request::post("/ingest")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let response = app(storage.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let (nodes, _) = storage.graph_size(Some("acme/form")).await.unwrap();
+        assert!(nodes > 0);
+    }
+
+    #[tokio::test]
+    async fn test_malicious_tarball_is_refused() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let archive = tarball(&[("src/main.rs", SOURCE), ("../../etc/cron.d/x", "pwned")]);
+        let request = Request::post("/ingest?repo_id=acme/evil")
+            .header(header::CONTENT_TYPE, "application/gzip")
+            .body(Body::from(archive))
+            .unwrap();
+        let response = app(storage.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("steps outside"));
+        assert_eq!(storage.graph_size(Some("acme/evil")).await.unwrap(), (0, 0));
+    }
+}
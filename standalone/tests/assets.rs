@@ -0,0 +1,78 @@
+use standalone::assets::find_static_dir;
+use std::path::Path;
+
+fn static_dir(root: &Path) -> std::path::PathBuf {
+    let dir = root.join("static");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "<html></html>").unwrap();
+    dir
+}
+
+#[test]
+fn test_static_dir_is_found_next_to_the_binary() {
+    let root = tempfile::tempdir().unwrap();
+    let dir = static_dir(root.path());
+    let exe = root.path().join("standalone");
+    assert_eq!(find_static_dir(None, Some(&exe)), Some(dir.clone()));
+    // a cargo build, target/debug/deps/<test binary>
+    let deep = root.path().join("target/debug/deps/standalone-1234");
+    assert_eq!(find_static_dir(None, Some(&deep)), Some(dir));
+}
+
+#[test]
+fn test_configured_static_dir_is_not_searched_past() {
+    let root = tempfile::tempdir().unwrap();
+    let dir = static_dir(root.path());
+    let exe = root.path().join("standalone");
+    assert_eq!(find_static_dir(Some(&dir), None), Some(dir.clone()));
+    let empty = root.path().join("empty");
+    assert_eq!(find_static_dir(Some(&empty), Some(&exe)), None);
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn index(state: AppState) -> (StatusCode, String) {
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let response = standalone::router(Arc::new(state))
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn state() -> AppState {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        AppState::new(storage, LanguageRegistry::new(), 16)
+    }
+
+    // the only test in this binary that touches the working directory
+    #[tokio::test]
+    async fn test_index_is_served_from_any_working_directory() {
+        let cwd = tempfile::tempdir().unwrap();
+        std::env::set_current_dir(cwd.path()).unwrap();
+        let state = state();
+        assert!(state.static_dir.is_some());
+        let (status, body) = index(state).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("app.js"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_static_dir_serves_placeholder() {
+        let mut state = state();
+        state.static_dir = None;
+        let (status, body) = index(state).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("MESH_STATIC_DIR"));
+    }
+}
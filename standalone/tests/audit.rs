@@ -0,0 +1,187 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use common::function_in;
+use serde_json::{json, Value};
+use standalone::audit::{AuditEntry, AuditLog, BACKEND};
+use standalone::auth::{key_id, ApiKeys, Auth, Scope};
+use standalone::lang::LanguageRegistry;
+use standalone::logging::REQUEST_ID;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::Storage;
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const KEY: &str = "writer";
+const ADMIN: &str = "admin";
+
+async fn app(target: &str) -> axum::Router {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    storage
+        .upsert_nodes(&[function_in("acme/app", "render")])
+        .await
+        .unwrap();
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+    state.auth = Some(Arc::new(
+        Auth::new(ApiKeys::new(&[KEY]), &[Scope::Mutating]).with_admin_keys(ApiKeys::new(&[ADMIN])),
+    ));
+    state.audit = Some(Arc::new(AuditLog::open(target).unwrap()));
+    standalone::router(Arc::new(state))
+}
+
+async fn clear(app: &axum::Router, body: Value, request_id: &str) -> StatusCode {
+    let request = Request::post("/clear")
+        .header("Content-Type", "application/json")
+        .header(header::AUTHORIZATION, format!("Bearer {}", KEY))
+        .header(REQUEST_ID, request_id)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.headers()[REQUEST_ID], request_id);
+    response.status()
+}
+
+async fn audit(app: &axum::Router, key: Option<&str>) -> (StatusCode, Vec<AuditEntry>) {
+    let mut request = Request::get("/audit?limit=10");
+    if let Some(key) = key {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", key));
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    if status != StatusCode::OK {
+        return (status, Vec::new());
+    }
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    (
+        status,
+        serde_json::from_value(body["entries"].clone()).unwrap(),
+    )
+}
+
+async fn check_clear_is_audited(app: axum::Router) {
+    let status = clear(
+        &app,
+        json!({"repo_id": "acme/app", "confirm": "acme/app"}),
+        "req-1",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    // refused for want of a confirmation
+    let status = clear(&app, json!({"repo_id": "acme/app"}), "req-2").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    // clearing every repo leaves the log be
+    let status = clear(&app, json!({"confirm": "ALL"}), "req-3").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, entries) = audit(&app, Some(ADMIN)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(entries.len(), 3, "{:?}", entries);
+    let (all, refused, cleared) = (&entries[0], &entries[1], &entries[2]);
+    assert_eq!(cleared.operation, "clear");
+    assert_eq!(cleared.repo.as_deref(), Some("acme/app"));
+    assert_eq!(cleared.identity, key_id(KEY));
+    assert_eq!(cleared.outcome, "succeeded");
+    assert_eq!(cleared.request_id.as_deref(), Some("req-1"));
+    assert!(cleared.timestamp > 0);
+    assert_eq!(refused.outcome, "validation");
+    assert_eq!(refused.request_id.as_deref(), Some("req-2"));
+    assert_eq!(all.repo, None);
+    assert_eq!(all.outcome, "succeeded");
+}
+
+#[tokio::test]
+async fn test_clear_is_audited_to_the_backend() {
+    check_clear_is_audited(app(BACKEND).await).await;
+}
+
+#[tokio::test]
+async fn test_clear_is_audited_to_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.log");
+    check_clear_is_audited(app(path.to_str().unwrap()).await).await;
+    let lines = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(lines.lines().count(), 3);
+}
+
+#[tokio::test]
+async fn test_every_keyed_write_is_audited() {
+    let app = app(BACKEND).await;
+    let writes = [
+        ("/process-file", "process-file"),
+        ("/import/json", "import-json"),
+        ("/cancel", "cancel"),
+        ("/schedule", "schedule"),
+        ("/validate", "validate"),
+        ("/warm", "warm"),
+        ("/retention/pin", "retention-pin"),
+    ];
+    for (path, _) in writes {
+        let request = Request::post(path)
+            .header("Content-Type", "application/json")
+            .header(header::AUTHORIZATION, format!("Bearer {}", KEY))
+            .body(Body::from("{}"))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap();
+    }
+    // asking for a clear token changes nothing
+    let request = Request::get("/clear?repo_id=acme/app")
+        .header(header::AUTHORIZATION, format!("Bearer {}", KEY))
+        .body(Body::empty())
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap();
+
+    let (_, entries) = audit(&app, Some(ADMIN)).await;
+    let operations: Vec<&str> = entries.iter().rev().map(|e| e.operation.as_str()).collect();
+    let expected: Vec<&str> = writes.iter().map(|(_, operation)| *operation).collect();
+    assert_eq!(operations, expected);
+    assert!(entries.iter().all(|e| e.identity == key_id(KEY)));
+}
+
+#[tokio::test]
+async fn test_only_admin_keys_read_the_log() {
+    let app = app(BACKEND).await;
+    assert_eq!(audit(&app, None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(audit(&app, Some(KEY)).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(audit(&app, Some(ADMIN)).await.0, StatusCode::OK);
+
+    // an admin key opens the other routes too
+    let request = Request::post("/clear")
+        .header("Content-Type", "application/json")
+        .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN))
+        .body(Body::from(r#"{"confirm": "ALL"}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let (_, entries) = audit(&app, Some(ADMIN)).await;
+    assert_eq!(entries[0].identity, key_id(ADMIN));
+}
+
+#[tokio::test]
+async fn test_backend_log_is_append_only() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let entry = AuditEntry {
+        timestamp: 1_000,
+        request_id: None,
+        identity: "anonymous".to_string(),
+        operation: "process".to_string(),
+        repo: Some("acme/app".to_string()),
+        outcome: "succeeded".to_string(),
+    };
+    storage.append_audit(&entry).await.unwrap();
+    storage.clear(None).await.unwrap();
+    assert_eq!(storage.audit_entries(10).await.unwrap(), [entry.clone()]);
+    assert!(storage
+        .query_raw("DELETE FROM audit_log", &Default::default())
+        .await
+        .is_err());
+    assert_eq!(storage.audit_entries(10).await.unwrap(), [entry]);
+}
@@ -0,0 +1,166 @@
+use axum::http::{HeaderMap, HeaderValue};
+use standalone::auth::{ApiKeys, Auth, Scope};
+
+fn bearer(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("authorization", HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+#[test]
+fn test_keys_are_verified() {
+    let keys = ApiKeys::new(&["first", " second "]);
+    assert!(keys.verify("first"));
+    assert!(keys.verify("second"));
+    assert!(!keys.verify("firs"));
+    assert!(!keys.verify("first "));
+    assert!(!keys.verify(""));
+}
+
+#[test]
+fn test_only_bearer_tokens_are_accepted() {
+    let auth = Auth::new(ApiKeys::new(&["k3y"]), &[Scope::Mutating]);
+    assert!(auth.authorize(&bearer("Bearer k3y")));
+    assert!(auth.authorize(&bearer("bearer k3y")));
+    assert!(!auth.authorize(&bearer("Basic k3y")));
+    assert!(!auth.authorize(&bearer("k3y")));
+    assert!(!auth.authorize(&bearer("Bearer other")));
+    assert!(!auth.authorize(&HeaderMap::new()));
+    assert!(auth.protects(Scope::Mutating));
+    assert!(!auth.protects(Scope::Events));
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use standalone::auth::{ApiKeys, Auth, Scope};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    const KEY: &str = "test-key";
+
+    fn app(protected: &[Scope]) -> axum::Router {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+        state.auth = Some(Arc::new(Auth::new(ApiKeys::new(&[KEY]), protected)));
+        standalone::router(Arc::new(state))
+    }
+
+    fn clear(key: Option<&str>) -> Request<Body> {
+        let mut request = Request::post("/clear").header("Content-Type", "application/json");
+        if let Some(key) = key {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", key));
+        }
+        request.body(Body::from(r#"{"confirm": "ALL"}"#)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mutating_route_needs_a_key() {
+        let app = app(&[Scope::Mutating]);
+        let response = app.clone().oneshot(clear(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+
+        let response = app.clone().oneshot(clear(Some("wrong"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.oneshot(clear(Some(KEY))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_reads_and_webhooks_need_no_key() {
+        let app = app(&[Scope::Mutating]);
+        let stats = Request::get("/events/stats").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(stats).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let health = Request::get("/healthz").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(health).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // refused for being unconfigured, not for lacking a key
+        let webhook = Request::post("/webhook").body(Body::from("{}")).unwrap();
+        let response = app.oneshot(webhook).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_events_can_be_protected() {
+        let app = app(&[Scope::Mutating, Scope::Events]);
+        let stats = || Request::get("/events/stats");
+        let response = app
+            .clone()
+            .oneshot(stats().body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = stats()
+            .header(header::AUTHORIZATION, format!("Bearer {}", KEY))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_no_keys_leaves_routes_open() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let state = AppState::new(storage, LanguageRegistry::new(), 16);
+        let response = standalone::router(Arc::new(state))
+            .oneshot(clear(None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_raw_statements_need_an_admin_key() {
+        const ADMIN: &str = "admin-key";
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+        state.allow_raw_cypher = true;
+        state.auth = Some(Arc::new(
+            Auth::new(ApiKeys::new(&[KEY]), &[Scope::Mutating])
+                .with_admin_keys(ApiKeys::new(&[ADMIN])),
+        ));
+        let app = standalone::router(Arc::new(state));
+        let raw = |statement: &str, key: Option<&str>| {
+            let mut request =
+                Request::post("/graph/query").header("Content-Type", "application/json");
+            if let Some(key) = key {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", key));
+            }
+            let body = serde_json::json!({ "cypher": statement });
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+
+        // neither no key nor a plain one lets a statement through
+        for key in [None, Some(KEY)] {
+            let request = raw("DELETE FROM nodes", key);
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = app
+            .clone()
+            .oneshot(raw("SELECT 1 AS one", Some(ADMIN)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // templates stay open to everyone
+        let template = Request::post("/graph/query")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                r#"{"query": "callers-of-function", "params": {"name": "main"}}"#,
+            ))
+            .unwrap();
+        let response = app.oneshot(template).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use common::REPO;
+use standalone::storage::batch::BatchingStorage;
+use standalone::storage::unconfigured::Unconfigured;
+use standalone::storage::{EdgeRecord, NodeRecord, Storage};
+use std::sync::Arc;
+
+fn node(name: &str, file: &str) -> NodeRecord {
+    NodeRecord {
+        end: 1,
+        ..common::node("Function", name, file)
+    }
+}
+
+fn edge(source: &NodeRecord, target: &NodeRecord) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: REPO.to_string(),
+        kind: "CALLS".to_string(),
+        source: source.id.clone(),
+        target: target.id.clone(),
+    }
+}
+
+#[tokio::test]
+async fn test_failed_batch_names_the_record() {
+    let storage = BatchingStorage::new(Arc::new(Unconfigured::new("no backend")), 2);
+    storage
+        .upsert_node(&node("main", "src/main.rs"))
+        .await
+        .unwrap();
+    let err = storage
+        .upsert_node(&node("helper", "src/util.rs"))
+        .await
+        .unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("failed to write node function-main-src/main.rs"),
+        "{:#}",
+        err
+    );
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use standalone::storage::sqlite::SqliteStorage;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_writes_are_buffered_until_full() {
+        let sqlite = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let storage = BatchingStorage::new(sqlite.clone(), 3);
+        let (main, helper) = (node("main", "src/main.rs"), node("helper", "src/main.rs"));
+        storage.upsert_node(&main).await.unwrap();
+        storage.upsert_node(&helper).await.unwrap();
+        assert_eq!(sqlite.graph_size(Some(REPO)).await.unwrap(), (0, 0));
+
+        // the third write fills the batch; nodes go first so the edge finds them
+        storage.upsert_edge(&edge(&main, &helper)).await.unwrap();
+        assert_eq!(sqlite.graph_size(Some(REPO)).await.unwrap(), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_reads_and_flush_write_pending_records() {
+        let sqlite = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let storage = BatchingStorage::new(sqlite.clone(), 100);
+        storage
+            .upsert_node(&node("main", "src/main.rs"))
+            .await
+            .unwrap();
+        assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (1, 0));
+
+        storage
+            .upsert_node(&node("helper", "src/main.rs"))
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+        assert_eq!(sqlite.graph_size(Some(REPO)).await.unwrap(), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_partial_batch_is_flushed_on_drop() {
+        let sqlite = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let storage = BatchingStorage::new(sqlite.clone(), 100);
+        let (main, helper) = (node("main", "src/main.rs"), node("helper", "src/main.rs"));
+        storage.upsert_node(&main).await.unwrap();
+        storage.upsert_node(&helper).await.unwrap();
+        storage.upsert_edge(&edge(&main, &helper)).await.unwrap();
+        drop(storage);
+
+        for _ in 0..100 {
+            if sqlite.graph_size(Some(REPO)).await.unwrap() == (2, 1) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("buffered writes were not flushed on drop");
+    }
+}
+
+/// Compares per-record and batched writes of a synthetic 50k-node graph.
+/// Needs a running neo4j: `cargo test --features neo4j -- --ignored --nocapture`.
+#[cfg(feature = "neo4j")]
+#[tokio::test]
+#[ignore]
+async fn bench_neo4j_batched_writes() {
+    use standalone::storage::neo4j::Neo4jStorage;
+    use std::time::Instant;
+
+    let nodes: Vec<NodeRecord> = (0..50_000)
+        .map(|i| node(&format!("f{}", i), &format!("src/m{}.rs", i / 100)))
+        .collect();
+    let edges: Vec<EdgeRecord> = nodes.windows(2).map(|w| edge(&w[0], &w[1])).collect();
+    let neo4j: Arc<dyn Storage> =
+        Arc::new(Neo4jStorage::connect(&Default::default()).await.unwrap());
+
+    neo4j.clear(Some(REPO)).await.unwrap();
+    let started = Instant::now();
+    for n in &nodes {
+        neo4j.upsert_node(n).await.unwrap();
+    }
+    for e in &edges {
+        neo4j.upsert_edge(e).await.unwrap();
+    }
+    let unbatched = started.elapsed();
+
+    neo4j.clear(Some(REPO)).await.unwrap();
+    let storage = BatchingStorage::new(neo4j.clone(), 500);
+    let started = Instant::now();
+    for n in &nodes {
+        storage.upsert_node(n).await.unwrap();
+    }
+    for e in &edges {
+        storage.upsert_edge(e).await.unwrap();
+    }
+    storage.flush().await.unwrap();
+    let batched = started.elapsed();
+
+    assert_eq!(
+        neo4j.graph_size(Some(REPO)).await.unwrap(),
+        (nodes.len(), edges.len())
+    );
+    neo4j.clear(Some(REPO)).await.unwrap();
+    println!("unbatched: {:?}, batched (500): {:?}", unbatched, batched);
+}
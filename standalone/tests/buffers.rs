@@ -0,0 +1,150 @@
+mod common;
+
+use standalone::buffers::{checkout, rebase};
+use standalone::storage::{kind_key, EdgeRecord, NodeRecord};
+use std::fs;
+
+fn node(kind: &str, name: &str, file: &str) -> NodeRecord {
+    NodeRecord {
+        id: kind_key(kind, name, file, 0, None),
+        end: 1,
+        ..common::node(kind, name, file)
+    }
+}
+
+#[test]
+fn test_rebase_moves_files_ids_and_edges() {
+    let scratch = "/tmp/mesh-bufferX1";
+    let mut nodes = vec![
+        node("File", "lib.rs", "/tmp/mesh-bufferX1/src/lib.rs"),
+        node("Function", "greet", "/tmp/mesh-bufferX1/src/lib.rs"),
+        node("Function", "main", "src/main.rs"),
+    ];
+    let mut edges = vec![
+        EdgeRecord {
+            repo_id: "acme/app".to_string(),
+            kind: "CONTAINS".to_string(),
+            source: nodes[0].id.clone(),
+            target: nodes[1].id.clone(),
+        },
+        EdgeRecord {
+            repo_id: "acme/app".to_string(),
+            kind: "CALLS".to_string(),
+            source: nodes[2].id.clone(),
+            target: nodes[1].id.clone(),
+        },
+    ];
+    rebase(&mut nodes, &mut edges, scratch, "/srv/repos/acme/app");
+
+    let greet = node("Function", "greet", "/srv/repos/acme/app/src/lib.rs");
+    assert_eq!(nodes[1].file, greet.file);
+    assert_eq!(nodes[1].id, greet.id);
+    assert_eq!(
+        nodes[0].id,
+        kind_key("File", "lib.rs", &greet.file, 0, None)
+    );
+    // files stored relative to the checkout stay as they are
+    assert_eq!(nodes[2].id, node("Function", "main", "src/main.rs").id);
+    assert_eq!(
+        (&edges[0].source, &edges[0].target),
+        (&nodes[0].id, &greet.id)
+    );
+    assert_eq!(
+        (&edges[1].source, &edges[1].target),
+        (&nodes[2].id, &greet.id)
+    );
+}
+
+#[test]
+fn test_checkout_holds_the_buffer_and_the_manifests_above_it() {
+    let repo = tempfile::tempdir().unwrap();
+    fs::write(repo.path().join("Cargo.toml"), "[workspace]\n").unwrap();
+    fs::create_dir_all(repo.path().join("crates/core/src")).unwrap();
+    fs::write(repo.path().join("crates/core/Cargo.toml"), "[package]\n").unwrap();
+    fs::write(repo.path().join("crates/core/src/lib.rs"), "// on disk\n").unwrap();
+
+    let scratch = checkout(repo.path(), "crates/core/src/lib.rs", "// unsaved\n").unwrap();
+    let read = |file: &str| fs::read_to_string(scratch.path().join(file)).unwrap();
+    assert_eq!(read("crates/core/src/lib.rs"), "// unsaved\n");
+    assert_eq!(read("Cargo.toml"), "[workspace]\n");
+    assert_eq!(read("crates/core/Cargo.toml"), "[package]\n");
+    // the file on disk is left alone
+    assert_eq!(
+        fs::read_to_string(repo.path().join("crates/core/src/lib.rs")).unwrap(),
+        "// on disk\n"
+    );
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_buffer_content_updates_symbols_without_a_file_on_disk() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::buffers::BUFFER_HASH;
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{repo_id, Storage};
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.clone()];
+    let app = standalone::router(Arc::new(state));
+    let repo = repo_id("", &root.display().to_string());
+
+    let send = |content: &str| {
+        let body = json!({ "repo_path": root, "file": "src/lib.rs", "content": content });
+        let request = Request::post("/process-file")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+    let functions = || async {
+        let (nodes, _) = storage.load_graph(Some(&repo)).await.unwrap();
+        let mut names: Vec<(String, String)> = nodes
+            .into_iter()
+            .filter(|n| n.kind == "Function")
+            .map(|n| (n.name, n.file))
+            .collect();
+        names.sort();
+        names
+    };
+
+    let (status, body) = send("pub fn greet() -> &'static str {\n    \"hi\"\n}\n").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["status"], "success");
+    assert!(!root.join("src/lib.rs").exists());
+    let found = functions().await;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, "greet");
+    assert!(found[0].1.ends_with("src/lib.rs"));
+    assert!(!found[0].1.contains("mesh-buffer"), "{}", found[0].1);
+
+    // the next edit of the buffer replaces its symbols
+    let (status, body) = send("pub fn farewell() {}\n\npub fn wave() {}\n").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["removed"], 1);
+    let names: Vec<String> = functions().await.into_iter().map(|(n, _)| n).collect();
+    assert_eq!(names, ["farewell", "wave"]);
+
+    // an ingest from disk parses the file again rather than keep the buffer
+    let hashes = storage.file_hashes(&repo).await.unwrap();
+    assert_eq!(hashes["src/lib.rs"], BUFFER_HASH);
+}
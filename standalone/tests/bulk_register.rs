@@ -0,0 +1,82 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use common::git;
+use serde_json::{json, Value};
+use standalone::clones::CloneDir;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::Storage;
+use standalone::AppState;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tower::ServiceExt;
+
+fn remote(dir: &Path, name: &str) -> String {
+    let source = dir.join("acme").join(name);
+    fs::create_dir_all(&source).unwrap();
+    git(&source, &["init", "-q", "-b", "main"]);
+    fs::write(source.join("main.rs"), "fn main() {}\n").unwrap();
+    git(&source, &["add", "."]);
+    git(&source, &["commit", "-q", "-m", "main"]);
+    source.to_string_lossy().into_owned()
+}
+
+#[tokio::test]
+async fn test_manifest_ingests_each_repo_and_reports_the_bad_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let app_url = remote(dir.path(), "app");
+    let missing_url = dir.path().join("acme/missing").display().to_string();
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 64);
+    state.clones = Arc::new(CloneDir::new(dir.path().join("clones"), 0, false));
+    let mut rx = state.tx.subscribe();
+    let app = standalone::router(Arc::new(state));
+
+    let manifest = json!({ "repos": [
+        { "url": app_url },
+        { "url": missing_url },
+        { "url": "" },
+    ]});
+    let request = Request::post("/register")
+        .header("Content-Type", "application/json")
+        .body(Body::from(manifest.to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["queued"], 2);
+    assert_eq!(body["repos"][0]["repo_id"], "acme/app");
+    assert_eq!(body["repos"][1]["status"], "queued");
+    // one that names no repo at all is turned away straight off
+    assert_eq!(body["repos"][2]["status"], "rejected");
+    assert!(body["repos"][2]["error"].is_string());
+
+    let mut outcomes = Vec::new();
+    while outcomes.len() < 2 {
+        let event = tokio::time::timeout(Duration::from_secs(30), rx.recv())
+            .await
+            .expect("the manifest never finished")
+            .unwrap();
+        if event.update.status.starts_with("bulk_") {
+            assert_eq!(event.total, Some(2));
+            outcomes.push((event.repo_id.unwrap(), event.update.status));
+        }
+    }
+    outcomes.sort();
+    assert_eq!(
+        outcomes,
+        [
+            ("acme/app".to_string(), "bulk_ingested".to_string()),
+            ("acme/missing".to_string(), "bulk_failed".to_string()),
+        ]
+    );
+    let (nodes, _) = storage.graph_size(Some("acme/app")).await.unwrap();
+    assert!(nodes > 0);
+}
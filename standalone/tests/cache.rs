@@ -0,0 +1,239 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use common::git;
+use standalone::lang::LanguageRegistry;
+use standalone::local::{content_hash, directory_hashes, hash_files, walk};
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{repo_id, Storage};
+use standalone::AppState;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const SOURCE: &str = "fn main() {\n    greet();\n}\n\nfn greet() {}\n";
+
+fn sample_repo(root: &Path) {
+    fs::write(root.join("main.rs"), SOURCE).unwrap();
+    git(root, &["init", "-q", "-b", "main"]);
+    git(root, &["add", "."]);
+    git(root, &["commit", "-q", "-m", "init"]);
+}
+
+fn process(root: &Path, force: bool) -> Request<Body> {
+    let body = serde_json::json!({ "repo_path": root, "force": force }).to_string();
+    Request::post("/process")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[test]
+fn test_hash_files_skips_missing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("main.rs"), SOURCE).unwrap();
+    let files = vec!["main.rs".to_string(), "gone.rs".to_string()];
+    let hashes = hash_files(dir.path(), &files);
+    assert_eq!(
+        hashes,
+        vec![("main.rs".to_string(), content_hash(SOURCE.as_bytes()))]
+    );
+    assert_ne!(content_hash(b"a"), content_hash(b"b"));
+}
+
+#[tokio::test]
+async fn test_unchanged_file_is_not_parsed_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    sample_repo(&root);
+    let repo = repo_id("", root.to_str().unwrap());
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    // as a previous run would have left it, minus the commit hash
+    let hashes = [("main.rs".to_string(), content_hash(SOURCE.as_bytes()))];
+    storage.set_file_hashes(&repo, &hashes).await.unwrap();
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    state.allowed_roots = vec![root.clone()];
+    let mut events = state.tx.subscribe();
+
+    let response = standalone::router(Arc::new(state))
+        .oneshot(process(&root, false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["nodes"], 0);
+
+    let mut statuses = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        statuses.push(event.update.status);
+    }
+    assert_eq!(statuses, vec!["cached"]);
+    assert_eq!(storage.graph_size(Some(&repo)).await.unwrap(), (0, 0));
+}
+
+#[tokio::test]
+async fn test_force_parses_unchanged_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    sample_repo(&root);
+    let repo = repo_id("", root.to_str().unwrap());
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let hashes = [("main.rs".to_string(), content_hash(SOURCE.as_bytes()))];
+    storage.set_file_hashes(&repo, &hashes).await.unwrap();
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    state.allowed_roots = vec![root.clone()];
+    let mut events = state.tx.subscribe();
+
+    let response = standalone::router(Arc::new(state))
+        .oneshot(process(&root, true))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    while let Ok(event) = events.try_recv() {
+        assert_ne!(event.update.status, "cached");
+    }
+    let (nodes, _) = storage.graph_size(Some(&repo)).await.unwrap();
+    assert!(nodes > 0);
+    assert_eq!(storage.file_hashes(&repo).await.unwrap(), hashes.into());
+}
+
+#[tokio::test]
+async fn test_dry_run_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("notes.bin"), [0u8, 159, 146, 150]).unwrap();
+    sample_repo(&root);
+    let repo = repo_id("", root.to_str().unwrap());
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.clone()];
+    let mut events = state.tx.subscribe();
+
+    let body = serde_json::json!({ "repo_path": root, "dry_run": true }).to_string();
+    let request = Request::post("/process")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["status"], "dry_run");
+    assert!(body["nodes"].as_u64().unwrap() > 0);
+
+    let mut summary = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if event.update.status == "dry_run" {
+            summary.push(event.update.message);
+        }
+    }
+    assert!(
+        summary.contains(&"Would parse 1 files".to_string()),
+        "{:?}",
+        summary
+    );
+    assert!(summary
+        .iter()
+        .any(|line| line.starts_with("Languages detected: rust")));
+    assert!(summary.contains(&"Skipped 1 files: notes.bin".to_string()));
+
+    assert_eq!(storage.graph_size(Some(&repo)).await.unwrap(), (0, 0));
+    assert!(storage.file_hashes(&repo).await.unwrap().is_empty());
+}
+
+/// A repo with a vendored dependency next to its own code.
+fn vendored_repo(root: &Path) {
+    fs::create_dir_all(root.join("vendor/dep")).unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join("vendor/dep/lib.rs"), "pub fn vendored() {}\n").unwrap();
+    fs::write(root.join("src/app.rs"), "pub fn app() {}\n").unwrap();
+    git(root, &["init", "-q", "-b", "main"]);
+    git(root, &["add", "."]);
+    git(root, &["commit", "-q", "-m", "init"]);
+}
+
+#[test]
+fn test_directory_hashes_leave_out_changed_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    vendored_repo(&root);
+    let hashes = directory_hashes(&root, false);
+    let dirs: Vec<&str> = hashes.keys().map(String::as_str).collect();
+    assert_eq!(dirs, ["src/", "vendor/", "vendor/dep/"]);
+
+    fs::write(root.join("vendor/dep/lib.rs"), "pub fn patched() {}\n").unwrap();
+    let dirty = directory_hashes(&root, false);
+    assert_eq!(dirty.keys().collect::<Vec<_>>(), ["src/"]);
+    assert_eq!(dirty["src/"], hashes["src/"]);
+    // a checkout the server owns is taken at its word
+    assert_eq!(directory_hashes(&root, true), hashes);
+    assert!(directory_hashes(&root.join("src"), false).is_empty());
+}
+
+#[tokio::test]
+async fn test_unchanged_directory_is_skipped_whole() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    vendored_repo(&root);
+    let repo = repo_id("", root.to_str().unwrap());
+
+    // as a previous run would have left it, minus the commit hash
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut hashes = hash_files(&root, &walk(&root).unwrap());
+    hashes.extend(directory_hashes(&root, false));
+    storage.set_file_hashes(&repo, &hashes).await.unwrap();
+
+    fs::write(
+        root.join("src/app.rs"),
+        "pub fn app() {}\n\npub fn extra() {}\n",
+    )
+    .unwrap();
+    git(&root, &["commit", "-q", "-am", "extra"]);
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.clone()];
+    let mut events = state.tx.subscribe();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(process(&root, false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut cached = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if event.update.status == "cached" {
+            cached.push(event.update.message);
+        }
+    }
+    assert_eq!(
+        cached,
+        ["vendor/ is unchanged, reusing the graph of its 1 files"]
+    );
+    let (nodes, _) = storage.load_graph(Some(&repo)).await.unwrap();
+    let mut functions: Vec<&str> = nodes
+        .iter()
+        .filter(|n| n.kind == "Function")
+        .map(|n| n.name.as_str())
+        .collect();
+    functions.sort();
+    assert_eq!(functions, ["app", "extra"]);
+
+    let stored = storage.file_hashes(&repo).await.unwrap();
+    let now = directory_hashes(&root, false);
+    assert_eq!(stored["src/"], now["src/"]);
+    assert_eq!(stored["vendor/"], now["vendor/"]);
+    assert_ne!(
+        stored["src/app.rs"],
+        hashes.iter().find(|(f, _)| f == "src/app.rs").unwrap().1
+    );
+}
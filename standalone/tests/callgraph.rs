@@ -0,0 +1,332 @@
+mod common;
+
+use standalone::callgraph::{
+    call_chains, references, resolve_calls, CallChain, CallGraph, Direction, FunctionCalls,
+};
+use standalone::storage::{EdgeRecord, NodeRecord};
+
+fn node(kind: &str, name: &str, file: &str, body: &str) -> NodeRecord {
+    NodeRecord {
+        body: body.to_string(),
+        ..common::node(kind, name, file)
+    }
+}
+
+fn function<'a>(graph: &'a CallGraph, name: &str) -> &'a FunctionCalls {
+    graph.functions.iter().find(|f| f.name == name).unwrap()
+}
+
+fn sample() -> Vec<NodeRecord> {
+    vec![
+        node(
+            "Function",
+            "main",
+            "acme/app/src/main.rs",
+            "fn main() {\n    let cfg = load();\n    util::render(cfg);\n    serde_json::to_string(&cfg);\n}",
+        ),
+        node("Function", "load", "acme/app/src/main.rs", "fn load() -> Config {}"),
+        node("Function", "render", "acme/app/src/util.rs", "fn render(cfg: Config) {}"),
+        node("Import", "use util", "acme/app/src/main.rs", "mod util;"),
+    ]
+}
+
+#[test]
+fn test_same_file_call() {
+    let graph = resolve_calls(&sample(), &[]);
+    let main = function(&graph, "main");
+    let load = main.calls.iter().find(|c| c.name == "load").unwrap();
+    assert_eq!(load.candidates.len(), 1);
+    assert_eq!(load.candidates[0].file, "acme/app/src/main.rs");
+    assert_eq!(load.candidates[0].confidence, 1.0);
+}
+
+#[test]
+fn test_cross_file_call() {
+    let graph = resolve_calls(&sample(), &[]);
+    let main = function(&graph, "main");
+    let render = main.calls.iter().find(|c| c.name == "render").unwrap();
+    assert_eq!(render.candidates.len(), 1);
+    assert_eq!(render.candidates[0].file, "acme/app/src/util.rs");
+    // main.rs imports util, so this is better than a blind name match
+    assert!(render.candidates[0].confidence > 0.5);
+}
+
+#[test]
+fn test_unresolved_external_call() {
+    let graph = resolve_calls(&sample(), &[]);
+    let main = function(&graph, "main");
+    assert_eq!(main.unresolved, vec!["to_string".to_string()]);
+    assert!(main.calls.iter().all(|c| c.name != "to_string"));
+    // its own signature is not a call
+    assert!(main.calls.iter().all(|c| c.name != "main"));
+}
+
+#[test]
+fn test_ambiguous_call_returns_all_candidates() {
+    let mut nodes = sample();
+    nodes.push(node(
+        "Function",
+        "render",
+        "acme/app/src/a.rs",
+        "fn render() {}",
+    ));
+    nodes.push(node(
+        "Function",
+        "render",
+        "acme/app/src/b.rs",
+        "fn render() {}",
+    ));
+    let graph = resolve_calls(&nodes, &[]);
+    let render = function(&graph, "main")
+        .calls
+        .iter()
+        .find(|c| c.name == "render")
+        .unwrap();
+    assert_eq!(render.candidates.len(), 3);
+    assert_eq!(render.candidates[0].file, "acme/app/src/util.rs");
+    assert_eq!(render.candidates[1].confidence, 0.25);
+}
+
+#[test]
+fn test_known_edges_and_reachability() {
+    let mut nodes = sample();
+    let orphan = node(
+        "Function",
+        "orphan",
+        "acme/app/src/util.rs",
+        "fn orphan() { helper() }",
+    );
+    let helper = node(
+        "Function",
+        "helper",
+        "acme/app/src/util.rs",
+        "fn helper() {}",
+    );
+    let edge = EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: "CALLS".to_string(),
+        source: nodes[2].id.clone(),
+        target: helper.id.clone(),
+    };
+    nodes.push(orphan);
+    nodes.push(helper);
+
+    let graph = resolve_calls(&nodes, &[edge]);
+    assert_eq!(function(&graph, "render").calls[0].name, "helper");
+
+    let reachable = graph.reachable_from("main");
+    let mut names: Vec<&str> = reachable
+        .functions
+        .iter()
+        .map(|f| f.name.as_str())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["helper", "load", "main", "render"]);
+}
+
+#[test]
+fn test_references_from_two_files() {
+    let mut nodes = sample();
+    let mut report = node(
+        "Function",
+        "report",
+        "acme/app/src/report.rs",
+        "fn report() {\n    let cfg = load();\n    render(cfg);\n    render(cfg);\n}",
+    );
+    report.start = 10;
+    nodes.push(report);
+    nodes.push(node(
+        "Import",
+        "use util",
+        "acme/app/src/report.rs",
+        "use crate::util::render;",
+    ));
+
+    let graph = resolve_calls(&nodes, &[]);
+    let usages = references(&graph, &nodes, "render");
+    assert_eq!(usages.len(), 1);
+    assert_eq!(usages[0].file, "acme/app/src/util.rs");
+    let callers: Vec<(&str, &[usize])> = usages[0]
+        .references
+        .iter()
+        .map(|r| (r.file.as_str(), r.lines.as_slice()))
+        .collect();
+    assert_eq!(
+        callers,
+        vec![
+            ("acme/app/src/main.rs", &[3][..]),
+            ("acme/app/src/report.rs", &[13, 14][..]),
+        ]
+    );
+    // the definition itself is not a reference
+    assert!(usages[0].references.iter().all(|r| r.name != "render"));
+}
+
+#[test]
+fn test_references_of_a_shared_name_are_grouped_per_definition() {
+    let nodes = vec![
+        node("Function", "parse", "acme/app/src/json.rs", "fn parse() {}"),
+        node("Function", "parse", "acme/app/src/args.rs", "fn parse() {}"),
+        node(
+            "Function",
+            "decode",
+            "acme/app/src/json.rs",
+            "fn decode() {\n    parse()\n}",
+        ),
+        node(
+            "Function",
+            "cli",
+            "acme/app/src/args.rs",
+            "fn cli() {\n    parse()\n}",
+        ),
+        node(
+            "Function",
+            "run",
+            "acme/app/src/main.rs",
+            "fn run() { parse() }",
+        ),
+    ];
+    let graph = resolve_calls(&nodes, &[]);
+    let usages = references(&graph, &nodes, "parse");
+    assert_eq!(usages.len(), 2);
+
+    let callers = |file: &str| -> Vec<(String, f32)> {
+        usages
+            .iter()
+            .find(|u| u.file == file)
+            .unwrap()
+            .references
+            .iter()
+            .map(|r| (r.name.clone(), r.confidence))
+            .collect()
+    };
+    // each file's own caller is the likeliest, the one in main.rs could be either
+    assert_eq!(
+        callers("acme/app/src/json.rs"),
+        vec![
+            ("decode".to_string(), 1.0),
+            ("cli".to_string(), 0.5),
+            ("run".to_string(), 0.25)
+        ]
+    );
+    assert_eq!(
+        callers("acme/app/src/args.rs"),
+        vec![
+            ("cli".to_string(), 1.0),
+            ("decode".to_string(), 0.5),
+            ("run".to_string(), 0.25)
+        ]
+    );
+    assert!(references(&graph, &nodes, "missing").is_empty());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_references_endpoint() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage.upsert_nodes(&sample()).await.unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let request = |name: &str| {
+        Request::post("/references")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "name": name, "repo": "acme/app" }).to_string(),
+            ))
+            .unwrap()
+    };
+    let response = app.clone().oneshot(request("load")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let references = &body["definitions"][0]["references"];
+    assert_eq!(references[0]["name"], "main");
+    assert_eq!(references[0]["lines"], serde_json::json!([2]));
+
+    let response = app.oneshot(request("to_string")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+fn chain_sample() -> Vec<NodeRecord> {
+    vec![
+        node(
+            "Function",
+            "main",
+            "acme/app/src/main.rs",
+            "fn main() {\n    serve();\n}",
+        ),
+        node(
+            "Function",
+            "serve",
+            "acme/app/src/main.rs",
+            "fn serve() {\n    walk();\n}",
+        ),
+        node(
+            "Function",
+            "walk",
+            "acme/app/src/main.rs",
+            "fn walk() {\n    visit();\n    walk();\n}",
+        ),
+        node(
+            "Function",
+            "visit",
+            "acme/app/src/main.rs",
+            "fn visit() {\n    walk();\n}",
+        ),
+    ]
+}
+
+fn reached(chain: &CallChain) -> Vec<(&str, usize)> {
+    chain
+        .functions
+        .iter()
+        .map(|f| (f.name.as_str(), f.distance))
+        .collect()
+}
+
+#[test]
+fn test_direct_callers() {
+    let graph = resolve_calls(&chain_sample(), &[]);
+    let chains = call_chains(&graph, "serve", Direction::Callers, 1);
+    assert_eq!(chains.len(), 1);
+    assert_eq!(reached(&chains[0]), [("main", 1)]);
+    assert!(!chains[0].recursive);
+    assert!(call_chains(&graph, "main", Direction::Callers, 3)[0]
+        .functions
+        .is_empty());
+}
+
+#[test]
+fn test_transitive_callers_carry_their_distance() {
+    let graph = resolve_calls(&chain_sample(), &[]);
+    let chain = &call_chains(&graph, "visit", Direction::Callers, 2)[0];
+    // walk calls visit, serve calls walk; main is a third hop out
+    assert_eq!(reached(chain), [("walk", 1), ("serve", 2)]);
+    assert_eq!(chain.functions[1].via, chain.functions[0].id);
+
+    let chain = &call_chains(&graph, "main", Direction::Callees, 2)[0];
+    assert_eq!(reached(chain), [("serve", 1), ("walk", 2)]);
+}
+
+#[test]
+fn test_recursion_ends_the_chain() {
+    let graph = resolve_calls(&chain_sample(), &[]);
+    let chain = &call_chains(&graph, "walk", Direction::Callees, 10)[0];
+    // walk calls itself and visit calls it back, yet each is listed once
+    assert_eq!(reached(chain), [("visit", 1)]);
+    assert!(chain.recursive);
+
+    let chain = &call_chains(&graph, "walk", Direction::Callers, 10)[0];
+    assert_eq!(reached(chain), [("serve", 1), ("visit", 1), ("main", 2)]);
+    assert!(chain.recursive);
+}
@@ -0,0 +1,184 @@
+use standalone::ingests::{Cancel, IngestStatus, Ingests};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+#[test]
+fn test_cancel_follows_the_ingest_lifecycle() {
+    let ingests = Arc::new(Ingests::default());
+    let shutdown = CancellationToken::new();
+    assert_eq!(ingests.cancel("acme/app"), Cancel::Unknown);
+
+    let first = ingests.start("acme/app", &shutdown);
+    let second = ingests.start("acme/app", &shutdown);
+    assert!(ingests.is_running("acme/app"));
+    assert_eq!(ingests.cancel("acme/app"), Cancel::Cancelled);
+    assert!(first.token().is_cancelled() && second.token().is_cancelled());
+
+    drop(first);
+    assert!(ingests.is_running("acme/app"));
+    drop(second);
+    assert!(!ingests.is_running("acme/app"));
+    assert_eq!(ingests.cancel("acme/app"), Cancel::Finished);
+
+    // a new ingest of the same repo starts uncancelled
+    let again = ingests.start("acme/app", &shutdown);
+    assert!(!again.token().is_cancelled());
+    assert_eq!(ingests.cancel("acme/other"), Cancel::Unknown);
+}
+
+#[test]
+fn test_status_follows_the_last_run() {
+    let ingests = Arc::new(Ingests::default());
+    let shutdown = CancellationToken::new();
+    assert_eq!(ingests.status("acme/app"), IngestStatus::Idle);
+
+    let first = ingests.start("acme/app", &shutdown);
+    let second = ingests.start("acme/app", &shutdown);
+    assert_eq!(ingests.status("acme/app"), IngestStatus::Ingesting);
+    first.succeeded();
+    assert_eq!(ingests.status("acme/app"), IngestStatus::Ingesting);
+    // one of the run's ingests failing fails the run
+    drop(second);
+    assert_eq!(ingests.status("acme/app"), IngestStatus::Failed);
+
+    ingests.start("acme/app", &shutdown).succeeded();
+    assert_eq!(ingests.status("acme/app"), IngestStatus::Idle);
+    assert_eq!(ingests.repo_ids(), vec!["acme/app"]);
+}
+
+#[test]
+fn test_shutdown_cancels_running_ingests() {
+    let ingests = Arc::new(Ingests::default());
+    let shutdown = CancellationToken::new();
+    let ingest = ingests.start("acme/app", &shutdown);
+    shutdown.cancel();
+    assert!(ingest.token().is_cancelled());
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time::timeout;
+    use tower::ServiceExt;
+
+    fn state(root: &Path) -> AppState {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+        state.allowed_roots = vec![root.to_path_buf()];
+        state
+    }
+
+    fn post(uri: &str, body: serde_json::Value) -> Request<Body> {
+        Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn sample_repo(root: &Path, files: usize) {
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"sample\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        for i in 0..files {
+            fs::write(
+                root.join(format!("src/m{}.rs", i)),
+                format!(
+                    "pub fn f{}() {{\n    g{}();\n}}\n\nfn g{}() {{}}\n",
+                    i, i, i
+                ),
+            )
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_unknown_and_finished_ingests() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        sample_repo(&root, 1);
+        let app = standalone::router(Arc::new(state(&root)));
+        let repo_id = "acme/app";
+
+        let response = app
+            .clone()
+            .oneshot(post("/cancel", serde_json::json!({ "repo_id": repo_id })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = serde_json::json!({ "path": root, "repo_id": repo_id });
+        let response = app
+            .clone()
+            .oneshot(post("/ingest-path", body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(post("/cancel", serde_json::json!({ "repo_id": repo_id })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_ingest_stops_promptly() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        sample_repo(&root, 500);
+        let state = state(&root);
+        let mut events = state.tx.subscribe();
+        let app = standalone::router(Arc::new(state));
+        let repo_id = "acme/big";
+
+        let body = serde_json::json!({ "path": root, "repo_id": repo_id });
+        let ingest = tokio::spawn(app.clone().oneshot(post("/ingest-path", body)));
+        let first = timeout(Duration::from_secs(10), events.recv())
+            .await
+            .expect("the ingest sent no events")
+            .unwrap();
+        assert_eq!(first.repo_id.as_deref(), Some(repo_id));
+
+        let response = app
+            .oneshot(post("/cancel", serde_json::json!({ "repo_id": repo_id })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let cancelled = timeout(Duration::from_secs(2), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.update.status == "cancelled" {
+                    return event;
+                }
+            }
+        })
+        .await
+        .expect("no cancelled event after /cancel");
+        assert_eq!(cancelled.repo_id.as_deref(), Some(repo_id));
+
+        let response = timeout(Duration::from_secs(2), ingest)
+            .await
+            .expect("the ingest kept running")
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["kind"], "cancelled");
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(events.try_recv().is_err(), "events after the cancellation");
+    }
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use standalone::captures::{CustomNode, CustomQueries, EdgeDefinition};
+use standalone::lang::{Extraction, LanguageRegistry};
+use standalone::storage::{records_from_plugins, NodeRecord, Span};
+use std::path::Path;
+
+const ROUTES: &str = r#"
+(decorated_definition
+  (decorator
+    (call
+      function: (attribute attribute: (identifier) @_verb)
+      arguments: (argument_list . (string (string_content) @name))))
+  (#eq? @_verb "route")) @definition
+"#;
+
+const APP: &str = r#"from flask import Flask
+
+app = Flask(__name__)
+
+@app.route("/users")
+def users():
+    return []
+
+@cache.memoize(60)
+def expensive():
+    return 1
+"#;
+
+const HANDLES: &str = r#"
+(decorated_definition
+  (decorator
+    (call
+      function: (attribute attribute: (identifier) @_verb)
+      arguments: (argument_list . (string (string_content) @route))))
+  definition: (function_definition name: (identifier) @handler)
+  (#eq? @_verb "route"))
+"#;
+
+fn handles(source: &str, target: &str) -> EdgeDefinition {
+    EdgeDefinition {
+        edge: "HANDLES".to_string(),
+        language: "python".to_string(),
+        query: HANDLES.to_string(),
+        source: source.to_string(),
+        target: target.to_string(),
+    }
+}
+
+fn query_dir(queries: &[(&str, &str)]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (path, query) in queries {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, query).unwrap();
+    }
+    dir
+}
+
+fn load_error(queries: &[(&str, &str)]) -> String {
+    let dir = query_dir(queries);
+    match CustomQueries::load(dir.path()) {
+        Ok(_) => panic!("loaded {:?}", queries),
+        Err(e) => format!("{:#}", e),
+    }
+}
+
+#[test]
+fn test_custom_query_extracts_nodes_of_its_kind() {
+    let dir = query_dir(&[("python/Route.scm", ROUTES), ("python/README.md", "notes")]);
+    let mut registry = LanguageRegistry::new();
+    registry.set_custom_queries(CustomQueries::load(dir.path()).unwrap());
+    assert!(registry.claims(Path::new("app.py")));
+    assert!(!registry.claims(Path::new("main.rs")));
+
+    let extraction = registry.extract("app.py", APP).unwrap();
+    assert!(extraction.nodes.is_empty());
+    assert_eq!(extraction.custom.len(), 1, "{:?}", extraction.custom);
+    let route = &extraction.custom[0];
+    assert_eq!(route.kind, "Route");
+    assert_eq!(route.name, "/users");
+    assert_eq!(route.file, "app.py");
+    assert_eq!((route.start, route.end), (4, 6));
+    assert!(route
+        .body
+        .starts_with("@app.route(\"/users\")\ndef users():"));
+    assert_eq!(&APP[route.span.start_byte..route.span.end_byte], route.body);
+
+    let files = tempfile::tempdir().unwrap();
+    std::fs::write(files.path().join("app.py"), APP).unwrap();
+    std::fs::write(files.path().join("notes.txt"), APP).unwrap();
+    let extraction = registry
+        .extract_dir(files.path().to_str().unwrap())
+        .unwrap();
+    assert_eq!(extraction.custom.len(), 1);
+}
+
+#[test]
+fn test_invalid_queries_fail_at_load() {
+    let message = load_error(&[("python/Broken.scm", "(function_definition name: @name")]);
+    assert!(message.contains("Broken.scm"), "{}", message);
+    assert!(message.contains("Query error"), "{}", message);
+
+    let message = load_error(&[("python/Node.scm", "(no_such_node) @name")]);
+    assert!(message.contains("no_such_node"), "{}", message);
+
+    let message = load_error(&[("python/Unnamed.scm", "(function_definition) @definition")]);
+    assert!(message.contains("captures no @name"), "{}", message);
+
+    let message = load_error(&[("python/my-route.scm", ROUTES)]);
+    assert!(
+        message.contains("invalid node kind 'my-route'"),
+        "{}",
+        message
+    );
+
+    let message = load_error(&[("cobol/Route.scm", ROUTES)]);
+    assert!(message.contains("unknown language 'cobol'"), "{}", message);
+}
+
+#[test]
+fn test_custom_nodes_join_the_file_node_ast_made() {
+    let file = NodeRecord {
+        id: "file-apppy-acmeappapppy-0".to_string(),
+        ..common::node("File", "app.py", "acme/app/app.py")
+    };
+    let custom = |file: &str| CustomNode {
+        kind: "Route".to_string(),
+        name: "/users".to_string(),
+        file: file.to_string(),
+        body: "@app.route(\"/users\")".to_string(),
+        start: 4,
+        end: 6,
+        span: Span::default(),
+    };
+    let extraction = Extraction {
+        custom: vec![custom("app.py"), custom("other.py")],
+        ..Default::default()
+    };
+
+    let (nodes, edges) = records_from_plugins(&extraction, "acme/app", &[file.clone()], "acme/app");
+    let kinds: Vec<(&str, &str)> = nodes
+        .iter()
+        .map(|n| (n.kind.as_str(), n.file.as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("Route", "acme/app/app.py"),
+            ("File", "other.py"),
+            ("Route", "other.py")
+        ]
+    );
+    assert_eq!(edges[0].source, file.id);
+    assert_eq!(edges[0].target, nodes[0].id);
+    assert_eq!(edges[1].source, nodes[1].id);
+}
+
+#[test]
+fn test_custom_edges_link_the_nodes_their_captures_fall_in() {
+    let dir = query_dir(&[("python/Route.scm", ROUTES)]);
+    let mut queries = CustomQueries::load(dir.path()).unwrap();
+    queries
+        .define_edges(&[handles("handler", "@route")])
+        .unwrap();
+    let mut registry = LanguageRegistry::new();
+    registry.set_custom_queries(queries);
+    let extraction = registry.extract("app.py", APP).unwrap();
+    assert_eq!(
+        extraction.custom_edges.len(),
+        1,
+        "{:?}",
+        extraction.custom_edges
+    );
+    assert_eq!(extraction.custom_edges[0].source.text, "users");
+    assert_eq!(extraction.custom_edges[0].target.text, "/users");
+
+    let function = |name: &str, start: usize, end: usize| {
+        common::symbol("Function", name, "acme/app/app.py", start, end)
+    };
+    let graph = [function("users", 5, 6), function("expensive", 9, 10)];
+    let (nodes, edges) = records_from_plugins(&extraction, "acme/app", &graph, "acme/app");
+    let route = nodes.iter().find(|n| n.kind == "Route").unwrap();
+    let handles: Vec<(&str, &str)> = edges
+        .iter()
+        .filter(|e| e.kind == "HANDLES")
+        .map(|e| (e.source.as_str(), e.target.as_str()))
+        .collect();
+    assert_eq!(handles, [("function-users", route.id.as_str())]);
+}
+
+#[test]
+fn test_custom_edges_with_unknown_captures_fail_to_load() {
+    let message = |definition: EdgeDefinition| {
+        let mut queries = CustomQueries::default();
+        format!("{:#}", queries.define_edges(&[definition]).unwrap_err())
+    };
+    let error = message(handles("handler", "path"));
+    assert!(error.contains("captures no @path"), "{}", error);
+    let error = message(handles("caller", "route"));
+    assert!(error.contains("captures no @caller"), "{}", error);
+
+    let error = message(EdgeDefinition {
+        edge: "handles-route".to_string(),
+        ..handles("handler", "route")
+    });
+    assert!(error.contains("invalid edge kind"), "{}", error);
+    let error = message(EdgeDefinition {
+        language: "cobol".to_string(),
+        ..handles("handler", "route")
+    });
+    assert!(error.contains("unknown language 'cobol'"), "{}", error);
+    let error = message(EdgeDefinition {
+        query: "(function_definition name: @handler".to_string(),
+        ..handles("handler", "route")
+    });
+    assert!(
+        error.contains("invalid query for edge HANDLES"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use common::git;
+use standalone::analysis::{range_diff, RangeChange, SymbolChange};
+use standalone::clone::{range_changes, resolve_commit};
+use standalone::storage::NodeRecord;
+use std::path::Path;
+
+const FIRST: &str =
+    "fn greet() {\n    println!(\"hi\");\n}\n\nfn helper() -> u32 {\n    1\n}\n\nfn legacy() {}\n";
+const SECOND: &str = "fn greet() {\n    println!(\"hello\");\n}\n\nfn helper() -> u32 {\n    1\n}\n\nfn legacy() {}\n";
+
+/// Writes `files` and commits them, returning the commit id.
+fn commit(dir: &Path, files: &[(&str, &str)], message: &str) -> String {
+    for (file, source) in files {
+        std::fs::write(dir.join(file), source).unwrap();
+    }
+    git(dir, &["add", "-A"]);
+    git(dir, &["commit", "-q", "-m", message]);
+    resolve_commit(dir, "HEAD").unwrap()
+}
+
+/// The functions in `source` as `ast` would store them: `fn` up to the next
+/// line that closes a brace, or the one line when it closes itself.
+fn functions(file: &str, source: &str) -> Vec<NodeRecord> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut nodes = Vec::new();
+    for (start, line) in lines.iter().enumerate() {
+        let Some(rest) = line.strip_prefix("fn ") else {
+            continue;
+        };
+        let name = rest.split(['(', ' ']).next().unwrap();
+        let end = (start..lines.len())
+            .find(|&n| lines[n].ends_with('}'))
+            .unwrap();
+        nodes.push(NodeRecord {
+            id: format!("function-{}-{}-{}", name, file, start),
+            start,
+            end,
+            body: lines[start..=end].join("\n"),
+            ..common::node("Function", name, file)
+        });
+    }
+    nodes
+}
+
+fn summary(changes: &[SymbolChange]) -> Vec<(RangeChange, &str, &str)> {
+    changes
+        .iter()
+        .map(|c| (c.change, c.file.as_str(), c.symbol.name.as_str()))
+        .collect()
+}
+
+#[test]
+fn test_edit_to_one_function_is_a_modification() {
+    let dir = tempfile::tempdir().unwrap();
+    let work = dir.path();
+    git(work, &["init", "-q", "-b", "main"]);
+    let first = commit(work, &[("lib.rs", FIRST)], "first");
+    let second = commit(work, &[("lib.rs", SECOND)], "greet louder");
+
+    let changes = range_changes(work, &first, &second).unwrap();
+    let found = range_diff(&changes, &functions("lib.rs", SECOND), &[]);
+    assert_eq!(
+        summary(&found),
+        [(RangeChange::Modified, "lib.rs", "greet")]
+    );
+    assert_eq!(found[0].commits, [second.clone()]);
+    assert_eq!((found[0].symbol.start, found[0].symbol.end), (0, 2));
+
+    // nothing in an empty range
+    let changes = range_changes(work, &second, &second).unwrap();
+    assert!(range_diff(&changes, &functions("lib.rs", SECOND), &[]).is_empty());
+}
+
+#[test]
+fn test_moves_additions_and_deletions() {
+    let dir = tempfile::tempdir().unwrap();
+    let work = dir.path();
+    git(work, &["init", "-q", "-b", "main"]);
+    let base = commit(work, &[("lib.rs", SECOND)], "first");
+    let lib = "fn greet() {\n    println!(\"hello\");\n}\n\nfn fresh() {}\n";
+    let util = "// moved out of lib.rs\nfn helper() -> u32 {\n    1\n}\n";
+    let head = commit(work, &[("lib.rs", lib), ("util.rs", util)], "split");
+
+    let changes = range_changes(work, &base, &head).unwrap();
+    let mut after = functions("lib.rs", lib);
+    after.extend(functions("util.rs", util));
+    let found = range_diff(&changes, &after, &functions("lib.rs", SECOND));
+    assert_eq!(
+        summary(&found),
+        [
+            (RangeChange::Added, "lib.rs", "fresh"),
+            (RangeChange::Deleted, "lib.rs", "legacy"),
+            (RangeChange::Moved, "util.rs", "helper"),
+        ]
+    );
+    assert_eq!(found[2].moved_from.as_deref(), Some("lib.rs"));
+    assert!(found[1].commits.is_empty());
+
+    // without a graph of the base, the deletion goes unnamed
+    let found = range_diff(&changes, &after, &[]);
+    assert!(found.iter().all(|c| c.change != RangeChange::Deleted));
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{RepoRecord, Storage};
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_changed_symbols_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        git(&root, &["init", "-q", "-b", "main"]);
+        let first = commit(&root, &[("lib.rs", FIRST)], "first");
+        let second = commit(&root, &[("lib.rs", SECOND)], "greet louder");
+
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let file = root.join("lib.rs").to_string_lossy().into_owned();
+        storage
+            .upsert_nodes(&functions(&file, SECOND))
+            .await
+            .unwrap();
+        storage
+            .record_ingest(&RepoRecord {
+                repo_id: "acme/app".to_string(),
+                url: String::new(),
+                git_ref: None,
+                commit: Some(second.clone()),
+                ingested_at: 0,
+            })
+            .await
+            .unwrap();
+        let app = standalone::router(Arc::new(AppState::new(
+            storage,
+            LanguageRegistry::new(),
+            16,
+        )));
+        let post = |body: Value| {
+            let request = Request::post("/changed-symbols")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<Value>(&body).unwrap())
+            }
+        };
+
+        let (status, body) = post(json!({ "repo": "acme/app", "base": &first[..7] })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["base"], first);
+        assert_eq!(body["head"], second);
+        assert_eq!(body["deletions_known"], false);
+        let symbols = body["symbols"].as_array().unwrap();
+        assert_eq!(symbols.len(), 1, "{}", body);
+        assert_eq!(symbols[0]["change"], "modified");
+        assert_eq!(symbols[0]["name"], "greet");
+        assert_eq!(symbols[0]["file"], "lib.rs");
+        assert_eq!(symbols[0]["commits"], json!([second]));
+
+        // the graph is of the second commit, so the range can't end anywhere else
+        let (status, _) = post(json!({ "repo": "acme/app", "base": first, "head": first })).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = post(json!({ "repo": "acme/app", "base": "nope" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = post(json!({ "repo": "acme/other", "base": first })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,352 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use common::git;
+use serde_json::{Map, Value};
+use standalone::checkpoints;
+use standalone::lang::{Diagnostic, LanguageRegistry};
+use standalone::query::QueryTemplate;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{repo_id, EdgeRecord, NodeRecord, RepoRecord, Storage, Transaction};
+use standalone::AppState;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tower::ServiceExt;
+
+/// A backend that stops answering, as a crashed server would, once it's
+/// asked to write the file `at`.
+struct Crashing {
+    db: Arc<SqliteStorage>,
+    at: &'static str,
+}
+
+struct Halting {
+    inner: Box<dyn Transaction>,
+    at: &'static str,
+}
+
+#[async_trait]
+impl Transaction for Halting {
+    async fn upsert_nodes(&mut self, nodes: &[NodeRecord]) -> Result<()> {
+        if nodes.iter().any(|n| n.file.ends_with(self.at)) {
+            std::future::pending::<()>().await;
+        }
+        self.inner.upsert_nodes(nodes).await
+    }
+
+    async fn upsert_edges(&mut self, edges: &[EdgeRecord]) -> Result<()> {
+        self.inner.upsert_edges(edges).await
+    }
+
+    async fn delete_nodes(&mut self, repo_id: &str, ids: &[String]) -> Result<()> {
+        self.inner.delete_nodes(repo_id, ids).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        self.inner.commit().await
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        self.inner.rollback().await
+    }
+}
+
+#[async_trait]
+impl Storage for Crashing {
+    fn backend(&self) -> &'static str {
+        "crashing"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.db.ping().await
+    }
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        self.db.upsert_node(node).await
+    }
+
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        self.db.upsert_edge(edge).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        Ok(Box::new(Halting {
+            inner: self.db.begin().await?,
+            at: self.at,
+        }))
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        self.db.file_node_ids(repo_id, file).await
+    }
+
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.db.delete_nodes(repo_id, ids).await
+    }
+
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        self.db.delete_file(repo_id, file).await
+    }
+
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.db.clear(repo_id).await
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.db.graph_size(repo_id).await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        self.db.graph_version(repo_id).await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        self.db.repo_hash(repo_url).await
+    }
+
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        self.db.set_repo_hash(repo_url, hash).await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        self.db.file_hashes(repo_id).await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        self.db.set_file_hashes(repo_id, hashes).await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.db
+            .replace_diagnostics(repo_id, files, diagnostics)
+            .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        self.db.find_repo(name).await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        self.db.node(repo_id, id).await
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        self.db.record_ingest(repo).await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.db.repos().await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        self.db.load_graph(repo_id).await
+    }
+
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.db.dangling_edges(repo_id, after, limit).await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.db.orphan_nodes(repo_id, after, limit).await
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.db.duplicate_nodes(repo_id, after, limit).await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        self.db.delete_edges(edges).await
+    }
+
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.db.dedupe_nodes(repo_id, ids).await
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.db.query(template, params).await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        self.db.query_raw(statement, params).await
+    }
+}
+
+const FILES: [&str; 4] = ["a.rs", "b.rs", "c.rs", "d.rs"];
+
+fn sample_repo(root: &Path) {
+    for file in FILES {
+        let name = file.trim_end_matches(".rs");
+        fs::write(root.join(file), format!("pub fn {}() {{}}\n", name)).unwrap();
+    }
+    git(root, &["init", "-q", "-b", "main"]);
+    git(root, &["add", "."]);
+    git(root, &["commit", "-q", "-m", "init"]);
+}
+
+fn process(root: &Path, discard: bool) -> Request<Body> {
+    let body = serde_json::json!({ "repo_path": root, "discard_interrupted": discard });
+    Request::post("/process")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Ingests `root` until the write of `c.rs`, where the server "crashes":
+/// the request is dropped with the write never coming back.
+async fn crash_at_c(storage: &Arc<SqliteStorage>, root: &Path) {
+    let crashing = Crashing {
+        db: storage.clone(),
+        at: "c.rs",
+    };
+    let mut state = AppState::new(Arc::new(crashing), LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.to_path_buf()];
+    let request = standalone::router(Arc::new(state)).oneshot(process(root, false));
+    let crashed = tokio::time::timeout(Duration::from_secs(5), request).await;
+    assert!(crashed.is_err(), "the ingest finished");
+}
+
+async fn functions(storage: &SqliteStorage, repo: &str) -> Vec<String> {
+    let (nodes, _) = storage.load_graph(Some(repo)).await.unwrap();
+    let mut names: Vec<String> = nodes
+        .into_iter()
+        .filter(|n| n.kind == "Function")
+        .map(|n| n.name)
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn test_an_interrupted_ingest_resumes_with_the_files_left() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    sample_repo(&root);
+    let repo = repo_id("", root.to_str().unwrap());
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+
+    crash_at_c(&storage, &root).await;
+    let checkpoint = checkpoints::interrupted(storage.as_ref(), &repo)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut done: Vec<&str> = checkpoint.files.keys().map(String::as_str).collect();
+    done.sort();
+    assert_eq!(done, ["a.rs", "b.rs"]);
+    assert_eq!(functions(&storage, &repo).await, ["a", "b"]);
+
+    // the restarted server
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.clone()];
+    let state = Arc::new(state);
+    let mut events = state.tx.subscribe();
+    let response = standalone::router(state.clone())
+        .oneshot(process(&root, false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut resumed = Vec::new();
+    let mut cached = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        match event.update.status.as_str() {
+            "resumed" => resumed.push(event.update.message),
+            "cached" => cached.push(event.update.message),
+            _ => {}
+        }
+    }
+    assert_eq!(resumed.len(), 1);
+    assert!(
+        resumed[0].contains("2 files were already stored"),
+        "{}",
+        resumed[0]
+    );
+    cached.sort();
+    assert_eq!(
+        cached,
+        [
+            "a.rs is unchanged, reusing its graph",
+            "b.rs is unchanged, reusing its graph"
+        ]
+    );
+    assert_eq!(state.metrics.files_parsed.get(), 2);
+    assert_eq!(functions(&storage, &repo).await, ["a", "b", "c", "d"]);
+    assert!(checkpoints::interrupted(storage.as_ref(), &repo)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(storage.file_hashes(&repo).await.unwrap().len(), FILES.len());
+}
+
+#[tokio::test]
+async fn test_an_interrupted_ingest_can_be_rolled_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    sample_repo(&root);
+    let repo = repo_id("", root.to_str().unwrap());
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    crash_at_c(&storage, &root).await;
+
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.clone()];
+    let state = Arc::new(state);
+    let mut events = state.tx.subscribe();
+    let response = standalone::router(state.clone())
+        .oneshot(process(&root, true))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut rolled_back = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        assert_ne!(event.update.status, "cached");
+        if event.update.status == "rolled_back" {
+            rolled_back.push(event.update.message);
+        }
+    }
+    assert_eq!(rolled_back.len(), 1);
+    assert!(
+        rolled_back[0].contains("removing the 2 files"),
+        "{}",
+        rolled_back[0]
+    );
+    // everything was parsed again
+    assert_eq!(state.metrics.files_parsed.get(), 4);
+    assert_eq!(functions(&storage, &repo).await, ["a", "b", "c", "d"]);
+    assert!(checkpoints::interrupted(storage.as_ref(), &repo)
+        .await
+        .unwrap()
+        .is_none());
+}
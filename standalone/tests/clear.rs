@@ -0,0 +1,105 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use common::function_in;
+use serde_json::{json, Value};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::Storage;
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+async fn setup() -> (Arc<SqliteStorage>, axum::Router) {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    storage
+        .upsert_nodes(&[
+            function_in("acme/app", "render"),
+            function_in("acme/lib", "parse"),
+        ])
+        .await
+        .unwrap();
+    let state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    (storage, standalone::router(Arc::new(state)))
+}
+
+async fn send(app: &axum::Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn clear(app: &axum::Router, body: Value) -> (StatusCode, Value) {
+    let request = Request::post("/clear")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn token(app: &axum::Router, query: &str) -> String {
+    let request = Request::get(format!("/clear{}", query))
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send(app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    body["token"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_clear_without_confirmation_is_refused() {
+    let (storage, app) = setup().await;
+    for body in [
+        json!({}),
+        json!({"repo_id": "acme/app"}),
+        json!({"repo_id": "acme/app", "confirm": "acme/lib"}),
+        json!({"confirm": "acme/app"}),
+        json!({"repo_id": "acme/app", "token": "made-up"}),
+    ] {
+        let (status, response) = clear(&app, body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(response["kind"], "validation");
+    }
+    let (_, response) = clear(&app, json!({"repo_id": "acme/app"})).await;
+    assert!(response["error"]
+        .as_str()
+        .unwrap()
+        .contains("set 'confirm' to 'acme/app'"));
+    assert_eq!(storage.graph_size(None).await.unwrap().0, 2);
+}
+
+#[tokio::test]
+async fn test_confirmed_clear() {
+    let (storage, app) = setup().await;
+    let (status, _) = clear(&app, json!({"repo_id": "acme/app", "confirm": "acme/app"})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(storage.graph_size(Some("acme/app")).await.unwrap().0, 0);
+    assert_eq!(storage.graph_size(Some("acme/lib")).await.unwrap().0, 1);
+
+    let (status, _) = clear(&app, json!({"confirm": "ALL"})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(storage.graph_size(None).await.unwrap().0, 0);
+}
+
+#[tokio::test]
+async fn test_clear_with_a_token() {
+    let (storage, app) = setup().await;
+    // a token only clears the scope it was issued for
+    let other = token(&app, "?repo_id=acme/lib").await;
+    let (status, _) = clear(&app, json!({"repo_id": "acme/app", "token": other})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let token = token(&app, "?repo_id=acme/app").await;
+    let body = json!({"repo_id": "acme/app", "token": token});
+    let (status, _) = clear(&app, body.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(storage.graph_size(Some("acme/app")).await.unwrap().0, 0);
+
+    let (status, _) = clear(&app, body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "a token is used once");
+    assert_eq!(storage.graph_size(Some("acme/lib")).await.unwrap().0, 1);
+}
@@ -0,0 +1,164 @@
+#![cfg(all(feature = "client", feature = "sqlite"))]
+
+mod common;
+
+use axum::http::{header, HeaderMap};
+use axum::routing::get;
+use axum::Router;
+use common::git;
+use futures::StreamExt;
+use standalone::client::{ApiError, MeshClient, ProcessBody, QueryBody, SearchBody};
+use standalone::clone::RetryPolicy;
+use standalone::events::StatusEvent;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Serves `app` on a free port, returning its base URL.
+async fn spawn(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}/", addr)
+}
+
+#[tokio::test]
+async fn test_client_against_a_running_server() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(
+        root.join("main.rs"),
+        "fn main() {\n    greet();\n}\n\nfn greet() {}\n",
+    )
+    .unwrap();
+    git(&root, &["init", "-q", "-b", "main"]);
+    git(&root, &["add", "."]);
+    git(&root, &["commit", "-q", "-m", "init"]);
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+    state.allowed_roots = vec![root.clone()];
+    let client = MeshClient::new(&spawn(standalone::router(Arc::new(state))).await);
+
+    let processed = client
+        .process(&ProcessBody {
+            repo_path: Some(root.to_string_lossy().into_owned()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(processed.status, "success");
+    assert!(processed.nodes > 0);
+
+    // the ingest is over, so its events come from the replay buffer
+    let events = client.subscribe_events_after(0);
+    let mut events = Box::pin(events);
+    let mut statuses = Vec::new();
+    while let Some(event) = tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .expect("no complete event")
+    {
+        let event = event.unwrap();
+        assert!(event.id > 0);
+        statuses.push(event.update.status.clone());
+        if event.update.status == "complete" {
+            assert!(event.summary.is_some());
+            break;
+        }
+    }
+    assert_eq!(statuses.last().map(String::as_str), Some("complete"));
+
+    let mut params = serde_json::Map::new();
+    params.insert("name".to_string(), "greet".into());
+    let query = QueryBody {
+        query: Some("callers-of-function".to_string()),
+        cypher: None,
+        params,
+        node_kinds: Vec::new(),
+        limit: None,
+        cursor: None,
+        repo: None,
+        git_ref: None,
+        project: None,
+        timeout_ms: None,
+    };
+    let rows = client.query(&query).await.unwrap();
+    assert_eq!(rows.query, "callers-of-function");
+    assert_eq!(rows.rows.len(), 1, "{:?}", rows.rows);
+
+    let found = client
+        .search(&SearchBody {
+            query: "greet".to_string(),
+            repo: None,
+            limit: None,
+        })
+        .await
+        .unwrap();
+    assert!(!found.hits.is_empty());
+
+    let unknown = QueryBody {
+        query: Some("no-such-query".to_string()),
+        ..query
+    };
+    let err = client.query(&unknown).await.unwrap_err();
+    let err = err.downcast_ref::<ApiError>().expect("not an API error");
+    assert_eq!(err.status, 400);
+    assert!(err.message.contains("no-such-query"), "{}", err.message);
+}
+
+#[tokio::test]
+async fn test_events_reconnect_with_the_last_event_id() {
+    // hands out one event per connection, then hangs up
+    let seen: Arc<Mutex<Vec<Option<String>>>> = Arc::default();
+    let recorded = seen.clone();
+    let events = move |headers: HeaderMap| {
+        let seen = recorded.clone();
+        async move {
+            let last = headers
+                .get("last-event-id")
+                .map(|v| v.to_str().unwrap().to_string());
+            let id = last.as_deref().map_or(0, |id| id.parse::<u64>().unwrap()) + 1;
+            seen.lock().unwrap().push(last);
+            let event = StatusEvent::new("progress", format!("event {}", id));
+            let body = format!(
+                ": keep-alive\n\nid: {}\ndata: {}\n\n",
+                id,
+                event.as_json_str()
+            );
+            ([(header::CONTENT_TYPE, "text/event-stream")], body)
+        }
+    };
+    let base = spawn(Router::new().route("/events", get(events))).await;
+    let client = MeshClient::new(&base).with_reconnect(RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+    });
+
+    let received: Vec<StatusEvent> = client
+        .subscribe_events()
+        .take(3)
+        .map(|event| event.unwrap())
+        .collect()
+        .await;
+    let ids: Vec<u64> = received.iter().map(|e| e.id).collect();
+    assert_eq!(ids, [1, 2, 3]);
+    assert_eq!(received[2].update.message, "event 3");
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [None, Some("1".to_string()), Some("2".to_string())]
+    );
+
+    // nothing listening: gives up after its attempts
+    let dead = MeshClient::new("http://127.0.0.1:9").with_reconnect(RetryPolicy {
+        max_attempts: 2,
+        base_delay: Duration::from_millis(1),
+    });
+    let results: Vec<_> = dead.subscribe_events().collect().await;
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use standalone::clones::CloneDir;
+use std::fs;
+
+#[test]
+fn test_sweep_removes_only_orphaned_checkouts() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("clones");
+    fs::create_dir_all(root.join("acme/app/.git")).unwrap();
+    fs::create_dir_all(root.join("acme/app@v1/.git")).unwrap();
+    fs::create_dir_all(root.join("notes/drafts")).unwrap();
+    fs::write(root.join("README"), "not a clone").unwrap();
+
+    let clones = CloneDir::new(root.clone(), 0, false);
+    assert_eq!(clones.sweep(), 2);
+    assert!(!root.join("acme").exists());
+    assert!(root.join("notes/drafts").is_dir());
+    assert!(root.join("README").is_file());
+
+    // kept clones survive restarts
+    fs::create_dir_all(root.join("acme/app/.git")).unwrap();
+    let kept = CloneDir::new(root.clone(), 0, true);
+    assert_eq!(kept.sweep(), 0);
+    assert!(root.join("acme/app").is_dir());
+}
+
+#[test]
+fn test_clone_paths_stay_under_the_root() {
+    let clones = CloneDir::new("/var/mesh".into(), 0, false);
+    assert_eq!(
+        clones.path_for("https://github.com/acme/app.git").unwrap(),
+        "/var/mesh/acme/app"
+    );
+    assert_eq!(
+        clones.path_for("git@github.com:acme/..").unwrap(),
+        "/var/mesh/acme/--"
+    );
+    assert!(clones.path_for("").is_err());
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use crate::common::git;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// A repo to clone from, and an empty clone dir beside it.
+    fn remote(dir: &Path) -> (String, PathBuf) {
+        let source = dir.join("acme").join("app");
+        fs::create_dir_all(&source).unwrap();
+        git(&source, &["init", "-q", "-b", "main"]);
+        fs::write(source.join("main.rs"), "fn main() {}\n").unwrap();
+        git(&source, &["add", "."]);
+        git(&source, &["commit", "-q", "-m", "main"]);
+        (source.to_string_lossy().into_owned(), dir.join("clones"))
+    }
+
+    async fn post(app: &Router, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_clone_is_refused_without_enough_free_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, root) = remote(dir.path());
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+        // more than any disk has
+        state.clones = Arc::new(CloneDir::new(root.clone(), u64::MAX, false));
+        let app = standalone::router(Arc::new(state));
+
+        let (status, body) = post(
+            &app,
+            "/fetch-repo",
+            json!({ "repo_name": "acme/app", "repo_url": url }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(body["kind"], "no_space");
+        assert!(body["error"].as_str().unwrap().contains("MB free"));
+
+        let (status, _) = post(&app, "/process", json!({ "repo_url": url })).await;
+        assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+        assert!(!root.join("acme").exists());
+    }
+
+    #[tokio::test]
+    async fn test_clone_is_removed_after_its_ingest() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, root) = remote(dir.path());
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+        let clones = Arc::new(CloneDir::new(root.clone(), 0, false));
+        state.clones = clones.clone();
+        let app = standalone::router(Arc::new(state));
+
+        let (status, body) = post(&app, "/process", json!({ "repo_url": url })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (nodes, _) = storage.graph_size(Some("acme/app")).await.unwrap();
+        assert!(nodes > 0);
+        let checkout = clones.path_for(&url).unwrap();
+        assert!(!Path::new(&checkout).exists());
+    }
+}
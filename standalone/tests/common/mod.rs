@@ -0,0 +1,81 @@
+//! Fixtures shared by the integration tests: graph node factories and a git
+//! runner for the repos the tests build on disk.
+
+#![allow(dead_code)]
+
+use standalone::storage::{NodeRecord, Span};
+use std::path::Path;
+use std::process::Command;
+
+/// The repo most fixtures live in.
+pub const REPO: &str = "acme/app";
+
+/// A `kind` node named `name` in `file` of [`REPO`], on line 0 with an empty
+/// body. Its id joins all three, lowercased.
+pub fn node(kind: &str, name: &str, file: &str) -> NodeRecord {
+    NodeRecord {
+        repo_id: REPO.to_string(),
+        id: format!("{}-{}-{}", kind, name, file).to_lowercase(),
+        kind: kind.to_string(),
+        name: name.to_string(),
+        file: file.to_string(),
+        start: 0,
+        end: 0,
+        body: String::new(),
+        meta: Default::default(),
+        span: None,
+    }
+}
+
+/// The function `name` in `src/lib.rs` of `repo_id`, with id `function-{name}`.
+pub fn function_in(repo_id: &str, name: &str) -> NodeRecord {
+    NodeRecord {
+        repo_id: repo_id.to_string(),
+        id: format!("function-{}", name),
+        ..node("Function", name, "src/lib.rs")
+    }
+}
+
+/// The function `name` in `src/lib.rs` of [`REPO`].
+pub fn function(name: &str) -> NodeRecord {
+    function_in(REPO, name)
+}
+
+/// A `kind` node named `name` on lines `start..=end` of `file` in [`REPO`].
+/// Its id is `{kind}-{name}`, with the kind lowercased.
+pub fn symbol(kind: &str, name: &str, file: &str, start: usize, end: usize) -> NodeRecord {
+    NodeRecord {
+        id: format!("{}-{}", kind.to_lowercase(), name),
+        start,
+        end,
+        ..node(kind, name, file)
+    }
+}
+
+/// A [`symbol`] whose body is `text` as it sits in `source`, with its lines
+/// and span found there.
+pub fn located(source: &str, file: &str, kind: &str, name: &str, text: &str) -> NodeRecord {
+    let start = source.find(text).unwrap();
+    let line = source[..start].matches('\n').count();
+    NodeRecord {
+        span: Span::locate(source, line, text),
+        body: text.to_string(),
+        ..symbol(kind, name, file, line, line + text.lines().count() - 1)
+    }
+}
+
+/// Runs git in `dir` as a fixed test identity, failing the test if it fails,
+/// and returns its trimmed stdout.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_AUTHOR_NAME", "test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use serde_json::Value;
+use standalone::auth::{ApiKeys, Auth, Scope};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{NodeRecord, Storage};
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const KEY: &str = "writer";
+const ADMIN: &str = "admin";
+
+fn node(i: usize) -> NodeRecord {
+    NodeRecord {
+        file: format!("src/f{}.rs", i),
+        end: 40,
+        body: format!("fn f{}() {{ {} }}", i, "x + ".repeat(200)),
+        ..common::function(&format!("f{}", i))
+    }
+}
+
+async fn compact(app: &axum::Router, key: &str) -> (StatusCode, Value) {
+    let request = Request::post("/admin/compact")
+        .header(header::AUTHORIZATION, format!("Bearer {}", key))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_compaction_shrinks_the_database_after_deletions() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mesh.db");
+    let storage = Arc::new(SqliteStorage::open(path.to_str().unwrap()).unwrap());
+    let nodes: Vec<NodeRecord> = (0..2000).map(node).collect();
+    storage.upsert_nodes(&nodes).await.unwrap();
+    for node in &nodes[..1900] {
+        storage.delete_file("acme/app", &node.file).await.unwrap();
+    }
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    state.auth = Some(Arc::new(
+        Auth::new(ApiKeys::new(&[KEY]), &[Scope::Mutating]).with_admin_keys(ApiKeys::new(&[ADMIN])),
+    ));
+    let app = standalone::router(Arc::new(state));
+
+    // a key that can clear the graph still can't compact it
+    let (status, _) = compact(&app, KEY).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = compact(&app, ADMIN).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["backend"], "sqlite");
+    let before = body["before_bytes"].as_u64().unwrap();
+    let after = body["after_bytes"].as_u64().unwrap();
+    assert!(
+        after < before / 2,
+        "{} bytes before, {} after",
+        before,
+        after
+    );
+    assert!(!body["steps"].as_array().unwrap().is_empty());
+    // what the file takes up on disk went down with it
+    assert!(std::fs::metadata(&path).unwrap().len() <= after);
+
+    // and nothing that was kept is gone
+    assert_eq!(storage.graph_size(None).await.unwrap(), (100, 0));
+    let kept = storage.node("acme/app", "function-f1999").await.unwrap();
+    assert_eq!(kept.unwrap().body, nodes[1999].body);
+}
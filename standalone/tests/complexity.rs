@@ -0,0 +1,153 @@
+mod common;
+
+use standalone::captures::Grammar;
+use standalone::complexity::{annotate, COMPLEXITY, LINES, NESTING};
+use standalone::storage::NodeRecord;
+use std::path::Path;
+use tree_sitter::Tree;
+
+const SOURCE: &str = "fn flat(a: u32) -> u32 {
+    let b = a + 1;
+    b * 2
+}
+
+fn nested(items: &[u32]) -> u32 {
+    let mut total = 0;
+    for item in items {
+        if *item > 10 {
+            while total < 100 && *item % 2 == 0 {
+                match item {
+                    11 => total += 1,
+                    _ => total += 2,
+                }
+            }
+        } else if *item == 0 {
+            total = 0;
+        }
+    }
+    total
+}
+";
+
+fn function(name: &str) -> NodeRecord {
+    let start = SOURCE
+        .lines()
+        .position(|l| l.starts_with(&format!("fn {}", name)))
+        .unwrap();
+    let body: Vec<&str> = SOURCE
+        .lines()
+        .skip(start)
+        .take_while(|l| !l.is_empty())
+        .collect();
+    common::located(SOURCE, "src/lib.rs", "Function", name, &body.join("\n"))
+}
+
+fn measure(node: &NodeRecord, key: &str) -> usize {
+    node.meta[key].parse().unwrap()
+}
+
+fn tree(file: &str, source: &str) -> Option<Tree> {
+    Grammar::for_path(Path::new(file))?
+        .parse(source, None)
+        .unwrap()
+}
+
+#[test]
+fn test_nested_function_is_more_complex_than_a_flat_one() {
+    let mut nodes = vec![function("flat"), function("nested")];
+    annotate(
+        "src/lib.rs",
+        SOURCE,
+        tree("src/lib.rs", SOURCE).as_ref(),
+        nodes.iter_mut(),
+    )
+    .unwrap();
+    let (flat, nested) = (&nodes[0], &nodes[1]);
+
+    assert_eq!(measure(flat, COMPLEXITY), 1);
+    assert_eq!(measure(flat, NESTING), 0);
+    assert_eq!(measure(flat, LINES), 4);
+    // for, if, else if, while, &&, and two match arms
+    assert_eq!(measure(nested, COMPLEXITY), 8);
+    // the else if continues the if rather than nesting in it
+    assert_eq!(measure(nested, NESTING), 4);
+    assert_eq!(measure(nested, LINES), 16);
+}
+
+#[test]
+fn test_file_and_unknown_languages() {
+    let mut file = function("flat");
+    file.kind = "File".to_string();
+    file.span = None;
+    let mut markdown = function("flat");
+    markdown.file = "README.md".to_string();
+    annotate(
+        "src/lib.rs",
+        SOURCE,
+        tree("src/lib.rs", SOURCE).as_ref(),
+        [&mut file],
+    )
+    .unwrap();
+    annotate(
+        "README.md",
+        "# flat\n",
+        tree("README.md", "# flat\n").as_ref(),
+        [&mut markdown],
+    )
+    .unwrap();
+
+    assert_eq!(measure(&file, COMPLEXITY), 8);
+    assert_eq!(measure(&file, LINES), 21);
+    assert_eq!(measure(&markdown, LINES), 4);
+    assert!(!markdown.meta.contains_key(COMPLEXITY));
+
+    // a file that didn't parse in time only has its lines counted
+    let mut unparsed = function("flat");
+    annotate("src/lib.rs", SOURCE, None, [&mut unparsed]).unwrap();
+    assert_eq!(measure(&unparsed, LINES), 4);
+    assert!(!unparsed.meta.contains_key(COMPLEXITY));
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_hotspots_are_sorted_most_complex_first() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let mut nodes = vec![function("flat"), function("nested")];
+    annotate(
+        "src/lib.rs",
+        SOURCE,
+        tree("src/lib.rs", SOURCE).as_ref(),
+        nodes.iter_mut(),
+    )
+    .unwrap();
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage.upsert_nodes(&nodes).await.unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+
+    let request = Request::post("/graph/query")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            r#"{"query": "complexity-hotspots", "params": {}}"#,
+        ))
+        .unwrap();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let rows = body["rows"].as_array().unwrap();
+    assert_eq!(rows[0]["name"], "nested");
+    assert_eq!(rows[0]["complexity"], 8);
+    assert_eq!(rows[0]["nesting"], 4);
+    assert_eq!(rows[1]["name"], "flat");
+}
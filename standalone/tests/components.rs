@@ -0,0 +1,89 @@
+mod common;
+
+use standalone::components::{
+    framework, link, COMPONENT_KIND, DEFINED_BY, FRAMEWORK, RENDERS, USES_COMPONENT,
+};
+use standalone::storage::NodeRecord;
+
+fn symbol(kind: &str, name: &str, file: &str, start: usize, end: usize) -> NodeRecord {
+    NodeRecord {
+        repo_id: "acme/web".to_string(),
+        ..common::symbol(kind, name, file, start, end)
+    }
+}
+
+const APP: &str = "import { Button } from './button';
+
+export function App() {
+  return (
+    <div>
+      <Button label=\"Save\" />
+      <Route component={Settings} />
+    </div>
+  );
+}
+
+function Settings() {
+  return <Menu.Item />;
+}
+
+function formatLabel(label) {
+  return label.trim();
+}
+";
+
+const BUTTON: &str = "export const Button = ({ label }) => <button>{label}</button>;
+";
+
+#[test]
+fn test_the_framework_comes_from_the_manifest() {
+    assert_eq!(
+        framework(r#"{"dependencies": {"react": "^18.2.0", "react-dom": "^18.2.0"}}"#),
+        Some("react")
+    );
+    assert_eq!(
+        framework(r#"{"devDependencies": {"solid-js": "^1.8.0"}}"#),
+        Some("solid")
+    );
+    assert_eq!(framework(r#"{"dependencies": {"express": "^4"}}"#), None);
+    assert_eq!(framework("not json"), None);
+}
+
+#[test]
+fn test_a_component_rendering_another_gets_an_edge() {
+    let app = symbol("Function", "App", "src/app.jsx", 2, 9);
+    let settings = symbol("Function", "Settings", "src/app.jsx", 11, 13);
+    let format = symbol("Function", "formatLabel", "src/app.jsx", 15, 17);
+    let button = symbol("Var", "Button", "src/button.jsx", 0, 0);
+    let (nodes, edges) = link(
+        "react",
+        "acme/web",
+        &[("src/app.jsx", APP), ("src/button.jsx", BUTTON)],
+        &[&app, &settings, &format, &button],
+        None,
+    )
+    .unwrap();
+
+    let names: Vec<&str> = nodes.iter().map(|n| n.name.as_str()).collect();
+    // a function returning no JSX isn't a component
+    assert_eq!(names, ["App", "Settings", "Button"]);
+    assert!(nodes.iter().all(|n| n.kind == COMPONENT_KIND));
+    assert!(nodes.iter().all(|n| n.meta[FRAMEWORK] == "react"));
+    assert_eq!(nodes[2].meta[DEFINED_BY], button.id);
+
+    let id = |name: &str| nodes.iter().find(|n| n.name == name).unwrap().id.clone();
+    let mut found: Vec<(String, String, String)> = edges
+        .iter()
+        .map(|e| (e.source.clone(), e.target.clone(), e.kind.clone()))
+        .collect();
+    found.sort();
+    let mut expected = vec![
+        // across files, found by name
+        (id("App"), id("Button"), RENDERS.to_string()),
+        // handed to a router rather than rendered
+        (id("App"), id("Settings"), USES_COMPONENT.to_string()),
+    ];
+    expected.sort();
+    // `Route` and `Menu.Item` come from libraries, so they get no edge
+    assert_eq!(found, expected);
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use common::function;
+use flate2::read::GzDecoder;
+use serde_json::{json, Value};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{EdgeRecord, Storage};
+use standalone::AppState;
+use std::io::Read;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+async fn app() -> axum::Router {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let mut nodes = vec![function("target")];
+    let mut edges = Vec::new();
+    for i in 0..100 {
+        let caller = function(&format!("caller{:03}", i));
+        edges.push(EdgeRecord {
+            repo_id: "acme/app".to_string(),
+            kind: "CALLS".to_string(),
+            source: caller.id.clone(),
+            target: "function-target".to_string(),
+        });
+        nodes.push(caller);
+    }
+    storage.upsert_nodes(&nodes).await.unwrap();
+    storage.upsert_edges(&edges).await.unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    standalone::router(Arc::new(state))
+}
+
+fn query(encoding: Option<&str>) -> Request<Body> {
+    let body = json!({ "query": "callers-of-function", "params": { "name": "target" } });
+    let mut request = Request::post("/graph/query").header("Content-Type", "application/json");
+    if let Some(encoding) = encoding {
+        request = request.header(header::ACCEPT_ENCODING, encoding);
+    }
+    request.body(Body::from(body.to_string())).unwrap()
+}
+
+#[tokio::test]
+async fn test_query_is_gzipped_when_asked() {
+    let response = app().await.oneshot(query(Some("gzip"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let mut json = String::new();
+    GzDecoder::new(&body[..]).read_to_string(&mut json).unwrap();
+    let rows: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(rows["rows"].as_array().unwrap().len(), 100);
+    assert!(
+        body.len() < json.len() / 2,
+        "{} of {}",
+        body.len(),
+        json.len()
+    );
+
+    let response = app().await.oneshot(query(Some("br"))).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+
+    let response = app().await.oneshot(query(None)).await.unwrap();
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(serde_json::from_slice::<Value>(&body).is_ok());
+}
+
+#[tokio::test]
+async fn test_event_stream_is_not_compressed() {
+    let request = Request::get("/events")
+        .header(header::ACCEPT_ENCODING, "gzip, br")
+        .body(Body::empty())
+        .unwrap();
+    let response = app().await.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/event-stream"
+    );
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+}
@@ -0,0 +1,248 @@
+use standalone::config::Config;
+use standalone::ids::IdScheme;
+use standalone::paths::PathMap;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn write(dir: &Path, text: &str) -> PathBuf {
+    let path = dir.join("mesh.toml");
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+fn load(path: Option<&Path>, env: &[(&str, &str)]) -> anyhow::Result<Config> {
+    let env: HashMap<String, String> = env
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    Config::load_from(path, |key| env.get(key).cloned())
+}
+
+fn error(path: Option<&Path>, env: &[(&str, &str)]) -> String {
+    format!("{:#}", load(path, env).unwrap_err())
+}
+
+#[test]
+fn test_defaults_without_file_or_env() {
+    assert_eq!(load(None, &[]).unwrap(), Config::default());
+}
+
+#[test]
+fn test_file_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(
+        dir.path(),
+        r#"
+port = 8080
+backend = "sqlite"
+api_keys = ["first", "second"]
+auth_events = true
+allowed_roots = ["/srv/repos"]
+cors_origins = ["https://mesh.example.com"]
+event_id_file = ""
+max_file_bytes = 0
+
+[[custom_edges]]
+edge = "HANDLES"
+language = "python"
+query = "(function_definition name: (identifier) @handler body: (block) @body)"
+source = "handler"
+target = "body"
+"#,
+    );
+    let config = load(Some(&path), &[]).unwrap();
+    assert_eq!(config.port, 8080);
+    assert_eq!(config.backend.as_deref(), Some("sqlite"));
+    assert_eq!(config.api_keys, vec!["first", "second"]);
+    assert!(config.auth().is_some());
+    assert_eq!(config.allowed_roots, vec![PathBuf::from("/srv/repos")]);
+    assert_eq!(config.cors_origins().unwrap().unwrap().len(), 1);
+    assert_eq!(config.event_id_file, None);
+    assert_eq!(config.max_file_bytes(), None);
+    assert_eq!(config.custom_edges.len(), 1);
+    assert_eq!(config.custom_edges[0].edge, "HANDLES");
+    // keys the file leaves out keep their defaults
+    assert_eq!(config.event_buffer, Config::default().event_buffer);
+    assert!(config.webhook().is_none());
+}
+
+#[test]
+fn test_env_overrides_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(
+        dir.path(),
+        r#"
+port = 8080
+api_keys = ["from-file"]
+rate_limit_per_min = 120.0
+allow_raw_cypher = true
+"#,
+    );
+    let config = load(
+        Some(&path),
+        &[
+            ("PORT", "9090"),
+            ("MESH_API_KEYS", "one, two,"),
+            ("MESH_ALLOW_RAW_CYPHER", "false"),
+            ("MESH_WEBHOOK_SECRET", "s3cret"),
+            ("MESH_WEBHOOK_REPOS", "acme/app,acme/web"),
+            ("MESH_EVENT_ID_FILE", ""),
+            ("MESH_SSE_KEEPALIVE_MS", "60000"),
+            ("MESH_MAX_NODES_PER_REPO", "50000"),
+            ("MESH_STORAGE_POOL_SIZE", "4"),
+            ("MESH_STORAGE_ATTEMPTS", "5"),
+            ("MESH_INDEX_TEXT", "true"),
+            ("MESH_EMBEDDED_SQL", "true"),
+            ("MESH_INTERFACE_REPOS", "acme/api"),
+            ("MESH_DEFAULT_BRANCHES", "trunk, develop"),
+            ("MESH_QUERY_TIMEOUT_MS", "5000"),
+            ("MESH_TASK_MARKERS", "TODO, NOTE"),
+            ("MESH_TASK_ISSUE_PATTERN", r"(GH-\d+)"),
+            ("MESH_MAX_SSE_CLIENTS", "0"),
+            ("MESH_SSE_COALESCE_MS", "250"),
+            ("MESH_READONLY", "true"),
+            (
+                "MESH_REDACT_PATTERNS",
+                "sk-[a-z0-9]{8,}\n\nAKIA[A-Z0-9]{16}",
+            ),
+            ("MESH_REDACT_TEXT_REPOS", "acme/vault"),
+            ("MESH_NODE_IDS", "stable"),
+            ("MESH_PATH_PREFIX_STRIP", "/srv/app"),
+            ("MESH_PATH_PREFIX_ADD", ""),
+            ("MESH_RETENTION_TTL_SECS", "86400"),
+            ("MESH_STREAM_FILE_BYTES", "0"),
+            ("MESH_PINNED_REPOS", "acme/app, acme/web"),
+            ("MESH_ADMIN_KEYS", "root"),
+            ("MESH_AUDIT_LOG", "backend"),
+            ("MESH_BREAKER_FAILURES", "2"),
+            ("MESH_WORKSPACES", "prod, experiments"),
+            ("MESH_MAX_EDGES_PER_NODE", "0"),
+            ("MESH_SLOW_QUERY_MS", "250"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(config.port, 9090);
+    assert_eq!(config.api_keys, vec!["one", "two"]);
+    assert!(!config.allow_raw_cypher);
+    assert!(config.webhook().unwrap().is_configured("acme/web"));
+    assert_eq!(config.event_id_file, None);
+    assert_eq!(config.keep_alive().interval, Duration::from_secs(60));
+    assert_eq!(config.keep_alive().text, "ping");
+    assert_eq!(config.graph_limit().max_nodes, Some(50_000));
+    assert_eq!(config.graph_limit().max_edges, None);
+    assert!(config.index_text);
+    assert!(config.embedded_sql);
+    assert_eq!(config.interface_repos, vec!["acme/api"]);
+    assert_eq!(config.default_branches, vec!["trunk", "develop"]);
+    assert_eq!(config.query_timeout(), Some(Duration::from_millis(5000)));
+    assert_eq!(config.task_markers, vec!["TODO", "NOTE"]);
+    let task = config
+        .task_rules()
+        .unwrap()
+        .unwrap()
+        .parse("// NOTE: GH-3")
+        .unwrap();
+    assert_eq!(task.issue.as_deref(), Some("GH-3"));
+    assert_eq!(config.max_sse_clients(), None);
+    assert_eq!(config.sse_coalesce(), Some(Duration::from_millis(250)));
+    assert!(config.readonly);
+    assert_eq!(
+        config.redact_patterns,
+        vec!["sk-[a-z0-9]{8,}", "AKIA[A-Z0-9]{16}"]
+    );
+    let redaction = config.redaction().unwrap().unwrap();
+    assert!(redaction.hides_text("acme/vault"));
+    assert_eq!(
+        redaction.scrub("key sk-abcdef123").as_deref(),
+        Some("key ***")
+    );
+    assert_eq!(config.node_ids, IdScheme::Stable);
+    assert_eq!(config.path_map(), PathMap::new(Some("/srv/app"), None));
+    assert_eq!(config.retention_ttl(), Some(Duration::from_secs(86_400)));
+    assert_eq!(config.pinned_repos, vec!["acme/app", "acme/web"]);
+    assert_eq!(config.stream_file_bytes(), None);
+    assert_eq!(config.admin_keys, vec!["root"]);
+    assert_eq!(config.audit_log.as_deref(), Some("backend"));
+    assert_eq!(config.outbound_policy().breaker_failures, 2);
+    assert_eq!(config.workspaces, vec!["prod", "experiments"]);
+    assert_eq!(config.edge_cap(), None);
+    assert_eq!(config.slow_query(), Some(Duration::from_millis(250)));
+    assert_eq!(config.storage_pool().max_connections, 4);
+    assert_eq!(config.storage_pool().retry.max_attempts, 5);
+    assert_eq!(
+        config.storage_pool().connect_timeout,
+        Config::default().storage_pool().connect_timeout
+    );
+    // untouched by the environment
+    assert_eq!(config.rate_limit_per_min, 120.0);
+}
+
+#[test]
+fn test_invalid_config() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let path = write(dir.path(), "port = 8080\nmax_ingests = 2\n");
+    let message = error(Some(&path), &[]);
+    assert!(
+        message.contains("unknown field `max_ingests`"),
+        "{}",
+        message
+    );
+
+    let path = write(dir.path(), "port = \"eighty\"\n");
+    let message = error(Some(&path), &[]);
+    assert!(message.contains("invalid config file"), "{}", message);
+
+    let path = write(dir.path(), "cors_origins = [\"mesh.example.com\"]\n");
+    let message = error(Some(&path), &[]);
+    assert!(message.contains("invalid CORS origin"), "{}", message);
+
+    let path = write(dir.path(), "max_concurrent_ingests = 0\n");
+    let message = error(Some(&path), &[]);
+    assert!(message.contains("max_concurrent_ingests"), "{}", message);
+
+    let message = error(None, &[("MESH_SSE_KEEPALIVE_MS", "0")]);
+    assert!(message.contains("sse_keepalive_ms"), "{}", message);
+    let path = write(dir.path(), "sse_keepalive_text = \"a\\nb\"\n");
+    let message = error(Some(&path), &[]);
+    assert!(message.contains("single line"), "{}", message);
+
+    let message = error(None, &[("MESH_WORKSPACES", "prod,Staging")]);
+    assert!(message.contains("'Staging'"), "{}", message);
+    let message = error(None, &[("MESH_WORKSPACES", "prod,prod")]);
+    assert!(message.contains("listed twice"), "{}", message);
+
+    let message = error(None, &[("MESH_PARSE_WORKERS", "lots")]);
+    assert!(message.contains("MESH_PARSE_WORKERS"), "{}", message);
+    let message = error(None, &[("MESH_WRITE_QUEUE", "0")]);
+    assert!(message.contains("write_queue"), "{}", message);
+    let message = error(None, &[("MESH_AUTH_STATIC", "yes")]);
+    assert!(message.contains("MESH_AUTH_STATIC"), "{}", message);
+
+    let message = error(None, &[("MESH_IDEMPOTENCY_TTL_SECS", "0")]);
+    assert!(message.contains("idempotency_ttl_secs"), "{}", message);
+    let message = error(None, &[("MESH_RETENTION_SWEEP_SECS", "0")]);
+    assert!(message.contains("retention_sweep_secs"), "{}", message);
+
+    let message = error(None, &[("MESH_TLS_CERT", "/etc/mesh/cert.pem")]);
+    assert!(message.contains("without tls_key"), "{}", message);
+    #[cfg(not(feature = "embeddings"))]
+    {
+        let message = error(None, &[("MESH_EMBEDDING_URL", "http://localhost:11434")]);
+        assert!(message.contains("no embeddings feature"), "{}", message);
+    }
+
+    let path = write(dir.path(), "[git_credentials.ci]\nusername = \"bot\"\n");
+    let message = error(Some(&path), &[]);
+    assert!(message.contains("neither a token"), "{}", message);
+    let path = write(
+        dir.path(),
+        "[git_credentials.deploy]\nssh_key = \"/no/such/key\"\n",
+    );
+    let message = error(Some(&path), &[]);
+    assert!(message.contains("/no/such/key"), "{}", message);
+
+    let message = error(Some(&dir.path().join("missing.toml")), &[]);
+    assert!(message.contains("missing.toml"), "{}", message);
+}
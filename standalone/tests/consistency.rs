@@ -0,0 +1,113 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use common::REPO;
+use serde_json::{json, Value};
+use standalone::consistency::check;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{EdgeRecord, NodeRecord, Storage};
+use standalone::AppState;
+use std::path::Path;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn node(name: &str) -> NodeRecord {
+    NodeRecord {
+        file: "src/main.rs".to_string(),
+        start: 1,
+        end: 2,
+        ..common::function(name)
+    }
+}
+
+fn calls(source: &str, target: &str) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: REPO.to_string(),
+        kind: "CALLS".to_string(),
+        source: format!("function-{}", source),
+        target: format!("function-{}", target),
+    }
+}
+
+/// `main` calls `a`, `b` and `c`, then `b` and `c` vanish the way an
+/// interrupted ingest writing with foreign keys off would leave them.
+async fn seed(path: &Path) -> SqliteStorage {
+    let storage = SqliteStorage::open(path.to_str().unwrap()).unwrap();
+    for name in ["main", "a", "b", "c", "lonely"] {
+        storage.upsert_node(&node(name)).await.unwrap();
+    }
+    for target in ["a", "b", "c"] {
+        storage.upsert_edge(&calls("main", target)).await.unwrap();
+    }
+    let raw = rusqlite::Connection::open(path).unwrap();
+    raw.execute(
+        "DELETE FROM nodes WHERE id IN ('function-b', 'function-c')",
+        [],
+    )
+    .unwrap();
+    storage
+}
+
+#[tokio::test]
+async fn test_check_finds_and_repair_removes_dangling_edges() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = seed(&dir.path().join("graph.db")).await;
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (3, 3));
+
+    // a chunk of one makes every problem take its own page
+    let report = check(&storage, REPO, false, 1).await.unwrap();
+    assert_eq!(report.dangling_edges, 2);
+    assert_eq!(
+        report.dangling_sample,
+        vec![calls("main", "b"), calls("main", "c")]
+    );
+    assert_eq!(report.orphan_sample, vec!["function-lonely"]);
+    assert_eq!(report.duplicate_nodes, 0);
+    assert!(!report.is_consistent());
+    assert!(report.repaired.is_none());
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (3, 3));
+
+    let report = check(&storage, REPO, true, 1).await.unwrap();
+    assert_eq!(report.dangling_edges, 2);
+    assert_eq!(report.repaired.unwrap().edges_removed, 2);
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (3, 1));
+    assert!(check(&storage, REPO, false, 1)
+        .await
+        .unwrap()
+        .is_consistent());
+}
+
+#[tokio::test]
+async fn test_validate_endpoint_repairs() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Arc::new(seed(&dir.path().join("graph.db")).await);
+    let state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let validate = |body: Value| {
+        let app = app.clone();
+        async move {
+            let request = Request::post("/validate")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        }
+    };
+    let report = validate(json!({"repo_id": REPO})).await;
+    assert_eq!(report["dangling_edges"], 2);
+    assert!(report["repaired"].is_null());
+
+    let report = validate(json!({"repo_id": REPO, "repair": true})).await;
+    assert_eq!(report["repaired"]["edges_removed"], 2);
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (3, 1));
+    let report = validate(json!({"repo_id": REPO})).await;
+    assert_eq!(report["dangling_edges"], 0);
+}
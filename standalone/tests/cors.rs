@@ -0,0 +1,64 @@
+use standalone::cors::parse_origins;
+
+#[test]
+fn test_parse_origins() {
+    let origins = parse_origins("https://mesh.example.com, http://localhost:3000").unwrap();
+    assert_eq!(origins.len(), 2);
+    assert_eq!(origins[1], "http://localhost:3000");
+}
+
+#[test]
+fn test_malformed_origins_are_rejected() {
+    for raw in [
+        "mesh.example.com",
+        "ftp://mesh.example.com",
+        "https://mesh.example.com/app",
+        "https://",
+        "*",
+        " , ",
+    ] {
+        assert!(parse_origins(raw).is_err(), "{} was accepted", raw);
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_disallowed_origin_gets_no_cors_headers() {
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+    state.cors_origins = Some(parse_origins("https://mesh.example.com").unwrap());
+    let app = standalone::router(Arc::new(state));
+
+    let request = |origin: &str| {
+        Request::get("/healthz")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let allowed = app
+        .clone()
+        .oneshot(request("https://mesh.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(
+        allowed.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://mesh.example.com"
+    );
+
+    let denied = app
+        .oneshot(request("https://evil.example.com"))
+        .await
+        .unwrap();
+    assert!(denied
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use standalone::coverage::{covered_by, is_test, test_edges, tests_for, TESTS};
+use standalone::storage::{EdgeRecord, NodeRecord};
+
+fn function(name: &str, file: &str, start: usize, body: &str) -> NodeRecord {
+    NodeRecord {
+        id: format!("function-{}-{}", name, file),
+        start,
+        end: start + body.lines().count(),
+        body: body.to_string(),
+        ..common::node("Function", name, file)
+    }
+}
+
+fn parser() -> Vec<NodeRecord> {
+    vec![
+        function(
+            "parse",
+            "src/parser.rs",
+            0,
+            "pub fn parse(s: &str) -> Vec<String> {\n    tokenize(s)\n}\n",
+        ),
+        function(
+            "tokenize",
+            "src/parser.rs",
+            4,
+            "fn tokenize(s: &str) -> Vec<String> {\n    vec![s.to_string()]\n}\n",
+        ),
+        function(
+            "render",
+            "src/render.rs",
+            0,
+            "pub fn render(s: &str) -> String {\n    parse(s).join(\" \")\n}\n",
+        ),
+        function(
+            "test_parse",
+            "src/parser.rs",
+            10,
+            "#[test]\nfn test_parse() {\n    assert_eq!(parse(\"a\").len(), 1);\n}\n",
+        ),
+    ]
+}
+
+fn edges_of(nodes: &[NodeRecord], edges: &[EdgeRecord]) -> Vec<String> {
+    let name = |id: &str| nodes.iter().find(|n| n.id == id).unwrap().name.clone();
+    test_edges(nodes, edges, "acme/app")
+        .iter()
+        .map(|e| format!("{} {} {}", name(&e.source), e.kind, name(&e.target)))
+        .collect()
+}
+
+#[test]
+fn test_unit_test_links_to_what_it_calls() {
+    let nodes = parser();
+    let mut edges = edges_of(&nodes, &[]);
+    edges.sort();
+    // through parse to tokenize, but not to render, which calls parse itself
+    assert_eq!(
+        edges,
+        vec!["test_parse TESTS parse", "test_parse TESTS tokenize"]
+    );
+}
+
+#[test]
+fn test_tests_are_told_apart_by_language() {
+    let cases = [
+        ("test_parse", "tests/test_parser.py", "def test_parse():\n"),
+        (
+            "TestParse",
+            "parser_test.go",
+            "func TestParse(t *testing.T) {\n",
+        ),
+        (
+            "suite",
+            "src/parser.test.ts",
+            "function suite() {\n  it('parses', () => {})\n}\n",
+        ),
+        (
+            "parses",
+            "src/ParserTest.java",
+            "@Test\nvoid parses() {\n}\n",
+        ),
+        (
+            "works",
+            "src/lib.rs",
+            "#[tokio::test]\nasync fn works() {\n}\n",
+        ),
+    ];
+    for (name, file, body) in cases {
+        assert!(
+            is_test(&function(name, file, 0, body)),
+            "{} in {}",
+            name,
+            file
+        );
+    }
+    let not_tests = [
+        // names alone only count in test files
+        ("test_connection", "app/db.py", "def test_connection():\n"),
+        ("Testify", "parser.go", "func Testify() {\n"),
+        ("parse", "src/parser.rs", "pub fn parse() {\n"),
+    ];
+    for (name, file, body) in not_tests {
+        assert!(
+            !is_test(&function(name, file, 0, body)),
+            "{} in {}",
+            name,
+            file
+        );
+    }
+}
+
+#[test]
+fn test_tests_for_and_covered_by_are_inverses() {
+    let nodes = parser();
+    let edges = test_edges(&nodes, &[], "acme/app");
+    assert!(edges.iter().all(|e| e.kind == TESTS));
+
+    let tested = tests_for(&nodes, &edges, "tokenize");
+    assert_eq!(tested.len(), 1);
+    let tests: Vec<&str> = tested[0].tests.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(tests, vec!["test_parse"]);
+    assert!(tests_for(&nodes, &edges, "render")[0].tests.is_empty());
+
+    let coverage = covered_by(&nodes, &edges, "test_parse");
+    assert_eq!(coverage.len(), 1);
+    let covers: Vec<(&str, usize)> = coverage[0]
+        .covers
+        .iter()
+        .map(|c| (c.name.as_str(), c.line))
+        .collect();
+    assert_eq!(covers, vec![("parse", 1), ("tokenize", 5)]);
+    // only tests cover anything
+    assert!(covered_by(&nodes, &edges, "render").is_empty());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_coverage_endpoints() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let nodes = parser();
+    storage.upsert_nodes(&nodes).await.unwrap();
+    storage
+        .upsert_edges(&test_edges(&nodes, &[], "acme/app"))
+        .await
+        .unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let request = |uri: &str, name: &str| {
+        Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "name": name, "repo": "acme/app" }).to_string(),
+            ))
+            .unwrap()
+    };
+    let response = app
+        .clone()
+        .oneshot(request("/tests-for", "parse"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["definitions"][0]["file"], "src/parser.rs");
+    assert_eq!(body["definitions"][0]["tests"][0]["name"], "test_parse");
+
+    let response = app
+        .clone()
+        .oneshot(request("/covered-by", "test_parse"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["tests"][0]["covers"][0]["name"], "parse");
+
+    let response = app.oneshot(request("/covered-by", "render")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use standalone::callgraph::{link_across_repos, resolve_calls};
+use standalone::imports::{link_imports, resolve_imports, Target};
+use standalone::storage::NodeRecord;
+use standalone::symbols::SymbolIndex;
+
+fn node(repo_id: &str, kind: &str, name: &str, file: &str, body: &str) -> NodeRecord {
+    NodeRecord {
+        repo_id: repo_id.to_string(),
+        body: body.to_string(),
+        ..common::node(kind, name, file)
+    }
+}
+
+fn shared() -> Vec<NodeRecord> {
+    let file = "acme/shared/shared/text.py";
+    vec![
+        node("acme/shared", "File", "text.py", file, ""),
+        node(
+            "acme/shared",
+            "Function",
+            "slugify",
+            file,
+            "def slugify(value):\n    return value.lower()",
+        ),
+    ]
+}
+
+fn web() -> Vec<NodeRecord> {
+    let file = "acme/web/app/views.py";
+    vec![
+        node(
+            "acme/web",
+            "Import",
+            "from shared.text import slugify",
+            file,
+            "from shared.text import slugify",
+        ),
+        node(
+            "acme/web",
+            "Function",
+            "article",
+            file,
+            "def article(title):\n    return render(slugify(title))",
+        ),
+    ]
+}
+
+#[test]
+fn test_symbol_index_by_qualified_name() {
+    let mut nodes = shared();
+    nodes.push(node(
+        "acme/utils",
+        "Function",
+        "slugify",
+        "acme/utils/src/text.rs",
+        "pub fn slugify() {}",
+    ));
+    let index = SymbolIndex::new(&nodes, "acme/web");
+
+    let python = index.symbol("shared.text", "slugify").unwrap();
+    assert_eq!(python.repo_id, "acme/shared");
+    let rust = index.symbol("utils::text::slugify", "slugify").unwrap();
+    assert_eq!(rust.repo_id, "acme/utils");
+    // `text.slugify` is defined by both
+    assert!(index.symbol("text", "slugify").is_none());
+    assert!(index.symbol("shared.text", "titlecase").is_none());
+    assert!(index.symbol(".text", "slugify").is_none());
+    assert_eq!(
+        index.module("shared.text").unwrap().file,
+        "acme/shared/shared/text.py"
+    );
+
+    // the repo's own definitions are never in its index
+    assert!(SymbolIndex::new(&nodes, "acme/shared")
+        .symbol("shared.text", "slugify")
+        .is_none());
+}
+
+#[test]
+fn test_unresolved_call_linked_to_other_repo() {
+    let web = web();
+    let mut all = shared();
+    all.extend(web.clone());
+    let index = SymbolIndex::new(&all, "acme/web");
+
+    let mut graph = resolve_calls(&web, &[]);
+    link_across_repos(&mut graph, &web, &index);
+    let article = &graph.functions[0];
+    assert_eq!(article.unresolved, vec!["render"]);
+    let slugify = article.calls.iter().find(|c| c.name == "slugify").unwrap();
+    assert_eq!(slugify.candidates.len(), 1);
+    assert_eq!(
+        slugify.candidates[0].repo_id.as_deref(),
+        Some("acme/shared")
+    );
+    assert_eq!(slugify.candidates[0].file, "acme/shared/shared/text.py");
+
+    let mut imports = resolve_imports(&web);
+    link_imports(&mut imports, &index);
+    assert_eq!(
+        imports[0].target,
+        Target::Repo {
+            repo_id: "acme/shared".to_string(),
+            file: "acme/shared/shared/text.py".to_string()
+        }
+    );
+}
+
+#[test]
+fn test_missing_repo_stays_unresolved() {
+    let web = web();
+    let index = SymbolIndex::new(&web, "acme/web");
+    let mut graph = resolve_calls(&web, &[]);
+    link_across_repos(&mut graph, &web, &index);
+    assert_eq!(graph.functions[0].unresolved, vec!["render", "slugify"]);
+    assert!(graph.functions[0].calls.is_empty());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_cross_repo_call_graph_endpoint() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage.upsert_nodes(&shared()).await.unwrap();
+    storage.upsert_nodes(&web()).await.unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let call_graph = |body: Value| {
+        let app = app.clone();
+        async move {
+            let request = Request::post("/call-graph")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+
+    let (status, body) =
+        call_graph(serde_json::json!({ "repo": "acme/web", "cross_repo": true })).await;
+    assert_eq!(status, StatusCode::OK);
+    let article = &body["functions"][0];
+    assert_eq!(article["calls"][0]["name"], "slugify");
+    assert_eq!(
+        article["calls"][0]["candidates"][0]["repo_id"],
+        "acme/shared"
+    );
+    assert_eq!(article["unresolved"], serde_json::json!(["render"]));
+
+    let (_, body) = call_graph(serde_json::json!({ "repo": "acme/web" })).await;
+    assert_eq!(
+        body["functions"][0]["unresolved"],
+        serde_json::json!(["render", "slugify"])
+    );
+
+    let (status, _) = call_graph(serde_json::json!({ "cross_repo": true })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_cross_repo_dependencies_endpoint() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    storage.upsert_nodes(&web()).await.unwrap();
+    let state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let dependencies = |body: Value| {
+        let app = app.clone();
+        async move {
+            let request = Request::post("/dependencies")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        }
+    };
+
+    let cross_repo = json!({"repo": "acme/web", "cross_repo": true});
+    // nothing else is ingested yet
+    let body = dependencies(cross_repo.clone()).await;
+    assert_eq!(body["dependencies"][0]["name"], "shared");
+    assert!(body.get("linked").is_none());
+
+    // once it is, the index built before is not used again
+    storage.upsert_nodes(&shared()).await.unwrap();
+    let body = dependencies(cross_repo).await;
+    assert_eq!(body["dependencies"], json!([]));
+    assert_eq!(body["linked"][0]["module"], "shared.text");
+    assert_eq!(
+        body["linked"][0]["target"],
+        json!({"kind": "repo", "path": {"repo_id": "acme/shared", "file": "acme/shared/shared/text.py"}})
+    );
+
+    let body = dependencies(json!({"repo": "acme/web"})).await;
+    assert_eq!(body["dependencies"][0]["name"], "shared");
+    assert!(body.get("linked").is_none());
+}
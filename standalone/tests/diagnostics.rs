@@ -0,0 +1,133 @@
+use standalone::lang::{Diagnostic, Severity};
+use standalone::report::{matches, to_sarif, to_text};
+
+fn diagnostic(file: &str, severity: Severity, message: &str) -> Diagnostic {
+    Diagnostic {
+        file: file.to_string(),
+        start: 10,
+        end: 13,
+        message: message.to_string(),
+        severity,
+    }
+}
+
+#[test]
+fn test_filter_by_severity_and_file() {
+    let error = diagnostic("src/parser/mod.rs", Severity::Error, "syntax error at `;`");
+    assert!(matches(&error, &[], None));
+    assert!(matches(&error, &[Severity::Error], Some("src/parser")));
+    assert!(matches(&error, &[], Some("src/parser/mod.rs")));
+    assert!(!matches(&error, &[Severity::Warning], None));
+    assert!(!matches(&error, &[], Some("src/pars")));
+}
+
+#[test]
+fn test_reports_name_a_rule_per_kind_of_problem() {
+    let diagnostics = [
+        diagnostic("src/lib.rs", Severity::Error, "syntax error at `;`"),
+        diagnostic(
+            "assets/logo.png",
+            Severity::Warning,
+            "file skipped, not a text file",
+        ),
+    ];
+    let text = to_text("acme/app", &diagnostics);
+    assert!(
+        text.contains("src/lib.rs: error at bytes 10..13: syntax error at `;`"),
+        "{}",
+        text
+    );
+
+    let sarif = to_sarif(&diagnostics);
+    let run = &sarif["runs"][0];
+    let rules: Vec<&str> = run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(rules, ["skipped-file", "syntax-error"]);
+    assert_eq!(run["results"][1]["ruleId"], "skipped-file");
+    assert_eq!(run["results"][1]["level"], "warning");
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use serde_json::Value;
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn get(app: &axum::Router, uri: &str) -> (StatusCode, Option<String>, String) {
+        let response = app
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            content_type,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_report_holds_a_seeded_diagnostic() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        storage
+            .replace_diagnostics(
+                "acme/app",
+                &[],
+                &[
+                    diagnostic("src/lib.rs", Severity::Error, "syntax error at `;`"),
+                    diagnostic("src/main.rs", Severity::Warning, "missing `}`"),
+                ],
+            )
+            .await
+            .unwrap();
+        let state = AppState::new(storage, LanguageRegistry::new(), 16);
+        let app = standalone::router(Arc::new(state));
+
+        let (status, _, body) = get(&app, "/diagnostics?repo=acme/app&severity=error").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["repo"], "acme/app");
+        let diagnostics = body["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["file"], "src/lib.rs");
+        assert_eq!(diagnostics[0]["message"], "syntax error at `;`");
+
+        let (status, content_type, body) = get(
+            &app,
+            "/diagnostics?repo=acme/app&file=src/lib.rs&format=sarif",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(content_type.as_deref(), Some("application/sarif+json"));
+        let sarif: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(sarif["version"], "2.1.0");
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["message"]["text"], "syntax error at `;`");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/lib.rs");
+        assert_eq!(location["region"]["byteOffset"], 10);
+
+        let (status, _, _) = get(&app, "/diagnostics?repo=acme/app&format=pdf").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _, _) = get(&app, "/diagnostics?repo=acme/app&severity=fatal").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use common::located;
+use standalone::captures::Grammar;
+use standalone::docs::{annotate, DOC};
+use standalone::storage::NodeRecord;
+use std::path::Path;
+use tree_sitter::Tree;
+
+const RUST: &str = "/// Greets whoever asks.
+///
+/// Says hello.
+#[inline]
+pub fn greet() {}
+
+// not documentation
+fn helper() {}
+";
+
+const PYTHON: &str = "def greet():
+    \"\"\"Greets whoever asks.
+
+    Says hello.
+    \"\"\"
+    pass
+
+def helper():
+    pass
+";
+
+const TYPESCRIPT: &str = "/**
+ * Greets whoever asks.
+ */
+export function greet() {}
+
+function helper() {}
+";
+
+const GO: &str = "// Greet greets whoever asks.
+func Greet() {}
+
+// Stray note.
+
+func helper() {}
+";
+
+const RUBY: &str = "class User
+  # Greets whoever asks.
+  def greet
+  end
+
+  def helper
+  end
+end
+";
+
+fn docs(file: &str, source: &str, mut nodes: Vec<NodeRecord>) -> Vec<Option<String>> {
+    annotate(file, source, tree(file, source).as_ref(), nodes.iter_mut()).unwrap();
+    nodes
+        .into_iter()
+        .map(|n| n.meta.get(DOC).cloned())
+        .collect()
+}
+
+fn tree(file: &str, source: &str) -> Option<Tree> {
+    Grammar::for_path(Path::new(file))?
+        .parse(source, None)
+        .unwrap()
+}
+
+#[test]
+fn test_documented_functions_keep_their_doc_and_others_none() {
+    let nodes = vec![
+        located(RUST, "src/lib.rs", "Function", "greet", "pub fn greet() {}"),
+        located(RUST, "src/lib.rs", "Function", "helper", "fn helper() {}"),
+    ];
+    assert_eq!(
+        docs("src/lib.rs", RUST, nodes),
+        [
+            Some("Greets whoever asks.\n\nSays hello.".to_string()),
+            None
+        ]
+    );
+
+    let nodes =
+        vec![
+        located(
+            PYTHON,
+            "app.py",
+            "Function",
+            "greet",
+            "def greet():\n    \"\"\"Greets whoever asks.\n\n    Says hello.\n    \"\"\"\n    pass",
+        ),
+        located(PYTHON, "app.py", "Function", "helper", "def helper():\n    pass"),
+    ];
+    assert_eq!(
+        docs("app.py", PYTHON, nodes),
+        [
+            Some("Greets whoever asks.\n\nSays hello.".to_string()),
+            None
+        ]
+    );
+}
+
+#[test]
+fn test_each_language_reads_its_own_comments() {
+    let nodes = vec![
+        located(
+            TYPESCRIPT,
+            "src/app.ts",
+            "Function",
+            "greet",
+            "function greet() {}",
+        ),
+        located(
+            TYPESCRIPT,
+            "src/app.ts",
+            "Function",
+            "helper",
+            "function helper() {}",
+        ),
+    ];
+    assert_eq!(
+        docs("src/app.ts", TYPESCRIPT, nodes),
+        [Some("Greets whoever asks.".to_string()), None]
+    );
+
+    // a comment a blank line away documents nothing
+    let nodes = vec![
+        located(GO, "main.go", "Function", "Greet", "func Greet() {}"),
+        located(GO, "main.go", "Function", "helper", "func helper() {}"),
+    ];
+    assert_eq!(
+        docs("main.go", GO, nodes),
+        [Some("Greet greets whoever asks.".to_string()), None]
+    );
+
+    let nodes = vec![
+        located(RUBY, "user.rb", "Function", "greet", "def greet\n  end"),
+        located(RUBY, "user.rb", "Function", "helper", "def helper\n  end"),
+    ];
+    assert_eq!(
+        docs("user.rb", RUBY, nodes),
+        [Some("Greets whoever asks.".to_string()), None]
+    );
+}
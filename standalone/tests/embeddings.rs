@@ -0,0 +1,135 @@
+mod common;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use standalone::embeddings::{embed_nodes, Embedder, EMBEDDING};
+use standalone::storage::NodeRecord;
+use std::sync::Mutex;
+
+/// Embeds every symbol as its name's length, and fails on `broken`.
+#[derive(Default)]
+struct Stub {
+    asked: Mutex<Vec<String>>,
+}
+
+impl Stub {
+    fn asked(&self) -> Vec<String> {
+        let mut asked = self.asked.lock().unwrap().clone();
+        asked.sort();
+        asked
+    }
+}
+
+#[async_trait]
+impl Embedder for Stub {
+    async fn embed(&self, node: &NodeRecord) -> Result<Vec<f32>> {
+        self.asked.lock().unwrap().push(node.name.clone());
+        if node.name == "broken" {
+            bail!("the provider is down");
+        }
+        Ok(vec![node.name.len() as f32, 0.5])
+    }
+}
+
+fn symbol(kind: &str, name: &str, body: &str) -> NodeRecord {
+    NodeRecord {
+        body: body.to_string(),
+        ..common::symbol(kind, name, "src/lib.rs", 0, 1)
+    }
+}
+
+#[tokio::test]
+async fn test_functions_and_files_are_embedded() {
+    let stub = Stub::default();
+    let mut nodes = vec![
+        symbol("File", "src/lib.rs", "fn main() {}\nfn broken() {}\n"),
+        symbol("Function", "main", "fn main() {}"),
+        symbol("Function", "broken", "fn broken() {}"),
+        symbol("Function", "empty", ""),
+        symbol("Class", "User", "struct User;"),
+    ];
+
+    let report = embed_nodes(&stub, &mut nodes, 2).await;
+    assert_eq!(stub.asked(), ["broken", "main", "src/lib.rs"]);
+    assert_eq!(report.embedded, 2);
+    assert_eq!(report.failed, ["function-broken"]);
+    assert_eq!(report.first_error.as_deref(), Some("the provider is down"));
+
+    let embedding = |i: usize| nodes[i].meta.get(EMBEDDING).map(String::as_str);
+    assert_eq!(embedding(0), Some("[10.0,0.5]"));
+    assert_eq!(embedding(1), Some("[4.0,0.5]"));
+    // kept, just without one
+    assert_eq!(nodes.len(), 5);
+    assert_eq!(embedding(2), None);
+    assert_eq!(embedding(3), None);
+    assert_eq!(embedding(4), None);
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_ingest_keeps_symbols_that_fail_to_embed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(
+            root.join("main.rs"),
+            "fn main() {\n    broken();\n}\n\nfn broken() {}\n",
+        )
+        .unwrap();
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let stub = Arc::new(Stub::default());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+        state.allowed_roots = vec![root.clone()];
+        state.embedder = Some(stub.clone());
+        let state = Arc::new(state);
+        let mut events = state.tx.subscribe();
+
+        let body = serde_json::json!({ "path": root }).to_string();
+        let request = Request::post("/ingest-path")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = standalone::router(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let names = stub.asked();
+        assert!(names.contains(&"main".to_string()), "{:?}", names);
+        assert!(names.contains(&"broken".to_string()), "{:?}", names);
+        let (nodes, _) = state.storage.load_graph(None).await.unwrap();
+        let function = |name: &str| {
+            nodes
+                .iter()
+                .find(|n| n.kind == "Function" && n.name == name)
+                .unwrap_or_else(|| panic!("{} wasn't stored", name))
+        };
+        assert!(function("main").meta.contains_key(EMBEDDING));
+        assert!(!function("broken").meta.contains_key(EMBEDDING));
+
+        let mut warnings = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.update.status == "warning" {
+                warnings.push(event.update.message);
+            }
+        }
+        assert!(
+            warnings
+                .iter()
+                .any(|w| w.contains("1 were kept without an embedding")),
+            "{:?}",
+            warnings
+        );
+    }
+}
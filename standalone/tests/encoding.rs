@@ -0,0 +1,68 @@
+use standalone::encoding::{decode, is_utf16, Encoding};
+
+const SOURCE: &str = "// naïve\nfn main() {}\n";
+
+fn utf16(source: &str, big_endian: bool) -> Vec<u8> {
+    source
+        .encode_utf16()
+        .flat_map(|u| {
+            if big_endian {
+                u.to_be_bytes()
+            } else {
+                u.to_le_bytes()
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_decode_detects_the_encoding() {
+    let decoded = decode(SOURCE.as_bytes()).unwrap();
+    assert_eq!(
+        (decoded.text.as_str(), decoded.encoding),
+        (SOURCE, Encoding::Utf8)
+    );
+
+    let mut bom = b"\xEF\xBB\xBF".to_vec();
+    bom.extend(SOURCE.as_bytes());
+    let decoded = decode(&bom).unwrap();
+    assert_eq!(
+        (decoded.text.as_str(), decoded.encoding),
+        (SOURCE, Encoding::Utf8Bom)
+    );
+
+    let mut le = vec![0xFF, 0xFE];
+    le.extend(utf16(SOURCE, false));
+    let decoded = decode(&le).unwrap();
+    assert_eq!(
+        (decoded.text.as_str(), decoded.encoding),
+        (SOURCE, Encoding::Utf16Le)
+    );
+
+    // without a BOM, told by where the NULs fall
+    let decoded = decode(&utf16(SOURCE, true)).unwrap();
+    assert_eq!(
+        (decoded.text.as_str(), decoded.encoding),
+        (SOURCE, Encoding::Utf16Be)
+    );
+    assert!(is_utf16(&utf16(SOURCE, false)));
+
+    let latin = b"// na\xefve\nfn main() {}\n";
+    let decoded = decode(latin).unwrap();
+    assert_eq!(
+        (decoded.text.as_str(), decoded.encoding),
+        (SOURCE, Encoding::Windows1252)
+    );
+}
+
+#[test]
+fn test_undetermined_encodings_fail() {
+    // bytes Windows-1252 leaves unassigned
+    assert!(decode(b"fn main() {}\n\x81\x8d").is_err());
+    // a control byte no text file holds
+    assert!(decode(b"fn main() {}\x01\xff").is_err());
+    // a BOM that lies
+    assert!(decode(b"\xEF\xBB\xBF\xff\xfe").is_err());
+    // the NULs of a binary file aren't UTF-16's
+    assert!(!is_utf16(b"PK\x03\x04\x00\x00\x14\x00"));
+}
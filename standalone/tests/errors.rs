@@ -0,0 +1,138 @@
+use axum::body::to_bytes;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use standalone::clone::CloneError;
+use standalone::types::MeshError;
+
+async fn respond(err: MeshError) -> (StatusCode, serde_json::Value) {
+    let response = err.into_response();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_error_status_codes() {
+    let cases = vec![
+        (
+            MeshError::Parse {
+                file: "src/main.rs".to_string(),
+                message: "unexpected token".to_string(),
+            },
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "parse",
+        ),
+        (
+            MeshError::Storage(anyhow::anyhow!("connection refused")),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "storage",
+        ),
+        (
+            MeshError::Git("bad revision".to_string()),
+            StatusCode::BAD_GATEWAY,
+            "git",
+        ),
+        (
+            MeshError::Clone(CloneError {
+                url: "https://example.com/a/b".to_string(),
+                attempts: 3,
+                message: "timed out".to_string(),
+                auth: false,
+            }),
+            StatusCode::BAD_GATEWAY,
+            "clone",
+        ),
+        (
+            MeshError::GitAuth(CloneError {
+                url: "https://example.com/a/b".to_string(),
+                attempts: 1,
+                message: "the remote rejected the credentials".to_string(),
+                auth: true,
+            }),
+            StatusCode::UNAUTHORIZED,
+            "git_auth",
+        ),
+        (
+            MeshError::validation("missing parameter"),
+            StatusCode::BAD_REQUEST,
+            "validation",
+        ),
+        (
+            MeshError::NotFound("Repository a/b not found".to_string()),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        ),
+        (
+            MeshError::Conflict("No ingest of a/b is running".to_string()),
+            StatusCode::CONFLICT,
+            "conflict",
+        ),
+        (
+            MeshError::Aborted("shutting down".to_string()),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "aborted",
+        ),
+        (
+            MeshError::ReadOnly("This server is read-only".to_string()),
+            StatusCode::METHOD_NOT_ALLOWED,
+            "read_only",
+        ),
+        (
+            MeshError::Cancelled("ingest cancelled".to_string()),
+            StatusCode::CONFLICT,
+            "cancelled",
+        ),
+        (
+            MeshError::Unauthorized("Invalid webhook signature".to_string()),
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+        ),
+        (
+            MeshError::Timeout {
+                message: "query timed out after 100 ms".to_string(),
+                elapsed: std::time::Duration::from_millis(100),
+            },
+            StatusCode::GATEWAY_TIMEOUT,
+            "timeout",
+        ),
+        (
+            MeshError::Unavailable {
+                message: "Too many event streams open".to_string(),
+                retry_after: std::time::Duration::from_secs(10),
+            },
+            StatusCode::SERVICE_UNAVAILABLE,
+            "unavailable",
+        ),
+        (
+            MeshError::from(std::io::Error::other("disk full")),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+        ),
+    ];
+    for (err, status, kind) in cases {
+        let (actual, body) = respond(err).await;
+        assert_eq!(actual, status, "{}", kind);
+        assert_eq!(body["kind"], kind);
+        assert!(body["error"].as_str().is_some_and(|e| !e.is_empty()));
+    }
+}
+
+#[tokio::test]
+async fn test_error_body_details() {
+    let (_, body) = respond(MeshError::Parse {
+        file: "src/main.rs".to_string(),
+        message: "unexpected token".to_string(),
+    })
+    .await;
+    assert_eq!(body["file"], "src/main.rs");
+
+    let (_, body) = respond(MeshError::Clone(CloneError {
+        url: "https://example.com/a/b".to_string(),
+        attempts: 3,
+        message: "timed out".to_string(),
+        auth: false,
+    }))
+    .await;
+    assert_eq!(body["attempts"], 3);
+    assert!(body["error"].as_str().unwrap().contains("timed out"));
+}
@@ -0,0 +1,516 @@
+mod common;
+
+use common::node;
+use regex::Regex;
+use standalone::export::{
+    ndjson_lines, parse_ndjson, to_cytoscape, to_dot, to_mermaid, CytoscapeOptions, Direction,
+    DotOptions, MermaidGraph, MermaidOptions, TRUNCATED_ID,
+};
+use standalone::storage::{EdgeRecord, NodeRecord};
+
+fn edge(kind: &str, source: &NodeRecord, target: &NodeRecord) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: kind.to_string(),
+        source: source.id.clone(),
+        target: target.id.clone(),
+    }
+}
+
+/// Checks the subset of DOT that `to_dot` emits, one statement per line, and
+/// returns the node ids and edge count.
+fn parse_dot(dot: &str) -> (Vec<String>, usize) {
+    let id = r#""(?:[^"\\]|\\.)*""#;
+    let attrs = format!(r#"\[(?:\w+=(?:{}|[\w#]+)(?:, )?)*\]"#, id);
+    let node_stmt = Regex::new(&format!(r"^  ({}) {};$", id, attrs)).unwrap();
+    let edge_stmt = Regex::new(&format!(r"^  {} -> {} {};$", id, id, attrs)).unwrap();
+    let graph_attr = Regex::new(r"^  (rankdir=\w+|node \[.*\]);$").unwrap();
+
+    let lines: Vec<&str> = dot.lines().collect();
+    assert_eq!(lines.first(), Some(&"digraph mesh {"));
+    assert_eq!(lines.last(), Some(&"}"));
+    let (mut nodes, mut edges) = (Vec::new(), 0);
+    for line in &lines[1..lines.len() - 1] {
+        if let Some(c) = node_stmt.captures(line) {
+            nodes.push(c[1].trim_matches('"').to_string());
+        } else if edge_stmt.is_match(line) {
+            edges += 1;
+        } else {
+            assert!(graph_attr.is_match(line), "not a DOT statement: {}", line);
+        }
+    }
+    (nodes, edges)
+}
+
+fn sample() -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let file = node("File", "main.rs", "src/main.rs");
+    let main = node("Function", "main", "src/main.rs");
+    let quoted = node("Function", "say \"hi\"", "src/main.rs");
+    let import = node("Import", "use util", "src/main.rs");
+    let edges = vec![
+        edge("CONTAINS", &file, &main),
+        edge("CONTAINS", &file, &import),
+        edge("CALLS", &main, &quoted),
+        edge("IMPORTS", &import, &file),
+    ];
+    (vec![file, main, quoted, import], edges)
+}
+
+#[test]
+fn test_dot_is_valid_and_complete() {
+    let (nodes, edges) = sample();
+    let dot = to_dot(&nodes, &edges, &DotOptions::default());
+    let (ids, edge_count) = parse_dot(&dot);
+    assert_eq!(ids.len(), 4);
+    assert_eq!(edge_count, 4);
+    assert!(dot.contains(r#"label="say \"hi\"""#));
+    assert!(dot.contains("style=dotted"));
+    assert!(dot.contains("style=dashed"));
+}
+
+#[test]
+fn test_dot_kind_filter_drops_dangling_edges() {
+    let (nodes, edges) = sample();
+    let options = DotOptions {
+        kinds: vec!["Function".to_string()],
+        ..Default::default()
+    };
+    let (ids, edge_count) = parse_dot(&to_dot(&nodes, &edges, &options));
+    assert_eq!(ids.len(), 2);
+    assert_eq!(edge_count, 1);
+}
+
+#[test]
+fn test_dot_truncation_marker() {
+    let (nodes, edges) = sample();
+    let options = DotOptions {
+        max_nodes: 2,
+        ..Default::default()
+    };
+    let dot = to_dot(&nodes, &edges, &options);
+    let (ids, edge_count) = parse_dot(&dot);
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids.last().map(String::as_str), Some(TRUNCATED_ID));
+    assert!(dot.contains("2 more nodes not shown"));
+    assert_eq!(edge_count, 1);
+}
+
+/// Checks the elements are what Cytoscape.js accepts: unique ids, a group on
+/// each, and edges between nodes that are present. Returns the node labels and
+/// edge count.
+fn parse_cytoscape(json: &serde_json::Value) -> (Vec<String>, usize) {
+    let elements = json["elements"].as_array().expect("an elements array");
+    let mut ids = std::collections::HashSet::new();
+    let (mut nodes, mut edges) = (Vec::new(), 0);
+    for element in elements {
+        let data = &element["data"];
+        let id = data["id"].as_str().expect("every element has an id");
+        assert!(ids.insert(id.to_string()), "duplicate id {}", id);
+        assert!(element["classes"].is_string());
+        match element["group"].as_str() {
+            Some("nodes") => {
+                assert_eq!(edges, 0, "nodes come before edges");
+                nodes.push(data["label"].as_str().unwrap().to_string());
+            }
+            Some("edges") => {
+                for end in ["source", "target"] {
+                    let end = data[end].as_str().unwrap();
+                    assert!(ids.contains(end), "edge to missing node {}", end);
+                }
+                edges += 1;
+            }
+            group => panic!("bad group {:?}", group),
+        }
+    }
+    (nodes, edges)
+}
+
+#[test]
+fn test_cytoscape_is_valid_and_complete() {
+    let (nodes, edges) = sample();
+    let graph = to_cytoscape(&nodes, &edges, &CytoscapeOptions::default()).unwrap();
+    let json = serde_json::to_value(&graph).unwrap();
+    let (labels, edge_count) = parse_cytoscape(&json);
+    assert_eq!(labels.len(), 4);
+    assert_eq!(edge_count, 4);
+    assert_eq!(json["truncated"], 0);
+    assert_eq!(json["elements"][0]["classes"], "file file");
+    assert_eq!(json["elements"][1]["classes"], "function function");
+    assert_eq!(json["elements"][4]["classes"], "contains");
+}
+
+#[test]
+fn test_cytoscape_neighborhood() {
+    let (nodes, edges) = sample();
+    let around = |radius, max_nodes| CytoscapeOptions {
+        around: Some("say \"hi\"".to_string()),
+        radius,
+        max_nodes,
+        ..Default::default()
+    };
+    let graph = to_cytoscape(&nodes, &edges, &around(1, 10)).unwrap();
+    let (labels, edge_count) = parse_cytoscape(&serde_json::to_value(&graph).unwrap());
+    assert_eq!(labels, vec!["say \"hi\"", "main"]);
+    assert_eq!(edge_count, 1);
+
+    // nearest first, so the cap drops the far end
+    let graph = to_cytoscape(&nodes, &edges, &around(2, 10)).unwrap();
+    let (labels, edge_count) = parse_cytoscape(&serde_json::to_value(&graph).unwrap());
+    assert_eq!(labels, vec!["say \"hi\"", "main", "main.rs"]);
+    assert_eq!(edge_count, 2);
+    let graph = to_cytoscape(&nodes, &edges, &around(2, 2)).unwrap();
+    let (labels, _) = parse_cytoscape(&serde_json::to_value(&graph).unwrap());
+    assert_eq!(labels.len(), 2);
+    assert_eq!(graph.truncated, 1);
+
+    let missing = CytoscapeOptions {
+        around: Some("nowhere".to_string()),
+        ..Default::default()
+    };
+    assert!(to_cytoscape(&nodes, &edges, &missing).is_none());
+}
+
+/// Checks the subset of Mermaid that `to_mermaid` emits: a flowchart header,
+/// then one node, edge or class statement per line, every edge between
+/// declared nodes. Returns the node labels and the edges by label.
+fn parse_mermaid(diagram: &str) -> (Vec<String>, Vec<(String, String, String)>) {
+    let node_stmt = Regex::new(r#"^  (\w+)\["([^"]*)"\]$"#).unwrap();
+    let edge_stmt = Regex::new(r"^  (\w+) (?:-->|-\.->)\|(\w+)\| (\w+)$").unwrap();
+    let class_stmt = Regex::new(r"^  (classDef \w+ [\w:-]+|class [\w,]+ \w+)$").unwrap();
+
+    let mut lines = diagram.lines();
+    let header = lines.next().unwrap();
+    assert!(
+        Regex::new(r"^flowchart (TB|BT|LR|RL)$")
+            .unwrap()
+            .is_match(header),
+        "not a flowchart: {}",
+        header
+    );
+    let mut labels = std::collections::HashMap::new();
+    let (mut nodes, mut edges) = (Vec::new(), Vec::new());
+    for line in lines {
+        if let Some(c) = node_stmt.captures(line) {
+            assert!(labels.insert(c[1].to_string(), c[2].to_string()).is_none());
+            nodes.push(c[2].to_string());
+        } else if let Some(c) = edge_stmt.captures(line) {
+            let label = |id: &str| labels.get(id).cloned().expect("edge to an undeclared node");
+            edges.push((label(&c[1]), c[2].to_string(), label(&c[3])));
+        } else {
+            assert!(
+                class_stmt.is_match(line),
+                "not a Mermaid statement: {}",
+                line
+            );
+        }
+    }
+    (nodes, edges)
+}
+
+fn function(name: &str, body: &str) -> NodeRecord {
+    NodeRecord {
+        body: body.to_string(),
+        ..node("Function", name, "src/app.py")
+    }
+}
+
+#[test]
+fn test_mermaid_call_graph_is_valid_and_bounded() {
+    let nodes = vec![
+        function("main", "def main():\n    serve()\n"),
+        function("serve", "def serve():\n    handle(\"<a href='#'>\")\n"),
+        function("handle", "def handle(html):\n    render(html)\n"),
+        function("render", "def render(html):\n    pass\n"),
+        function("unrelated", "def unrelated():\n    pass\n"),
+    ];
+    let options = MermaidOptions {
+        around: "serve".to_string(),
+        radius: 1,
+        ..Default::default()
+    };
+    let diagram = to_mermaid(&nodes, &[], &options).unwrap();
+    assert!(diagram.starts_with("flowchart LR\n"));
+    let (labels, edges) = parse_mermaid(&diagram);
+    assert_eq!(labels, ["serve", "main", "handle"]);
+    let edge = |a: &str, b: &str| (a.to_string(), "CALLS".to_string(), b.to_string());
+    assert_eq!(edges, [edge("main", "serve"), edge("serve", "handle")]);
+    assert!(diagram.contains("  class n0 focus"));
+
+    let options = MermaidOptions {
+        radius: 2,
+        max_nodes: 3,
+        direction: Direction::TopDown,
+        ..options
+    };
+    let diagram = to_mermaid(&nodes, &[], &options).unwrap();
+    assert!(diagram.starts_with("flowchart TB\n"));
+    let (labels, edges) = parse_mermaid(&diagram);
+    assert_eq!(
+        labels,
+        ["serve", "main", "handle", "1 more nodes not shown"]
+    );
+    assert_eq!(edges.len(), 2);
+    assert!(diagram.contains(&format!("  {}[", TRUNCATED_ID)));
+
+    let missing = MermaidOptions {
+        around: "nowhere".to_string(),
+        ..Default::default()
+    };
+    assert!(to_mermaid(&nodes, &[], &missing).is_none());
+}
+
+#[test]
+fn test_mermaid_labels_are_escaped() {
+    let (nodes, edges) = sample();
+    let options = MermaidOptions {
+        around: "main".to_string(),
+        ..Default::default()
+    };
+    let diagram = to_mermaid(&nodes, &edges, &options).unwrap();
+    let (labels, edges) = parse_mermaid(&diagram);
+    assert_eq!(labels, ["main", "say #quot;hi#quot;"]);
+    assert_eq!(edges.len(), 1);
+
+    let odd = vec![
+        function("operator<<", "fn x() {}"),
+        function("C#`sharp`", ""),
+    ];
+    for (around, label) in [
+        ("operator<<", "operator#lt;#lt;"),
+        ("C#`sharp`", "C#35;#96;sharp#96;"),
+    ] {
+        let options = MermaidOptions {
+            around: around.to_string(),
+            ..Default::default()
+        };
+        let (labels, _) = parse_mermaid(&to_mermaid(&odd, &[], &options).unwrap());
+        assert_eq!(labels, [label]);
+    }
+}
+
+#[test]
+fn test_mermaid_hierarchy() {
+    let class = |name: &str, body: &str| NodeRecord {
+        body: body.to_string(),
+        ..node("Class", name, "zoo.py")
+    };
+    let nodes = vec![
+        class("Animal", "class Animal:\n    pass\n"),
+        class("Dog", "class Dog(Animal):\n    pass\n"),
+        class("Puppy", "class Puppy(Dog):\n    pass\n"),
+        class("Rock", "class Rock:\n    pass\n"),
+    ];
+    let options = MermaidOptions {
+        graph: MermaidGraph::Hierarchy,
+        around: "Dog".to_string(),
+        direction: Direction::BottomUp,
+        ..Default::default()
+    };
+    let diagram = to_mermaid(&nodes, &[], &options).unwrap();
+    assert!(diagram.starts_with("flowchart BT\n"));
+    let (mut labels, mut edges) = parse_mermaid(&diagram);
+    labels.sort();
+    edges.sort();
+    assert_eq!(labels, ["Animal", "Dog", "Puppy"]);
+    let extends = |a: &str, b: &str| (a.to_string(), "EXTENDS".to_string(), b.to_string());
+    assert_eq!(edges, [extends("Dog", "Animal"), extends("Puppy", "Dog")]);
+}
+
+#[test]
+fn test_ndjson_round_trip() {
+    let (nodes, edges) = sample();
+    let dump: String = ndjson_lines(nodes.clone(), edges.clone()).collect();
+    assert_eq!(dump.lines().count(), nodes.len() + edges.len());
+    for line in dump.lines() {
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(
+            value["type"] == "node" || value["type"] == "edge",
+            "{}",
+            line
+        );
+    }
+    assert_eq!(parse_ndjson(&dump, None).unwrap(), (nodes, edges));
+
+    let (moved, _) = parse_ndjson(&dump, Some("acme/copy")).unwrap();
+    assert!(moved.iter().all(|n| n.repo_id == "acme/copy"));
+}
+
+#[test]
+fn test_ndjson_reports_first_bad_line() {
+    let (nodes, edges) = sample();
+    let dump: String = ndjson_lines(nodes[1..].to_vec(), edges).collect();
+    // the first edge is CONTAINS from the File node that was left out
+    let err = parse_ndjson(&dump, None).unwrap_err();
+    assert_eq!(err.line, 4);
+    assert!(err.message.contains("unknown node"), "{}", err);
+
+    let mut lines: Vec<&str> = dump.lines().collect();
+    lines.insert(1, "{\"type\": \"nodes\"}");
+    let err = parse_ndjson(&lines.join("\n"), None).unwrap_err();
+    assert_eq!(err.line, 2);
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::sample;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_json_export_import_round_trip() {
+        let source = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let (nodes, edges) = sample();
+        for node in &nodes {
+            source.upsert_node(node).await.unwrap();
+        }
+        for edge in &edges {
+            source.upsert_edge(edge).await.unwrap();
+        }
+        let app = standalone::router(Arc::new(AppState::new(
+            source.clone(),
+            LanguageRegistry::new(),
+            16,
+        )));
+        let response = app
+            .oneshot(
+                Request::get("/export/json?repo=acme/app")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let dump = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let target = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let app = standalone::router(Arc::new(AppState::new(
+            target.clone(),
+            LanguageRegistry::new(),
+            16,
+        )));
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/import/json")
+                    .body(Body::from(dump.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (mut exported, mut restored) = (
+            source.load_graph(Some("acme/app")).await.unwrap(),
+            target.load_graph(Some("acme/app")).await.unwrap(),
+        );
+        for graph in [&mut exported, &mut restored] {
+            graph.0.sort_by(|a, b| a.id.cmp(&b.id));
+            graph
+                .1
+                .sort_by(|a, b| (&a.source, &a.target).cmp(&(&b.source, &b.target)));
+        }
+        assert_eq!(exported, restored);
+
+        // without the `main` node its CONTAINS and CALLS edges dangle
+        let broken: String = String::from_utf8(dump.to_vec())
+            .unwrap()
+            .lines()
+            .filter(|l| !(l.starts_with(r#"{"type":"node""#) && l.contains(r#""name":"main""#)))
+            .map(|l| format!("{}\n", l))
+            .collect();
+        let response = app
+            .oneshot(
+                Request::post("/import/json")
+                    .body(Body::from(broken))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_export_is_not_modified() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let (nodes, edges) = sample();
+        storage.upsert_nodes(&nodes).await.unwrap();
+        storage.upsert_edges(&edges).await.unwrap();
+        let app = standalone::router(Arc::new(AppState::new(
+            storage.clone(),
+            LanguageRegistry::new(),
+            16,
+        )));
+        let get = |uri: &str, etag: Option<&str>| {
+            let mut request = Request::get(uri);
+            if let Some(etag) = etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        for uri in [
+            "/export/json?repo=acme/app",
+            "/export/dot?repo=acme/app",
+            "/export/cytoscape?repo=acme/app",
+            "/export/mermaid?repo=acme/app&around=main&direction=TB",
+        ] {
+            let response = get(uri, None).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let etag = response.headers()[header::ETAG]
+                .to_str()
+                .unwrap()
+                .to_string();
+
+            let response = get(uri, Some(&etag)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(response.headers()[header::ETAG], etag.as_str());
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert!(body.is_empty());
+
+            let response = get(uri, Some(&format!("\"other\", W/{}", etag)))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        }
+
+        let response = get("/export/cytoscape?repo=acme/app&around=main&radius=1", None)
+            .await
+            .unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        // main, the file holding it and the function it calls
+        assert_eq!(
+            super::parse_cytoscape(&body),
+            (
+                vec![
+                    "main".to_string(),
+                    "main.rs".to_string(),
+                    "say \"hi\"".to_string()
+                ],
+                2
+            )
+        );
+        let response = get("/export/cytoscape?repo=acme/app&around=nowhere", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = get("/export/json?repo=acme/app", None).await.unwrap();
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        storage.delete_edges(&edges[..1]).await.unwrap();
+        let response = get("/export/json?repo=acme/app", Some(&etag))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[header::ETAG], etag.as_str());
+    }
+}
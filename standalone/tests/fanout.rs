@@ -0,0 +1,150 @@
+mod common;
+
+use standalone::callgraph::{cap_references, references, resolve_calls};
+use standalone::fanout::cap;
+use standalone::neighborhood::{neighborhood, Scope};
+use standalone::storage::{EdgeRecord, NodeRecord};
+
+const CALLERS: usize = 3_000;
+/// Callers that are called themselves, by `main`.
+const CENTRAL: usize = 10;
+
+fn function(name: &str, file: &str, body: &str) -> NodeRecord {
+    NodeRecord {
+        file: file.to_string(),
+        end: 2,
+        body: body.to_string(),
+        ..common::function(name)
+    }
+}
+
+fn calls(source: &str, target: &str) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: "CALLS".to_string(),
+        source: format!("function-{}", source),
+        target: format!("function-{}", target),
+    }
+}
+
+/// `util`, called from thousands of functions, of which `main` calls a few.
+fn hub() -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let mut nodes = vec![function("util", "src/util.rs", "fn util() {}")];
+    let mut edges = Vec::new();
+    // numbered from the back, so the central callers don't sort first by name
+    let caller = |i: usize| format!("c{:04}", CALLERS - i);
+    for i in 0..CALLERS {
+        let name = caller(i);
+        let body = format!("fn {}() {{\n    util()\n}}", name);
+        nodes.push(function(&name, &format!("src/{}.rs", name), &body));
+        edges.push(calls(&name, "util"));
+    }
+    let central: Vec<String> = (0..CENTRAL).map(caller).collect();
+    let body = central
+        .iter()
+        .map(|name| format!("    {}();\n", name))
+        .collect::<String>();
+    nodes.push(function(
+        "main",
+        "src/main.rs",
+        &format!("fn main() {{\n{}}}", body),
+    ));
+    edges.extend(central.iter().map(|name| calls("main", name)));
+    (nodes, edges)
+}
+
+fn central() -> Vec<String> {
+    let mut ids: Vec<String> = (0..CENTRAL)
+        .map(|i| format!("function-c{:04}", CALLERS - i))
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn test_neighborhood_of_a_hub_is_capped_to_its_best_connected_neighbors() {
+    let (nodes, edges) = hub();
+    let scope = Scope {
+        max_edges_per_node: Some(50),
+        max_nodes: 10_000,
+        ..Scope::default()
+    };
+    let subgraph = neighborhood(&nodes, &edges, "function-util", &scope).unwrap();
+    assert!(subgraph.truncated);
+    assert_eq!(subgraph.nodes.len(), 51);
+    assert_eq!(subgraph.nodes[0].omitted, CALLERS - 50);
+    assert_eq!(subgraph.edges.len(), 50);
+    // the callers main calls have an edge more than the rest
+    let first: Vec<String> = subgraph.nodes[1..=CENTRAL]
+        .iter()
+        .map(|n| n.node.id.clone())
+        .collect();
+    assert_eq!(first, central());
+
+    // without a cap every caller is there
+    let scope = Scope {
+        max_edges_per_node: None,
+        max_nodes: 10_000,
+        ..Scope::default()
+    };
+    let subgraph = neighborhood(&nodes, &edges, "function-util", &scope).unwrap();
+    assert!(!subgraph.truncated);
+    assert_eq!(subgraph.nodes.len(), CALLERS + 1);
+    assert_eq!(subgraph.nodes[0].omitted, 0);
+}
+
+#[test]
+fn test_references_to_a_hub_are_capped_and_ordered() {
+    let (nodes, edges) = hub();
+    let graph = resolve_calls(&nodes, &edges);
+    let mut usages = references(&graph, &nodes, "util");
+    assert_eq!(usages[0].references.len(), CALLERS);
+
+    cap_references(&mut usages, &graph, Some(100));
+    assert_eq!(usages[0].references.len(), 100);
+    assert_eq!(usages[0].omitted, CALLERS - 100);
+    let mut first: Vec<String> = usages[0].references[..CENTRAL]
+        .iter()
+        .map(|r| r.id.clone())
+        .collect();
+    first.sort();
+    assert_eq!(first, central());
+    let json = serde_json::to_value(&usages[0]).unwrap();
+    assert_eq!(json["omitted"], CALLERS - 100);
+}
+
+#[test]
+fn test_call_candidates_are_capped() {
+    let mut nodes: Vec<NodeRecord> = (0..200)
+        .map(|i| {
+            let mut node = function("handle", &format!("src/h{}.rs", i), "fn handle() {}");
+            node.id = format!("function-handle-{}", i);
+            node
+        })
+        .collect();
+    nodes.push(function(
+        "main",
+        "src/main.rs",
+        "fn main() {\n    handle()\n}",
+    ));
+    let mut graph = resolve_calls(&nodes, &[]);
+    graph.cap_fan_out(Some(20));
+    let main = graph.functions.iter().find(|f| f.name == "main").unwrap();
+    assert_eq!(main.calls[0].candidates.len(), 20);
+    assert_eq!(main.calls[0].omitted, 180);
+    assert_eq!(main.omitted, 0);
+    // functions calling nothing say nothing was left out
+    let handle = serde_json::to_value(&graph.functions[0]).unwrap();
+    assert!(handle.get("omitted").is_none());
+}
+
+#[test]
+fn test_cap_leaves_short_lists_in_order() {
+    let mut short = vec![3, 1, 2];
+    assert_eq!(cap(&mut short, Some(3), |a, b| b.cmp(a)), 0);
+    assert_eq!(short, [3, 1, 2]);
+    let mut long = vec![3, 1, 4, 1, 5];
+    assert_eq!(cap(&mut long, Some(2), |a, b| b.cmp(a)), 3);
+    assert_eq!(long, [5, 4]);
+    assert_eq!(cap(&mut long, None, |a, b| a.cmp(b)), 0);
+}
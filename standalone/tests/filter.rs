@@ -0,0 +1,183 @@
+use standalone::filter::{detect_language, FileFilter};
+use standalone::lang::LanguageRegistry;
+use std::path::Path;
+
+const FILES: &[&str] = &[
+    "src/main.rs",
+    "src/lib.rs",
+    "vendor/dep/src/lib.rs",
+    "scripts/build.py",
+    "scripts/gen/proto.py",
+    "web/app.ts",
+    "README.md",
+];
+
+fn filtered(include: &[&str], exclude: &[&str]) -> Vec<String> {
+    let include: Vec<String> = include.iter().map(|s| s.to_string()).collect();
+    let exclude: Vec<String> = exclude.iter().map(|s| s.to_string()).collect();
+    let filter = FileFilter::new(&include, &exclude, &LanguageRegistry::new()).unwrap();
+    filter.apply(FILES.iter().map(|s| s.to_string()).collect())
+}
+
+#[test]
+fn test_empty_filter_allows_everything() {
+    let filter = FileFilter::new(&[], &[], &LanguageRegistry::new()).unwrap();
+    assert!(filter.is_empty());
+    assert_eq!(filtered(&[], &[]).len(), FILES.len());
+}
+
+#[test]
+fn test_include_langs() {
+    assert_eq!(
+        filtered(&["Rust", "python"], &[]),
+        vec![
+            "src/main.rs",
+            "src/lib.rs",
+            "vendor/dep/src/lib.rs",
+            "scripts/build.py",
+            "scripts/gen/proto.py",
+        ]
+    );
+}
+
+#[test]
+fn test_exclude_globs() {
+    assert_eq!(
+        filtered(&[], &["vendor/**", "*.md"]),
+        vec![
+            "src/main.rs",
+            "src/lib.rs",
+            "scripts/build.py",
+            "scripts/gen/proto.py",
+            "web/app.ts",
+        ]
+    );
+    // `*` does not cross directories
+    assert_eq!(
+        filtered(&[], &["scripts/*.py"]),
+        vec![
+            "src/main.rs",
+            "src/lib.rs",
+            "vendor/dep/src/lib.rs",
+            "scripts/gen/proto.py",
+            "web/app.ts",
+            "README.md",
+        ]
+    );
+}
+
+#[test]
+fn test_exclude_wins_over_include() {
+    assert_eq!(
+        filtered(&["rust", "python"], &["vendor/**", "scripts/gen/**"]),
+        vec!["src/main.rs", "src/lib.rs", "scripts/build.py"]
+    );
+}
+
+#[test]
+fn test_bad_filters_are_rejected() {
+    let registry = LanguageRegistry::new();
+    let cobol = vec!["cobol".to_string()];
+    assert!(FileFilter::new(&cobol, &[], &registry).is_err());
+    let glob = vec!["src/[".to_string()];
+    assert!(FileFilter::new(&[], &glob, &registry).is_err());
+}
+
+#[test]
+fn test_meshignore_narrows_listed_files() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join(".meshignore"),
+        "vendor/\nscripts/**/*.py\n!scripts/build.py\n",
+    )
+    .unwrap();
+    let mut filter = FileFilter::new(&[], &[], &LanguageRegistry::new()).unwrap();
+    filter.ignore_file(dir.path()).unwrap();
+    assert!(!filter.is_empty());
+    let files = filter.apply(FILES.iter().map(|s| s.to_string()).collect());
+    assert_eq!(
+        files,
+        vec![
+            "src/main.rs",
+            "src/lib.rs",
+            "scripts/build.py",
+            "web/app.ts",
+            "README.md"
+        ]
+    );
+}
+
+#[test]
+fn test_missing_meshignore_leaves_filter_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut filter = FileFilter::new(&[], &[], &LanguageRegistry::new()).unwrap();
+    filter.ignore_file(dir.path()).unwrap();
+    assert!(filter.is_empty());
+}
+
+#[test]
+fn test_detect_language_from_shebang() {
+    let detect = |path: &str, head: &str| detect_language(Path::new(path), head.as_bytes());
+    assert_eq!(
+        detect("bin/deploy", "#!/usr/bin/env python3\nimport sys\n"),
+        Some("python")
+    );
+    assert_eq!(
+        detect("bin/serve", "#!/usr/local/bin/python3.12 -u\n"),
+        Some("python")
+    );
+    assert_eq!(
+        detect("bin/run", "#!/usr/bin/env -S deno run\n"),
+        Some("javascript")
+    );
+    assert_eq!(detect("bin/setup", "#!/bin/bash\nset -e\n"), Some("shell"));
+    assert_eq!(detect("bin/data", "just some text\n"), None);
+    // an unambiguous extension wins over the shebang
+    assert_eq!(detect("tool.rb", "#!/usr/bin/env python3\n"), Some("ruby"));
+}
+
+#[test]
+fn test_detect_language_from_file_name() {
+    let detect = |path: &str| detect_language(Path::new(path), b"");
+    assert_eq!(detect("Makefile"), Some("make"));
+    assert_eq!(detect("src/GNUmakefile"), Some("make"));
+    assert_eq!(detect("Dockerfile"), Some("dockerfile"));
+    assert_eq!(detect("docker/Dockerfile.dev"), Some("dockerfile"));
+    assert_eq!(detect("Gemfile"), Some("ruby"));
+    assert_eq!(detect("LICENSE"), None);
+}
+
+#[test]
+fn test_detect_header_language() {
+    let header = |head: &str| detect_language(Path::new("include/api.h"), head.as_bytes());
+    assert_eq!(
+        header("#include <stdio.h>\nint api_init(void);\n"),
+        Some("c")
+    );
+    assert_eq!(
+        header("#include <string>\nnamespace api {\nstd::string name();\n}\n"),
+        Some("cpp")
+    );
+}
+
+#[test]
+fn test_include_langs_reads_extensionless_files() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("bin")).unwrap();
+    std::fs::write(dir.path().join("bin/deploy"), "#!/usr/bin/env python3\n").unwrap();
+    std::fs::write(dir.path().join("bin/notes"), "nothing to see\n").unwrap();
+    std::fs::write(dir.path().join("Makefile"), "all:\n\tcargo build\n").unwrap();
+    let files: Vec<String> = ["bin/deploy", "bin/notes", "Makefile", "src/main.rs"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+    let langs = vec!["python".to_string(), "make".to_string()];
+    let filter = FileFilter::new(&langs, &[], &LanguageRegistry::new()).unwrap();
+    // going by the path alone, neither has an extension to match
+    assert!(filter.apply(files.clone()).is_empty());
+    assert_eq!(
+        filter.apply_in(dir.path(), files),
+        vec!["bin/deploy", "Makefile"]
+    );
+}
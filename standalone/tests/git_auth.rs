@@ -0,0 +1,412 @@
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use common::git;
+use standalone::clone::{clone_repo, redact_urls, CloneScope, Credentials, RetryPolicy, REDACTED};
+use standalone::events::EventSender;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const TOKEN: &str = "s3cret-token";
+/// `x-access-token:s3cret-token`, as Basic auth sends it.
+const AUTHORIZATION: &str = "Basic eC1hY2Nlc3MtdG9rZW46czNjcmV0LXRva2Vu";
+
+/// Answers like a git host serving private repos: `git http-backend` over
+/// `root`, behind Basic auth with [`TOKEN`].
+async fn backend(State(root): State<PathBuf>, request: Request) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .is_some_and(|v| v == AUTHORIZATION);
+    if !authorized {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(header::WWW_AUTHENTICATE, "Basic realm=\"git\"")
+            .body(Body::empty())
+            .unwrap();
+    }
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, usize::MAX).await.unwrap();
+    let header = |name: header::HeaderName| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let mut child = Command::new("git")
+        .arg("http-backend")
+        .env("GIT_PROJECT_ROOT", &root)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("REQUEST_METHOD", parts.method.as_str())
+        .env("PATH_INFO", parts.uri.path())
+        .env("QUERY_STRING", parts.uri.query().unwrap_or_default())
+        .env("CONTENT_TYPE", header(header::CONTENT_TYPE))
+        .env("HTTP_CONTENT_ENCODING", header(header::CONTENT_ENCODING))
+        .env("REMOTE_USER", "x-access-token")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), &body).unwrap();
+    let output = child.wait_with_output().unwrap().stdout;
+
+    // CGI headers, a blank line, then the body
+    let split = output
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|i| (i, i + 4))
+        .or_else(|| {
+            output
+                .windows(2)
+                .position(|w| w == b"\n\n")
+                .map(|i| (i, i + 2))
+        })
+        .unwrap();
+    let mut response = Response::builder();
+    for line in String::from_utf8_lossy(&output[..split.0]).lines() {
+        let (name, value) = line.split_once(':').unwrap();
+        if name.eq_ignore_ascii_case("status") {
+            let code: u16 = value.trim()[..3].parse().unwrap();
+            response = response.status(code);
+        } else {
+            response = response.header(name.trim(), value.trim());
+        }
+    }
+    response
+        .body(Body::from(output[split.1..].to_vec()))
+        .unwrap()
+}
+
+/// Serves a repo with one commit as `acme/app.git`, returning its URL.
+async fn private_remote(dir: &Path) -> String {
+    let work = dir.join("work");
+    let root = dir.join("served");
+    std::fs::create_dir_all(&work).unwrap();
+    std::fs::create_dir_all(root.join("acme")).unwrap();
+    git(&work, &["init", "-q", "-b", "main"]);
+    std::fs::write(work.join("main.rs"), "fn main() {}\n").unwrap();
+    git(&work, &["add", "."]);
+    git(&work, &["commit", "-q", "-m", "init"]);
+    git(
+        dir,
+        &["clone", "-q", "--bare", "work", "served/acme/app.git"],
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = axum::Router::new().fallback(backend).with_state(root);
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    format!("http://{}/acme/app.git", addr)
+}
+
+fn policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(1),
+    }
+}
+
+#[tokio::test]
+async fn test_token_clone_of_a_private_repo() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = private_remote(dir.path()).await;
+    let tx = EventSender::new(16, 16);
+
+    let dest = dir.path().join("checkout");
+    let credentials = Credentials::token(None, Some(TOKEN.to_string()));
+    let fetched = clone_repo(
+        &url,
+        &dest,
+        &credentials,
+        None,
+        &CloneScope::full(),
+        policy(),
+        &tx,
+    )
+    .await
+    .unwrap();
+    assert_eq!(fetched.git_ref, "main");
+    assert!(dest.join("main.rs").is_file());
+}
+
+#[tokio::test]
+async fn test_refused_credentials_fail_at_once_and_stay_secret() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = private_remote(dir.path()).await;
+    let tx = EventSender::new(16, 16);
+    let mut events = tx.subscribe();
+
+    for credentials in [
+        Credentials::default(),
+        Credentials::token(None, Some("wrong-token".to_string())),
+    ] {
+        let dest = dir.path().join("checkout");
+        let err = clone_repo(
+            &url,
+            &dest,
+            &credentials,
+            None,
+            &CloneScope::full(),
+            policy(),
+            &tx,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.auth, "{:?}", err);
+        assert_eq!(err.attempts, 1);
+        assert!(!err.message.contains("wrong-token"), "{}", err.message);
+    }
+    // an auth failure isn't retried, so nothing was reported as retrying
+    assert!(events.try_recv().is_err());
+}
+
+/// The `sshd` to serve [`ssh_remote`] with, when one is installed.
+fn sshd() -> Option<PathBuf> {
+    ["/usr/sbin/sshd", "/usr/bin/sshd", "/usr/local/sbin/sshd"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_file())
+}
+
+fn keygen(path: &Path, passphrase: &str) {
+    let status = Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", passphrase, "-f"])
+        .arg(path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// A server stopped when the test is done with it.
+struct Server(std::process::Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+/// Serves the repo of [`private_remote`] over SSH to the current user, who
+/// must log in with the key at `dir/id_ed25519`, returning its URL. The
+/// host is made known through `HOME`, where libgit2 looks for known_hosts.
+async fn ssh_remote(sshd: &Path, dir: &Path) -> (Server, String) {
+    private_remote(dir).await;
+    keygen(&dir.join("host_key"), "");
+    keygen(&dir.join("id_ed25519"), "open sesame");
+    std::fs::copy(dir.join("id_ed25519.pub"), dir.join("authorized_keys")).unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = format!(
+        "Port {port}\nListenAddress 127.0.0.1\nHostKey {dir}/host_key\n\
+         AuthorizedKeysFile {dir}/authorized_keys\nPidFile {dir}/sshd.pid\n\
+         StrictModes no\nPasswordAuthentication no\nKbdInteractiveAuthentication no\n",
+        port = port,
+        dir = dir.display()
+    );
+    std::fs::write(dir.join("sshd_config"), config).unwrap();
+    let server = Command::new(sshd)
+        .arg("-D")
+        .arg("-f")
+        .arg(dir.join("sshd_config"))
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let server = Server(server);
+    while tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .is_err()
+    {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let host_key = std::fs::read_to_string(dir.join("host_key.pub")).unwrap();
+    let home = dir.join("home");
+    std::fs::create_dir_all(home.join(".ssh")).unwrap();
+    std::fs::write(
+        home.join(".ssh/known_hosts"),
+        format!("[127.0.0.1]:{} {}", port, host_key),
+    )
+    .unwrap();
+    std::env::set_var("HOME", &home);
+    let user = String::from_utf8(Command::new("id").arg("-un").output().unwrap().stdout).unwrap();
+    let url = format!(
+        "ssh://{}@127.0.0.1:{}{}/served/acme/app.git",
+        user.trim(),
+        port,
+        dir.display()
+    );
+    (server, url)
+}
+
+#[tokio::test]
+async fn test_ssh_key_clone_and_refused_key() {
+    let Some(sshd) = sshd() else {
+        eprintln!("sshd is not installed, so SSH auth isn't tested");
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path().canonicalize().unwrap();
+    let (_server, url) = ssh_remote(&sshd, &dir).await;
+    let tx = EventSender::new(16, 16);
+
+    let credentials = Credentials {
+        ssh_key: Some(dir.join("id_ed25519")),
+        passphrase: Some("open sesame".to_string()),
+        ..Default::default()
+    };
+    let dest = dir.join("checkout");
+    let fetched = clone_repo(
+        &url,
+        &dest,
+        &credentials,
+        None,
+        &CloneScope::full(),
+        policy(),
+        &tx,
+    )
+    .await
+    .unwrap();
+    assert_eq!(fetched.git_ref, "main");
+    assert!(dest.join("main.rs").is_file());
+
+    // a key the server doesn't know is refused at once
+    keygen(&dir.join("stranger"), "");
+    let stranger = Credentials {
+        ssh_key: Some(dir.join("stranger")),
+        passphrase: Some("open sesame".to_string()),
+        ..Default::default()
+    };
+    let err = clone_repo(
+        &url,
+        &dir.join("refused"),
+        &stranger,
+        None,
+        &CloneScope::full(),
+        policy(),
+        &tx,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.auth, "{:?}", err);
+    assert_eq!(err.attempts, 1);
+    assert!(!err.message.contains("open sesame"), "{}", err.message);
+}
+
+#[test]
+fn test_credentials_are_redacted() {
+    let credentials = Credentials {
+        username: Some("bot".to_string()),
+        token: Some(TOKEN.to_string()),
+        ssh_key: Some(PathBuf::from("/etc/mesh/id_ed25519")),
+        passphrase: Some("open sesame".to_string()),
+    };
+    let debug = format!("{:?}", credentials);
+    assert!(
+        !debug.contains(TOKEN) && !debug.contains("open sesame"),
+        "{}",
+        debug
+    );
+    assert!(debug.contains("bot") && debug.contains(REDACTED));
+
+    let message = format!(
+        "fetch of https://bot:{}@example.com/acme/app.git failed: token {} rejected",
+        TOKEN, TOKEN
+    );
+    assert_eq!(
+        credentials.redact(&message),
+        "fetch of https://example.com/acme/app.git failed: token *** rejected"
+    );
+    assert_eq!(
+        redact_urls("see ssh://git:pw@example.com/a and git@example.com:a/b"),
+        "see ssh://example.com/a and git@example.com:a/b"
+    );
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::*;
+    use serde_json::{json, Value};
+    use standalone::clones::CloneDir;
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_fetch_repo_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = private_remote(dir.path()).await;
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+        state.clone_retry = policy();
+        state.clones = Arc::new(CloneDir::new(dir.path().join("clones"), 0, false));
+        state.git_credentials = Arc::new(BTreeMap::from([(
+            "ci".to_string(),
+            Credentials::token(None, Some(TOKEN.to_string())),
+        )]));
+        let app = standalone::router(Arc::new(state));
+        let fetch = |body: Value| {
+            let request = axum::http::Request::post("/fetch-repo")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (status, body) = fetch(json!({
+            "repo_name": "acme/app",
+            "repo_url": url,
+            "pat": "wrong-token",
+        }))
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+        assert!(body.contains("\"kind\":\"git_auth\""), "{}", body);
+        assert!(!body.contains("wrong-token"), "{}", body);
+
+        // credentials in the URL don't come back in the error either
+        let with_userinfo = url.replace("http://", "http://x-access-token:wrong-token@");
+        let (status, body) = fetch(json!({
+            "repo_name": "acme/app",
+            "repo_url": with_userinfo,
+        }))
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+        assert!(!body.contains("wrong-token"), "{}", body);
+
+        let (status, body) = fetch(json!({
+            "repo_name": "acme/app",
+            "repo_url": url,
+            "credential": "nope",
+        }))
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+        // the clone goes through; the repo has no graph stored yet
+        let (status, body) = fetch(json!({
+            "repo_name": "acme/app",
+            "repo_url": url,
+            "credential": "ci",
+        }))
+        .await;
+        assert_ne!(status, StatusCode::UNAUTHORIZED, "{}", body);
+        assert!(!body.contains("git_auth"), "{}", body);
+        assert!(dir.path().join("clones/acme/app/main.rs").is_file());
+    }
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use standalone::grammars::{current, mismatches, version_for, Mismatch};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[test]
+fn test_mismatches_name_each_changed_grammar() {
+    let stored: BTreeMap<String, String> = [
+        ("rust".to_string(), "0.21.0".to_string()),
+        ("python".to_string(), "0.23.0".to_string()),
+        ("cobol".to_string(), "0.1.0".to_string()),
+    ]
+    .into();
+    let now: BTreeMap<String, String> = [
+        ("rust".to_string(), "0.23.0".to_string()),
+        ("python".to_string(), "0.23.0".to_string()),
+        ("go".to_string(), "0.23.0".to_string()),
+    ]
+    .into();
+    let changed = mismatches(&stored, &now);
+    assert_eq!(
+        changed,
+        [
+            Mismatch {
+                grammar: "cobol".to_string(),
+                stored: Some("0.1.0".to_string()),
+                current: None,
+            },
+            Mismatch {
+                grammar: "go".to_string(),
+                stored: None,
+                current: Some("0.23.0".to_string()),
+            },
+            Mismatch {
+                grammar: "rust".to_string(),
+                stored: Some("0.21.0".to_string()),
+                current: Some("0.23.0".to_string()),
+            },
+        ]
+    );
+    assert_eq!(changed[2].to_string(), "rust 0.21.0 -> 0.23.0");
+    assert!(mismatches(current(), current()).is_empty());
+}
+
+#[test]
+fn test_files_take_the_version_of_their_grammar() {
+    assert_eq!(
+        version_for(Path::new("src/main.rs")),
+        current().get("rust").map(String::as_str)
+    );
+    assert!(version_for(Path::new("src/main.rs")).is_some());
+    assert_eq!(version_for(Path::new("README")), None);
+    assert_eq!(version_for(Path::new("notes.unknown")), None);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_changed_grammars_reparse_the_repo() {
+    use crate::common::git;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::json;
+    use standalone::grammars::{check, id, GRAMMAR_VERSION};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+    git(&root, &["init", "-q", "-b", "main"]);
+    git(&root, &["add", "."]);
+    git(&root, &["commit", "-q", "-m", "init"]);
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 1024);
+    state.allowed_roots = vec![root.clone()];
+    let mut rx = state.tx.subscribe();
+    let app = standalone::router(Arc::new(state));
+    let send = || {
+        let request = Request::post("/process")
+            .header("Content-Type", "application/json")
+            .body(Body::from(json!({ "repo_path": root }).to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, String::from_utf8_lossy(&body).into_owned())
+        }
+    };
+
+    let (status, body) = send().await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let repo_id = storage.repos().await.unwrap()[0].repo_id.clone();
+    assert!(check(storage.as_ref(), &repo_id).await.unwrap().is_empty());
+    let (status, body) = send().await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body.contains("already processed"), "{}", body);
+
+    // as if it had been parsed before the rust grammar was upgraded
+    storage
+        .set_repo_hash(&id(&repo_id), r#"{"rust":"0.0.0"}"#)
+        .await
+        .unwrap();
+    let changed = check(storage.as_ref(), &repo_id).await.unwrap();
+    assert!(changed.iter().any(|m| m.grammar == "rust"), "{:?}", changed);
+
+    while rx.try_recv().is_ok() {}
+    let (status, body) = send().await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(!body.contains("already processed"), "{}", body);
+    let mut warned = false;
+    while let Ok(event) = rx.try_recv() {
+        warned |= event.update.status == "grammar_changed";
+    }
+    assert!(warned);
+    assert!(check(storage.as_ref(), &repo_id).await.unwrap().is_empty());
+    let (nodes, _) = storage.load_graph(Some(&repo_id)).await.unwrap();
+    let function = nodes.iter().find(|n| n.kind == "Function").unwrap();
+    assert_eq!(
+        function.meta.get(GRAMMAR_VERSION).map(String::as_str),
+        version_for(Path::new("main.rs"))
+    );
+}
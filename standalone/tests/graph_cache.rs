@@ -0,0 +1,371 @@
+mod common;
+
+use standalone::storage::cache::{CachedGraph, GraphCache, Inserted};
+use standalone::storage::NodeRecord;
+
+fn function(name: &str, file: &str, start: usize) -> NodeRecord {
+    NodeRecord {
+        file: file.to_string(),
+        start,
+        end: start + 2,
+        body: format!("fn {}() {{}}", name),
+        ..common::function(name)
+    }
+}
+
+#[test]
+fn test_least_recently_used_graph_is_evicted() {
+    let graph = || CachedGraph::new(vec![function("a", "src/lib.rs", 0)], Vec::new());
+    let bytes = graph().bytes;
+    let cache = GraphCache::new(bytes * 2);
+
+    for repo in ["acme/one", "acme/two"] {
+        let since = cache.generation();
+        assert!(matches!(
+            cache.insert(repo, graph(), since),
+            Inserted::Cached { .. }
+        ));
+    }
+    // reading one makes it the most recently used
+    assert!(cache.get("acme/one").is_some());
+    let since = cache.generation();
+    assert_eq!(
+        cache.insert("acme/three", graph(), since),
+        Inserted::Cached {
+            bytes,
+            evicted: vec!["acme/two".to_string()]
+        }
+    );
+    assert_eq!(cache.repos(), ["acme/three", "acme/one"]);
+
+    let since = cache.generation();
+    let big = CachedGraph::new(
+        vec![
+            function("a", "src/lib.rs", 0),
+            function("b", "src/lib.rs", 3),
+            function("c", "src/lib.rs", 6),
+        ],
+        Vec::new(),
+    );
+    assert!(matches!(
+        cache.insert("acme/big", big, since),
+        Inserted::TooLarge { .. }
+    ));
+
+    // invalidated while it was loading, so not cached
+    let since = cache.generation();
+    cache.invalidate("acme/one");
+    assert_eq!(cache.insert("acme/one", graph(), since), Inserted::Stale);
+    assert_eq!(cache.repos(), ["acme/three"]);
+
+    cache.set_budget(0);
+    assert!(cache.repos().is_empty());
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::*;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Map, Value};
+    use standalone::lang::{Diagnostic, LanguageRegistry};
+    use standalone::query::{self, QueryTemplate};
+    use standalone::storage::cache::CachingStorage;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{EdgeRecord, RepoRecord, Storage, Transaction};
+    use standalone::AppState;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// A backend counting the reads that reach it.
+    struct Counting {
+        db: SqliteStorage,
+        reads: AtomicUsize,
+    }
+
+    impl Counting {
+        fn read(&self) -> &SqliteStorage {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            &self.db
+        }
+
+        fn reads(&self) -> usize {
+            self.reads.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl Storage for Counting {
+        fn backend(&self) -> &'static str {
+            "counting"
+        }
+
+        async fn ping(&self) -> Result<()> {
+            self.db.ping().await
+        }
+
+        async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+            self.db.upsert_node(node).await
+        }
+
+        async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+            self.db.upsert_edge(edge).await
+        }
+
+        async fn begin(&self) -> Result<Box<dyn Transaction>> {
+            self.db.begin().await
+        }
+
+        async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+            self.read().file_node_ids(repo_id, file).await
+        }
+
+        async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+            self.db.delete_nodes(repo_id, ids).await
+        }
+
+        async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+            self.db.delete_file(repo_id, file).await
+        }
+
+        async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+            self.db.clear(repo_id).await
+        }
+
+        async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+            self.read().graph_size(repo_id).await
+        }
+
+        async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+            self.read().graph_version(repo_id).await
+        }
+
+        async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+            self.read().repo_hash(repo_url).await
+        }
+
+        async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+            self.db.set_repo_hash(repo_url, hash).await
+        }
+
+        async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+            self.read().file_hashes(repo_id).await
+        }
+
+        async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+            self.db.set_file_hashes(repo_id, hashes).await
+        }
+
+        async fn replace_diagnostics(
+            &self,
+            repo_id: &str,
+            files: &[String],
+            diagnostics: &[Diagnostic],
+        ) -> Result<()> {
+            self.db
+                .replace_diagnostics(repo_id, files, diagnostics)
+                .await
+        }
+
+        async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+            self.read().find_repo(name).await
+        }
+
+        async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+            self.read().node(repo_id, id).await
+        }
+
+        async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+            self.db.record_ingest(repo).await
+        }
+
+        async fn repos(&self) -> Result<Vec<RepoRecord>> {
+            self.read().repos().await
+        }
+
+        async fn load_graph(
+            &self,
+            repo_id: Option<&str>,
+        ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+            self.read().load_graph(repo_id).await
+        }
+
+        async fn dangling_edges(
+            &self,
+            repo_id: &str,
+            after: Option<&EdgeRecord>,
+            limit: usize,
+        ) -> Result<Vec<EdgeRecord>> {
+            self.read().dangling_edges(repo_id, after, limit).await
+        }
+
+        async fn orphan_nodes(
+            &self,
+            repo_id: &str,
+            after: Option<&str>,
+            limit: usize,
+        ) -> Result<Vec<String>> {
+            self.read().orphan_nodes(repo_id, after, limit).await
+        }
+
+        async fn duplicate_nodes(
+            &self,
+            repo_id: &str,
+            after: Option<&str>,
+            limit: usize,
+        ) -> Result<Vec<String>> {
+            self.read().duplicate_nodes(repo_id, after, limit).await
+        }
+
+        async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+            self.db.delete_edges(edges).await
+        }
+
+        async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+            self.db.dedupe_nodes(repo_id, ids).await
+        }
+
+        async fn query(
+            &self,
+            template: &QueryTemplate,
+            params: &Map<String, Value>,
+        ) -> Result<Vec<Value>> {
+            self.read().query(template, params).await
+        }
+
+        async fn query_raw(
+            &self,
+            statement: &str,
+            params: &Map<String, Value>,
+        ) -> Result<Vec<Value>> {
+            self.read().query_raw(statement, params).await
+        }
+    }
+
+    fn calls(source: &str, target: &str) -> EdgeRecord {
+        EdgeRecord {
+            repo_id: "acme/app".to_string(),
+            kind: "CALLS".to_string(),
+            source: format!("function-{}", source),
+            target: format!("function-{}", target),
+        }
+    }
+
+    async fn seeded() -> Arc<Counting> {
+        let backend = Arc::new(Counting {
+            db: SqliteStorage::open_in_memory().unwrap(),
+            reads: AtomicUsize::new(0),
+        });
+        backend
+            .upsert_nodes(&[
+                function("main", "src/main.rs", 0),
+                function("helper", "src/lib.rs", 0),
+                function("parse", "src/lib.rs", 4),
+            ])
+            .await
+            .unwrap();
+        backend
+            .upsert_edges(&[calls("main", "helper"), calls("parse", "helper")])
+            .await
+            .unwrap();
+        backend
+    }
+
+    #[tokio::test]
+    async fn test_warmed_reads_skip_the_backend_until_a_write() {
+        let backend = seeded().await;
+        let cache = Arc::new(GraphCache::default());
+        let storage = CachingStorage::new(backend.clone(), cache.clone());
+        let template = query::find_template("callers-of-function").unwrap();
+        let mut params = Map::new();
+        params.insert("name".to_string(), json!("helper"));
+        let params = query::scoped_params(&params, Some("acme/app"));
+        let from_backend = storage.query(template, &params).await.unwrap();
+        assert_eq!(from_backend.len(), 2);
+
+        let since = cache.generation();
+        let (nodes, edges) = storage.load_graph(Some("acme/app")).await.unwrap();
+        cache.insert("acme/app", CachedGraph::new(nodes, edges), since);
+        let reads = backend.reads();
+
+        assert_eq!(
+            storage.query(template, &params).await.unwrap(),
+            from_backend
+        );
+        let (nodes, edges) = storage.load_graph(Some("acme/app")).await.unwrap();
+        assert_eq!((nodes.len(), edges.len()), (3, 2));
+        assert!(storage
+            .node("acme/app", "function-main")
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(backend.reads(), reads, "a warmed read reached the backend");
+        // other repos still go to the backend
+        storage.load_graph(Some("acme/other")).await.unwrap();
+        assert_eq!(backend.reads(), reads + 1);
+
+        storage
+            .upsert_node(&function("fresh", "src/lib.rs", 8))
+            .await
+            .unwrap();
+        assert!(cache.repos().is_empty());
+        let (nodes, _) = storage.load_graph(Some("acme/app")).await.unwrap();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(backend.reads(), reads + 2);
+    }
+
+    #[tokio::test]
+    async fn test_warm_endpoint() {
+        let backend = seeded().await;
+        let state = Arc::new(AppState::new(backend.clone(), LanguageRegistry::new(), 16));
+        let app = standalone::router(state.clone());
+        let post = |uri: &str, body: Value| {
+            let request = Request::post(uri)
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<Value>(&body).unwrap())
+            }
+        };
+
+        let (status, body) = post("/warm", json!({ "repo": "acme/app" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(
+            (body["nodes"].as_u64(), body["edges"].as_u64()),
+            (Some(3), Some(2))
+        );
+        let reads = backend.reads();
+
+        let (status, body) = post(
+            "/references",
+            json!({ "name": "helper", "repo": "acme/app" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (status, _) = post("/call-graph", json!({ "repo": "acme/app" })).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = post(
+            "/graph/query",
+            json!({ "query": "callers-of-function", "params": { "name": "helper" }, "repo": "acme/app" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["rows"].as_array().unwrap().len(), 2);
+        assert_eq!(backend.reads(), reads);
+
+        let (status, _) = post("/warm", json!({ "repo": "acme/none" })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        state.graph_cache.set_budget(1);
+        let (status, _) = post("/warm", json!({ "repo": "acme/app" })).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,151 @@
+#![cfg(all(feature = "graphql", feature = "sqlite"))]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use serde_json::{json, Value};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{NodeRecord, RepoRecord, Storage};
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn node(kind: &str, name: &str, file: &str, start: usize, body: &str) -> NodeRecord {
+    NodeRecord {
+        start,
+        end: start + body.lines().count() - 1,
+        body: body.to_string(),
+        ..common::node(kind, name, file)
+    }
+}
+
+async fn app() -> axum::Router {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage
+        .upsert_nodes(&[
+            node("File", "main.rs", "acme/app/src/main.rs", 0, "mod cli;"),
+            node(
+                "File",
+                "cli.rs",
+                "acme/app/src/cli.rs",
+                0,
+                "use crate::load;",
+            ),
+            node(
+                "Function",
+                "main",
+                "acme/app/src/main.rs",
+                2,
+                "fn main() {\n    let cfg = load();\n}",
+            ),
+            node(
+                "Function",
+                "load",
+                "acme/app/src/main.rs",
+                6,
+                "fn load() -> Config {}",
+            ),
+            node(
+                "Function",
+                "run",
+                "acme/app/src/cli.rs",
+                4,
+                "fn run() {\n    load();\n    load();\n}",
+            ),
+        ])
+        .await
+        .unwrap();
+    storage
+        .record_ingest(&RepoRecord {
+            repo_id: "acme/app".to_string(),
+            url: "https://github.com/acme/app".to_string(),
+            git_ref: Some("main".to_string()),
+            commit: None,
+            ingested_at: 0,
+        })
+        .await
+        .unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    standalone::router(Arc::new(state))
+}
+
+async fn query(app: axum::Router, query: &str) -> Value {
+    let request = Request::post("/graphql")
+        .header("Content-Type", "application/json")
+        .body(Body::from(json!({ "query": query }).to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert!(body.get("errors").is_none(), "{}", body);
+    body["data"].clone()
+}
+
+#[tokio::test]
+async fn test_symbol_with_its_callers() {
+    let data = query(
+        app().await,
+        r#"{
+            repo(id: "acme/app") {
+                ref
+                symbols(name: "load") {
+                    name
+                    startLine
+                    callers {
+                        name
+                        file { path symbols { name } }
+                    }
+                    references { caller { name } lines }
+                }
+            }
+        }"#,
+    )
+    .await;
+    let repo = &data["repo"];
+    assert_eq!(repo["ref"], "main");
+    let load = &repo["symbols"][0];
+    assert_eq!(load["startLine"], 7);
+    assert_eq!(
+        load["callers"],
+        json!([
+            {
+                "name": "main",
+                "file": {
+                    "path": "acme/app/src/main.rs",
+                    "symbols": [{ "name": "main" }, { "name": "load" }]
+                }
+            },
+            {
+                "name": "run",
+                "file": { "path": "acme/app/src/cli.rs", "symbols": [{ "name": "run" }] }
+            }
+        ])
+    );
+    let run = load["references"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["caller"]["name"] == "run")
+        .unwrap();
+    assert_eq!(run["lines"], json!([6, 7]));
+}
+
+#[tokio::test]
+async fn test_callees_of_a_symbol() {
+    let data = query(
+        app().await,
+        r#"{
+            symbol(repo: "acme/app", id: "function-run-acme/app/src/cli.rs") {
+                callees { name file { path } }
+            }
+        }"#,
+    )
+    .await;
+    assert_eq!(
+        data["symbol"]["callees"],
+        json!([{ "name": "load", "file": { "path": "acme/app/src/main.rs" } }])
+    );
+}
@@ -0,0 +1,135 @@
+use standalone::captures::CustomQueries;
+use standalone::grep::{self, Matcher, COMMENT_KIND, STRING_KIND};
+use standalone::lang::LanguageRegistry;
+
+const SOURCE: &str = r#"/// Parses the config.
+pub fn parse() -> &'static str {
+    // TODO: read it from disk
+    let key = "magic-4242";
+    key
+}
+"#;
+
+fn registry() -> LanguageRegistry {
+    let mut queries = CustomQueries::default();
+    queries.index_text().unwrap();
+    let mut languages = LanguageRegistry::new();
+    languages.set_custom_queries(queries);
+    languages
+}
+
+#[test]
+fn test_comments_and_strings_are_indexed() {
+    let extraction = registry().extract("src/config.rs", SOURCE).unwrap();
+    let text: Vec<(&str, &str, usize)> = extraction
+        .custom
+        .iter()
+        .map(|n| (n.kind.as_str(), n.name.as_str(), n.start))
+        .collect();
+    assert_eq!(
+        text,
+        vec![
+            (COMMENT_KIND, "/// Parses the config.", 0),
+            (COMMENT_KIND, "// TODO: read it from disk", 2),
+            (STRING_KIND, "\"magic-4242\"", 3),
+        ]
+    );
+    // only with the index turned on
+    let extraction = LanguageRegistry::new()
+        .extract("src/config.rs", SOURCE)
+        .unwrap();
+    assert!(extraction.custom.is_empty());
+}
+
+#[test]
+fn test_oversized_regex_is_refused() {
+    assert!(Matcher::new("(a{1000}){1000}", true).is_err());
+    assert!(Matcher::new("(unclosed", true).is_err());
+    assert!(Matcher::new(&"a".repeat(grep::MAX_PATTERN_LEN + 1), false).is_err());
+    assert!(Matcher::new("TODO", false)
+        .unwrap()
+        .is_match("// TODO: later"));
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn send(app: &axum::Router, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_grep_finds_a_todo_and_a_string_by_regex() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/config.rs"), SOURCE).unwrap();
+
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, registry(), 1024);
+        state.allowed_roots = vec![root.clone()];
+        let app = standalone::router(Arc::new(state));
+        let (status, _) = send(
+            &app,
+            "/ingest-path",
+            json!({"path": root, "repo_id": "acme/app"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(
+            &app,
+            "/grep",
+            json!({"pattern": r"TODO|magic-\d+", "regex": true, "repo": "acme/app"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let hits = body["hits"].as_array().unwrap();
+        let found: Vec<(&str, u64, &str)> = hits
+            .iter()
+            .map(|h| {
+                (
+                    h["kind"].as_str().unwrap(),
+                    h["line"].as_u64().unwrap(),
+                    h["text"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (COMMENT_KIND, 3, "// TODO: read it from disk"),
+                (STRING_KIND, 4, "\"magic-4242\""),
+            ]
+        );
+        assert!(hits[0]["file"].as_str().unwrap().ends_with("src/config.rs"));
+        assert_eq!(body["truncated"], false);
+
+        // the symbols themselves aren't searched
+        let (_, body) = send(&app, "/grep", json!({"pattern": "fn parse"})).await;
+        assert_eq!(body["hits"], json!([]));
+
+        let (_, body) = send(&app, "/grep", json!({"pattern": "TODO", "limit": 0})).await;
+        assert_eq!(body["truncated"], true);
+
+        let (status, body) = send(&app, "/grep", json!({"pattern": "(", "regex": true})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "validation");
+    }
+}
@@ -0,0 +1,46 @@
+#![cfg(any(feature = "neo4j", feature = "sqlite"))]
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use standalone::health::HealthResponse;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::unconfigured::Unconfigured;
+use standalone::storage::Storage;
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+async fn get(storage: Arc<dyn Storage>, path: &str) -> (StatusCode, HealthResponse) {
+    let state = Arc::new(AppState::new(storage, LanguageRegistry::new(), 16));
+    let request = Request::get(path).body(Body::empty()).unwrap();
+    let response = standalone::router(state).oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_readyz_is_unavailable_without_backend() {
+    let storage = Arc::new(Unconfigured::new("MESH_BACKEND 'neo4j' is not available"));
+    let (status, body) = get(storage.clone(), "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body.backend, "unconfigured");
+    assert!(body.reason.unwrap().contains("not available"));
+
+    // liveness does not depend on the backend
+    let (status, body) = get(storage, "/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.status, "ok");
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_readyz_with_sqlite() {
+    use standalone::storage::sqlite::SqliteStorage;
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let (status, body) = get(storage, "/readyz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.status, "ready");
+    assert!(body.reason.is_none());
+}
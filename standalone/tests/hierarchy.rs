@@ -0,0 +1,238 @@
+mod common;
+
+use standalone::hierarchy::{hierarchy, inheritance_edges, Related};
+use standalone::storage::{EdgeRecord, NodeRecord};
+
+fn node(kind: &str, name: &str, file: &str, body: &str) -> NodeRecord {
+    NodeRecord {
+        id: format!("{}-{}-{}", kind.to_lowercase(), name.to_lowercase(), file),
+        start: 1,
+        end: 2,
+        body: body.to_string(),
+        ..common::node(kind, name, file)
+    }
+}
+
+fn edges_of(nodes: &[NodeRecord]) -> Vec<String> {
+    let name = |id: &str| nodes.iter().find(|n| n.id == id).unwrap().name.clone();
+    inheritance_edges(nodes, "acme/app")
+        .iter()
+        .map(|e| format!("{} {} {}", name(&e.source), e.kind, name(&e.target)))
+        .collect()
+}
+
+fn names(related: &[Related]) -> Vec<(&str, &str, usize)> {
+    related
+        .iter()
+        .map(|r| (r.name.as_str(), r.relation.as_str(), r.depth))
+        .collect()
+}
+
+fn animals() -> Vec<NodeRecord> {
+    vec![
+        node(
+            "Class",
+            "Animal",
+            "zoo/base.py",
+            "class Animal:\n    pass\n",
+        ),
+        node(
+            "Import",
+            "import",
+            "zoo/dogs.py",
+            "from zoo.base import Animal",
+        ),
+        node(
+            "Class",
+            "Dog",
+            "zoo/dogs.py",
+            "class Dog(Animal, metaclass=Registry):\n    def bark(self): ...\n",
+        ),
+        node(
+            "Class",
+            "Puppy",
+            "zoo/puppy.py",
+            "class Puppy(Dog):\n    pass\n",
+        ),
+    ]
+}
+
+#[test]
+fn test_single_inheritance_chain_across_files() {
+    let nodes = animals();
+    assert_eq!(
+        edges_of(&nodes),
+        vec!["Dog EXTENDS Animal", "Puppy EXTENDS Dog"]
+    );
+
+    let edges: Vec<EdgeRecord> = inheritance_edges(&nodes, "acme/app");
+    let puppy = &hierarchy(&nodes, &edges, "Puppy")[0];
+    assert_eq!(
+        names(&puppy.ancestors),
+        vec![("Dog", "EXTENDS", 1), ("Animal", "EXTENDS", 2)]
+    );
+    // each step names the type it was reached through
+    assert_eq!(puppy.ancestors[1].via, puppy.ancestors[0].id);
+    assert!(puppy.descendants.is_empty());
+
+    let animal = &hierarchy(&nodes, &edges, "Animal")[0];
+    assert!(animal.ancestors.is_empty());
+    assert_eq!(
+        names(&animal.descendants),
+        vec![("Dog", "EXTENDS", 1), ("Puppy", "EXTENDS", 2)]
+    );
+}
+
+#[test]
+fn test_interface_implemented_by_two_classes() {
+    let nodes = vec![
+        node(
+            "Interface",
+            "Shape",
+            "src/Shape.java",
+            "public interface Shape {\n    double area();\n}\n",
+        ),
+        node(
+            "Class",
+            "Polygon",
+            "src/Polygon.java",
+            "public abstract class Polygon {\n}\n",
+        ),
+        node(
+            "Class",
+            "Circle",
+            "src/Circle.java",
+            "public class Circle implements Shape {\n    double r;\n}\n",
+        ),
+        node(
+            "Class",
+            "Square",
+            "src/Square.java",
+            "public class Square extends Polygon implements Shape, Comparable<Square> {\n}\n",
+        ),
+    ];
+    let edges = inheritance_edges(&nodes, "acme/app");
+    let shape = &hierarchy(&nodes, &edges, "Shape")[0];
+    assert_eq!(
+        names(&shape.descendants),
+        vec![("Circle", "IMPLEMENTS", 1), ("Square", "IMPLEMENTS", 1)]
+    );
+
+    let square = &hierarchy(&nodes, &edges, "Square")[0];
+    assert_eq!(
+        names(&square.ancestors),
+        vec![("Polygon", "EXTENDS", 1), ("Shape", "IMPLEMENTS", 1)]
+    );
+    assert_eq!(square.unresolved, vec!["Comparable"]);
+}
+
+#[test]
+fn test_colon_lists_and_rust_impls() {
+    let nodes = vec![
+        node(
+            "Class",
+            "View",
+            "ui/View.kt",
+            "open class View(val label: String)",
+        ),
+        node(
+            "Trait",
+            "Clickable",
+            "ui/Clickable.kt",
+            "interface Clickable",
+        ),
+        node(
+            "Class",
+            "Button",
+            "ui/Button.kt",
+            "class Button(label: String) : View(label), Clickable {\n}\n",
+        ),
+        node("Class", "Base", "ui/widget.hpp", "class Base {\n};\n"),
+        node(
+            "Class",
+            "Widget",
+            "ui/widget.hpp",
+            "class Widget : public Base, private virtual Observer {\n};\n",
+        ),
+        node("Trait", "Area", "src/geo.rs", "pub trait Area {}"),
+        node("DataModel", "Point", "src/geo.rs", "pub struct Point;"),
+        node(
+            "File",
+            "geo.rs",
+            "src/geo.rs",
+            concat!(
+                "pub trait Area {}\npub struct Point;\n",
+                "impl Area for Point {}\nimpl fmt::Display for Point {}\n",
+            ),
+        ),
+    ];
+    assert_eq!(
+        edges_of(&nodes),
+        vec![
+            "Button EXTENDS View",
+            "Button IMPLEMENTS Clickable",
+            "Widget EXTENDS Base",
+            "Point IMPLEMENTS Area",
+        ]
+    );
+    let widget = &hierarchy(&nodes, &[], "Widget")[0];
+    assert_eq!(widget.unresolved, vec!["Observer"]);
+}
+
+#[test]
+fn test_ambiguous_bases_are_left_unresolved() {
+    let nodes = vec![
+        node("Class", "Base", "a/base.py", "class Base:\n    pass\n"),
+        node("Class", "Base", "b/base.py", "class Base:\n    pass\n"),
+        node(
+            "Class",
+            "Child",
+            "c/child.py",
+            "class Child(Base):\n    pass\n",
+        ),
+    ];
+    assert!(inheritance_edges(&nodes, "acme/app").is_empty());
+    let child = &hierarchy(&nodes, &[], "Child")[0];
+    assert_eq!(child.unresolved, vec!["Base"]);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_hierarchy_endpoint() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let nodes = animals();
+    storage.upsert_nodes(&nodes).await.unwrap();
+    storage
+        .upsert_edges(&inheritance_edges(&nodes, "acme/app"))
+        .await
+        .unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let request = |name: &str| {
+        Request::post("/hierarchy")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "name": name, "repo": "acme/app" }).to_string(),
+            ))
+            .unwrap()
+    };
+    let response = app.clone().oneshot(request("Dog")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["types"][0]["ancestors"][0]["name"], "Animal");
+    assert_eq!(body["types"][0]["descendants"][0]["name"], "Puppy");
+
+    let response = app.oneshot(request("Cat")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,123 @@
+#![cfg(feature = "sqlite")]
+
+use reqwest::Version;
+use serde_json::Value;
+use standalone::events::KeepAliveConfig;
+use standalone::lang::LanguageRegistry;
+use standalone::server::{self, HttpConfig};
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// More than the connections a browser opens per origin over HTTP/1.1.
+const STREAMS: usize = 10;
+
+async fn start(config: HttpConfig) -> (String, oneshot::Sender<()>) {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 64);
+    state.keep_alive = KeepAliveConfig {
+        interval: Duration::from_millis(50),
+        text: "ping".to_string(),
+    };
+    let app = standalone::router(Arc::new(state));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (stop, stopped) = oneshot::channel();
+    tokio::spawn(server::serve(listener, app, config, async {
+        let _ = stopped.await;
+    }));
+    (url, stop)
+}
+
+async fn subscribers(client: &reqwest::Client, url: &str) -> u64 {
+    let stats: Value = client
+        .get(format!("{}/events/stats", url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    stats["subscribers"].as_u64().unwrap()
+}
+
+#[tokio::test]
+async fn test_http2_client_holds_many_event_streams_at_once() {
+    let (url, _stop) = start(HttpConfig::default()).await;
+    let client = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+
+    let mut streams = Vec::new();
+    for _ in 0..STREAMS {
+        let response = client.get(format!("{}/events", url)).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.version(), Version::HTTP_2);
+        streams.push(response);
+    }
+    // every stream is open and being written to, none waiting on another
+    for stream in &mut streams {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), stream.chunk())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&chunk).contains("ping"));
+    }
+    assert_eq!(subscribers(&client, &url).await, STREAMS as u64);
+
+    drop(streams);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(subscribers(&client, &url).await, 0);
+}
+
+#[tokio::test]
+async fn test_http1_clients_are_still_served() {
+    let (url, _stop) = start(HttpConfig::default()).await;
+    let client = reqwest::Client::builder().http1_only().build().unwrap();
+    let response = client.get(format!("{}/healthz", url)).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.version(), Version::HTTP_11);
+
+    // with HTTP/2 turned off, a client that insists on it is turned away
+    let (url, _stop) = start(HttpConfig {
+        http2: false,
+        ..HttpConfig::default()
+    })
+    .await;
+    let response = client.get(format!("{}/healthz", url)).send().await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+    let http2 = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    assert!(http2.get(format!("{}/healthz", url)).send().await.is_err());
+}
+
+#[tokio::test]
+async fn test_serving_stops_once_signalled() {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let state = AppState::new(storage, LanguageRegistry::new(), 64);
+    let app = standalone::router(Arc::new(state));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(server::serve(listener, app, HttpConfig::default(), async {
+        let _ = stopped.await;
+    }));
+
+    let response = reqwest::get(format!("{}/healthz", url)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    drop(response);
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(reqwest::get(format!("{}/healthz", url)).await.is_err());
+}
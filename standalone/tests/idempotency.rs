@@ -0,0 +1,78 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use common::git;
+use serde_json::{json, Value};
+use standalone::idempotency::{HEADER, REPLAYED};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::fs;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+async fn post(app: &Router, key: &str, body: &Value) -> (StatusCode, bool, Value) {
+    let request = Request::post("/process")
+        .header("Content-Type", "application/json")
+        .header(HEADER, key)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let replayed = response.headers().contains_key(REPLAYED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (
+        status,
+        replayed,
+        serde_json::from_slice(&body).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn test_repeated_key_launches_one_ingest() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    git(&root, &["init", "-q", "-b", "main"]);
+    fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+    git(&root, &["add", "."]);
+    git(&root, &["commit", "-q", "-m", "main"]);
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+    state.allowed_roots = vec![root.clone()];
+    let state = Arc::new(state);
+    let app = standalone::router(state.clone());
+    let ingests = || state.metrics.repos_ingested.get() + state.metrics.ingest_failures.get();
+    let body = json!({ "repo_path": root });
+
+    // a retry sent while the first is still running waits for it
+    let (first, second) = tokio::join!(post(&app, "retry-1", &body), post(&app, "retry-1", &body));
+    assert_eq!(first.0, StatusCode::OK);
+    assert_eq!(second.0, StatusCode::OK);
+    assert_eq!(first.2, second.2);
+    assert!(first.1 != second.1, "exactly one response is a replay");
+    assert_eq!(ingests(), 1);
+
+    // and one sent after it gets the same response
+    let (status, replayed, response) = post(&app, "retry-1", &body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(replayed);
+    assert_eq!(response, first.2);
+    assert_eq!(ingests(), 1);
+
+    // another key is another request
+    let (status, replayed, _) = post(&app, "retry-2", &body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!replayed);
+    assert_eq!(ingests(), 2);
+
+    // a key can't be reused for something else
+    let (status, _, response) = post(&app, "retry-1", &json!({ "repo_path": "/tmp" })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(response["kind"], "conflict");
+    assert_eq!(ingests(), 2);
+}
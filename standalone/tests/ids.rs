@@ -0,0 +1,88 @@
+mod common;
+
+use standalone::ids::{id_for, symbol_path, IdScheme};
+use standalone::storage::{kind_key, EdgeRecord, NodeRecord};
+
+fn node(kind: &str, name: &str, file: &str, start: usize) -> NodeRecord {
+    NodeRecord {
+        id: kind_key(kind, name, file, start, None),
+        start,
+        end: start + 2,
+        ..common::node(kind, name, file)
+    }
+}
+
+fn contains(file: &NodeRecord, node: &NodeRecord) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: "CONTAINS".to_string(),
+        source: file.id.clone(),
+        target: node.id.clone(),
+    }
+}
+
+/// The ids of `src/lib.rs`, checked out at `root`, and its `helper` function
+/// starting on line `start`.
+fn ingest(scheme: IdScheme, root: &str, start: usize) -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let path = format!("{}/src/lib.rs", root);
+    let file = node("File", &path, &path, 0);
+    let helper = node("Function", "helper", &path, start);
+    let mut edges = vec![contains(&file, &helper)];
+    let mut nodes = vec![file, helper];
+    scheme.apply(root, &mut nodes, &mut edges);
+    (nodes, edges)
+}
+
+#[test]
+fn test_moving_a_function_keeps_its_stable_id() {
+    let (before, before_edges) = ingest(IdScheme::Stable, "/tmp/clones/acme/app", 3);
+    let (after, after_edges) = ingest(IdScheme::Stable, "/home/me/acme/app", 40);
+    assert_eq!(before[1].id, after[1].id);
+    assert_eq!(before[0].id, after[0].id);
+    assert!(before[1].id.starts_with("function-"), "{}", before[1].id);
+    assert_eq!(
+        (&after_edges[0].source, &after_edges[0].target),
+        (&after[0].id, &after[1].id)
+    );
+    assert_eq!(before_edges, after_edges);
+
+    // position ids are left as they were, and they change when code moves
+    let (before, _) = ingest(IdScheme::Position, "/tmp/clones/acme/app", 3);
+    let (after, _) = ingest(IdScheme::Position, "/tmp/clones/acme/app", 40);
+    assert_eq!(before[1].id, "function-helper-tmpclonesacmeappsrclibrs-3");
+    assert_ne!(before[1].id, after[1].id);
+}
+
+#[test]
+fn test_stable_ids_tell_symbols_apart() {
+    let helper = node("Function", "helper", "src/lib.rs", 3);
+    let moved = node("Function", "helper", "src/lib.rs", 30);
+    assert_eq!(id_for(&helper), id_for(&moved));
+    for other in [
+        node("Function", "helper", "src/main.rs", 3),
+        node("Function", "helpers", "src/lib.rs", 3),
+        node("Var", "helper", "src/lib.rs", 3),
+        NodeRecord {
+            repo_id: "acme/app@v2".to_string(),
+            ..helper.clone()
+        },
+    ] {
+        assert_ne!(id_for(&helper), id_for(&other), "{:?}", other);
+    }
+
+    let mut method = helper.clone();
+    method
+        .meta
+        .insert("operand".to_string(), "Parser".to_string());
+    assert_eq!(symbol_path(&method), "Parser.helper");
+    assert_ne!(id_for(&method), id_for(&helper));
+    let mut endpoint = node("Endpoint", "/users", "src/routes.rs", 8);
+    endpoint.meta.insert("verb".to_string(), "get".to_string());
+    assert_eq!(symbol_path(&endpoint), "GET /users");
+
+    // overloads share a path; the later one is numbered
+    let mut nodes = vec![moved, helper];
+    IdScheme::Stable.apply("/repo", &mut nodes, &mut []);
+    assert_eq!(nodes[1].id, id_for(&nodes[1]));
+    assert_eq!(nodes[0].id, format!("{}-2", nodes[1].id));
+}
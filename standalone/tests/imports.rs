@@ -0,0 +1,172 @@
+mod common;
+
+use standalone::imports::{dependencies, import_edges, resolve_imports, ResolvedImport, Target};
+use standalone::storage::NodeRecord;
+
+fn record(kind: &str, file: &str, body: &str) -> NodeRecord {
+    NodeRecord {
+        id: format!("{}-{}", kind, file).to_lowercase(),
+        body: body.to_string(),
+        ..common::node(kind, file.rsplit('/').next().unwrap(), file)
+    }
+}
+
+/// `File` nodes for every path plus one `Import` node per (file, body).
+fn repo(files: &[&str], imports: &[(&str, &str)]) -> Vec<NodeRecord> {
+    let mut nodes: Vec<NodeRecord> = files.iter().map(|f| record("File", f, "")).collect();
+    nodes.extend(imports.iter().map(|(f, body)| record("Import", f, body)));
+    nodes
+}
+
+fn target(resolved: &[ResolvedImport], file: &str, module: &str) -> Target {
+    resolved
+        .iter()
+        .find(|r| r.file == file && r.module == module)
+        .unwrap_or_else(|| panic!("no import of {} in {}", module, file))
+        .target
+        .clone()
+}
+
+#[test]
+fn test_relative_imports() {
+    let nodes = repo(
+        &[
+            "web/src/app.ts",
+            "web/src/util.ts",
+            "web/src/components/index.tsx",
+            "pkg/service.py",
+            "pkg/helpers.py",
+        ],
+        &[
+            (
+                "web/src/app.ts",
+                "import { fmt } from './util';\nimport * as ui from \"./components\";",
+            ),
+            ("pkg/service.py", "from .helpers import slugify"),
+        ],
+    );
+    let resolved = resolve_imports(&nodes);
+    assert_eq!(
+        target(&resolved, "web/src/app.ts", "./util"),
+        Target::File("web/src/util.ts".to_string())
+    );
+    assert_eq!(
+        target(&resolved, "web/src/app.ts", "./components"),
+        Target::File("web/src/components/index.tsx".to_string())
+    );
+    assert_eq!(
+        target(&resolved, "pkg/service.py", ".helpers"),
+        Target::File("pkg/helpers.py".to_string())
+    );
+}
+
+#[test]
+fn test_package_root_imports_and_reexports() {
+    let nodes = repo(
+        &[
+            "src/lib.rs",
+            "src/storage/mod.rs",
+            "src/storage/sqlite.rs",
+            "src/query.rs",
+        ],
+        &[
+            ("src/lib.rs", "pub mod query;\npub mod storage;"),
+            ("src/storage/mod.rs", "pub use self::sqlite::SqliteStorage;"),
+            (
+                "src/storage/sqlite.rs",
+                "use crate::query::{QueryTemplate, validate};",
+            ),
+        ],
+    );
+    let resolved = resolve_imports(&nodes);
+    assert_eq!(
+        target(&resolved, "src/lib.rs", "self::storage"),
+        Target::File("src/storage/mod.rs".to_string())
+    );
+    assert_eq!(
+        target(
+            &resolved,
+            "src/storage/mod.rs",
+            "self::sqlite::SqliteStorage"
+        ),
+        Target::File("src/storage/sqlite.rs".to_string())
+    );
+    assert_eq!(
+        target(&resolved, "src/storage/sqlite.rs", "crate::query"),
+        Target::File("src/query.rs".to_string())
+    );
+}
+
+#[test]
+fn test_third_party_import_is_external() {
+    let nodes = repo(
+        &["src/main.rs", "app/views.py", "web/app.js"],
+        &[
+            ("src/main.rs", "use serde::Serialize;"),
+            (
+                "app/views.py",
+                "import requests\nfrom django.http import HttpResponse",
+            ),
+            ("web/app.js", "const _ = require('lodash');"),
+        ],
+    );
+    let resolved = resolve_imports(&nodes);
+    assert_eq!(resolved.len(), 4);
+    assert!(resolved
+        .iter()
+        .all(|r| matches!(r.target, Target::External(_))));
+    assert_eq!(
+        target(&resolved, "app/views.py", "django.http"),
+        Target::External("django.http".to_string())
+    );
+    assert!(import_edges(&nodes, "acme/app").is_empty());
+}
+
+#[test]
+fn test_import_edges_link_file_nodes() {
+    let nodes = repo(
+        &["web/app.ts", "web/util.ts"],
+        &[(
+            "web/app.ts",
+            "import { a } from './util';\nimport b from 'react';",
+        )],
+    );
+    let edges = import_edges(&nodes, "acme/app");
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].kind, "IMPORTS");
+    assert_eq!(edges[0].source, nodes[0].id);
+    assert_eq!(edges[0].target, nodes[1].id);
+}
+
+#[test]
+fn test_dependencies_are_counted_once_per_file() {
+    let nodes = repo(
+        &["app/views.py", "app/api.py", "app/models.py", "web/app.ts"],
+        &[
+            (
+                "app/views.py",
+                "import requests\nfrom requests.adapters import HTTPAdapter\nfrom . import models",
+            ),
+            (
+                "app/api.py",
+                "import requests.exceptions\nimport app.models",
+            ),
+            ("app/models.py", "from .missing import nothing"),
+            (
+                "web/app.ts",
+                "import { Component } from '@angular/core';\nimport x from 'lodash/fp';",
+            ),
+        ],
+    );
+    let found = dependencies(&resolve_imports(&nodes));
+    let names: Vec<(&str, usize)> = found.iter().map(|d| (d.name.as_str(), d.files)).collect();
+    // the relative and the repo's own imports aren't packages
+    assert_eq!(
+        names,
+        [("requests", 2), ("@angular/core", 1), ("lodash", 1)]
+    );
+    assert_eq!(
+        found[0].modules,
+        ["requests", "requests.adapters", "requests.exceptions"]
+    );
+}
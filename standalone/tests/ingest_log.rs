@@ -0,0 +1,79 @@
+#![cfg(feature = "sqlite")]
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use serde_json::{json, Value};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn app(storage: Arc<SqliteStorage>, root: &Path) -> axum::Router {
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+    state.allowed_roots = vec![root.to_path_buf()];
+    standalone::router(Arc::new(state))
+}
+
+async fn log(app: &axum::Router, accept: &str) -> (StatusCode, String) {
+    let request = Request::get("/repos/log?repo=acme/app")
+        .header("Accept", accept)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_completed_ingest_leaves_its_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(root.join("lib.rs"), "pub fn greet() {}\n").unwrap();
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let app = app(storage.clone(), &root);
+
+    let (status, _) = log(&app, "application/json").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let request = Request::post("/ingest-path")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({"path": root, "repo_id": "acme/app"}).to_string(),
+        ))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (status, body) = log(&app, "application/json").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["status"], "idle");
+    let events = body["events"].as_array().unwrap();
+    let statuses: Vec<&str> = events
+        .iter()
+        .map(|e| e["status"].as_str().unwrap())
+        .collect();
+    assert!(statuses.contains(&"stored"), "{:?}", statuses);
+    let complete = events.iter().find(|e| e["status"] == "complete").unwrap();
+    assert_eq!(complete["repo_id"], "acme/app");
+    assert!(complete["summary"]["files_parsed"].as_u64().unwrap() > 0);
+    let ids: Vec<u64> = events.iter().map(|e| e["id"].as_u64().unwrap()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", ids);
+
+    // a server started since has only what was stored
+    let restarted = self::app(storage, &root);
+    let (status, lines) = log(&restarted, "application/x-ndjson").await;
+    assert_eq!(status, StatusCode::OK);
+    let stored: Vec<Value> = lines
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    // stored as the ingest completed
+    assert_eq!(stored.last(), Some(complete));
+    assert_eq!(stored[..], events[..stored.len()]);
+}
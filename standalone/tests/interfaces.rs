@@ -0,0 +1,216 @@
+mod common;
+
+use standalone::annotations::annotate;
+use standalone::interfaces::{
+    link, parse, route_key, ACCEPTS, MESSAGE_KIND, METHOD, OPERATION_ID, OPERATION_KIND, PATH,
+    RETURNS, SERVES,
+};
+use standalone::storage::{EdgeRecord, NodeRecord};
+
+const SPEC: &str = r##"openapi: 3.0.3
+info:
+  title: Users
+  version: "1.0"
+paths:
+  /users:
+    get:
+      operationId: listUsers
+      responses:
+        "200":
+          content:
+            application/json:
+              schema:
+                type: array
+                items:
+                  $ref: "#/components/schemas/User"
+    post:
+      operationId: createUser
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/NewUser"
+      responses:
+        "201":
+          content:
+            application/json:
+              schema:
+                $ref: "#/components/schemas/User"
+  /users/{user_id}:
+    delete:
+      operationId: deleteUser
+      responses:
+        "204":
+          description: deleted
+components:
+  schemas:
+    User:
+      type: object
+    NewUser:
+      type: object
+"##;
+
+fn function(name: &str, file: &str, start: usize, end: usize, body: &str) -> NodeRecord {
+    NodeRecord {
+        file: file.to_string(),
+        start,
+        end,
+        body: body.to_string(),
+        ..common::function_in("acme/api", name)
+    }
+}
+
+#[test]
+fn test_openapi_operations_are_linked_to_their_route_handlers() {
+    let (nodes, edges) = parse("api/openapi.yaml", SPEC, "acme/api")
+        .unwrap()
+        .unwrap();
+    let operations: Vec<&NodeRecord> = nodes.iter().filter(|n| n.kind == OPERATION_KIND).collect();
+    let names: Vec<&str> = operations.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["GET /users", "POST /users", "DELETE /users/{user_id}"]
+    );
+    let create = operations[1];
+    assert_eq!(create.meta[METHOD], "POST");
+    assert_eq!(create.meta[PATH], "/users");
+    assert_eq!(create.meta[OPERATION_ID], "createUser");
+    assert_eq!(create.start, 16);
+    assert!(create.body.contains("NewUser"));
+    let messages: Vec<&str> = nodes
+        .iter()
+        .filter(|n| n.kind == MESSAGE_KIND)
+        .map(|n| n.name.as_str())
+        .collect();
+    assert_eq!(messages, ["User", "NewUser"]);
+    let sent_to_create: Vec<(&str, &str)> = edges
+        .iter()
+        .filter(|e| e.source == create.id)
+        .map(|e| {
+            let target = nodes.iter().find(|n| n.id == e.target).unwrap();
+            (e.kind.as_str(), target.name.as_str())
+        })
+        .collect();
+    assert_eq!(sent_to_create, [(ACCEPTS, "NewUser"), (RETURNS, "User")]);
+
+    let source = "@app.get(\"/users\")\ndef list_users():\n    return []\n\n@app.route(\"/users/<int:user_id>\", methods=[\"DELETE\"])\ndef delete_user(user_id):\n    pass\n\ndef helper():\n    pass\n";
+    let symbols = [
+        function("list_users", "app.py", 1, 2, ""),
+        function("delete_user", "app.py", 5, 6, ""),
+        function("helper", "app.py", 8, 9, ""),
+    ];
+    let refs: Vec<&NodeRecord> = symbols.iter().collect();
+    let (annotations, annotated) = annotate("app.py", source, "acme/api", &refs, None).unwrap();
+    let mut code: Vec<NodeRecord> = symbols.to_vec();
+    code.extend(annotations);
+
+    let served = link(&nodes, &code, &annotated);
+    let mut pairs: Vec<(&str, &str)> = served
+        .iter()
+        .map(|e| {
+            assert_eq!(e.kind, SERVES);
+            let op = nodes.iter().find(|n| n.id == e.target).unwrap();
+            (e.source.as_str(), op.name.as_str())
+        })
+        .collect();
+    pairs.sort();
+    assert_eq!(
+        pairs,
+        [
+            ("function-delete_user", "DELETE /users/{user_id}"),
+            ("function-list_users", "GET /users"),
+        ]
+    );
+}
+
+#[test]
+fn test_endpoints_serve_operations_through_their_handlers() {
+    let (nodes, _) = parse("openapi.json", &yaml_as_json(), "acme/api")
+        .unwrap()
+        .unwrap();
+    let mut endpoint = function("/users/:id", "routes.js", 3, 3, "");
+    endpoint.id = "endpoint-users".to_string();
+    endpoint.kind = "Endpoint".to_string();
+    endpoint
+        .meta
+        .insert("verb".to_string(), "delete".to_string());
+    let handler = function("removeUser", "users.js", 10, 14, "");
+    let handles = EdgeRecord {
+        repo_id: "acme/api".to_string(),
+        kind: "HANDLER".to_string(),
+        source: endpoint.id.clone(),
+        target: handler.id.clone(),
+    };
+    let served = link(&nodes, &[endpoint, handler], &[handles]);
+    assert_eq!(served.len(), 1);
+    assert_eq!(served[0].source, "function-removeUser");
+    assert!(served[0].target.starts_with("operation-deleteusersuserid"));
+}
+
+fn yaml_as_json() -> String {
+    let document: serde_json::Value = serde_yaml::from_str(SPEC).unwrap();
+    serde_json::to_string_pretty(&document).unwrap()
+}
+
+#[test]
+fn test_proto_services_and_messages() {
+    let source = "syntax = \"proto3\";\npackage users.v1;\n\nservice UserService {\n  // looks a user up\n  rpc GetUser (GetUserRequest) returns (User);\n  rpc WatchUsers (WatchRequest) returns (stream User) {\n    option (google.api.http) = { get: \"/v1/users:watch\" };\n  }\n}\n\nmessage GetUserRequest {\n  string id = 1;\n}\n\nmessage User {\n  string id = 1;\n  message Address {\n    string city = 1;\n  }\n  Address address = 2;\n}\n";
+    let (nodes, edges) = parse("proto/users.proto", source, "acme/api")
+        .unwrap()
+        .unwrap();
+    let named = |kind: &str| -> Vec<&str> {
+        nodes
+            .iter()
+            .filter(|n| n.kind == kind)
+            .map(|n| n.name.as_str())
+            .collect()
+    };
+    assert_eq!(
+        named(MESSAGE_KIND),
+        ["GetUserRequest", "User", "User.Address"]
+    );
+    assert_eq!(
+        named(OPERATION_KIND),
+        ["UserService.GetUser", "UserService.WatchUsers"]
+    );
+    let user = nodes.iter().find(|n| n.name == "User").unwrap();
+    assert_eq!((user.start, user.end), (15, 21));
+    let get = nodes
+        .iter()
+        .find(|n| n.name == "UserService.GetUser")
+        .unwrap();
+    assert_eq!(get.start, 5);
+    let returned: Vec<&str> = edges
+        .iter()
+        .filter(|e| e.kind == RETURNS)
+        .map(|e| e.target.as_str())
+        .collect();
+    assert_eq!(returned, [user.id.as_str(), user.id.as_str()]);
+
+    let handler = function(
+        "get_user",
+        "server.py",
+        3,
+        5,
+        "def get_user(self, request: GetUserRequest, context):\n    return self.users[request.id]",
+    );
+    let mut unrelated = function("get_user", "cli.py", 1, 2, "def get_user(name):\n    pass");
+    unrelated.id = "function-cli-get_user".to_string();
+    let served = link(&nodes, &[handler, unrelated], &[]);
+    assert_eq!(served.len(), 1);
+    assert_eq!(served[0].source, "function-get_user");
+    assert_eq!(served[0].target, get.id);
+}
+
+#[test]
+fn test_other_yaml_is_not_an_interface() {
+    let compose = "services:\n  swagger-ui:\n    image: swaggerapi/swagger-ui\n";
+    assert!(parse("docker-compose.yml", compose, "acme/api")
+        .unwrap()
+        .is_none());
+    assert!(parse("src/main.rs", "fn main() {}", "acme/api")
+        .unwrap()
+        .is_none());
+    assert_eq!(route_key("/users/{id}/"), route_key("/Users/:user_id"));
+    assert_eq!(route_key("/users/<int:id>"), "/users/{}");
+}
@@ -0,0 +1,261 @@
+use ast::lang::NodeType;
+use standalone::lang::{LanguagePlugin, LanguageRegistry, QuerySet, Severity};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Pretends to be an internal DSL, borrowing the rust grammar.
+struct FakeDsl {
+    queries: QuerySet,
+}
+
+impl FakeDsl {
+    fn new() -> Self {
+        Self {
+            queries: QuerySet::new().with(
+                NodeType::Function,
+                "(function_item name: (identifier) @name) @definition",
+            ),
+        }
+    }
+}
+
+impl LanguagePlugin for FakeDsl {
+    fn name(&self) -> &str {
+        "fake-dsl"
+    }
+    fn grammar(&self) -> tree_sitter::Language {
+        tree_sitter_rust::LANGUAGE.into()
+    }
+    fn file_extensions(&self) -> &[&str] {
+        &["fdsl"]
+    }
+    fn node_queries(&self) -> &QuerySet {
+        &self.queries
+    }
+}
+
+#[test]
+fn test_registered_plugin_produces_nodes() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("rules.fdsl"),
+        "fn alpha() {}\nfn beta() {}\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "fn not_parsed() {}\n").unwrap();
+
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+
+    let nodes = registry
+        .extract_dir(dir.path().to_str().unwrap())
+        .unwrap()
+        .nodes;
+
+    let names: Vec<&str> = nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, vec!["alpha", "beta"]);
+    assert!(nodes.iter().all(|n| n.file == "rules.fdsl"));
+    assert_eq!(nodes[1].start, 1);
+}
+
+#[test]
+fn test_unknown_extension_falls_back_to_plain_text() {
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+
+    let extraction = registry.extract("README.md", "fn alpha() {}").unwrap();
+    assert!(extraction.nodes.is_empty());
+    assert!(extraction.diagnostics.is_empty());
+}
+
+#[test]
+fn test_syntax_error_yields_partial_nodes_and_a_diagnostic() {
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+
+    let source = "fn alpha() {}\n\nfn beta() {\n    let x = ;\n}\n";
+    let extraction = registry.extract("rules.fdsl", source).unwrap();
+
+    let names: Vec<&str> = extraction.nodes.iter().map(|n| n.name.as_str()).collect();
+    assert!(names.contains(&"alpha"), "{:?}", names);
+    let diagnostic = extraction.diagnostics.first().expect("no diagnostic");
+    assert_eq!(diagnostic.file, "rules.fdsl");
+    let broken = source.find("let x").unwrap();
+    assert!(diagnostic.start >= broken && diagnostic.end <= source.len());
+    assert!(diagnostic.start <= diagnostic.end);
+}
+
+#[test]
+fn test_clean_file_has_no_diagnostics() {
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+
+    let extraction = registry.extract("rules.fdsl", "fn alpha() {}\n").unwrap();
+    assert_eq!(extraction.nodes.len(), 1);
+    assert!(extraction.diagnostics.is_empty());
+}
+
+#[test]
+fn test_non_utf8_file_is_a_diagnostic() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rules.fdsl");
+    std::fs::write(&path, b"fn alpha() {}\n\x81\x8d").unwrap();
+
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+
+    let extraction = registry.extract_file(&path, "rules.fdsl").unwrap();
+    assert!(extraction.nodes.is_empty());
+    assert_eq!(extraction.diagnostics.len(), 1);
+    assert_eq!(extraction.diagnostics[0].start, 14);
+    assert_eq!(extraction.diagnostics[0].severity, Severity::Error);
+}
+
+#[test]
+fn test_other_encodings_are_parsed_as_utf8() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = "// café\nfn alpha() {}\n";
+    let mut utf16 = vec![0xFF, 0xFE];
+    utf16.extend(source.encode_utf16().flat_map(|u| u.to_le_bytes()));
+    let mut bom = b"\xEF\xBB\xBF".to_vec();
+    bom.extend(source.as_bytes());
+
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+    for (file, bytes) in [("utf16.fdsl", utf16), ("bom.fdsl", bom)] {
+        let path = dir.path().join(file);
+        std::fs::write(&path, bytes).unwrap();
+        let extraction = registry.extract_file(&path, file).unwrap();
+        assert!(extraction.diagnostics.is_empty(), "{}", file);
+        let node = &extraction.nodes[0];
+        assert_eq!(node.name, "alpha");
+        // offsets are into the decoded text, with no BOM before it
+        let at = source.find("fn alpha").unwrap();
+        assert_eq!(node.span.start_byte, at, "{}", file);
+        assert_eq!(
+            &source[node.span.start_byte..node.span.end_byte],
+            "fn alpha() {}"
+        );
+    }
+}
+
+#[test]
+fn test_slow_parse_times_out_and_the_rest_is_extracted() {
+    let dir = tempfile::tempdir().unwrap();
+    let huge: String = (0..400_000)
+        .map(|i| format!("fn f{}() {{ let a = 1 + 2 * (3 - {}); }}\n", i, i))
+        .collect();
+    std::fs::write(dir.path().join("generated.fdsl"), &huge).unwrap();
+    std::fs::write(dir.path().join("rules.fdsl"), "fn alpha() {}\n").unwrap();
+
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+    registry.set_parse_timeout(Some(Duration::from_millis(50)));
+
+    let extraction = registry.extract_dir(dir.path().to_str().unwrap()).unwrap();
+    assert_eq!(extraction.timed_out, vec!["generated.fdsl"]);
+    let names: Vec<&str> = extraction.nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, vec!["alpha"]);
+    let diagnostic = &extraction.diagnostics[0];
+    assert_eq!(diagnostic.file, "generated.fdsl");
+    assert_eq!(diagnostic.severity, Severity::Warning);
+    assert_eq!(diagnostic.end, huge.len());
+
+    // without a timeout the same file parses in full
+    registry.set_parse_timeout(None);
+    let extraction = registry.extract("generated.fdsl", &huge).unwrap();
+    assert!(extraction.timed_out.is_empty());
+    assert_eq!(extraction.nodes.len(), 400_000);
+}
+
+#[test]
+fn test_plugin_nodes_carry_their_span() {
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+
+    let source = "fn alpha() {}\n  fn beta() {\n  }\n";
+    let extraction = registry.extract("rules.fdsl", source).unwrap();
+    let beta = &extraction.nodes[1];
+    assert_eq!(&source[beta.span.start_byte..beta.span.end_byte], beta.body);
+    assert_eq!(beta.span.start_column, 2);
+    assert_eq!(beta.span.end_column, 3);
+    assert_eq!((beta.start, beta.end), (1, 2));
+}
+
+/// A directory of `count` plugin files, a few with syntax errors.
+fn generated_repo(count: usize) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..count {
+        let mut source: String = (0..20)
+            .map(|j| format!("fn f{}_{}() {{ let x = {}; }}\n", i, j, j))
+            .collect();
+        if i % 7 == 0 {
+            source.push_str("fn broken() {\n    let = ;\n}\n");
+        }
+        let sub = dir.path().join(format!("m{}", i / 100));
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(sub.join(format!("f{}.fdsl", i)), source).unwrap();
+    }
+    dir
+}
+
+fn registry_with_workers(workers: usize) -> LanguageRegistry {
+    let mut registry = LanguageRegistry::new();
+    registry.register(Arc::new(FakeDsl::new())).unwrap();
+    registry.set_parse_workers(workers);
+    registry
+}
+
+#[test]
+fn test_parallel_extraction_matches_sequential() {
+    let dir = generated_repo(300);
+    let root = dir.path().to_str().unwrap();
+    let sequential = registry_with_workers(1).extract_dir(root).unwrap();
+    let parallel = registry_with_workers(8).extract_dir(root).unwrap();
+
+    let nodes = |e: &standalone::lang::Extraction| -> Vec<(String, String, usize, usize)> {
+        e.nodes
+            .iter()
+            .map(|n| (n.file.clone(), n.name.clone(), n.start, n.end))
+            .collect()
+    };
+    assert!(nodes(&sequential).len() >= 300 * 20);
+    assert_eq!(nodes(&parallel), nodes(&sequential));
+    assert_eq!(parallel.diagnostics, sequential.diagnostics);
+}
+
+#[test]
+fn test_parallel_extraction_fails_like_sequential() {
+    let dir = generated_repo(50);
+    let mut files: Vec<(std::path::PathBuf, String)> = (0..50)
+        .map(|i| {
+            let rel = format!("m0/f{}.fdsl", i);
+            (dir.path().join(&rel), rel)
+        })
+        .collect();
+    files.insert(20, (dir.path().join("gone.fdsl"), "gone.fdsl".to_string()));
+
+    let error = registry_with_workers(4).extract_files(&files).unwrap_err();
+    assert!(format!("{:#}", error).contains("gone.fdsl"), "{:#}", error);
+}
+
+/// Times extracting a 5000-file repo on one worker and on every CPU:
+/// `cargo test --test lang_plugins -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_parallel_extraction() {
+    use std::time::Instant;
+
+    let dir = generated_repo(5000);
+    let root = dir.path().to_str().unwrap();
+    let started = Instant::now();
+    let sequential = registry_with_workers(1).extract_dir(root).unwrap();
+    let one = started.elapsed();
+    let workers = standalone::config::Config::default().parse_workers();
+    let started = Instant::now();
+    let parallel = registry_with_workers(workers).extract_dir(root).unwrap();
+    let all = started.elapsed();
+
+    assert_eq!(parallel.nodes.len(), sequential.nodes.len());
+    println!("1 worker: {:?}, {} workers: {:?}", one, workers, all);
+}
@@ -0,0 +1,192 @@
+use standalone::limits::{GraphLimit, RateLimiter};
+use std::net::{IpAddr, Ipv4Addr};
+
+const ALICE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+const BOB: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+#[test]
+fn test_token_bucket_is_per_client() {
+    let limiter = RateLimiter::new(1.0, 2.0);
+    assert!(limiter.check(ALICE).is_ok());
+    assert!(limiter.check(ALICE).is_ok());
+    let wait = limiter.check(ALICE).unwrap_err();
+    assert!(wait.as_secs_f64() > 0.0 && wait.as_secs_f64() <= 1.0);
+    assert!(limiter.check(BOB).is_ok());
+}
+
+#[test]
+fn test_graph_budget_counts_on_top_of_what_is_stored() {
+    let limit = GraphLimit {
+        max_nodes: Some(10),
+        max_edges: None,
+    };
+    let mut budget = limit.budget(6, 100);
+    assert!(!budget.started_empty());
+    assert!(budget.take(4, 1_000).is_ok());
+    let reason = budget.take(1, 0).unwrap_err();
+    assert!(reason.contains("11 nodes"), "{}", reason);
+    // a refused file isn't counted
+    assert!(budget.take(0, 5).is_ok());
+    assert!(limit.budget(0, 0).started_empty());
+    assert!(GraphLimit::default().is_unlimited());
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::limits::{GraphLimit, RateLimiter};
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tokio::sync::Semaphore;
+    use tower::ServiceExt;
+
+    fn state() -> AppState {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        AppState::new(storage, LanguageRegistry::new(), 16)
+    }
+
+    fn ingest_path(path: &std::path::Path) -> Request<Body> {
+        let body = serde_json::json!({ "path": path }).to_string();
+        Request::post("/ingest-path")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ingest_over_the_cap_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let mut state = state();
+        state.allowed_roots = vec![root.clone()];
+        state.ingest_slots = Arc::new(Semaphore::new(2));
+        // two ingests in flight hold every slot
+        let running: Vec<_> = (0..2)
+            .map(|_| state.ingest_slots.clone().try_acquire_owned().unwrap())
+            .collect();
+        let app = standalone::router(Arc::new(state));
+
+        let response = app.clone().oneshot(ingest_path(&root)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after = response.headers()[header::RETRY_AFTER].to_str().unwrap();
+        assert!(retry_after.parse::<u64>().unwrap() >= 1);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["kind"], "too_many_requests");
+
+        // a freed slot lets the next one through to validation (the dir is empty)
+        drop(running);
+        let response = app.oneshot(ingest_path(&root)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_only_applies_to_mutating_routes() {
+        let mut state = state();
+        state.rate_limit = Some(Arc::new(RateLimiter::new(0.01, 1.0)));
+        let app = standalone::router(Arc::new(state));
+        let clear = || {
+            Request::post("/clear")
+                .header("Content-Type", "application/json")
+                .body(Body::from(r#"{"confirm": "ALL"}"#))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(clear()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.clone().oneshot(clear()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+        for uri in ["/healthz", "/events/stats", "/healthz"] {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ingest_past_the_graph_limit_is_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for i in 0..5 {
+            let source = format!("fn f{}() {{\n    g{}();\n}}\n\nfn g{}() {{}}\n", i, i, i);
+            std::fs::write(root.join(format!("m{}.rs", i)), source).unwrap();
+        }
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 1024);
+        state.allowed_roots = vec![root.clone()];
+        state.graph_limit = GraphLimit {
+            max_nodes: Some(6),
+            max_edges: None,
+        };
+        let mut events = state.tx.subscribe();
+        let app = standalone::router(Arc::new(state));
+
+        let request = Request::post("/ingest-path")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "path": root, "repo_id": "acme/app" }).to_string(),
+            ))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["kind"], "too_large");
+        assert!(
+            body["error"]
+                .as_str()
+                .unwrap()
+                .contains("over the limit of 6"),
+            "{}",
+            body
+        );
+
+        let mut statuses = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            statuses.push(event.update.status);
+        }
+        assert!(statuses.iter().any(|s| s == "stored"), "{:?}", statuses);
+        assert_eq!(statuses.last().unwrap(), "graph_too_large");
+        assert_eq!(storage.graph_size(Some("acme/app")).await.unwrap(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_reprocessing_a_file_past_the_graph_limit_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(
+            root.join("lib.rs"),
+            "fn a() {}
+
+fn b() {}
+
+fn c() {}
+",
+        )
+        .unwrap();
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+        state.allowed_roots = vec![root.clone()];
+        state.graph_limit = GraphLimit {
+            max_nodes: Some(2),
+            max_edges: None,
+        };
+        let app = standalone::router(Arc::new(state));
+
+        let request = Request::post("/process-file")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "repo_path": root, "file": "lib.rs" }).to_string(),
+            ))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(storage.graph_size(None).await.unwrap(), (0, 0));
+    }
+}
@@ -0,0 +1,311 @@
+use standalone::encoding::Encoding;
+use standalone::local::{check_allowed, check_encoding, unparseable, walk, Unparseable};
+use std::fs;
+
+#[test]
+fn test_walk_honors_gitignore_without_git() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("target")).unwrap();
+    fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("src/lib.rs"), "pub fn lib() {}").unwrap();
+    fs::write(root.join("target/out.rs"), "fn built() {}").unwrap();
+    fs::write(root.join("debug.log"), "noise").unwrap();
+
+    let files = walk(&root).unwrap();
+    assert_eq!(files, vec!["src/lib.rs", "src/main.rs"]);
+}
+
+#[test]
+fn test_walk_honors_meshignore() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("vendor/dep")).unwrap();
+    fs::write(
+        root.join(".meshignore"),
+        "vendor/
+",
+    )
+    .unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("vendor/dep/lib.rs"), "pub fn dep() {}").unwrap();
+
+    let files = walk(&root).unwrap();
+    assert_eq!(files, vec!["src/main.rs"]);
+}
+
+#[test]
+fn test_meshignore_negation_re_includes() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::create_dir_all(root.join("generated")).unwrap();
+    fs::write(
+        root.join(".meshignore"),
+        "generated/*\n!generated/schema.rs\n",
+    )
+    .unwrap();
+    fs::write(root.join("generated/schema.rs"), "pub struct Schema;").unwrap();
+    fs::write(root.join("generated/bindings.rs"), "pub fn bind() {}").unwrap();
+
+    let files = walk(&root).unwrap();
+    assert_eq!(files, vec!["generated/schema.rs"]);
+}
+
+#[test]
+fn test_meshignore_wins_over_gitignore() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join(".gitignore"), "*.gen.rs\n!notes.txt\n").unwrap();
+    fs::write(root.join(".meshignore"), "!api.gen.rs\n*.txt\n").unwrap();
+    fs::write(root.join("api.gen.rs"), "pub fn api() {}").unwrap();
+    fs::write(root.join("db.gen.rs"), "pub fn db() {}").unwrap();
+    fs::write(root.join("notes.txt"), "notes").unwrap();
+
+    let files = walk(&root).unwrap();
+    // kept by .meshignore despite .gitignore, and the other way around
+    assert_eq!(files, vec!["api.gen.rs"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_skips_symlinks_outside_root() {
+    let outside = tempfile::tempdir().unwrap();
+    fs::write(outside.path().join("secret.rs"), "fn secret() {}").unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+    std::os::unix::fs::symlink(outside.path().join("secret.rs"), root.join("secret.rs")).unwrap();
+    std::os::unix::fs::symlink(outside.path(), root.join("linked")).unwrap();
+    std::os::unix::fs::symlink(root.join("main.rs"), root.join("alias.rs")).unwrap();
+
+    let files = walk(&root).unwrap();
+    assert_eq!(files, vec!["alias.rs", "main.rs"]);
+}
+
+#[test]
+fn test_check_allowed() {
+    let allowed = tempfile::tempdir().unwrap();
+    let other = tempfile::tempdir().unwrap();
+    let roots = vec![allowed.path().canonicalize().unwrap()];
+    fs::create_dir_all(allowed.path().join("repo")).unwrap();
+
+    let repo = allowed.path().join("repo");
+    assert!(check_allowed(repo.to_str().unwrap(), &roots).is_ok());
+    assert!(check_allowed(other.path().to_str().unwrap(), &roots).is_err());
+    assert!(check_allowed("repo", &roots).is_err());
+
+    // `..` is resolved before the check
+    let escape = repo.join("..").join("..");
+    assert!(check_allowed(escape.to_str().unwrap(), &roots).is_err());
+    // nothing is allowed when no roots are configured
+    assert!(check_allowed(repo.to_str().unwrap(), &[]).is_err());
+}
+
+#[test]
+fn test_unparseable_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::write(root.join("small.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("big.rs"), "// padding\n".repeat(200)).unwrap();
+    let mut blob = b"PK".to_vec();
+    blob.extend([0u8, 3, 4, 0, 20]);
+    fs::write(root.join("archive.rs"), blob).unwrap();
+
+    assert_eq!(unparseable(root, "small.rs", Some(1024)), None);
+    assert_eq!(
+        unparseable(root, "big.rs", Some(1024)),
+        Some(Unparseable::TooLarge {
+            bytes: 2200,
+            limit: 1024
+        })
+    );
+    assert_eq!(unparseable(root, "big.rs", None), None);
+    assert_eq!(
+        unparseable(root, "archive.rs", Some(1024)),
+        Some(Unparseable::Binary)
+    );
+    // left for the parser to report
+    assert_eq!(unparseable(root, "missing.rs", Some(1024)), None);
+    // NUL bytes, but text
+    let utf16: Vec<u8> = "fn main() {}\n"
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes())
+        .collect();
+    fs::write(root.join("wide.rs"), utf16).unwrap();
+    assert_eq!(unparseable(root, "wide.rs", Some(1024)), None);
+}
+
+#[test]
+fn test_check_encoding() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::write(root.join("plain.rs"), "fn main() {}\n").unwrap();
+    fs::write(root.join("latin.rs"), b"// caf\xe9\nfn main() {}\n").unwrap();
+
+    assert_eq!(check_encoding(root, "plain.rs", true), Ok(None));
+    // a directory on disk is left as it is
+    assert!(matches!(
+        check_encoding(root, "latin.rs", false),
+        Err(Unparseable::Encoding(_))
+    ));
+    assert_eq!(fs::read(root.join("latin.rs")).unwrap()[6], 0xE9);
+
+    assert_eq!(
+        check_encoding(root, "latin.rs", true),
+        Ok(Some(Encoding::Windows1252))
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("latin.rs")).unwrap(),
+        "// café\nfn main() {}\n"
+    );
+    assert_eq!(check_encoding(root, "latin.rs", true), Ok(None));
+    assert_eq!(check_encoding(root, "missing.rs", true), Ok(None));
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn state(roots: Vec<std::path::PathBuf>) -> AppState {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+        state.allowed_roots = roots;
+        state
+    }
+
+    fn request(path: &std::path::Path) -> Request<Body> {
+        let body = serde_json::json!({ "path": path }).to_string();
+        Request::post("/ingest-path")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ingest_path_outside_roots_is_rejected() {
+        let allowed = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let state = state(vec![allowed.path().canonicalize().unwrap()]);
+
+        let response = standalone::router(Arc::new(state))
+            .oneshot(request(other.path()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_temp_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"sample\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src/main.rs"),
+            "fn main() {\n    greet();\n}\n\nfn greet() {}\n",
+        )
+        .unwrap();
+
+        let state = state(vec![root.clone()]);
+        let mut events = state.tx.subscribe();
+        let response = standalone::router(Arc::new(state))
+            .oneshot(request(&root))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["nodes"].as_u64().unwrap() > 0);
+
+        let mut complete = None;
+        while let Ok(event) = events.try_recv() {
+            if event.update.status == "complete" {
+                complete = Some(event);
+            }
+        }
+        let complete = complete.expect("no completion event was sent");
+        assert_eq!(complete.total, Some(2));
+        assert_eq!(complete.completed, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_ingest_skips_oversized_and_binary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        let generated: String = (0..200).map(|i| format!("fn f{}() {{}}\n", i)).collect();
+        fs::write(root.join("src/generated.rs"), generated).unwrap();
+        fs::write(root.join("src/blob.rs"), b"fn \0\x01\x02 {}").unwrap();
+
+        let mut state = state(vec![root.clone()]);
+        state.max_file_bytes = Some(1024);
+        let storage = state.storage.clone();
+        let mut events = state.tx.subscribe();
+        let response = standalone::router(Arc::new(state))
+            .oneshot(request(&root))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut warnings = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.update.status == "warning" {
+                warnings.push(event.update.message);
+            }
+        }
+        warnings.sort();
+        assert_eq!(
+            warnings,
+            vec![
+                "Skipped src/blob.rs: not a text file",
+                "Skipped src/generated.rs: 2490 bytes is over the 1024 byte limit",
+            ]
+        );
+        let (nodes, _) = storage.load_graph(None).await.unwrap();
+        assert!(nodes.iter().any(|n| n.name == "main"));
+        assert!(
+            nodes
+                .iter()
+                .all(|n| !n.file.ends_with("generated.rs") && !n.file.ends_with("blob.rs")),
+            "{:?}",
+            nodes
+        );
+
+        // and kept for the diagnostics report
+        let template = standalone::query::find_template("parse-diagnostics").unwrap();
+        let params = standalone::query::scoped_params(&Default::default(), None);
+        let rows = storage.query(template, &params).await.unwrap();
+        let skipped: Vec<(&str, &str)> = rows
+            .iter()
+            .map(|r| (r["file"].as_str().unwrap(), r["message"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                ("src/blob.rs", "file skipped, not a text file"),
+                (
+                    "src/generated.rs",
+                    "file skipped, 2490 bytes is over the 1024 byte limit"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+mod common;
+
+use standalone::locks::RepoLocks;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_writers_to_a_repo_take_turns() {
+    let locks = RepoLocks::default();
+    let ingest = locks.write(Some("acme/app")).await;
+    assert!(locks.try_write(Some("acme/app")).is_none());
+    // nor can everything be cleared meanwhile
+    assert!(locks.try_write(None).is_none());
+    // other repos carry on
+    assert!(locks.try_write(Some("acme/web")).is_some());
+    let _read = locks.read(Some("acme/web")).await;
+    let _all = locks.read(None).await;
+
+    let waiting = tokio::time::timeout(Duration::from_millis(50), locks.read(Some("acme/app")));
+    assert!(waiting.await.is_err());
+    drop(ingest);
+    assert!(locks.try_write(Some("acme/app")).is_some());
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use crate::common::git;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{NodeRecord, Storage};
+    use standalone::AppState;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    async fn post(app: &axum::Router, path: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(path)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_clear_during_an_ingest_is_serialized() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 1024);
+        state.allowed_roots = vec![root.clone()];
+        let state = Arc::new(state);
+        let app = standalone::router(state.clone());
+
+        // held as an ingest holds it, until it's done
+        let running = state.repo_locks.write(Some("acme/app")).await;
+        let ingest = tokio::spawn({
+            let app = app.clone();
+            let root = root.clone();
+            async move {
+                post(
+                    &app,
+                    "/ingest-path",
+                    json!({"path": root, "repo_id": "acme/app"}),
+                )
+                .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!ingest.is_finished());
+        assert_eq!(storage.graph_size(Some("acme/app")).await.unwrap().0, 0);
+
+        let clear = json!({"repo_id": "acme/app", "confirm": "acme/app"});
+        let (status, body) = post(&app, "/clear", clear.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["kind"], "conflict");
+        // a clear of another repo isn't held up
+        let other = json!({"repo_id": "acme/web", "confirm": "acme/web"});
+        assert_eq!(post(&app, "/clear", other).await.0, StatusCode::OK);
+
+        drop(running);
+        let (status, _) = ingest.await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(storage.graph_size(Some("acme/app")).await.unwrap().0 > 0);
+
+        // one that waits goes after the ingest holding the lock
+        let running = state.repo_locks.write(Some("acme/app")).await;
+        let mut waiting = clear;
+        waiting["wait"] = json!(true);
+        let clear = tokio::spawn({
+            let app = app.clone();
+            async move { post(&app, "/clear", waiting).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!clear.is_finished());
+        assert!(storage.graph_size(Some("acme/app")).await.unwrap().0 > 0);
+        drop(running);
+        assert_eq!(clear.await.unwrap().0, StatusCode::OK);
+        assert_eq!(storage.graph_size(Some("acme/app")).await.unwrap().0, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reads_of_a_repo_wait_out_its_ingest() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let state = Arc::new(AppState::new(storage, LanguageRegistry::new(), 1024));
+        let app = standalone::router(state.clone());
+
+        let running = state.repo_locks.write(Some("acme/app")).await;
+        let search = tokio::spawn({
+            let app = app.clone();
+            async move {
+                post(
+                    &app,
+                    "/search",
+                    json!({"query": "main", "repo": "acme/app"}),
+                )
+                .await
+            }
+        });
+        let export = tokio::spawn({
+            let app = app.clone();
+            async move {
+                let request = Request::get("/export/dot?repo=acme/app")
+                    .body(Body::empty())
+                    .unwrap();
+                app.oneshot(request).await.unwrap().status()
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!search.is_finished());
+        assert!(!export.is_finished());
+        // reads of other repos carry on
+        let (status, _) = post(
+            &app,
+            "/search",
+            json!({"query": "main", "repo": "acme/web"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        drop(running);
+        assert_eq!(search.await.unwrap().0, StatusCode::OK);
+        assert_eq!(export.await.unwrap(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_an_update_touches_nothing_stored_until_it_holds_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        git(&root, &["init", "-q", "-b", "main"]);
+        std::fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(root.join("old.rs"), "fn old() {}\n").unwrap();
+        git(&root, &["add", "."]);
+        git(&root, &["commit", "-q", "-m", "init"]);
+        let first = git(&root, &["rev-parse", "HEAD"]);
+        git(&root, &["rm", "-q", "old.rs"]);
+        git(&root, &["commit", "-q", "-m", "drop old.rs"]);
+
+        let repo = standalone::storage::repo_id("", root.to_str().unwrap());
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let old = NodeRecord {
+            file: "old.rs".to_string(),
+            body: "fn old() {}".to_string(),
+            ..crate::common::function_in(&repo, "old")
+        };
+        storage.upsert_node(&old).await.unwrap();
+        // a local checkout's commit is stored under the empty URL
+        storage.set_repo_hash("", &first).await.unwrap();
+        let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 1024);
+        state.allowed_roots = vec![root.clone()];
+        let state = Arc::new(state);
+        let app = standalone::router(state.clone());
+
+        // held as a clear holds it
+        let clearing = state.repo_locks.write(Some(&repo)).await;
+        let update = tokio::spawn({
+            let app = app.clone();
+            let root = root.clone();
+            async move { post(&app, "/process", json!({"repo_path": root})).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!update.is_finished());
+        // old.rs was deleted upstream, but it's only dropped from the graph
+        // once the update runs
+        assert!(storage.node(&repo, "function-old").await.unwrap().is_some());
+
+        drop(clearing);
+        let (status, body) = update.await.unwrap();
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(storage.node(&repo, "function-old").await.unwrap().is_none());
+    }
+}
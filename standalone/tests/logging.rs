@@ -0,0 +1,131 @@
+#![cfg(feature = "sqlite")]
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use standalone::lang::LanguageRegistry;
+use standalone::logging::REQUEST_ID;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+/// A span or event: its name, or message for events, and its fields.
+type Record = (String, HashMap<String, String>);
+
+#[derive(Default, Clone)]
+struct Capture {
+    spans: Arc<Mutex<Vec<Record>>>,
+    events: Arc<Mutex<Vec<Record>>>,
+}
+
+#[derive(Default)]
+struct Fields(HashMap<String, String>);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Capture {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let name = attrs.metadata().name().to_string();
+        self.spans.lock().unwrap().push((name, fields.0));
+    }
+
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let message = fields.0.remove("message").unwrap_or_default();
+        self.events.lock().unwrap().push((message, fields.0));
+    }
+}
+
+impl Capture {
+    fn spans(&self, name: &str) -> Vec<HashMap<String, String>> {
+        let spans = self.spans.lock().unwrap();
+        spans
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, f)| f.clone())
+            .collect()
+    }
+
+    fn events(&self, message: &str) -> Vec<HashMap<String, String>> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter(|(m, _)| m == message)
+            .map(|(_, f)| f.clone())
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn test_ingest_logs_carry_repo_and_request_id() {
+    let capture = Capture::default();
+    let subscriber = tracing_subscriber::registry().with(capture.clone());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.clone()];
+    let app = standalone::router(Arc::new(state));
+
+    let body = serde_json::json!({ "path": root, "repo_id": "acme/app" }).to_string();
+    let request = Request::post("/ingest-path")
+        .header("Content-Type", "application/json")
+        .header(REQUEST_ID, "req-42")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[REQUEST_ID], "req-42");
+
+    let ingests = capture.spans("ingest");
+    assert_eq!(ingests.len(), 1);
+    assert_eq!(ingests[0]["repo_id"], "acme/app");
+    assert_eq!(ingests[0]["request_id"], "req-42");
+    assert!(ingests[0].contains_key("ref"));
+    let finished = capture.events("ingest finished");
+    assert_eq!(finished.len(), 1);
+    assert_eq!(finished[0]["outcome"], "succeeded");
+    assert_eq!(finished[0]["repo_id"], "acme/app");
+    assert!(finished[0]["duration_ms"].parse::<u64>().is_ok());
+
+    // a failed ingest is logged too, under an id of the server's own
+    let body = serde_json::json!({ "repo_path": root.join("missing") }).to_string();
+    let request = Request::post("/process")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert!(!response.status().is_success());
+    let id = response.headers()[REQUEST_ID].to_str().unwrap().to_string();
+    assert_eq!(id.len(), 16);
+    let ingests = capture.spans("ingest");
+    assert_eq!(ingests.len(), 2);
+    assert_eq!(ingests[1]["request_id"], id);
+    let finished = capture.events("ingest finished");
+    assert_eq!(finished.len(), 2);
+    assert_ne!(finished[1]["outcome"], "succeeded");
+    assert!(finished[1].contains_key("error"));
+}
@@ -0,0 +1,152 @@
+use standalone::metrics::{Histogram, Metrics, PARSE_BUCKETS};
+use std::time::Duration;
+
+fn sample(text: &str, name: &str) -> f64 {
+    text.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("{} not in\n{}", name, text))
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn test_histogram_buckets_are_cumulative() {
+    let metrics = Metrics::default();
+    metrics.parse_duration.observe(Duration::from_micros(500));
+    metrics
+        .parse_duration
+        .observe_n(Duration::from_millis(20), 2);
+    metrics.parse_duration.observe(Duration::from_secs(60));
+    let text = metrics.render(&[]);
+
+    assert_eq!(
+        sample(&text, "mesh_parse_duration_seconds_bucket{le=\"0.001\"}"),
+        1.0
+    );
+    assert_eq!(
+        sample(&text, "mesh_parse_duration_seconds_bucket{le=\"0.05\"}"),
+        3.0
+    );
+    assert_eq!(
+        sample(&text, "mesh_parse_duration_seconds_bucket{le=\"+Inf\"}"),
+        4.0
+    );
+    assert_eq!(sample(&text, "mesh_parse_duration_seconds_count"), 4.0);
+    let sum = sample(&text, "mesh_parse_duration_seconds_sum");
+    assert!((sum - 60.0405).abs() < 1e-9, "{}", sum);
+}
+
+#[test]
+fn test_exposition_declares_every_metric() {
+    let metrics = Metrics::default();
+    metrics.files_parsed.add(3);
+    let text = metrics.render(&[("mesh_active_ingests", "Ingests running now", 2.0)]);
+    assert!(text.contains("# TYPE mesh_files_parsed_total counter\n"));
+    assert!(text.contains("# TYPE mesh_ingest_duration_seconds histogram\n"));
+    assert!(text.contains("# TYPE mesh_active_ingests gauge\n"));
+    assert_eq!(sample(&text, "mesh_files_parsed_total"), 3.0);
+    assert_eq!(sample(&text, "mesh_active_ingests"), 2.0);
+}
+
+#[test]
+fn test_dropped_timer_counts_a_failure() {
+    let metrics = Metrics::default();
+    drop(metrics.ingest_timer());
+    metrics.ingest_timer().succeeded();
+    assert_eq!(metrics.ingest_failures.get(), 1);
+    assert_eq!(metrics.repos_ingested.get(), 1);
+    assert_eq!(metrics.ingest_duration.count(), 1);
+
+    let histogram = Histogram::new(PARSE_BUCKETS);
+    assert_eq!(histogram.count(), 0);
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::sample;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn scrape(app: &axum::Router) -> String {
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ingest_moves_the_counters() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"sample\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src/main.rs"),
+            "fn main() {\n    greet();\n}\n\nfn greet() {}\n",
+        )
+        .unwrap();
+
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+        state.allowed_roots = vec![root.clone()];
+        let app = standalone::router(Arc::new(state));
+
+        let before = scrape(&app).await;
+        assert_eq!(sample(&before, "mesh_repos_ingested_total"), 0.0);
+        assert_eq!(sample(&before, "mesh_active_ingests"), 0.0);
+
+        let body = serde_json::json!({ "path": root }).to_string();
+        let request = Request::post("/ingest-path")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let after = scrape(&app).await;
+        assert_eq!(sample(&after, "mesh_repos_ingested_total"), 1.0);
+        assert_eq!(sample(&after, "mesh_ingest_failures_total"), 0.0);
+        assert!(sample(&after, "mesh_files_parsed_total") > 0.0);
+        assert!(sample(&after, "mesh_nodes_written_total") > 0.0);
+        assert_eq!(sample(&after, "mesh_ingest_duration_seconds_count"), 1.0);
+        assert_eq!(sample(&after, "mesh_active_ingests"), 0.0);
+        assert_eq!(sample(&after, "mesh_sse_subscribers"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_ingest_is_counted() {
+        let allowed = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+        state.allowed_roots = vec![allowed.path().canonicalize().unwrap()];
+        let app = standalone::router(Arc::new(state));
+
+        let body = serde_json::json!({ "path": other.path() }).to_string();
+        let request = Request::post("/ingest-path")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let text = scrape(&app).await;
+        assert_eq!(sample(&text, "mesh_ingest_failures_total"), 1.0);
+        assert_eq!(sample(&text, "mesh_repos_ingested_total"), 0.0);
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use standalone::msgpack::{Format, MSGPACK};
+
+fn accept(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+#[test]
+fn test_msgpack_is_only_sent_when_asked_for() {
+    assert_eq!(Format::of(&accept(MSGPACK)), Format::MessagePack);
+    assert_eq!(
+        Format::of(&accept("application/json;q=0.5, application/x-msgpack")),
+        Format::MessagePack
+    );
+    assert_eq!(
+        Format::of(&accept("text/html,application/xhtml+xml,*/*;q=0.8")),
+        Format::Json
+    );
+    assert_eq!(Format::of(&HeaderMap::new()), Format::Json);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_msgpack_body_decodes_to_the_json_structure() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{EdgeRecord, NodeRecord, Storage};
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let node = |name: &str, start: usize| NodeRecord {
+        start,
+        end: start + 2,
+        body: format!("fn {}() {{}}", name),
+        meta: [("visibility".to_string(), "pub".to_string())].into(),
+        ..crate::common::function(name)
+    };
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage
+        .upsert_nodes(&[node("main", 1), node("greet", 5)])
+        .await
+        .unwrap();
+    storage
+        .upsert_edge(&EdgeRecord {
+            repo_id: "acme/app".to_string(),
+            kind: "CALLS".to_string(),
+            source: "function-main".to_string(),
+            target: "function-greet".to_string(),
+        })
+        .await
+        .unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let fetch = |path: &str, body: Value, accept: Option<&str>| {
+        let mut request = Request::post(path).header("Content-Type", "application/json");
+        if let Some(accept) = accept {
+            request = request.header("Accept", accept);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let content_type = response.headers()[header::CONTENT_TYPE].clone();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (content_type, body)
+        }
+    };
+
+    for (path, body) in [
+        (
+            "/graph/query",
+            json!({ "query": "symbols-in-file", "params": { "file": "src/lib.rs" } }),
+        ),
+        (
+            "/neighborhood",
+            json!({ "repo": "acme/app", "id": "function-main" }),
+        ),
+        ("/call-graph", json!({ "repo": "acme/app" })),
+    ] {
+        let (content_type, packed) = fetch(path, body.clone(), Some(MSGPACK)).await;
+        assert_eq!(content_type, MSGPACK);
+        let (_, plain) = fetch(path, body, None).await;
+        let packed: Value = rmp_serde::from_slice(&packed).unwrap();
+        let plain: Value = serde_json::from_slice(&plain).unwrap();
+        assert_eq!(packed, plain, "{}", path);
+    }
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use standalone::neighborhood::{neighborhood, Scope, Subgraph};
+use standalone::storage::{EdgeRecord, NodeRecord};
+
+fn node(id: &str) -> NodeRecord {
+    NodeRecord {
+        id: id.to_string(),
+        ..common::function(id)
+    }
+}
+
+fn edge(kind: &str, source: &str, target: &str) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: kind.to_string(),
+        source: source.to_string(),
+        target: target.to_string(),
+    }
+}
+
+/// `a` calls `b` and `c`, `d` calls `a`, `b` calls `c` and `e`, and `e`
+/// contains `f`.
+fn graph() -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let nodes = ["a", "b", "c", "d", "e", "f"].map(node).to_vec();
+    let edges = vec![
+        edge("CALLS", "a", "b"),
+        edge("CALLS", "a", "c"),
+        edge("CALLS", "d", "a"),
+        edge("CALLS", "b", "c"),
+        edge("CALLS", "b", "e"),
+        edge("CONTAINS", "e", "f"),
+        // endpoint not stored
+        edge("CALLS", "a", "gone"),
+    ];
+    (nodes, edges)
+}
+
+fn ids(subgraph: &Subgraph) -> Vec<(&str, usize)> {
+    subgraph
+        .nodes
+        .iter()
+        .map(|n| (n.node.id.as_str(), n.hops))
+        .collect()
+}
+
+fn scope(depth: usize) -> Scope {
+    Scope {
+        depth,
+        ..Scope::default()
+    }
+}
+
+#[test]
+fn test_depth_one_is_exactly_the_direct_neighbors() {
+    let (nodes, edges) = graph();
+    let subgraph = neighborhood(&nodes, &edges, "a", &scope(1)).unwrap();
+    let mut found = ids(&subgraph);
+    found.sort();
+    assert_eq!(found, vec![("a", 0), ("b", 1), ("c", 1), ("d", 1)]);
+    assert!(!subgraph.truncated);
+    // induced: the edge between two neighbors is in, edges out of them aren't
+    let mut pairs: Vec<_> = subgraph
+        .edges
+        .iter()
+        .map(|e| (e.source.as_str(), e.target.as_str()))
+        .collect();
+    pairs.sort();
+    assert_eq!(pairs, vec![("a", "b"), ("a", "c"), ("b", "c"), ("d", "a")]);
+
+    let alone = neighborhood(&nodes, &edges, "a", &scope(0)).unwrap();
+    assert_eq!(ids(&alone), vec![("a", 0)]);
+    assert!(neighborhood(&nodes, &edges, "nope", &scope(1)).is_none());
+}
+
+#[test]
+fn test_edge_kinds_limit_what_is_followed() {
+    let (nodes, edges) = graph();
+    let everything = neighborhood(&nodes, &edges, "a", &scope(3)).unwrap();
+    let mut found = ids(&everything);
+    found.sort();
+    assert_eq!(
+        found,
+        vec![("a", 0), ("b", 1), ("c", 1), ("d", 1), ("e", 2), ("f", 3)]
+    );
+
+    let calls = Scope {
+        edge_kinds: vec!["CALLS".to_string()],
+        ..scope(3)
+    };
+    let subgraph = neighborhood(&nodes, &edges, "a", &calls).unwrap();
+    assert!(subgraph.nodes.iter().all(|n| n.node.id != "f"));
+    assert!(subgraph.edges.iter().all(|e| e.kind == "CALLS"));
+}
+
+#[test]
+fn test_node_cap_keeps_the_nearest() {
+    let (nodes, edges) = graph();
+    let capped = Scope {
+        max_nodes: 4,
+        ..scope(3)
+    };
+    let subgraph = neighborhood(&nodes, &edges, "a", &capped).unwrap();
+    assert!(subgraph.truncated);
+    assert_eq!(subgraph.nodes.len(), 4);
+    assert!(subgraph.nodes.iter().all(|n| n.hops <= 1));
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_neighborhood_endpoint() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let (nodes, edges) = graph();
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage.upsert_nodes(&nodes).await.unwrap();
+    storage.upsert_edges(&edges).await.unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+    let request = |body: Value| {
+        Request::post("/neighborhood")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(request(json!({ "repo": "acme/app", "id": "b" })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["depth"], 1);
+    let mut found: Vec<&str> = body["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|n| n["id"].as_str().unwrap())
+        .collect();
+    found.sort();
+    assert_eq!(found, vec!["a", "b", "c", "e"]);
+    assert_eq!(body["edges"].as_array().unwrap().len(), 4);
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            json!({ "repo": "acme/app", "id": "a", "depth": 50 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app
+        .oneshot(request(json!({ "repo": "acme/app", "id": "nope" })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,113 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use common::git;
+use serde_json::{json, Value};
+use standalone::filter::KindFilter;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{repo_id, EdgeRecord, Storage};
+use standalone::AppState;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const SOURCE: &str = "use std::fmt;\n\nstruct Greeter;\n\nfn main() {\n    greet();\n}\n\nfn greet() {\n    let name = \"mesh\";\n    println!(\"{}\", name);\n}\n";
+
+async fn ingest(storage: Arc<SqliteStorage>, body: Value) -> Value {
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 64);
+    // the checkout the body names is one the server may read
+    state.allowed_roots = body["repo_path"]
+        .as_str()
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+    let request = Request::post("/ingest")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_function_only_ingest_keeps_functions_and_calls() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("main.rs"), SOURCE).unwrap();
+    git(&root, &["init", "-q", "-b", "main"]);
+    git(&root, &["add", "."]);
+    git(&root, &["commit", "-q", "-m", "init"]);
+    let repo = repo_id("", root.to_str().unwrap());
+
+    let everything = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    ingest(everything.clone(), json!({ "repo_path": root })).await;
+    let (all_nodes, _) = everything.load_graph(Some(&repo)).await.unwrap();
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let body = ingest(
+        storage.clone(),
+        json!({ "repo_path": root, "node_kinds": ["function"] }),
+    )
+    .await;
+    let (nodes, edges) = storage.load_graph(Some(&repo)).await.unwrap();
+    assert_eq!(body["nodes"], nodes.len());
+    assert!(nodes.len() < all_nodes.len());
+    let names: Vec<&str> = nodes.iter().map(|n| n.name.as_str()).collect();
+    assert!(
+        names.contains(&"main") && names.contains(&"greet"),
+        "{:?}",
+        names
+    );
+    assert!(nodes.iter().all(|n| n.kind == "Function"), "{:?}", nodes);
+    assert!(!edges.is_empty());
+    assert!(edges.iter().all(|e| e.kind == "CALLS"), "{:?}", edges);
+}
+
+#[test]
+fn test_edges_to_dropped_kinds_go_too() {
+    let node = |kind: &str, name: &str| common::symbol(kind, name, "src/lib.rs", 1, 2);
+    let edge = |kind: &str, source: &str, target: &str| EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: kind.to_string(),
+        source: source.to_string(),
+        target: target.to_string(),
+    };
+    let mut nodes = vec![
+        node("File", "lib"),
+        node("Function", "a"),
+        node("Function", "b"),
+    ];
+    let mut edges = vec![
+        edge("CONTAINS", "file-lib", "function-a"),
+        edge("CALLS", "function-a", "function-b"),
+        // into a node stored by an earlier ingest
+        edge("CALLS", "function-b", "function-c"),
+        edge("USES", "function-b", "var-x"),
+    ];
+
+    KindFilter::default().apply(&mut nodes, &mut edges);
+    assert_eq!((nodes.len(), edges.len()), (3, 4));
+
+    KindFilter::new(&["Function".to_string()]).apply(&mut nodes, &mut edges);
+    assert_eq!(
+        nodes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+    assert_eq!(
+        edges,
+        vec![
+            edge("CALLS", "function-a", "function-b"),
+            edge("CALLS", "function-b", "function-c"),
+        ]
+    );
+}
@@ -0,0 +1,117 @@
+use anyhow::anyhow;
+use standalone::clone::RetryPolicy;
+use standalone::metrics::Metrics;
+use standalone::outbound::{self, Breaker, BreakerState, Outbound, OutboundPolicy};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const COOLDOWN: Duration = Duration::from_millis(50);
+
+#[test]
+fn test_breaker_opens_after_failures_and_half_opens_after_the_cooldown() {
+    let breaker = Breaker::new(3, COOLDOWN);
+    breaker.failed();
+    breaker.failed();
+    assert_eq!(breaker.state(), BreakerState::Closed);
+    // a success starts the count again
+    breaker.succeeded();
+    breaker.failed();
+    breaker.failed();
+    assert!(breaker.allow());
+    breaker.failed();
+    assert_eq!(breaker.state(), BreakerState::Open);
+    assert!(!breaker.allow());
+    assert_eq!(breaker.trips.get(), 1);
+
+    std::thread::sleep(COOLDOWN * 2);
+    assert_eq!(breaker.state(), BreakerState::HalfOpen);
+    // one call tests the service; the rest wait on it
+    assert!(breaker.allow());
+    assert!(!breaker.allow());
+    breaker.failed();
+    assert_eq!(breaker.state(), BreakerState::Open);
+    assert_eq!(breaker.trips.get(), 2);
+
+    std::thread::sleep(COOLDOWN * 2);
+    assert!(breaker.allow());
+    breaker.succeeded();
+    assert_eq!(breaker.state(), BreakerState::Closed);
+    assert!(breaker.allow());
+}
+
+fn policy(attempts: u32, failures: u32) -> OutboundPolicy {
+    OutboundPolicy {
+        timeout: Duration::from_millis(100),
+        retry: RetryPolicy {
+            max_attempts: attempts,
+            base_delay: Duration::from_millis(1),
+        },
+        breaker_failures: failures,
+        breaker_cooldown: COOLDOWN,
+    }
+}
+
+#[tokio::test]
+async fn test_calls_are_retried_until_the_breaker_opens() {
+    let outbound = Outbound::new("embeddings", policy(5, 3));
+    let calls = AtomicU32::new(0);
+    let failing = || async {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err::<(), _>(anyhow!("the provider is down"))
+    };
+    let error = outbound.call(failing).await.unwrap_err();
+    assert_eq!(error.to_string(), "the provider is down");
+    // three of the five attempts, when the breaker opened
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(outbound.failures.get(), 3);
+
+    // refused without a call while it's open
+    let error = outbound.call(failing).await.unwrap_err();
+    assert!(
+        error.to_string().contains("embeddings is failing"),
+        "{}",
+        error
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(outbound.rejected.get(), 1);
+
+    tokio::time::sleep(COOLDOWN * 2).await;
+    let recovered = outbound.call(|| async { Ok(7) }).await.unwrap();
+    assert_eq!(recovered, 7);
+    assert_eq!(outbound.breaker.state(), BreakerState::Closed);
+}
+
+#[tokio::test]
+async fn test_slow_calls_time_out() {
+    let outbound = Outbound::new("embeddings", policy(2, 5));
+    let attempts = AtomicU32::new(0);
+    let result = outbound
+        .call(|| async {
+            // the first attempt hangs, the retry doesn't
+            if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+            Ok("done")
+        })
+        .await;
+    assert_eq!(result.unwrap(), "done");
+    assert_eq!(outbound.failures.get(), 1);
+}
+
+#[tokio::test]
+async fn test_breaker_state_is_rendered_for_metrics() {
+    let outbound = Arc::new(Outbound::new("embeddings", policy(1, 1)));
+    let _ = outbound
+        .call(|| async { Err::<(), _>(anyhow!("down")) })
+        .await;
+    let mut out = Metrics::default().render(&[]);
+    outbound::render(&mut out, &[outbound]);
+    assert!(
+        out.contains("mesh_outbound_breaker_state{service=\"embeddings\"} 2"),
+        "{}",
+        out
+    );
+    assert!(out.contains("mesh_outbound_breaker_trips_total{service=\"embeddings\"} 1"));
+    assert!(out.contains("mesh_outbound_failures_total{service=\"embeddings\"} 1"));
+}
@@ -0,0 +1,138 @@
+#![cfg(feature = "sqlite")]
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use serde_json::{json, Value};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::Storage;
+use standalone::AppState;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const SOURCE: &str =
+    "struct Greeter;\n\nfn greet(name: &str) -> String {\n    format!(\"hello {}\", name)\n}\n";
+
+async fn parse_tree(storage: Arc<SqliteStorage>, body: Value) -> (StatusCode, Value) {
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 64);
+    // the checkout the body names is one the server may read
+    state.allowed_roots = body["repo_path"]
+        .as_str()
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+    let request = Request::post("/parse-tree")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn names(body: &Value) -> Vec<&str> {
+    body["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|n| n["name"].as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_source_is_parsed_without_storing_it() {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let (status, body) = parse_tree(
+        storage.clone(),
+        json!({ "file": "src/lib.rs", "source": SOURCE }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let sexp = body["sexp"].as_str().unwrap();
+    assert!(sexp.starts_with("(source_file"), "{}", sexp);
+    for kind in [
+        "struct_item",
+        "function_item",
+        "parameters",
+        "macro_invocation",
+    ] {
+        assert!(sexp.contains(kind), "no {} in {}", kind, sexp);
+    }
+    assert!(names(&body).contains(&"greet"), "{}", body);
+    assert!(storage.repos().await.unwrap().is_empty());
+    let (nodes, _) = storage.load_graph(None).await.unwrap();
+    assert!(nodes.is_empty());
+}
+
+#[tokio::test]
+async fn test_file_is_read_from_the_repo() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    std::fs::write(root.join("main.rs"), SOURCE).unwrap();
+    std::fs::write(root.join("other.rs"), "fn other() {}\n").unwrap();
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+
+    let (status, body) = parse_tree(
+        storage.clone(),
+        json!({ "repo_path": root, "file": "main.rs" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body["sexp"].as_str().unwrap().contains("function_item"));
+    let names = names(&body);
+    assert!(names.contains(&"greet"), "{:?}", names);
+    assert!(!names.contains(&"other"), "{:?}", names);
+
+    let (status, _) = parse_tree(storage, json!({ "repo_path": root, "file": "missing.rs" })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_file_without_a_grammar_is_refused() {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let (status, _) = parse_tree(storage, json!({ "file": "notes.txt", "source": "hello" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_piped_source_streams_its_nodes() {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let state = AppState::new(storage.clone(), LanguageRegistry::new(), 64);
+    let app = standalone::router(Arc::new(state));
+    let source = "class Greeter:\n    def greet(self, name):\n        return 'hello ' + name\n";
+    let request = Request::post("/parse-stream?lang=python&repo=acme/app")
+        .body(Body::from(source))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let lines: Vec<Value> = std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let greet = lines
+        .iter()
+        .find(|l| l["name"] == "greet")
+        .expect("no greet line");
+    assert_eq!(greet["type"], "node");
+    assert_eq!(greet["kind"], "Function");
+    assert_eq!(greet["file"], "stdin.py");
+    assert_eq!(greet["repo_id"], "acme/app");
+    assert!(lines.iter().any(|l| l["name"] == "Greeter"));
+    let (nodes, _) = storage.load_graph(None).await.unwrap();
+    assert!(nodes.is_empty());
+
+    // a language nothing parses is refused
+    let request = Request::post("/parse-stream?lang=klingon")
+        .body(Body::from(source))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use common::git;
+use serde_json::json;
+use standalone::lang::LanguageRegistry;
+use standalone::paths::PathMap;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{repo_id, Storage};
+use standalone::AppState;
+use std::fs;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+#[test]
+fn test_prefix_is_stripped_by_whole_components() {
+    let map = PathMap::new(Some("/srv/app"), Some("/home/dev/app/"));
+    assert_eq!(map.map("/srv/app/src/main.rs"), "/home/dev/app/src/main.rs");
+    assert_eq!(map.map("/srv/app"), "/home/dev/app");
+    // not under the stripped directory, so only added to
+    assert_eq!(
+        map.map("/srv/apps/main.rs"),
+        "/home/dev/app/srv/apps/main.rs"
+    );
+
+    let strip = PathMap::new(Some("/srv/app"), None);
+    assert_eq!(strip.map("/srv/app/src/main.rs"), "src/main.rs");
+    assert_eq!(strip.map("/elsewhere/main.rs"), "/elsewhere/main.rs");
+    assert!(PathMap::default().is_identity());
+    assert_eq!(PathMap::default().map("/srv/app/x.rs"), "/srv/app/x.rs");
+}
+
+#[tokio::test]
+async fn test_stored_paths_are_normalized() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::create_dir(root.join("src")).unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {\n    greet();\n}\n").unwrap();
+    fs::write(root.join("src/greet.rs"), "fn greet() {}\n").unwrap();
+    git(&root, &["init", "-q", "-b", "main"]);
+    git(&root, &["add", "."]);
+    git(&root, &["commit", "-q", "-m", "init"]);
+    let root_str = root.to_str().unwrap();
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.clone()];
+    state.path_map = PathMap::new(Some(root_str), Some("/home/dev/app"));
+    let request = Request::post("/ingest")
+        .header("Content-Type", "application/json")
+        .body(Body::from(json!({ "repo_path": root }).to_string()))
+        .unwrap();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (nodes, _) = storage
+        .load_graph(Some(&repo_id("", root_str)))
+        .await
+        .unwrap();
+    assert!(!nodes.is_empty());
+    for node in &nodes {
+        assert!(
+            node.file.starts_with("/home/dev/app/src/"),
+            "{} is not normalized",
+            node.file
+        );
+        assert!(!node.file.contains(root_str), "{}", node.file);
+    }
+}
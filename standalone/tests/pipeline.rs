@@ -0,0 +1,70 @@
+use standalone::pipeline::{Feed, Pipeline};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_slow_consumer_holds_the_producer_back() {
+    const BOUND: usize = 4;
+    let produced = Arc::new(AtomicUsize::new(0));
+    let counter = produced.clone();
+    let mut pipeline = Pipeline::spawn(BOUND, move |feed| {
+        for i in 0..50 {
+            if !feed.send(i) {
+                break;
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+
+    let mut consumed = 0;
+    while let Some(i) = pipeline.next().await {
+        assert_eq!(i, consumed);
+        consumed += 1;
+        // whatever was sent and not yet taken is in the channel
+        let ahead = produced.load(Ordering::SeqCst).saturating_sub(consumed);
+        assert!(ahead <= BOUND, "{} items waiting", ahead);
+        // an artificially slow writer
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+    pipeline.finish().await.unwrap();
+    assert_eq!(consumed, 50);
+    assert_eq!(produced.load(Ordering::SeqCst), 50);
+}
+
+#[tokio::test]
+async fn test_dropping_the_pipeline_stops_the_producer() {
+    let produced = Arc::new(AtomicUsize::new(0));
+    let stopped = Arc::new(AtomicBool::new(false));
+    let (counter, done) = (produced.clone(), stopped.clone());
+    let mut pipeline = Pipeline::spawn(2, move |feed| {
+        while feed.send(()) {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+        done.store(true, Ordering::SeqCst);
+    });
+    for _ in 0..3 {
+        pipeline.next().await.unwrap();
+    }
+    drop(pipeline);
+
+    for _ in 0..500 {
+        if stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+    assert!(stopped.load(Ordering::SeqCst));
+    assert!(produced.load(Ordering::SeqCst) <= 3 + 2);
+}
+
+#[tokio::test]
+async fn test_a_panicking_producer_fails_the_pipeline() {
+    let mut pipeline = Pipeline::spawn(1, |feed: Feed<u32>| {
+        feed.send(1);
+        panic!("parser blew up");
+    });
+    assert_eq!(pipeline.next().await, Some(1));
+    assert_eq!(pipeline.next().await, None);
+    assert!(pipeline.finish().await.is_err());
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use standalone::projects::{detect, PROJECT_KIND, PROJECT_META};
+use standalone::storage::{EdgeRecord, NodeRecord};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const FILES: &[&str] = &[
+    "crates/core/src/lib.rs",
+    "crates/core/src/parse.rs",
+    "crates/cli/src/main.rs",
+    "crates/cli/tests/cli.rs",
+    "scripts/release.py",
+];
+
+fn node(kind: &str, name: &str, file: &str) -> NodeRecord {
+    NodeRecord {
+        repo_id: "acme/mono".to_string(),
+        id: format!("{}-{}", kind, file).to_lowercase(),
+        ..common::node(kind, name, file)
+    }
+}
+
+/// A checkout with two Cargo projects and a script outside of both; the
+/// files are stored under the checkout's `acme/mono` prefix.
+fn monorepo(root: &Path) -> (Vec<NodeRecord>, Vec<NodeRecord>, Vec<EdgeRecord>) {
+    for file in FILES {
+        let path = root.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+    for krate in ["crates/core", "crates/cli"] {
+        std::fs::write(root.join(krate).join("Cargo.toml"), "[package]\n").unwrap();
+    }
+    let mut files: Vec<NodeRecord> = FILES
+        .iter()
+        .map(|f| node("File", f, &format!("acme/mono/{}", f)))
+        .collect();
+    let tagged = files
+        .iter_mut()
+        .map(|n| {
+            let rel = n.file.trim_start_matches("acme/mono/").to_string();
+            (n, rel)
+        })
+        .collect();
+    let (projects, edges) = detect(root, "acme/mono", tagged, &[]);
+    (files, projects, edges)
+}
+
+#[test]
+fn test_two_cargo_projects_are_grouped_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    let (files, projects, edges) = monorepo(dir.path());
+
+    assert_eq!(projects.len(), 2);
+    let roots: Vec<&str> = projects.iter().map(|p| p.meta["root"].as_str()).collect();
+    assert_eq!(roots, vec!["crates/cli", "crates/core"]);
+    for project in &projects {
+        assert_eq!(project.kind, PROJECT_KIND);
+        assert_eq!(project.meta["manifest"], "Cargo.toml");
+    }
+    assert_eq!(projects[1].name, "core");
+    assert_eq!(projects[1].file, "acme/mono/crates/core/Cargo.toml");
+
+    let mut grouped: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for edge in &edges {
+        assert_eq!(edge.kind, "CONTAINS");
+        let project = projects.iter().find(|p| p.id == edge.source).unwrap();
+        let file = files.iter().find(|f| f.id == edge.target).unwrap();
+        grouped
+            .entry(&project.name)
+            .or_default()
+            .push(file.name.as_str());
+    }
+    assert_eq!(
+        grouped,
+        BTreeMap::from([
+            (
+                "cli",
+                vec!["crates/cli/src/main.rs", "crates/cli/tests/cli.rs"]
+            ),
+            (
+                "core",
+                vec!["crates/core/src/lib.rs", "crates/core/src/parse.rs"]
+            ),
+        ])
+    );
+
+    assert_eq!(files[0].meta[PROJECT_META], "crates/core");
+    assert_eq!(files[2].meta[PROJECT_META], "crates/cli");
+    // above every manifest
+    assert!(!files[4].meta.contains_key(PROJECT_META));
+}
+
+#[test]
+fn test_top_level_manifest_owns_the_rest() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+    let (files, projects, _) = monorepo(dir.path());
+
+    assert_eq!(projects.len(), 3);
+    let top = projects.iter().find(|p| p.meta["root"] == ".").unwrap();
+    assert_eq!(top.name, "mono");
+    assert_eq!(top.meta["manifest"], "package.json");
+    assert_eq!(files[4].meta[PROJECT_META], ".");
+    assert_eq!(files[0].meta[PROJECT_META], "crates/core");
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_query_scoped_to_a_project() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (files, projects, edges) = monorepo(dir.path());
+    let mut functions = Vec::new();
+    for (name, file) in [("parse", &files[1]), ("run", &files[2])] {
+        let mut function = node("Function", name, &file.file);
+        function
+            .meta
+            .insert("complexity".to_string(), "3".to_string());
+        functions.push(function);
+    }
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    for nodes in [&files, &projects, &functions] {
+        storage.upsert_nodes(nodes).await.unwrap();
+    }
+    storage.upsert_edges(&edges).await.unwrap();
+    let app = standalone::router(Arc::new(AppState::new(
+        Arc::new(storage),
+        LanguageRegistry::new(),
+        16,
+    )));
+
+    let query = |body: Value| {
+        let app = app.clone();
+        async move {
+            let request = Request::post("/graph/query")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+    let (status, body) = query(json!({
+        "query": "complexity-hotspots",
+        "repo": "acme/mono",
+        "project": "crates/core",
+    }))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&str> = body["rows"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["parse"]);
+
+    let (status, _) =
+        query(json!({"query": "complexity-hotspots", "project": "crates/core"})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = query(json!({
+        "query": "complexity-hotspots",
+        "repo": "acme/mono",
+        "project": "crates/web",
+    }))
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,284 @@
+mod common;
+
+use serde_json::{json, Map, Value};
+use standalone::query::{
+    filter_kinds, find_template, paginate, templates, validate, writes, PageError,
+};
+use std::collections::HashSet;
+
+fn params(value: Value) -> Map<String, Value> {
+    value.as_object().unwrap().clone()
+}
+
+#[test]
+fn test_builtin_templates_are_registered() {
+    for key in [
+        "callers-of-function",
+        "files-importing-module",
+        "symbols-in-file",
+        "file-dependencies",
+        "complexity-hotspots",
+    ] {
+        assert!(find_template(key).is_some(), "missing template {}", key);
+    }
+    assert!(templates().len() >= 3);
+}
+
+#[test]
+fn test_validate_params() {
+    let template = find_template("callers-of-function").unwrap();
+    assert!(validate(template, &params(json!({"name": "main"}))).is_ok());
+    assert!(validate(template, &params(json!({}))).is_err());
+    assert!(validate(template, &params(json!({"name": 3}))).is_err());
+    assert!(validate(template, &params(json!({"name": "main", "x": 1}))).is_err());
+}
+
+fn rows(n: usize) -> Vec<Value> {
+    // reversed, so paging can't rely on the input order
+    (0..n)
+        .rev()
+        .map(|i| {
+            let kind = if i % 3 == 0 { "Class" } else { "Function" };
+            json!({"kind": kind, "name": format!("f{:03}", i)})
+        })
+        .collect()
+}
+
+#[test]
+fn test_pages_visit_every_row_once() {
+    let mut seen = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = paginate("q", rows(47), Some(10), cursor.as_deref()).unwrap();
+        assert!(page.rows.len() <= 10);
+        seen.extend(page.rows);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(seen.len(), 47);
+    let names: HashSet<&str> = seen.iter().map(|r| r["name"].as_str().unwrap()).collect();
+    assert_eq!(names.len(), 47);
+    let mut sorted = seen.clone();
+    sorted.sort_by_key(|r| r.to_string());
+    assert_eq!(seen, sorted);
+}
+
+#[test]
+fn test_cursor_is_checked_against_query_and_results() {
+    let first = paginate("q", rows(20), Some(5), None).unwrap();
+    let cursor = first.next_cursor.unwrap();
+    assert!(paginate("q", rows(20), Some(5), Some(&cursor)).is_ok());
+    assert_eq!(
+        paginate("q", rows(21), Some(5), Some(&cursor)).err(),
+        Some(PageError::Changed)
+    );
+    assert!(matches!(
+        paginate("other", rows(20), Some(5), Some(&cursor)),
+        Err(PageError::Invalid(_))
+    ));
+    assert!(matches!(
+        paginate("q", rows(20), Some(5), Some("zz")),
+        Err(PageError::Invalid(_))
+    ));
+    assert!(paginate("q", rows(20), Some(0), None).is_err());
+}
+
+#[test]
+fn test_writes_looks_past_literals_and_quoted_names() {
+    assert!(writes("INSERT INTO nodes VALUES (1)"));
+    assert!(writes("MATCH (n) SET n.name = 'x'"));
+    assert!(writes("match (n) detach delete n"));
+    assert!(!writes("SELECT name FROM nodes WHERE body = 'DELETE me'"));
+    assert!(!writes("MATCH (n) RETURN n.`set` AS updated_at"));
+}
+
+#[test]
+fn test_filter_kinds() {
+    let functions = filter_kinds(rows(9), &["Function".to_string()]).unwrap();
+    assert_eq!(functions.len(), 6);
+    assert_eq!(filter_kinds(rows(9), &[]).unwrap().len(), 9);
+    let no_kind = vec![json!({"name": "main"})];
+    assert!(filter_kinds(no_kind, &["Function".to_string()]).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_missing_parameter_is_bad_request() {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let state = Arc::new(AppState::new(storage, LanguageRegistry::new(), 16));
+    let request = Request::post("/graph/query")
+        .header("Content-Type", "application/json")
+        .body(Body::from(r#"{"query": "symbols-in-file", "params": {}}"#))
+        .unwrap();
+
+    let response = standalone::router(state).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[cfg(feature = "sqlite")]
+mod paging {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{NodeRecord, Storage};
+    use standalone::AppState;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn node(kind: &str, name: &str) -> NodeRecord {
+        NodeRecord {
+            id: format!("{}-{}", kind, name).to_lowercase(),
+            start: 1,
+            end: 2,
+            ..crate::common::node(kind, name, "src/lib.rs")
+        }
+    }
+
+    async fn page(app: &axum::Router, cursor: Option<&str>) -> (StatusCode, Value) {
+        let body = json!({
+            "query": "symbols-in-file",
+            "params": {"file": "src/lib.rs"},
+            "node_kinds": ["Function"],
+            "limit": 4,
+            "cursor": cursor,
+        });
+        let request = Request::post("/graph/query")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    async fn setup() -> (Arc<SqliteStorage>, axum::Router) {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        for i in 0..10 {
+            storage
+                .upsert_node(&node("Function", &format!("f{}", i)))
+                .await
+                .unwrap();
+        }
+        storage.upsert_node(&node("Class", "Widget")).await.unwrap();
+        let state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+        (storage, standalone::router(Arc::new(state)))
+    }
+
+    #[tokio::test]
+    async fn test_query_pages_through_every_function() {
+        let (_storage, app) = setup().await;
+        let mut names = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let (status, body) = page(&app, cursor.as_deref()).await;
+            assert_eq!(status, StatusCode::OK);
+            for row in body["rows"].as_array().unwrap() {
+                assert_eq!(row["kind"], "Function");
+                names.push(row["name"].as_str().unwrap().to_string());
+            }
+            match body["next_cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+        assert_eq!(names.len(), 10);
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_cursor_after_the_graph_changed_is_a_conflict() {
+        let (storage, app) = setup().await;
+        let (_, first) = page(&app, None).await;
+        let cursor = first["next_cursor"].as_str().unwrap().to_string();
+        storage
+            .upsert_node(&node("Function", "late"))
+            .await
+            .unwrap();
+
+        let (status, body) = page(&app, Some(&cursor)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["kind"], "conflict");
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod refs {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{NodeRecord, Storage};
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn post(app: &axum::Router, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    async fn symbols(app: &axum::Router, git_ref: Option<&str>) -> Vec<String> {
+        let body = json!({
+            "query": "symbols-in-file",
+            "params": {"file": "src/lib.rs"},
+            "repo": "acme/app",
+            "ref": git_ref,
+        });
+        let (status, body) = post(app, "/graph/query", body).await;
+        assert_eq!(status, StatusCode::OK);
+        body["rows"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_two_refs_are_queryable_and_cleared_independently() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        for (repo_id, name) in [("acme/app@main", "render"), ("acme/app@feature", "redraw")] {
+            let node = NodeRecord {
+                start: 1,
+                end: 2,
+                ..crate::common::function_in(repo_id, name)
+            };
+            storage.upsert_node(&node).await.unwrap();
+        }
+        let state = AppState::new(storage, LanguageRegistry::new(), 16);
+        let app = standalone::router(Arc::new(state));
+
+        assert_eq!(symbols(&app, Some("main")).await, vec!["render"]);
+        assert_eq!(symbols(&app, Some("feature")).await, vec!["redraw"]);
+
+        let clear = json!({"repo_id": "acme/app", "ref": "feature", "confirm": "acme/app"});
+        let (status, _) = post(&app, "/clear", clear).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(symbols(&app, Some("feature")).await.is_empty());
+        assert_eq!(symbols(&app, Some("main")).await, vec!["render"]);
+
+        let (status, _) = post(&app, "/clear", json!({"ref": "main"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
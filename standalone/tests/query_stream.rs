@@ -0,0 +1,134 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use common::function;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use standalone::auth::{ApiKeys, Auth};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::{SqliteStorage, STREAM_BUFFER};
+use standalone::storage::{EdgeRecord, Storage};
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const CALLERS: usize = 2000;
+const ADMIN: &str = "admin-key";
+
+async fn app() -> axum::Router {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let mut nodes = vec![function("target")];
+    let mut edges = Vec::new();
+    for i in 0..CALLERS {
+        let caller = function(&format!("caller{:04}", i));
+        edges.push(EdgeRecord {
+            repo_id: "acme/app".to_string(),
+            kind: "CALLS".to_string(),
+            source: caller.id.clone(),
+            target: "function-target".to_string(),
+        });
+        nodes.push(caller);
+    }
+    storage.upsert_nodes(&nodes).await.unwrap();
+    storage.upsert_edges(&edges).await.unwrap();
+    let mut state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    state.allow_raw_cypher = true;
+    state.auth = Some(Arc::new(
+        Auth::new(ApiKeys::new(&[]), &[]).with_admin_keys(ApiKeys::new(&[ADMIN])),
+    ));
+    standalone::router(Arc::new(state))
+}
+
+fn query(body: Value, ndjson: bool) -> Request<Body> {
+    let mut request = Request::post("/graph/query")
+        .header("Content-Type", "application/json")
+        .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN));
+    if ndjson {
+        request = request.header(header::ACCEPT, "application/x-ndjson");
+    }
+    request.body(Body::from(body.to_string())).unwrap()
+}
+
+#[tokio::test]
+async fn test_large_result_streams_row_by_row() {
+    let callers = json!({ "query": "callers-of-function", "params": { "name": "target" } });
+    let response = app().await.oneshot(query(callers, true)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/x-ndjson"
+    );
+
+    let mut chunks = response.into_body().into_data_stream();
+    let first = chunks.next().await.unwrap().unwrap();
+    // the first row is sent on its own, long before the rest are read
+    assert_eq!(first.iter().filter(|b| **b == b'\n').count(), 1);
+    let mut body = first.to_vec();
+    let mut count = 1;
+    while let Some(chunk) = chunks.next().await {
+        body.extend_from_slice(&chunk.unwrap());
+        count += 1;
+    }
+    assert!(count > CALLERS / STREAM_BUFFER, "{} chunks", count);
+
+    let rows: Vec<Value> = String::from_utf8(body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), CALLERS);
+    assert_eq!(rows[0]["name"], "caller0000");
+    assert!(rows.iter().all(|row| row.get("error").is_none()));
+}
+
+#[tokio::test]
+async fn test_json_stays_the_default() {
+    let callers = json!({ "query": "callers-of-function", "params": { "name": "target" } });
+    let response = app().await.oneshot(query(callers, false)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["rows"].as_array().unwrap().len(), CALLERS);
+
+    // paging needs the whole result, so it can't be streamed
+    let paged =
+        json!({ "query": "callers-of-function", "params": { "name": "target" }, "limit": 10 });
+    let response = app().await.oneshot(query(paged, true)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_failure_mid_stream_ends_with_an_error_line() {
+    // `json('nope')` only fails once the fifth row is stepped to
+    let statement = "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 10)
+                     SELECT x, CASE WHEN x = 5 THEN json('nope') ELSE x END AS v FROM n";
+    let response = app()
+        .await
+        .oneshot(query(json!({ "cypher": statement }), true))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let lines: Vec<Value> = String::from_utf8(body.to_vec())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 5, "{:?}", lines);
+    assert_eq!(lines[3]["x"], 4);
+    assert!(lines[4]["error"]
+        .as_str()
+        .unwrap()
+        .contains("malformed JSON"));
+
+    // a statement that fails before its first row gets an error status instead
+    let response = app()
+        .await
+        .oneshot(query(json!({ "cypher": "SELECT * FROM nowhere" }), true))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
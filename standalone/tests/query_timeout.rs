@@ -0,0 +1,281 @@
+#![cfg(feature = "sqlite")]
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use serde_json::{json, Map, Value};
+use standalone::auth::{ApiKeys, Auth};
+use standalone::lang::{Diagnostic, LanguageRegistry};
+use standalone::query::QueryTemplate;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{EdgeRecord, NodeRecord, RepoRecord, Storage, Transaction};
+use standalone::AppState;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+
+/// A backend whose template queries never finish unless they're cancelled.
+struct Stuck {
+    db: SqliteStorage,
+    cancelled: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl Storage for Stuck {
+    fn backend(&self) -> &'static str {
+        "stuck"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.db.ping().await
+    }
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        self.db.upsert_node(node).await
+    }
+
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        self.db.upsert_edge(edge).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        self.db.begin().await
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        self.db.file_node_ids(repo_id, file).await
+    }
+
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.db.delete_nodes(repo_id, ids).await
+    }
+
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        self.db.delete_file(repo_id, file).await
+    }
+
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.db.clear(repo_id).await
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.db.graph_size(repo_id).await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        self.db.graph_version(repo_id).await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        self.db.repo_hash(repo_url).await
+    }
+
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        self.db.set_repo_hash(repo_url, hash).await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        self.db.file_hashes(repo_id).await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        self.db.set_file_hashes(repo_id, hashes).await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.db
+            .replace_diagnostics(repo_id, files, diagnostics)
+            .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        self.db.find_repo(name).await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        self.db.node(repo_id, id).await
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        self.db.record_ingest(repo).await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.db.repos().await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        self.db.load_graph(repo_id).await
+    }
+
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.db.dangling_edges(repo_id, after, limit).await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.db.orphan_nodes(repo_id, after, limit).await
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.db.duplicate_nodes(repo_id, after, limit).await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        self.db.delete_edges(edges).await
+    }
+
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.db.dedupe_nodes(repo_id, ids).await
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.db.query(template, params).await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        self.db.query_raw(statement, params).await
+    }
+
+    async fn query_cancellable(
+        &self,
+        _: &QueryTemplate,
+        _: &Map<String, Value>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Value>> {
+        cancel.cancelled().await;
+        self.cancelled.store(true, Ordering::SeqCst);
+        anyhow::bail!("query cancelled")
+    }
+}
+
+const ADMIN: &str = "admin-key";
+
+async fn send(app: &axum::Router, body: Value) -> (StatusCode, Value) {
+    let request = Request::post("/graph/query")
+        .header("Content-Type", "application/json")
+        .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN))
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn stream(app: &axum::Router, body: Value) -> (StatusCode, String) {
+    let request = Request::post("/graph/query")
+        .header("Content-Type", "application/json")
+        .header(header::ACCEPT, "application/x-ndjson")
+        .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN))
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_a_query_past_the_timeout_is_cancelled_in_the_backend() {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let storage = Stuck {
+        db: SqliteStorage::open_in_memory().unwrap(),
+        cancelled: cancelled.clone(),
+    };
+    let mut state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    state.query_timeout = Some(Duration::from_millis(50));
+    let app = standalone::router(Arc::new(state));
+
+    let query = json!({ "query": "callers-of-function", "params": { "name": "helper" } });
+    let (status, body) = send(&app, query.clone()).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT, "{}", body);
+    assert_eq!(body["kind"], "timeout");
+    assert!(body["elapsed_ms"].as_u64().unwrap() >= 50);
+    assert!(cancelled.load(Ordering::SeqCst));
+
+    // a request can ask to give up sooner, but not later
+    cancelled.store(false, Ordering::SeqCst);
+    let mut sooner = query;
+    sooner["timeout_ms"] = json!(10_000);
+    let (status, body) = send(&app, sooner).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert!(body["elapsed_ms"].as_u64().unwrap() < 10_000);
+    assert!(cancelled.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_a_runaway_statement_is_interrupted_and_the_connection_let_go() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let mut state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    state.allow_raw_cypher = true;
+    state.auth = Some(Arc::new(
+        Auth::new(ApiKeys::new(&[]), &[]).with_admin_keys(ApiKeys::new(&[ADMIN])),
+    ));
+    state.query_timeout = Some(Duration::from_millis(100));
+    let app = standalone::router(Arc::new(state));
+
+    let endless =
+        "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT count(*) FROM c";
+    let (status, body) = send(&app, json!({ "cypher": endless })).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT, "{}", body);
+    assert_eq!(body["kind"], "timeout");
+
+    let (status, body) = send(&app, json!({ "cypher": "SELECT 1 AS one" })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["rows"][0]["one"], 1);
+}
+
+#[tokio::test]
+async fn test_a_streamed_statement_past_the_timeout_is_dropped_with_a_504() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let mut state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    state.allow_raw_cypher = true;
+    state.auth = Some(Arc::new(
+        Auth::new(ApiKeys::new(&[]), &[]).with_admin_keys(ApiKeys::new(&[ADMIN])),
+    ));
+    state.query_timeout = Some(Duration::from_millis(100));
+    let app = standalone::router(Arc::new(state));
+
+    let endless =
+        "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT count(*) FROM c";
+    let (status, body) = stream(&app, json!({ "cypher": endless })).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT, "{}", body);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["kind"], "timeout");
+
+    // the dropped stream let go of the connection
+    let (status, body) = stream(&app, json!({ "cypher": "SELECT 1 AS one" })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body, "{\"one\":1}\n");
+}
@@ -0,0 +1,105 @@
+#![cfg(feature = "sqlite")]
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use serde_json::{json, Value};
+use standalone::auth::{ApiKeys, Auth};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+async fn send(app: &axum::Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn post(path: &str, body: Value) -> Request<Body> {
+    Request::post(path)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+const ADMIN: &str = "admin-key";
+
+fn query(body: Value) -> Request<Body> {
+    Request::post("/graph/query")
+        .header("Content-Type", "application/json")
+        .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN))
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_readonly_refuses_writes_and_serves_reads() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+    state.readonly = true;
+    let app = standalone::router(Arc::new(state));
+
+    let path = dir.path().display().to_string();
+    for (route, body) in [
+        ("/process", json!({ "repo_path": path })),
+        ("/ingest-path", json!({ "path": path })),
+        (
+            "/fetch-repo",
+            json!({ "repo_url": "https://example.com/acme/app" }),
+        ),
+        ("/clear", json!({})),
+        ("/webhook", json!({})),
+        ("/snapshots", json!({ "repo": "acme/app" })),
+        ("/admin/compact", json!({})),
+    ] {
+        let (status, body) = send(&app, post(route, body)).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED, "{}", route);
+        assert_eq!(body["kind"], "read_only", "{}", route);
+    }
+
+    let (status, body) = send(&app, Request::get("/repos").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = send(
+        &app,
+        Request::get("/snapshots?repo=acme/app")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED);
+    let (status, body) = send(&app, post("/search", json!({ "query": "main" }))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn test_readonly_runs_queries_but_refuses_raw_writes() {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+    state.readonly = true;
+    state.allow_raw_cypher = true;
+    state.auth = Some(Arc::new(
+        Auth::new(ApiKeys::new(&[]), &[]).with_admin_keys(ApiKeys::new(&[ADMIN])),
+    ));
+    let app = standalone::router(Arc::new(state));
+
+    let template = json!({ "query": "callers-of-function", "params": { "name": "helper" } });
+    let (status, body) = send(&app, query(template)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["rows"], json!([]));
+    let (status, body) = send(&app, query(json!({ "cypher": "SELECT 1 AS one" }))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["rows"][0]["one"], 1);
+
+    for statement in [
+        "DELETE FROM nodes",
+        "drop table edges",
+        "MATCH (n) DETACH DELETE n",
+    ] {
+        let (status, body) = send(&app, query(json!({ "cypher": statement }))).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED, "{}", statement);
+        assert_eq!(body["kind"], "read_only", "{}", statement);
+    }
+}
@@ -0,0 +1,119 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use serde_json::{json, Value};
+use standalone::coverage::TESTS;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{EdgeRecord, NodeRecord, Storage};
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn function(name: &str, start: usize, body: &str) -> NodeRecord {
+    NodeRecord {
+        file: "src/parser.rs".to_string(),
+        start,
+        end: start + body.lines().count(),
+        body: body.to_string(),
+        ..common::function(name)
+    }
+}
+
+fn edge(kind: &str, source: &str, target: &str) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: kind.to_string(),
+        source: format!("function-{}", source),
+        target: format!("function-{}", target),
+    }
+}
+
+async fn reanalyze(app: &axum::Router, repo: &str) -> (StatusCode, Value) {
+    let request = Request::post("/reanalyze")
+        .header("Content-Type", "application/json")
+        .body(Body::from(json!({ "repo": repo }).to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_reanalyze_rederives_call_edges_and_keeps_the_nodes() {
+    let nodes = vec![
+        function(
+            "parse",
+            0,
+            "pub fn parse(s: &str) -> Vec<String> {\n    tokenize(s)\n}\n",
+        ),
+        function(
+            "tokenize",
+            4,
+            "fn tokenize(s: &str) -> Vec<String> {\n    vec![s.to_string()]\n}\n",
+        ),
+        function(
+            "render",
+            8,
+            "pub fn render(s: &str) -> String {\n    s\n}\n",
+        ),
+        function(
+            "test_parse",
+            12,
+            "#[test]\nfn test_parse() {\n    assert_eq!(parse(\"a\").len(), 1);\n}\n",
+        ),
+    ];
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    storage.upsert_nodes(&nodes).await.unwrap();
+    // what an ingest under older rules left: a test linked to what it
+    // doesn't call, and a parsed call edge that isn't derived
+    storage
+        .upsert_edges(&[
+            edge(TESTS, "test_parse", "render"),
+            edge("CALLS", "parse", "tokenize"),
+        ])
+        .await
+        .unwrap();
+    let state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let (status, body) = reanalyze(&app, "acme/app").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["removed"], 1);
+    assert_eq!(body["added"], 2);
+
+    let (stored, edges) = storage.load_graph(Some("acme/app")).await.unwrap();
+    let mut edges: Vec<String> = edges
+        .iter()
+        .map(|e| format!("{} {} {}", e.source, e.kind, e.target))
+        .collect();
+    edges.sort();
+    assert_eq!(
+        edges,
+        [
+            "function-parse CALLS function-tokenize",
+            "function-test_parse TESTS function-parse",
+            "function-test_parse TESTS function-tokenize",
+        ]
+    );
+    let mut stored: Vec<(String, String)> = stored.into_iter().map(|n| (n.id, n.body)).collect();
+    stored.sort();
+    let mut expected: Vec<(String, String)> = nodes.into_iter().map(|n| (n.id, n.body)).collect();
+    expected.sort();
+    assert_eq!(stored, expected);
+
+    // nothing changed since, so nothing's rewritten
+    let (_, body) = reanalyze(&app, "acme/app").await;
+    assert_eq!(
+        (body["removed"].as_u64(), body["added"].as_u64()),
+        (Some(0), Some(0))
+    );
+
+    let (status, body) = reanalyze(&app, "acme/missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["kind"], "not_found");
+}
@@ -0,0 +1,373 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use common::function;
+use serde_json::{Map, Value};
+use standalone::clone::RetryPolicy;
+use standalone::lang::Diagnostic;
+use standalone::query::QueryTemplate;
+use standalone::storage::reconnect::{is_transient, Connector, PoolConfig, ReconnectingStorage};
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{EdgeRecord, NodeRecord, RepoRecord, Storage, Transaction};
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// One connection to a database that outlives it: once `dropped` is set,
+/// every operation fails as a reset socket would.
+struct MockConnection {
+    db: Arc<SqliteStorage>,
+    dropped: Arc<AtomicBool>,
+}
+
+impl MockConnection {
+    fn live(&self) -> Result<&SqliteStorage> {
+        if self.dropped.load(Ordering::SeqCst) {
+            return Err(io::Error::from(io::ErrorKind::ConnectionReset).into());
+        }
+        Ok(&self.db)
+    }
+}
+
+#[async_trait]
+impl Storage for MockConnection {
+    fn backend(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.live()?.ping().await
+    }
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        self.live()?.upsert_node(node).await
+    }
+
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        self.live()?.upsert_edge(edge).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        self.live()?.begin().await
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        self.live()?.file_node_ids(repo_id, file).await
+    }
+
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.live()?.delete_nodes(repo_id, ids).await
+    }
+
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        self.live()?.delete_file(repo_id, file).await
+    }
+
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.live()?.clear(repo_id).await
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.live()?.graph_size(repo_id).await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        self.live()?.graph_version(repo_id).await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        self.live()?.repo_hash(repo_url).await
+    }
+
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        self.live()?.set_repo_hash(repo_url, hash).await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        self.live()?.file_hashes(repo_id).await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        self.live()?.set_file_hashes(repo_id, hashes).await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.live()?
+            .replace_diagnostics(repo_id, files, diagnostics)
+            .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        self.live()?.find_repo(name).await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        self.live()?.node(repo_id, id).await
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        self.live()?.record_ingest(repo).await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.live()?.repos().await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        self.live()?.load_graph(repo_id).await
+    }
+
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.live()?.dangling_edges(repo_id, after, limit).await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.live()?.orphan_nodes(repo_id, after, limit).await
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.live()?.duplicate_nodes(repo_id, after, limit).await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        self.live()?.delete_edges(edges).await
+    }
+
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.live()?.dedupe_nodes(repo_id, ids).await
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        self.live()?.query(template, params).await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        self.live()?.query_raw(statement, params).await
+    }
+}
+
+/// The mock backend: a database, every connection opened to it, how many
+/// were asked for, and whether new ones are refused.
+#[derive(Clone)]
+struct MockServer {
+    db: Arc<SqliteStorage>,
+    connections: Arc<Mutex<Vec<Arc<AtomicBool>>>>,
+    attempts: Arc<AtomicUsize>,
+    refusing: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    fn new() -> Self {
+        MockServer {
+            db: Arc::new(SqliteStorage::open_in_memory().unwrap()),
+            connections: Default::default(),
+            attempts: Default::default(),
+            refusing: Default::default(),
+        }
+    }
+
+    fn connector(&self) -> Connector {
+        let server = self.clone();
+        Box::new(move || {
+            let server = server.clone();
+            Box::pin(async move {
+                server.attempts.fetch_add(1, Ordering::SeqCst);
+                if server.refusing.load(Ordering::SeqCst) {
+                    return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+                }
+                let dropped = Arc::new(AtomicBool::new(false));
+                server.connections.lock().unwrap().push(dropped.clone());
+                Ok(Arc::new(MockConnection {
+                    db: server.db.clone(),
+                    dropped,
+                }) as Arc<dyn Storage>)
+            })
+        })
+    }
+
+    fn opened(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    /// Drops every open connection, as a restarted server would.
+    fn drop_connections(&self) {
+        for dropped in self.connections.lock().unwrap().iter() {
+            dropped.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+fn pool(max_attempts: u32) -> PoolConfig {
+    PoolConfig {
+        max_connections: 1,
+        connect_timeout: Duration::from_secs(5),
+        retry: RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_dropped_connection_is_reestablished() {
+    let server = MockServer::new();
+    let storage = ReconnectingStorage::connect(server.connector(), &pool(3))
+        .await
+        .unwrap();
+    storage.upsert_node(&function("render")).await.unwrap();
+    assert_eq!(server.opened(), 1);
+
+    server.drop_connections();
+    storage.upsert_node(&function("parse")).await.unwrap();
+    assert_eq!(server.opened(), 2);
+    assert_eq!(storage.graph_size(None).await.unwrap().0, 2);
+    assert_eq!(server.opened(), 2, "the new connection is kept");
+}
+
+#[tokio::test]
+async fn test_retries_are_bounded() {
+    let server = MockServer::new();
+    let storage = ReconnectingStorage::connect(server.connector(), &pool(3))
+        .await
+        .unwrap();
+    server.drop_connections();
+    server.refusing.store(true, Ordering::SeqCst);
+    let err = storage.graph_size(None).await.unwrap_err();
+    assert!(is_transient(&err), "{:#}", err);
+    // the first connection, then one between each of the three attempts
+    assert_eq!(server.attempts.load(Ordering::SeqCst), 3);
+    assert_eq!(server.opened(), 1);
+
+    // back up again
+    server.refusing.store(false, Ordering::SeqCst);
+    assert_eq!(storage.graph_size(None).await.unwrap(), (0, 0));
+    assert_eq!(server.opened(), 2);
+}
+
+#[tokio::test]
+async fn test_other_errors_are_not_retried() {
+    let server = MockServer::new();
+    let storage = ReconnectingStorage::connect(server.connector(), &pool(3))
+        .await
+        .unwrap();
+    let err = storage
+        .query_raw("SELECT * FROM no_such_table", &Map::new())
+        .await
+        .unwrap_err();
+    assert!(!is_transient(&err), "{:#}", err);
+    assert_eq!(server.opened(), 1);
+}
+
+#[tokio::test]
+async fn test_first_connection_is_retried() {
+    let server = MockServer::new();
+    server.refusing.store(true, Ordering::SeqCst);
+    let err = ReconnectingStorage::connect(server.connector(), &pool(2))
+        .await
+        .err()
+        .unwrap();
+    assert!(is_transient(&err), "{:#}", err);
+    assert_eq!(server.attempts.load(Ordering::SeqCst), 2);
+    assert_eq!(server.opened(), 0);
+}
+
+#[tokio::test]
+async fn test_unreachable_backend_answers_queries_with_503() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use standalone::auth::{ApiKeys, Auth};
+    use standalone::lang::LanguageRegistry;
+    use standalone::AppState;
+    use tower::ServiceExt;
+
+    const ADMIN: &str = "admin-key";
+    let server = MockServer::new();
+    let storage = ReconnectingStorage::connect(server.connector(), &pool(3))
+        .await
+        .unwrap();
+    let mut state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    state.allow_raw_cypher = true;
+    state.auth = Some(Arc::new(
+        Auth::new(ApiKeys::new(&[]), &[]).with_admin_keys(ApiKeys::new(&[ADMIN])),
+    ));
+    let app = standalone::router(Arc::new(state));
+    let post = |path: &str, body: Value| {
+        let request = Request::post(path)
+            .header("Content-Type", "application/json")
+            .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let retry_after = response.headers().get(header::RETRY_AFTER).cloned();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            (status, retry_after, body)
+        }
+    };
+
+    // a query the backend rejects is still the query's fault
+    let (status, retry_after, body) = post(
+        "/graph/query",
+        serde_json::json!({ "cypher": "SELECT * FROM no_such_table" }),
+    )
+    .await;
+    assert_ne!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+    assert!(retry_after.is_none());
+
+    server.drop_connections();
+    server.refusing.store(true, Ordering::SeqCst);
+    let before = server.attempts.load(Ordering::SeqCst);
+    let (status, retry_after, body) =
+        post("/references", serde_json::json!({ "name": "render" })).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+    assert_eq!(body["kind"], "unavailable");
+    assert!(retry_after.is_some());
+    // the socket error stays in the log
+    assert!(
+        !body["error"].as_str().unwrap().contains("refused"),
+        "{}",
+        body
+    );
+    // reconnected between each of the three attempts before giving up
+    assert_eq!(server.attempts.load(Ordering::SeqCst) - before, 2);
+
+    server.refusing.store(false, Ordering::SeqCst);
+    let (status, _, body) = post("/references", serde_json::json!({ "name": "render" })).await;
+    // back up, and simply nothing of that name
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use standalone::grep::{COMMENT_KIND, STRING_KIND};
+use standalone::redact::{Redaction, REDACTED};
+use standalone::storage::{kind_key, EdgeRecord, NodeRecord};
+
+const SOURCE: &str = r#"// the staging key is sk-live4242
+pub fn client() -> Client {
+    Client::new("sk-live4242")
+}
+"#;
+
+fn node(kind: &str, name: &str, start: usize, body: &str) -> NodeRecord {
+    NodeRecord {
+        id: kind_key(kind, name, "src/client.rs", start, None),
+        start,
+        end: start,
+        body: body.to_string(),
+        ..common::node(kind, name, "src/client.rs")
+    }
+}
+
+fn patterns() -> Vec<String> {
+    vec![r"sk-[a-z0-9]+".to_string()]
+}
+
+#[test]
+fn test_matches_are_redacted_and_their_nodes_rekeyed() {
+    let mut nodes = vec![
+        node(
+            "Function",
+            "client",
+            1,
+            &SOURCE[SOURCE.find("pub").unwrap()..],
+        ),
+        node(STRING_KIND, "\"sk-live4242\"", 2, "\"sk-live4242\""),
+    ];
+    let secret_id = nodes[1].id.clone();
+    let mut edges = vec![EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: "CONTAINS".to_string(),
+        source: nodes[0].id.clone(),
+        target: secret_id.clone(),
+    }];
+    Redaction::new(&patterns(), &[])
+        .unwrap()
+        .apply("acme/app", &mut nodes, &mut edges);
+
+    assert!(!nodes[0].body.contains("sk-live4242"), "{}", nodes[0].body);
+    assert!(nodes[0].body.contains("Client::new(\"***\")"));
+    assert_eq!(
+        nodes[0].id,
+        kind_key("Function", "client", "src/client.rs", 1, None)
+    );
+    assert_eq!(nodes[1].name, "\"***\"");
+    assert_eq!(nodes[1].body, "\"***\"");
+    assert_eq!(nodes[1].meta[REDACTED], "true");
+    // the id was made from the secret, so it's made again
+    assert_ne!(nodes[1].id, secret_id);
+    assert!(!nodes[1].id.contains("live4242"));
+    assert_eq!(edges[0].target, nodes[1].id);
+}
+
+#[test]
+fn test_text_repos_keep_no_comment_or_string_text() {
+    let redaction = Redaction::new(&[], &["acme/app".to_string()]).unwrap();
+    assert!(redaction.hides_text("acme/app@release"));
+    assert!(!redaction.hides_text("acme/application"));
+    let mut nodes = vec![
+        node(
+            COMMENT_KIND,
+            "// the staging key is sk-live4242",
+            0,
+            "// the staging key is sk-live4242",
+        ),
+        node(STRING_KIND, "\"one\"", 2, "\"one\""),
+        node(STRING_KIND, "\"two\"", 2, "\"two\""),
+        node("Function", "client", 1, "pub fn client() -> Client {}"),
+    ];
+    redaction.apply("acme/app", &mut nodes, &mut []);
+    for text in &nodes[..3] {
+        assert_eq!(text.name, "***");
+        assert_eq!(text.body, "");
+        assert_eq!(text.meta[REDACTED], "true");
+    }
+    // two strings on one line still get an id each
+    assert_ne!(nodes[1].id, nodes[2].id);
+    assert_eq!(nodes[3].body, "pub fn client() -> Client {}");
+    assert!(!nodes[3].meta.contains_key(REDACTED));
+}
+
+#[test]
+fn test_invalid_pattern_is_refused() {
+    assert!(Redaction::new(&["sk-(".to_string()], &[]).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_configured_secret_never_reaches_the_store() {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::json;
+    use standalone::captures::CustomQueries;
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join("src/client.rs"), SOURCE).unwrap();
+
+    let mut queries = CustomQueries::default();
+    queries.index_text().unwrap();
+    let mut languages = LanguageRegistry::new();
+    languages.set_custom_queries(queries);
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage.clone(), languages, 1024);
+    state.allowed_roots = vec![root.clone()];
+    state.redaction = Some(Arc::new(Redaction::new(&patterns(), &[]).unwrap()));
+    let app = standalone::router(Arc::new(state));
+    let request = Request::post("/ingest-path")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({"path": root, "repo_id": "acme/app"}).to_string(),
+        ))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (nodes, _) = storage.load_graph(Some("acme/app")).await.unwrap();
+    let strings: Vec<&NodeRecord> = nodes.iter().filter(|n| n.kind == STRING_KIND).collect();
+    assert_eq!(strings.len(), 1);
+    assert_eq!(strings[0].name, "\"***\"");
+    assert_eq!(strings[0].meta[REDACTED], "true");
+    for node in &nodes {
+        let stored = serde_json::to_string(node).unwrap();
+        assert!(!stored.contains("live4242"), "{}", stored);
+    }
+}
@@ -0,0 +1,127 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use common::git;
+use serde_json::{json, Value};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tower::ServiceExt;
+
+/// A checkout of one commit on `main` with `files` source files, returning
+/// the commit's SHA.
+fn checkout(root: &Path, files: usize) -> String {
+    fs::create_dir_all(root.join("src")).unwrap();
+    for i in 0..files {
+        fs::write(
+            root.join(format!("src/m{}.rs", i)),
+            format!(
+                "pub fn f{}() {{\n    g{}();\n}}\n\nfn g{}() {{}}\n",
+                i, i, i
+            ),
+        )
+        .unwrap();
+    }
+    git(root, &["init", "-q", "-b", "main"]);
+    git(root, &["add", "."]);
+    git(root, &["commit", "-q", "-m", "initial"]);
+    git(root, &["rev-parse", "HEAD"])
+}
+
+fn ingest_path(root: &Path) -> Request<Body> {
+    Request::post("/ingest-path")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({ "path": root, "repo_id": "acme/app" }).to_string(),
+        ))
+        .unwrap()
+}
+
+fn state(db: &Path, root: PathBuf) -> AppState {
+    let storage = Arc::new(SqliteStorage::open(db.to_str().unwrap()).unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+    state.allowed_roots = vec![root];
+    state
+}
+
+async fn repos(app: &Router) -> Vec<Value> {
+    let request = Request::get("/repos").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    body["repos"].as_array().unwrap().clone()
+}
+
+#[tokio::test]
+async fn test_repo_is_ingesting_while_a_job_runs_and_idle_after() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("app");
+    fs::create_dir_all(&root).unwrap();
+    let root = root.canonicalize().unwrap();
+    let commit = checkout(&root, 500);
+
+    let state = state(&dir.path().join("graph.db"), root.clone());
+    let mut events = state.tx.subscribe();
+    let app = standalone::router(Arc::new(state));
+    assert!(repos(&app).await.is_empty());
+
+    let job = tokio::spawn(app.clone().oneshot(ingest_path(&root)));
+    timeout(Duration::from_secs(10), events.recv())
+        .await
+        .expect("the ingest sent no events")
+        .unwrap();
+    let listed = repos(&app).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["repo_id"], "acme/app");
+    assert_eq!(listed[0]["status"], "ingesting");
+    assert!(listed[0]["last_ingested_at"].is_null());
+
+    let response = job.await.unwrap().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let listed = repos(&app).await;
+    assert_eq!(listed[0]["status"], "idle");
+    assert_eq!(listed[0]["ref"], "main");
+    assert_eq!(listed[0]["commit"], commit.as_str());
+    assert!(listed[0]["url"].is_null());
+    assert!(listed[0]["last_ingested_at"].as_u64().unwrap() > 0);
+    assert!(listed[0]["nodes"].as_u64().unwrap() > 0);
+    assert!(listed[0]["edges"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn test_failed_ingest_and_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("app");
+    fs::create_dir_all(&root).unwrap();
+    let root = root.canonicalize().unwrap();
+    checkout(&root, 1);
+    let db = dir.path().join("graph.db");
+
+    let running = Arc::new(state(&db, root.clone()));
+    let app = standalone::router(running.clone());
+    let response = app.clone().oneshot(ingest_path(&root)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // dropped without succeeding, as on an error or a cancel
+    drop(running.ingests.start("acme/app", &running.shutdown));
+    let listed = repos(&app).await;
+    assert_eq!(listed[0]["status"], "failed");
+    assert_eq!(listed[0]["ref"], "main");
+
+    // the record outlives the process; the failure doesn't
+    let app = standalone::router(Arc::new(state(&db, root)));
+    let listed = repos(&app).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["status"], "idle");
+    assert_eq!(listed[0]["ref"], "main");
+}
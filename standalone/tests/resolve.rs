@@ -0,0 +1,111 @@
+mod common;
+
+use standalone::storage::{kind_key, NodeRecord};
+use standalone::symbols::{qualified_name, FQN};
+
+fn symbol(kind: &str, name: &str, file: &str, operand: Option<&str>) -> NodeRecord {
+    NodeRecord {
+        id: kind_key(kind, name, file, 0, None),
+        end: 1,
+        meta: operand
+            .map(|o| [("operand".to_string(), o.to_string())].into())
+            .unwrap_or_default(),
+        ..common::node(kind, name, file)
+    }
+}
+
+#[test]
+fn test_qualified_names_follow_each_language() {
+    let name = |kind: &str, name: &str, file: &str, operand: Option<&str>| {
+        qualified_name(file, &symbol(kind, name, file, operand))
+    };
+    assert_eq!(
+        name("Function", "slugify", "src/text.rs", None).as_deref(),
+        Some("crate::text::slugify")
+    );
+    assert_eq!(
+        name(
+            "Function",
+            "new",
+            "crates/core/src/text/mod.rs",
+            Some("Slug")
+        )
+        .as_deref(),
+        Some("crate::text::Slug::new")
+    );
+    assert_eq!(
+        name("Function", "greet", "src/lib.rs", None).as_deref(),
+        Some("crate::greet")
+    );
+    assert_eq!(
+        name("Function", "save", "app/models/__init__.py", Some("User")).as_deref(),
+        Some("app.models.User.save")
+    );
+    assert_eq!(
+        name("Function", "Slugify", "internal/text/slug.go", None).as_deref(),
+        Some("internal/text.Slugify")
+    );
+    assert_eq!(
+        name("Class", "User", "src/main/java/com/acme/User.java", None).as_deref(),
+        Some("com.acme.User")
+    );
+    assert_eq!(
+        name("Function", "slugify", "web/util/index.ts", None).as_deref(),
+        Some("web/util.slugify")
+    );
+    // files and imports aren't symbols
+    assert_eq!(name("File", "text.rs", "src/text.rs", None), None);
+    assert_eq!(name("Import", "use", "src/text.rs", None), None);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_resolve_finds_the_one_symbol_of_a_name() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let mut nodes = vec![
+        symbol("Function", "slugify", "src/text.rs", None),
+        symbol("Function", "slugify", "src/html.rs", None),
+    ];
+    for node in &mut nodes {
+        let fqn = qualified_name(&node.file, node).unwrap();
+        node.meta.insert(FQN.to_string(), fqn);
+    }
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage.upsert_nodes(&nodes).await.unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+    let resolve = |fqn: &str| {
+        let body = json!({ "fqn": fqn, "repo": "acme/app" });
+        let request = Request::post("/resolve")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+
+    // the bare name is ambiguous, the qualified one isn't
+    let (status, body) = resolve("crate::text::slugify").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let found = body["nodes"].as_array().unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["file"], "src/text.rs");
+
+    let (status, body) = resolve("crate::text::unslugify").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["kind"], "not_found");
+}
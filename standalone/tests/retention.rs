@@ -0,0 +1,166 @@
+mod common;
+
+use standalone::retention::Retention;
+use standalone::storage::RepoRecord;
+use std::time::Duration;
+
+fn repo(repo_id: &str, ingested_at: u64) -> RepoRecord {
+    RepoRecord {
+        repo_id: repo_id.to_string(),
+        url: format!("https://github.com/{}", repo_id),
+        git_ref: None,
+        commit: None,
+        ingested_at,
+    }
+}
+
+#[test]
+fn test_expired_repos() {
+    let retention = Retention::default();
+    let repos = [
+        repo("acme/old", 1_000),
+        repo("acme/new", 9_000),
+        repo("acme/kept@v1", 1_000),
+    ];
+    assert!(retention.expired(&repos, 10_000).is_empty());
+
+    retention.set_ttl(Some(Duration::from_secs(5_000)));
+    retention.pin("acme/kept", true);
+    let expired: Vec<&str> = retention
+        .expired(&repos, 10_000)
+        .iter()
+        .map(|r| r.repo_id.as_str())
+        .collect();
+    assert_eq!(expired, ["acme/old"]);
+    assert_eq!(retention.expires_at(&repos[1]), Some(14_000));
+    assert_eq!(retention.expires_at(&repos[2]), None);
+
+    retention.pin("acme/kept", false);
+    assert_eq!(retention.expired(&repos, 10_000).len(), 2);
+
+    // a use since the ingest counts from then
+    retention.touch("acme/old");
+    assert!(retention.last_access("acme/old").unwrap() > 10_000);
+    assert_eq!(retention.expired(&repos, 10_000).len(), 1);
+
+    retention.set_ttl(Some(Duration::ZERO));
+    assert_eq!(retention.ttl(), None);
+}
+
+#[cfg(feature = "sqlite")]
+mod sweep {
+    use super::repo;
+    use crate::common::function_in;
+    use standalone::lang::LanguageRegistry;
+    use standalone::retention::sweep;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_sweep_clears_unused_repos_but_not_pinned_ones() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        storage
+            .upsert_nodes(&[
+                function_in("acme/old", "render"),
+                function_in("acme/kept", "parse"),
+            ])
+            .await
+            .unwrap();
+        // straight to the backend, so neither counts as used just now
+        for repo_id in ["acme/old", "acme/kept"] {
+            storage.record_ingest(&repo(repo_id, 1_000)).await.unwrap();
+        }
+        let state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+        state.retention.set_ttl(Some(Duration::from_secs(60)));
+        state.retention.pin("acme/kept", true);
+        let mut events = state.tx.subscribe();
+
+        let swept = sweep(&state, 10_000).await.unwrap();
+        assert_eq!(swept, ["acme/old"]);
+        assert_eq!(storage.graph_size(Some("acme/old")).await.unwrap(), (0, 0));
+        assert_eq!(storage.graph_size(Some("acme/kept")).await.unwrap(), (1, 0));
+        let repos: Vec<String> = storage
+            .repos()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.repo_id)
+            .collect();
+        assert_eq!(repos, ["acme/kept"]);
+
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.update.status, "swept");
+        assert_eq!(event.repo_id.as_deref(), Some("acme/old"));
+        assert!(event.update.message.contains("1 nodes"), "{:?}", event);
+
+        // nothing left to sweep
+        assert!(sweep(&state, 10_000).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reads_count_as_use() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        storage
+            .upsert_nodes(&[function_in("acme/app", "render")])
+            .await
+            .unwrap();
+        let state = AppState::new(storage, LanguageRegistry::new(), 16);
+        assert_eq!(state.retention.last_access("acme/app"), None);
+
+        // listing every repo uses none of them
+        state.storage.graph_size(None).await.unwrap();
+        state.storage.load_graph(None).await.unwrap();
+        assert_eq!(state.retention.last_access("acme/app"), None);
+
+        state.storage.load_graph(Some("acme/app")).await.unwrap();
+        assert!(state.retention.last_access("acme/app").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pins_and_uses_outlast_a_restart() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use serde_json::json;
+        use tower::ServiceExt;
+
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        storage
+            .upsert_nodes(&[
+                function_in("acme/pinned", "parse"),
+                function_in("acme/read", "render"),
+            ])
+            .await
+            .unwrap();
+        for repo_id in ["acme/pinned", "acme/read", "acme/idle"] {
+            storage.record_ingest(&repo(repo_id, 1_000)).await.unwrap();
+        }
+
+        let state = Arc::new(AppState::new(storage.clone(), LanguageRegistry::new(), 16));
+        let request = Request::post("/retention/pin")
+            .header("Content-Type", "application/json")
+            .body(Body::from(json!({ "repo_id": "acme/pinned" }).to_string()))
+            .unwrap();
+        let response = standalone::router(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        state.storage.load_graph(Some("acme/read")).await.unwrap();
+        drop(state);
+
+        // a new server over the same store, as after a restart
+        let state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+        state.retention.set_ttl(Some(Duration::from_secs(60)));
+        assert_eq!(state.retention.last_access("acme/read"), None);
+        let swept = sweep(&state, 10_000).await.unwrap();
+        assert_eq!(swept, ["acme/idle"]);
+        assert!(state.retention.is_pinned("acme/pinned"));
+        assert!(state.retention.last_access("acme/read").unwrap() > 10_000);
+        let stored = storage.retention().await.unwrap();
+        assert!(stored["acme/pinned"].pinned);
+        assert!(!stored.contains_key("acme/idle"));
+    }
+}
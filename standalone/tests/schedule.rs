@@ -0,0 +1,145 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use common::git;
+use serde_json::{json, Value};
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{repo_id, Storage};
+use standalone::AppState;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tower::ServiceExt;
+
+fn commit(root: &Path, file: &str, source: &str) {
+    fs::write(root.join(file), source).unwrap();
+    git(root, &["add", "."]);
+    git(root, &["commit", "-q", "-m", file]);
+}
+
+async fn post(app: &Router, uri: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::post(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_scheduled_repo_is_re_ingested() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    git(&root, &["init", "-q", "-b", "main"]);
+    commit(&root, "main.rs", "fn main() {}\n");
+    let repo = repo_id("", root.to_str().unwrap());
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 1024);
+    state.allowed_roots = vec![root.clone()];
+    let state = Arc::new(state);
+    let app = standalone::router(state.clone());
+    let (status, _) = post(&app, "/process", json!({ "repo_path": root })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (before, _) = storage.graph_size(Some(&repo)).await.unwrap();
+
+    // nothing triggers the ingest of this commit but the schedule
+    commit(&root, "util.rs", "fn helper() {}\n\nfn other() {}\n");
+    let (status, body) = post(
+        &app,
+        "/schedule",
+        json!({ "repo_path": root, "interval_secs": 1 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "scheduled");
+    assert_eq!(body["repo_id"], repo);
+    assert!(state.schedules.is_scheduled(&repo));
+
+    timeout(Duration::from_secs(20), async {
+        while storage.graph_size(Some(&repo)).await.unwrap().0 <= before {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("the scheduled re-ingest never ran");
+
+    let (status, body) = post(
+        &app,
+        "/schedule",
+        json!({ "repo_path": root, "remove": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "unscheduled");
+    assert!(!state.schedules.is_scheduled(&repo));
+}
+
+#[tokio::test]
+async fn test_schedule_needs_an_interval_and_a_known_repo_to_remove() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+    state.allowed_roots = vec![root.clone()];
+    let app = standalone::router(Arc::new(state));
+
+    let (status, _) = post(&app, "/schedule", json!({ "repo_path": root })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(
+        &app,
+        "/schedule",
+        json!({ "repo_path": root, "interval_secs": 0 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(
+        &app,
+        "/schedule",
+        json!({ "repo_path": root, "remove": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_a_repo_path_outside_the_allowed_roots_is_refused() {
+    let allowed = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let outside = outside.path().canonicalize().unwrap();
+    git(&outside, &["init", "-q", "-b", "main"]);
+    commit(&outside, "main.rs", "fn main() {}\n");
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    state.allowed_roots = vec![allowed.path().canonicalize().unwrap()];
+    let app = standalone::router(Arc::new(state));
+
+    let escaping = allowed.path().join("..").join(outside.file_name().unwrap());
+    for (route, body) in [
+        ("/process", json!({ "repo_path": outside })),
+        ("/process", json!({ "repo_path": escaping })),
+        ("/process", json!({ "repo_path": "relative/app" })),
+        (
+            "/process-file",
+            json!({ "repo_path": outside, "file": "main.rs" }),
+        ),
+        (
+            "/schedule",
+            json!({ "repo_path": outside, "interval_secs": 60 }),
+        ),
+    ] {
+        let (status, body) = post(&app, route, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", route, body);
+    }
+    assert_eq!(storage.graph_size(None).await.unwrap(), (0, 0));
+}
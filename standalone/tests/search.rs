@@ -0,0 +1,98 @@
+mod common;
+
+use standalone::search::search;
+use standalone::storage::{EdgeRecord, NodeRecord};
+
+fn record(kind: &str, name: &str, file: &str) -> NodeRecord {
+    NodeRecord {
+        start: 1,
+        end: 3,
+        ..common::node(kind, name, file)
+    }
+}
+
+/// A fixed symbol set; `parseFile` has five callers and the `helper` in
+/// `b.rs` has two more than the one in `a.rs`.
+fn symbols() -> (Vec<NodeRecord>, Vec<EdgeRecord>) {
+    let nodes = vec![
+        record("Function", "parse", "src/parse.rs"),
+        record("Function", "parseFile", "src/parse.rs"),
+        record("Function", "parseFileUrl", "src/parse.rs"),
+        record("Function", "reparse", "src/cache.rs"),
+        record("Class", "FileParser", "src/parse.rs"),
+        record("Var", "path_filter", "src/filter.rs"),
+        record("Function", "helper", "src/a.rs"),
+        record("Function", "helper", "src/b.rs"),
+        record("File", "parse.rs", "src/parse.rs"),
+        record("Import", "parse", "src/main.rs"),
+    ];
+    let mut edges = Vec::new();
+    let mut calls = |target: &NodeRecord, n: usize| {
+        for i in 0..n {
+            edges.push(EdgeRecord {
+                repo_id: "acme/app".to_string(),
+                kind: "CALLS".to_string(),
+                source: format!("caller-{}", i),
+                target: target.id.clone(),
+            });
+        }
+    };
+    calls(&nodes[1], 5);
+    calls(&nodes[6], 1);
+    calls(&nodes[7], 3);
+    (nodes, edges)
+}
+
+fn ranked(query: &str) -> Vec<String> {
+    let (nodes, edges) = symbols();
+    search(&nodes, &edges, query, 20)
+        .into_iter()
+        .map(|hit| format!("{}@{}", hit.name, hit.file))
+        .collect()
+}
+
+#[test]
+fn test_exact_then_prefix_then_word_then_substring() {
+    assert_eq!(
+        ranked("parse"),
+        vec![
+            "parse@src/parse.rs",
+            "parseFile@src/parse.rs",
+            "parseFileUrl@src/parse.rs",
+            "FileParser@src/parse.rs",
+            "reparse@src/cache.rs",
+        ]
+    );
+}
+
+#[test]
+fn test_camel_and_snake_case_initials() {
+    assert_eq!(ranked("pfu"), vec!["parseFileUrl@src/parse.rs"]);
+    assert_eq!(ranked("pathf"), vec!["path_filter@src/filter.rs"]);
+    // word initials beat a scattered subsequence of the same letters
+    assert_eq!(
+        ranked("pfile"),
+        vec![
+            "parseFile@src/parse.rs",
+            "parseFileUrl@src/parse.rs",
+            "path_filter@src/filter.rs",
+        ]
+    );
+}
+
+#[test]
+fn test_callers_break_ties() {
+    assert_eq!(ranked("helper"), vec!["helper@src/b.rs", "helper@src/a.rs"]);
+    let (nodes, edges) = symbols();
+    let hits = search(&nodes, &edges, "HELPER", 1);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].callers, 3);
+    assert_eq!((hits[0].start, hits[0].end), (1, 3));
+    assert_eq!(hits[0].kind, "Function");
+}
+
+#[test]
+fn test_non_symbols_and_blank_queries() {
+    assert!(ranked("parse.rs").is_empty());
+    assert!(ranked("  ").is_empty());
+}
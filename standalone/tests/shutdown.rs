@@ -0,0 +1,60 @@
+#![cfg(feature = "sqlite")]
+
+use standalone::events::StatusEvent;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn test_shutdown_closes_event_stream() {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let state = Arc::new(AppState::new(storage, LanguageRegistry::new(), 16));
+    let token = state.shutdown.clone();
+    let tx = state.tx.clone();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = token.clone();
+    let server = tokio::spawn(async move {
+        axum::serve(listener, standalone::router(state))
+            .with_graceful_shutdown(async move { shutdown.cancelled().await })
+            .await
+    });
+
+    let mut conn = TcpStream::connect(addr).await.unwrap();
+    conn.write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    // wait for the headers and the first event so the stream is mid-flight
+    let mut received = String::new();
+    let mut buf = [0u8; 1024];
+    while !received.contains("data:") {
+        let _ = tx.send(StatusEvent::new("parsing", "a.rs".to_string()));
+        let n = timeout(Duration::from_secs(5), conn.read(&mut buf))
+            .await
+            .expect("no event before timeout")
+            .unwrap();
+        received.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+    assert!(received.starts_with("HTTP/1.1 200"));
+
+    token.cancel();
+
+    // the body must end with the chunked terminator, not a reset connection
+    let mut rest = Vec::new();
+    timeout(Duration::from_secs(5), conn.read_to_end(&mut rest))
+        .await
+        .expect("stream did not close after shutdown")
+        .unwrap();
+    received.push_str(&String::from_utf8_lossy(&rest));
+    assert!(received.ends_with("0\r\n\r\n"), "{:?}", received);
+
+    let served = timeout(Duration::from_secs(5), server).await.unwrap();
+    assert!(served.unwrap().is_ok());
+}
@@ -0,0 +1,269 @@
+#![cfg(feature = "sqlite")]
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::{json, Map, Value};
+use standalone::lang::{Diagnostic, LanguageRegistry};
+use standalone::query::QueryTemplate;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{EdgeRecord, NodeRecord, RepoRecord, Storage, Transaction};
+use standalone::AppState;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower::ServiceExt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+/// A backend whose template queries take `delay` longer than they would.
+struct Slow {
+    db: SqliteStorage,
+    delay: Duration,
+}
+
+#[async_trait]
+impl Storage for Slow {
+    fn backend(&self) -> &'static str {
+        "slow"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.db.ping().await
+    }
+
+    async fn upsert_node(&self, node: &NodeRecord) -> Result<()> {
+        self.db.upsert_node(node).await
+    }
+
+    async fn upsert_edge(&self, edge: &EdgeRecord) -> Result<()> {
+        self.db.upsert_edge(edge).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn Transaction>> {
+        self.db.begin().await
+    }
+
+    async fn file_node_ids(&self, repo_id: &str, file: &str) -> Result<Vec<String>> {
+        self.db.file_node_ids(repo_id, file).await
+    }
+
+    async fn delete_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.db.delete_nodes(repo_id, ids).await
+    }
+
+    async fn delete_file(&self, repo_id: &str, file: &str) -> Result<usize> {
+        self.db.delete_file(repo_id, file).await
+    }
+
+    async fn clear(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.db.clear(repo_id).await
+    }
+
+    async fn graph_size(&self, repo_id: Option<&str>) -> Result<(usize, usize)> {
+        self.db.graph_size(repo_id).await
+    }
+
+    async fn graph_version(&self, repo_id: &str) -> Result<u64> {
+        self.db.graph_version(repo_id).await
+    }
+
+    async fn repo_hash(&self, repo_url: &str) -> Result<Option<String>> {
+        self.db.repo_hash(repo_url).await
+    }
+
+    async fn set_repo_hash(&self, repo_url: &str, hash: &str) -> Result<()> {
+        self.db.set_repo_hash(repo_url, hash).await
+    }
+
+    async fn file_hashes(&self, repo_id: &str) -> Result<HashMap<String, String>> {
+        self.db.file_hashes(repo_id).await
+    }
+
+    async fn set_file_hashes(&self, repo_id: &str, hashes: &[(String, String)]) -> Result<()> {
+        self.db.set_file_hashes(repo_id, hashes).await
+    }
+
+    async fn replace_diagnostics(
+        &self,
+        repo_id: &str,
+        files: &[String],
+        diagnostics: &[Diagnostic],
+    ) -> Result<()> {
+        self.db
+            .replace_diagnostics(repo_id, files, diagnostics)
+            .await
+    }
+
+    async fn find_repo(&self, name: &str) -> Result<Option<NodeRecord>> {
+        self.db.find_repo(name).await
+    }
+
+    async fn node(&self, repo_id: &str, id: &str) -> Result<Option<NodeRecord>> {
+        self.db.node(repo_id, id).await
+    }
+
+    async fn record_ingest(&self, repo: &RepoRecord) -> Result<()> {
+        self.db.record_ingest(repo).await
+    }
+
+    async fn repos(&self) -> Result<Vec<RepoRecord>> {
+        self.db.repos().await
+    }
+
+    async fn load_graph(
+        &self,
+        repo_id: Option<&str>,
+    ) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>)> {
+        self.db.load_graph(repo_id).await
+    }
+
+    async fn dangling_edges(
+        &self,
+        repo_id: &str,
+        after: Option<&EdgeRecord>,
+        limit: usize,
+    ) -> Result<Vec<EdgeRecord>> {
+        self.db.dangling_edges(repo_id, after, limit).await
+    }
+
+    async fn orphan_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.db.orphan_nodes(repo_id, after, limit).await
+    }
+
+    async fn duplicate_nodes(
+        &self,
+        repo_id: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        self.db.duplicate_nodes(repo_id, after, limit).await
+    }
+
+    async fn delete_edges(&self, edges: &[EdgeRecord]) -> Result<usize> {
+        self.db.delete_edges(edges).await
+    }
+
+    async fn dedupe_nodes(&self, repo_id: &str, ids: &[String]) -> Result<usize> {
+        self.db.dedupe_nodes(repo_id, ids).await
+    }
+
+    async fn query(
+        &self,
+        template: &QueryTemplate,
+        params: &Map<String, Value>,
+    ) -> Result<Vec<Value>> {
+        tokio::time::sleep(self.delay).await;
+        self.db.query(template, params).await
+    }
+
+    async fn query_raw(&self, statement: &str, params: &Map<String, Value>) -> Result<Vec<Value>> {
+        self.db.query_raw(statement, params).await
+    }
+}
+
+/// The fields of every `slow query` event.
+#[derive(Default, Clone)]
+struct Capture(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+#[derive(Default)]
+struct Fields(HashMap<String, String>);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Capture {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        if fields.0.remove("message").as_deref() == Some("slow query") {
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+}
+
+fn query(repo: &str) -> Request<Body> {
+    let body = json!({
+        "query": "callers-of-function",
+        "params": { "name": "helper" },
+        "repo": repo,
+    });
+    Request::post("/graph/query")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_queries_past_the_threshold_are_logged_and_counted() {
+    let capture = Capture::default();
+    let subscriber = tracing_subscriber::registry().with(capture.clone());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let storage = Slow {
+        db: SqliteStorage::open_in_memory().unwrap(),
+        delay: Duration::from_millis(100),
+    };
+    let mut state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    state.slow_query = Some(Duration::from_millis(50));
+    let state = Arc::new(state);
+    let app = standalone::router(state.clone());
+
+    let response = app.clone().oneshot(query("acme/app")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let logged = capture.0.lock().unwrap().clone();
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0]["query"], "callers-of-function");
+    let params: Value = serde_json::from_str(&logged[0]["params"]).unwrap();
+    assert_eq!(params["name"], "helper");
+    assert_eq!(params["repo_id"], "acme/app");
+    assert!(logged[0]["duration_ms"].parse::<u64>().unwrap() >= 100);
+    assert_eq!(state.metrics.slow_queries.get(), 1);
+    assert!(state
+        .metrics
+        .render(&[])
+        .contains("mesh_slow_queries_total 1"));
+}
+
+#[tokio::test]
+async fn test_queries_within_the_threshold_are_not_logged() {
+    let capture = Capture::default();
+    let subscriber = tracing_subscriber::registry().with(capture.clone());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    for threshold in [Some(Duration::from_secs(5)), None] {
+        let storage = Slow {
+            db: SqliteStorage::open_in_memory().unwrap(),
+            delay: Duration::from_millis(20),
+        };
+        let mut state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+        state.slow_query = threshold;
+        let state = Arc::new(state);
+        let app = standalone::router(state.clone());
+        let response = app.oneshot(query("acme/app")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.metrics.slow_queries.get(), 0);
+    }
+    assert!(capture.0.lock().unwrap().is_empty());
+}
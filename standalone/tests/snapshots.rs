@@ -0,0 +1,161 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use common::REPO;
+use serde_json::{json, Value};
+use standalone::lang::LanguageRegistry;
+use standalone::snapshots::validate_name;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{EdgeRecord, NodeRecord, RepoRecord, Storage};
+use standalone::AppState;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn function(name: &str, file: &str) -> NodeRecord {
+    NodeRecord {
+        file: file.to_string(),
+        start: 1,
+        end: 3,
+        body: format!("fn {}() {{}}", name),
+        ..common::function(name)
+    }
+}
+
+async fn send(app: &axum::Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn post(path: &str, body: Value) -> Request<Body> {
+    Request::post(path)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[test]
+fn test_snapshot_names() {
+    for name in ["release", "v1.2.0", "before_refactor-2"] {
+        assert!(validate_name(name).is_ok(), "{}", name);
+    }
+    for name in ["", "a#b", "main@v1", "a/b", "last release"] {
+        assert!(validate_name(name).is_err(), "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn test_snapshot_keeps_the_graph_it_was_taken_of() {
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    storage
+        .upsert_nodes(&[
+            function("main", "src/main.rs"),
+            function("greet", "src/greet.rs"),
+        ])
+        .await
+        .unwrap();
+    storage
+        .upsert_edge(&EdgeRecord {
+            repo_id: REPO.to_string(),
+            kind: "CALLS".to_string(),
+            source: "function-main".to_string(),
+            target: "function-greet".to_string(),
+        })
+        .await
+        .unwrap();
+    let ingest = RepoRecord {
+        repo_id: REPO.to_string(),
+        url: "https://github.com/acme/app".to_string(),
+        git_ref: None,
+        commit: Some("abc123".to_string()),
+        ingested_at: 1,
+    };
+    storage.record_ingest(&ingest).await.unwrap();
+    let (before, _) = storage.load_graph(Some(REPO)).await.unwrap();
+    let state = AppState::new(storage.clone(), LanguageRegistry::new(), 16);
+    let app = standalone::router(Arc::new(state));
+
+    let release = json!({ "repo": REPO, "name": "release" });
+    let (status, body) = send(&app, post("/snapshots", release.clone())).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        (body["nodes"].as_u64(), body["edges"].as_u64()),
+        (Some(2), Some(1))
+    );
+    assert_eq!(body["graph"], "acme/app#release");
+    let (status, _) = send(&app, post("/snapshots", release.clone())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // the graph moves on: greet goes, farewell arrives
+    storage.delete_file(REPO, "src/greet.rs").await.unwrap();
+    storage
+        .upsert_node(&function("farewell", "src/farewell.rs"))
+        .await
+        .unwrap();
+
+    let (mut kept, edges) = storage.load_graph(Some("acme/app#release")).await.unwrap();
+    kept.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut expected = before.clone();
+    expected.sort_by(|a, b| a.id.cmp(&b.id));
+    for (kept, expected) in kept.iter().zip(&expected) {
+        assert_eq!((&kept.id, &kept.body), (&expected.id, &expected.body));
+    }
+    assert_eq!(kept.len(), 2);
+    assert_eq!(edges.len(), 1);
+    let export = Request::get("/export/json?repo=acme/app%23release")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(export).await.unwrap().status(),
+        StatusCode::OK
+    );
+
+    let (status, body) = send(
+        &app,
+        post("/diff", json!({ "repo": REPO, "base_snapshot": "release" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let files: Vec<&str> = body["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["file"].as_str().unwrap())
+        .collect();
+    // main lost its call to greet
+    assert_eq!(files, ["src/farewell.rs", "src/greet.rs", "src/main.rs"]);
+
+    let list = Request::get("/snapshots?repo=acme/app")
+        .body(Body::empty())
+        .unwrap();
+    let (status, body) = send(&app, list).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["snapshots"][0]["name"], "release");
+
+    // rolling back brings greet back, farewell goes, and the repo stays listed
+    let (status, body) = send(&app, post("/snapshots/restore", release.clone())).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (2, 1));
+    assert!(storage
+        .node(REPO, "function-farewell")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(storage.repos().await.unwrap(), vec![ingest]);
+
+    let (status, _) = send(&app, post("/snapshots/delete", release.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(storage.snapshots(REPO).await.unwrap().is_empty());
+    assert_eq!(
+        storage.graph_size(Some("acme/app#release")).await.unwrap(),
+        (0, 0)
+    );
+    let (status, _) = send(&app, post("/snapshots/restore", release)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // the restored graph is the repo's own, not the deleted snapshot's
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (2, 1));
+}
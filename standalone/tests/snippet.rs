@@ -0,0 +1,160 @@
+use standalone::snippet::{snippet, SourceCache};
+use standalone::storage::{NodeRecord, Span};
+use std::sync::Arc;
+
+const SOURCE: &str = "use std::fmt;
+
+/// Says hello.
+pub fn greet(name: &str) -> String {
+    format!(\"hello {}\", name)
+}
+
+pub fn part() {}
+";
+
+fn greet(file: &str) -> NodeRecord {
+    let start_byte = SOURCE.find("pub fn greet").unwrap();
+    let end_byte = SOURCE.find("}\n").unwrap() + 1;
+    NodeRecord {
+        repo_id: "acme/app".to_string(),
+        id: "function-greet".to_string(),
+        kind: "Function".to_string(),
+        name: "greet".to_string(),
+        file: file.to_string(),
+        start: 3,
+        end: 5,
+        body: SOURCE[start_byte..end_byte].to_string(),
+        meta: Default::default(),
+        span: Some(Span {
+            start_column: 0,
+            end_column: 1,
+            start_byte,
+            end_byte,
+        }),
+    }
+}
+
+#[test]
+fn test_snippet_context_stays_in_the_file() {
+    let node = greet("src/lib.rs");
+    let found = snippet(&node, SOURCE, 1).unwrap();
+    assert_eq!(found.text, node.body);
+    assert_eq!((found.start_line, found.end_line), (4, 6));
+    assert_eq!(found.context_start_line, 3);
+    assert!(found.context.starts_with("/// Says hello.\npub fn greet"));
+    assert!(found.context.ends_with("}\n"));
+
+    let found = snippet(&node, SOURCE, 100).unwrap();
+    assert_eq!(found.context_start_line, 1);
+    assert_eq!(found.context, SOURCE.trim_end());
+
+    // a span past the end of a file that shrank falls back to the lines
+    let shorter = &SOURCE[..SOURCE.find("\n\npub fn part").unwrap()];
+    let mut moved = node.clone();
+    moved.span.as_mut().unwrap().end_byte = SOURCE.len() + 10;
+    assert_eq!(snippet(&moved, shorter, 0).unwrap().text, node.body);
+    moved.start = 40;
+    assert!(snippet(&moved, shorter, 0).is_none());
+}
+
+#[test]
+fn test_source_cache_reuses_unchanged_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("lib.rs");
+    std::fs::write(&path, SOURCE).unwrap();
+    let missing = dir.path().join("gone.rs");
+
+    let cache = SourceCache::default();
+    let first = cache.read(&[missing.clone(), path.clone()]).unwrap();
+    let second = cache.read(&[path.clone()]).unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(cache.read(&[missing]).is_none());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_snippet_endpoint_returns_the_function_source() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("acme/app/src/lib.rs");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, SOURCE).unwrap();
+
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let mut node = greet(path.to_str().unwrap());
+    node.meta.insert(
+        standalone::docs::DOC.to_string(),
+        "Greets someone.".to_string(),
+    );
+    storage.upsert_node(&node).await.unwrap();
+    // a node whose file lies outside every root is never read
+    let outside = tempfile::tempdir().unwrap();
+    let secret = outside.path().join("lib.rs");
+    std::fs::write(&secret, SOURCE).unwrap();
+    let mut stray = greet(secret.to_str().unwrap());
+    stray.id = "function-stray".to_string();
+    storage.upsert_node(&stray).await.unwrap();
+    let mut state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+    state.allowed_roots = vec![dir.path().canonicalize().unwrap()];
+    let app = standalone::router(Arc::new(state));
+
+    let request = |body: Value| {
+        Request::post("/snippet")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let response = app
+        .clone()
+        .oneshot(request(
+            json!({ "repo": "acme/app", "id": "function-greet", "context": 1 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body["text"],
+        "pub fn greet(name: &str) -> String {\n    format!(\"hello {}\", name)\n}"
+    );
+    assert_eq!(body["file"], "src/lib.rs");
+    assert_eq!(body["doc"], "Greets someone.");
+    assert_eq!(body["start_line"], 4);
+    assert_eq!(body["context_start_line"], 3);
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            json!({ "repo": "acme/app", "id": "function-nope" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            json!({ "repo": "acme/app", "id": "function-stray" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    std::fs::remove_file(&path).unwrap();
+    let response = app
+        .oneshot(request(
+            json!({ "repo": "acme/app", "id": "function-greet" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,118 @@
+use ast::lang::NodeType;
+use standalone::lang::{LanguagePlugin, LanguageRegistry, QuerySet};
+use standalone::source::{ChunkedFile, Source};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Borrows the rust grammar for files ending in `.big`.
+struct Big {
+    queries: QuerySet,
+}
+
+impl LanguagePlugin for Big {
+    fn name(&self) -> &str {
+        "big"
+    }
+    fn grammar(&self) -> tree_sitter::Language {
+        tree_sitter_rust::LANGUAGE.into()
+    }
+    fn file_extensions(&self) -> &[&str] {
+        &["big"]
+    }
+    fn node_queries(&self) -> &QuerySet {
+        &self.queries
+    }
+}
+
+fn registry() -> LanguageRegistry {
+    let mut registry = LanguageRegistry::new();
+    registry
+        .register(Arc::new(Big {
+            queries: QuerySet::new().with(
+                NodeType::Function,
+                "(function_item name: (identifier) @name) @definition",
+            ),
+        }))
+        .unwrap();
+    registry
+}
+
+#[test]
+fn test_chunked_file_reads_ranges() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("text.big");
+    std::fs::write(&path, "fn héllo() {}\n").unwrap();
+    let file = ChunkedFile::open(&path, 5).unwrap();
+    assert_eq!(file.len(), 15);
+    assert!(file.is_plain_utf8().unwrap());
+    assert_eq!(&*file.chunk(0).unwrap(), b"fn h\xC3");
+    assert_eq!(&*file.chunk(13).unwrap(), b"}\n");
+    assert!(file.chunk(15).unwrap().is_empty());
+    assert_eq!(file.text(3..9).unwrap(), "héllo");
+    // the é cut in two
+    assert_eq!(file.text(3..5).unwrap(), "h\u{FFFD}");
+    assert_eq!(file.text(12..100).unwrap(), "{}\n");
+    assert!(file.largest_read() <= 6);
+
+    for (bytes, plain) in [
+        (&b"\xEF\xBB\xBFfn main() {}"[..], false),
+        (b"f\0n\0", false),
+        (b"fn caf\xE9() {}", false),
+        // the file ends halfway through the é
+        (b"fn caf\xC3", false),
+        (b"", true),
+    ] {
+        std::fs::write(&path, bytes).unwrap();
+        let file = ChunkedFile::open(&path, 4).unwrap();
+        assert_eq!(file.is_plain_utf8().unwrap(), plain, "{:?}", bytes);
+    }
+}
+
+#[test]
+fn test_large_file_is_parsed_a_chunk_at_a_time() {
+    let mut text = String::new();
+    for i in 0..40_000 {
+        writeln!(text, "fn f{}() {{ let s = \"ünïcödé\"; }}", i).unwrap();
+    }
+    text.push_str("fn broken( {}\n");
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("generated.big");
+    std::fs::write(&path, &text).unwrap();
+    let registry = registry();
+
+    let chunk = 4096;
+    let file = ChunkedFile::open(&path, chunk).unwrap();
+    let streamed = registry.extract_source("generated.big", &file).unwrap();
+    assert!(file.len() > 1024 * 1024);
+    // at most a chunk, or a node's text, was ever held at once
+    assert!(file.reads() >= file.len() / chunk);
+    assert!(file.largest_read() <= chunk, "{}", file.largest_read());
+
+    let whole = registry.extract("generated.big", &text).unwrap();
+    assert!(streamed.nodes.len() >= 40_000);
+    assert_eq!(streamed.nodes.len(), whole.nodes.len());
+    for (a, b) in streamed.nodes.iter().zip(&whole.nodes) {
+        assert_eq!((&a.name, &a.body, a.span), (&b.name, &b.body, b.span));
+    }
+    assert!(!streamed.diagnostics.is_empty());
+    assert_eq!(streamed.diagnostics, whole.diagnostics);
+
+    // over the threshold, extract_file takes the same path
+    let mut registry = registry;
+    registry.set_stream_bytes(Some(1024));
+    let extracted = registry.extract_file(&path, "generated.big").unwrap();
+    assert_eq!(extracted.nodes.len(), whole.nodes.len());
+    assert_eq!(extracted.diagnostics, whole.diagnostics);
+}
+
+#[test]
+fn test_files_not_plain_utf8_are_decoded_whole() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("latin1.big");
+    std::fs::write(&path, b"fn caf\xE9() {}\nfn bar() {}\n").unwrap();
+    let mut registry = registry();
+    registry.set_stream_bytes(Some(1));
+    let extraction = registry.extract_file(&path, "latin1.big").unwrap();
+    let names: Vec<&str> = extraction.nodes.iter().map(|n| n.name.as_str()).collect();
+    assert_eq!(names, ["café", "bar"]);
+}
@@ -0,0 +1,92 @@
+use standalone::storage::Span;
+
+const SOURCE: &str = "fn main() {\n    greet();\n}\n\nimpl Greeter {\n    fn greet(&self) {\n        wave();\n    }\n}\n";
+
+#[test]
+fn test_locate_finds_the_body_on_its_line() {
+    let body = "fn greet(&self) {\n        wave();\n    }";
+    let span = Span::locate(SOURCE, 5, body).unwrap();
+    assert_eq!(&SOURCE[span.start_byte..span.end_byte], body);
+    assert_eq!(span.start_column, 4);
+    assert_eq!(span.end_column, 5);
+
+    let main = Span::locate(SOURCE, 0, "fn main() {\n    greet();\n}").unwrap();
+    assert_eq!((main.start_byte, main.start_column), (0, 0));
+    assert_eq!(main.end_column, 1);
+}
+
+#[test]
+fn test_locate_needs_the_body_to_start_on_that_line() {
+    // `greet` occurs earlier, as a call, but not on line 5
+    assert!(Span::locate(SOURCE, 1, "fn greet(&self)").is_none());
+    assert!(Span::locate(SOURCE, 5, "fn gone() {}").is_none());
+    assert!(Span::locate(SOURCE, 40, "fn main()").is_none());
+    assert!(Span::locate(SOURCE, 0, "").is_none());
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    const MAIN: &str = "fn main() {\n    greet();\n}\n\nfn greet() {}\n";
+
+    async fn post(app: &axum::Router, uri: &str, body: Value) -> Value {
+        let request = Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query_reports_a_functions_exact_span() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), MAIN).unwrap();
+
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+        state.allowed_roots = vec![root.clone()];
+        let app = standalone::router(Arc::new(state));
+        post(
+            &app,
+            "/ingest-path",
+            json!({ "path": root, "repo_id": "acme/app" }),
+        )
+        .await;
+
+        let body = post(
+            &app,
+            "/graph/query",
+            json!({
+                "query": "symbols-in-file",
+                "params": {"file": "src/main.rs"},
+                "repo": "acme/app",
+            }),
+        )
+        .await;
+        let rows = body["rows"].as_array().unwrap();
+        let greet = rows
+            .iter()
+            .find(|r| r["name"] == "greet")
+            .unwrap_or_else(|| panic!("no greet in {:?}", rows));
+        let expected = MAIN.find("fn greet() {}").unwrap();
+        assert_eq!(greet["start"], 4);
+        assert_eq!(greet["start_byte"], expected);
+        assert_eq!(greet["end_byte"], expected + "fn greet() {}".len());
+        assert_eq!(greet["start_column"], 0);
+        assert_eq!(greet["end_column"], 13);
+    }
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::symbol;
+use standalone::sql::{
+    link, looks_like_sql, references, unquote, COLUMN_KIND, QUERIES_COLUMN, QUERIES_TABLE,
+    TABLE_KIND,
+};
+
+#[test]
+fn test_function_with_a_select_queries_its_tables() {
+    let source = "def active_users(db):\n    return db.execute(\n        \"SELECT u.id, u.email FROM users u JOIN orders o ON o.user_id = u.id WHERE o.total > %s\"\n    )\n\ndef greeting():\n    return \"Select a file from the list.\"\n";
+    let active = symbol("Function", "active_users", "app.py", 0, 3);
+    let greeting = symbol("Function", "greeting", "app.py", 5, 6);
+    let (nodes, edges) = link("app.py", source, "acme/app", &[&active, &greeting], None).unwrap();
+
+    let mut tables: Vec<&str> = nodes
+        .iter()
+        .filter(|n| n.kind == TABLE_KIND)
+        .map(|n| n.name.as_str())
+        .collect();
+    tables.sort();
+    assert_eq!(tables, ["orders", "users"]);
+    let mut columns: Vec<&str> = nodes
+        .iter()
+        .filter(|n| n.kind == COLUMN_KIND)
+        .map(|n| n.name.as_str())
+        .collect();
+    columns.sort();
+    assert_eq!(
+        columns,
+        ["orders.total", "orders.user_id", "users.email", "users.id"]
+    );
+
+    assert!(edges.iter().all(|e| e.source == active.id));
+    let queried = |kind: &str| edges.iter().filter(|e| e.kind == kind).count();
+    assert_eq!(queried(QUERIES_TABLE), 2);
+    assert_eq!(queried(QUERIES_COLUMN), 4);
+    for edge in &edges {
+        assert!(nodes.iter().any(|n| n.id == edge.target), "{:?}", edge);
+    }
+}
+
+#[test]
+fn test_prose_and_code_are_not_taken_for_sql() {
+    assert!(looks_like_sql("SELECT id FROM users"));
+    assert!(looks_like_sql("  delete from sessions where expires < ?"));
+    assert!(looks_like_sql("UPDATE users SET name = ? WHERE id = ?"));
+    assert!(!looks_like_sql("Select a file from the list."));
+    assert!(!looks_like_sql("update the settings"));
+    assert!(!looks_like_sql("selection from the menu"));
+    assert!(!looks_like_sql("users"));
+    // looks the part but isn't SQL
+    assert!(references("select the best from").is_none());
+}
+
+#[test]
+fn test_references_skip_common_table_expressions() {
+    let found = references(
+        "WITH recent AS (SELECT * FROM events WHERE created > ?) SELECT kind FROM recent JOIN public.kinds k ON k.id = recent.kind_id",
+    )
+    .unwrap();
+    assert_eq!(found.tables, ["events", "public.kinds"]);
+    assert_eq!(
+        found.columns,
+        [("public.kinds".to_string(), "id".to_string())]
+    );
+
+    let found = references("INSERT INTO audit (who) SELECT name FROM users WHERE id = 1").unwrap();
+    assert_eq!(found.tables, ["audit", "users"]);
+}
+
+#[test]
+fn test_literal_quotes_and_interpolations_are_removed() {
+    assert_eq!(unquote("\"SELECT 1\""), "SELECT 1");
+    assert_eq!(unquote("r#\"SELECT * FROM \"t\"\"#"), "SELECT * FROM \"t\"");
+    assert_eq!(
+        unquote("f\"\"\"SELECT * FROM {table} WHERE id = {id}\"\"\""),
+        "SELECT * FROM ? WHERE id = ?"
+    );
+    assert_eq!(
+        unquote("`SELECT * FROM users WHERE id = ${id}`"),
+        "SELECT * FROM users WHERE id = ?"
+    );
+    assert_eq!(unquote("\"a\\nb \\\"c\\\"\""), "a b \"c\"");
+}
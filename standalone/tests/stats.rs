@@ -0,0 +1,141 @@
+mod common;
+
+use standalone::stats::{repo_stats, LanguageStats, LANGUAGE};
+use standalone::storage::{EdgeRecord, NodeRecord};
+use std::collections::BTreeMap;
+
+fn node(kind: &str, name: &str, file: &str, meta: &[(&str, &str)]) -> NodeRecord {
+    NodeRecord {
+        meta: meta
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        ..common::node(kind, name, file)
+    }
+}
+
+fn calls(source: &NodeRecord, target: &NodeRecord) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: "CALLS".to_string(),
+        source: source.id.clone(),
+        target: target.id.clone(),
+    }
+}
+
+#[test]
+fn test_stats_from_stored_meta() {
+    let (main, greet, wave) = (
+        node("Function", "main", "src/main.rs", &[]),
+        node("Function", "greet", "src/util.rs", &[]),
+        node("Function", "wave", "src/util.rs", &[]),
+    );
+    let nodes = vec![
+        node(
+            "File",
+            "main.rs",
+            "src/main.rs",
+            &[(LANGUAGE, "rust"), ("lines", "12")],
+        ),
+        node(
+            "File",
+            "util.rs",
+            "src/util.rs",
+            &[(LANGUAGE, "rust"), ("lines", "30")],
+        ),
+        // stored before languages were: judged on its name, with no lines
+        node("File", "setup.py", "setup.py", &[]),
+        main.clone(),
+        greet.clone(),
+        wave.clone(),
+        node("Class", "Config", "src/util.rs", &[]),
+    ];
+    let edges = vec![
+        calls(&main, &greet),
+        calls(&wave, &greet),
+        calls(&main, &wave),
+    ];
+    let stats = repo_stats("acme/app", &nodes, &edges, 1);
+
+    assert_eq!(
+        stats.languages,
+        BTreeMap::from([
+            ("python".to_string(), LanguageStats { files: 1, lines: 0 }),
+            (
+                "rust".to_string(),
+                LanguageStats {
+                    files: 2,
+                    lines: 42
+                }
+            ),
+        ])
+    );
+    assert_eq!(
+        stats.symbols,
+        BTreeMap::from([("Class".to_string(), 1), ("Function".to_string(), 3)])
+    );
+    assert_eq!(stats.most_called.len(), 1);
+    assert_eq!(stats.most_called[0].name, "greet");
+    assert_eq!(stats.most_called[0].callers, 2);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_language_breakdown_of_a_two_language_repo() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("src/main.rs"),
+        "fn main() {\n    greet();\n    greet();\n}\n\nfn greet() {}\n",
+    )
+    .unwrap();
+    fs::write(root.join("tool.py"), "def hello():\n    return 1\n").unwrap();
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+    state.allowed_roots = vec![root.clone()];
+    let app = standalone::router(Arc::new(state));
+    let send = |uri: &str, body: Value| {
+        let request = Request::post(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+    let (status, _) = send("/ingest-path", json!({"path": root, "repo_id": "acme/app"})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send("/stats", json!({"repo": "acme/app"})).await;
+    assert_eq!(status, StatusCode::OK);
+    let repos = body["repos"].as_array().unwrap();
+    assert_eq!(repos.len(), 1);
+    assert_eq!(
+        repos[0]["languages"],
+        json!({
+            "python": {"files": 1, "lines": 2},
+            "rust": {"files": 1, "lines": 6},
+        })
+    );
+    assert!(repos[0]["symbols"]["Function"].as_u64().unwrap() >= 2);
+    assert_eq!(repos[0]["most_called"][0]["name"], "greet");
+
+    let (status, _) = send("/stats", json!({"repo": "acme/none"})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,429 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::REPO;
+use serde_json::{json, Map, Value};
+use standalone::paths::PathMap;
+use standalone::query::find_template;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{same_file, stored_paths, EdgeRecord, NodeRecord, Storage, Transaction};
+
+fn node(kind: &str, name: &str, file: &str, start: usize) -> NodeRecord {
+    NodeRecord {
+        id: format!("{}-{}-{}-{}", kind, name, file, start).to_lowercase(),
+        start,
+        end: start + 2,
+        ..common::node(kind, name, file)
+    }
+}
+
+fn edge(kind: &str, source: &NodeRecord, target: &NodeRecord) -> EdgeRecord {
+    EdgeRecord {
+        repo_id: source.repo_id.clone(),
+        kind: kind.to_string(),
+        source: source.id.clone(),
+        target: target.id.clone(),
+    }
+}
+
+fn params(value: Value) -> Map<String, Value> {
+    value.as_object().unwrap().clone()
+}
+
+async fn run(storage: &SqliteStorage, key: &str, p: Value) -> Vec<Value> {
+    let template = find_template(key).unwrap();
+    storage.query(template, &params(p)).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_sqlite_round_trip() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+
+    let file = node("File", "main.rs", "src/main.rs", 0);
+    let main = node("Function", "main", "src/main.rs", 1);
+    let helper = node("Function", "helper", "src/main.rs", 5);
+    let leaf = node("Function", "leaf", "src/util.rs", 1);
+    for n in [&file, &main, &helper, &leaf] {
+        storage.upsert_node(n).await.unwrap();
+    }
+    storage
+        .upsert_edge(&edge("CONTAINS", &file, &main))
+        .await
+        .unwrap();
+    storage
+        .upsert_edge(&edge("CONTAINS", &file, &helper))
+        .await
+        .unwrap();
+    storage
+        .upsert_edge(&edge("CALLS", &main, &helper))
+        .await
+        .unwrap();
+    storage
+        .upsert_edge(&edge("CALLS", &helper, &leaf))
+        .await
+        .unwrap();
+    // dangling edges are dropped rather than failing the upload
+    let ghost = node("Function", "ghost", "src/ghost.rs", 1);
+    storage
+        .upsert_edge(&edge("CALLS", &main, &ghost))
+        .await
+        .unwrap();
+
+    // upserting again must not duplicate anything
+    storage.upsert_node(&main).await.unwrap();
+    storage
+        .upsert_edge(&edge("CALLS", &main, &helper))
+        .await
+        .unwrap();
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (4, 4));
+
+    let symbols = run(&storage, "symbols-in-file", json!({"file": "src/main.rs"})).await;
+    let names: Vec<&str> = symbols
+        .iter()
+        .map(|r| r["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["main", "helper"]);
+    assert_eq!(symbols[0]["kind"], "Function");
+
+    let callers = run(&storage, "callers-of-function", json!({"name": "helper"})).await;
+    assert_eq!(callers.len(), 1);
+    assert_eq!(callers[0]["name"], "main");
+
+    let reachable = run(&storage, "call-graph-from", json!({"name": "main"})).await;
+    let names: Vec<&str> = reachable
+        .iter()
+        .map(|r| r["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["helper", "leaf"]);
+
+    // removing a node takes its edges with it
+    assert_eq!(
+        storage
+            .delete_nodes(REPO, &[helper.id.clone()])
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (3, 1));
+
+    assert_eq!(storage.clear(None).await.unwrap(), (0, 0));
+}
+
+#[tokio::test]
+async fn test_sqlite_clear_is_scoped_to_repo() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+
+    // two checkouts of the same code produce identical node keys
+    let mut nodes = Vec::new();
+    for repo in ["acme/app", "fork/app"] {
+        let mut file = node("File", "main.rs", "src/main.rs", 0);
+        let mut main = node("Function", "main", "src/main.rs", 1);
+        file.repo_id = repo.to_string();
+        main.repo_id = repo.to_string();
+        storage.upsert_node(&file).await.unwrap();
+        storage.upsert_node(&main).await.unwrap();
+        storage
+            .upsert_edge(&edge("CONTAINS", &file, &main))
+            .await
+            .unwrap();
+        nodes.push(main);
+    }
+    assert_eq!(nodes[0].id, nodes[1].id);
+    assert_eq!(storage.graph_size(None).await.unwrap(), (4, 2));
+
+    assert_eq!(storage.clear(Some("acme/app")).await.unwrap(), (0, 0));
+    assert_eq!(storage.graph_size(Some("fork/app")).await.unwrap(), (2, 1));
+    let (left, _) = storage.load_graph(None).await.unwrap();
+    assert!(left.iter().all(|n| n.repo_id == "fork/app"));
+
+    let symbols = run(&storage, "symbols-in-file", json!({"file": "src/main.rs"})).await;
+    assert_eq!(symbols.len(), 1);
+}
+
+#[tokio::test]
+async fn test_sqlite_file_dependencies() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let app = node("File", "app.ts", "web/app.ts", 0);
+    let util = node("File", "util.ts", "web/util.ts", 0);
+    storage.upsert_node(&app).await.unwrap();
+    storage.upsert_node(&util).await.unwrap();
+    storage
+        .upsert_edge(&edge("IMPORTS", &app, &util))
+        .await
+        .unwrap();
+
+    let deps = run(&storage, "file-dependencies", json!({"file": "web/app.ts"})).await;
+    assert_eq!(deps.len(), 1);
+    assert_eq!(deps[0]["file"], "web/util.ts");
+    assert!(run(
+        &storage,
+        "file-dependencies",
+        json!({"file": "web/util.ts"})
+    )
+    .await
+    .is_empty());
+}
+
+#[tokio::test]
+async fn test_sqlite_repo_hash() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let url = "https://github.com/owner/repo";
+    assert_eq!(storage.repo_hash(url).await.unwrap(), None);
+    storage.set_repo_hash(url, "abc").await.unwrap();
+    storage.set_repo_hash(url, "def").await.unwrap();
+    assert_eq!(
+        storage.repo_hash(url).await.unwrap().as_deref(),
+        Some("def")
+    );
+}
+
+#[tokio::test]
+async fn test_sqlite_graph_version_goes_up_on_every_write() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    assert_eq!(storage.graph_version(REPO).await.unwrap(), 0);
+    let main = node("Function", "main", "src/main.rs", 0);
+    let helper = node("Function", "helper", "src/main.rs", 4);
+    storage
+        .upsert_nodes(&[main.clone(), helper.clone()])
+        .await
+        .unwrap();
+    let written = storage.graph_version(REPO).await.unwrap();
+    assert!(written > 0);
+
+    // reads and other repos leave it alone
+    storage.load_graph(Some(REPO)).await.unwrap();
+    let mut other = node("Function", "main", "src/main.rs", 0);
+    other.repo_id = "acme/other".to_string();
+    storage.upsert_node(&other).await.unwrap();
+    assert_eq!(storage.graph_version(REPO).await.unwrap(), written);
+
+    let mut tx = storage.begin().await.unwrap();
+    tx.upsert_edges(&[edge("CALLS", &main, &helper)])
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    let linked = storage.graph_version(REPO).await.unwrap();
+    assert!(linked > written);
+
+    storage.clear(Some(REPO)).await.unwrap();
+    let cleared = storage.graph_version(REPO).await.unwrap();
+    assert!(cleared > linked);
+
+    let versions = storage.graph_versions().await.unwrap();
+    assert_eq!(versions.get(REPO), Some(&cleared));
+    assert!(versions["acme/other"] > 0);
+    assert_eq!(versions.len(), 2);
+}
+
+#[tokio::test]
+async fn test_sqlite_transaction_deletes_land_with_its_writes() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let main = node("Function", "main", "src/main.rs", 1);
+    let old = node("Function", "old", "src/main.rs", 5);
+    storage.upsert_node(&main).await.unwrap();
+    storage.upsert_node(&old).await.unwrap();
+    storage
+        .upsert_edge(&edge("CALLS", &main, &old))
+        .await
+        .unwrap();
+
+    let mut tx = storage.begin().await.unwrap();
+    tx.delete_nodes(REPO, &[old.id.clone()]).await.unwrap();
+    tx.rollback().await.unwrap();
+    assert!(storage.node(REPO, &old.id).await.unwrap().is_some());
+
+    let new = node("Function", "new", "src/main.rs", 9);
+    let mut tx = storage.begin().await.unwrap();
+    tx.upsert_nodes(&[new.clone()]).await.unwrap();
+    tx.delete_nodes(REPO, &[old.id.clone()]).await.unwrap();
+    // nothing changes until it commits
+    assert!(storage.node(REPO, &new.id).await.unwrap().is_none());
+    assert!(storage.node(REPO, &old.id).await.unwrap().is_some());
+    tx.commit().await.unwrap();
+    assert!(storage.node(REPO, &new.id).await.unwrap().is_some());
+    assert!(storage.node(REPO, &old.id).await.unwrap().is_none());
+    // and its edges went with it
+    assert_eq!(storage.graph_size(Some(REPO)).await.unwrap(), (2, 0));
+}
+
+#[tokio::test]
+async fn test_sqlite_refs_of_a_repo_are_kept_apart() {
+    use standalone::query::scoped_params;
+    use standalone::storage::with_ref;
+
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    for (git_ref, name) in [(None, "main"), (Some("feature"), "feature_only")] {
+        let mut function = node("Function", name, "src/main.rs", 1);
+        function.repo_id = with_ref(REPO, git_ref);
+        storage.upsert_node(&function).await.unwrap();
+    }
+    let template = find_template("symbols-in-file").unwrap();
+    let file = params(json!({"file": "src/main.rs"}));
+    let scoped = |repo_id: &str| scoped_params(&file, Some(repo_id));
+
+    let feature = storage
+        .query(template, &scoped("acme/app@feature"))
+        .await
+        .unwrap();
+    assert_eq!(feature.len(), 1);
+    assert_eq!(feature[0]["name"], "feature_only");
+    let main = storage.query(template, &scoped(REPO)).await.unwrap();
+    assert_eq!(main[0]["name"], "main");
+    assert_eq!(storage.query(template, &file).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_sqlite_file_hashes_go_with_their_files() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let hashes = [
+        ("src/main.rs".to_string(), "aa".to_string()),
+        ("src/lib.rs".to_string(), "bb".to_string()),
+    ];
+    storage.set_file_hashes(REPO, &hashes).await.unwrap();
+    storage
+        .set_file_hashes(REPO, &[("src/lib.rs".to_string(), "cc".to_string())])
+        .await
+        .unwrap();
+    let stored = storage.file_hashes(REPO).await.unwrap();
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored["src/lib.rs"], "cc");
+    assert!(storage.file_hashes("acme/other").await.unwrap().is_empty());
+
+    storage.delete_file(REPO, "src/main.rs").await.unwrap();
+    let stored = storage.file_hashes(REPO).await.unwrap();
+    assert_eq!(stored.keys().collect::<Vec<_>>(), vec!["src/lib.rs"]);
+    storage.clear(Some(REPO)).await.unwrap();
+    assert!(storage.file_hashes(REPO).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_sqlite_files_are_matched_by_their_whole_path() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let root = node("Function", "main", "main.rs", 1);
+    let nested = node("Function", "main", "a/main.rs", 1);
+    let underscore = node("Function", "x", "a_b.rs", 1);
+    let lookalike = node("Function", "x", "axb.rs", 1);
+    for n in [&root, &nested, &underscore, &lookalike] {
+        storage.upsert_node(n).await.unwrap();
+    }
+    let hashes = [
+        ("main.rs".to_string(), "aa".to_string()),
+        ("a/main.rs".to_string(), "bb".to_string()),
+    ];
+    storage.set_file_hashes(REPO, &hashes).await.unwrap();
+
+    assert_eq!(
+        storage.file_node_ids(REPO, "main.rs").await.unwrap(),
+        [root.id.clone()]
+    );
+    assert_eq!(
+        storage.file_node_ids(REPO, "a_b.rs").await.unwrap(),
+        [underscore.id.clone()]
+    );
+    assert_eq!(storage.delete_file(REPO, "main.rs").await.unwrap(), 1);
+    let (nodes, _) = storage.load_graph(Some(REPO)).await.unwrap();
+    let mut files: Vec<&str> = nodes.iter().map(|n| n.file.as_str()).collect();
+    files.sort();
+    assert_eq!(files, ["a/main.rs", "a_b.rs", "axb.rs"]);
+    let stored = storage.file_hashes(REPO).await.unwrap();
+    assert_eq!(stored.keys().collect::<Vec<_>>(), vec!["a/main.rs"]);
+}
+
+#[test]
+fn test_a_root_file_is_not_one_of_the_same_name_below_it() {
+    assert!(same_file("/srv/app/main.rs", "main.rs", "/srv/app"));
+    assert!(same_file("main.rs", "main.rs", "/srv/app"));
+    assert!(!same_file("/srv/app/a/main.rs", "main.rs", "/srv/app"));
+    assert!(!same_file("a/main.rs", "main.rs", "/srv/app"));
+    assert_eq!(
+        stored_paths("/srv/app", "main.rs", &PathMap::default()),
+        ["main.rs", "/srv/app/main.rs"]
+    );
+    assert_eq!(
+        stored_paths(
+            "/srv/app/",
+            "main.rs",
+            &PathMap::new(Some("/srv/app"), Some("/home/dev/app"))
+        ),
+        ["main.rs", "/home/dev/app/main.rs", "/srv/app/main.rs"]
+    );
+}
+
+#[tokio::test]
+async fn test_sqlite_diagnostics_are_replaced_per_file() {
+    use standalone::lang::{Diagnostic, Severity};
+    use standalone::query::scoped_params;
+
+    let diagnostic = |file: &str, start: usize| Diagnostic {
+        file: file.to_string(),
+        start,
+        end: start + 3,
+        message: "syntax error at `;`".to_string(),
+        severity: Severity::Error,
+    };
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage
+        .replace_diagnostics(
+            REPO,
+            &[],
+            &[diagnostic("src/a.rs", 10), diagnostic("src/b.rs", 4)],
+        )
+        .await
+        .unwrap();
+    // b.rs was parsed again and is clean now
+    storage
+        .replace_diagnostics(REPO, &["src/b.rs".to_string()], &[])
+        .await
+        .unwrap();
+
+    let template = find_template("parse-diagnostics").unwrap();
+    let rows = storage
+        .query(template, &scoped_params(&Map::new(), Some(REPO)))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["file"], "src/a.rs");
+    assert_eq!(rows[0]["start"], 10);
+    assert_eq!(rows[0]["severity"], "error");
+    let other = storage
+        .query(template, &scoped_params(&Map::new(), Some("acme/other")))
+        .await
+        .unwrap();
+    assert!(other.is_empty());
+
+    storage.delete_file(REPO, "src/a.rs").await.unwrap();
+    assert!(storage
+        .query(template, &Map::new())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_sqlite_keeps_spans() {
+    use standalone::storage::Span;
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let span = Span {
+        start_column: 4,
+        end_column: 5,
+        start_byte: 60,
+        end_byte: 98,
+    };
+    let mut located = node("Function", "located", "src/main.rs", 3);
+    located.span = Some(span);
+    storage.upsert_node(&located).await.unwrap();
+    storage
+        .upsert_node(&node("Function", "unlocated", "src/main.rs", 9))
+        .await
+        .unwrap();
+
+    let (nodes, _) = storage.load_graph(Some(REPO)).await.unwrap();
+    let spans: Vec<Option<Span>> = nodes.iter().map(|n| n.span).collect();
+    assert!(spans.contains(&Some(span)) && spans.contains(&None));
+
+    let symbols = run(&storage, "symbols-in-file", json!({"file": "src/main.rs"})).await;
+    assert_eq!(symbols[0]["start_byte"], 60);
+    assert_eq!(symbols[0]["end_column"], 5);
+    assert!(symbols[1]["start_byte"].is_null());
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use standalone::filter::FileFilter;
+use standalone::lang::LanguageRegistry;
+use standalone::local::{check_subdir, walk_under};
+use std::fs;
+
+#[test]
+fn test_filter_within_a_subdir() {
+    let mut filter =
+        FileFilter::new(&[], &["**/gen/**".to_string()], &LanguageRegistry::new()).unwrap();
+    filter.within("./services/foo/");
+    assert_eq!(filter.subdir(), Some("services/foo"));
+    assert!(!filter.is_empty());
+    assert!(filter.allows("services/foo/lib.rs"));
+    assert!(filter.allows("./services/foo/src/main.rs"));
+    assert!(!filter.allows("services/foobar/lib.rs"));
+    assert!(!filter.allows("services/bar/lib.rs"));
+    assert!(!filter.allows("main.rs"));
+    // globs still match from the repo root
+    assert!(!filter.allows("services/foo/gen/api.rs"));
+
+    let mut whole = FileFilter::default();
+    whole.within(".");
+    assert!(whole.is_empty());
+    assert!(whole.in_subdir("main.rs"));
+}
+
+#[test]
+fn test_walk_and_check_a_subdir() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("services/foo/src")).unwrap();
+    fs::create_dir_all(root.join("services/bar")).unwrap();
+    fs::write(root.join(".meshignore"), "*.gen.rs\n").unwrap();
+    fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("services/foo/src/lib.rs"), "pub fn foo() {}").unwrap();
+    fs::write(root.join("services/foo/api.gen.rs"), "pub fn api() {}").unwrap();
+    fs::write(root.join("services/bar/lib.rs"), "pub fn bar() {}").unwrap();
+
+    assert_eq!(
+        walk_under(root, "services/foo").unwrap(),
+        ["services/foo/src/lib.rs"]
+    );
+
+    check_subdir(root, "services/foo").unwrap();
+    check_subdir(root, "./services/bar/").unwrap();
+    for (subdir, expected) in [
+        ("../elsewhere", "relative to the repo root"),
+        ("/etc", "relative to the repo root"),
+        ("", "relative to the repo root"),
+        ("services/none", "not in the repo"),
+        ("main.rs", "not a directory"),
+    ] {
+        let message = check_subdir(root, subdir).unwrap_err().to_string();
+        assert!(message.contains(expected), "{}: {}", subdir, message);
+    }
+
+    #[cfg(unix)]
+    {
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("linked")).unwrap();
+        let message = check_subdir(root, "linked").unwrap_err().to_string();
+        assert!(message.contains("leads outside the repo"), "{}", message);
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use crate::common::git;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::fs;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn process(body: Value) -> Request<Body> {
+        Request::post("/process")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_process_only_the_subdir() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("services/foo")).unwrap();
+        fs::create_dir_all(root.join("services/bar")).unwrap();
+        fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(
+            root.join("services/foo/lib.rs"),
+            "pub fn foo() {\n    helper();\n}\n\nfn helper() {}\n",
+        )
+        .unwrap();
+        fs::write(root.join("services/bar/lib.rs"), "pub fn bar() {}\n").unwrap();
+        git(&root, &["init", "-q", "-b", "main"]);
+        git(&root, &["add", "."]);
+        git(&root, &["commit", "-q", "-m", "init"]);
+
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 1024);
+        state.allowed_roots = vec![root.clone()];
+        let state = Arc::new(state);
+        let app = standalone::router(state.clone());
+        let send = |body: Value| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(process(body)).await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, String::from_utf8_lossy(&body).into_owned())
+            }
+        };
+
+        let (status, body) = send(json!({ "repo_path": root, "subdir": "../" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        let (status, body) = send(json!({ "repo_path": root, "subdir": "services/none" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+        let (status, body) = send(json!({ "repo_path": root, "subdir": "services/foo" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (nodes, _) = state.storage.load_graph(None).await.unwrap();
+        let functions = |nodes: &[standalone::storage::NodeRecord]| {
+            let mut names: Vec<String> = nodes
+                .iter()
+                .filter(|n| n.kind == "Function")
+                .map(|n| n.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(functions(&nodes), ["foo", "helper"]);
+        let files: Vec<&str> = nodes
+            .iter()
+            .filter(|n| n.kind == "File")
+            .map(|n| n.file.as_str())
+            .collect();
+        assert_eq!(files.len(), 1, "{:?}", files);
+        assert!(files[0].ends_with("services/foo/lib.rs"), "{:?}", files);
+
+        // the whole repo isn't taken as already done
+        let (status, body) = send(json!({ "repo_path": root })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(!body.contains("already processed"), "{}", body);
+        let (nodes, _) = state.storage.load_graph(None).await.unwrap();
+        assert_eq!(functions(&nodes), ["bar", "foo", "helper", "main"]);
+    }
+}
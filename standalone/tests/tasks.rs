@@ -0,0 +1,111 @@
+mod common;
+
+use standalone::storage::{kind_key, NodeRecord};
+use standalone::tasks::{extract, TaskRules, ASSIGNEE, HAS_TASK, ISSUE, MARKER, TASK_KIND};
+
+const SOURCE: &str = "// TODO(alice): fix\nfn main() {\n    /* FIXME: see PROJ-12\n     * and HACK around it */\n    run(); // XXX\n}\n";
+
+fn function(name: &str, file: &str, start: usize, end: usize) -> NodeRecord {
+    NodeRecord {
+        id: kind_key("Function", name, file, start, None),
+        start,
+        end,
+        ..common::node("Function", name, file)
+    }
+}
+
+#[test]
+fn test_task_markers_become_task_nodes() {
+    let main = function("main", "src/main.rs", 1, 5);
+    let (nodes, edges) = extract(
+        "src/main.rs",
+        SOURCE,
+        "acme/app",
+        &[&main],
+        &TaskRules::default(),
+        None,
+    )
+    .unwrap();
+    assert!(nodes.iter().all(|n| n.kind == TASK_KIND));
+    let found: Vec<(&str, &str, usize)> = nodes
+        .iter()
+        .map(|n| (n.meta[MARKER].as_str(), n.name.as_str(), n.start))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("TODO", "fix", 0),
+            ("FIXME", "see PROJ-12", 2),
+            ("HACK", "around it", 3),
+            ("XXX", "XXX", 4),
+        ]
+    );
+    assert_eq!(nodes[0].meta[ASSIGNEE], "alice");
+    assert!(!nodes[0].meta.contains_key(ISSUE));
+    assert_eq!(nodes[1].meta[ISSUE], "PROJ-12");
+    assert!(!nodes[1].meta.contains_key(ASSIGNEE));
+
+    // the first is above main, so belongs to nothing given here
+    assert_eq!(edges.len(), 3);
+    assert!(edges
+        .iter()
+        .all(|e| e.kind == HAS_TASK && e.source == main.id));
+    assert_eq!(edges[0].target, nodes[1].id);
+}
+
+#[test]
+fn test_assignees_and_issues_are_read_as_configured() {
+    let rules = TaskRules::default();
+    let task = rules.parse("# TODO(#42): ask @bob").unwrap();
+    assert_eq!(task.issue.as_deref(), Some("42"));
+    assert_eq!(task.assignee.as_deref(), Some("bob"));
+    assert_eq!(task.text, "ask @bob");
+    let task = rules.parse("// FIXME(PROJ-7) flaky").unwrap();
+    assert_eq!(task.issue.as_deref(), Some("PROJ-7"));
+    assert_eq!(task.assignee, None);
+    assert!(rules.parse("// nothing to do here").is_none());
+    assert!(rules.parse("// TODOS are not markers").is_none());
+
+    let rules = TaskRules::new(&["NOTE".to_string()], r"\[owner=(\w+)\]", r"(GH-\d+)").unwrap();
+    let task = rules.parse("-- NOTE [owner=carol] GH-9").unwrap();
+    assert_eq!(task.marker, "NOTE");
+    assert_eq!(task.assignee.as_deref(), Some("carol"));
+    assert_eq!(task.issue.as_deref(), Some("GH-9"));
+    assert!(rules.parse("// TODO: not a marker here").is_none());
+    assert!(TaskRules::new(&["TODO".to_string()], "(", "").is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_open_tasks_lists_them_by_file() {
+    use serde_json::Map;
+    use standalone::query::{find_template, scoped_params};
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+
+    let (nodes, _) = extract(
+        "src/main.rs",
+        SOURCE,
+        "acme/app",
+        &[],
+        &TaskRules::default(),
+        None,
+    )
+    .unwrap();
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    for node in &nodes {
+        storage.upsert_node(node).await.unwrap();
+    }
+    let template = find_template("open-tasks").unwrap();
+    let rows = storage
+        .query(template, &scoped_params(&Map::new(), Some("acme/app")))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[0]["marker"], "TODO");
+    assert_eq!(rows[0]["text"], "fix");
+    assert_eq!(rows[0]["assignee"], "alice");
+    assert_eq!(rows[0]["file"], "src/main.rs");
+    assert_eq!(rows[1]["issue"], "PROJ-12");
+    assert!(rows[3]["assignee"].is_null());
+}
@@ -0,0 +1,72 @@
+#![cfg(all(feature = "otel", feature = "sqlite"))]
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use opentelemetry::Value;
+use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+use opentelemetry_sdk::trace::TracerProvider;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::AppState;
+use std::fs;
+use std::sync::Arc;
+use tower::ServiceExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+#[tokio::test]
+async fn test_ingest_produces_spans() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry().with(standalone::telemetry::layer(&provider));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"sample\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("src/main.rs"),
+        "fn main() {\n    greet();\n}\n\nfn greet() {}\n",
+    )
+    .unwrap();
+
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let mut state = AppState::new(storage, LanguageRegistry::new(), 64);
+    state.allowed_roots = vec![root.clone()];
+    let body = serde_json::json!({ "path": root }).to_string();
+    let request = Request::post("/ingest-path")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    provider.force_flush();
+    let spans = exporter.get_finished_spans().unwrap();
+    let named = |name: &str| spans.iter().filter(move |s| s.name == name);
+    for name in ["ingest_path", "build_graph", "write_graph", "upsert_node"] {
+        assert!(named(name).next().is_some(), "no {} span", name);
+    }
+
+    let attribute = |span: &opentelemetry_sdk::export::trace::SpanData, key: &str| {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+    };
+    let write = named("write_graph").next().unwrap();
+    assert_eq!(attribute(write, "backend"), Some(Value::from("sqlite")));
+    assert!(matches!(attribute(write, "nodes"), Some(Value::I64(n)) if n > 0));
+    let upsert = named("upsert_node").next().unwrap();
+    assert_eq!(attribute(upsert, "backend"), Some(Value::from("sqlite")));
+    assert!(attribute(upsert, "file").is_some());
+}
@@ -0,0 +1,101 @@
+#![cfg(all(feature = "tls", feature = "sqlite"))]
+
+use standalone::lang::LanguageRegistry;
+use standalone::server::HttpConfig;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::{tls, AppState};
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+struct SelfSigned {
+    _dir: tempfile::TempDir,
+    cert: PathBuf,
+    key: PathBuf,
+    pem: String,
+}
+
+fn self_signed() -> SelfSigned {
+    let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let (cert, key) = (dir.path().join("cert.pem"), dir.path().join("key.pem"));
+    let pem = generated.cert.pem();
+    fs::write(&cert, &pem).unwrap();
+    fs::write(&key, generated.key_pair.serialize_pem()).unwrap();
+    SelfSigned {
+        _dir: dir,
+        cert,
+        key,
+        pem,
+    }
+}
+
+#[tokio::test]
+async fn test_https_request_against_a_self_signed_cert() {
+    let signed = self_signed();
+    let config = tls::load(&signed.cert, &signed.key).await.unwrap();
+    let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let state = AppState::new(storage, LanguageRegistry::new(), 1024);
+    let app = standalone::router(Arc::new(state));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(tls::serve(
+        listener,
+        config,
+        app,
+        HttpConfig::default(),
+        std::future::pending(),
+        Duration::from_secs(1),
+    ));
+
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(signed.pem.as_bytes()).unwrap())
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!("https://localhost:{}/healthz", port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // progress events stream over the same connection type
+    let response = client
+        .get(format!("https://localhost:{}/events", port))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "text/event-stream"
+    );
+
+    // a client that doesn't trust the cert is refused
+    let untrusting = reqwest::Client::new();
+    assert!(untrusting
+        .get(format!("https://localhost:{}/healthz", port))
+        .send()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_bad_cert_or_key_is_named() {
+    let signed = self_signed();
+    let missing = signed.cert.with_file_name("missing.pem");
+    let e = tls::load(&missing, &signed.key).await.unwrap_err();
+    assert!(format!("{:#}", e).contains("missing.pem"));
+
+    let garbage = signed.cert.with_file_name("garbage.pem");
+    fs::write(&garbage, "not a certificate").unwrap();
+    let e = tls::load(&garbage, &signed.key).await.unwrap_err();
+    assert!(format!("{:#}", e).contains("garbage.pem"));
+
+    // a key that isn't a key, with a good cert
+    let e = tls::load(&signed.cert, &signed.cert).await.unwrap_err();
+    assert!(format!("{:#}", e).contains("invalid TLS certificate"));
+}
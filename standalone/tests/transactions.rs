@@ -0,0 +1,115 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use common::function;
+use serde_json::Value;
+use standalone::lang::LanguageRegistry;
+use standalone::storage::sqlite::SqliteStorage;
+use standalone::storage::{EdgeRecord, Storage};
+use standalone::AppState;
+use std::fs;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn test_rolled_back_transaction_leaves_nothing() {
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    let edge = EdgeRecord {
+        repo_id: "acme/app".to_string(),
+        kind: "CALLS".to_string(),
+        source: "function-a".to_string(),
+        target: "function-b".to_string(),
+    };
+
+    let mut tx = storage.begin().await.unwrap();
+    tx.upsert_nodes(&[function("a"), function("b")])
+        .await
+        .unwrap();
+    tx.upsert_edges(std::slice::from_ref(&edge)).await.unwrap();
+    tx.rollback().await.unwrap();
+    assert_eq!(storage.graph_size(Some("acme/app")).await.unwrap(), (0, 0));
+
+    let mut tx = storage.begin().await.unwrap();
+    tx.upsert_nodes(&[function("a"), function("b")])
+        .await
+        .unwrap();
+    tx.upsert_edges(&[edge]).await.unwrap();
+    // nothing is visible before the commit
+    assert_eq!(storage.graph_size(Some("acme/app")).await.unwrap(), (0, 0));
+    tx.commit().await.unwrap();
+    assert_eq!(storage.graph_size(Some("acme/app")).await.unwrap(), (2, 1));
+}
+
+#[tokio::test]
+async fn test_failed_file_leaves_no_trace_and_the_rest_are_stored() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("repo");
+    fs::create_dir_all(root.join("src")).unwrap();
+    for (file, function) in [("a.rs", "alpha"), ("b.rs", "beta"), ("c.rs", "gamma")] {
+        let source = format!(
+            "pub fn {}() {{}}\n\npub fn {}_too() {{}}\n",
+            function, function
+        );
+        fs::write(root.join("src").join(file), source).unwrap();
+    }
+    let root = root.canonicalize().unwrap();
+
+    let db = dir.path().join("graph.db");
+    let storage = Arc::new(SqliteStorage::open(db.to_str().unwrap()).unwrap());
+    // fails partway through b.rs: its nodes are in by the time its first edge is
+    rusqlite::Connection::open(&db)
+        .unwrap()
+        .execute_batch(
+            "CREATE TRIGGER fail_b BEFORE INSERT ON edges
+             WHEN NEW.source IN (SELECT id FROM nodes WHERE file LIKE '%b.rs')
+               OR NEW.target IN (SELECT id FROM nodes WHERE file LIKE '%b.rs')
+             BEGIN SELECT RAISE(ABORT, 'disk on fire'); END;",
+        )
+        .unwrap();
+
+    let mut state = AppState::new(storage.clone(), LanguageRegistry::new(), 1024);
+    state.allowed_roots = vec![root.clone()];
+    let mut rx = state.tx.subscribe();
+    let body = serde_json::json!({ "path": root, "repo_id": "acme/app" }).to_string();
+    let request = Request::post("/ingest-path")
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert!(body["message"].as_str().unwrap().contains("src/b.rs"));
+
+    let mut errors = Vec::new();
+    while let Ok(event) = rx.try_recv() {
+        if event.update.status == "error" {
+            errors.push(event.update.message);
+        }
+    }
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].contains("src/b.rs") && errors[0].contains("disk on fire"));
+
+    let (nodes, edges) = storage.load_graph(Some("acme/app")).await.unwrap();
+    assert!(
+        !nodes.iter().any(|n| n.file.ends_with("b.rs")),
+        "{:?}",
+        nodes
+    );
+    let names: Vec<&str> = nodes.iter().map(|n| n.name.as_str()).collect();
+    for name in ["alpha", "alpha_too", "gamma", "gamma_too"] {
+        assert!(names.contains(&name), "{} missing from {:?}", name, names);
+    }
+    assert!(!names.contains(&"beta"));
+    let ids: Vec<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+    for edge in &edges {
+        assert!(ids.contains(&edge.source.as_str()) && ids.contains(&edge.target.as_str()));
+    }
+    assert!(!edges.is_empty());
+}
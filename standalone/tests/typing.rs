@@ -0,0 +1,169 @@
+mod common;
+
+use common::located;
+use serde_json::json;
+use standalone::captures::Grammar;
+use standalone::storage::NodeRecord;
+use standalone::typing::{annotate, PARAM_TYPES, TYPE};
+use std::path::Path;
+use tree_sitter::Tree;
+
+const RUST: &str = "const LIMIT: usize = 10;
+
+fn render(user: &User, count: u32) -> String {
+    let greeting = \"hi\";
+    format!(\"{} {}\", greeting, count)
+}
+
+fn untyped(&self) {
+    let n = 5;
+}
+";
+
+const PYTHON: &str = "def greet(name: str, times=1) -> str:
+    return name * times
+
+def anything(value):
+    return value
+";
+
+fn param_types(node: &NodeRecord) -> serde_json::Value {
+    serde_json::from_str(&node.meta[PARAM_TYPES]).unwrap()
+}
+
+fn tree(file: &str, source: &str) -> Option<Tree> {
+    Grammar::for_path(Path::new(file))?
+        .parse(source, None)
+        .unwrap()
+}
+
+#[test]
+fn test_annotated_parameters_store_their_declared_types() {
+    let mut nodes = vec![
+        located(RUST, "src/lib.rs", "Function", "render", "fn render(user: &User, count: u32) -> String {\n    let greeting = \"hi\";\n    format!(\"{} {}\", greeting, count)\n}"),
+        located(RUST, "src/lib.rs", "Function", "untyped", "fn untyped(&self) {\n    let n = 5;\n}"),
+        located(RUST, "src/lib.rs", "Var", "LIMIT", "const LIMIT: usize = 10;"),
+        located(RUST, "src/lib.rs", "Var", "greeting", "let greeting = \"hi\";"),
+        located(RUST, "src/lib.rs", "Var", "n", "let n = 5;"),
+    ];
+    annotate(
+        "src/lib.rs",
+        RUST,
+        tree("src/lib.rs", RUST).as_ref(),
+        nodes.iter_mut(),
+    )
+    .unwrap();
+
+    let render = &nodes[0];
+    assert_eq!(render.meta[TYPE], "String");
+    assert_eq!(
+        param_types(render),
+        json!({"user": "&User", "count": "u32"})
+    );
+    // nothing written, nothing guessed
+    assert!(!nodes[1].meta.contains_key(TYPE));
+    assert!(!nodes[1].meta.contains_key(PARAM_TYPES));
+    assert_eq!(nodes[2].meta[TYPE], "usize");
+    // a string literal is a &str wherever it's used
+    assert_eq!(nodes[3].meta[TYPE], "&str");
+    // an integer may be any of them
+    assert!(!nodes[4].meta.contains_key(TYPE));
+}
+
+#[test]
+fn test_python_annotations() {
+    let mut nodes = vec![
+        located(
+            PYTHON,
+            "app.py",
+            "Function",
+            "greet",
+            "def greet(name: str, times=1) -> str:\n    return name * times",
+        ),
+        located(
+            PYTHON,
+            "app.py",
+            "Function",
+            "anything",
+            "def anything(value):\n    return value",
+        ),
+    ];
+    annotate(
+        "app.py",
+        PYTHON,
+        tree("app.py", PYTHON).as_ref(),
+        nodes.iter_mut(),
+    )
+    .unwrap();
+    assert_eq!(nodes[0].meta[TYPE], "str");
+    assert_eq!(param_types(&nodes[0]), json!({"name": "str"}));
+    assert!(nodes[1].meta.is_empty());
+}
+
+#[test]
+fn test_nodes_without_a_span_or_rule_are_left_alone() {
+    let mut unspanned = located(RUST, "src/lib.rs", "Function", "render", "fn render(");
+    unspanned.span = None;
+    let mut markdown = located(RUST, "README.md", "Function", "render", "fn render(");
+    annotate(
+        "src/lib.rs",
+        RUST,
+        tree("src/lib.rs", RUST).as_ref(),
+        [&mut unspanned],
+    )
+    .unwrap();
+    annotate(
+        "README.md",
+        RUST,
+        tree("README.md", RUST).as_ref(),
+        [&mut markdown],
+    )
+    .unwrap();
+    assert!(unspanned.meta.is_empty());
+    assert!(markdown.meta.is_empty());
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_functions_returning_a_type_are_queried() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let mut nodes = vec![
+        located(RUST, "src/lib.rs", "Function", "render", "fn render(user: &User, count: u32) -> String {\n    let greeting = \"hi\";\n    format!(\"{} {}\", greeting, count)\n}"),
+        located(RUST, "src/lib.rs", "Function", "untyped", "fn untyped(&self) {\n    let n = 5;\n}"),
+    ];
+    annotate(
+        "src/lib.rs",
+        RUST,
+        tree("src/lib.rs", RUST).as_ref(),
+        nodes.iter_mut(),
+    )
+    .unwrap();
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage.upsert_nodes(&nodes).await.unwrap();
+    let state = AppState::new(Arc::new(storage), LanguageRegistry::new(), 16);
+
+    let request = Request::post("/graph/query")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            r#"{"query": "functions-returning-type", "params": {"type": "String"}}"#,
+        ))
+        .unwrap();
+    let response = standalone::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let rows = body["rows"].as_array().unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["name"], "render");
+}
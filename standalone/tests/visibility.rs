@@ -0,0 +1,180 @@
+mod common;
+
+use common::located;
+use standalone::captures::Grammar;
+use standalone::storage::NodeRecord;
+use standalone::visibility::{annotate, VISIBILITY};
+use std::path::Path;
+use tree_sitter::Tree;
+
+const RUST: &str = "pub fn greet() {}
+
+fn helper() {}
+
+pub(crate) struct Config;
+
+impl Display for Config {
+    fn fmt(&self) {}
+}
+";
+
+const PYTHON: &str = "def greet():
+    pass
+
+def _helper():
+    pass
+
+class User:
+    def __init__(self):
+        pass
+";
+
+const TYPESCRIPT: &str = "export function greet() {}
+
+function helper() {}
+
+export class User {
+    private secret() {}
+    load() {}
+}
+";
+
+const GO: &str = "func Greet() {}
+
+func helper() {}
+";
+
+fn visibilities(file: &str, source: &str, mut nodes: Vec<NodeRecord>) -> Vec<String> {
+    annotate(file, source, tree(file, source).as_ref(), nodes.iter_mut()).unwrap();
+    nodes
+        .into_iter()
+        .map(|n| n.meta.get(VISIBILITY).cloned().unwrap_or_default())
+        .collect()
+}
+
+fn tree(file: &str, source: &str) -> Option<Tree> {
+    Grammar::for_path(Path::new(file))?
+        .parse(source, None)
+        .unwrap()
+}
+
+#[test]
+fn test_rust_reads_its_visibility_modifiers() {
+    let nodes = vec![
+        located(RUST, "src/lib.rs", "Function", "greet", "pub fn greet() {}"),
+        located(RUST, "src/lib.rs", "Function", "helper", "fn helper() {}"),
+        located(
+            RUST,
+            "src/lib.rs",
+            "DataModel",
+            "Config",
+            "pub(crate) struct Config;",
+        ),
+        // an impl of a trait is as public as the trait
+        located(RUST, "src/lib.rs", "Function", "fmt", "fn fmt(&self) {}"),
+    ];
+    assert_eq!(
+        visibilities("src/lib.rs", RUST, nodes),
+        ["public", "private", "internal", "public"]
+    );
+}
+
+#[test]
+fn test_conventions_stand_in_for_missing_modifiers() {
+    let nodes = vec![
+        located(
+            PYTHON,
+            "app.py",
+            "Function",
+            "greet",
+            "def greet():\n    pass",
+        ),
+        located(
+            PYTHON,
+            "app.py",
+            "Function",
+            "_helper",
+            "def _helper():\n    pass",
+        ),
+        located(
+            PYTHON,
+            "app.py",
+            "Function",
+            "__init__",
+            "def __init__(self):\n        pass",
+        ),
+    ];
+    assert_eq!(
+        visibilities("app.py", PYTHON, nodes),
+        ["public", "private", "public"]
+    );
+    let nodes = vec![
+        located(GO, "main.go", "Function", "Greet", "func Greet() {}"),
+        located(GO, "main.go", "Function", "helper", "func helper() {}"),
+    ];
+    assert_eq!(visibilities("main.go", GO, nodes), ["exported", "private"]);
+}
+
+#[test]
+fn test_typescript_exports_and_members() {
+    let nodes = vec![
+        located(
+            TYPESCRIPT,
+            "src/app.ts",
+            "Function",
+            "greet",
+            "function greet() {}",
+        ),
+        located(
+            TYPESCRIPT,
+            "src/app.ts",
+            "Function",
+            "helper",
+            "function helper() {}",
+        ),
+        located(
+            TYPESCRIPT,
+            "src/app.ts",
+            "Function",
+            "secret",
+            "private secret() {}",
+        ),
+        located(TYPESCRIPT, "src/app.ts", "Function", "load", "load() {}"),
+    ];
+    assert_eq!(
+        visibilities("src/app.ts", TYPESCRIPT, nodes),
+        ["exported", "private", "private", "public"]
+    );
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_public_api_lists_what_a_file_offers() {
+    use serde_json::{json, Map};
+    use standalone::query::{find_template, scoped_params};
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::Storage;
+
+    let mut nodes = vec![
+        located(RUST, "src/lib.rs", "Function", "greet", "pub fn greet() {}"),
+        located(RUST, "src/lib.rs", "Function", "helper", "fn helper() {}"),
+    ];
+    annotate(
+        "src/lib.rs",
+        RUST,
+        tree("src/lib.rs", RUST).as_ref(),
+        nodes.iter_mut(),
+    )
+    .unwrap();
+    let storage = SqliteStorage::open_in_memory().unwrap();
+    storage.upsert_nodes(&nodes).await.unwrap();
+    let template = find_template("public-api").unwrap();
+    let params = Map::from_iter([("file".to_string(), json!("lib.rs"))]);
+    let rows = storage
+        .query(template, &scoped_params(&params, Some("acme/app")))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["name"], "greet");
+    assert_eq!(rows[0]["visibility"], "public");
+}
@@ -0,0 +1,257 @@
+use axum::http::{HeaderMap, HeaderValue};
+use serde_json::json;
+use standalone::webhook::{github_signature, parse, Delivery, Push, WebhookConfig};
+
+const SECRET: &str = "s3cret";
+
+fn github_push() -> serde_json::Value {
+    json!({
+        "ref": "refs/heads/main",
+        "after": "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+        "forced": false,
+        "repository": {
+            "full_name": "acme/app",
+            "clone_url": "https://example.invalid/acme/app.git",
+            "default_branch": "main"
+        },
+        "commits": [
+            { "added": ["src/new.rs"], "modified": ["src/lib.rs"], "removed": [] },
+            { "added": [], "modified": ["src/main.rs"], "removed": ["src/new.rs", "src/old.rs"] }
+        ]
+    })
+}
+
+fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.insert(*name, HeaderValue::from_str(value).unwrap());
+    }
+    headers
+}
+
+fn push(delivery: Delivery) -> Push {
+    match delivery {
+        Delivery::Push(push) => push,
+        Delivery::Ignored(reason) => panic!("ignored: {}", reason),
+    }
+}
+
+#[test]
+fn test_github_signature_is_verified() {
+    let config = WebhookConfig::new(SECRET, &["acme/app"]);
+    let body = github_push().to_string();
+    let signature = github_signature(SECRET, body.as_bytes());
+    let signed = headers(&[("x-hub-signature-256", &signature)]);
+    assert!(config.verify(&signed, body.as_bytes()));
+
+    let tampered = body.replace("main", "evil");
+    assert!(!config.verify(&signed, tampered.as_bytes()));
+    let wrong_key = github_signature("other", body.as_bytes());
+    let wrong_key = headers(&[("x-hub-signature-256", &wrong_key)]);
+    assert!(!config.verify(&wrong_key, body.as_bytes()));
+    assert!(!config.verify(&HeaderMap::new(), body.as_bytes()));
+}
+
+#[test]
+fn test_gitlab_token_is_verified() {
+    let config = WebhookConfig::new(SECRET, &["acme/app"]);
+    assert!(config.verify(&headers(&[("x-gitlab-token", SECRET)]), b"{}"));
+    assert!(!config.verify(&headers(&[("x-gitlab-token", "s3cre")]), b"{}"));
+}
+
+#[test]
+fn test_github_push_lists_net_changes() {
+    let body = github_push().to_string();
+    let delivery = parse(&headers(&[("x-github-event", "push")]), body.as_bytes()).unwrap();
+    let push = push(delivery);
+    assert_eq!(push.repo_id, "acme/app");
+    assert_eq!(push.branch, "main");
+    assert_eq!(push.default_branch.as_deref(), Some("main"));
+    // the last commit wins: src/new.rs was added, then removed
+    assert_eq!(push.changed, vec!["src/lib.rs", "src/main.rs"]);
+    assert_eq!(push.removed, vec!["src/new.rs", "src/old.rs"]);
+    assert!(push.incremental());
+}
+
+#[test]
+fn test_gitlab_push_is_parsed() {
+    let body = json!({
+        "object_kind": "push",
+        "ref": "refs/heads/main",
+        "after": "da1560886d4f094c3e6c9ef40349f7d38b5d27d7",
+        "total_commits_count": 1,
+        "project": {
+            "path_with_namespace": "acme/app",
+            "git_http_url": "https://example.invalid/acme/app.git",
+            "default_branch": "main"
+        },
+        "commits": [{ "added": [], "modified": ["app.py"], "removed": [] }]
+    })
+    .to_string();
+    let delivery = parse(
+        &headers(&[("x-gitlab-event", "Push Hook")]),
+        body.as_bytes(),
+    )
+    .unwrap();
+    let push = push(delivery);
+    assert_eq!(push.repo_id, "acme/app");
+    assert_eq!(push.changed, vec!["app.py"]);
+    assert!(push.incremental());
+}
+
+#[test]
+fn test_incomplete_pushes_are_not_incremental() {
+    let github = headers(&[("x-github-event", "push")]);
+    let mut forced = github_push();
+    forced["forced"] = json!(true);
+    let delivery = parse(&github, forced.to_string().as_bytes()).unwrap();
+    assert!(!push(delivery).incremental());
+
+    let mut truncated = github_push();
+    truncated["total_commits_count"] = json!(40);
+    let delivery = parse(&github, truncated.to_string().as_bytes()).unwrap();
+    assert!(!push(delivery).incremental());
+}
+
+#[test]
+fn test_other_events_are_ignored() {
+    let ping = parse(&headers(&[("x-github-event", "ping")]), b"{}").unwrap();
+    assert!(matches!(ping, Delivery::Ignored(_)));
+    let mut deleted = github_push();
+    deleted["after"] = json!("0000000000000000000000000000000000000000");
+    let github = headers(&[("x-github-event", "push")]);
+    let delivery = parse(&github, deleted.to_string().as_bytes()).unwrap();
+    assert!(matches!(delivery, Delivery::Ignored(_)));
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use super::{github_push, SECRET};
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{RepoRecord, Storage};
+    use standalone::webhook::{github_signature, WebhookConfig};
+    use standalone::AppState;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn app(repos: &[&str]) -> axum::Router {
+        app_with(Arc::new(SqliteStorage::open_in_memory().unwrap()), repos)
+    }
+
+    fn app_with(storage: Arc<SqliteStorage>, repos: &[&str]) -> axum::Router {
+        let mut state = AppState::new(storage, LanguageRegistry::new(), 16);
+        state.webhook = Some(Arc::new(WebhookConfig::new(SECRET, repos)));
+        standalone::router(Arc::new(state))
+    }
+
+    fn delivery(body: &str, signature: &str) -> Request<Body> {
+        Request::post("/webhook")
+            .header("Content-Type", "application/json")
+            .header("X-GitHub-Event", "push")
+            .header("X-Hub-Signature-256", signature)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn json(response: axum::response::Response) -> serde_json::Value {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalid_signature_is_rejected_before_parsing() {
+        let signature = github_signature("wrong", b"not json");
+        let response = app(&["acme/app"])
+            .oneshot(delivery("not json", &signature))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json(response).await["kind"], "unauthorized");
+    }
+
+    #[tokio::test]
+    async fn test_unconfigured_repo_is_not_found() {
+        let body = github_push().to_string();
+        let signature = github_signature(SECRET, body.as_bytes());
+        let response = app(&["acme/other"])
+            .oneshot(delivery(&body, &signature))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_signed_push_is_queued() {
+        let body = github_push().to_string();
+        let signature = github_signature(SECRET, body.as_bytes());
+        let response = app(&["acme/app"])
+            .oneshot(delivery(&body, &signature))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = json(response).await;
+        assert_eq!(body["status"], "queued");
+        assert_eq!(body["repo_id"], "acme/app");
+        assert_eq!(body["files"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_push_to_other_branch_is_ignored() {
+        let mut payload = github_push();
+        payload["ref"] = serde_json::json!("refs/heads/feature");
+        let body = payload.to_string();
+        let signature = github_signature(SECRET, body.as_bytes());
+        let response = app(&["acme/app"])
+            .oneshot(delivery(&body, &signature))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(json(response).await["status"], "ignored");
+    }
+
+    #[tokio::test]
+    async fn test_push_without_a_default_branch_is_ignored() {
+        let mut payload = github_push();
+        payload["repository"]
+            .as_object_mut()
+            .unwrap()
+            .remove("default_branch");
+        let body = payload.to_string();
+        let signature = github_signature(SECRET, body.as_bytes());
+        let response = app(&["acme/app"])
+            .oneshot(delivery(&body, &signature))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(json(response).await["status"], "ignored");
+    }
+
+    #[tokio::test]
+    async fn test_push_to_a_registered_ref_is_queued() {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        storage
+            .record_ingest(&RepoRecord {
+                repo_id: "acme/app@feature".to_string(),
+                url: "https://example.invalid/acme/app.git".to_string(),
+                git_ref: Some("feature".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut payload = github_push();
+        payload["ref"] = serde_json::json!("refs/heads/feature");
+        let body = payload.to_string();
+        let signature = github_signature(SECRET, body.as_bytes());
+        let response = app_with(storage, &["acme/app"])
+            .oneshot(delivery(&body, &signature))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = json(response).await;
+        assert_eq!(body["status"], "queued");
+        assert_eq!(body["repo_id"], "acme/app@feature");
+    }
+}
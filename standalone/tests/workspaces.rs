@@ -0,0 +1,120 @@
+mod common;
+
+use standalone::workspaces::{sqlite_path, validate_name};
+use std::path::Path;
+
+#[test]
+fn test_workspace_names_and_files() {
+    for name in ["prod", "experiments", "team-a_2"] {
+        assert!(validate_name(name).is_ok(), "{}", name);
+    }
+    for name in ["", "Prod", "2024", "a/b", "a.b", "../x", &"x".repeat(65)] {
+        assert!(validate_name(name).is_err(), "{}", name);
+    }
+    assert_eq!(
+        sqlite_path(Path::new("/var/lib/mesh/mesh.db"), "prod"),
+        Path::new("/var/lib/mesh/mesh.prod.db")
+    );
+    assert_eq!(
+        sqlite_path(Path::new("graph"), "prod"),
+        Path::new("graph.prod")
+    );
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_workspaces_do_not_see_each_other() {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::json;
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::storage::{NodeRecord, Storage};
+    use standalone::workspaces::HEADER;
+    use standalone::AppState;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    let node = |repo: &str| NodeRecord {
+        file: "src/main.rs".to_string(),
+        end: 1,
+        body: "fn main() {}".to_string(),
+        ..crate::common::function_in(repo, "main")
+    };
+    let state = |nodes: &[NodeRecord]| {
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        let nodes = nodes.to_vec();
+        async move {
+            storage.upsert_nodes(&nodes).await.unwrap();
+            Arc::new(AppState::new(
+                Arc::new(storage),
+                LanguageRegistry::new(),
+                16,
+            ))
+        }
+    };
+    let default = state(&[]).await;
+    let prod = state(&[node("acme/app")]).await;
+    let experiments = state(&[node("acme/app"), node("acme/lab")]).await;
+    let app = standalone::workspaces::router(
+        default,
+        BTreeMap::from([
+            ("prod".to_string(), prod.clone()),
+            ("experiments".to_string(), experiments.clone()),
+        ]),
+    );
+
+    let send = |request: Request<Body>| {
+        let app = app.clone();
+        async move { app.oneshot(request).await.unwrap().status() }
+    };
+    let export = |workspace: Option<&str>, repo: &str| {
+        let mut request = Request::get(format!("/export/json?repo={}", repo));
+        if let Some(workspace) = workspace {
+            request = request.header(HEADER, workspace);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+
+    // the header and the prefix pick the same workspace
+    assert_eq!(send(export(Some("prod"), "acme/app")).await, StatusCode::OK);
+    let prefixed = Request::get("/workspaces/prod/export/json?repo=acme/app")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(prefixed).await, StatusCode::OK);
+    assert_eq!(
+        send(export(Some("prod"), "acme/lab")).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        send(export(Some("experiments"), "acme/lab")).await,
+        StatusCode::OK
+    );
+    // the default graph has neither
+    assert_eq!(send(export(None, "acme/app")).await, StatusCode::NOT_FOUND);
+
+    // clearing everything in one workspace leaves the other as it was
+    let clear = Request::post("/clear")
+        .header(HEADER, "experiments")
+        .header("Content-Type", "application/json")
+        .body(Body::from(json!({ "confirm": "ALL" }).to_string()))
+        .unwrap();
+    assert_eq!(send(clear).await, StatusCode::OK);
+    assert_eq!(experiments.storage.graph_size(None).await.unwrap(), (0, 0));
+    assert_eq!(prod.storage.graph_size(None).await.unwrap(), (1, 0));
+    assert_eq!(send(export(Some("prod"), "acme/app")).await, StatusCode::OK);
+
+    for request in [
+        export(Some("staging"), "acme/app"),
+        Request::get("/workspaces/staging/repos")
+            .body(Body::empty())
+            .unwrap(),
+    ] {
+        assert_eq!(send(request).await, StatusCode::NOT_FOUND);
+    }
+    assert_eq!(
+        send(export(Some("Prod!"), "acme/app")).await,
+        StatusCode::BAD_REQUEST
+    );
+}
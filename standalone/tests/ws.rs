@@ -0,0 +1,117 @@
+use standalone::ws::ClientMessage;
+
+#[test]
+fn test_client_messages_are_tagged_by_type() {
+    let message: ClientMessage =
+        serde_json::from_str(r#"{"type": "subscribe", "repo_id": "acme/app"}"#).unwrap();
+    assert_eq!(
+        message,
+        ClientMessage::Subscribe {
+            repo_id: "acme/app".to_string()
+        }
+    );
+    let message: ClientMessage = serde_json::from_str(r#"{"type": "replay", "since": 4}"#).unwrap();
+    assert_eq!(message, ClientMessage::Replay { since: 4 });
+    assert!(serde_json::from_str::<ClientMessage>(r#"{"type": "shout"}"#).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+mod server {
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use standalone::events::StatusEvent;
+    use standalone::lang::LanguageRegistry;
+    use standalone::storage::sqlite::SqliteStorage;
+    use standalone::AppState;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn serve() -> (Arc<AppState>, String) {
+        let storage = Arc::new(SqliteStorage::open_in_memory().unwrap());
+        let state = Arc::new(AppState::new(storage, LanguageRegistry::new(), 16));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let app = standalone::router(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (state, url)
+    }
+
+    async fn send(client: &mut Client, message: Value) {
+        client
+            .send(Message::Text(message.to_string()))
+            .await
+            .unwrap();
+    }
+
+    async fn next(client: &mut Client) -> Value {
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(5), client.next())
+                .await
+                .expect("no frame within 5s")
+                .unwrap()
+                .unwrap();
+            if let Message::Text(text) = frame {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    fn event(repo_id: &str, message: &str) -> StatusEvent {
+        StatusEvent::new("parsing", message.to_string()).for_repo(repo_id)
+    }
+
+    #[tokio::test]
+    async fn test_client_only_gets_its_subscribed_repo() {
+        let (state, url) = serve().await;
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        send(
+            &mut client,
+            json!({"type": "subscribe", "repo_id": "acme/app"}),
+        )
+        .await;
+        assert_eq!(
+            next(&mut client).await,
+            json!({"type": "subscribed", "repo_ids": ["acme/app"]})
+        );
+
+        state.tx.send(event("other/repo", "skipped"));
+        state.tx.send(event("acme/app", "first"));
+        state.tx.send(event("other/repo", "skipped"));
+        state.tx.send(event("acme/app", "second"));
+
+        for expected in ["first", "second"] {
+            let frame = next(&mut client).await;
+            assert_eq!(frame["type"], "event");
+            assert_eq!(frame["repo_id"], "acme/app");
+            assert_eq!(frame["message"], expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_resends_buffered_events() {
+        let (state, url) = serve().await;
+        let first = state.tx.send(event("acme/app", "before"));
+        state.tx.send(event("other/repo", "skipped"));
+        state.tx.send(event("acme/app", "after"));
+
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        send(
+            &mut client,
+            json!({"type": "subscribe", "repo_id": "acme/app"}),
+        )
+        .await;
+        next(&mut client).await;
+        send(&mut client, json!({"type": "replay", "since": first})).await;
+        let frame = next(&mut client).await;
+        assert_eq!(frame["message"], "after");
+        assert!(frame["id"].as_u64().unwrap() > first);
+
+        send(&mut client, json!({"type": "nonsense"})).await;
+        assert_eq!(next(&mut client).await["type"], "error");
+    }
+}